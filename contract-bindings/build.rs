@@ -0,0 +1,192 @@
+//! Generates the contract bindings from the artifacts in `artifacts/`.
+//!
+//! Artifacts are produced by `just bindings`, which compiles the contracts with forge. Before
+//! generating the bindings, each artifact's ABI is checked against the declarations in the
+//! corresponding Solidity source, and the artifact's deployed bytecode is checked against its ABI,
+//! so that the build fails if a contract is changed without its artifact being refreshed.
+
+use ethers::{abi::Abi, contract::Abigen, utils::hex};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Contracts for which bindings are generated, as `(name, source file)`.
+const CONTRACTS: &[(&str, &str)] = &[
+    ("DepositEscrow", "../contracts/src/DepositEscrow.sol"),
+    ("ExampleRollup", "../contracts/src/ExampleRollup.sol"),
+];
+
+fn main() {
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    for (name, source) in CONTRACTS {
+        let artifact = format!("artifacts/{name}.json");
+        println!("cargo:rerun-if-changed={artifact}");
+        println!("cargo:rerun-if-changed={source}");
+
+        check_abi(name, Path::new(&artifact), Path::new(source));
+        check_bytecode(name, Path::new(&artifact));
+
+        Abigen::new(*name, &artifact)
+            .unwrap_or_else(|err| panic!("invalid artifact {artifact}: {err}"))
+            .generate()
+            .unwrap_or_else(|err| panic!("failed to generate bindings for {name}: {err}"))
+            .write_to_file(out_dir.join(format!("{}.rs", snake_case(name))))
+            .unwrap_or_else(|err| panic!("failed to write bindings for {name}: {err}"));
+    }
+}
+
+/// Fail the build if the externally visible items declared in `source` differ from those in the
+/// ABI of `artifact`.
+fn check_abi(name: &str, artifact: &Path, source: &Path) {
+    let artifact: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(artifact).unwrap()).unwrap();
+    let from_abi = artifact["abi"]
+        .as_array()
+        .expect("artifact has no ABI")
+        .iter()
+        .filter_map(|item| {
+            let kind = item["type"].as_str()?;
+            let name = item["name"].as_str()?;
+            Some(format!("{kind} {name}"))
+        })
+        .collect::<BTreeSet<_>>();
+    let from_source = declarations(&std::fs::read_to_string(source).unwrap());
+
+    if from_abi != from_source {
+        let missing = from_source.difference(&from_abi).collect::<Vec<_>>();
+        let stale = from_abi.difference(&from_source).collect::<Vec<_>>();
+        panic!(
+            "The {name} artifact is out of date with its Solidity source (missing from ABI: \
+             {missing:?}, not in source: {stale:?}). Run `just bindings` to regenerate it."
+        );
+    }
+}
+
+/// Fail the build if the deployed bytecode of `artifact` does not dispatch every function in its
+/// ABI.
+///
+/// An ABI edited by hand, without recompiling the contract, would otherwise produce bindings for
+/// functions which revert when called.
+fn check_bytecode(name: &str, artifact: &Path) {
+    let artifact: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(artifact).unwrap()).unwrap();
+    let abi: Abi = serde_json::from_value(artifact["abi"].clone())
+        .unwrap_or_else(|err| panic!("invalid ABI in the {name} artifact: {err}"));
+    let code = artifact["deployedBytecode"]["object"]
+        .as_str()
+        .expect("artifact has no deployed bytecode");
+    let code = hex::decode(code.trim_start_matches("0x"))
+        .unwrap_or_else(|err| panic!("invalid bytecode in the {name} artifact: {err}"));
+
+    let pushed = pushed_words(&code);
+    let missing = abi
+        .functions()
+        .filter(|function| !pushed.contains(&u32::from_be_bytes(function.short_signature())))
+        .map(|function| function.signature())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        panic!(
+            "The {name} artifact's bytecode does not implement {missing:?} from its ABI. Run \
+             `just bindings` to regenerate it."
+        );
+    }
+}
+
+/// Collect the values of up to 4 bytes pushed onto the stack by `code`.
+///
+/// The function dispatcher compares the calldata selector against each selector the contract
+/// implements, pushed as an immediate, so a function whose selector is never pushed cannot be
+/// called. Selectors with leading zero bytes are pushed with fewer than 4 bytes.
+fn pushed_words(code: &[u8]) -> BTreeSet<u32> {
+    const PUSH1: u8 = 0x60;
+    const PUSH32: u8 = 0x7f;
+
+    let mut words = BTreeSet::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = code[pc];
+        pc += 1;
+        if (PUSH1..=PUSH32).contains(&op) {
+            let len = (op - PUSH1 + 1) as usize;
+            let data = &code[pc..(pc + len).min(code.len())];
+            if len <= 4 {
+                words.insert(
+                    data.iter()
+                        .fold(0, |word, byte| (word << 8) | u32::from(*byte)),
+                );
+            }
+            pc += len;
+        }
+    }
+    words
+}
+
+/// Collect the functions, events and errors a Solidity source exposes in its ABI.
+///
+/// This is a lightweight scan rather than a full parser: it recognizes `function`, `event` and
+/// `error` declarations, and `public` state variables, which is sufficient for the contracts in
+/// this repository.
+fn declarations(source: &str) -> BTreeSet<String> {
+    // Strip comments so that commented out code and prose are ignored.
+    let code = source
+        .lines()
+        .map(|line| line.split("//").next().unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut items = BTreeSet::new();
+    let mut depth = 0;
+    let mut statement = String::new();
+    for c in code.chars() {
+        match c {
+            '{' | ';' | '}' => {
+                let words = statement.split_whitespace().collect::<Vec<_>>();
+                // Only declarations directly inside the contract body are part of its ABI.
+                if depth == 1 {
+                    if let Some(item) = declaration(&words) {
+                        items.insert(item);
+                    }
+                }
+                statement.clear();
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+            }
+            '(' | ')' | ',' => {
+                statement.push(' ');
+                statement.push(c);
+                statement.push(' ');
+            }
+            _ => statement.push(c),
+        }
+    }
+    items
+}
+
+fn declaration(words: &[&str]) -> Option<String> {
+    match words {
+        ["function", name, rest @ ..] if rest.contains(&"external") || rest.contains(&"public") => {
+            Some(format!("function {name}"))
+        }
+        ["event", name, ..] => Some(format!("event {name}")),
+        ["error", name, ..] => Some(format!("error {name}")),
+        _ if words.contains(&"(") => None,
+        // Public state variables have getter functions.
+        _ => {
+            let public = words.iter().position(|word| *word == "public")?;
+            words.get(public + 1).map(|name| format!("function {name}"))
+        }
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
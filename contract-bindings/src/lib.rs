@@ -3,11 +3,19 @@
 //! This is autogenerated code.
 //! Do not manually edit these files.
 //! These files may be overwritten by the codegen system at any time.
+//!
+//! Bindings for the contracts in this repository are generated at build time from the artifacts
+//! in `artifacts/`, see `build.rs`.
 pub mod address;
 pub mod bn254;
 pub mod context_upgradeable;
+pub mod deposit_escrow {
+    include!(concat!(env!("OUT_DIR"), "/deposit_escrow.rs"));
+}
 pub mod erc1967_utils;
-pub mod example_rollup;
+pub mod example_rollup {
+    include!(concat!(env!("OUT_DIR"), "/example_rollup.rs"));
+}
 pub mod i_beacon;
 pub mod i_plonk_verifier;
 pub mod ierc1822_proxiable;
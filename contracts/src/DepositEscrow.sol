@@ -0,0 +1,45 @@
+pragma solidity ^0.8.13;
+
+// Escrow for deposits onto the rollup.
+//
+// Ether sent to `deposit` is held by this contract and announced with a `Deposited` event. The
+// rollup credits each deposit, one token per wei, to `account` in the first rollup block whose
+// HotShot header finalizes the L1 block containing the event.
+//
+// The escrow is deployed by the rollup contract, which pays out withdrawals from it with `release`.
+// Ether sent with the deployment backs the rollup's genesis balances.
+contract DepositEscrow {
+    // The rollup contract which deployed this escrow, the only caller allowed to release funds.
+    address public rollup;
+
+    // Rollup balances are 64-bit, so a larger deposit could never be credited.
+    error InvalidDeposit(uint256 amount);
+    // `release` was called by an account other than the rollup contract.
+    error Unauthorized();
+    // Sending released ether to its recipient failed.
+    error TransferFailed();
+
+    event Deposited(address account, uint256 amount);
+
+    constructor() payable {
+        rollup = msg.sender;
+    }
+
+    function deposit(address account) external payable {
+        if (msg.value == 0 || msg.value > type(uint64).max) {
+            revert InvalidDeposit(msg.value);
+        }
+        emit Deposited(account, msg.value);
+    }
+
+    // Send `amount` wei held in escrow to `recipient`, for a withdrawal claimed from the rollup.
+    function release(address recipient, uint256 amount) external {
+        if (msg.sender != rollup) {
+            revert Unauthorized();
+        }
+        (bool sent,) = payable(recipient).call{value: amount}("");
+        if (!sent) {
+            revert TransferFailed();
+        }
+    }
+}
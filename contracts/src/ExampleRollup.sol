@@ -1,11 +1,22 @@
 pragma solidity ^0.8.13;
 
 import "../lib/espresso-sequencer/contracts/src/LightClient.sol";
+import "./DepositEscrow.sol";
 
 contract ExampleRollup {
     LightClient public lightClient;
+    // The only account allowed to submit batch proofs. The proofs are mocks, so they cannot stop
+    // anyone else from posting an arbitrary state or withdrawal tree.
+    address public prover;
+    // Holds the ether deposited onto the rollup, from which withdrawals are paid out.
+    DepositEscrow public escrow;
     uint256 public stateCommitment;
     uint256 public numVerifiedBlocks;
+    // Root of the rollup's withdrawal tree over its first `numWithdrawals` withdrawals, as of the
+    // last verified batch which added withdrawals.
+    bytes32 public withdrawalsRoot;
+    uint64 public numWithdrawals;
+    mapping(uint64 => bool) internal withdrawn;
 
     // An example batch proof of the execution of a chain of blocks.
     //  In a real rollup, this batch proof should be modified based on the requirements.
@@ -14,6 +25,20 @@ contract ExampleRollup {
         uint256 lastBlock;
         uint256 oldState;
         uint256 newState;
+        // The withdrawal tree of `newState`, proven along with it. Only checked for batches which
+        // add withdrawals, submitted with `verifyBlocksWithWithdrawals`.
+        bytes32 withdrawalsRoot;
+        uint64 numWithdrawals;
+    }
+
+    // A Merkle proof that a withdrawal is in the withdrawal tree. Leaves are
+    // `keccak256(abi.encodePacked(recipient, amount, index))` and `siblings` lists sibling hashes
+    // from the leaf up to the root.
+    struct WithdrawalProof {
+        address recipient;
+        uint64 amount;
+        uint64 index;
+        bytes32[] siblings;
     }
 
     // Attempted to verify a proof of the blocks from `numVerifiedBlocks` to
@@ -25,17 +50,135 @@ contract ExampleRollup {
 
     // Thrown when the proof is invalid
     error InvalidProof();
+    // A batch proof was submitted by an account other than the prover.
+    error NotProver(address caller);
+
+    // The withdrawal at `index` is not in the withdrawal tree, or has already been claimed.
+    error InvalidWithdrawal(uint64 index);
+    error AlreadyWithdrawn(uint64 index);
 
     event StateUpdate(uint256 blockHeight, uint256 stateCommitment);
 
-    constructor(address lightClientAddress, uint256 initialState) {
+    // Hash chain digest of the state commitment after each block in a verified batch, for batches
+    // submitted with `verifyBlocksWithCommitments` or `verifyBlocksCompressed`. The digest starts
+    // at zero and each commitment `c` is folded in as `keccak256(abi.encodePacked(digest, c))`.
+    event CommitmentsDigest(uint256 blockHeight, bytes32 digest);
+
+    event WithdrawalsUpdate(uint256 blockHeight, bytes32 withdrawalsRoot, uint64 numWithdrawals);
+
+    event Withdrawn(address recipient, uint64 amount, uint64 index);
+
+    // The ether sent with the deployment funds the escrow, backing the genesis balances one wei per
+    // token, so that withdrawing them does not pay out of other users' deposits.
+    constructor(address lightClientAddress, uint256 initialState, address proverAddress) payable {
         lightClient = LightClient(lightClientAddress);
+        prover = proverAddress;
+        escrow = new DepositEscrow{value: msg.value}();
 
         stateCommitment = initialState;
         numVerifiedBlocks = 0;
     }
 
     function verifyBlocks(uint64 count, uint256 nextStateCommitment, BatchProof memory proof) external {
+        _verifyBlocks(count, nextStateCommitment, proof);
+    }
+
+    // Verify a batch proof, also posting the state commitment after each block in the batch.
+    //
+    // The commitments are only checked for consistency with the batch proof, but since they are
+    // part of the calldata, anyone can reconstruct every intermediate state commitment from L1 data.
+    function verifyBlocksWithCommitments(
+        uint64 count,
+        uint256 nextStateCommitment,
+        BatchProof memory proof,
+        uint256[] calldata commitments
+    ) external {
+        if (commitments.length == 0 || commitments[commitments.length - 1] != proof.newState) {
+            revert InvalidProof();
+        }
+        _verifyBlocks(count, nextStateCommitment, proof);
+
+        bytes32 digest = 0;
+        for (uint256 i = 0; i < commitments.length; i++) {
+            digest = keccak256(abi.encodePacked(digest, commitments[i]));
+        }
+        emit CommitmentsDigest(numVerifiedBlocks, digest);
+    }
+
+    // Verify a batch proof, posting only a digest of the intermediate state commitments.
+    //
+    // The commitments themselves are kept off-chain by the rollup, which saves calldata at the cost
+    // of making them unavailable from L1 data alone.
+    function verifyBlocksCompressed(
+        uint64 count,
+        uint256 nextStateCommitment,
+        BatchProof memory proof,
+        bytes32 commitmentsDigest
+    ) external {
+        _verifyBlocks(count, nextStateCommitment, proof);
+        emit CommitmentsDigest(numVerifiedBlocks, commitmentsDigest);
+    }
+
+    // Verify a batch proof which added withdrawals, posting the new root of the withdrawal tree
+    // along with a digest of the intermediate state commitments, as in `verifyBlocksCompressed`.
+    //
+    // The new tree must be the one proven for the new state, and cannot drop withdrawals.
+    function verifyBlocksWithWithdrawals(
+        uint64 count,
+        uint256 nextStateCommitment,
+        BatchProof memory proof,
+        bytes32 commitmentsDigest,
+        bytes32 nextWithdrawalsRoot,
+        uint64 nextNumWithdrawals
+    ) external {
+        _verifyBlocks(count, nextStateCommitment, proof);
+        if (
+            proof.withdrawalsRoot != nextWithdrawalsRoot || proof.numWithdrawals != nextNumWithdrawals
+                || nextNumWithdrawals < numWithdrawals
+        ) {
+            revert InvalidProof();
+        }
+        withdrawalsRoot = nextWithdrawalsRoot;
+        numWithdrawals = nextNumWithdrawals;
+        emit CommitmentsDigest(numVerifiedBlocks, commitmentsDigest);
+        emit WithdrawalsUpdate(numVerifiedBlocks, nextWithdrawalsRoot, nextNumWithdrawals);
+    }
+
+    // Claim a withdrawal from the rollup, proven against the current withdrawal tree.
+    //
+    // The withdrawn amount is paid to the recipient from the deposit escrow, one wei per token.
+    function withdraw(WithdrawalProof calldata proof) external {
+        if (proof.index >= numWithdrawals) {
+            revert InvalidWithdrawal(proof.index);
+        }
+        if (withdrawn[proof.index]) {
+            revert AlreadyWithdrawn(proof.index);
+        }
+        bytes32 node = keccak256(abi.encodePacked(proof.recipient, proof.amount, proof.index));
+        for (uint256 i = 0; i < proof.siblings.length; i++) {
+            if ((proof.index >> i) & 1 == 1) {
+                node = keccak256(abi.encodePacked(proof.siblings[i], node));
+            } else {
+                node = keccak256(abi.encodePacked(node, proof.siblings[i]));
+            }
+        }
+        if (node != withdrawalsRoot) {
+            revert InvalidWithdrawal(proof.index);
+        }
+        withdrawn[proof.index] = true;
+        emit Withdrawn(proof.recipient, proof.amount, proof.index);
+        escrow.release(proof.recipient, proof.amount);
+    }
+
+    function isWithdrawn(uint64 index) external view returns (bool) {
+        return withdrawn[index];
+    }
+
+    function _verifyBlocks(uint64 count, uint256 nextStateCommitment, BatchProof memory proof) internal {
+        if (msg.sender != prover) {
+            revert NotProver(msg.sender);
+        }
+
         if (count == 0) {
             revert NoBlocks();
         }
@@ -54,4 +197,26 @@ contract ExampleRollup {
         stateCommitment = nextStateCommitment;
         emit StateUpdate(numVerifiedBlocks, stateCommitment);
     }
+
+    // Verify a Merkle proof that `account` has the given balance and nonce in a rollup accounts
+    // tree with the given root. Leaves are `keccak256(abi.encodePacked(account, balance, nonce))`
+    // and `proof` lists sibling hashes from the leaf up to the root.
+    function verifyBalance(
+        bytes32 root,
+        address account,
+        uint64 balance,
+        uint64 nonce,
+        uint256 index,
+        bytes32[] calldata proof
+    ) external pure returns (bool) {
+        bytes32 node = keccak256(abi.encodePacked(account, balance, nonce));
+        for (uint256 i = 0; i < proof.length; i++) {
+            if ((index >> i) & 1 == 1) {
+                node = keccak256(abi.encodePacked(proof[i], node));
+            } else {
+                node = keccak256(abi.encodePacked(node, proof[i]));
+            }
+        }
+        return node == root;
+    }
 }
@@ -0,0 +1,51 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.13;
+
+import "forge-std/Test.sol";
+
+import "../src/DepositEscrow.sol";
+
+contract DepositEscrowTest is Test {
+    DepositEscrow public escrow;
+
+    event Deposited(address account, uint256 amount);
+
+    function setUp() public {
+        escrow = new DepositEscrow();
+    }
+
+    function testDeposit() public {
+        address account = address(0x1234);
+        vm.expectEmit(false, false, false, true, address(escrow));
+        emit Deposited(account, 100);
+        escrow.deposit{value: 100}(account);
+        assertEq(address(escrow).balance, 100);
+    }
+
+    function testRelease() public {
+        address recipient = address(0x1234);
+        escrow.deposit{value: 100}(recipient);
+
+        vm.prank(recipient);
+        vm.expectRevert(DepositEscrow.Unauthorized.selector);
+        escrow.release(recipient, 100);
+
+        // This contract deployed the escrow, so it acts as the rollup.
+        escrow.release(recipient, 60);
+        assertEq(recipient.balance, 60);
+        assertEq(address(escrow).balance, 40);
+
+        vm.expectRevert(DepositEscrow.TransferFailed.selector);
+        escrow.release(recipient, 41);
+    }
+
+    function testInvalidDeposit() public {
+        vm.expectRevert(abi.encodeWithSelector(DepositEscrow.InvalidDeposit.selector, 0));
+        escrow.deposit(address(0x1234));
+
+        uint256 amount = uint256(type(uint64).max) + 1;
+        vm.deal(address(this), amount);
+        vm.expectRevert(abi.encodeWithSelector(DepositEscrow.InvalidDeposit.selector, amount));
+        escrow.deposit{value: amount}(address(0x1234));
+    }
+}
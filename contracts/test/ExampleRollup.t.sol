@@ -14,27 +14,125 @@ contract ExampleRollupTest is Test {
 
     function setUp() public {
         lightClient = new LightClient();
-        rollup = new ExampleRollup(address(lightClient), 0);
+        // This contract is the prover, and funds the genesis balances.
+        rollup = new ExampleRollup{value: 1000}(address(lightClient), 0, address(this));
+    }
+
+    function batch(uint256 newState) internal pure returns (ExampleRollup.BatchProof memory) {
+        return ExampleRollup.BatchProof({
+            firstBlock: 0,
+            lastBlock: 0,
+            oldState: 0,
+            newState: newState,
+            withdrawalsRoot: 0,
+            numWithdrawals: 0
+        });
     }
 
     function testNoblocks() public {
-        ExampleRollup.BatchProof memory proof =
-            ExampleRollup.BatchProof({firstBlock: 0, lastBlock: 0, oldState: 0, newState: 0x1});
+        ExampleRollup.BatchProof memory proof = batch(0x1);
         vm.expectRevert(ExampleRollup.NoBlocks.selector);
         rollup.verifyBlocks(0, 0, proof);
     }
 
     function testNotYetSequenced() public {
-        ExampleRollup.BatchProof memory proof =
-            ExampleRollup.BatchProof({firstBlock: 0, lastBlock: 0, oldState: 0, newState: 0x1});
+        ExampleRollup.BatchProof memory proof = batch(0x1);
         vm.expectRevert(abi.encodeWithSelector(ExampleRollup.NotYetSequenced.selector, 0, 2, 0));
         rollup.verifyBlocks(2, 0x1, proof);
     }
 
     function testInvalidProof() public {
-        ExampleRollup.BatchProof memory proof =
-            ExampleRollup.BatchProof({firstBlock: 0, lastBlock: 0, oldState: 0, newState: 0x2});
+        ExampleRollup.BatchProof memory proof = batch(0x2);
         vm.expectRevert(ExampleRollup.InvalidProof.selector);
         rollup.verifyBlocks(1, 0x1, proof);
     }
+
+    function testInvalidCommitments() public {
+        ExampleRollup.BatchProof memory proof = batch(0x1);
+        uint256[] memory commitments = new uint256[](2);
+        commitments[0] = 0x1;
+        commitments[1] = 0x2;
+        vm.expectRevert(ExampleRollup.InvalidProof.selector);
+        rollup.verifyBlocksWithCommitments(1, 0x1, proof, commitments);
+
+        vm.expectRevert(ExampleRollup.InvalidProof.selector);
+        rollup.verifyBlocksWithCommitments(1, 0x1, proof, new uint256[](0));
+    }
+
+    function testVerifyBalance() public {
+        address account = address(0x1234);
+        bytes32 leaf = keccak256(abi.encodePacked(account, uint64(100), uint64(1)));
+        bytes32 sibling = keccak256("sibling");
+        bytes32 root = keccak256(abi.encodePacked(sibling, leaf));
+        bytes32[] memory proof = new bytes32[](1);
+        proof[0] = sibling;
+
+        assertTrue(rollup.verifyBalance(root, account, 100, 1, 1, proof));
+        assertFalse(rollup.verifyBalance(root, account, 101, 1, 1, proof));
+        assertFalse(rollup.verifyBalance(root, account, 100, 1, 0, proof));
+    }
+
+    function testWithdraw() public {
+        address recipient = address(0x1234);
+        bytes32 leaf = keccak256(abi.encodePacked(recipient, uint64(100), uint64(0)));
+        bytes32 sibling = keccak256("sibling");
+        bytes32 root = keccak256(abi.encodePacked(leaf, sibling));
+        bytes32[] memory siblings = new bytes32[](1);
+        siblings[0] = sibling;
+        ExampleRollup.WithdrawalProof memory withdrawal =
+            ExampleRollup.WithdrawalProof({recipient: recipient, amount: 100, index: 0, siblings: siblings});
+
+        // Nothing can be claimed before a withdrawal tree is posted.
+        vm.expectRevert(abi.encodeWithSelector(ExampleRollup.InvalidWithdrawal.selector, 0));
+        rollup.withdraw(withdrawal);
+
+        vm.mockCall(
+            address(lightClient),
+            abi.encodeWithSelector(LightClient.finalizedState.selector),
+            abi.encode(uint64(0), uint64(2), uint256(0))
+        );
+        ExampleRollup.BatchProof memory proof = batch(0x1);
+
+        // Only the prover can post a withdrawal tree.
+        vm.prank(recipient);
+        vm.expectRevert(abi.encodeWithSelector(ExampleRollup.NotProver.selector, recipient));
+        rollup.verifyBlocksWithWithdrawals(1, 0x1, proof, 0, root, 2);
+
+        // The tree must be the one proven for the new state.
+        vm.expectRevert(ExampleRollup.InvalidProof.selector);
+        rollup.verifyBlocksWithWithdrawals(1, 0x1, proof, 0, root, 2);
+
+        proof.withdrawalsRoot = root;
+        proof.numWithdrawals = 2;
+        rollup.verifyBlocksWithWithdrawals(1, 0x1, proof, 0, root, 2);
+        assertEq(rollup.withdrawalsRoot(), root);
+
+        // Withdrawals cannot be dropped from the tree.
+        ExampleRollup.BatchProof memory shrinking = batch(0x2);
+        shrinking.numWithdrawals = 1;
+        vm.expectRevert(ExampleRollup.InvalidProof.selector);
+        rollup.verifyBlocksWithWithdrawals(1, 0x2, shrinking, 0, 0, 1);
+
+        withdrawal.amount = 101;
+        vm.expectRevert(abi.encodeWithSelector(ExampleRollup.InvalidWithdrawal.selector, 0));
+        rollup.withdraw(withdrawal);
+
+        // The withdrawal is paid out of the escrow, which holds the genesis funding.
+        DepositEscrow escrow = rollup.escrow();
+        assertEq(address(escrow).balance, 1000);
+        withdrawal.amount = 100;
+        rollup.withdraw(withdrawal);
+        assertTrue(rollup.isWithdrawn(0));
+        assertEq(recipient.balance, 100);
+        assertEq(address(escrow).balance, 900);
+        vm.expectRevert(abi.encodeWithSelector(ExampleRollup.AlreadyWithdrawn.selector, 0));
+        rollup.withdraw(withdrawal);
+    }
+
+    function testNotProver() public {
+        address caller = address(0x5678);
+        vm.prank(caller);
+        vm.expectRevert(abi.encodeWithSelector(ExampleRollup.NotProver.selector, caller));
+        rollup.verifyBlocks(1, 0x1, batch(0x1));
+    }
 }
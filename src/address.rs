@@ -0,0 +1,138 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Parsing of user supplied addresses.
+
+use ethers::{types::Address, utils::to_checksum};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Snafu, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressError {
+    #[snafu(display(
+        "Malformed address {input}. Ensure that the address is valid hex encoded Ethereum address."
+    ))]
+    Malformed { input: String },
+    #[snafu(display("Invalid checksum for address {input}. Did you mean {expected}?"))]
+    InvalidChecksum { input: String, expected: String },
+    #[snafu(display("Unknown address alias {alias}."))]
+    UnknownAlias { alias: String },
+}
+
+/// Resolves user supplied addresses, which may be hex encoded or configured aliases.
+#[derive(Clone, Debug, Default)]
+pub struct AddressBook {
+    aliases: HashMap<String, Address>,
+}
+
+impl AddressBook {
+    pub fn new(aliases: impl IntoIterator<Item = (String, Address)>) -> Self {
+        Self {
+            aliases: aliases
+                .into_iter()
+                .map(|(alias, address)| (alias.to_lowercase(), address))
+                .collect(),
+        }
+    }
+
+    /// Register `alias` as a name for `address`.
+    pub fn insert(&mut self, alias: impl Into<String>, address: Address) {
+        self.aliases.insert(alias.into().to_lowercase(), address);
+    }
+
+    /// Resolve `input` to an address.
+    ///
+    /// Hex encoded addresses must either be entirely lowercase, entirely uppercase, or carry a
+    /// valid EIP-55 checksum. Anything else is looked up as an alias.
+    pub fn resolve(&self, input: &str) -> Result<Address, AddressError> {
+        if input.starts_with("0x") || input.starts_with("0X") {
+            return parse_checksummed(input);
+        }
+        self.aliases
+            .get(&input.to_lowercase())
+            .copied()
+            .ok_or_else(|| AddressError::UnknownAlias {
+                alias: input.to_string(),
+            })
+    }
+}
+
+/// Parse a hex encoded address, validating its EIP-55 checksum if it is mixed case.
+pub fn parse_checksummed(input: &str) -> Result<Address, AddressError> {
+    let address = input
+        .parse::<Address>()
+        .map_err(|_| AddressError::Malformed {
+            input: input.to_string(),
+        })?;
+    let digits = &input[2..];
+    let mixed_case = digits.chars().any(|c| c.is_ascii_lowercase())
+        && digits.chars().any(|c| c.is_ascii_uppercase());
+    if mixed_case {
+        let expected = to_checksum(&address, None);
+        if expected[2..] != *digits {
+            return Err(AddressError::InvalidChecksum {
+                input: input.to_string(),
+                expected,
+            });
+        }
+    }
+    Ok(address)
+}
+
+/// Parse an alias definition of the form `NAME=ADDRESS`.
+pub fn parse_alias(s: &str) -> Result<(String, Address), String> {
+    let (alias, address) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid alias {s}, expected NAME=ADDRESS"))?;
+    let address = parse_checksummed(address).map_err(|err| err.to_string())?;
+    Ok((alias.to_string(), address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_address() {
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let address = checksummed.parse::<Address>().unwrap();
+        let book = AddressBook::new([("alice.rollup".to_string(), address)]);
+
+        assert_eq!(book.resolve(checksummed).unwrap(), address);
+        assert_eq!(book.resolve(&checksummed.to_lowercase()).unwrap(), address);
+        assert_eq!(book.resolve("Alice.Rollup").unwrap(), address);
+
+        let bad_checksum = "0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        assert_eq!(
+            book.resolve(bad_checksum).unwrap_err(),
+            AddressError::InvalidChecksum {
+                input: bad_checksum.to_string(),
+                expected: checksummed.to_string(),
+            }
+        );
+        assert!(matches!(
+            book.resolve("0x1234").unwrap_err(),
+            AddressError::Malformed { .. }
+        ));
+        assert!(matches!(
+            book.resolve("bob.rollup").unwrap_err(),
+            AddressError::UnknownAlias { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_alias() {
+        let (alias, address) =
+            parse_alias("alice.rollup=0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap();
+        assert_eq!(alias, "alice.rollup");
+        assert_eq!(
+            to_checksum(&address, None),
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+        assert!(parse_alias("alice.rollup").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Aggregation of block proofs by an external service.
+//!
+//! In production rollups, the nodes which execute blocks are often separate from the network which
+//! aggregates their proofs. With an [`ExternalAggregator`] configured, the executor does not
+//! aggregate block proofs itself. Instead each proof is posted, as a [`StreamedProof`], to the
+//! aggregator's `proofs` endpoint, in execution order. The aggregator returns batch proofs by
+//! posting them to the `rollup/aggregator/batch` route of the node's API, which checks each batch
+//! against the block proofs it aggregates and records it in the outbox for submission to the
+//! rollup contract.
+//!
+//! Proofs which could not be posted are retried after the next executed blocks. Proofs which have
+//! been posted but not yet returned in a batch are kept in memory only, so after a restart the node
+//! must re-execute those blocks, for example from a checkpoint taken before they were streamed.
+
+use crate::http::HttpClientPool;
+use crate::outbox::{Outbox, OutboxError};
+use crate::prover::{BatchProof, Proof, ProofError, ProofShape};
+use async_std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::VecDeque;
+use surf_disco::Url;
+use tide_disco::{error::ServerError, StatusCode};
+
+#[derive(Clone, Debug, Snafu)]
+pub enum AggregatorError {
+    #[snafu(display("Batch does not start at the oldest block proof awaiting aggregation."))]
+    OutOfOrder,
+    #[snafu(display("Batch covers block proofs which were not streamed to the aggregator."))]
+    UnknownProofs,
+    #[snafu(display("Batch does not match the block proofs it covers."))]
+    Mismatch,
+    #[snafu(display("Invalid batch: {source}"))]
+    InvalidBatch { source: ProofError },
+    #[snafu(display("Unable to record batch in the outbox: {source}"))]
+    Outbox { source: OutboxError },
+}
+
+impl From<AggregatorError> for ServerError {
+    fn from(err: AggregatorError) -> Self {
+        let status = match &err {
+            AggregatorError::Outbox { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::CONFLICT,
+        };
+        Self {
+            status,
+            message: err.to_string(),
+        }
+    }
+}
+
+/// A block proof posted to the aggregator.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StreamedProof {
+    /// Position of the proof in the stream, starting from 0 when the node starts.
+    pub sequence: u64,
+    /// Number of blocks covered by the proof, including blocks without rollup transactions
+    /// executed alongside it.
+    pub blocks: u64,
+    pub(crate) proof: Proof,
+}
+
+#[derive(Debug, Default)]
+struct AggregatorInner {
+    next_sequence: u64,
+    /// Proofs which have not yet been posted to the aggregator.
+    unsent: VecDeque<StreamedProof>,
+    /// Proofs which have been posted but not yet returned in a batch.
+    in_flight: VecDeque<StreamedProof>,
+}
+
+/// Client of an external proof aggregation service, shared by the executor and the API.
+#[derive(Clone, Debug)]
+pub struct ExternalAggregator {
+    url: Url,
+    http: HttpClientPool,
+    outbox: Outbox,
+    proof_shape: ProofShape,
+    inner: Arc<Mutex<AggregatorInner>>,
+}
+
+impl ExternalAggregator {
+    /// Stream proofs to the aggregator at `url`, recording the batches it returns in `outbox`.
+    pub fn new(url: Url, http: HttpClientPool, outbox: Outbox) -> Self {
+        Self {
+            url,
+            http,
+            outbox,
+            proof_shape: Default::default(),
+            inner: Default::default(),
+        }
+    }
+
+    /// Submit returned batches in `shape`.
+    pub fn with_proof_shape(mut self, shape: ProofShape) -> Self {
+        self.proof_shape = shape;
+        self
+    }
+
+    /// Post `proofs`, each paired with the number of blocks it covers, to the aggregator, after any
+    /// proofs which could not be posted earlier.
+    pub(crate) async fn stream(&self, proofs: Vec<(Proof, u64)>) {
+        let mut inner = self.inner.lock().await;
+        for (proof, blocks) in proofs {
+            let sequence = inner.next_sequence;
+            inner.next_sequence += 1;
+            inner.unsent.push_back(StreamedProof {
+                sequence,
+                blocks,
+                proof,
+            });
+        }
+        let client = self.http.client(&self.url).await;
+        while let Some(proof) = inner.unsent.front() {
+            if let Err(err) = client.post::<()>("proofs", proof).await {
+                tracing::warn!(
+                    "Unable to stream proof {} to the aggregator, retrying after the next block: \
+                     {err}",
+                    proof.sequence
+                );
+                return;
+            }
+            tracing::debug!("Streamed proof {} to the aggregator", proof.sequence);
+            let proof = inner.unsent.pop_front().unwrap();
+            inner.in_flight.push_back(proof);
+        }
+    }
+
+    /// Check a batch returned by the aggregator against the oldest proofs awaiting aggregation,
+    /// and record it in the outbox, returning the number of blocks it covers.
+    pub(crate) async fn accept(&self, batch: BatchProof) -> Result<u64, AggregatorError> {
+        let mut inner = self.inner.lock().await;
+        if inner
+            .in_flight
+            .front()
+            .is_some_and(|first| first.proof.block() != batch.first_block())
+        {
+            return Err(AggregatorError::OutOfOrder);
+        }
+        let len = inner
+            .in_flight
+            .iter()
+            .position(|streamed| streamed.proof.block() == batch.last_block())
+            .ok_or(AggregatorError::UnknownProofs)?
+            + 1;
+        let covered = inner.in_flight.range(..len);
+        let blocks = covered.clone().map(|streamed| streamed.blocks).sum();
+        let proofs: Vec<_> = covered.map(|streamed| streamed.proof.clone()).collect();
+        let expected = BatchProof::generate(&proofs)
+            .map_err(|source| AggregatorError::InvalidBatch { source })?;
+        if batch != expected {
+            return Err(AggregatorError::Mismatch);
+        }
+        self.outbox
+            .enqueue(batch.into(), blocks, self.proof_shape)
+            .await
+            .map_err(|source| AggregatorError::Outbox { source })?;
+        inner.in_flight.drain(..len);
+        tracing::info!("Accepted aggregated proof for {blocks} blocks");
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+    use committable::Commitment;
+    use espresso_types::SeqTypes;
+    use hotshot_query_service::availability::BlockHash;
+
+    /// Proofs for a chain of `n` blocks, whose hashes are small enough for the outbox to read as
+    /// block numbers.
+    fn proofs(n: u8) -> Vec<Proof> {
+        let states: Vec<Commitment<State>> = (0..=n)
+            .map(|_| Commitment::from_raw(rand::random()))
+            .collect();
+        states
+            .windows(2)
+            .zip(1..)
+            .map(|(states, block)| {
+                let mut hash = [0; 32];
+                hash[0] = block;
+                Proof::new(BlockHash::<SeqTypes>::from_raw(hash), states[0], states[1])
+            })
+            .collect()
+    }
+
+    #[async_std::test]
+    async fn test_accept_aggregated_batches() {
+        let outbox = Outbox::in_memory();
+        let aggregator = ExternalAggregator::new(
+            "http://localhost:1".parse().unwrap(),
+            HttpClientPool::default(),
+            outbox.clone(),
+        );
+        let proofs = proofs(3);
+        aggregator.inner.lock().await.in_flight = proofs
+            .iter()
+            .enumerate()
+            .map(|(i, proof)| StreamedProof {
+                sequence: i as u64,
+                blocks: 2,
+                proof: proof.clone(),
+            })
+            .collect();
+
+        // Batches must start at the oldest proof awaiting aggregation.
+        let batch = BatchProof::generate(&proofs[1..]).unwrap();
+        assert!(matches!(
+            aggregator.accept(batch).await,
+            Err(AggregatorError::OutOfOrder)
+        ));
+        // A batch must be the aggregate of the proofs it covers.
+        let mut forged = proofs[..2].to_vec();
+        forged[1] = Proof::new(
+            forged[1].block(),
+            forged[0].new_state(),
+            forged[0].new_state(),
+        );
+        let batch = BatchProof::generate(&forged).unwrap();
+        assert!(matches!(
+            aggregator.accept(batch).await,
+            Err(AggregatorError::Mismatch)
+        ));
+
+        let batch = BatchProof::generate(&proofs[..2]).unwrap();
+        assert_eq!(aggregator.accept(batch).await.unwrap(), 4);
+        assert_eq!(aggregator.inner.lock().await.in_flight.len(), 1);
+        assert_eq!(outbox.entries().await.len(), 1);
+
+        // The same batch is not accepted twice.
+        let batch = BatchProof::generate(&proofs[..2]).unwrap();
+        assert!(aggregator.accept(batch).await.is_err());
+    }
+}
@@ -4,112 +4,1872 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
-use crate::{state::State, transaction::SignedTransaction};
+use crate::{
+    address::AddressBook,
+    aggregator::ExternalAggregator,
+    backfill::CatchUpTracker,
+    canary::Canary,
+    chain::ChainId,
+    error::RollupError,
+    events::{EventFanout, EventFilter, EventIndex, EventKind, StreamMessage, SubscriptionRequest},
+    fees::{FeeEstimate, FeeHistory},
+    gossip::CheckpointStore,
+    history::{AccountHistory, BlockInfo, HistoryError},
+    http::HttpClientPool,
+    inclusion::fetch_inclusion_proof,
+    mempool::Mempool,
+    metrics::NodeMetrics,
+    middleware::{run_middleware, AppListener, CborResponses, CorsAllowList, Middleware},
+    nonce::NonceStats,
+    outbox::{Outbox, PendingBatch, StateCheckStats},
+    prover::{BatchProof, ProofVerifier},
+    query::{
+        TransactionIndex, TransactionQuery, TransactionRecord, TransactionStatus,
+        DEFAULT_QUERY_LIMIT,
+    },
+    random::DemoRng,
+    receipt::{ExecutionStatus, Receipt, ReceiptIndex},
+    relay::{should_fall_back, TransactionRelay, RELAYED_HEADER},
+    scheduler::DaIncidentLog,
+    schema::ApiSchema,
+    seed::SeedIdentity,
+    snapshot::{SnapshotError, SnapshotExporter},
+    spot_audit::SpotAuditLog,
+    state::{
+        Account, Amount, CommitmentIndex, Nonce, OrderingPolicy, ReplayProtection, State,
+        SubmissionPolicy,
+    },
+    stats::{
+        unix_millis, BlockExecutionStats, ExecutionStatsIndex, FinalityLagTracker, LatencyTracker,
+    },
+    submitters::{SubmitterRegistration, SubmitterRegistry},
+    trace::trace_transaction,
+    transaction::{
+        OperatorEnvelope, SignedTransaction, Submission, Transaction as RollupTransaction,
+    },
+    verify::fetch_receipt_bundle,
+    watchdog::ExecutionWatchdog,
+    webhooks::{WebhookError, WebhookRegistration, WebhookRegistry},
+    SequencerApiVersion,
+};
+use async_compatibility_layer::async_primitives::broadcast::BroadcastReceiver;
 use async_std::sync::RwLock;
 use committable::{Commitment, Committable};
 use espresso_types::{NamespaceId, Transaction};
 use ethers::abi::Address;
-use futures::FutureExt;
-use sequencer::SequencerApiVersion;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::H256;
+use futures::{future::BoxFuture, FutureExt, SinkExt, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
 use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use surf_disco::error::ClientError;
-use surf_disco::{Client, Url};
-use tide_disco::{error::ServerError, Api, App};
+use surf_disco::Url;
+use tide_disco::{
+    api::ApiError,
+    error::ServerError,
+    socket::{Connection, SocketError},
+    Api, App, Error as _, RequestParams,
+};
 
 #[derive(Clone, Debug)]
 pub struct APIOptions {
     pub api_port: u16,
+    /// Local addresses on which the API listens, all on `api_port`. Requests on any of them are
+    /// served by the same app, so a node can listen on both IPv4 and IPv6.
+    pub bind_addresses: Vec<IpAddr>,
+    /// Public URL at which clients reach this API, if it differs from the bind address, for
+    /// example when the node is behind a load balancer.
+    pub advertise_url: Option<Url>,
     pub sequencer_url: Url,
+    /// Hooks run, in order, on every request before it is handled.
+    pub middleware: Vec<Arc<dyn Middleware>>,
+    /// Origins allowed to make cross-origin requests, enforced in front of every module.
+    pub cors: CorsAllowList,
+    /// HTTP date after which the deprecated `rollup/v0` API may be removed, advertised in the
+    /// `Sunset` header of its responses.
+    pub v0_sunset: Option<String>,
+    /// Aliases accepted in place of hex encoded addresses.
+    pub address_book: AddressBook,
+    /// Enable the `sign-and-submit` route, which signs transactions with the seed identities'
+    /// keys. The seed keys are public, so this must only be enabled in development environments.
+    pub dev_signing: bool,
+    /// Enable the routes which pause and resume proof submission. These are unauthenticated unless
+    /// [`AdminAuth`](crate::auth::AdminAuth) middleware is registered, which the node always does.
+    pub submission_control: bool,
+    /// Source of randomness, such as the salts of server-signed transactions.
+    pub rng: DemoRng,
+    /// Additional routes served, unchanged, in every version of the `rollup` module.
+    pub extensions: ApiExtensions,
+    /// Key with which submitted transactions are countersigned, marking them as submitted through
+    /// this API. See [`SubmissionPolicy`].
+    pub operator_signer: Option<LocalWallet>,
+    /// Clients for the HotShot query service.
+    pub http: HttpClientPool,
+    /// The effective configuration of the node, with secrets redacted, served by `rollup/config`.
+    pub config: serde_json::Value,
 }
 
-async fn submit_transaction(
-    submit_url: Url,
+/// The rollup API module, to which extensions register handlers.
+pub type RollupApi = Api<Arc<RwLock<State>>, ServerError, SequencerApiVersion>;
+
+/// Versions of the rollup API, which are served side by side so that clients can migrate between
+/// them gradually.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiVersion {
+    /// Responses are the bare result of each route. This is the original format of the API, and is
+    /// deprecated.
+    V0,
+    /// Responses are wrapped in a [`ResponseEnvelope`].
+    V1,
+}
+
+/// The modules under which each version of the API is served. The unversioned `rollup` module is
+/// kept for existing clients, and serves the same routes as `rollup/v0`.
+pub const API_VERSIONS: [(&str, ApiVersion); 3] = [
+    ("rollup", ApiVersion::V0),
+    ("rollup/v0", ApiVersion::V0),
+    ("rollup/v1", ApiVersion::V1),
+];
+
+/// The body of a successful `rollup/v1` response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseEnvelope<T> {
+    pub data: T,
+    /// Height of the state which served the request.
+    pub block_height: u64,
+    /// Commitment to the state after `block_height`, or `None` if no block has been executed yet.
+    pub state_commitment: Option<Commitment<State>>,
+    /// The rollup chain served by this node, or `None` if it is not yet known.
+    #[serde(default)]
+    pub chain_id: Option<ChainId>,
+}
+
+/// A response in the format of the API version which served it.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Versioned<T> {
+    V0(T),
+    V1(ResponseEnvelope<T>),
+}
+
+/// Converts route results to the response format of one API version.
+#[derive(Clone, Debug)]
+struct Responder {
+    version: ApiVersion,
+    commitments: CommitmentIndex,
+}
+
+impl Responder {
+    /// Wrap the result of a request served from `state`.
+    fn wrap<'a, T: Send + 'a>(
+        &self,
+        state: &State,
+        response: impl Future<Output = Result<T, ServerError>> + Send + 'a,
+    ) -> BoxFuture<'a, Result<Versioned<T>, ServerError>> {
+        let block_height = state.block_height();
+        let chain_id = state.chain_id();
+        let version = self.version;
+        let commitments = self.commitments.clone();
+        async move {
+            let data = response.await?;
+            Ok(match version {
+                ApiVersion::V0 => Versioned::V0(data),
+                ApiVersion::V1 => Versioned::V1(ResponseEnvelope {
+                    data,
+                    block_height,
+                    state_commitment: commitments.get(block_height).await,
+                    chain_id,
+                }),
+            })
+        }
+        .boxed()
+    }
+}
+
+/// The body of a failed `rollup/v1` response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorEnvelope {
+    pub error: ErrorDetails,
+}
+
+/// Why a `rollup/v1` request failed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorDetails {
+    /// HTTP status of the response.
+    pub status: u16,
+    pub message: String,
+    /// The [`RollupError::code`] of a rejected transaction.
+    pub code: Option<u16>,
+    /// Whether the same request may succeed if it is retried later.
+    pub retryable: bool,
+}
+
+impl ErrorDetails {
+    /// Describe an error with `status` and `message`.
+    ///
+    /// The code of a rejected transaction is recovered from its message, which [`rejected`] formats
+    /// as `(error CODE)` or `(error CODE, retryable)`. Any other error is retryable if its status
+    /// means the node or its dependencies are temporarily unable to serve the request.
+    pub fn new(status: u16, message: String) -> Self {
+        let rejection = message
+            .split_once("(error ")
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .and_then(|(detail, _)| {
+                let (code, retryable) = match detail.split_once(',') {
+                    Some((code, flag)) => (code, flag.trim() == "retryable"),
+                    None => (detail, false),
+                };
+                Some((code.trim().parse::<u16>().ok()?, retryable))
+            });
+        let (code, retryable) = match rejection {
+            Some((code, retryable)) => (Some(code), retryable),
+            None => (None, matches!(status, 429 | 502 | 503 | 504)),
+        };
+        Self {
+            status,
+            message,
+            code,
+            retryable,
+        }
+    }
+}
+
+/// The error with which a transaction which cannot execute is rejected.
+fn rejected(err: &RollupError) -> ServerError {
+    let retryable = if err.is_retryable() {
+        ", retryable"
+    } else {
+        ""
+    };
+    ServerError {
+        status: tide_disco::StatusCode::BAD_REQUEST,
+        message: format!(
+            "Transaction rejected (error {}{retryable}): {err}",
+            err.code()
+        ),
+    }
+}
+
+/// The API version serving a request to `path`, or `None` if it is not a request to the `rollup`
+/// module. The path may be prefixed by the version of the app, as in `/v0/rollup/v1/...`.
+fn api_version_of(path: &str) -> Option<ApiVersion> {
+    let mut segments = path.trim_start_matches('/').split('/').peekable();
+    if segments.peek().is_some_and(|segment| {
+        segment.len() > 1
+            && segment.starts_with('v')
+            && segment[1..].bytes().all(|b| b.is_ascii_digit())
+    }) {
+        segments.next();
+    }
+    if segments.next()? != "rollup" {
+        return None;
+    }
+    Some(match segments.next() {
+        Some("v1") => ApiVersion::V1,
+        _ => ApiVersion::V0,
+    })
+}
+
+/// App middleware which applies the conventions of each API version to its responses.
+///
+/// Responses from `rollup` and `rollup/v0` are marked deprecated, with a `Link` to `rollup/v1`
+/// and, if configured, the date on which v0 is retired in a `Sunset` header. Errors from
+/// `rollup/v1` are wrapped in an [`ErrorEnvelope`].
+#[derive(Clone, Debug, Default)]
+pub struct VersionedResponses {
+    /// HTTP date after which `rollup/v0` may be removed.
+    pub v0_sunset: Option<String>,
+}
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> tide::Middleware<S> for VersionedResponses {
+    async fn handle(&self, req: tide::Request<S>, next: tide::Next<'_, S>) -> tide::Result {
+        let Some(version) = api_version_of(req.url().path()) else {
+            return Ok(next.run(req).await);
+        };
+        let mut res = next.run(req).await;
+        match version {
+            ApiVersion::V0 => {
+                res.insert_header("Deprecation", "true");
+                if let Some(sunset) = &self.v0_sunset {
+                    res.insert_header("Sunset", sunset.as_str());
+                }
+                res.insert_header("Link", "</rollup/v1>; rel=\"successor-version\"");
+            }
+            ApiVersion::V1 if res.status().is_client_error() || res.status().is_server_error() => {
+                let body = res.take_body().into_bytes().await?;
+                let message = match serde_json::from_slice::<serde_json::Value>(&body) {
+                    Ok(serde_json::Value::Object(error)) => match error.get("message") {
+                        Some(serde_json::Value::String(message)) => message.clone(),
+                        _ => serde_json::Value::Object(error).to_string(),
+                    },
+                    _ => String::from_utf8_lossy(&body).into_owned(),
+                };
+                let error = ErrorDetails::new(res.status() as u16, message);
+                res.set_body(tide::Body::from_json(&ErrorEnvelope { error })?);
+            }
+            ApiVersion::V1 => {}
+        }
+        Ok(res)
+    }
+}
+
+type RouteRegistration = Arc<
+    dyn Fn(&mut RollupApi, Arc<Vec<Arc<dyn Middleware>>>) -> Result<(), ApiError> + Send + Sync,
+>;
+
+/// Routes added to the rollup API by an embedding application.
+///
+/// Each extension provides route definitions, in the same format as `api.toml`, along with a
+/// function registering their handlers. The definitions are merged into the built-in ones before
+/// the API is constructed, so extension routes are served and documented alongside the built-in
+/// routes. Extension routes may not redefine built-in routes.
+#[derive(Clone, Default)]
+pub struct ApiExtensions {
+    routes: toml::Table,
+    registrations: Vec<RouteRegistration>,
+}
+
+impl std::fmt::Debug for ApiExtensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiExtensions")
+            .field("routes", &self.routes.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl ApiExtensions {
+    /// Add the routes defined in `toml`, registering their handlers with `register`.
+    ///
+    /// `register` is passed the request middleware, which handlers should run with
+    /// [`run_middleware`] before handling each request.
+    pub fn with_routes(
+        mut self,
+        toml: &str,
+        register: impl Fn(&mut RollupApi, Arc<Vec<Arc<dyn Middleware>>>) -> Result<(), ApiError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> io::Result<Self> {
+        let toml: toml::Table =
+            toml::from_str(toml).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        if let Some(toml::Value::Table(routes)) = toml.get("route") {
+            for (name, route) in routes {
+                if self.routes.insert(name.clone(), route.clone()).is_some() {
+                    return Err(duplicate_route(name));
+                }
+            }
+        }
+        self.registrations.push(Arc::new(register));
+        Ok(self)
+    }
+
+    /// Merge the extension routes into the route definitions in `api`.
+    fn merge(&self, api: &mut toml::Value) -> io::Result<()> {
+        let Some(toml::Value::Table(routes)) = api.get_mut("route") else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "API definition has no routes",
+            ));
+        };
+        for (name, route) in &self.routes {
+            if routes.insert(name.clone(), route.clone()).is_some() {
+                return Err(duplicate_route(name));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn duplicate_route(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("route {name} is defined more than once"),
+    )
+}
+
+/// A transaction to be signed by a seed identity and submitted on its behalf.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignAndSubmitRequest {
+    pub identity: SeedIdentity,
+    /// The transaction to sign. If its nonce is 0 or omitted, the next nonce of the identity is
+    /// filled in.
+    pub transaction: RollupTransaction,
+}
+
+/// A block to simulate, served by `rollup/simulate`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SimulationRequest {
+    /// Hashes of transactions held in the mempool, applied first, in the given order.
+    #[serde(default)]
+    pub mempool: Vec<H256>,
+    /// New transactions, applied after those from the mempool.
+    #[serde(default)]
+    pub transactions: Vec<SignedTransaction>,
+}
+
+/// The sequencer transaction carrying `transaction` in the rollup's `namespace`, countersigned by
+/// `operator_signer` if given.
+async fn sequencer_transaction(
+    namespace: NamespaceId,
     transaction: SignedTransaction,
-) -> Result<Commitment<Transaction>, ServerError> {
-    let raw_tx = transaction.encode();
-    let txn = Transaction::new(NamespaceId::from(1_u64), raw_tx);
-    let client: Client<ClientError, SequencerApiVersion> = Client::new(submit_url.clone());
-    client.connect(None).await;
-    client
-        .post::<()>("submit/submit")
-        .body_json(&txn)
-        .unwrap()
-        .send()
+    operator_signer: Option<&LocalWallet>,
+) -> Transaction {
+    let raw_tx = match operator_signer {
+        Some(operator) => OperatorEnvelope::new(transaction, operator).await.encode(),
+        None => transaction.encode(),
+    };
+    Transaction::new(namespace, raw_tx)
+}
+
+/// Post a sequencer transaction to the sequencer.
+pub(crate) async fn post_transaction(
+    http: &HttpClientPool,
+    submit_url: &Url,
+    txn: &Transaction,
+) -> Result<(), ServerError> {
+    http.client(submit_url)
         .await
-        .unwrap();
+        .post::<()>("submit/submit", txn)
+        .await
+        .map_err(|err| ServerError {
+            status: tide_disco::StatusCode::BAD_GATEWAY,
+            message: format!("Error submitting transaction to sequencer: {err}"),
+        })
+}
+
+/// Submit `transaction` to the sequencer in the rollup's `namespace`, countersigned by
+/// `operator_signer` if given.
+pub(crate) async fn submit_transaction(
+    http: &HttpClientPool,
+    submit_url: &Url,
+    namespace: NamespaceId,
+    transaction: SignedTransaction,
+    operator_signer: Option<&LocalWallet>,
+) -> Result<Commitment<Transaction>, ServerError> {
+    let txn = sequencer_transaction(namespace, transaction, operator_signer).await;
+    post_transaction(http, submit_url, &txn).await?;
     let tx_hash = txn.commit();
     Ok(tx_hash)
 }
 
-pub async fn serve(options: &APIOptions, state: Arc<RwLock<State>>) -> io::Result<()> {
+/// Submit `transaction` through the primary replica if `relay` has one, or else straight to the
+/// sequencer in the rollup's `namespace`, countersigned by `operator_signer` if given.
+///
+/// A submission `relayed` from another replica is never relayed again.
+async fn relay_or_submit(
+    relay: &TransactionRelay,
+    relayed: bool,
+    http: &HttpClientPool,
+    submit_url: &Url,
+    namespace: NamespaceId,
+    transaction: SignedTransaction,
+    operator_signer: Option<&LocalWallet>,
+) -> Result<Commitment<Transaction>, ServerError> {
+    if relayed {
+        relay.record_received().await;
+    } else if let Some(result) = relay.forward(&transaction).await {
+        match result {
+            Ok(commitment) => return Ok(commitment),
+            Err(err) if !should_fall_back(&err) => {
+                return Err(ServerError {
+                    status: err.status(),
+                    message: format!("Transaction rejected by primary: {err}"),
+                })
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "Unable to relay transaction {:?} to the primary, submitting it to the \
+                     sequencer: {err}",
+                    transaction.hash()
+                );
+                relay.record_fallback().await;
+            }
+        }
+    }
+    submit_transaction(http, submit_url, namespace, transaction, operator_signer).await
+}
+
+/// The path by which the `submit` and `sign-and-submit` routes submit transactions.
+#[derive(Clone)]
+struct SubmissionPath {
+    http: HttpClientPool,
+    sequencer_url: Url,
+    relay: TransactionRelay,
+    mempool: Mempool,
+    operator_signer: Option<LocalWallet>,
+    latency: LatencyTracker,
+}
+
+impl SubmissionPath {
+    /// Submit `transaction`, received at `received_ms`, after checking it against `state`.
+    ///
+    /// Transactions which can never succeed are rejected rather than sequenced. Errors which may
+    /// resolve as the state changes are not surfaced here, but a nonce ahead of the sender's is
+    /// held in the mempool until it is current, unless the transaction will be relayed to the
+    /// primary, which holds it instead.
+    async fn submit(
+        &self,
+        state: &State,
+        transaction: SignedTransaction,
+        relayed: bool,
+        received_ms: u64,
+    ) -> Result<Commitment<Transaction>, ServerError> {
+        let hash = transaction.hash();
+        match state.simulate([&transaction]).results.pop() {
+            Some(Err(err)) if !err.is_retryable() => return Err(rejected(&err)),
+            Some(Err(RollupError::InvalidNonce {
+                address, actual, ..
+            })) if self.mempool.is_enabled() && (relayed || self.relay.primary().is_none()) => {
+                let fee = transaction.transaction.fee;
+                let txn = sequencer_transaction(
+                    state.vm.into(),
+                    transaction,
+                    self.operator_signer.as_ref(),
+                )
+                .await;
+                let commitment = self.mempool.hold(address, actual, hash, fee, txn).await?;
+                if relayed {
+                    self.relay.record_received().await;
+                }
+                self.latency.record_received(hash, received_ms).await;
+                return Ok(commitment);
+            }
+            _ => {}
+        }
+        let commitment = relay_or_submit(
+            &self.relay,
+            relayed,
+            &self.http,
+            &self.sequencer_url,
+            state.vm.into(),
+            transaction,
+            self.operator_signer.as_ref(),
+        )
+        .await?;
+        self.latency.record_received(hash, received_ms).await;
+        self.latency.record_submitted(hash, unix_millis()).await;
+        Ok(commitment)
+    }
+}
+
+/// Static information about the rollup served by this node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollupInfo {
+    pub namespace: NamespaceId,
+    /// Public URL of this node's API, if one is configured.
+    pub api_url: Option<Url>,
+    pub block_height: u64,
+    pub replay_protection: ReplayProtection,
+    /// Number of blocks for which executed transactions are remembered, if `replay_protection` is
+    /// `recent-hashes`.
+    pub replay_window: u64,
+    pub submission_policy: SubmissionPolicy,
+    pub ordering_policy: OrderingPolicy,
+    /// The rollup chain for which transactions should be signed, once the rollup contract is
+    /// deployed.
+    pub chain_id: Option<ChainId>,
+}
+
+impl RollupInfo {
+    fn new(state: &State, api_url: Option<Url>) -> Self {
+        Self {
+            namespace: state.vm.into(),
+            api_url,
+            block_height: state.block_height(),
+            replay_protection: state.replay_protection(),
+            replay_window: state.replay_window(),
+            submission_policy: state.submission_policy(),
+            ordering_policy: state.ordering_policy(),
+            chain_id: state.chain_id(),
+        }
+    }
+}
+
+/// Maximum number of addresses in a single `balances` request.
+pub const MAX_BALANCES_QUERY: usize = 1000;
+
+/// The balance and nonce of an account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountSummary {
+    pub address: Address,
+    pub balance: Amount,
+    pub nonce: Nonce,
+}
+
+/// The balances and nonces of several accounts, read from the state after `block_height`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balances {
+    pub block_height: u64,
+    /// One entry for each requested address, in the order requested.
+    pub accounts: Vec<AccountSummary>,
+}
+
+/// Blocks which have been executed by this node but not yet verified on the L1.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingBatches {
+    /// Height of the most recent block executed by this node.
+    pub latest_executed_block: u64,
+    /// Last block of the most recent batch confirmed on the L1.
+    pub latest_confirmed_block: Option<u64>,
+    /// Batches which have been proven but not yet confirmed, in order. Blocks executed after the
+    /// last of these batches are awaiting aggregation into a batch.
+    pub batches: Vec<PendingBatch>,
+    /// Whether proof submission is paused, in which case batches are buffered until it resumes.
+    #[serde(default)]
+    pub submission_paused: bool,
+    /// Results of comparing each batch with the rollup contract's state before submitting it.
+    #[serde(default)]
+    pub state_checks: StateCheckStats,
+    /// Nonces of the L1 transactions submitting batches.
+    #[serde(default)]
+    pub nonces: NonceStats,
+}
+
+/// Handles to the node subsystems backing API routes other than those served directly from the
+/// rollup state.
+#[derive(Clone, Debug, Default)]
+pub struct ApiServices {
+    pub finality_lag: FinalityLagTracker,
+    pub latency: LatencyTracker,
+    pub events: EventIndex,
+    pub fanout: EventFanout,
+    pub checkpoints: CheckpointStore,
+    pub commitments: CommitmentIndex,
+    pub history: AccountHistory,
+    pub transactions: TransactionIndex,
+    pub receipts: ReceiptIndex,
+    pub execution_stats: ExecutionStatsIndex,
+    pub outbox: Outbox,
+    pub snapshots: SnapshotExporter,
+    pub webhooks: WebhookRegistry,
+    pub watchdog: ExecutionWatchdog,
+    pub da_incidents: DaIncidentLog,
+    pub spot_audits: SpotAuditLog,
+    pub catch_up: CatchUpTracker,
+    pub proof_verifier: ProofVerifier,
+    pub relay: TransactionRelay,
+    pub mempool: Mempool,
+    /// Fees of recently accepted transactions, for `estimate-fee`.
+    pub fees: FeeHistory,
+    /// If set, block proofs are aggregated by an external service, which returns batch proofs to
+    /// the `aggregated_batch` route.
+    pub aggregator: Option<ExternalAggregator>,
+    /// Served in the Prometheus text format at `status/metrics`.
+    pub metrics: NodeMetrics,
+    pub canary: Option<Canary>,
+    /// If set, submissions must carry a token registered to their sender.
+    pub submitters: Option<SubmitterRegistry>,
+}
+
+/// Content type of CBOR encoded request and response bodies.
+pub const CBOR_CONTENT_TYPE: &str = "application/cbor";
+
+/// The account of `address` in the state at `height`.
+///
+/// The state is looked up in the retained history, falling back to replaying the ledger of the
+/// current state for heights which have left the history window.
+async fn account_at(
+    history: &AccountHistory,
+    state: &State,
+    address: &Address,
+    height: u64,
+) -> Result<Account, ServerError> {
+    match history.account_at(address, height).await {
+        Ok(account) => Ok(account),
+        Err(_) => state
+            .account_at(address, height)
+            .map_err(|err| ServerError {
+                status: tide_disco::StatusCode::NOT_FOUND,
+                message: err.to_string(),
+            }),
+    }
+}
+
+/// The value of the `Authorization` header of a request, if any.
+fn authorization(req: &RequestParams) -> Option<String> {
+    req.headers()
+        .get("Authorization")
+        .map(|value| value.last().as_str().to_string())
+}
+
+/// Decode a request body according to its `Content-Type`.
+///
+/// In addition to the JSON and bincode encodings supported by tide-disco, request bodies may be
+/// CBOR encoded by setting the content type to [`CBOR_CONTENT_TYPE`]. Responses are JSON unless
+/// the client requests bincode with `Accept: application/octet-stream`, or CBOR with
+/// `Accept: application/cbor` (see [`CborResponses`](crate::middleware::CborResponses)).
+fn decode_body<T: DeserializeOwned>(req: &RequestParams) -> Result<T, ServerError> {
+    let is_cbor = req
+        .headers()
+        .get("Content-Type")
+        .is_some_and(|content_type| content_type.last().as_str().starts_with(CBOR_CONTENT_TYPE));
+    if is_cbor {
+        ciborium::from_reader(req.body_bytes().as_slice()).map_err(|err| ServerError {
+            status: tide_disco::StatusCode::BAD_REQUEST,
+            message: format!("Malformed CBOR body: {err}"),
+        })
+    } else {
+        req.body_auto::<T, SequencerApiVersion>(SequencerApiVersion {})
+            .map_err(ServerError::from)
+    }
+}
+
+/// Resolve the `address` parameter of a request, which may be a hex encoded address or an alias.
+fn address_param(req: &RequestParams, address_book: &AddressBook) -> Result<Address, ServerError> {
+    let address = req.string_param("address")?;
+    address_book.resolve(address).map_err(|err| ServerError {
+        status: tide_disco::StatusCode::BAD_REQUEST,
+        message: err.to_string(),
+    })
+}
+
+/// Keep `snapshot` in sync with the states published on the executor's output stream.
+///
+/// The executor only publishes its state at block boundaries, so serving the API from `snapshot`
+/// rather than from the executor's working state guarantees that readers never observe a
+/// partially applied block.
+///
+/// Per-block data derived from the published states, such as events, is indexed in `services`.
+pub async fn follow_executor(
+    mut updates: BroadcastReceiver<(u64, State)>,
+    snapshot: Arc<RwLock<State>>,
+    services: ApiServices,
+) {
+    while let Ok((block_height, state)) = updates.recv_async().await {
+        tracing::debug!("API state updated to block {block_height}");
+        let block = BlockInfo::of(&state);
+        services
+            .events
+            .insert(block_height, state.block_events().to_vec())
+            .await;
+        services.fanout.publish(block, state.block_events()).await;
+        let mut receipts = Receipt::for_block(&state);
+        services
+            .latency
+            .record_executed(
+                block_height,
+                state.block_timestamp() * 1000,
+                unix_millis(),
+                receipts.iter().map(|receipt| receipt.hash),
+            )
+            .await;
+        for receipt in &mut receipts {
+            if let Some(timings) = services.latency.timings(&receipt.hash).await {
+                receipt.timings = timings;
+            }
+        }
+        services.receipts.insert_block(block_height, receipts).await;
+        services.fees.record_block(&state).await;
+        if let Err(err) = services
+            .transactions
+            .insert_block(block_height, TransactionRecord::for_block(&state))
+            .await
+        {
+            tracing::error!("Unable to index transactions of block {block_height}: {err}");
+        }
+        services
+            .execution_stats
+            .insert(BlockExecutionStats::new(
+                block_height,
+                state.block_metrics(),
+            ))
+            .await;
+        let commitment = state.commit();
+        services.commitments.insert(block_height, commitment).await;
+        services
+            .history
+            .insert(block, state.ledger().shared_accounts())
+            .await;
+        services.checkpoints.record(block_height, commitment).await;
+        let mut snapshot = snapshot.write().await;
+        services.webhooks.notify(&snapshot, &state).await;
+        *snapshot = state;
+    }
+    tracing::warn!("Executor output stream closed, API state will no longer be updated");
+}
+
+/// Build the rollup API module, serving responses in the format of `version`.
+fn rollup_api(
+    options: &APIOptions,
+    services: &ApiServices,
+    version: ApiVersion,
+) -> io::Result<RollupApi> {
     type StateType = Arc<RwLock<State>>;
     let error_mapper = |err| io::Error::new(io::ErrorKind::Other, err);
     let APIOptions {
-        api_port,
+        advertise_url,
         sequencer_url,
+        middleware,
+        address_book,
+        dev_signing,
+        submission_control,
+        rng,
+        extensions,
+        operator_signer,
+        http,
+        config,
         ..
     } = options.clone();
-    let mut app = App::<StateType, ServerError>::with_state(state);
-    let toml = toml::from_str::<toml::Value>(include_str!("api.toml"))
+    let middleware = Arc::new(middleware);
+    let address_book = Arc::new(address_book);
+    let responder = Responder {
+        version,
+        commitments: services.commitments.clone(),
+    };
+    let mut toml = toml::from_str::<toml::Value>(include_str!("api.toml"))
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    extensions.merge(&mut toml)?;
     let mut api =
         Api::<StateType, ServerError, SequencerApiVersion>::new(toml).map_err(error_mapper)?;
 
-    api.post("submit",  move|req, _state| {
-        let url = sequencer_url.clone();
-        async move {
-            let transaction = req
-                .body_auto::<SignedTransaction, SequencerApiVersion>(SequencerApiVersion {}).
+    let submission = SubmissionPath {
+        http: http.clone(),
+        sequencer_url: sequencer_url.clone(),
+        relay: services.relay.clone(),
+        mempool: services.mempool.clone(),
+        operator_signer: operator_signer.clone(),
+        latency: services.latency.clone(),
+    };
+
+    let submit_middleware = middleware.clone();
+    let submit_submission = submission.clone();
+    let submit_submitters = services.submitters.clone();
+    let respond = responder.clone();
+    api.post("submit", move |req, state| {
+        let middleware = submit_middleware.clone();
+        let submission = submit_submission.clone();
+        let submitters = submit_submitters.clone();
+        respond.wrap(state, async move {
+            let received_ms = unix_millis();
+            run_middleware(&middleware, "submit", &req)?;
+            let transaction = decode_body::<SignedTransaction>(&req).
             map_err(|_| ServerError {
                 status: tide_disco::StatusCode::BAD_REQUEST,
-                message: "Malformed transaction. Ensure that the transaction is a JSON serialized SignedTransaction".into()
+                message: "Malformed transaction. Ensure that the transaction is a JSON, CBOR or bincode serialized SignedTransaction".into()
             })?;
-             submit_transaction(url, transaction).await
-        }
-        .boxed()
+            let relayed = req.headers().get(RELAYED_HEADER).is_some();
+            // A submission relayed from another replica was checked against the tokens registered
+            // with that replica.
+            if let Some(submitters) = submitters.as_ref().filter(|_| !relayed) {
+                let sender = transaction.recover().map_err(|err| ServerError {
+                    status: tide_disco::StatusCode::BAD_REQUEST,
+                    message: format!("Transaction is not validly signed: {err}"),
+                })?;
+                submitters
+                    .authorize(authorization(&req).as_deref(), sender)
+                    .await?;
+            }
+            submission
+                .submit(state, transaction, relayed, received_ms)
+                .await
+        })
     })
     .map_err(error_mapper)?;
 
-    api.get("balance", |req, state| {
-        async move {
-            let address_str = req.string_param("address")?;
-            let address = address_str.parse::<Address>().
-            map_err(|_| ServerError {
+    let sign_middleware = middleware.clone();
+    let sign_submitters = services.submitters.clone();
+    let sign_mempool = services.mempool.clone();
+    // The last nonce filled in for each identity, which may not have executed yet.
+    let signed_nonces = Arc::new(RwLock::new(HashMap::<Address, Nonce>::new()));
+    let respond = responder.clone();
+    api.post("sign_and_submit", move |req, state| {
+        let middleware = sign_middleware.clone();
+        let submission = submission.clone();
+        let submitters = sign_submitters.clone();
+        let mempool = sign_mempool.clone();
+        let signed_nonces = signed_nonces.clone();
+        let rng = rng.clone();
+        respond.wrap(state, async move {
+            let received_ms = unix_millis();
+            run_middleware(&middleware, "sign_and_submit", &req)?;
+            if !dev_signing {
+                return Err(ServerError {
+                    status: tide_disco::StatusCode::FORBIDDEN,
+                    message: "Server-side signing is disabled on this node".into(),
+                });
+            }
+            let SignAndSubmitRequest {
+                identity,
+                mut transaction,
+            } = decode_body::<SignAndSubmitRequest>(&req)?;
+            let wallet = identity.wallet();
+            let address = wallet.address();
+            if let Some(submitters) = &submitters {
+                submitters
+                    .authorize(authorization(&req).as_deref(), address)
+                    .await?;
+            }
+            // A transaction which is only deduplicated for the replay window must expire within it.
+            if state.replay_protection() == ReplayProtection::RecentHashes {
+                transaction
+                    .expires_at
+                    .get_or_insert(state.block_height() + state.replay_window());
+            }
+            // Requests which fill in the nonce are serialized, so that each takes the nonce after
+            // the previous one's, whether that transaction has executed, is held in the mempool or
+            // is still on its way to the sequencer.
+            let mut signed_nonces = signed_nonces.write().await;
+            let fill_nonce = transaction.nonce == 0;
+            if fill_nonce {
+                transaction.nonce = match state.replay_protection() {
+                    ReplayProtection::Nonce => {
+                        let executed = state.get_nonce(&address);
+                        let held = mempool.highest_nonce(&address).await.unwrap_or_default();
+                        let signed = signed_nonces.get(&address).copied().unwrap_or_default();
+                        executed.max(held).max(signed) + 1
+                    }
+                    // Without sequential nonces, the nonce is only a salt distinguishing otherwise
+                    // identical transfers.
+                    ReplayProtection::RecentHashes => rng.random(),
+                };
+            }
+            let nonce = transaction.nonce;
+            let signed_transaction = match state.chain_id() {
+                Some(chain_id) => {
+                    SignedTransaction::new_for_chain(transaction, chain_id, &wallet).await
+                }
+                None => SignedTransaction::new(transaction, &wallet).await,
+            };
+            let commitment = submission
+                .submit(state, signed_transaction, false, received_ms)
+                .await?;
+            if fill_nonce && state.replay_protection() == ReplayProtection::Nonce {
+                signed_nonces.insert(address, nonce);
+            }
+            Ok(commitment)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let simulate_middleware = middleware.clone();
+    let respond = responder.clone();
+    let simulate_mempool = services.mempool.clone();
+    api.post("simulate", move |req, state| {
+        let middleware = simulate_middleware.clone();
+        let mempool = simulate_mempool.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "simulate", &req)?;
+            // A bare array of transactions is still accepted, as before mempool hashes could be
+            // given.
+            let request = decode_body::<SimulationRequest>(&req)
+                .or_else(|_| {
+                    decode_body::<Vec<SignedTransaction>>(&req).map(|transactions| {
+                        SimulationRequest {
+                            transactions,
+                            ..Default::default()
+                        }
+                    })
+                })
+                .map_err(|_| ServerError {
+                    status: tide_disco::StatusCode::BAD_REQUEST,
+                    message: "Malformed simulation. Ensure that the body is a JSON, CBOR or bincode serialized SimulationRequest or array of SignedTransactions".into(),
+                })?;
+            let mut transactions = vec![];
+            for hash in &request.mempool {
+                let held = mempool.get(hash).await.ok_or_else(|| ServerError {
+                    status: tide_disco::StatusCode::NOT_FOUND,
+                    message: format!("Transaction {hash:?} is not held in the mempool"),
+                })?;
+                let submission = Submission::decode(held.payload()).map_err(|err| ServerError {
+                    status: tide_disco::StatusCode::INTERNAL_SERVER_ERROR,
+                    message: format!("Held transaction {hash:?} is malformed: {err}"),
+                })?;
+                transactions.push(submission.transaction().clone());
+            }
+            transactions.extend(request.transactions);
+            Ok(state.simulate(&transactions))
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let register_submitter_middleware = middleware.clone();
+    let submitters = services.submitters.clone();
+    let respond = responder.clone();
+    api.post("register_submitter", move |req, state| {
+        let middleware = register_submitter_middleware.clone();
+        let submitters = submitters.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "register_submitter", &req)?;
+            let submitters = submitters.ok_or_else(|| ServerError {
+                status: tide_disco::StatusCode::NOT_FOUND,
+                message: "This node does not require submitter tokens".into(),
+            })?;
+            let registration = decode_body::<SubmitterRegistration>(&req)?;
+            Ok(submitters.register(registration).await?)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let subscribe_middleware = middleware.clone();
+    let webhooks = services.webhooks.clone();
+    let respond = responder.clone();
+    api.post("subscribe", move |req, state| {
+        let middleware = subscribe_middleware.clone();
+        let webhooks = webhooks.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "subscribe", &req)?;
+            let registration = decode_body::<WebhookRegistration>(&req)?;
+            webhooks
+                .register(registration)
+                .await
+                .map_err(|err| ServerError {
+                    status: match err {
+                        WebhookError::InvalidSignature { .. } => {
+                            tide_disco::StatusCode::UNAUTHORIZED
+                        }
+                        WebhookError::TooManyWebhooks { .. } => {
+                            tide_disco::StatusCode::TOO_MANY_REQUESTS
+                        }
+                    },
+                    message: err.to_string(),
+                })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let trace_middleware = middleware.clone();
+    let respond = responder.clone();
+    api.post("trace_tx", move |req, state| {
+        let middleware = trace_middleware.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "trace_tx", &req)?;
+            Ok(trace_transaction(state, &req.body_bytes()))
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let balance_middleware = middleware.clone();
+    let balance_address_book = address_book.clone();
+    let balance_history = services.history.clone();
+    let respond = responder.clone();
+    api.get("balance", move |req, state| {
+        let middleware = balance_middleware.clone();
+        let address_book = balance_address_book.clone();
+        let history = balance_history.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "balance", &req)?;
+            let address = address_param(&req, &address_book)?;
+            match req.opt_integer_param("height")? {
+                Some(height) => Ok(account_at(&history, &state, &address, height)
+                    .await?
+                    .balance),
+                None => Ok(state.get_balance(&address)),
+            }
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let nonce_middleware = middleware.clone();
+    let nonce_address_book = address_book.clone();
+    let nonce_history = services.history.clone();
+    let respond = responder.clone();
+    api.get("nonce", move |req, state| {
+        let middleware = nonce_middleware.clone();
+        let address_book = nonce_address_book.clone();
+        let history = nonce_history.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "nonce", &req)?;
+            let address = address_param(&req, &address_book)?;
+            match req.opt_integer_param("height")? {
+                Some(height) => Ok(account_at(&history, &state, &address, height).await?.nonce),
+                None => Ok(state.get_nonce(&address)),
+            }
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let balances_middleware = middleware.clone();
+    let balances_address_book = address_book.clone();
+    let respond = responder.clone();
+    api.post("balances", move |req, state| {
+        let middleware = balances_middleware.clone();
+        let address_book = balances_address_book.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "balances", &req)?;
+            let addresses = decode_body::<Vec<String>>(&req).map_err(|_| ServerError {
                 status: tide_disco::StatusCode::BAD_REQUEST,
-                message: "Malformed address. Ensure that the address is valid hex encoded Ethereum address.".into()
+                message: "Malformed request. Ensure that the body is an array of addresses".into(),
             })?;
-            let balance = state.get_balance(&address);
-            Ok(balance)
-        }
-        .boxed()
+            if addresses.len() > MAX_BALANCES_QUERY {
+                return Err(ServerError {
+                    status: tide_disco::StatusCode::BAD_REQUEST,
+                    message: format!(
+                        "Too many addresses: {} requested, at most {MAX_BALANCES_QUERY} allowed",
+                        addresses.len()
+                    ),
+                });
+            }
+            let accounts = addresses
+                .iter()
+                .map(|address| {
+                    let address = address_book.resolve(address).map_err(|err| ServerError {
+                        status: tide_disco::StatusCode::BAD_REQUEST,
+                        message: err.to_string(),
+                    })?;
+                    Ok(AccountSummary {
+                        address,
+                        balance: state.get_balance(&address),
+                        nonce: state.get_nonce(&address),
+                    })
+                })
+                .collect::<Result<_, ServerError>>()?;
+            Ok(Balances {
+                block_height: state.block_height(),
+                accounts,
+            })
+        })
     })
     .map_err(error_mapper)?;
 
-    api.get("nonce", |req, state| {
-        async move {
-            let address_str = req.string_param("address")?;
-            let address = address_str.parse::<Address>().
-            map_err(|_| ServerError {
+    let finality_lag_middleware = middleware.clone();
+    let finality_lag = services.finality_lag.clone();
+    let respond = responder.clone();
+    api.get("finality_lag", move |req, state| {
+        let middleware = finality_lag_middleware.clone();
+        let finality_lag = finality_lag.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "finality_lag", &req)?;
+            Ok(finality_lag.report().await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let snapshot_middleware = middleware.clone();
+    let snapshots = services.snapshots.clone();
+    let snapshot_commitments = services.commitments.clone();
+    let respond = responder.clone();
+    api.post("snapshot", move |req, state| {
+        let middleware = snapshot_middleware.clone();
+        let snapshots = snapshots.clone();
+        let commitments = snapshot_commitments.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "snapshot", &req)?;
+            let commitment = commitments.get(state.block_height()).await;
+            snapshots
+                .start(state, commitment)
+                .await
+                .map_err(|err| ServerError {
+                    status: match err {
+                        SnapshotError::Disabled => tide_disco::StatusCode::FORBIDDEN,
+                        SnapshotError::Busy => tide_disco::StatusCode::CONFLICT,
+                        _ => tide_disco::StatusCode::INTERNAL_SERVER_ERROR,
+                    },
+                    message: err.to_string(),
+                })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let snapshot_status_middleware = middleware.clone();
+    let snapshots = services.snapshots.clone();
+    let respond = responder.clone();
+    api.get("snapshot_status", move |req, state| {
+        let middleware = snapshot_status_middleware.clone();
+        let snapshots = snapshots.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "snapshot_status", &req)?;
+            Ok(snapshots.status().await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let latency_middleware = middleware.clone();
+    let latency = services.latency.clone();
+    let respond = responder.clone();
+    api.get("latency", move |req, state| {
+        let middleware = latency_middleware.clone();
+        let latency = latency.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "latency", &req)?;
+            Ok(latency.report().await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let proof_verification_middleware = middleware.clone();
+    let proof_verifier = services.proof_verifier.clone();
+    let respond = responder.clone();
+    api.get("proof_verification", move |req, state| {
+        let middleware = proof_verification_middleware.clone();
+        let proof_verifier = proof_verifier.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "proof_verification", &req)?;
+            Ok(proof_verifier.stats().await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let catch_up_middleware = middleware.clone();
+    let catch_up = services.catch_up.clone();
+    let respond = responder.clone();
+    api.get("catch_up", move |req, state| {
+        let middleware = catch_up_middleware.clone();
+        let catch_up = catch_up.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "catch_up", &req)?;
+            Ok(catch_up.progress().await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let canary_middleware = middleware.clone();
+    let canary = services.canary.clone();
+    let respond = responder.clone();
+    api.get("canary", move |req, state| {
+        let middleware = canary_middleware.clone();
+        let canary = canary.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "canary", &req)?;
+            Ok(match canary {
+                Some(canary) => Some(canary.report().await),
+                None => None,
+            })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let config_middleware = middleware.clone();
+    let respond = responder.clone();
+    api.get("config", move |req, state| {
+        let middleware = config_middleware.clone();
+        let config = config.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "config", &req)?;
+            Ok(config)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let batch_middleware = middleware.clone();
+    let aggregator = services.aggregator.clone();
+    let respond = responder.clone();
+    api.post("aggregated_batch", move |req, state| {
+        let middleware = batch_middleware.clone();
+        let aggregator = aggregator.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "aggregated_batch", &req)?;
+            let Some(aggregator) = aggregator else {
+                return Err(ServerError {
+                    status: tide_disco::StatusCode::NOT_FOUND,
+                    message: "No external aggregator is configured on this node".into(),
+                });
+            };
+            let batch = decode_body::<BatchProof>(&req)?;
+            Ok(aggregator.accept(batch).await?)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let mempool_middleware = middleware.clone();
+    let mempool_address_book = address_book.clone();
+    let mempool = services.mempool.clone();
+    let respond = responder.clone();
+    api.get("mempool", move |req, state| {
+        let middleware = mempool_middleware.clone();
+        let address_book = mempool_address_book.clone();
+        let mempool = mempool.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "mempool", &req)?;
+            let address = address_param(&req, &address_book)?;
+            Ok(mempool.pending(&address).await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let estimate_fee_middleware = middleware.clone();
+    let estimate_fee_mempool = services.mempool.clone();
+    let fees = services.fees.clone();
+    let respond = responder.clone();
+    api.get("estimate_fee", move |req, state| {
+        let middleware = estimate_fee_middleware.clone();
+        let mempool = estimate_fee_mempool.clone();
+        let fees = fees.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "estimate_fee", &req)?;
+            Ok(FeeEstimate::new(&state.submission_policy(), &mempool, &fees).await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let relay_middleware = middleware.clone();
+    let relay = services.relay.clone();
+    let respond = responder.clone();
+    api.get("relay", move |req, state| {
+        let middleware = relay_middleware.clone();
+        let relay = relay.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "relay", &req)?;
+            Ok(relay.stats().await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let events_middleware = middleware.clone();
+    let events_address_book = address_book.clone();
+    let events = services.events.clone();
+    let respond = responder.clone();
+    api.get("block_events", move |req, state| {
+        let middleware = events_middleware.clone();
+        let address_book = events_address_book.clone();
+        let events = events.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "block_events", &req)?;
+            let height = req.integer_param("height")?;
+            let filter = match req.opt_string_param("topic")? {
+                Some(topic) => Some(match topic.parse::<EventKind>() {
+                    Ok(kind) => EventFilter::Kind(kind),
+                    Err(_) => EventFilter::Address(address_book.resolve(topic).map_err(|err| {
+                        ServerError {
+                            status: tide_disco::StatusCode::BAD_REQUEST,
+                            message: err.to_string(),
+                        }
+                    })?),
+                }),
+                None => None,
+            };
+            events.get(height, filter).await.ok_or_else(|| ServerError {
+                status: tide_disco::StatusCode::NOT_FOUND,
+                message: format!("Block {height} has not been executed or is no longer retained."),
+            })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let stream_middleware = middleware.clone();
+    let fanout = services.fanout.clone();
+    api.socket(
+        "stream_events",
+        move |req,
+              mut conn: Connection<StreamMessage, SubscriptionRequest, ServerError, _>,
+              _state| {
+            let middleware = stream_middleware.clone();
+            let fanout = fanout.clone();
+            async move {
+                run_middleware(&middleware, "stream_events", &req)?;
+                let socket_error = |err: SocketError<ServerError>| ServerError {
+                    status: tide_disco::StatusCode::INTERNAL_SERVER_ERROR,
+                    message: err.to_string(),
+                };
+                let request = match conn.next().await {
+                    Some(request) => request.map_err(socket_error)?,
+                    None => return Ok(()),
+                };
+                let mut subscription = fanout.open(request).await.map_err(|err| ServerError {
+                    status: tide_disco::StatusCode::BAD_REQUEST,
+                    message: err.to_string(),
+                })?;
+                conn.send(&StreamMessage::Subscribed {
+                    subscription_id: subscription.id,
+                    next_seq: subscription.next_seq,
+                })
+                .await
+                .map_err(socket_error)?;
+                while let Some(event) = subscription.events.next().await {
+                    conn.send(&StreamMessage::Event(event))
+                        .await
+                        .map_err(socket_error)?;
+                }
+                Ok(())
+            }
+            .boxed()
+        },
+    )
+    .map_err(error_mapper)?;
+
+    let pending_middleware = middleware.clone();
+    let outbox = services.outbox.clone();
+    let respond = responder.clone();
+    api.get("pending_batches", move |req, state| {
+        let middleware = pending_middleware.clone();
+        let outbox = outbox.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "pending_batches", &req)?;
+            Ok(PendingBatches {
+                latest_executed_block: state.block_height(),
+                latest_confirmed_block: outbox.latest_confirmed().await,
+                batches: outbox.pending().await,
+                submission_paused: outbox.is_paused().await,
+                state_checks: outbox.state_checks().await,
+                nonces: outbox.nonces().await,
+            })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    for (route, pause) in [("pause_submission", true), ("resume_submission", false)] {
+        let middleware = middleware.clone();
+        let outbox = services.outbox.clone();
+        let respond = responder.clone();
+        api.post(route, move |req, state| {
+            let middleware = middleware.clone();
+            let outbox = outbox.clone();
+            respond.wrap(state, async move {
+                run_middleware(&middleware, route, &req)?;
+                if !submission_control {
+                    return Err(ServerError {
+                        status: tide_disco::StatusCode::FORBIDDEN,
+                        message: "Submission control is disabled on this node".into(),
+                    });
+                }
+                if pause {
+                    outbox.pause().await;
+                } else {
+                    outbox.resume().await;
+                }
+                Ok(outbox.is_paused().await)
+            })
+        })
+        .map_err(error_mapper)?;
+    }
+
+    let receipt_middleware = middleware.clone();
+    let receipts = services.receipts.clone();
+    let receipt_latency = services.latency.clone();
+    let receipt_relay = services.relay.clone();
+    let respond = responder.clone();
+    api.get("receipt", move |req, state| {
+        let middleware = receipt_middleware.clone();
+        let receipts = receipts.clone();
+        let latency = receipt_latency.clone();
+        let relay = receipt_relay.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "receipt", &req)?;
+            let hash = req.string_param("hash")?;
+            let hash = hash.parse::<H256>().map_err(|err| ServerError {
                 status: tide_disco::StatusCode::BAD_REQUEST,
-                message: "Malformed address. Ensure that the address is valid hex encoded Ethereum address.".into()
+                message: format!("Malformed transaction hash {hash}: {err}"),
             })?;
-            let nonce = state.get_nonce(&address);
-            Ok(nonce)
-        }
-        .boxed()
+            // Another replica may have executed the transaction before this node caught up. Requests
+            // relayed from another replica are only answered locally, so lookups cannot loop.
+            let receipt = match receipts.get(&hash).await {
+                Some(receipt) => Some(receipt),
+                None if req.headers().get(RELAYED_HEADER).is_none() => {
+                    relay.fetch_receipt(hash).await
+                }
+                None => None,
+            };
+            let mut receipt = receipt.ok_or_else(|| ServerError {
+                status: tide_disco::StatusCode::NOT_FOUND,
+                message: format!("Transaction {hash:?} has not been executed."),
+            })?;
+            // The transaction may have been verified on the L1 since it was executed.
+            if let Some(timings) = latency.timings(&hash).await {
+                receipt.timings = timings;
+            }
+            Ok(receipt)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let status_middleware = middleware.clone();
+    let status_receipts = services.receipts.clone();
+    let status_latency = services.latency.clone();
+    let status_relay = services.relay.clone();
+    let respond = responder.clone();
+    api.get("transaction_status", move |req, state| {
+        let middleware = status_middleware.clone();
+        let receipts = status_receipts.clone();
+        let latency = status_latency.clone();
+        let relay = status_relay.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "transaction_status", &req)?;
+            let hash = req.string_param("hash")?;
+            let hash = hash.parse::<H256>().map_err(|err| ServerError {
+                status: tide_disco::StatusCode::BAD_REQUEST,
+                message: format!("Malformed transaction hash {hash}: {err}"),
+            })?;
+            let receipt = match receipts.get(&hash).await {
+                Some(receipt) => Some(receipt),
+                None if req.headers().get(RELAYED_HEADER).is_none() => {
+                    relay.fetch_receipt(hash).await
+                }
+                None => None,
+            };
+            let timings = latency.timings(&hash).await;
+            ExecutionStatus::new(receipt.as_ref(), timings.as_ref()).ok_or_else(|| ServerError {
+                status: tide_disco::StatusCode::NOT_FOUND,
+                message: format!("Transaction {hash:?} is not known to this node."),
+            })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let inclusion_middleware = middleware.clone();
+    let inclusion_sequencer_url = sequencer_url.clone();
+    let inclusion_http = http.clone();
+    let respond = responder.clone();
+    api.get("inclusion_proof", move |req, state| {
+        let middleware = inclusion_middleware.clone();
+        let sequencer_url = inclusion_sequencer_url.clone();
+        let http = inclusion_http.clone();
+        let namespace: NamespaceId = state.vm.into();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "inclusion_proof", &req)?;
+            let hash = req.string_param("hash")?;
+            fetch_inclusion_proof(&http, &sequencer_url, hash, namespace)
+                .await
+                .map_err(|err| ServerError {
+                    status: tide_disco::StatusCode::BAD_GATEWAY,
+                    message: format!("Error querying sequencer: {err}"),
+                })?
+                .ok_or_else(|| ServerError {
+                    status: tide_disco::StatusCode::NOT_FOUND,
+                    message: format!("Transaction {hash} has not been sequenced in this rollup."),
+                })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let bundle_middleware = middleware.clone();
+    let bundle_receipts = services.receipts.clone();
+    let bundle_outbox = services.outbox.clone();
+    let bundle_sequencer_url = sequencer_url.clone();
+    let bundle_http = http.clone();
+    let respond = responder.clone();
+    api.get("receipt_bundle", move |req, state| {
+        let middleware = bundle_middleware.clone();
+        let receipts = bundle_receipts.clone();
+        let outbox = bundle_outbox.clone();
+        let sequencer_url = bundle_sequencer_url.clone();
+        let http = bundle_http.clone();
+        let namespace: NamespaceId = state.vm.into();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "receipt_bundle", &req)?;
+            let hash = req.string_param("hash")?;
+            let hash = hash.parse::<H256>().map_err(|err| ServerError {
+                status: tide_disco::StatusCode::BAD_REQUEST,
+                message: format!("Malformed transaction hash {hash}: {err}"),
+            })?;
+            let receipt = receipts.get(&hash).await.ok_or_else(|| ServerError {
+                status: tide_disco::StatusCode::NOT_FOUND,
+                message: format!("Transaction {hash:?} has not been executed."),
+            })?;
+            fetch_receipt_bundle(&http, &sequencer_url, namespace, receipt, &outbox)
+                .await
+                .map_err(|err| ServerError {
+                    status: tide_disco::StatusCode::BAD_GATEWAY,
+                    message: format!("Error querying sequencer: {err}"),
+                })?
+                .ok_or_else(|| ServerError {
+                    status: tide_disco::StatusCode::NOT_FOUND,
+                    message: format!(
+                        "Transaction {hash:?} is not a transfer sequenced in this rollup."
+                    ),
+                })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let commitment_middleware = middleware.clone();
+    let commitments = services.commitments.clone();
+    let respond = responder.clone();
+    api.get("block_commitment", move |req, state| {
+        let middleware = commitment_middleware.clone();
+        let commitments = commitments.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "block_commitment", &req)?;
+            let height = req.integer_param("height")?;
+            commitments.get(height).await.ok_or_else(|| ServerError {
+                status: tide_disco::StatusCode::NOT_FOUND,
+                message: format!("Block {height} has not been executed."),
+            })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let block_middleware = middleware.clone();
+    let history = services.history.clone();
+    let respond = responder.clone();
+    api.get("block", move |req, state| {
+        let middleware = block_middleware.clone();
+        let history = history.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "block", &req)?;
+            let height = req.integer_param("height")?;
+            history.block(height).await.map_err(|err| ServerError {
+                status: tide_disco::StatusCode::NOT_FOUND,
+                message: err.to_string(),
+            })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let block_stats_middleware = middleware.clone();
+    let execution_stats = services.execution_stats.clone();
+    let respond = responder.clone();
+    api.get("block_stats", move |req, state| {
+        let middleware = block_stats_middleware.clone();
+        let execution_stats = execution_stats.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "block_stats", &req)?;
+            let height = req.integer_param("height")?;
+            execution_stats
+                .get(height)
+                .await
+                .ok_or_else(|| ServerError {
+                    status: tide_disco::StatusCode::NOT_FOUND,
+                    message: format!("Block {height} has not been executed."),
+                })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let incidents_middleware = middleware.clone();
+    let watchdog = services.watchdog.clone();
+    let respond = responder.clone();
+    api.get("incidents", move |req, state| {
+        let middleware = incidents_middleware.clone();
+        let watchdog = watchdog.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "incidents", &req)?;
+            Ok(watchdog.incidents().await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let da_incidents_middleware = middleware.clone();
+    let da_incidents = services.da_incidents.clone();
+    let respond = responder.clone();
+    api.get("data_unavailability", move |req, state| {
+        let middleware = da_incidents_middleware.clone();
+        let da_incidents = da_incidents.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "data_unavailability", &req)?;
+            Ok(da_incidents.incidents().await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let spot_audit_middleware = middleware.clone();
+    let spot_audits = services.spot_audits.clone();
+    let respond = responder.clone();
+    api.get("spot_audit", move |req, state| {
+        let middleware = spot_audit_middleware.clone();
+        let spot_audits = spot_audits.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "spot_audit", &req)?;
+            Ok(spot_audits.report().await)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let diff_middleware = middleware.clone();
+    let history = services.history.clone();
+    let respond = responder.clone();
+    api.get("diff", move |req, state| {
+        let middleware = diff_middleware.clone();
+        let history = history.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "diff", &req)?;
+            let from_height = req.integer_param("from_height")?;
+            let to_height = req.integer_param("to_height")?;
+            history
+                .diff(from_height, to_height)
+                .await
+                .map_err(|err| ServerError {
+                    status: match err {
+                        HistoryError::InvalidRange { .. } => tide_disco::StatusCode::BAD_REQUEST,
+                        _ => tide_disco::StatusCode::NOT_FOUND,
+                    },
+                    message: err.to_string(),
+                })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let transactions_middleware = middleware.clone();
+    let transactions = services.transactions.clone();
+    let transactions_address_book = address_book.clone();
+    let respond = responder.clone();
+    api.get("transactions", move |req, state| {
+        let middleware = transactions_middleware.clone();
+        let transactions = transactions.clone();
+        let address_book = transactions_address_book.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "transactions", &req)?;
+            let bad_request = |message: String| ServerError {
+                status: tide_disco::StatusCode::BAD_REQUEST,
+                message,
+            };
+            let address = match req.opt_string_param("address")? {
+                Some(address) => Some(
+                    address_book
+                        .resolve(address)
+                        .map_err(|err| bad_request(err.to_string()))?,
+                ),
+                None => None,
+            };
+            let status = match req.opt_string_param("status")? {
+                Some(status) => Some(
+                    status
+                        .parse::<TransactionStatus>()
+                        .map_err(|_| bad_request(format!("Unknown status {status}.")))?,
+                ),
+                None => None,
+            };
+            let query = TransactionQuery {
+                address,
+                min_amount: req.opt_integer_param("min_amount")?,
+                max_amount: req.opt_integer_param("max_amount")?,
+                from_time: req.opt_integer_param("from_time")?,
+                to_time: req.opt_integer_param("to_time")?,
+                status,
+                limit: req
+                    .opt_integer_param("limit")?
+                    .unwrap_or(DEFAULT_QUERY_LIMIT),
+            };
+            transactions.query(&query).await.map_err(|err| ServerError {
+                status: if err.is_invalid_query() {
+                    tide_disco::StatusCode::BAD_REQUEST
+                } else {
+                    tide_disco::StatusCode::INTERNAL_SERVER_ERROR
+                },
+                message: err.to_string(),
+            })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let checkpoint_middleware = middleware.clone();
+    let checkpoints = services.checkpoints.clone();
+    let respond = responder.clone();
+    api.get("checkpoint", move |req, state| {
+        let middleware = checkpoint_middleware.clone();
+        let checkpoints = checkpoints.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "checkpoint", &req)?;
+            match req.opt_integer_param("height")? {
+                Some(height) => Ok(checkpoints.get(height).await),
+                None => Ok(checkpoints.latest().await),
+            }
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let schema_middleware = middleware.clone();
+    let api_schema = ApiSchema::generate();
+    let respond = responder.clone();
+    api.get("schema", move |req, state| {
+        let middleware = schema_middleware.clone();
+        let api_schema = api_schema.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "schema", &req)?;
+            Ok(api_schema)
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let info_middleware = middleware.clone();
+    let respond = responder.clone();
+    api.get("info", move |req, state| {
+        let middleware = info_middleware.clone();
+        let advertise_url = advertise_url.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "info", &req)?;
+            Ok(RollupInfo::new(state, advertise_url))
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let supply_middleware = middleware.clone();
+    let respond = responder.clone();
+    api.get("supply", move |req, state| {
+        let middleware = supply_middleware.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "supply", &req)?;
+            Ok(state.supply())
+        })
+    })
+    .map_err(error_mapper)?;
+
+    let withdrawal_middleware = middleware.clone();
+    let respond = responder.clone();
+    api.get("withdrawal", move |req, state| {
+        let middleware = withdrawal_middleware.clone();
+        respond.wrap(state, async move {
+            run_middleware(&middleware, "withdrawal", &req)?;
+            let index = req.integer_param("index")?;
+            let count = req.opt_integer_param("count")?;
+            state
+                .withdrawal_proof(index, count)
+                .ok_or_else(|| ServerError {
+                    status: tide_disco::StatusCode::NOT_FOUND,
+                    message: format!("There is no withdrawal {index} in the requested tree."),
+                })
+        })
+    })
+    .map_err(error_mapper)?;
+
+    for register in &extensions.registrations {
+        register(&mut api, middleware.clone()).map_err(error_mapper)?;
+    }
+    Ok(api)
+}
+
+/// The `status` API, which serves the executor metrics to Prometheus.
+fn status_api(metrics: NodeMetrics) -> io::Result<RollupApi> {
+    let error_mapper = |err| io::Error::new(io::ErrorKind::Other, err);
+    let toml = toml::from_str::<toml::Value>(include_str!("status.toml"))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let mut api = RollupApi::new(toml).map_err(error_mapper)?;
+    api.metrics("metrics", move |_, _| {
+        let metrics = metrics.clone();
+        async move { Ok(Cow::Owned(metrics)) }.boxed()
     })
     .map_err(error_mapper)?;
+    Ok(api)
+}
 
-    app.register_module("rollup", api)
+pub async fn serve(
+    options: &APIOptions,
+    state: Arc<RwLock<State>>,
+    services: ApiServices,
+) -> io::Result<()> {
+    let mut app = App::<Arc<RwLock<State>>, ServerError>::with_state(state);
+    for (base_url, version) in API_VERSIONS {
+        let api = rollup_api(options, &services, version)?;
+        app.register_module(base_url, api)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    }
+    app.register_module("status", status_api(services.metrics.clone())?)
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
-    app.serve(format!("0.0.0.0:{}", api_port), SequencerApiVersion {})
-        .await
+    let bind_addresses = &options.bind_addresses;
+    if bind_addresses.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no bind address for the API",
+        ));
+    }
+    let listeners = bind_addresses
+        .iter()
+        .map(|address| {
+            AppListener::new(
+                SocketAddr::new(*address, options.api_port),
+                vec![
+                    Arc::new(options.cors.clone()),
+                    Arc::new(CborResponses),
+                    Arc::new(VersionedResponses {
+                        v0_sunset: options.v0_sunset.clone(),
+                    }),
+                ],
+            )
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    app.serve(listeners, SequencerApiVersion {}).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::machine::RollupStateMachine;
+    use crate::mempool::PendingTransaction;
+    use crate::state::Simulation;
+    use crate::stats::{LatencyReport, LatencyStage};
     use crate::transaction::Transaction;
     use crate::RollupVM;
     use async_std::task::spawn;
@@ -123,6 +1883,7 @@ mod tests {
     use sequencer::api::Options;
     use sequencer::testing::wait_for_decide_on_handle;
     use sequencer::testing::TestConfigBuilder;
+    use surf_disco::error::ClientError;
     use surf_disco::Client;
 
     const GENESIS_BALANCE: u64 = 9999;
@@ -142,10 +1903,23 @@ mod tests {
         let client: Client<ClientError, SequencerApiVersion> = Client::new(api_url.clone());
         let options = APIOptions {
             api_port: port,
+            bind_addresses: vec![IpAddr::from([127, 0, 0, 1])],
+            advertise_url: None,
             sequencer_url: api_url,
+            middleware: vec![],
+            cors: Default::default(),
+            v0_sunset: None,
+            address_book: Default::default(),
+            dev_signing: false,
+            submission_control: false,
+            rng: Default::default(),
+            extensions: Default::default(),
+            operator_signer: None,
+            http: Default::default(),
+            config: Default::default(),
         };
 
-        spawn(async move { serve(&options, state).await });
+        spawn(async move { serve(&options, state, Default::default()).await });
 
         client.connect(None).await;
 
@@ -159,6 +1933,646 @@ mod tests {
         assert_eq!(balance, GENESIS_BALANCE);
     }
 
+    /// Options for an API on a free local port, with no sequencer behind it.
+    fn local_options() -> APIOptions {
+        let port = pick_unused_port().expect("No ports free");
+        APIOptions {
+            api_port: port,
+            bind_addresses: vec![IpAddr::from([127, 0, 0, 1])],
+            advertise_url: None,
+            sequencer_url: format!("http://localhost:{port}").parse().unwrap(),
+            middleware: vec![],
+            cors: Default::default(),
+            v0_sunset: None,
+            address_book: Default::default(),
+            dev_signing: false,
+            submission_control: false,
+            rng: Default::default(),
+            extensions: Default::default(),
+            operator_signer: None,
+            http: Default::default(),
+            config: Default::default(),
+        }
+    }
+
+    /// A state in which a random genesis address holds [`GENESIS_BALANCE`], bound to a rollup chain.
+    fn genesis_state() -> (State, Address) {
+        let genesis_address = Address::random();
+        let vm = RollupVM::new(NamespaceId::from(1_u32));
+        let mut state = State::from_initial_balances([(genesis_address, GENESIS_BALANCE)], vm);
+        let chain_id = ChainId::derive(vm.into(), state.commit(), 1337, Address::random());
+        state.set_chain_id(chain_id);
+        (state, genesis_address)
+    }
+
+    /// Serve `state` with `options`, returning a client connected to the API.
+    async fn start_api(
+        options: APIOptions,
+        state: State,
+    ) -> Client<ClientError, SequencerApiVersion> {
+        start_api_with_services(options, state, Default::default()).await
+    }
+
+    /// Serve `state` with `options`, backed by `services`, returning a client connected to the API.
+    async fn start_api_with_services(
+        options: APIOptions,
+        state: State,
+        services: ApiServices,
+    ) -> Client<ClientError, SequencerApiVersion> {
+        let api_url = format!("http://localhost:{}", options.api_port)
+            .parse()
+            .unwrap();
+        let client: Client<ClientError, SequencerApiVersion> = Client::new(api_url);
+        let state = Arc::new(RwLock::new(state));
+        spawn(async move { serve(&options, state, services).await });
+        client.connect(None).await;
+        client
+    }
+
+    #[async_std::test]
+    async fn address_alias_test() {
+        let (state, genesis_address) = genesis_state();
+        let options = APIOptions {
+            address_book: AddressBook::new([("genesis.rollup".to_string(), genesis_address)]),
+            ..local_options()
+        };
+        let client = start_api(options, state).await;
+
+        // Aliases and checksummed addresses are accepted in place of hex encoded addresses.
+        let balance = client
+            .get::<u64>("rollup/balance/Genesis.Rollup")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(balance, GENESIS_BALANCE);
+        let checksummed = ethers::utils::to_checksum(&genesis_address, None);
+        let balance = client
+            .get::<u64>(&format!("rollup/balance/{checksummed}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(balance, GENESIS_BALANCE);
+
+        // An unknown alias or a bad checksum is rejected.
+        client
+            .get::<u64>("rollup/balance/nobody.rollup")
+            .send()
+            .await
+            .expect_err("an unknown alias should be rejected");
+        client
+            .get::<u64>("rollup/balance/0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed")
+            .send()
+            .await
+            .expect_err("a bad checksum should be rejected");
+    }
+
+    #[async_std::test]
+    async fn advertise_url_test() {
+        let (state, _) = genesis_state();
+        let chain_id = state.chain_id();
+        let advertise_url: Url = "https://rollup.example.com/".parse().unwrap();
+        let options = APIOptions {
+            advertise_url: Some(advertise_url.clone()),
+            ..local_options()
+        };
+        let client = start_api(options, state).await;
+
+        // The advertised URL is reported instead of the bind address.
+        let info = client
+            .get::<RollupInfo>("rollup/info")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(info.api_url, Some(advertise_url));
+        assert_eq!(info.chain_id, chain_id);
+    }
+
+    #[async_std::test]
+    async fn sign_and_submit_disabled_test() {
+        let (state, genesis_address) = genesis_state();
+        let client = start_api(local_options(), state).await;
+
+        // Server-side signing is disabled by default.
+        let request = SignAndSubmitRequest {
+            identity: SeedIdentity::Alice,
+            transaction: Transaction {
+                amount: 1,
+                destination: genesis_address,
+                ..Default::default()
+            },
+        };
+        client
+            .post::<Commitment<SeqTransaction>>("rollup/sign-and-submit")
+            .body_json(&request)
+            .unwrap()
+            .send()
+            .await
+            .expect_err("sign-and-submit should be disabled");
+    }
+
+    #[async_std::test]
+    async fn pending_batches_test() {
+        let (state, _) = genesis_state();
+        let client = start_api(local_options(), state).await;
+
+        // Nothing has been proven, so there are no pending batches.
+        let pending = client
+            .get::<PendingBatches>("rollup/pending-batches")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(pending.latest_executed_block, 0);
+        assert!(pending.batches.is_empty());
+        assert!(!pending.submission_paused);
+        assert_eq!(pending.state_checks, StateCheckStats::default());
+        assert_eq!(pending.nonces, NonceStats::default());
+    }
+
+    #[async_std::test]
+    async fn balances_test() {
+        let (state, genesis_address) = genesis_state();
+        let client = start_api(local_options(), state).await;
+
+        // Query several balances at once.
+        let unknown = Address::random();
+        let balances = client
+            .post::<Balances>("rollup/balances")
+            .body_json(&vec![
+                format!("{genesis_address:?}"),
+                format!("{unknown:?}"),
+            ])
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            balances.accounts,
+            [
+                AccountSummary {
+                    address: genesis_address,
+                    balance: GENESIS_BALANCE,
+                    nonce: 0,
+                },
+                AccountSummary {
+                    address: unknown,
+                    balance: 0,
+                    nonce: 0,
+                },
+            ]
+        );
+        client
+            .post::<Balances>("rollup/balances")
+            .body_json(&vec![format!("{unknown:?}"); MAX_BALANCES_QUERY + 1])
+            .unwrap()
+            .send()
+            .await
+            .expect_err("too many addresses should be rejected");
+    }
+
+    #[async_std::test]
+    async fn simulate_test() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let vm = RollupVM::new(NamespaceId::from(1_u32));
+        let state = State::from_initial_balances([(alice.address(), GENESIS_BALANCE)], vm);
+        let mut signed = vec![];
+        for nonce in 1..=3 {
+            let transaction = Transaction {
+                amount: 10,
+                destination: bob,
+                nonce,
+                ..Default::default()
+            };
+            signed.push(SignedTransaction::new(transaction, &alice).await);
+        }
+
+        // Hold the first two transactions in the mempool.
+        let mempool = Mempool::default();
+        for transaction in &signed[..2] {
+            let txn = sequencer_transaction(vm.into(), transaction.clone(), None).await;
+            mempool
+                .hold(
+                    alice.address(),
+                    transaction.transaction.nonce,
+                    transaction.hash(),
+                    0,
+                    txn,
+                )
+                .await
+                .unwrap();
+        }
+        let services = ApiServices {
+            mempool,
+            ..Default::default()
+        };
+        let client = start_api_with_services(local_options(), state, services).await;
+        let simulate = |request: SimulationRequest| {
+            client
+                .post::<Simulation>("rollup/simulate")
+                .body_json(&request)
+                .unwrap()
+                .send()
+        };
+
+        // Held transactions are applied in the requested order, followed by new ones.
+        let simulation = simulate(SimulationRequest {
+            mempool: vec![signed[1].hash(), signed[0].hash()],
+            transactions: vec![signed[2].clone()],
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            simulation.results,
+            [
+                Err(RollupError::InvalidNonce {
+                    address: alice.address(),
+                    expected: 1,
+                    actual: 2,
+                }),
+                Ok(()),
+                Err(RollupError::InvalidNonce {
+                    address: alice.address(),
+                    expected: 2,
+                    actual: 3,
+                }),
+            ]
+        );
+        let simulation = simulate(SimulationRequest {
+            mempool: vec![signed[0].hash(), signed[1].hash()],
+            transactions: vec![signed[2].clone()],
+        })
+        .await
+        .unwrap();
+        assert_eq!(simulation.results, [Ok(()), Ok(()), Ok(())]);
+        // Each result comes with the resources the transaction used.
+        assert_eq!(simulation.metrics.len(), 3);
+        assert!(simulation
+            .metrics
+            .iter()
+            .all(|metrics| metrics.signature_verifications == 1 && metrics.state_writes > 0));
+
+        // A hash which is not held is rejected.
+        let err = simulate(SimulationRequest {
+            mempool: vec![H256::random()],
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.status(), tide_disco::StatusCode::NOT_FOUND);
+
+        // A bare array of transactions is still accepted.
+        let simulation = client
+            .post::<Simulation>("rollup/simulate")
+            .body_json(&signed[..1])
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(simulation.results, [Ok(())]);
+    }
+
+    #[async_std::test]
+    async fn extension_test() {
+        let (state, _) = genesis_state();
+        let options = APIOptions {
+            extensions: ApiExtensions::default()
+                .with_routes(
+                    r#"
+                    [route.echo_height]
+                    PATH = ["/extension/height"]
+                    METHOD = "GET"
+                    DOC = "Extension route returning the block height."
+                    "#,
+                    |api, middleware| {
+                        api.get("echo_height", move |req, state| {
+                            let middleware = middleware.clone();
+                            async move {
+                                run_middleware(&middleware, "echo_height", &req)?;
+                                Ok(state.block_height())
+                            }
+                            .boxed()
+                        })?;
+                        Ok(())
+                    },
+                )
+                .unwrap(),
+            ..local_options()
+        };
+        let client = start_api(options, state).await;
+
+        // Routes added by extensions are served alongside the built-in routes.
+        let height = client
+            .get::<u64>("rollup/extension/height")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(height, 0);
+    }
+
+    #[async_std::test]
+    async fn latency_test() {
+        let (state, _) = genesis_state();
+        let client = start_api(local_options(), state).await;
+
+        // No transactions have been submitted, so every latency stage is empty.
+        let latency = client
+            .get::<LatencyReport>("rollup/stats/latency")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(latency.stages.len(), LatencyStage::ALL.len());
+        assert!(latency.stages.iter().all(|stage| stage.count == 0));
+    }
+
+    #[async_std::test]
+    async fn submission_control_test() {
+        let (state, _) = genesis_state();
+        let client = start_api(local_options(), state).await;
+
+        // Submission control is disabled by default.
+        client
+            .post::<bool>("rollup/pending-batches/pause")
+            .send()
+            .await
+            .expect_err("pausing submission should be disabled");
+    }
+
+    #[async_std::test]
+    async fn versions_test() {
+        let (state, genesis_address) = genesis_state();
+        let chain_id = state.chain_id();
+        let client = start_api(local_options(), state).await;
+
+        // Each version of the API serves the same routes, in its own response format.
+        let balance = client
+            .get::<u64>(&format!("rollup/v0/balance/{:?}", genesis_address))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(balance, GENESIS_BALANCE);
+        let response = client
+            .get::<ResponseEnvelope<u64>>(&format!("rollup/v1/balance/{:?}", genesis_address))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response,
+            ResponseEnvelope {
+                data: GENESIS_BALANCE,
+                block_height: 0,
+                state_commitment: None,
+                chain_id,
+            }
+        );
+    }
+
+    #[async_std::test]
+    async fn schema_test() {
+        let (state, _) = genesis_state();
+        let client = start_api(local_options(), state).await;
+
+        // The API describes its own payload types.
+        let schema = client
+            .get::<ApiSchema>("rollup/schema")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(schema, ApiSchema::generate());
+    }
+
+    /// Send an HTTP/1.1 request to the API on `port`, returning the status, content type and body
+    /// of the response.
+    ///
+    /// The surf-disco client only speaks JSON and bincode, so CBOR is exercised over a bare
+    /// connection.
+    async fn http_request(
+        port: u16,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> (u16, Option<String>, Vec<u8>) {
+        use async_std::io::{ReadExt, WriteExt};
+        use async_std::net::TcpStream;
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut head = format!(
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+             Content-Length: {}\r\n",
+            body.len()
+        );
+        for (name, value) in headers {
+            head += &format!("{name}: {value}\r\n");
+        }
+        head += "\r\n";
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(body).await.unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await.unwrap();
+
+        let end = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap();
+        let head = String::from_utf8_lossy(&response[..end]).to_string();
+        let mut lines = head.lines();
+        let status = lines
+            .next()
+            .and_then(|line| line.split(' ').nth(1))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let content_type = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, value)| value.trim().to_string());
+        (status, content_type, response[end + 4..].to_vec())
+    }
+
+    #[async_std::test]
+    async fn cbor_test() {
+        let (state, address) = genesis_state();
+        let options = local_options();
+        let port = options.api_port;
+        start_api(options, state).await;
+
+        // A CBOR request body is answered with a CBOR response.
+        let mut body = vec![];
+        ciborium::into_writer(&vec![format!("{address:?}")], &mut body).unwrap();
+        let (status, content_type, body) = http_request(
+            port,
+            "POST",
+            "/rollup/balances",
+            &[
+                ("Content-Type", CBOR_CONTENT_TYPE),
+                ("Accept", CBOR_CONTENT_TYPE),
+            ],
+            &body,
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(content_type.as_deref(), Some(CBOR_CONTENT_TYPE));
+        let balances: Balances = ciborium::from_reader(body.as_slice()).unwrap();
+        assert_eq!(
+            balances.accounts,
+            vec![AccountSummary {
+                address,
+                balance: GENESIS_BALANCE,
+                nonce: 0,
+            }]
+        );
+
+        // So is a query, in every version of the API.
+        let (status, content_type, body) = http_request(
+            port,
+            "GET",
+            &format!("/rollup/balance/{address:?}"),
+            &[("Accept", CBOR_CONTENT_TYPE)],
+            &[],
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(content_type.as_deref(), Some(CBOR_CONTENT_TYPE));
+        let balance: u64 = ciborium::from_reader(body.as_slice()).unwrap();
+        assert_eq!(balance, GENESIS_BALANCE);
+        let (_, _, body) = http_request(
+            port,
+            "GET",
+            &format!("/rollup/v1/balance/{address:?}"),
+            &[("Accept", CBOR_CONTENT_TYPE)],
+            &[],
+        )
+        .await;
+        let envelope: ResponseEnvelope<u64> = ciborium::from_reader(body.as_slice()).unwrap();
+        assert_eq!(envelope.data, GENESIS_BALANCE);
+
+        // Errors are CBOR encoded too, and a malformed CBOR body is rejected.
+        let (status, content_type, _) = http_request(
+            port,
+            "POST",
+            "/rollup/balances",
+            &[
+                ("Content-Type", CBOR_CONTENT_TYPE),
+                ("Accept", CBOR_CONTENT_TYPE),
+            ],
+            &[0xff],
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert_eq!(content_type.as_deref(), Some(CBOR_CONTENT_TYPE));
+
+        // Without an `Accept` header, responses are JSON.
+        let (status, content_type, body) = http_request(
+            port,
+            "GET",
+            &format!("/rollup/balance/{address:?}"),
+            &[],
+            &[],
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert!(content_type.unwrap().starts_with("application/json"));
+        assert_eq!(
+            serde_json::from_slice::<u64>(&body).unwrap(),
+            GENESIS_BALANCE
+        );
+    }
+
+    #[async_std::test]
+    async fn test_versioned_responses() {
+        assert_eq!(
+            api_version_of("/rollup/balance/alice"),
+            Some(ApiVersion::V0)
+        );
+        assert_eq!(
+            api_version_of("/v0/rollup/v0/balance/alice"),
+            Some(ApiVersion::V0)
+        );
+        assert_eq!(
+            api_version_of("/v0/rollup/v1/balance/alice"),
+            Some(ApiVersion::V1)
+        );
+        assert_eq!(api_version_of("/status/metrics"), None);
+
+        let err = rejected(&RollupError::SignatureError);
+        let details = ErrorDetails::new(u16::from(err.status), err.message);
+        assert_eq!(details.code, Some(RollupError::SignatureError.code()));
+        assert!(!details.retryable);
+        let future_nonce = RollupError::InvalidNonce {
+            address: Address::zero(),
+            expected: 2,
+            actual: 3,
+        };
+        let err = rejected(&future_nonce);
+        let details = ErrorDetails::new(u16::from(err.status), err.message);
+        assert_eq!(details.code, Some(future_nonce.code()));
+        assert!(details.retryable);
+        assert_eq!(
+            ErrorDetails::new(503, "sequencer unavailable".into()),
+            ErrorDetails {
+                status: 503,
+                message: "sequencer unavailable".into(),
+                code: None,
+                retryable: true,
+            }
+        );
+
+        let error = |req: tide::Request<()>| async move {
+            let mut res = tide::Response::new(tide::StatusCode::BadRequest);
+            res.set_body(tide::Body::from_json(&ServerError {
+                status: tide_disco::StatusCode::BAD_REQUEST,
+                message: format!("Transaction rejected (error 7): {}", req.url().path()),
+            })?);
+            Ok(res)
+        };
+        let mut app = tide::new();
+        app.with(VersionedResponses {
+            v0_sunset: Some("Thu, 31 Dec 2026 23:59:59 GMT".into()),
+        });
+        app.at("*").all(error);
+        let request = |path: &str| {
+            tide::http::Request::new(
+                tide::http::Method::Get,
+                tide::http::Url::parse("http://localhost")
+                    .unwrap()
+                    .join(path)
+                    .unwrap(),
+            )
+        };
+
+        // v0 responses are marked deprecated, and errors are unchanged.
+        let mut res: tide::http::Response = app.respond(request("/rollup/submit")).await.unwrap();
+        assert_eq!(res["Deprecation"], "true");
+        assert_eq!(res["Sunset"], "Thu, 31 Dec 2026 23:59:59 GMT");
+        assert_eq!(res["Link"], "</rollup/v1>; rel=\"successor-version\"");
+        let err: ServerError = res.body_json().await.unwrap();
+        assert_eq!(
+            err.message,
+            "Transaction rejected (error 7): /rollup/submit"
+        );
+
+        // v1 errors are wrapped in an envelope.
+        let mut res: tide::http::Response =
+            app.respond(request("/rollup/v1/submit")).await.unwrap();
+        assert_eq!(res.status(), tide::StatusCode::BadRequest);
+        assert!(res.header("Deprecation").is_none());
+        let envelope: ErrorEnvelope = res.body_json().await.unwrap();
+        assert_eq!(
+            envelope.error,
+            ErrorDetails {
+                status: 400,
+                message: "Transaction rejected (error 7): /rollup/v1/submit".into(),
+                code: Some(7),
+                retryable: false,
+            }
+        );
+
+        // Other modules are left alone.
+        let res: tide::http::Response = app.respond(request("/status/metrics")).await.unwrap();
+        assert!(res.header("Deprecation").is_none());
+    }
+
     #[async_std::test]
     async fn submit_test() {
         // Start a sequencer network.
@@ -175,8 +2589,8 @@ mod tests {
         let network = TestNetwork::new(config, MockSequencerVersions::new()).await;
         let mut events = network.server.event_stream().await;
 
-        // Start the Rollup API
-        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        // Start the Rollup API, for a rollup in a namespace other than the default.
+        let vm = RollupVM::new(NamespaceId::from(7_u64));
 
         let api_port = pick_unused_port().unwrap();
         let genesis_wallet = LocalWallet::new(&mut ChaChaRng::seed_from_u64(0));
@@ -188,16 +2602,30 @@ mod tests {
 
         let options = APIOptions {
             api_port,
+            bind_addresses: vec![IpAddr::from([0, 0, 0, 0])],
+            advertise_url: None,
             sequencer_url: format!("http://localhost:{port}").parse().unwrap(),
+            middleware: vec![],
+            cors: Default::default(),
+            v0_sunset: None,
+            address_book: Default::default(),
+            dev_signing: false,
+            submission_control: false,
+            rng: Default::default(),
+            extensions: Default::default(),
+            operator_signer: None,
+            http: Default::default(),
+            config: Default::default(),
         };
 
-        spawn(async move { serve(&options, state).await });
+        spawn(async move { serve(&options, state, Default::default()).await });
 
         // Create a transaction
         let transaction = Transaction {
             amount: 100,
             destination: genesis_address,
             nonce: 1,
+            ..Default::default()
         };
         let signed_transaction = SignedTransaction::new(transaction, &genesis_wallet).await;
 
@@ -213,9 +2641,116 @@ mod tests {
             .await
             .unwrap();
 
-        // Wait for a Decide event containing transaction matching the one we sent
+        // Wait for a Decide event containing transaction matching the one we sent, in the rollup's
+        // namespace
         let raw_tx = signed_transaction.encode();
         let txn = SeqTransaction::new(vm.0, raw_tx);
         wait_for_decide_on_handle(&mut events, &txn).await;
     }
+
+    #[async_std::test]
+    async fn sign_and_submit_test() {
+        // Start a sequencer network.
+        let port = portpicker::pick_unused_port().unwrap();
+
+        let options = Options::with_port(port).submit(Default::default());
+        let anvil = Anvil::new().spawn();
+        let l1 = anvil.endpoint().parse().unwrap();
+        let network_config = TestConfigBuilder::default().l1_url(l1).build();
+        let config = TestNetworkConfigBuilder::default()
+            .api_config(options)
+            .network_config(network_config)
+            .build();
+        let network = TestNetwork::new(config, MockSequencerVersions::new()).await;
+        let mut events = network.server.event_stream().await;
+
+        // Start the Rollup API with server-side signing enabled.
+        let vm = RollupVM::new(NamespaceId::from(7_u64));
+        let alice = SeedIdentity::Alice.wallet();
+        let state = Arc::new(RwLock::new(State::from_initial_balances(
+            [(alice.address(), GENESIS_BALANCE)],
+            vm,
+        )));
+        let api_port = pick_unused_port().unwrap();
+        let options = APIOptions {
+            api_port,
+            bind_addresses: vec![IpAddr::from([0, 0, 0, 0])],
+            advertise_url: None,
+            sequencer_url: format!("http://localhost:{port}").parse().unwrap(),
+            middleware: vec![],
+            cors: Default::default(),
+            v0_sunset: None,
+            address_book: Default::default(),
+            dev_signing: true,
+            submission_control: false,
+            rng: Default::default(),
+            extensions: Default::default(),
+            operator_signer: None,
+            http: Default::default(),
+            config: Default::default(),
+        };
+        spawn(async move { serve(&options, state, Default::default()).await });
+
+        let api_url = format!("http://localhost:{api_port}").parse().unwrap();
+        let client: Client<ClientError, SequencerApiVersion> = Client::new(api_url);
+        client.connect(None).await;
+
+        // An unsigned transaction is signed with the next nonce of the identity and submitted.
+        let destination = Address::random();
+        let transaction = Transaction {
+            amount: 100,
+            destination,
+            memo: Some("first".into()),
+            ..Default::default()
+        };
+        let request = SignAndSubmitRequest {
+            identity: SeedIdentity::Alice,
+            transaction: transaction.clone(),
+        };
+        let commitment = client
+            .post::<Commitment<SeqTransaction>>("rollup/sign-and-submit")
+            .body_json(&request)
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        let signed = SignedTransaction::new(
+            Transaction {
+                nonce: 1,
+                ..transaction.clone()
+            },
+            &alice,
+        )
+        .await;
+        let txn = SeqTransaction::new(vm.0, signed.encode());
+        assert_eq!(commitment, txn.commit());
+
+        // A second request before the first executes takes the following nonce, and is held in
+        // the mempool until the first has executed.
+        let request = SignAndSubmitRequest {
+            identity: SeedIdentity::Alice,
+            transaction: Transaction {
+                memo: Some("second".into()),
+                ..transaction
+            },
+        };
+        client
+            .post::<Commitment<SeqTransaction>>("rollup/sign-and-submit")
+            .body_json(&request)
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        let held = client
+            .get::<Vec<PendingTransaction>>(&format!("rollup/mempool/{:?}", alice.address()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            held.iter().map(|pending| pending.nonce).collect::<Vec<_>>(),
+            [2]
+        );
+
+        wait_for_decide_on_handle(&mut events, &txn).await;
+    }
 }
@@ -0,0 +1,235 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Independent re-execution of the rollup, for auditing an operator.
+//!
+//! An [`Auditor`] executes the rollup namespace of each HotShot block from its own copy of the
+//! genesis state, trusting nothing but the sequencer. The state commitments it computes can then be
+//! compared with those served by an operator's rollup API and with the commitments the operator has
+//! proven to the rollup contract on the L1. Any difference is reported as a [`Divergence`].
+//!
+//! The auditor is generic over the [`RollupStateMachine`], so it can follow a rollup under either
+//! [`StateModel`](crate::machine::StateModel).
+
+use crate::chain::ChainId;
+use crate::data_source::SequencerDataSource;
+use crate::machine::RollupStateMachine;
+use crate::scheduler::{BlockScheduler, NamespaceBlock};
+use crate::state::State;
+use committable::{Commitment, Committable};
+use espresso_types::{Header, NamespaceId};
+use ethers::types::U256;
+use sequencer_utils::commitment_to_u256;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::BTreeMap;
+
+/// A disagreement between the auditor and the operator.
+///
+/// Commitments are given as integers, as they are stored by the rollup contract, so that
+/// divergences have the same form whatever the state model.
+#[derive(Clone, Debug, PartialEq, Eq, Snafu, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "kebab-case")]
+pub enum Divergence {
+    /// The rollup API served a different commitment for an executed block, or none at all.
+    #[snafu(display(
+        "API commitment for block {block_height} is {served:x?}, expected {expected:x}"
+    ))]
+    Api {
+        block_height: u64,
+        expected: U256,
+        served: Option<U256>,
+    },
+    /// The rollup contract holds a different commitment after verifying `num_verified_blocks`.
+    #[snafu(display(
+        "L1 commitment after {num_verified_blocks} blocks is {contract:#x}, expected {expected:#x}"
+    ))]
+    L1 {
+        num_verified_blocks: u64,
+        expected: U256,
+        contract: U256,
+    },
+}
+
+/// Re-executes the rollup and records the state commitment after each block.
+#[derive(Debug)]
+pub struct Auditor<S: RollupStateMachine = State> {
+    state: S,
+    genesis: Commitment<S>,
+    /// Commitment after each executed block. Blocks without rollup transactions leave the state
+    /// unchanged, so they are not recorded.
+    commitments: BTreeMap<u64, Commitment<S>>,
+    /// Number of blocks processed so far, which is also the height of the next block.
+    processed: u64,
+}
+
+impl<S: RollupStateMachine> Auditor<S> {
+    /// Audit a rollup starting from the `genesis` state.
+    pub fn new(genesis: S) -> Self {
+        Self {
+            genesis: genesis.commit(),
+            state: genesis,
+            commitments: Default::default(),
+            processed: 0,
+        }
+    }
+
+    /// Bind the audited state to the rollup chain `chain_id`, as the audited node does.
+    pub fn set_chain_id(&mut self, chain_id: ChainId) {
+        self.state.set_chain_id(chain_id);
+    }
+
+    /// The namespace of the audited rollup.
+    pub fn namespace(&self) -> NamespaceId {
+        self.state.namespace()
+    }
+
+    pub fn genesis_commitment(&self) -> Commitment<S> {
+        self.genesis
+    }
+
+    /// Number of blocks processed so far.
+    pub fn processed(&self) -> u64 {
+        self.processed
+    }
+
+    /// Execute the next block, fetching its rollup data from `data_source`.
+    ///
+    /// Returns the new state commitment if the block contains rollup transactions.
+    pub async fn execute(
+        &mut self,
+        data_source: &dyn SequencerDataSource,
+        header: Header,
+    ) -> Option<Commitment<S>> {
+        let block_height = header.height();
+        assert_eq!(
+            block_height, self.processed,
+            "blocks must be audited in order"
+        );
+        self.processed += 1;
+        let NamespaceBlock {
+            namespace_proof,
+            block_hash,
+            ..
+        } = BlockScheduler::new(data_source, vec![self.state.namespace()])
+            .schedule(&header)
+            .await
+            .pop()?;
+        self.state
+            .execute_transactions(&header, &namespace_proof, block_hash);
+        let commitment = self.state.commit();
+        self.commitments.insert(block_height, commitment);
+        Some(commitment)
+    }
+
+    /// The commitment to the state after the block at `block_height`, if it has been processed.
+    pub fn commitment_after(&self, block_height: u64) -> Option<Commitment<S>> {
+        if block_height >= self.processed {
+            return None;
+        }
+        Some(
+            self.commitments
+                .range(..=block_height)
+                .next_back()
+                .map_or(self.genesis, |(_, commitment)| *commitment),
+        )
+    }
+
+    /// Compare the commitment `served` by the rollup API for an executed block with our own.
+    pub fn check_api(
+        &self,
+        block_height: u64,
+        served: Option<Commitment<S>>,
+    ) -> Result<(), Divergence> {
+        let Some(&expected) = self.commitments.get(&block_height) else {
+            return Ok(());
+        };
+        if served != Some(expected) {
+            return Err(Divergence::Api {
+                block_height,
+                expected: commitment_to_u256(expected),
+                served: served.map(commitment_to_u256),
+            });
+        }
+        Ok(())
+    }
+
+    /// Compare the commitment held by the rollup contract after it has verified
+    /// `num_verified_blocks` blocks with our own.
+    ///
+    /// Returns `None` if those blocks have not all been processed yet, so the comparison must be
+    /// retried later.
+    pub fn check_l1(
+        &self,
+        num_verified_blocks: u64,
+        contract: U256,
+    ) -> Option<Result<(), Divergence>> {
+        let expected = match num_verified_blocks {
+            0 => self.genesis,
+            n => self.commitment_after(n - 1)?,
+        };
+        let expected = commitment_to_u256(expected);
+        Some(if contract == expected {
+            Ok(())
+        } else {
+            Err(Divergence::L1 {
+                num_verified_blocks,
+                expected,
+                contract,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RollupVM;
+    use ethers::types::Address;
+
+    #[test]
+    fn test_audit_comparisons() {
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let genesis = State::from_initial_balances([(Address::random(), 100)], vm);
+        let other = State::from_initial_balances([(Address::random(), 100)], vm).commit();
+        let mut auditor = Auditor::new(genesis);
+        let genesis = auditor.genesis_commitment();
+
+        // Blocks 0 and 1 have been processed, and only block 1 contained rollup transactions.
+        auditor.processed = 2;
+        auditor.commitments.insert(1, other);
+        assert_eq!(auditor.commitment_after(0), Some(genesis));
+        assert_eq!(auditor.commitment_after(1), Some(other));
+        assert_eq!(auditor.commitment_after(2), None);
+
+        assert_eq!(auditor.check_api(1, Some(other)), Ok(()));
+        assert_eq!(
+            auditor.check_api(1, Some(genesis)),
+            Err(Divergence::Api {
+                block_height: 1,
+                expected: commitment_to_u256(other),
+                served: Some(commitment_to_u256(genesis)),
+            })
+        );
+        // Blocks which were not executed are not served by the API.
+        assert_eq!(auditor.check_api(0, None), Ok(()));
+
+        assert_eq!(
+            auditor.check_l1(0, commitment_to_u256(genesis)),
+            Some(Ok(()))
+        );
+        assert_eq!(auditor.check_l1(2, commitment_to_u256(other)), Some(Ok(())));
+        assert_eq!(
+            auditor.check_l1(1, commitment_to_u256(other)),
+            Some(Err(Divergence::L1 {
+                num_verified_blocks: 1,
+                expected: commitment_to_u256(genesis),
+                contract: commitment_to_u256(other),
+            }))
+        );
+        assert_eq!(auditor.check_l1(3, commitment_to_u256(other)), None);
+    }
+}
@@ -0,0 +1,491 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Authentication of administrative API routes.
+//!
+//! Routes which change how the node operates, such as pausing proof submission, can be restricted
+//! with [`AdminAuth`] middleware. Each restricted route requires a [`Role`], which the caller proves
+//! with a credential in an `Authorization: Bearer` header. If no credentials are configured, the
+//! restricted routes are disabled rather than left open. The credential is either
+//! * a static API key configured on the node, which grants a fixed role, or
+//! * a JSON Web Token whose `role` claim grants the role, signed by a trusted issuer.
+//!
+//! Tokens are signed with an issuer's Ethereum key rather than a secret shared with the node: the
+//! signature is an EIP-191 personal signature over the JWT signing input, and the `alg` in the token
+//! header is [`JWT_ALGORITHM`]. The node only needs the addresses of the issuers it trusts.
+//!
+//! Every request to a restricted route, authorized or not, is logged under the [`AUDIT_TARGET`]
+//! tracing target.
+
+use crate::middleware::Middleware;
+use crate::secret::Secret;
+use crate::stats::unix_millis;
+use clap::ValueEnum;
+use ethers::{
+    signers::Signer,
+    types::{Address, Signature, H256},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::HashMap;
+use strum_macros::Display;
+use tide_disco::{error::ServerError, RequestParams, StatusCode};
+
+/// Tracing target of the audit log of administrative requests.
+pub const AUDIT_TARGET: &str = "rollup::audit";
+
+/// The `alg` of tokens signed with an EIP-191 personal signature.
+pub const JWT_ALGORITHM: &str = "EIP191";
+
+/// A level of administrative access. Each role grants everything the roles before it do.
+#[derive(
+    ValueEnum,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    /// Routine operation of the node, such as pausing proof submission or exporting snapshots.
+    Operator,
+    /// Unrestricted access.
+    Admin,
+}
+
+impl Role {
+    /// Whether this role grants access to routes requiring `required`.
+    pub fn grants(self, required: Role) -> bool {
+        self >= required
+    }
+}
+
+/// The administrative routes of the rollup API and the role each requires.
+pub const ADMIN_ROUTES: [(&str, Role); 5] = [
+    ("aggregated_batch", Role::Operator),
+    ("config", Role::Operator),
+    ("pause_submission", Role::Operator),
+    ("resume_submission", Role::Operator),
+    ("snapshot", Role::Operator),
+];
+
+#[derive(Clone, Debug, Snafu, PartialEq, Eq)]
+pub enum AuthError {
+    #[snafu(display(
+        "This route is disabled, since no administrative API keys or token issuers are configured."
+    ))]
+    NotConfigured,
+    #[snafu(display("This route requires an Authorization: Bearer credential."))]
+    MissingCredential,
+    #[snafu(display("Unknown API key."))]
+    UnknownApiKey,
+    #[snafu(display("Malformed token: {reason}"))]
+    MalformedToken { reason: String },
+    #[snafu(display("Token was not issued by a trusted issuer (signed by {issuer:#x})."))]
+    UntrustedIssuer { issuer: Address },
+    #[snafu(display("Token expired at {exp}."))]
+    TokenExpired { exp: u64 },
+    #[snafu(display(
+        "This route requires the {required} role, but the credential grants {granted}."
+    ))]
+    InsufficientRole { required: Role, granted: Role },
+}
+
+impl AuthError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NotConfigured | Self::InsufficientRole { .. } => StatusCode::FORBIDDEN,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+impl From<AuthError> for ServerError {
+    fn from(err: AuthError) -> Self {
+        Self {
+            status: err.status(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Claims of a token granting administrative access.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    /// The holder of the token, recorded in the audit log.
+    pub sub: String,
+    pub role: Role,
+    /// Unix timestamp (in seconds) after which the token is no longer accepted.
+    pub exp: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TokenHeader {
+    alg: String,
+    typ: String,
+}
+
+/// Sign a token with `issuer`'s key, granting the access described by `claims`.
+pub async fn issue_token(claims: &Claims, issuer: &impl Signer) -> String {
+    let header = TokenHeader {
+        alg: JWT_ALGORITHM.into(),
+        typ: "JWT".into(),
+    };
+    let signing_input = format!(
+        "{}.{}",
+        base64url_encode(&serde_json::to_vec(&header).expect("Serialization should not fail")),
+        base64url_encode(&serde_json::to_vec(claims).expect("Serialization should not fail")),
+    );
+    let signature = issuer.sign_message(signing_input.as_bytes()).await.unwrap();
+    format!("{signing_input}.{}", base64url_encode(&signature.to_vec()))
+}
+
+/// Parse an API key definition of the form `ROLE:KEY`.
+pub fn parse_api_key(s: &str) -> Result<(Role, Secret<String>), String> {
+    let (role, key) = s
+        .split_once(':')
+        .ok_or_else(|| "Invalid API key, expected ROLE:KEY".to_string())?;
+    let role = Role::from_str(role, true)?;
+    if key.is_empty() {
+        return Err("API key must not be empty".into());
+    }
+    Ok((role, Secret::new(key.to_string())))
+}
+
+/// Middleware restricting administrative routes to authenticated callers.
+///
+/// Routes without a required role are not affected.
+#[derive(Clone, Debug, Default)]
+pub struct AdminAuth {
+    // Roles granted by each API key, by the hash of the key, so that keys are not compared
+    // byte-by-byte and are never logged.
+    api_keys: HashMap<H256, Role>,
+    issuers: Vec<Address>,
+    routes: HashMap<String, Role>,
+}
+
+impl AdminAuth {
+    /// Require the default role for each of the [`ADMIN_ROUTES`].
+    pub fn new() -> Self {
+        ADMIN_ROUTES
+            .into_iter()
+            .fold(Self::default(), |auth, (route, role)| {
+                auth.require(route, role)
+            })
+    }
+
+    /// Grant `role` to callers presenting `key`.
+    pub fn with_api_key(mut self, key: &str, role: Role) -> Self {
+        self.api_keys.insert(H256(keccak256(key)), role);
+        self
+    }
+
+    /// Accept tokens signed by `issuer`.
+    pub fn with_jwt_issuer(mut self, issuer: Address) -> Self {
+        self.issuers.push(issuer);
+        self
+    }
+
+    /// Require `role` for requests to `route`, replacing any role already required.
+    pub fn require(mut self, route: &str, role: Role) -> Self {
+        self.routes.insert(route.to_string(), role);
+        self
+    }
+
+    /// The role required for requests to `route`, if any.
+    pub fn required_role(&self, route: &str) -> Option<Role> {
+        self.routes.get(route).copied()
+    }
+
+    /// Authenticate the value of an `Authorization` header, at Unix time `now` (in seconds).
+    ///
+    /// Returns the principal the credential identifies and the role it grants.
+    pub fn authenticate(
+        &self,
+        authorization: Option<&str>,
+        now: u64,
+    ) -> Result<(String, Role), AuthError> {
+        let credential = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|credential| !credential.is_empty())
+            .ok_or(AuthError::MissingCredential)?;
+        if credential.contains('.') {
+            let claims = self.verify_token(credential, now)?;
+            return Ok((format!("jwt:{}", claims.sub), claims.role));
+        }
+        let hash = H256(keccak256(credential));
+        let role = self.api_keys.get(&hash).ok_or(AuthError::UnknownApiKey)?;
+        // Identify the key by a prefix of its hash, which is safe to log.
+        Ok((format!("api-key:{}", hex_prefix(&hash)), *role))
+    }
+
+    fn verify_token(&self, token: &str, now: u64) -> Result<Claims, AuthError> {
+        let malformed = |reason: &str| AuthError::MalformedToken {
+            reason: reason.into(),
+        };
+        let (signing_input, signature) = token
+            .rsplit_once('.')
+            .ok_or_else(|| malformed("expected header.claims.signature"))?;
+        let (header, claims) = signing_input
+            .split_once('.')
+            .ok_or_else(|| malformed("expected header.claims.signature"))?;
+        let header: TokenHeader = decode_segment(header)?;
+        if header.alg != JWT_ALGORITHM {
+            return Err(malformed(&format!(
+                "unsupported algorithm {}, expected {JWT_ALGORITHM}",
+                header.alg
+            )));
+        }
+        let claims: Claims = decode_segment(claims)?;
+        let signature = base64url_decode(signature)
+            .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+            .ok_or_else(|| malformed("invalid signature"))?;
+        let issuer = signature
+            .recover(signing_input.as_bytes())
+            .map_err(|_| malformed("invalid signature"))?;
+        if !self.issuers.contains(&issuer) {
+            return Err(AuthError::UntrustedIssuer { issuer });
+        }
+        if claims.exp <= now {
+            return Err(AuthError::TokenExpired { exp: claims.exp });
+        }
+        Ok(claims)
+    }
+
+    /// Check that the credential in `authorization` grants access to `route` at Unix time `now`.
+    ///
+    /// Returns the principal the credential identifies, or `None` if the route is unrestricted.
+    /// Restricted routes are always rejected if no API keys or token issuers are configured.
+    pub fn authorize(
+        &self,
+        route: &str,
+        authorization: Option<&str>,
+        now: u64,
+    ) -> Result<Option<String>, AuthError> {
+        let Some(required) = self.required_role(route) else {
+            return Ok(None);
+        };
+        if self.api_keys.is_empty() && self.issuers.is_empty() {
+            return Err(AuthError::NotConfigured);
+        }
+        let (principal, granted) = self.authenticate(authorization, now)?;
+        if !granted.grants(required) {
+            return Err(AuthError::InsufficientRole { required, granted });
+        }
+        Ok(Some(principal))
+    }
+}
+
+impl Middleware for AdminAuth {
+    fn on_request(&self, route: &str, req: &RequestParams) -> Result<(), ServerError> {
+        let authorization = req
+            .headers()
+            .get("Authorization")
+            .map(|value| value.last().as_str().to_string());
+        match self.authorize(route, authorization.as_deref(), unix_millis() / 1000) {
+            Ok(None) => Ok(()),
+            Ok(Some(principal)) => {
+                tracing::info!(target: AUDIT_TARGET, route, %principal, "admin request authorized");
+                Ok(())
+            }
+            Err(err) => {
+                tracing::warn!(target: AUDIT_TARGET, route, %err, "admin request rejected");
+                Err(err.into())
+            }
+        }
+    }
+}
+
+fn hex_prefix(hash: &H256) -> String {
+    hash.as_bytes()[..4]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn decode_segment<T: for<'de> Deserialize<'de>>(segment: &str) -> Result<T, AuthError> {
+    let bytes = base64url_decode(segment).ok_or_else(|| AuthError::MalformedToken {
+        reason: "invalid base64url encoding".into(),
+    })?;
+    serde_json::from_slice(&bytes).map_err(|err| AuthError::MalformedToken {
+        reason: err.to_string(),
+    })
+}
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encode `bytes` as unpadded base64url, as used by JWTs.
+fn base64url_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | ((*byte as u32) << (16 - 8 * i)));
+        for i in 0..=chunk.len() {
+            out.push(BASE64URL[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+/// Decode unpadded base64url, or return `None` if `s` is not valid base64url.
+fn base64url_decode(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    for chunk in s.as_bytes().chunks(4) {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = BASE64URL.iter().position(|b| b == c)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::LocalWallet;
+
+    #[test]
+    fn test_base64url() {
+        for bytes in [&b""[..], b"f", b"fo", b"foo", b"foob", b"\xff\xfe\xfd\x00"] {
+            assert_eq!(base64url_decode(&base64url_encode(bytes)).unwrap(), bytes);
+        }
+        assert_eq!(base64url_encode(b"\xfb\xff"), "-_8");
+        assert_eq!(base64url_decode("a"), None);
+        assert_eq!(base64url_decode("a+=="), None);
+    }
+
+    #[test]
+    fn test_not_configured() {
+        // Without credentials, restricted routes are closed to everyone.
+        let auth = AdminAuth::new();
+        assert_eq!(auth.authorize("balance", None, 0), Ok(None));
+        for (route, _) in ADMIN_ROUTES {
+            for authorization in [None, Some("Bearer any-key")] {
+                let err = auth.authorize(route, authorization, 0).unwrap_err();
+                assert_eq!(err, AuthError::NotConfigured);
+                assert_eq!(err.status(), StatusCode::FORBIDDEN);
+            }
+        }
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let auth = AdminAuth::new()
+            .with_api_key("operator-key", Role::Operator)
+            .with_api_key("admin-key", Role::Admin)
+            .require("resync", Role::Admin);
+
+        // Unrestricted routes need no credential.
+        assert_eq!(auth.authorize("balance", None, 0), Ok(None));
+
+        assert_eq!(
+            auth.authorize("pause_submission", None, 0),
+            Err(AuthError::MissingCredential)
+        );
+        assert_eq!(
+            auth.authorize("pause_submission", Some("Bearer wrong-key"), 0),
+            Err(AuthError::UnknownApiKey)
+        );
+        let principal = auth
+            .authorize("pause_submission", Some("Bearer operator-key"), 0)
+            .unwrap()
+            .unwrap();
+        assert!(principal.starts_with("api-key:"));
+        assert!(!principal.contains("operator-key"));
+        assert_eq!(
+            auth.authorize("resync", Some("Bearer operator-key"), 0),
+            Err(AuthError::InsufficientRole {
+                required: Role::Admin,
+                granted: Role::Operator,
+            })
+        );
+        assert!(auth
+            .authorize("resync", Some("Bearer admin-key"), 0)
+            .unwrap()
+            .is_some());
+
+        assert_eq!(
+            parse_api_key("Operator:secret"),
+            Ok((Role::Operator, Secret::new("secret".into())))
+        );
+        assert!(parse_api_key("secret").is_err());
+        assert!(parse_api_key("root:secret").is_err());
+    }
+
+    #[async_std::test]
+    async fn test_tokens() {
+        let mut rng = rand::thread_rng();
+        let issuer = LocalWallet::new(&mut rng);
+        let stranger = LocalWallet::new(&mut rng);
+        let auth = AdminAuth::new()
+            .with_jwt_issuer(issuer.address())
+            .require("resync", Role::Admin);
+        let claims = Claims {
+            sub: "alice".into(),
+            role: Role::Operator,
+            exp: 1000,
+        };
+        let token = issue_token(&claims, &issuer).await;
+        let bearer = format!("Bearer {token}");
+
+        assert_eq!(
+            auth.authorize("snapshot", Some(&bearer), 999),
+            Ok(Some("jwt:alice".into()))
+        );
+        assert_eq!(
+            auth.authorize("snapshot", Some(&bearer), 1000),
+            Err(AuthError::TokenExpired { exp: 1000 })
+        );
+        assert_eq!(
+            auth.authorize("resync", Some(&bearer), 999),
+            Err(AuthError::InsufficientRole {
+                required: Role::Admin,
+                granted: Role::Operator,
+            })
+        );
+
+        // A token signed by anyone else is rejected.
+        let forged = issue_token(&claims, &stranger).await;
+        assert_eq!(
+            auth.authorize("snapshot", Some(&format!("Bearer {forged}")), 999),
+            Err(AuthError::UntrustedIssuer {
+                issuer: stranger.address()
+            })
+        );
+
+        // Escalating the role in the claims invalidates the signature.
+        let (header, rest) = token.split_once('.').unwrap();
+        let (_, signature) = rest.split_once('.').unwrap();
+        let escalated = base64url_encode(
+            &serde_json::to_vec(&Claims {
+                role: Role::Admin,
+                ..claims
+            })
+            .unwrap(),
+        );
+        let tampered = format!("Bearer {header}.{escalated}.{signature}");
+        assert!(auth.authorize("resync", Some(&tampered), 999).is_err());
+    }
+}
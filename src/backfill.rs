@@ -0,0 +1,353 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Backfilling of historical headers.
+//!
+//! The header stream of the query service is meant for following new blocks as they are decided.
+//! A node which restarts far behind the sequencer, or starts from genesis on a long-lived chain,
+//! would otherwise replay all of history through a single websocket, which is slow and starts over
+//! whenever the connection drops. Instead, the [`HeaderFetcher`] fetches historical headers with
+//! paginated range queries, retrying pages which fail, and only subscribes to the stream once it has
+//! caught up with the sequencer's block height.
+//!
+//! A long catch-up reports its progress before each page: the number of blocks processed and
+//! remaining, the rate and the estimated time to completion are logged as structured fields and
+//! recorded in a [`CatchUpTracker`], which the API serves.
+
+use crate::clock::{Clock, SystemClock};
+use crate::data_source::SequencerDataSource;
+use async_std::sync::{Arc, RwLock};
+use espresso_types::Header;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::time::Duration;
+
+/// Default number of headers requested by each range query. This is the default limit of the
+/// query service.
+pub const DEFAULT_PAGE_SIZE: u64 = 100;
+
+/// Default number of attempts to fetch a page before giving up.
+pub const DEFAULT_MAX_ATTEMPTS: usize = 10;
+
+/// Delay before the first retry of a page. The delay doubles after each attempt, up to
+/// [`MAX_BACKOFF`].
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq, Eq, Snafu)]
+pub enum FetchError {
+    #[snafu(display("Headers {from}-{until} are unavailable after {attempts} attempts"))]
+    Unavailable {
+        from: u64,
+        until: u64,
+        attempts: usize,
+    },
+}
+
+/// Progress of a catch-up with the sequencer's block height.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CatchUpProgress {
+    /// Height at which the catch-up started.
+    pub start: u64,
+    /// Number of historical headers handed on for execution so far.
+    pub processed: u64,
+    /// Number of historical headers still to be fetched, up to the sequencer's latest block height.
+    pub remaining: u64,
+    /// Average number of headers processed per second since the catch-up started.
+    pub blocks_per_second: f64,
+    /// Estimated number of seconds until the catch-up completes, if the rate is known.
+    pub eta_seconds: Option<u64>,
+    /// Whether the catch-up is complete, and headers are followed from the live stream.
+    pub caught_up: bool,
+}
+
+impl CatchUpProgress {
+    fn new(start: u64, next: u64, height: u64, elapsed: Duration) -> Self {
+        let processed = next - start;
+        let remaining = height.saturating_sub(next);
+        let blocks_per_second = if elapsed.is_zero() {
+            0.
+        } else {
+            processed as f64 / elapsed.as_secs_f64()
+        };
+        let eta_seconds =
+            (blocks_per_second > 0.).then(|| (remaining as f64 / blocks_per_second).ceil() as u64);
+        Self {
+            start,
+            processed,
+            remaining,
+            blocks_per_second,
+            eta_seconds,
+            caught_up: remaining == 0,
+        }
+    }
+}
+
+/// The latest progress of a [`HeaderFetcher`], shared with the API.
+#[derive(Clone, Debug, Default)]
+pub struct CatchUpTracker {
+    progress: Arc<RwLock<Option<CatchUpProgress>>>,
+}
+
+impl CatchUpTracker {
+    async fn record(&self, progress: CatchUpProgress) {
+        *self.progress.write().await = Some(progress);
+    }
+
+    /// The latest progress, or `None` if no catch-up has started.
+    pub async fn progress(&self) -> Option<CatchUpProgress> {
+        self.progress.read().await.clone()
+    }
+}
+
+/// Fetches headers from a [`SequencerDataSource`], backfilling history with range queries before
+/// following the live header stream.
+#[derive(Debug)]
+pub struct HeaderFetcher<'a> {
+    data_source: &'a dyn SequencerDataSource,
+    page_size: u64,
+    max_attempts: usize,
+    clock: &'a dyn Clock,
+    progress: CatchUpTracker,
+}
+
+impl<'a> HeaderFetcher<'a> {
+    pub fn new(data_source: &'a dyn SequencerDataSource) -> Self {
+        Self {
+            data_source,
+            page_size: DEFAULT_PAGE_SIZE,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            clock: &SystemClock,
+            progress: Default::default(),
+        }
+    }
+
+    /// Request at most `page_size` headers with each range query.
+    pub fn with_page_size(mut self, page_size: u64) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Record the progress of catching up in `progress`.
+    pub fn with_progress(mut self, progress: CatchUpTracker) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Try each page at most `max_attempts` times, waiting between attempts on `clock`.
+    pub fn with_retries(mut self, max_attempts: usize, clock: &'a dyn Clock) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.clock = clock;
+        self
+    }
+
+    /// Fetch the headers with heights in `from..until`, retrying with exponential backoff.
+    ///
+    /// The range is fetched as a single query, so it should be no longer than the page size.
+    pub async fn fetch_page(&self, from: u64, until: u64) -> Result<Vec<Header>, FetchError> {
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=self.max_attempts {
+            match self.data_source.headers(from, until).await {
+                Some(headers) if headers.len() as u64 == until - from => return Ok(headers),
+                Some(headers) => tracing::warn!(
+                    "Expected {} headers {from}-{until}, got {}",
+                    until - from,
+                    headers.len()
+                ),
+                None => tracing::warn!("Headers {from}-{until} are unavailable"),
+            }
+            if attempt < self.max_attempts {
+                tracing::info!("Retrying headers {from}-{until} in {backoff:?}");
+                self.clock.sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+        Err(FetchError::Unavailable {
+            from,
+            until,
+            attempts: self.max_attempts,
+        })
+    }
+
+    /// Stream headers of decided blocks, starting at height `from`.
+    ///
+    /// Headers below the sequencer's block height are fetched a page at a time. Once the fetcher
+    /// catches up, or if the block height cannot be determined, it hands over to the live header
+    /// stream from the next height, so no header is skipped or repeated. Progress is reported before
+    /// each page, with the rate measured on the fetcher's clock.
+    ///
+    /// # Panics
+    ///
+    /// The stream panics if a page of historical headers is still unavailable after every retry,
+    /// just as the live stream panics on an error.
+    pub fn headers(&self, from: u64) -> BoxStream<'_, Header> {
+        let started = self.clock.now();
+        stream::unfold(Some(from), move |next| async move {
+            let next = next?;
+            let height = self.data_source.block_height().await.unwrap_or(next);
+            let progress = CatchUpProgress::new(from, next, height, self.clock.now() - started);
+            if next >= height {
+                tracing::info!("Following live headers from block {next}");
+                self.progress.record(progress).await;
+                return Some((self.data_source.subscribe_headers(next).await, None));
+            }
+            let until = height.min(next + self.page_size);
+            tracing::info!(
+                processed = progress.processed,
+                remaining = progress.remaining,
+                blocks_per_second = progress.blocks_per_second,
+                eta_seconds = progress.eta_seconds,
+                "Backfilling headers {next}-{until} of {height}"
+            );
+            self.progress.record(progress).await;
+            let page = self
+                .fetch_page(next, until)
+                .await
+                .expect("Error fetching block headers");
+            Some((stream::iter(page).boxed(), Some(until)))
+        })
+        .flatten()
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::data_source::MockDataSource;
+    use crate::fixtures::mock_block;
+    use espresso_types::{NamespaceId, NsProof, SeqTypes};
+    use futures::future::{BoxFuture, FutureExt};
+    use hotshot_query_service::availability::BlockHash;
+    use hotshot_query_service::VidCommon;
+    use std::sync::Mutex;
+
+    /// A data source whose range queries fail a given number of times, and which records the
+    /// ranges requested and where the live stream was subscribed.
+    #[derive(Debug, Default)]
+    struct FlakyDataSource {
+        inner: MockDataSource,
+        failures: Mutex<usize>,
+        requested: Mutex<Vec<(u64, u64)>>,
+        subscribed: Mutex<Vec<u64>>,
+    }
+
+    impl SequencerDataSource for FlakyDataSource {
+        fn subscribe_headers(&self, from: u64) -> BoxFuture<'_, BoxStream<'static, Header>> {
+            self.subscribed.lock().unwrap().push(from);
+            self.inner.subscribe_headers(from)
+        }
+
+        fn headers(&self, from: u64, until: u64) -> BoxFuture<'_, Option<Vec<Header>>> {
+            self.requested.lock().unwrap().push((from, until));
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return async { None }.boxed();
+            }
+            self.inner.headers(from, until)
+        }
+
+        fn block_height(&self) -> BoxFuture<'_, Option<u64>> {
+            self.inner.block_height()
+        }
+
+        fn namespace_proof(
+            &self,
+            height: u64,
+            namespace: NamespaceId,
+        ) -> BoxFuture<'_, Option<NsProof>> {
+            self.inner.namespace_proof(height, namespace)
+        }
+
+        fn vid_common(&self, height: u64) -> BoxFuture<'_, Option<VidCommon>> {
+            self.inner.vid_common(height)
+        }
+
+        fn block_hash(&self, height: u64) -> BoxFuture<'_, BlockHash<SeqTypes>> {
+            self.inner.block_hash(height)
+        }
+
+        fn view_number(&self, height: u64) -> BoxFuture<'_, Option<u64>> {
+            self.inner.view_number(height)
+        }
+    }
+
+    #[async_std::test]
+    async fn test_backfill_headers() {
+        let namespace = NamespaceId::from(1_u64);
+        let data_source = FlakyDataSource::default();
+        for _ in 0..5 {
+            data_source
+                .inner
+                .push(mock_block(namespace, &[(namespace, vec![])]).await);
+        }
+        let expected: Vec<_> = data_source.inner.subscribe_headers(1).await.collect().await;
+
+        // The first attempt at a page fails, and is retried.
+        *data_source.failures.lock().unwrap() = 1;
+        let clock = VirtualClock::default();
+        let progress = CatchUpTracker::default();
+        let fetcher = HeaderFetcher::new(&data_source)
+            .with_page_size(2)
+            .with_retries(3, &clock)
+            .with_progress(progress.clone());
+        let fetch = async { fetcher.headers(1).collect::<Vec<_>>().await };
+        let advance = async {
+            while data_source.subscribed.lock().unwrap().is_empty() {
+                clock.advance(MAX_BACKOFF);
+                async_std::task::yield_now().await;
+            }
+        };
+        let (headers, ()) = futures::join!(fetch, advance);
+
+        // History is fetched a page at a time, and the live stream picks up where it ends.
+        assert_eq!(headers, expected);
+        assert_eq!(
+            *data_source.requested.lock().unwrap(),
+            [(1, 3), (1, 3), (3, 5)]
+        );
+        assert_eq!(*data_source.subscribed.lock().unwrap(), [5]);
+        let progress = progress.progress().await.unwrap();
+        assert_eq!((progress.start, progress.processed), (1, 4));
+        assert_eq!(progress.remaining, 0);
+        assert!(progress.caught_up);
+
+        // A page which never becomes available is an error.
+        *data_source.failures.lock().unwrap() = 3;
+        let fetch = fetcher.fetch_page(0, 2);
+        let advance = async {
+            for _ in 0..3 {
+                clock.advance(MAX_BACKOFF);
+                async_std::task::yield_now().await;
+            }
+        };
+        let (res, ()) = futures::join!(fetch, advance);
+        assert_eq!(
+            res,
+            Err(FetchError::Unavailable {
+                from: 0,
+                until: 2,
+                attempts: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_catch_up_progress() {
+        let progress = CatchUpProgress::new(10, 30, 110, Duration::from_secs(4));
+        assert_eq!((progress.processed, progress.remaining), (20, 80));
+        assert_eq!(progress.blocks_per_second, 5.);
+        assert_eq!(progress.eta_seconds, Some(16));
+        assert!(!progress.caught_up);
+
+        // Before any time has passed, the rate is unknown.
+        let progress = CatchUpProgress::new(10, 10, 110, Duration::ZERO);
+        assert_eq!(progress.eta_seconds, None);
+    }
+}
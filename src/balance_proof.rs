@@ -0,0 +1,147 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Merkle proofs of account balances, formatted for verification by the rollup contract.
+//!
+//! Accounts are hashed into a binary Merkle tree whose leaves are
+//! `keccak256(abi.encodePacked(address, uint64 balance, uint64 nonce))`, ordered by address and
+//! padded with zero leaves up to a power of two. Internal nodes are `keccak256(left ++ right)`.
+//! This matches the `verifyBalance` function of the `ExampleRollup` contract, so a proof produced
+//! here can be checked on chain.
+
+use crate::state::{Amount, Nonce};
+use ethers::{abi::Address, types::H256, utils::keccak256};
+use serde::{Deserialize, Serialize};
+
+/// Proof that an account has a given balance and nonce in a tree with a particular root.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceProof {
+    pub address: Address,
+    pub balance: Amount,
+    pub nonce: Nonce,
+    /// Position of the account's leaf in the tree.
+    pub index: u64,
+    /// Sibling hashes on the path from the leaf to the root, starting at the leaf.
+    pub siblings: Vec<H256>,
+}
+
+pub(crate) fn leaf_hash(address: &Address, balance: Amount, nonce: Nonce) -> H256 {
+    let mut bytes = address.as_bytes().to_vec();
+    bytes.extend_from_slice(&balance.to_be_bytes());
+    bytes.extend_from_slice(&nonce.to_be_bytes());
+    H256(keccak256(bytes))
+}
+
+pub(crate) fn node_hash(left: &H256, right: &H256) -> H256 {
+    H256(keccak256([left.as_bytes(), right.as_bytes()].concat()))
+}
+
+/// Compute every level of the tree with the given leaves, from the leaves up to the root.
+pub(crate) fn tree_levels(mut leaves: Vec<H256>) -> Vec<Vec<H256>> {
+    leaves.resize(leaves.len().max(1).next_power_of_two(), H256::zero());
+    let mut levels = vec![leaves];
+    while levels.last().unwrap().len() > 1 {
+        let next = levels
+            .last()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| node_hash(&pair[0], &pair[1]))
+            .collect();
+        levels.push(next);
+    }
+    levels
+}
+
+/// Extract the sibling path of the leaf at `index` from the levels of a tree.
+pub(crate) fn sibling_path(levels: &[Vec<H256>], mut index: usize) -> Vec<H256> {
+    let mut siblings = vec![];
+    for level in &levels[..levels.len() - 1] {
+        siblings.push(level[index ^ 1]);
+        index /= 2;
+    }
+    siblings
+}
+
+impl BalanceProof {
+    /// Compute the root implied by this proof.
+    pub fn root(&self) -> H256 {
+        let mut node = leaf_hash(&self.address, self.balance, self.nonce);
+        for (i, sibling) in self.siblings.iter().enumerate() {
+            node = if (self.index >> i) & 1 == 1 {
+                node_hash(sibling, &node)
+            } else {
+                node_hash(&node, sibling)
+            };
+        }
+        node
+    }
+
+    /// Check the proof against a known accounts root.
+    pub fn verify(&self, root: H256) -> bool {
+        self.root() == root
+    }
+
+    /// ABI encoded calldata for a `verifyBalance` call on the rollup contract checking this proof
+    /// against `root`.
+    #[cfg(feature = "executor")]
+    pub fn calldata(&self, root: H256) -> ethers::types::Bytes {
+        use contract_bindings::example_rollup::VerifyBalanceCall;
+        use ethers::{abi::AbiEncode, types::U256};
+
+        VerifyBalanceCall {
+            root: root.0,
+            account: self.address,
+            balance: self.balance,
+            nonce: self.nonce,
+            index: U256::from(self.index),
+            proof: self.siblings.iter().map(|sibling| sibling.0).collect(),
+        }
+        .encode()
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+    use crate::RollupVM;
+    use espresso_types::NamespaceId;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[test]
+    fn test_balance_proof() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let wallets = (0..3)
+            .map(|_| LocalWallet::new(&mut rng))
+            .collect::<Vec<_>>();
+        let state = State::from_initial_balances(
+            wallets
+                .iter()
+                .enumerate()
+                .map(|(i, wallet)| (wallet.address(), 100 * i as u64)),
+            vm,
+        );
+        let root = state.accounts_root();
+
+        for (i, wallet) in wallets.iter().enumerate() {
+            let proof = state
+                .balance_proof(&wallet.address())
+                .expect("Account should exist");
+            assert_eq!(proof.balance, 100 * i as u64);
+            assert!(proof.verify(root));
+
+            let mut forged = proof.clone();
+            forged.balance += 1;
+            assert!(!forged.verify(root));
+        }
+
+        assert!(state
+            .balance_proof(&LocalWallet::new(&mut rng).address())
+            .is_none());
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Standalone auditor of a rollup operator.
+//!
+//! The auditor follows HotShot headers from the sequencer, re-executes the rollup namespace from
+//! the genesis state, and continuously compares the resulting state commitments with those served
+//! by a target rollup API and with the `StateUpdate` events emitted by the rollup contract on the
+//! L1. Every divergence is logged as an error. The genesis flags must match those of the audited
+//! node, and use the same environment variables, so the auditor can share its configuration.
+//!
+//! Since only the execution of blocks depends on the rollup state, the auditor can follow a rollup
+//! under either state model, selected with `--state-model`.
+//!
+//! With `--replay-report <path>`, the auditor instead scans the history of a rollup under the
+//! account model for replayed transactions, up to the current block height, writes the
+//! [`ReplayReport`](example_l2::replay_audit::ReplayReport) to
+//! `path` as JSON, and exits.
+
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+use clap::Parser;
+use committable::{Commitment, Committable};
+use contract_bindings::example_rollup::{ExampleRollup, StateUpdateFilter};
+use espresso_types::NamespaceId;
+use ethers::{providers::Middleware, signers::Signer, types::Address};
+use example_l2::{
+    audit::Auditor,
+    backfill::HeaderFetcher,
+    chain::ChainId,
+    data_source::{QueryServiceDataSource, SequencerDataSource},
+    deployment::DeploymentRecord,
+    http::HttpClientPool,
+    l1::{ClientPool, L1Provider},
+    machine::{RollupStateMachine, StateModel},
+    replay_audit::ReplayAudit,
+    seed::{seed_accounts, INITIAL_BALANCE},
+    state::{
+        Amount, OrderingPolicy, ReplayProtection, State, SubmissionPolicy, UntrustedSubmissions,
+        MAX_FEE_BURN_BPS,
+    },
+    utxo::UtxoState,
+    RollupVM, SequencerApiVersion,
+};
+use futures::StreamExt;
+use sequencer_utils::commitment_to_u256;
+use surf_disco::{error::ClientError, Client, Url};
+
+#[derive(Parser, Clone, Debug)]
+pub struct Options {
+    /// URL of the HotShot query service.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_URL",
+        default_value = "http://0.0.0.0:24000/v0/"
+    )]
+    pub sequencer_url: Url,
+
+    /// URL of layer 1 Ethereum JSON-RPC provider.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_L1_HTTP_PROVIDER",
+        default_value = "http://localhost:8545"
+    )]
+    pub l1_http_provider: Url,
+
+    /// Address of the rollup contract.
+    ///
+    /// If not provided, the address is read from `deployment_file`.
+    #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_ADDRESS")]
+    pub rollup_address: Option<Address>,
+
+    /// Namespace of the sequencer in which the audited rollup's transactions are sequenced.
+    #[clap(long, env = "ESPRESSO_DEMO_NAMESPACE", default_value = "1")]
+    pub namespace: u64,
+
+    /// JSON file recording the deployment of the rollup contract. If given, the genesis state is
+    /// also checked against the recorded genesis commitment.
+    #[clap(long, env = "ESPRESSO_DEMO_DEPLOYMENT_FILE")]
+    pub deployment_file: Option<PathBuf>,
+
+    /// URL of the rollup API to audit.
+    #[clap(
+        long,
+        env = "ESPRESSO_AUDITOR_TARGET_API",
+        required_unless_present = "replay_report"
+    )]
+    pub target_api: Option<Url>,
+
+    /// Scan the rollup's history for replayed transactions and write the report to this file,
+    /// instead of auditing the target API.
+    ///
+    /// Only rollups under the account state model can be scanned.
+    #[clap(long, env = "ESPRESSO_AUDITOR_REPLAY_REPORT")]
+    pub replay_report: Option<PathBuf>,
+
+    /// State model of the audited rollup.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_STATE_MODEL",
+        value_enum,
+        default_value_t = StateModel::Account
+    )]
+    pub state_model: StateModel,
+
+    /// Interval between polls of the rollup contract for state updates, in seconds.
+    #[clap(long, env = "ESPRESSO_AUDITOR_L1_POLL_INTERVAL", default_value = "10")]
+    pub l1_poll_interval: u64,
+
+    /// Number of seed accounts funded at genesis by the audited node.
+    #[clap(long, env = "ESPRESSO_DEMO_SEED_ACCOUNTS", default_value = "3")]
+    pub seed_accounts: u64,
+
+    /// Initial balance of each seed account.
+    #[clap(long, env = "ESPRESSO_DEMO_SEED_BALANCE", default_value_t = INITIAL_BALANCE)]
+    pub seed_balance: u64,
+
+    /// Replay protection of the audited rollup.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_REPLAY_PROTECTION",
+        value_enum,
+        default_value_t = ReplayProtection::Nonce
+    )]
+    pub replay_protection: ReplayProtection,
+
+    /// Replay window of the audited rollup, with `recent-hashes` replay protection.
+    #[clap(long, env = "ESPRESSO_DEMO_REPLAY_WINDOW", default_value = "100")]
+    pub replay_window: u64,
+
+    /// Transaction ordering policy of the audited rollup.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_ORDERING_POLICY",
+        value_enum,
+        default_value_t = OrderingPolicy::Namespace
+    )]
+    pub ordering_policy: OrderingPolicy,
+
+    /// Submission operator of the audited rollup.
+    #[clap(long, env = "ESPRESSO_DEMO_SUBMISSION_OPERATOR")]
+    pub submission_operator: Option<Address>,
+
+    /// Handling of untrusted submissions by the audited rollup.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_UNTRUSTED_SUBMISSIONS",
+        value_enum,
+        default_value_t = UntrustedSubmissions::Strict
+    )]
+    pub untrusted_submissions: UntrustedSubmissions,
+
+    /// Share of each transaction fee burned by the audited rollup, in basis points.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_FEE_BURN_BPS",
+        default_value = "0",
+        value_parser = clap::value_parser!(u16).range(0..=MAX_FEE_BURN_BPS as i64)
+    )]
+    pub fee_burn_bps: u16,
+}
+
+fn genesis_balances(opt: &Options) -> Vec<(Address, Amount)> {
+    seed_accounts(opt.seed_accounts)
+        .map(|account| (account.wallet.address(), opt.seed_balance))
+        .collect()
+}
+
+fn genesis_state(opt: &Options, vm: RollupVM) -> State {
+    State::from_initial_balances(genesis_balances(opt), vm)
+        .with_replay_protection(opt.replay_protection, opt.replay_window)
+        .with_ordering_policy(opt.ordering_policy)
+        .with_submission_policy(SubmissionPolicy {
+            operator: opt.submission_operator,
+            untrusted: opt.untrusted_submissions,
+            fee_burn_bps: opt.fee_burn_bps,
+        })
+}
+
+/// Compares the auditor's commitments with those served by the target API and the L1.
+struct Comparisons<S: RollupStateMachine> {
+    api: Client<ClientError, SequencerApiVersion>,
+    rollup: ExampleRollup<L1Provider>,
+    /// Executed blocks whose commitment has not yet been served by the target API.
+    unchecked_blocks: VecDeque<u64>,
+    /// State updates on the L1 beyond the blocks processed so far.
+    unchecked_updates: VecDeque<StateUpdateFilter>,
+    next_l1_block: u64,
+    last_l1_poll: Option<Instant>,
+    l1_poll_interval: Duration,
+    divergences: usize,
+    _state: PhantomData<S>,
+}
+
+impl<S: RollupStateMachine> Comparisons<S> {
+    async fn check_api(&mut self, auditor: &Auditor<S>) {
+        while let Some(&block_height) = self.unchecked_blocks.front() {
+            let served = match self
+                .api
+                .get::<Commitment<S>>(&format!("rollup/block/{block_height}/commitment"))
+                .send()
+                .await
+            {
+                Ok(commitment) => commitment,
+                Err(err) => {
+                    // The operator may simply not have executed the block yet.
+                    tracing::debug!("Target API has no commitment for block {block_height}: {err}");
+                    return;
+                }
+            };
+            self.unchecked_blocks.pop_front();
+            match auditor.check_api(block_height, Some(served)) {
+                Ok(()) => tracing::info!("Target API agrees with state after block {block_height}"),
+                Err(divergence) => self.report(divergence.to_string()),
+            }
+        }
+    }
+
+    async fn check_l1(&mut self, auditor: &Auditor<S>) {
+        if self
+            .last_l1_poll
+            .is_some_and(|last| last.elapsed() < self.l1_poll_interval)
+        {
+            return;
+        }
+        self.last_l1_poll = Some(Instant::now());
+        match self
+            .rollup
+            .state_update_filter()
+            .from_block(self.next_l1_block)
+            .query_with_meta()
+            .await
+        {
+            Ok(logs) => {
+                for (update, meta) in logs {
+                    self.next_l1_block = meta.block_number.as_u64() + 1;
+                    self.unchecked_updates.push_back(update);
+                }
+            }
+            Err(err) => tracing::error!("Error polling rollup contract for state updates: {err}"),
+        }
+        while let Some(update) = self.unchecked_updates.front() {
+            let num_verified_blocks = update.block_height.as_u64();
+            let Some(result) = auditor.check_l1(num_verified_blocks, update.state_commitment)
+            else {
+                // Not yet re-executed.
+                return;
+            };
+            self.unchecked_updates.pop_front();
+            match result {
+                Ok(()) => tracing::info!(
+                    "L1 commitment after {num_verified_blocks} blocks agrees with re-execution"
+                ),
+                Err(divergence) => self.report(divergence.to_string()),
+            }
+        }
+    }
+
+    fn report(&mut self, divergence: String) {
+        self.divergences += 1;
+        tracing::error!("DIVERGENCE ({} so far): {divergence}", self.divergences);
+    }
+}
+
+#[async_std::main]
+async fn main() {
+    setup_logging();
+    setup_backtrace();
+
+    let opt = Options::parse();
+    let vm = RollupVM::new(NamespaceId::from(opt.namespace));
+    if let Some(path) = &opt.replay_report {
+        if opt.state_model != StateModel::Account {
+            panic!("Replay audits require the account state model");
+        }
+        replay_audit(&opt, path, genesis_state(&opt, vm)).await;
+        return;
+    }
+    match opt.state_model {
+        StateModel::Account => audit(&opt, Auditor::new(genesis_state(&opt, vm))).await,
+        StateModel::Utxo => {
+            let genesis = UtxoState::from_initial_balances(genesis_balances(&opt), vm);
+            audit(&opt, Auditor::new(genesis)).await
+        }
+    }
+}
+
+/// Find the rollup contract, checking `genesis` against the deployment record if there is one, and
+/// derive the rollup chain ID, returning the contract address, the L1 provider and the chain ID.
+async fn rollup_deployment<S: Committable>(
+    opt: &Options,
+    namespace: NamespaceId,
+    genesis: Commitment<S>,
+) -> (Address, L1Provider, ChainId) {
+    let rollup_address = match (&opt.rollup_address, &opt.deployment_file) {
+        (Some(address), _) => *address,
+        (None, Some(path)) => {
+            let record = DeploymentRecord::load(path)
+                .expect("Error reading deployment file")
+                .expect("Deployment file does not exist");
+            if commitment_to_u256(record.genesis_commitment) != commitment_to_u256(genesis) {
+                panic!(
+                    "Genesis state {genesis} does not match the deployed genesis {}; check that \
+                     the genesis flags match the audited node",
+                    record.genesis_commitment
+                );
+            }
+            record.rollup_address
+        }
+        (None, None) => panic!("Either --rollup-address or --deployment-file is required"),
+    };
+    let provider = ClientPool::new(&opt.l1_http_provider, [])
+        .expect("Invalid L1 provider URL")
+        .provider();
+    let l1_chain_id = provider
+        .get_chainid()
+        .await
+        .expect("Error fetching L1 chain ID")
+        .as_u64();
+    let chain_id = ChainId::derive(namespace, genesis, l1_chain_id, rollup_address);
+    tracing::info!("Rollup chain ID is {chain_id}");
+    (rollup_address, provider, chain_id)
+}
+
+async fn audit<S: RollupStateMachine>(opt: &Options, mut auditor: Auditor<S>) {
+    tracing::info!(
+        "Auditing {} rollup from genesis state {}",
+        opt.state_model,
+        auditor.genesis_commitment()
+    );
+
+    let (rollup_address, provider, chain_id) =
+        rollup_deployment(opt, auditor.namespace(), auditor.genesis_commitment()).await;
+    auditor.set_chain_id(chain_id);
+    let target_api = opt
+        .target_api
+        .clone()
+        .expect("--target-api is required to audit a rollup API");
+    let mut comparisons = Comparisons {
+        api: Client::new(target_api),
+        rollup: ExampleRollup::new(rollup_address, Arc::new(provider)),
+        unchecked_blocks: Default::default(),
+        unchecked_updates: Default::default(),
+        next_l1_block: 0,
+        last_l1_poll: None,
+        l1_poll_interval: Duration::from_secs(opt.l1_poll_interval),
+        divergences: 0,
+        _state: PhantomData,
+    };
+
+    let http = HttpClientPool::default();
+    let data_source = QueryServiceDataSource::connect(&opt.sequencer_url, &http).await;
+    let fetcher = HeaderFetcher::new(&data_source);
+    let mut headers = fetcher.headers(0);
+    while let Some(header) = headers.next().await {
+        let block_height = header.height();
+        if let Some(commitment) = auditor.execute(&data_source, header).await {
+            tracing::debug!("Re-executed block {block_height}: {commitment}");
+            comparisons.unchecked_blocks.push_back(block_height);
+        }
+        comparisons.check_api(&auditor).await;
+        comparisons.check_l1(&auditor).await;
+    }
+    tracing::error!("Header stream ended after {} blocks", auditor.processed());
+}
+
+async fn replay_audit(opt: &Options, path: &Path, mut genesis: State) {
+    tracing::info!(
+        "Scanning rollup history for replays from genesis state {}",
+        genesis.commit()
+    );
+    let (_, _, chain_id) = rollup_deployment(opt, genesis.namespace(), genesis.commit()).await;
+    genesis.set_chain_id(chain_id);
+    let mut audit = ReplayAudit::new(genesis);
+
+    let http = HttpClientPool::default();
+    let data_source = QueryServiceDataSource::connect(&opt.sequencer_url, &http).await;
+    let block_height = data_source
+        .block_height()
+        .await
+        .expect("Error fetching block height");
+    let fetcher = HeaderFetcher::new(&data_source);
+    let mut headers = fetcher.headers(0).take(block_height as usize);
+    while let Some(header) = headers.next().await {
+        audit.scan(&data_source, header).await;
+    }
+
+    let report = audit.report();
+    tracing::info!(
+        "Scanned {} transactions in {} blocks, {} findings",
+        report.transactions_scanned,
+        report.blocks_scanned,
+        report.findings.len()
+    );
+    std::fs::write(path, serde_json::to_string_pretty(report).unwrap())
+        .expect("Error writing replay report");
+}
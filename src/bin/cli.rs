@@ -4,26 +4,30 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
+use std::io::Write;
+use std::path::PathBuf;
 use std::time::Duration;
 
+use async_std::{io::stdin, task::sleep};
 use clap::{Args, Parser, Subcommand};
+use espresso_types::NamespaceId;
 use ethers::{
     prelude::k256::ecdsa::SigningKey,
-    signers::{LocalWallet, Signer, Wallet},
-    types::Address,
+    signers::{Signer, Wallet},
 };
 use example_l2::{
+    client::RollupClient,
+    repl::{ReplCommand, Session, REPL_HELP},
     seed::SeedIdentity,
     state::{Amount, Nonce},
-    transaction::{SignedTransaction, Transaction},
+    transaction::TransactionBuilder,
+    verify::ReceiptBundle,
 };
-use rand::SeedableRng;
-use rand_chacha::ChaChaRng;
-use sequencer::SequencerApiVersion;
-use surf_disco::{error::ClientError, Client};
+use futures::future::{select, FutureExt};
 use tide_disco::Url;
 
-type RollupClient = Client<ClientError, SequencerApiVersion>;
+/// Interval at which `watch` polls the balance of an account.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Parser, Clone, Debug)]
 pub struct Options {
@@ -31,6 +35,10 @@ pub struct Options {
     #[clap(short, long, default_value = "http://localhost:8084")]
     pub rollup_url: Url,
 
+    /// Url of the sequencer API, used to submit transfers if the Rollup client is unavailable.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_URL")]
+    pub sequencer_url: Option<Url>,
+
     #[command(subcommand)]
     pub command: ExampleRollupCommand,
 }
@@ -39,6 +47,12 @@ pub struct Options {
 pub enum ExampleRollupCommand {
     Transfer(Transfer),
     CheckBalance(CheckBalance),
+    /// Start an interactive session, which caches nonces and remembers named identities between
+    /// commands. Enter `help` for the available commands.
+    Repl,
+    /// Check a receipt bundle downloaded from `rollup/tx/:hash/receipt-bundle`, without
+    /// connecting to the rollup.
+    VerifyReceipt(VerifyReceipt),
 }
 
 #[derive(Args, Clone, Debug)]
@@ -46,6 +60,28 @@ pub struct Transfer {
     pub sender: SeedIdentity,
     pub receiver: SeedIdentity,
     pub amount: Amount,
+
+    /// Nonce to sign the transaction with. Required if the Rollup client is unavailable, since the
+    /// next nonce of the sender cannot be looked up.
+    #[clap(long)]
+    pub nonce: Option<Nonce>,
+
+    /// Note to attach to the transaction.
+    #[clap(long)]
+    pub memo: Option<String>,
+
+    /// Fee to pay the rollup operator, on top of the amount.
+    #[clap(long, default_value = "0")]
+    pub fee: Amount,
+
+    /// Block height from which the transaction is no longer valid.
+    #[clap(long)]
+    pub expires_at: Option<u64>,
+
+    /// Withdraw the amount to the receiver's address on the L1, instead of transferring it on the
+    /// rollup.
+    #[clap(long)]
+    pub withdraw: bool,
 }
 
 #[derive(Args, Clone, Debug)]
@@ -53,21 +89,64 @@ pub struct CheckBalance {
     pub identity: SeedIdentity,
 }
 
+#[derive(Args, Clone, Debug)]
+pub struct VerifyReceipt {
+    /// Path to the bundle.
+    pub path: PathBuf,
+
+    /// Namespace of the rollup which executed the transfer.
+    #[clap(long, default_value = "1")]
+    pub namespace: u64,
+}
+
 fn get_wallet_from_identity(identity: &SeedIdentity) -> Wallet<SigningKey> {
-    LocalWallet::new(&mut ChaChaRng::seed_from_u64(*identity as u64))
+    identity.wallet()
 }
 
-async fn transfer(transfer: &Transfer, client: &RollupClient) {
+async fn transfer(transfer: &Transfer, client: &RollupClient, connected: bool) {
     let sender = get_wallet_from_identity(&transfer.sender);
     let receiver = get_wallet_from_identity(&transfer.receiver);
     let amount = transfer.amount;
-    let nonce = get_nonce(&sender.address(), client).await + 1;
-    let transaction = Transaction {
-        amount,
-        destination: receiver.address(),
-        nonce,
-    };
-    let signed_transaction = SignedTransaction::new(transaction, &sender).await;
+    let mut builder = TransactionBuilder::new()
+        .amount(amount)
+        .destination(receiver.address())
+        .fee(transfer.fee);
+    if let Some(nonce) = transfer.nonce {
+        builder = builder.nonce(nonce);
+    }
+    if let Some(memo) = &transfer.memo {
+        builder = builder.memo(memo);
+    }
+    if let Some(expires_at) = transfer.expires_at {
+        builder = builder.expires_at(expires_at);
+    }
+    if transfer.withdraw {
+        builder = builder.withdraw();
+    }
+    // Look up the nonce, and sign for the rollup chain if the API reports one. Without the API, the
+    // transaction is signed without a chain ID, which any deployment accepts.
+    if connected {
+        builder = client
+            .prepare(builder, sender.address())
+            .await
+            .expect("Error preparing the transaction");
+    }
+    let signed_transaction = builder.sign(&sender).await.expect("Invalid transaction");
+
+    if !connected {
+        println!(
+            "Submitting Transaction directly to the sequencer: Transferring {} tokens from {} to {}",
+            amount,
+            sender.address(),
+            receiver.address(),
+        );
+        let hash = client
+            .submit_via_sequencer(&signed_transaction)
+            .await
+            .expect("Error sending the transfer transaction to the sequencer");
+        println!("Sequencer transaction: {hash}");
+        return;
+    }
 
     println!(
         "Submitting Transaction to Rollup API: Transferring {} tokens from {} to {}",
@@ -77,50 +156,235 @@ async fn transfer(transfer: &Transfer, client: &RollupClient) {
     );
 
     client
-        .post::<()>("rollup/submit")
-        .body_json(&signed_transaction)
-        .expect("Error setting the response body")
-        .send()
+        .submit(&signed_transaction)
         .await
         .expect("Error sending the transfer transaction")
 }
 
-async fn get_nonce(address: &Address, client: &RollupClient) -> Nonce {
-    client
-        .get::<u64>(&format!("rollup/nonce/{:?}", address))
-        .send()
-        .await
-        .expect("Error sending the get nonce request")
-}
-
 async fn check_balance(check_balance: &CheckBalance, client: &RollupClient) {
     let address = get_wallet_from_identity(&check_balance.identity).address();
     let balance = client
-        .get::<u64>(&format!("rollup/balance/{:?}", address))
-        .send()
+        .balance(address)
         .await
         .expect("Error sending the check balance request");
 
     println!("Balance of {:?}: {}", address, balance)
 }
 
+/// Run one REPL command, returning `false` if the session should end.
+async fn run_command(session: &mut Session, client: &RollupClient, command: ReplCommand) -> bool {
+    match command {
+        ReplCommand::Balance(who) => match session.resolve(&who) {
+            Ok(address) => match client.balance(address).await {
+                Ok(balance) => println!("{who}: {balance}"),
+                Err(err) => println!("Error fetching balance: {err}"),
+            },
+            Err(err) => println!("{err}"),
+        },
+        ReplCommand::Nonce(who) => match session.resolve(&who) {
+            Ok(address) => match client.nonce(address).await {
+                Ok(nonce) => println!("{who}: {nonce}"),
+                Err(err) => println!("Error fetching nonce: {err}"),
+            },
+            Err(err) => println!("{err}"),
+        },
+        ReplCommand::Send {
+            from,
+            to,
+            amount,
+            fee,
+        } => send(session, client, &from, &to, amount, fee).await,
+        ReplCommand::Watch(who) => match session.resolve(&who) {
+            Ok(address) => {
+                println!("Watching {who}, press Enter to stop");
+                let stop = async {
+                    let mut line = String::new();
+                    let _ = stdin().read_line(&mut line).await;
+                };
+                let poll = async {
+                    let mut last = None;
+                    loop {
+                        match client.balance(address).await {
+                            Ok(balance) if last != Some(balance) => {
+                                println!("{who}: {balance}");
+                                last = Some(balance);
+                            }
+                            Ok(_) => {}
+                            Err(err) => println!("Error fetching balance: {err}"),
+                        }
+                        sleep(WATCH_INTERVAL).await;
+                    }
+                };
+                select(stop.boxed_local(), poll.boxed_local()).await;
+            }
+            Err(err) => println!("{err}"),
+        },
+        ReplCommand::Identity { name, index } => {
+            let address = session.add_identity(&name, index);
+            println!("{name}: {address:?}");
+        }
+        ReplCommand::Identities => {
+            for (name, address) in session.identities() {
+                println!("{name}: {address:?}");
+            }
+        }
+        ReplCommand::History => {
+            for (i, line) in session.history().iter().enumerate() {
+                println!("{:>4}  {line}", i + 1);
+            }
+        }
+        ReplCommand::Help => println!("{REPL_HELP}"),
+        ReplCommand::Exit => return false,
+    }
+    true
+}
+
+/// Submit a transfer from the REPL, using the session's cached nonce of the sender if it has one.
+async fn send(
+    session: &mut Session,
+    client: &RollupClient,
+    from: &str,
+    to: &str,
+    amount: Amount,
+    fee: Amount,
+) {
+    let (sender, destination) = match (session.wallet(from), session.resolve(to)) {
+        (Ok(sender), Ok(destination)) => (sender.clone(), destination),
+        (Err(err), _) | (_, Err(err)) => {
+            println!("{err}");
+            return;
+        }
+    };
+    let mut builder = TransactionBuilder::new()
+        .amount(amount)
+        .destination(destination)
+        .fee(fee);
+    if let Some(nonce) = session.next_nonce(sender.address()) {
+        builder = builder.nonce(nonce);
+    }
+    let signed_transaction = match client.prepare(builder, sender.address()).await {
+        Ok(builder) => match builder.sign(&sender).await {
+            Ok(transaction) => transaction,
+            Err(err) => {
+                println!("Invalid transaction: {err}");
+                return;
+            }
+        },
+        Err(err) => {
+            println!("Error preparing the transaction: {err}");
+            return;
+        }
+    };
+    let nonce = signed_transaction.transaction.nonce;
+    match client.submit(&signed_transaction).await {
+        Ok(()) => {
+            session.record_nonce(sender.address(), nonce);
+            println!("Submitted transfer of {amount} from {from} to {to} with nonce {nonce}");
+        }
+        Err(err) => {
+            // The cached nonce may be what was wrong, so look it up again next time.
+            session.forget_nonce(sender.address());
+            println!("Error submitting the transfer: {err}");
+        }
+    }
+}
+
+fn verify_receipt(verify: &VerifyReceipt) {
+    let bundle = std::fs::read(&verify.path).expect("Error reading the bundle");
+    let bundle: ReceiptBundle = serde_json::from_slice(&bundle).expect("Malformed bundle");
+    match bundle.verify(NamespaceId::from(verify.namespace)) {
+        Ok(payment) => {
+            println!(
+                "Transfer {:?} of {} (fee {}) from {:?} to {:?} executed in block {}",
+                payment.hash,
+                payment.amount,
+                payment.fee,
+                payment.from,
+                payment.to,
+                payment.block_height
+            );
+            println!(
+                "State commitment after the block: {}",
+                payment.state_commitment
+            );
+            match (payment.verified_on_l1, payment.l1_tx_hash) {
+                (true, Some(tx_hash)) => println!("Verified on the L1 in {tx_hash:?}"),
+                (true, None) => println!("Verified on the L1"),
+                (false, _) => println!("Not yet verified on the L1"),
+            }
+        }
+        Err(err) => {
+            println!("Invalid receipt bundle: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn repl(client: &RollupClient) {
+    let mut session = Session::new();
+    println!("Connected to the rollup. Enter `help` for the available commands.");
+    loop {
+        print!("rollup> ");
+        std::io::stdout().flush().unwrap();
+        let mut line = String::new();
+        match stdin().read_line(&mut line).await {
+            // End of input.
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) => {
+                println!("Error reading input: {err}");
+                break;
+            }
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        session.record(&line);
+        match line.parse() {
+            Ok(command) => {
+                if !run_command(&mut session, client, command).await {
+                    break;
+                }
+            }
+            Err(err) => println!("{err}"),
+        }
+    }
+}
+
 #[async_std::main]
 async fn main() {
     let Options {
         rollup_url,
+        sequencer_url,
         command,
     } = Options::parse();
-    let client: RollupClient = Client::new(rollup_url);
+    if let ExampleRollupCommand::VerifyReceipt(verify) = &command {
+        verify_receipt(verify);
+        return;
+    }
+    let mut client = RollupClient::new(rollup_url);
+    let can_fall_back = match (&sequencer_url, &command) {
+        (Some(_), ExampleRollupCommand::Transfer(transfer)) => transfer.nonce.is_some(),
+        _ => false,
+    };
+    if let Some(url) = sequencer_url {
+        client = client.with_sequencer(url);
+    }
     let connected = client.connect(Some(Duration::from_secs(2))).await;
-    if !connected {
+    if !connected && !can_fall_back {
         println!("Could not connect to the Rollup Client. Ensure that the client is running and that the supplied port is correct.");
+        println!("To transfer without the Rollup Client, pass --sequencer-url and --nonce.");
         return;
     }
 
     match command {
-        ExampleRollupCommand::Transfer(transfer_cmd) => transfer(&transfer_cmd, &client).await,
+        ExampleRollupCommand::Transfer(transfer_cmd) => {
+            transfer(&transfer_cmd, &client, connected).await
+        }
         ExampleRollupCommand::CheckBalance(check_balance_cmd) => {
             check_balance(&check_balance_cmd, &client).await;
         }
+        ExampleRollupCommand::Repl => repl(&client).await,
+        ExampleRollupCommand::VerifyReceipt(_) => unreachable!(),
     };
 }
@@ -0,0 +1,232 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Operator tooling for inspecting and driving the rollup contract on the L1.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::{Args, Parser, Subcommand};
+use contract_bindings::example_rollup::{ExampleRollup, ExampleRollupErrors};
+use ethers::{
+    abi::AbiDecode,
+    providers::{Middleware, RpcError},
+    types::{transaction::eip2718::TypedTransaction, Address, H256},
+};
+use example_l2::{
+    deployment::DeploymentRecord,
+    l1::{connect_l1_client, ClientPool, L1ClientKind, L1Provider},
+    outbox::{Outbox, SubmissionStatus},
+    secret::Secret,
+    signer::L1SignerConfig,
+};
+use tide_disco::Url;
+
+#[derive(Parser, Clone, Debug)]
+pub struct Options {
+    /// URL of layer 1 Ethereum JSON-RPC provider.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_L1_HTTP_PROVIDER",
+        default_value = "http://localhost:8545"
+    )]
+    pub l1_http_provider: Url,
+
+    /// Address of the rollup contract.
+    ///
+    /// If not provided, the address is read from `deployment_file`.
+    #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_ADDRESS")]
+    pub rollup_address: Option<Address>,
+
+    /// JSON file recording the deployment of the rollup contract.
+    #[clap(long, env = "ESPRESSO_DEMO_DEPLOYMENT_FILE")]
+    pub deployment_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: OpsCommand,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum OpsCommand {
+    /// Print the state commitment and number of verified blocks stored by the rollup contract.
+    State,
+    /// Decode the reason a transaction sent to the rollup contract reverted.
+    RevertReason(RevertReason),
+    /// Submit a batch proof recorded in the outbox.
+    ///
+    /// A new transaction is sent regardless of any earlier submission of the batch, and the outbox
+    /// is not updated, so the executor should be stopped while this is used.
+    SubmitBatch(SubmitBatch),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct RevertReason {
+    pub tx_hash: H256,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SubmitBatch {
+    /// JSON file recording batch proofs awaiting submission.
+    #[clap(long, env = "ESPRESSO_DEMO_OUTBOX_FILE")]
+    pub outbox_file: PathBuf,
+
+    /// Last block of the batch to submit. Defaults to the first unconfirmed batch.
+    #[clap(long)]
+    pub last_block: Option<u64>,
+
+    /// Mnemonic phrase for the wallet sending the proof.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_ROLLUP_MNEMONIC",
+        default_value = "test test test test test test test test test test test junk"
+    )]
+    pub rollup_mnemonic: Secret<String>,
+
+    /// Index of the account derived from the mnemonic which sends the proof.
+    #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_ACCOUNT_INDEX", default_value = "1")]
+    pub rollup_account_index: u32,
+}
+
+fn rollup_address(opt: &Options) -> Address {
+    if let Some(address) = opt.rollup_address {
+        return address;
+    }
+    let path = opt
+        .deployment_file
+        .as_ref()
+        .expect("Either --rollup-address or --deployment-file is required");
+    DeploymentRecord::load(path)
+        .expect("Error reading deployment file")
+        .expect("Deployment file does not exist")
+        .rollup_address
+}
+
+async fn print_state(rollup: &ExampleRollup<L1Provider>) {
+    let state_commitment = rollup
+        .state_commitment()
+        .call()
+        .await
+        .expect("Error reading state commitment");
+    let num_verified_blocks = rollup
+        .num_verified_blocks()
+        .call()
+        .await
+        .expect("Error reading number of verified blocks");
+    println!("State commitment: {state_commitment:#x}");
+    println!("Verified blocks: {num_verified_blocks}");
+}
+
+async fn print_revert_reason(provider: &L1Provider, revert_reason: &RevertReason) {
+    let tx_hash = revert_reason.tx_hash;
+    let receipt = provider
+        .get_transaction_receipt(tx_hash)
+        .await
+        .expect("Error fetching transaction receipt");
+    let Some(receipt) = receipt else {
+        println!("Transaction {tx_hash:?} has not been included");
+        return;
+    };
+    if receipt.status == Some(1.into()) {
+        println!("Transaction {tx_hash:?} succeeded");
+        return;
+    }
+    let transaction = provider
+        .get_transaction(tx_hash)
+        .await
+        .expect("Error fetching transaction")
+        .expect("Transaction has a receipt but was not found");
+    let block_number = receipt
+        .block_number
+        .expect("Receipt of an included transaction has a block number");
+
+    // Replay the transaction against the state before its block to recover the revert data. This
+    // does not account for transactions earlier in the same block.
+    let call = TypedTransaction::from(&transaction);
+    match provider
+        .call(&call, Some((block_number.as_u64() - 1).into()))
+        .await
+    {
+        Ok(_) => println!(
+            "Transaction {tx_hash:?} reverted in block {block_number}, but succeeds when replayed \
+             at the start of the block"
+        ),
+        Err(err) => {
+            let reason = err
+                .as_error_response()
+                .and_then(|response| response.as_revert_data())
+                .and_then(|data| ExampleRollupErrors::decode(data).ok());
+            match reason {
+                Some(reason) => println!("Transaction {tx_hash:?} reverted: {reason:?}"),
+                None => println!("Transaction {tx_hash:?} reverted: {err}"),
+            }
+        }
+    }
+}
+
+async fn submit_batch(opt: &Options, l1: &ClientPool, submit: &SubmitBatch) {
+    let outbox = Outbox::open(&submit.outbox_file).expect("Error opening outbox");
+    let entries = outbox.entries().await;
+    let entry = match submit.last_block {
+        Some(last_block) => entries
+            .into_iter()
+            .find(|entry| entry.last_block == last_block)
+            .unwrap_or_else(|| panic!("No batch ending at block {last_block} in the outbox")),
+        None => entries
+            .into_iter()
+            .find(|entry| !entry.status.is_verified())
+            .expect("No unconfirmed batches in the outbox"),
+    };
+    if let SubmissionStatus::Confirmed { tx_hash } = entry.status {
+        println!(
+            "Batch {}-{} was already confirmed in transaction {tx_hash:?}",
+            entry.first_block, entry.last_block
+        );
+        return;
+    }
+    if entry.status == SubmissionStatus::VerifiedExternally {
+        println!(
+            "Batch {}-{} was already verified by the rollup contract",
+            entry.first_block, entry.last_block
+        );
+        return;
+    }
+
+    let signer = L1SignerConfig::Mnemonic {
+        mnemonic: submit.rollup_mnemonic.clone(),
+        account_index: submit.rollup_account_index,
+    };
+    let l1 = connect_l1_client(L1ClientKind::Ethers, l1, &signer, rollup_address(opt))
+        .await
+        .expect("Error connecting to L1");
+    let nonce = l1.next_nonce().await.expect("Error fetching nonce");
+    println!(
+        "Submitting batch {}-{} ({} blocks) with nonce {nonce}",
+        entry.first_block, entry.last_block, entry.count
+    );
+    let tx_hash = l1
+        .send_verify_blocks(entry.count, entry.proof, entry.shape, nonce)
+        .await
+        .expect("Error submitting batch proof");
+    println!("Submitted in transaction {tx_hash:?}");
+}
+
+#[async_std::main]
+async fn main() {
+    let opt = Options::parse();
+    let l1 = ClientPool::new(&opt.l1_http_provider, []).expect("Invalid L1 provider URL");
+    let provider = l1.provider();
+
+    match &opt.command {
+        OpsCommand::State => {
+            let rollup = ExampleRollup::new(rollup_address(&opt), Arc::new(provider));
+            print_state(&rollup).await;
+        }
+        OpsCommand::RevertReason(revert_reason) => {
+            print_revert_reason(&provider, revert_reason).await
+        }
+        OpsCommand::SubmitBatch(submit) => submit_batch(&opt, &l1, submit).await,
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Circuit breaker which halts proof submission when execution looks anomalous.
+//!
+//! After each executed block, the executor reports a summary of the block to the breaker, which
+//! runs it through a configurable set of [`SafetyCheck`]s. The first failing check trips the
+//! breaker, and from then on no further proofs are submitted to the L1, so that a bug in execution
+//! is not compounded by on-chain state updates. A tripped breaker stays tripped until the node is
+//! restarted.
+
+use async_std::sync::{Arc, RwLock};
+use clap::ValueEnum;
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use strum_macros::Display;
+
+/// Summary of the execution of one block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockProgress {
+    pub block_height: u64,
+    /// Number of transactions in the block which were applied successfully.
+    pub applied_transactions: usize,
+    /// Number of transactions in the block which failed to apply.
+    pub rejected_transactions: usize,
+    pub prev_accounts_root: H256,
+    pub accounts_root: H256,
+    pub prev_total_balance: u128,
+    pub total_balance: u128,
+}
+
+/// A condition which must hold after every block for proofs to be submitted.
+pub trait SafetyCheck: Debug + Send + Sync {
+    /// Check `progress`, returning a description of the anomaly if the check fails.
+    ///
+    /// `finalized_height` is the latest block height finalized by the light client.
+    fn check(&self, progress: &BlockProgress, finalized_height: u64) -> Result<(), String>;
+}
+
+/// The built-in safety checks.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Display, Serialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum SafetyCheckKind {
+    /// Accounts must not change in a block with no applied transactions.
+    EmptyBlockStateChange,
+    /// The sum of all balances must not change, since transfers only move funds.
+    BalanceConservation,
+    /// Execution must not fall too far behind the light client.
+    ExecutionLag,
+}
+
+/// Accounts must not change in a block with no applied transactions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EmptyBlockStateChange;
+
+impl SafetyCheck for EmptyBlockStateChange {
+    fn check(&self, progress: &BlockProgress, _finalized_height: u64) -> Result<(), String> {
+        if progress.applied_transactions == 0
+            && progress.accounts_root != progress.prev_accounts_root
+        {
+            return Err(format!(
+                "accounts changed in block {} with no applied transactions",
+                progress.block_height
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The sum of all balances must not change.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BalanceConservation;
+
+impl SafetyCheck for BalanceConservation {
+    fn check(&self, progress: &BlockProgress, _finalized_height: u64) -> Result<(), String> {
+        if progress.total_balance != progress.prev_total_balance {
+            return Err(format!(
+                "total balance changed from {} to {} in block {}",
+                progress.prev_total_balance, progress.total_balance, progress.block_height
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Execution must be no more than `max_blocks` behind the light client.
+#[derive(Clone, Copy, Debug)]
+pub struct ExecutionLag {
+    pub max_blocks: u64,
+}
+
+impl SafetyCheck for ExecutionLag {
+    fn check(&self, progress: &BlockProgress, finalized_height: u64) -> Result<(), String> {
+        let lag = finalized_height.saturating_sub(progress.block_height);
+        if lag > self.max_blocks {
+            return Err(format!(
+                "execution of block {} is {lag} blocks behind the light client (max {})",
+                progress.block_height, self.max_blocks
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Why a [`CircuitBreaker`] tripped.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trip {
+    pub block_height: u64,
+    pub reason: String,
+}
+
+/// Halts proof submission once any of its checks fails.
+#[derive(Clone, Debug, Default)]
+pub struct CircuitBreaker {
+    checks: Arc<Vec<Box<dyn SafetyCheck>>>,
+    tripped: Arc<RwLock<Option<Trip>>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(checks: Vec<Box<dyn SafetyCheck>>) -> Self {
+        Self {
+            checks: Arc::new(checks),
+            tripped: Default::default(),
+        }
+    }
+
+    /// A breaker running the built-in checks in `kinds`, allowing execution to lag the light client
+    /// by at most `max_lag` blocks.
+    pub fn from_kinds(kinds: &[SafetyCheckKind], max_lag: u64) -> Self {
+        Self::new(
+            kinds
+                .iter()
+                .map(|kind| -> Box<dyn SafetyCheck> {
+                    match kind {
+                        SafetyCheckKind::EmptyBlockStateChange => Box::new(EmptyBlockStateChange),
+                        SafetyCheckKind::BalanceConservation => Box::new(BalanceConservation),
+                        SafetyCheckKind::ExecutionLag => Box::new(ExecutionLag {
+                            max_blocks: max_lag,
+                        }),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Run the checks against the execution of a block, tripping the breaker if any fails.
+    pub async fn observe(&self, progress: &BlockProgress, finalized_height: u64) {
+        if self.checks.is_empty() {
+            return;
+        }
+        let mut tripped = self.tripped.write().await;
+        if tripped.is_some() {
+            return;
+        }
+        for check in self.checks.iter() {
+            if let Err(reason) = check.check(progress, finalized_height) {
+                tracing::error!(
+                    "Circuit breaker tripped, halting proof submission: {reason} ({check:?})"
+                );
+                *tripped = Some(Trip {
+                    block_height: progress.block_height,
+                    reason,
+                });
+                return;
+            }
+        }
+    }
+
+    /// Why the breaker tripped, or `None` if proofs may be submitted.
+    pub async fn tripped(&self) -> Option<Trip> {
+        self.tripped.read().await.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_circuit_breaker() {
+        let kinds = [
+            SafetyCheckKind::EmptyBlockStateChange,
+            SafetyCheckKind::BalanceConservation,
+            SafetyCheckKind::ExecutionLag,
+        ];
+        let breaker = CircuitBreaker::from_kinds(&kinds, 10);
+        let root = H256::random();
+        let progress = BlockProgress {
+            block_height: 5,
+            applied_transactions: 0,
+            rejected_transactions: 0,
+            prev_accounts_root: root,
+            accounts_root: root,
+            prev_total_balance: 100,
+            total_balance: 100,
+        };
+        breaker.observe(&progress, 15).await;
+        assert_eq!(breaker.tripped().await, None);
+
+        // Each anomaly trips a fresh breaker.
+        let anomalies = [
+            (
+                BlockProgress {
+                    accounts_root: H256::random(),
+                    ..progress
+                },
+                15,
+            ),
+            (
+                BlockProgress {
+                    applied_transactions: 1,
+                    total_balance: 101,
+                    ..progress
+                },
+                15,
+            ),
+            (progress, 16),
+        ];
+        for (anomaly, finalized_height) in anomalies {
+            let breaker = CircuitBreaker::from_kinds(&kinds, 10);
+            breaker.observe(&anomaly, finalized_height).await;
+            assert_eq!(breaker.tripped().await.unwrap().block_height, 5);
+
+            // The breaker stays tripped once execution looks normal again.
+            breaker.observe(&progress, 15).await;
+            assert!(breaker.tripped().await.is_some());
+        }
+
+        // A breaker without checks never trips.
+        let breaker = CircuitBreaker::default();
+        breaker.observe(&progress, 1000).await;
+        assert_eq!(breaker.tripped().await, None);
+    }
+}
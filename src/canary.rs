@@ -0,0 +1,292 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Canary execution of new versions of the transaction execution logic.
+//!
+//! Before a node switches to a new version of the VM, the new version can be run as a canary: a
+//! second copy of the state, which executes the same blocks as the primary state but applies each
+//! transfer with the logic of a [`VmVersion`]. After each block the canary's state commitment and
+//! transaction results are compared with the primary's, and the first divergence is logged and
+//! reported by `rollup/stats/canary`. The canary never affects the primary state, the API or the
+//! proofs submitted to the L1.
+//!
+//! A new VM version is any implementation of [`VmVersion`], for example one compiled behind a
+//! cargo feature, given to [`Canary::new`]. The versions built into the node are listed in
+//! [`CanaryVm`].
+
+use crate::deposit::Deposit;
+use crate::error::RollupError;
+use crate::machine::RollupStateMachine;
+use crate::state::{reference, State};
+use crate::transaction::SignedTransaction;
+use async_std::sync::{Arc, Mutex};
+use clap::ValueEnum;
+use committable::{Commitment, Committable};
+use espresso_types::{Header, NsProof, SeqTypes};
+use ethers::types::H256;
+use hotshot_query_service::availability::BlockHash;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use strum_macros::Display;
+
+/// A version of the logic which applies a transfer to the rollup state.
+pub trait VmVersion: Debug + Send + Sync {
+    /// A name for the version, used in logs and reports.
+    fn name(&self) -> String;
+
+    /// Apply `transaction` to `state`, as [`State::apply_transaction`] does in the current
+    /// version.
+    fn apply_transaction(
+        &self,
+        state: &mut State,
+        transaction: &SignedTransaction,
+    ) -> Result<(), RollupError>;
+}
+
+/// The VM versions which can be run as a canary from the command line.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum CanaryVm {
+    /// The version run by the primary state, which should never diverge.
+    Current,
+    /// The reference implementation used by `--execution-self-check`.
+    Reference,
+}
+
+impl VmVersion for CanaryVm {
+    fn name(&self) -> String {
+        self.to_string()
+    }
+
+    fn apply_transaction(
+        &self,
+        state: &mut State,
+        transaction: &SignedTransaction,
+    ) -> Result<(), RollupError> {
+        match self {
+            Self::Current => state.apply_transaction(transaction),
+            Self::Reference => reference::apply_transaction(state, transaction),
+        }
+    }
+}
+
+/// The first block at which a canary diverged from the primary state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Divergence {
+    pub block_height: u64,
+    /// State commitment of the primary state after the block.
+    pub primary: Commitment<State>,
+    /// State commitment of the canary after the block.
+    pub canary: Commitment<State>,
+    /// Transactions in the block whose results differ between the primary state and the canary.
+    pub mismatched_transactions: Vec<H256>,
+}
+
+/// How a canary compares with the primary state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanaryReport {
+    /// Name of the VM version run by the canary.
+    pub vm: String,
+    /// Height of the first block executed by the canary, or `None` if it has not started.
+    pub started_at: Option<u64>,
+    /// Number of blocks whose results have been compared.
+    pub blocks_compared: u64,
+    /// The first divergence, after which the canary stops executing blocks.
+    pub divergence: Option<Divergence>,
+}
+
+#[derive(Debug)]
+struct CanaryInner {
+    state: Option<State>,
+    report: CanaryReport,
+}
+
+/// A copy of the state executing blocks with a different [`VmVersion`], shared with the API.
+#[derive(Clone, Debug)]
+pub struct Canary {
+    vm: Arc<dyn VmVersion>,
+    inner: Arc<Mutex<CanaryInner>>,
+}
+
+impl Canary {
+    pub fn new(vm: Arc<dyn VmVersion>) -> Self {
+        let report = CanaryReport {
+            vm: vm.name(),
+            started_at: None,
+            blocks_compared: 0,
+            divergence: None,
+        };
+        Self {
+            vm,
+            inner: Arc::new(Mutex::new(CanaryInner {
+                state: None,
+                report,
+            })),
+        }
+    }
+
+    /// Start the canary from a copy of `primary`, discarding any earlier comparison.
+    pub async fn start(&self, primary: &State) {
+        let mut inner = self.inner.lock().await;
+        inner.state = Some(primary.clone());
+        inner.report = CanaryReport {
+            vm: self.vm.name(),
+            started_at: None,
+            blocks_compared: 0,
+            divergence: None,
+        };
+        tracing::info!(
+            "Running VM version {} as a canary from block {}",
+            inner.report.vm,
+            primary.block_height()
+        );
+    }
+
+    /// Execute the block with `header` and compare the result with `primary`, the primary state
+    /// after executing the same block.
+    ///
+    /// `deposits` are the deposits which were staged in the primary state before the block, with
+    /// the L1 block through which they were collected, and are staged in the canary state in the
+    /// same way.
+    ///
+    /// Does nothing if the canary has not been started or has already diverged.
+    pub(crate) async fn execute(
+        &self,
+        header: &Header,
+        namespace_proof: &NsProof,
+        block_hash: BlockHash<SeqTypes>,
+        deposits: Option<&(u64, Vec<Deposit>)>,
+        primary: &State,
+    ) {
+        let mut inner = self.inner.lock().await;
+        let CanaryInner { state, report } = &mut *inner;
+        let Some(canary) = state else {
+            return;
+        };
+        if let Some((l1_block, deposits)) = deposits {
+            canary.stage_deposits(*l1_block, deposits.clone());
+        }
+        canary.execute_transactions_with(header, namespace_proof, block_hash, |state, txn| {
+            self.vm.apply_transaction(state, txn)
+        });
+        let block_height = header.height();
+        report.started_at.get_or_insert(block_height);
+        report.blocks_compared += 1;
+
+        let mismatched_transactions: Vec<_> = primary
+            .block_results()
+            .iter()
+            .zip(canary.block_results())
+            .filter(|(primary, canary)| primary != canary)
+            .map(|((hash, _), _)| *hash)
+            .collect();
+        let (primary, canary) = (primary.commit(), canary.commit());
+        if primary == canary && mismatched_transactions.is_empty() {
+            return;
+        }
+        tracing::error!(
+            "Canary VM version {} diverged at block {block_height}: primary state {primary}, \
+             canary state {canary}, {} mismatched transactions",
+            report.vm,
+            mismatched_transactions.len()
+        );
+        report.divergence = Some(Divergence {
+            block_height,
+            primary,
+            canary,
+            mismatched_transactions,
+        });
+        *state = None;
+    }
+
+    /// The comparison so far.
+    pub async fn report(&self) -> CanaryReport {
+        self.inner.lock().await.report.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mock_block;
+    use crate::transaction::Transaction;
+    use crate::RollupVM;
+    use espresso_types::NamespaceId;
+    use ethers::signers::{LocalWallet, Signer};
+    use ethers::types::Address;
+
+    /// A VM version which rejects every transfer.
+    #[derive(Debug)]
+    struct Frozen;
+
+    impl VmVersion for Frozen {
+        fn name(&self) -> String {
+            "frozen".into()
+        }
+
+        fn apply_transaction(
+            &self,
+            _state: &mut State,
+            transaction: &SignedTransaction,
+        ) -> Result<(), RollupError> {
+            Err(RollupError::InsufficientBalance {
+                address: transaction.recover()?,
+            })
+        }
+    }
+
+    #[async_std::test]
+    async fn test_canary_divergence() {
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let namespace: NamespaceId = vm.into();
+        let alice = LocalWallet::new(&mut rand::thread_rng());
+        let transfer = SignedTransaction::new(
+            Transaction {
+                amount: 10,
+                destination: Address::random(),
+                nonce: 1,
+                ..Default::default()
+            },
+            &alice,
+        )
+        .await;
+        let block = mock_block(namespace, &[(namespace, vec![transfer.encode()])]).await;
+        let namespace_proof = block.namespace_proof.as_ref().unwrap();
+        let block_hash = block.header.commit();
+
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let reference = Canary::new(Arc::new(CanaryVm::Reference));
+        let frozen = Canary::new(Arc::new(Frozen));
+        reference.start(&state).await;
+        frozen.start(&state).await;
+        state.execute_transactions(&block.header, namespace_proof, block_hash);
+        for canary in [&reference, &frozen] {
+            canary
+                .execute(&block.header, namespace_proof, block_hash, None, &state)
+                .await;
+        }
+
+        let report = reference.report().await;
+        assert_eq!(report.vm, "reference");
+        assert_eq!(report.started_at, Some(block.header.height()));
+        assert_eq!(report.blocks_compared, 1);
+        assert_eq!(report.divergence, None);
+
+        let report = frozen.report().await;
+        let divergence = report.divergence.unwrap();
+        assert_eq!(divergence.block_height, block.header.height());
+        assert_eq!(divergence.primary, state.commit());
+        assert_ne!(divergence.canary, divergence.primary);
+        assert_eq!(divergence.mismatched_transactions, [transfer.hash()]);
+
+        // A diverged canary stops executing blocks.
+        frozen
+            .execute(&block.header, namespace_proof, block_hash, None, &state)
+            .await;
+        assert_eq!(frozen.report().await.blocks_compared, 1);
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! The rollup chain ID.
+//!
+//! Nonces, recent transaction hashes and operator countersignatures all keep a transaction from
+//! executing twice on one rollup, but none of them keep a transaction signed for one rollup from
+//! being replayed on another: a redeployment with the same seed accounts, say, or a rollup built
+//! from this example in another namespace. A [`ChainId`] identifies one deployment of the rollup,
+//! so that signatures can be bound to it.
+//!
+//! The chain ID is the hash of everything which distinguishes a deployment: the namespace, the
+//! genesis state commitment, the L1 chain ID and the address of the rollup contract. It is known
+//! once the rollup contract is deployed, and is served by `rollup/info`. A transaction signed for a
+//! chain carries its ID and is signed as EIP-712 typed data in the chain's
+//! [domain](ChainId::eip712_domain), so wallets can show which rollup they are signing for.
+
+use committable::{Commitment, Committable};
+use espresso_types::NamespaceId;
+use ethers::{
+    abi::{self, Token},
+    types::{transaction::eip712::EIP712Domain, Address, H256, U256},
+    utils::keccak256,
+};
+use sequencer_utils::commitment_to_u256;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// Name of the rollup in the EIP-712 domain of signed transactions.
+pub const EIP712_NAME: &str = "Espresso Example Rollup";
+
+/// Version of the transaction format in the EIP-712 domain of signed transactions.
+pub const EIP712_VERSION: &str = "1";
+
+/// Identifier of one deployment of the rollup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ChainId(pub H256);
+
+impl ChainId {
+    /// The chain ID of the rollup in `namespace`, starting from the state with commitment
+    /// `genesis`, whose contract is deployed at `rollup_contract` on the L1 with chain ID
+    /// `l1_chain_id`.
+    ///
+    /// The genesis commitment may be that of any state model, so the auditor can derive the chain
+    /// ID of the node it audits.
+    pub fn derive<S: Committable>(
+        namespace: NamespaceId,
+        genesis: Commitment<S>,
+        l1_chain_id: u64,
+        rollup_contract: Address,
+    ) -> Self {
+        let encoded = abi::encode(&[
+            Token::Uint(u64::from(namespace).into()),
+            Token::Uint(commitment_to_u256(genesis)),
+            Token::Uint(l1_chain_id.into()),
+            Token::Address(rollup_contract),
+        ]);
+        Self(H256(keccak256(encoded)))
+    }
+
+    /// The EIP-712 domain of transactions signed for this chain.
+    ///
+    /// The chain ID takes the place of the `chainId` of the domain, which is usually the chain ID
+    /// of an EVM network.
+    pub fn eip712_domain(&self) -> EIP712Domain {
+        EIP712Domain {
+            name: Some(EIP712_NAME.into()),
+            version: Some(EIP712_VERSION.into()),
+            chain_id: Some(U256::from_big_endian(self.0.as_bytes())),
+            verifying_contract: None,
+            salt: None,
+        }
+    }
+}
+
+impl Display for ChainId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+    use crate::RollupVM;
+
+    #[test]
+    fn test_derive_chain_id() {
+        let namespace = NamespaceId::from(1_u64);
+        let vm = RollupVM::new(namespace);
+        let genesis = State::from_initial_balances([(Address::random(), 100)], vm).commit();
+        let contract = Address::random();
+        let chain_id = ChainId::derive(namespace, genesis, 1337, contract);
+
+        // The derivation is deterministic, and changing any input changes the chain ID.
+        assert_eq!(
+            chain_id,
+            ChainId::derive(namespace, genesis, 1337, contract)
+        );
+        for other in [
+            ChainId::derive(NamespaceId::from(2_u64), genesis, 1337, contract),
+            ChainId::derive(
+                namespace,
+                State::from_initial_balances([], vm).commit(),
+                1337,
+                contract,
+            ),
+            ChainId::derive(namespace, genesis, 1, contract),
+            ChainId::derive(namespace, genesis, 1337, Address::random()),
+        ] {
+            assert_ne!(chain_id, other);
+        }
+
+        // The chain ID serializes as a hex string, as it is displayed.
+        assert_eq!(
+            serde_json::to_value(chain_id).unwrap(),
+            serde_json::Value::String(chain_id.to_string())
+        );
+    }
+}
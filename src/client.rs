@@ -0,0 +1,187 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Client for users of the rollup.
+//!
+//! Transactions are normally submitted through the rollup API, which checks them and, if an
+//! operator is configured, countersigns them before forwarding them to the sequencer. The rollup
+//! namespace is open to anyone, though, so if the rollup API is down a user can still post a
+//! transaction straight to the sequencer with [`RollupClient::submit_via_sequencer`]. The executor
+//! reads such payloads from the namespace like any other: without an operator they are executed as
+//! usual, and with one they are untrusted submissions, executed subject to the
+//! [`SubmissionPolicy`](crate::state::SubmissionPolicy) fixed at genesis.
+
+use crate::api::RollupInfo;
+use crate::state::{Amount, Nonce, ReplayProtection, Supply};
+use crate::transaction::{SignedTransaction, TransactionBuilder};
+use crate::SequencerApiVersion;
+use committable::{Commitment, Committable};
+use espresso_types::{NamespaceId, Transaction};
+use ethers::types::Address;
+use std::time::Duration;
+use surf_disco::{error::ClientError, Client, Url};
+use tide_disco::{Error as _, StatusCode};
+
+type ApiClient = Client<ClientError, SequencerApiVersion>;
+
+/// Encode `transaction` as a sequencer transaction in the rollup `namespace`.
+///
+/// The payload is a bare signed transaction, without an operator countersignature.
+pub fn sequencer_transaction(
+    namespace: NamespaceId,
+    transaction: &SignedTransaction,
+) -> Transaction {
+    Transaction::new(namespace, transaction.encode())
+}
+
+/// Client of the rollup API, with a fallback to the sequencer for submissions.
+#[derive(Clone, Debug)]
+pub struct RollupClient {
+    api: ApiClient,
+    sequencer: Option<ApiClient>,
+    namespace: NamespaceId,
+}
+
+impl RollupClient {
+    /// A client of the rollup API at `rollup_url`, for the rollup in namespace 1.
+    pub fn new(rollup_url: Url) -> Self {
+        Self {
+            api: Client::new(rollup_url),
+            sequencer: None,
+            namespace: NamespaceId::from(1_u64),
+        }
+    }
+
+    /// Allow submitting directly to the sequencer API at `sequencer_url`.
+    pub fn with_sequencer(mut self, sequencer_url: Url) -> Self {
+        self.sequencer = Some(Client::new(sequencer_url));
+        self
+    }
+
+    /// Submit to the rollup in `namespace` instead of namespace 1.
+    pub fn with_namespace(mut self, namespace: NamespaceId) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    /// Wait for the rollup API to become available, returning whether it did within `timeout`.
+    pub async fn connect(&self, timeout: Option<Duration>) -> bool {
+        self.api.connect(timeout).await
+    }
+
+    /// Static information about the rollup, including the chain to sign transactions for.
+    pub async fn info(&self) -> Result<RollupInfo, ClientError> {
+        self.api.get("rollup/info").send().await
+    }
+
+    pub async fn supply(&self) -> Result<Supply, ClientError> {
+        self.api.get("rollup/supply").send().await
+    }
+
+    pub async fn balance(&self, address: Address) -> Result<Amount, ClientError> {
+        self.api
+            .get(&format!("rollup/balance/{address:?}"))
+            .send()
+            .await
+    }
+
+    pub async fn nonce(&self, address: Address) -> Result<Nonce, ClientError> {
+        self.api
+            .get(&format!("rollup/nonce/{address:?}"))
+            .send()
+            .await
+    }
+
+    /// Fill in the fields of `builder` which depend on the rollup: the next nonce of `sender`, if
+    /// no nonce is set, the chain to sign for, if the rollup is bound to one, and, if the rollup uses
+    /// `recent-hashes` replay protection, an expiry at the end of the replay window.
+    pub async fn prepare(
+        &self,
+        mut builder: TransactionBuilder,
+        sender: Address,
+    ) -> Result<TransactionBuilder, ClientError> {
+        if builder.get_nonce().is_none() {
+            builder = builder.nonce(self.nonce(sender).await? + 1);
+        }
+        if builder.get_chain_id().is_none() || builder.get_expires_at().is_none() {
+            let info = self.info().await?;
+            if let (None, Some(chain_id)) = (builder.get_chain_id(), info.chain_id) {
+                builder = builder.chain_id(chain_id);
+            }
+            if builder.get_expires_at().is_none()
+                && info.replay_protection == ReplayProtection::RecentHashes
+            {
+                builder = builder.expires_at(info.block_height + info.replay_window);
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Submit `transaction` through the rollup API.
+    pub async fn submit(&self, transaction: &SignedTransaction) -> Result<(), ClientError> {
+        self.api
+            .post("rollup/submit")
+            .body_json(transaction)?
+            .send()
+            .await
+    }
+
+    /// Submit `transaction` directly to the sequencer, bypassing the rollup API.
+    ///
+    /// Returns the hash of the sequencer transaction. Fails if no sequencer URL was configured with
+    /// [`with_sequencer`](Self::with_sequencer).
+    pub async fn submit_via_sequencer(
+        &self,
+        transaction: &SignedTransaction,
+    ) -> Result<Commitment<Transaction>, ClientError> {
+        let Some(sequencer) = &self.sequencer else {
+            return Err(ClientError::catch_all(
+                StatusCode::BAD_REQUEST,
+                "no sequencer URL configured".into(),
+            ));
+        };
+        let txn = sequencer_transaction(self.namespace, transaction);
+        sequencer
+            .post::<()>("submit/submit")
+            .body_json(&txn)?
+            .send()
+            .await?;
+        Ok(txn.commit())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{Submission, Transaction as RollupTransaction};
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[async_std::test]
+    async fn test_sequencer_transaction() {
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let transaction = SignedTransaction::new(
+            RollupTransaction {
+                amount: 10,
+                destination: Address::random(),
+                nonce: 1,
+                ..Default::default()
+            },
+            &wallet,
+        )
+        .await;
+        let namespace = NamespaceId::from(1_u64);
+        let txn = sequencer_transaction(namespace, &transaction);
+        assert_eq!(txn.namespace(), namespace);
+
+        // The executor decodes the payload as a submission without a countersignature.
+        match Submission::decode(txn.payload()).unwrap() {
+            Submission::Unwrapped(decoded) => {
+                assert_eq!(decoded.recover().unwrap(), wallet.address())
+            }
+            Submission::Wrapped(_) => panic!("payload decoded as a countersigned transaction"),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+use futures::future::{BoxFuture, FutureExt};
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+/// Source of time for the executor's timers.
+///
+/// Retry delays and other waits in the executor go through a `Clock` rather than calling
+/// `sleep` directly, so that tests can substitute a [`VirtualClock`] and control the passage of
+/// time deterministically.
+pub trait Clock: Debug + Send + Sync {
+    /// The current time according to this clock.
+    fn now(&self) -> Instant;
+
+    /// Wait until `duration` has elapsed according to this clock.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// A [`Clock`] backed by the system's monotonic clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        async_std::task::sleep(duration).boxed()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+pub use virtual_time::VirtualClock;
+
+#[cfg(any(test, feature = "testing"))]
+mod virtual_time {
+    use super::*;
+    use futures::channel::oneshot;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug)]
+    struct Inner {
+        elapsed: Duration,
+        sleepers: Vec<(Duration, oneshot::Sender<()>)>,
+    }
+
+    /// A [`Clock`] which only advances when explicitly told to.
+    ///
+    /// Sleeping on a virtual clock never blocks on real time. Instead, pending sleeps complete as
+    /// soon as [`advance`](Self::advance) moves the clock past their deadline.
+    #[derive(Clone, Debug)]
+    pub struct VirtualClock {
+        start: Instant,
+        inner: Arc<Mutex<Inner>>,
+    }
+
+    impl Default for VirtualClock {
+        fn default() -> Self {
+            Self {
+                start: Instant::now(),
+                inner: Arc::new(Mutex::new(Inner {
+                    elapsed: Duration::ZERO,
+                    sleepers: vec![],
+                })),
+            }
+        }
+    }
+
+    impl VirtualClock {
+        /// Move the clock forward by `duration`, waking any sleeps whose deadline has passed.
+        pub fn advance(&self, duration: Duration) {
+            let mut inner = self.inner.lock().unwrap();
+            inner.elapsed += duration;
+            let now = inner.elapsed;
+            let (expired, pending) = std::mem::take(&mut inner.sleepers)
+                .into_iter()
+                .partition(|(deadline, _)| *deadline <= now);
+            inner.sleepers = pending;
+            for (_, waker) in expired {
+                waker.send(()).ok();
+            }
+        }
+
+        /// Total virtual time elapsed since the clock was created.
+        pub fn elapsed(&self) -> Duration {
+            self.inner.lock().unwrap().elapsed
+        }
+
+        /// The number of sleeps which are waiting for the clock to advance.
+        pub fn num_sleepers(&self) -> usize {
+            self.inner.lock().unwrap().sleepers.len()
+        }
+    }
+
+    impl Clock for VirtualClock {
+        fn now(&self) -> Instant {
+            self.start + self.elapsed()
+        }
+
+        fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+            let mut inner = self.inner.lock().unwrap();
+            if duration.is_zero() {
+                return async {}.boxed();
+            }
+            let (sender, receiver) = oneshot::channel();
+            let deadline = inner.elapsed + duration;
+            inner.sleepers.push((deadline, sender));
+            receiver.map(|_| ()).boxed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_virtual_clock_sleep() {
+        let clock = VirtualClock::default();
+        let start = clock.now();
+        let mut short = clock.sleep(Duration::from_secs(1));
+        let mut long = clock.sleep(Duration::from_secs(10));
+        assert_eq!(clock.num_sleepers(), 2);
+        assert!((&mut short).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(5));
+        assert!((&mut short).now_or_never().is_some());
+        assert!((&mut long).now_or_never().is_none());
+        assert_eq!(clock.num_sleepers(), 1);
+
+        clock.advance(Duration::from_secs(5));
+        long.await;
+        assert_eq!(clock.now() - start, Duration::from_secs(10));
+    }
+}
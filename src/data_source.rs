@@ -0,0 +1,287 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+use crate::http::{HttpClientPool, PooledClient};
+use espresso_types::{Header, NamespaceId, NsProof, SeqTypes, Transaction};
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{BoxStream, StreamExt};
+use hotshot_query_service::availability::{
+    BlockHash, LeafQueryData, PayloadQueryData, VidCommonQueryData,
+};
+use hotshot_query_service::VidCommon;
+use hotshot_types::traits::node_implementation::ConsensusTime;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use surf_disco::Url;
+
+/// The transactions of a namespace in a block with their proof, as served by the query service at
+/// `availability/block/:height/namespace/:namespace`.
+///
+/// This mirrors the sequencer's own response type, so that the rollup API does not depend on the
+/// sequencer crate.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NamespaceProofQueryData {
+    pub proof: Option<NsProof>,
+    pub transactions: Vec<Transaction>,
+}
+
+/// Source of the HotShot data the executor needs to execute blocks.
+///
+/// The executor reads headers, namespace proofs and VID data through a `SequencerDataSource`
+/// rather than a query service client, so that tests can substitute a [`MockDataSource`] and run
+/// the executor without a live sequencer.
+pub trait SequencerDataSource: Debug + Send + Sync {
+    /// Stream headers of decided blocks, starting at height `from`.
+    ///
+    /// The stream is meant for following new blocks as they are decided. Historical headers are
+    /// better fetched with [`headers`](Self::headers), through a
+    /// [`HeaderFetcher`](crate::backfill::HeaderFetcher).
+    fn subscribe_headers(&self, from: u64) -> BoxFuture<'_, BoxStream<'static, Header>>;
+
+    /// Headers of the decided blocks with heights in `from..until`, in order.
+    ///
+    /// Returns `None` if the headers are unavailable. The query service limits the number of
+    /// headers returned by a single query, so large ranges must be fetched a page at a time.
+    fn headers(&self, from: u64, until: u64) -> BoxFuture<'_, Option<Vec<Header>>>;
+
+    /// Number of decided blocks, or `None` if it is unavailable.
+    fn block_height(&self) -> BoxFuture<'_, Option<u64>>;
+
+    /// Proof of the transactions in `namespace` in the block at `height`.
+    ///
+    /// Returns `None` if the block does not contain the namespace or the proof is unavailable.
+    fn namespace_proof(
+        &self,
+        height: u64,
+        namespace: NamespaceId,
+    ) -> BoxFuture<'_, Option<NsProof>>;
+
+    /// VID common data for the block at `height`.
+    ///
+    /// Returns `None` if the data is unavailable.
+    fn vid_common(&self, height: u64) -> BoxFuture<'_, Option<VidCommon>>;
+
+    /// Hash of the block at `height`.
+    fn block_hash(&self, height: u64) -> BoxFuture<'_, BlockHash<SeqTypes>>;
+
+    /// The HotShot view in which the block at `height` was decided.
+    ///
+    /// Returns `None` if the leaf is unavailable.
+    fn view_number(&self, height: u64) -> BoxFuture<'_, Option<u64>>;
+}
+
+/// A [`SequencerDataSource`] backed by the HotShot query service.
+#[derive(Debug)]
+pub struct QueryServiceDataSource {
+    client: PooledClient,
+    node: PooledClient,
+}
+
+impl QueryServiceDataSource {
+    /// Connect to the availability and node APIs of the query service at `sequencer_url`, using
+    /// clients from `http`.
+    pub async fn connect(sequencer_url: &Url, http: &HttpClientPool) -> Self {
+        let client = http
+            .client(&sequencer_url.join("availability").unwrap())
+            .await;
+        client.inner().connect(None).await;
+        let node = http.client(&sequencer_url.join("node").unwrap()).await;
+        Self { client, node }
+    }
+}
+
+impl SequencerDataSource for QueryServiceDataSource {
+    fn subscribe_headers(&self, from: u64) -> BoxFuture<'_, BoxStream<'static, Header>> {
+        async move {
+            self.client
+                .inner()
+                .socket(&format!("stream/headers/{from}"))
+                .subscribe::<Header>()
+                .await
+                .expect("Unable to subscribe to HotShot block header stream")
+                .map(|result| result.expect("Error fetching block header"))
+                .boxed()
+        }
+        .boxed()
+    }
+
+    fn headers(&self, from: u64, until: u64) -> BoxFuture<'_, Option<Vec<Header>>> {
+        async move {
+            self.client
+                .get::<Vec<Header>>(&format!("header/{from}/{until}"))
+                .await
+                .ok()
+        }
+        .boxed()
+    }
+
+    fn block_height(&self) -> BoxFuture<'_, Option<u64>> {
+        async move { self.node.get::<u64>("block-height").await.ok() }.boxed()
+    }
+
+    fn namespace_proof(
+        &self,
+        height: u64,
+        namespace: NamespaceId,
+    ) -> BoxFuture<'_, Option<NsProof>> {
+        async move {
+            self.client
+                .get::<NamespaceProofQueryData>(&format!("block/{height}/namespace/{namespace}"))
+                .await
+                .ok()
+                .and_then(|res| res.proof)
+        }
+        .boxed()
+    }
+
+    fn vid_common(&self, height: u64) -> BoxFuture<'_, Option<VidCommon>> {
+        async move {
+            self.client
+                .get::<VidCommonQueryData<SeqTypes>>(&format!("vid/common/{height}"))
+                .await
+                .ok()
+                .map(|res| res.common().clone())
+        }
+        .boxed()
+    }
+
+    fn block_hash(&self, height: u64) -> BoxFuture<'_, BlockHash<SeqTypes>> {
+        async move {
+            self.client
+                .get::<PayloadQueryData<SeqTypes>>(&format!("payload/{height}"))
+                .await
+                .unwrap()
+                .block_hash()
+        }
+        .boxed()
+    }
+
+    fn view_number(&self, height: u64) -> BoxFuture<'_, Option<u64>> {
+        async move {
+            self.client
+                .get::<LeafQueryData<SeqTypes>>(&format!("leaf/{height}"))
+                .await
+                .ok()
+                .map(|res| res.leaf().view_number().u64())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+pub use mock::{MockBlock, MockDataSource};
+
+#[cfg(any(test, feature = "testing"))]
+mod mock {
+    use super::*;
+    use committable::Committable;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// A block served by a [`MockDataSource`].
+    #[derive(Clone, Debug)]
+    pub struct MockBlock {
+        pub header: Header,
+        /// Proof for the rollup namespace, or `None` if the block does not contain it.
+        pub namespace_proof: Option<NsProof>,
+        /// VID common data, or `None` if it is unavailable.
+        pub vid_common: Option<VidCommon>,
+        /// View in which the block was decided, or `None` if the leaf is unavailable.
+        pub view_number: Option<u64>,
+    }
+
+    /// An in-memory [`SequencerDataSource`] serving a fixed sequence of blocks.
+    ///
+    /// Blocks are served in the order they are pushed, and the block at index `i` is treated as
+    /// the block at height `i`. The header stream ends after the last block.
+    #[derive(Clone, Debug, Default)]
+    pub struct MockDataSource {
+        blocks: Arc<Mutex<Vec<MockBlock>>>,
+        /// Proofs for namespaces other than the one each block was built for.
+        namespace_proofs: Arc<Mutex<HashMap<(u64, NamespaceId), NsProof>>>,
+    }
+
+    impl MockDataSource {
+        pub fn push(&self, block: MockBlock) {
+            self.blocks.lock().unwrap().push(block);
+        }
+
+        /// Serve `proof` for `namespace` in the block at `height`, in addition to the proof of the
+        /// namespace the block was built for.
+        pub fn push_namespace_proof(&self, height: u64, namespace: NamespaceId, proof: NsProof) {
+            self.namespace_proofs
+                .lock()
+                .unwrap()
+                .insert((height, namespace), proof);
+        }
+
+        fn block(&self, height: u64) -> MockBlock {
+            self.blocks
+                .lock()
+                .unwrap()
+                .get(height as usize)
+                .cloned()
+                .unwrap_or_else(|| panic!("mock block {height} does not exist"))
+        }
+    }
+
+    impl SequencerDataSource for MockDataSource {
+        fn subscribe_headers(&self, from: u64) -> BoxFuture<'_, BoxStream<'static, Header>> {
+            let headers: Vec<_> = self
+                .blocks
+                .lock()
+                .unwrap()
+                .iter()
+                .skip(from as usize)
+                .map(|block| block.header.clone())
+                .collect();
+            async move { futures::stream::iter(headers).boxed() }.boxed()
+        }
+
+        fn headers(&self, from: u64, until: u64) -> BoxFuture<'_, Option<Vec<Header>>> {
+            let blocks = self.blocks.lock().unwrap();
+            let headers = blocks
+                .get(from as usize..until as usize)
+                .map(|blocks| blocks.iter().map(|block| block.header.clone()).collect());
+            async move { headers }.boxed()
+        }
+
+        fn block_height(&self) -> BoxFuture<'_, Option<u64>> {
+            let height = self.blocks.lock().unwrap().len() as u64;
+            async move { Some(height) }.boxed()
+        }
+
+        fn namespace_proof(
+            &self,
+            height: u64,
+            namespace: NamespaceId,
+        ) -> BoxFuture<'_, Option<NsProof>> {
+            let proof = self
+                .namespace_proofs
+                .lock()
+                .unwrap()
+                .get(&(height, namespace))
+                .cloned()
+                .or_else(|| self.block(height).namespace_proof);
+            async move { proof }.boxed()
+        }
+
+        fn vid_common(&self, height: u64) -> BoxFuture<'_, Option<VidCommon>> {
+            let common = self.block(height).vid_common;
+            async move { common }.boxed()
+        }
+
+        fn block_hash(&self, height: u64) -> BoxFuture<'_, BlockHash<SeqTypes>> {
+            let hash = self.block(height).header.commit();
+            async move { hash }.boxed()
+        }
+
+        fn view_number(&self, height: u64) -> BoxFuture<'_, Option<u64>> {
+            let view = self.block(height).view_number;
+            async move { view }.boxed()
+        }
+    }
+}
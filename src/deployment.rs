@@ -0,0 +1,309 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Records of rollup contract deployments.
+//!
+//! A deployment record is written when the rollup contract is deployed, and reused on subsequent
+//! runs instead of deploying a new contract, so that restarting a node does not create duplicate
+//! deployments.
+//!
+//! Before reusing a deployment, the node also checks that the state recorded by the contract is
+//! consistent with its own genesis state and the batches it has confirmed, so that a node whose
+//! configuration has drifted from the deployed contract refuses to start rather than submitting
+//! divergent proofs later.
+
+use crate::l1::{L1Client, L1Error};
+use crate::outbox::OutboxEntry;
+use crate::state::State;
+use committable::Commitment;
+use ethers::types::U256;
+use ethers::types::{Address, H256};
+use sequencer_utils::commitment_to_u256;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Snafu)]
+pub enum DeploymentError {
+    #[snafu(display("Error accessing deployment file {}: {source}", path.display()))]
+    Io { path: PathBuf, source: io::Error },
+    #[snafu(display("Malformed deployment file {}: {source}", path.display()))]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[snafu(display(
+        "Deployment record does not match this node's configuration: {field} is {recorded} in the \
+         record but {configured} for this node. Remove the deployment file to deploy a new contract."
+    ))]
+    Mismatch {
+        field: &'static str,
+        recorded: String,
+        configured: String,
+    },
+    #[snafu(display("Error reading rollup contract state: {message}"))]
+    Contract { message: String },
+    #[snafu(display(
+        "Rollup contract state is inconsistent with this node: after {num_verified_blocks} verified \
+         blocks the contract records state {contract:#x}, but this node expects {expected:#x}."
+    ))]
+    Diverged {
+        num_verified_blocks: u64,
+        contract: U256,
+        expected: U256,
+    },
+}
+
+/// A deployment of the rollup contract.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeploymentRecord {
+    pub rollup_address: Address,
+    pub chain_id: u64,
+    pub light_client_address: Address,
+    /// The state commitment the contract was initialized with.
+    pub genesis_commitment: Commitment<State>,
+    pub deployer: Address,
+    pub tx_hash: H256,
+}
+
+impl DeploymentRecord {
+    /// Load the record at `path`, or `None` if there is no file at `path`.
+    pub fn load(path: &Path) -> Result<Option<Self>, DeploymentError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(DeploymentError::Io {
+                    path: path.into(),
+                    source,
+                })
+            }
+        };
+        serde_json::from_str(&contents)
+            .map(Some)
+            .context(JsonSnafu { path })
+    }
+
+    /// Write the record to `path`.
+    ///
+    /// The record is written to a temporary file which is then renamed over `path`, so that a
+    /// crash never leaves a partially written record behind.
+    pub fn save(&self, path: &Path) -> Result<(), DeploymentError> {
+        let contents = serde_json::to_string_pretty(self).context(JsonSnafu { path })?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, contents).context(IoSnafu { path: &tmp })?;
+        std::fs::rename(&tmp, path).context(IoSnafu { path })
+    }
+
+    /// Check that this deployment can be reused by a node with the given configuration.
+    pub fn check(
+        &self,
+        chain_id: u64,
+        light_client_address: Address,
+        genesis_commitment: Commitment<State>,
+    ) -> Result<(), DeploymentError> {
+        fn check_field<T: PartialEq + std::fmt::Debug>(
+            field: &'static str,
+            recorded: T,
+            configured: T,
+        ) -> Result<(), DeploymentError> {
+            if recorded == configured {
+                Ok(())
+            } else {
+                Err(DeploymentError::Mismatch {
+                    field,
+                    recorded: format!("{recorded:?}"),
+                    configured: format!("{configured:?}"),
+                })
+            }
+        }
+        check_field("chain ID", self.chain_id, chain_id)?;
+        check_field(
+            "light client address",
+            self.light_client_address,
+            light_client_address,
+        )?;
+        check_field(
+            "genesis commitment",
+            self.genesis_commitment,
+            genesis_commitment,
+        )
+    }
+}
+
+/// The state recorded by a deployed rollup contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContractState {
+    pub state_commitment: U256,
+    pub num_verified_blocks: u64,
+}
+
+impl ContractState {
+    /// Read the state of the rollup contract connected to `rollup`.
+    pub async fn fetch(rollup: &dyn L1Client) -> Result<Self, DeploymentError> {
+        let contract_error = |err: L1Error| DeploymentError::Contract {
+            message: err.to_string(),
+        };
+        let state_commitment = rollup.state_commitment().await.map_err(contract_error)?;
+        let num_verified_blocks = rollup.num_verified_blocks().await.map_err(contract_error)?;
+        Ok(Self {
+            state_commitment: U256::from_big_endian(&state_commitment),
+            num_verified_blocks,
+        })
+    }
+
+    /// Check that this state is consistent with a node starting from `genesis_commitment` which
+    /// has recorded `entries` in its outbox.
+    ///
+    /// A contract with no verified blocks must still hold the genesis commitment. Otherwise, if the
+    /// node has confirmed a batch ending at the contract's latest verified block, the contract must
+    /// hold the state that batch was proven to produce. A contract which has verified fewer blocks
+    /// than the node has confirmed is also inconsistent. Progress beyond the node's confirmed
+    /// batches, such as batches submitted by another node, cannot be checked until it is
+    /// re-executed.
+    pub fn check(
+        &self,
+        genesis_commitment: Commitment<State>,
+        entries: &[OutboxEntry],
+    ) -> Result<(), DeploymentError> {
+        let diverged = |expected| DeploymentError::Diverged {
+            num_verified_blocks: self.num_verified_blocks,
+            contract: self.state_commitment,
+            expected,
+        };
+        let latest_confirmed = entries
+            .iter()
+            .rev()
+            .find(|entry| entry.status.is_verified());
+        let expected = match latest_confirmed {
+            None if self.num_verified_blocks == 0 => commitment_to_u256(genesis_commitment),
+            None => return Ok(()),
+            Some(entry) => {
+                let new_state = U256::from_big_endian(&entry.proof.new_state);
+                match self.num_verified_blocks.cmp(&(entry.last_block + 1)) {
+                    std::cmp::Ordering::Greater => return Ok(()),
+                    std::cmp::Ordering::Equal => new_state,
+                    std::cmp::Ordering::Less => return Err(diverged(new_state)),
+                }
+            }
+        };
+        if self.state_commitment != expected {
+            return Err(diverged(expected));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ProofShape;
+    use crate::l1::BatchProofInput;
+    use crate::outbox::SubmissionStatus;
+    use crate::RollupVM;
+    use committable::Committable;
+    use espresso_types::NamespaceId;
+
+    #[test]
+    fn test_deployment_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deployment.json");
+        assert!(DeploymentRecord::load(&path).unwrap().is_none());
+
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let genesis = State::from_initial_balances([(Address::random(), 100)], vm).commit();
+        let record = DeploymentRecord {
+            rollup_address: Address::random(),
+            chain_id: 1337,
+            light_client_address: Address::random(),
+            genesis_commitment: genesis,
+            deployer: Address::random(),
+            tx_hash: H256::random(),
+        };
+        record.save(&path).unwrap();
+        assert_eq!(DeploymentRecord::load(&path).unwrap(), Some(record.clone()));
+
+        record
+            .check(1337, record.light_client_address, genesis)
+            .unwrap();
+        let other_genesis = State::from_initial_balances([], vm).commit();
+        assert!(matches!(
+            record.check(1337, record.light_client_address, other_genesis),
+            Err(DeploymentError::Mismatch {
+                field: "genesis commitment",
+                ..
+            })
+        ));
+        assert!(matches!(
+            record.check(1, record.light_client_address, genesis),
+            Err(DeploymentError::Mismatch {
+                field: "chain ID",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_contract_state_check() {
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let genesis = State::from_initial_balances([(Address::random(), 100)], vm).commit();
+        let other = State::from_initial_balances([], vm).commit();
+
+        // A fresh contract must hold the genesis state.
+        let fresh = ContractState {
+            state_commitment: commitment_to_u256(genesis),
+            num_verified_blocks: 0,
+        };
+        fresh.check(genesis, &[]).unwrap();
+        assert!(matches!(
+            fresh.check(other, &[]),
+            Err(DeploymentError::Diverged { .. })
+        ));
+
+        // After a confirmed batch, the contract must hold the state it produced.
+        let mut proof = BatchProofInput::default();
+        commitment_to_u256(other).to_big_endian(&mut proof.new_state);
+        let entry = OutboxEntry {
+            first_block: 0,
+            last_block: 4,
+            count: 5,
+            proof,
+            shape: ProofShape::Endpoints,
+            status: SubmissionStatus::Confirmed {
+                tx_hash: H256::random(),
+            },
+            enqueued_at: 0,
+            attempts: 1,
+        };
+        let verified = ContractState {
+            state_commitment: commitment_to_u256(other),
+            num_verified_blocks: 5,
+        };
+        verified.check(genesis, &[entry.clone()]).unwrap();
+        assert!(matches!(
+            ContractState {
+                state_commitment: commitment_to_u256(genesis),
+                ..verified
+            }
+            .check(genesis, &[entry.clone()]),
+            Err(DeploymentError::Diverged { .. })
+        ));
+
+        // A contract behind the node's confirmed batches is inconsistent, but one ahead of them
+        // cannot be checked.
+        assert!(matches!(
+            fresh.check(genesis, &[entry.clone()]),
+            Err(DeploymentError::Diverged { .. })
+        ));
+        ContractState {
+            num_verified_blocks: 10,
+            ..fresh
+        }
+        .check(genesis, &[entry])
+        .unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Deposits from the L1 onto the rollup.
+//!
+//! Ether sent to the `DepositEscrow` contract is held in escrow and announced with a
+//! `Deposited(address account, uint256 amount)` event. The executor follows these events in the
+//! background, collecting them in a [`DepositQueue`] as the L1 blocks containing them are
+//! finalized.
+//!
+//! Deposits are credited at a boundary every node agrees on: each HotShot header records the
+//! latest finalized L1 block, and before executing a rollup block the executor stages every
+//! deposit from an L1 block after the one last credited, up to and including the header's. The
+//! staged deposits are minted, one token per wei, at the start of the block, before its
+//! transactions, so they are reflected in the state commitment after the block. The executor waits
+//! for the queue to catch up with the header's L1 block first, so that a deposit is never missed
+//! because the listener is behind.
+
+use crate::state::Amount;
+use async_std::sync::{Arc, RwLock};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(feature = "executor")]
+use {
+    crate::clock::Clock,
+    contract_bindings::deposit_escrow::{DepositEscrow, DepositedFilter},
+    ethers::{
+        contract::LogMeta,
+        providers::{Middleware, Provider, Ws},
+        types::BlockNumber,
+    },
+    futures::StreamExt,
+    std::time::Duration,
+    surf_disco::Url,
+};
+
+/// Tokens deposited to `account` by a `Deposited` event on the L1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deposit {
+    pub account: Address,
+    pub amount: Amount,
+    /// The L1 block containing the event.
+    pub l1_block: u64,
+    /// Position of the event among the logs of its L1 block.
+    pub log_index: u64,
+}
+
+impl Deposit {
+    /// A deposit of `amount` wei, or `None` if `amount` does not fit in a rollup balance.
+    ///
+    /// The escrow contract rejects such deposits, so this only guards against a misbehaving
+    /// contract. Every node skips the same events, so execution remains deterministic.
+    pub fn new(account: Address, amount: U256, l1_block: u64, log_index: u64) -> Option<Self> {
+        if amount > U256::from(Amount::MAX) {
+            return None;
+        }
+        Some(Self {
+            account,
+            amount: amount.as_u64(),
+            l1_block,
+            log_index,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct DepositLog {
+    // Deposits not yet credited, by position on the L1.
+    deposits: BTreeMap<(u64, u64), Deposit>,
+    // The last finalized L1 block whose deposits have all been collected.
+    synced_through: Option<u64>,
+}
+
+/// Deposits collected from the L1, waiting to be credited.
+#[derive(Clone, Debug, Default)]
+pub struct DepositQueue {
+    log: Arc<RwLock<DepositLog>>,
+}
+
+impl DepositQueue {
+    /// A queue for a state which has credited the deposits up to and including `l1_block`, from
+    /// which collection starts.
+    pub fn new(l1_block: u64) -> Self {
+        Self {
+            log: Arc::new(RwLock::new(DepositLog {
+                deposits: BTreeMap::new(),
+                synced_through: Some(l1_block),
+            })),
+        }
+    }
+
+    /// Add a deposit observed on the L1. A deposit which is already queued is ignored.
+    pub async fn insert(&self, deposit: Deposit) {
+        self.log
+            .write()
+            .await
+            .deposits
+            .insert((deposit.l1_block, deposit.log_index), deposit);
+    }
+
+    /// Record that every deposit up to and including `l1_block` has been collected.
+    pub async fn set_synced_through(&self, l1_block: u64) {
+        let mut log = self.log.write().await;
+        log.synced_through = log.synced_through.max(Some(l1_block));
+    }
+
+    /// The last L1 block whose deposits have all been collected, if any.
+    pub async fn synced_through(&self) -> Option<u64> {
+        self.log.read().await.synced_through
+    }
+
+    /// Remove the deposits from L1 blocks up to and including `through`, returning those from
+    /// blocks after `after`, in the order they occurred on the L1.
+    ///
+    /// Deposits from blocks up to `after` have already been credited, and are discarded.
+    pub async fn take(&self, after: u64, through: u64) -> Vec<Deposit> {
+        let mut log = self.log.write().await;
+        let later = log.deposits.split_off(&(through + 1, 0));
+        let taken = std::mem::replace(&mut log.deposits, later);
+        taken
+            .into_values()
+            .filter(|deposit| deposit.l1_block > after)
+            .collect()
+    }
+
+    /// Wait on `clock` until every deposit up to and including `l1_block` has been collected.
+    #[cfg(feature = "executor")]
+    pub async fn wait_synced_through(&self, l1_block: u64, clock: &dyn Clock) {
+        while self.synced_through().await < Some(l1_block) {
+            tracing::info!("Waiting for deposits from L1 block {l1_block}");
+            clock.sleep(SYNC_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Delay before reconnecting to the L1 after the websocket connection fails.
+#[cfg(feature = "executor")]
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Interval at which the executor checks whether the deposits it needs have been collected.
+#[cfg(feature = "executor")]
+const SYNC_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Collect `Deposited` events emitted by the escrow contract at `escrow_address` into `queue`,
+/// starting after the last L1 block it has collected.
+///
+/// The listener subscribes to new L1 blocks over a websocket. On each new block, it fetches the
+/// events from the newly finalized blocks through the same connection, so only events which can no
+/// longer be reorganized are queued. If the connection drops, it is re-established and collection
+/// resumes after the last block collected. Runs forever.
+#[cfg(feature = "executor")]
+pub async fn follow_deposits(
+    ws_url: Url,
+    escrow_address: Address,
+    clock: Arc<dyn Clock>,
+    queue: DepositQueue,
+) {
+    loop {
+        match Provider::<Ws>::connect(ws_url.as_str()).await {
+            Ok(provider) => {
+                let provider = Arc::new(provider);
+                let escrow = DepositEscrow::new(escrow_address, provider.clone());
+                match provider.subscribe_blocks().await {
+                    Ok(mut blocks) => {
+                        tracing::info!("Following deposits to escrow {escrow_address:?}");
+                        while blocks.next().await.is_some() {
+                            let next = queue.synced_through().await.map_or(0, |synced| synced + 1);
+                            if let Err(err) =
+                                collect_deposits(&provider, &escrow, next, &queue).await
+                            {
+                                tracing::warn!("Unable to fetch deposits from L1: {err}");
+                            }
+                        }
+                        tracing::warn!("L1 block stream ended, reconnecting");
+                    }
+                    Err(err) => tracing::warn!("Unable to subscribe to L1 blocks: {err}"),
+                }
+            }
+            Err(err) => tracing::warn!("Unable to make websocket connection to L1: {err}"),
+        }
+        clock.sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Queue the deposits from L1 block `from_block` through the latest finalized block.
+#[cfg(feature = "executor")]
+async fn collect_deposits(
+    provider: &Provider<Ws>,
+    escrow: &DepositEscrow<Provider<Ws>>,
+    from_block: u64,
+    queue: &DepositQueue,
+) -> Result<(), String> {
+    let Some(finalized) = provider
+        .get_block(BlockNumber::Finalized)
+        .await
+        .map_err(|err| err.to_string())?
+        .and_then(|block| block.number)
+        .map(|number| number.as_u64())
+    else {
+        return Ok(());
+    };
+    if finalized < from_block {
+        return Ok(());
+    }
+    let events = escrow
+        .deposited_filter()
+        .from_block(from_block)
+        .to_block(finalized)
+        .query_with_meta()
+        .await
+        .map_err(|err| err.to_string())?;
+    for (event, meta) in events {
+        let LogMeta {
+            block_number,
+            log_index,
+            ..
+        } = meta;
+        let DepositedFilter { account, amount } = event;
+        match Deposit::new(account, amount, block_number.as_u64(), log_index.as_u64()) {
+            Some(deposit) => queue.insert(deposit).await,
+            None => tracing::error!("Ignoring deposit of {amount} wei to {account:?}"),
+        }
+    }
+    queue.set_synced_through(finalized).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_deposit_queue() {
+        let alice = Address::random();
+        let deposit =
+            |l1_block, log_index| Deposit::new(alice, U256::from(10), l1_block, log_index).unwrap();
+        let queue = DepositQueue::new(2);
+        assert_eq!(queue.synced_through().await, Some(2));
+        for (l1_block, log_index) in [(5, 1), (3, 0), (5, 0), (8, 2), (3, 0)] {
+            queue.insert(deposit(l1_block, log_index)).await;
+        }
+        queue.set_synced_through(8).await;
+        // The synced block never goes backwards.
+        queue.set_synced_through(6).await;
+        assert_eq!(queue.synced_through().await, Some(8));
+
+        // Deposits are taken in L1 order, and those already credited are discarded.
+        assert_eq!(queue.take(3, 5).await, vec![deposit(5, 0), deposit(5, 1)]);
+        assert_eq!(queue.take(5, 7).await, vec![]);
+        assert_eq!(queue.take(7, 8).await, vec![deposit(8, 2)]);
+
+        // Deposits which do not fit in a balance are rejected.
+        assert_eq!(Deposit::new(alice, U256::from(Amount::MAX) + 1, 1, 0), None);
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! A local devnet, launched by the `demo up` subcommand.
+//!
+//! Running the demo by hand takes a terminal each for the L1, the sequencer and the rollup, and
+//! their ports and addresses must be kept in agreement. `demo up` instead derives the
+//! configuration of each [`Service`] from the node's [`Options`], launches them as child processes
+//! in dependency order, waiting for each to become ready before launching the next, and stops all of
+//! them when it exits.
+//!
+//! The rollup is this executable, run without the `demo up` subcommand. It inherits the
+//! environment and the options given before the subcommand, so every node option can be set as
+//! usual.
+
+use crate::http::HttpClientPool;
+use crate::{DemoUpOptions, Options};
+use async_std::task::sleep;
+use ethers::providers::{Http, Middleware, Provider};
+use snafu::{ResultExt, Snafu};
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, Instant};
+use surf_disco::Url;
+
+/// Interval between checks of whether a service is ready.
+const READY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Snafu)]
+pub enum DevnetError {
+    #[snafu(display("Unable to launch {service} ({program}): {source}"))]
+    Spawn {
+        service: &'static str,
+        program: String,
+        source: io::Error,
+    },
+    #[snafu(display("{service} exited with {status}"))]
+    Exited {
+        service: &'static str,
+        status: ExitStatus,
+    },
+    #[snafu(display("{service} was not ready after {timeout:?}"))]
+    NotReady {
+        service: &'static str,
+        timeout: Duration,
+    },
+    #[snafu(display("{url} has no port, so the devnet cannot listen on it"))]
+    NoPort { url: Url },
+}
+
+/// How to tell that a service is ready.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Readiness {
+    /// The L1 JSON-RPC provider at this URL reports its chain ID.
+    L1(Url),
+    /// The query service at this URL reports its block height.
+    QueryService(Url),
+    /// The rollup API at this URL serves `rollup/info`.
+    RollupApi(Url),
+}
+
+impl Readiness {
+    pub fn url(&self) -> &Url {
+        match self {
+            Self::L1(url) | Self::QueryService(url) | Self::RollupApi(url) => url,
+        }
+    }
+
+    async fn check(&self, http: &HttpClientPool) -> bool {
+        match self {
+            Self::L1(url) => match Provider::<Http>::try_from(url.as_str()) {
+                Ok(provider) => provider.get_chainid().await.is_ok(),
+                Err(_) => false,
+            },
+            Self::QueryService(url) => http
+                .client(url)
+                .await
+                .get::<u64>("status/block-height")
+                .await
+                .is_ok(),
+            Self::RollupApi(url) => http
+                .client(url)
+                .await
+                .get::<serde_json::Value>("rollup/info")
+                .await
+                .is_ok(),
+        }
+    }
+}
+
+/// A process launched by the devnet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Service {
+    pub name: &'static str,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Environment variables set in addition to those inherited from this process.
+    pub env: Vec<(String, String)>,
+    pub readiness: Readiness,
+}
+
+impl Service {
+    fn spawn(&self) -> Result<Child, DevnetError> {
+        Command::new(&self.program)
+            .args(&self.args)
+            .envs(self.env.iter().cloned())
+            .spawn()
+            .context(SpawnSnafu {
+                service: self.name,
+                program: &self.program,
+            })
+    }
+}
+
+impl Display for Service {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:<12} {}", self.name, self.readiness.url())
+    }
+}
+
+fn port(url: &Url) -> Result<u16, DevnetError> {
+    url.port_or_known_default()
+        .ok_or_else(|| DevnetError::NoPort { url: url.clone() })
+}
+
+/// The options given to this executable before the `demo` subcommand, to be passed on to the
+/// rollup.
+pub fn node_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    args.into_iter()
+        .skip(1)
+        .take_while(|arg| arg != "demo")
+        .collect()
+}
+
+/// The services of a devnet configured by `opt`, in the order in which they are launched.
+///
+/// `rollup_program` is the executable run as the rollup, with `node_args`.
+pub fn plan(
+    opt: &Options,
+    up: &DemoUpOptions,
+    rollup_program: String,
+    node_args: Vec<String>,
+) -> Result<Vec<Service>, DevnetError> {
+    let l1_port = port(&opt.l1_http_provider)?;
+    let sequencer_port = port(&opt.sequencer_url)?;
+    // Anvil serves WebSocket connections on the same port as HTTP.
+    let l1_ws_provider = if up.no_anvil {
+        opt.l1_ws_provider.clone()
+    } else {
+        let mut url = opt.l1_http_provider.clone();
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme).ok();
+        url
+    };
+
+    let mut services = vec![];
+    if !up.no_anvil {
+        services.push(Service {
+            name: "L1 (Anvil)",
+            program: up.anvil_bin.clone(),
+            args: vec![
+                "--host".into(),
+                "0.0.0.0".into(),
+                "--port".into(),
+                l1_port.to_string(),
+                "--block-time".into(),
+                "1".into(),
+                "--mnemonic".into(),
+                opt.rollup_mnemonic.expose().clone(),
+            ],
+            env: vec![],
+            readiness: Readiness::L1(opt.l1_http_provider.clone()),
+        });
+    }
+    services.push(Service {
+        name: "Sequencer",
+        program: up.sequencer_bin.clone(),
+        args: vec![],
+        env: vec![
+            (
+                "ESPRESSO_SEQUENCER_L1_PROVIDER".into(),
+                opt.l1_http_provider.to_string(),
+            ),
+            (
+                "ESPRESSO_SEQUENCER_API_PORT".into(),
+                sequencer_port.to_string(),
+            ),
+        ],
+        readiness: Readiness::QueryService(opt.sequencer_url.clone()),
+    });
+    let rollup_url: Url = format!("http://localhost:{}/v0/", opt.api_port)
+        .parse()
+        .unwrap();
+    services.push(Service {
+        name: "Rollup API",
+        program: rollup_program,
+        args: node_args,
+        env: vec![
+            (
+                "ESPRESSO_SEQUENCER_URL".into(),
+                opt.sequencer_url.to_string(),
+            ),
+            (
+                "ESPRESSO_DEMO_L1_HTTP_PROVIDER".into(),
+                opt.l1_http_provider.to_string(),
+            ),
+            (
+                "ESPRESSO_DEMO_L1_WS_PROVIDER".into(),
+                l1_ws_provider.to_string(),
+            ),
+            (
+                "ESPRESSO_DEMO_LIGHT_CLIENT_ADDRESS".into(),
+                format!("{:?}", opt.light_client_address),
+            ),
+            ("ESPRESSO_DEMO_ROLLUP_PORT".into(), opt.api_port.to_string()),
+        ],
+        readiness: Readiness::RollupApi(rollup_url),
+    });
+    Ok(services)
+}
+
+/// The running services of a devnet, which are stopped when it is dropped.
+#[derive(Debug, Default)]
+pub struct Devnet {
+    running: Vec<(Service, Child)>,
+}
+
+impl Devnet {
+    /// Launch `services` in order, waiting up to `timeout` for each to become ready.
+    ///
+    /// If any service fails to start, those already running are stopped.
+    pub async fn launch(
+        services: Vec<Service>,
+        http: &HttpClientPool,
+        timeout: Duration,
+    ) -> Result<Self, DevnetError> {
+        let mut devnet = Self::default();
+        for service in services {
+            tracing::info!("Launching {}", service.name);
+            let child = service.spawn()?;
+            devnet.running.push((service, child));
+            devnet.wait_ready(http, timeout).await?;
+        }
+        Ok(devnet)
+    }
+
+    /// Wait until the most recently launched service is ready.
+    async fn wait_ready(
+        &mut self,
+        http: &HttpClientPool,
+        timeout: Duration,
+    ) -> Result<(), DevnetError> {
+        let start = Instant::now();
+        let (service, _) = self.running.last().unwrap();
+        let (name, readiness) = (service.name, service.readiness.clone());
+        loop {
+            if readiness.check(http).await {
+                tracing::info!("{name} is ready at {}", readiness.url());
+                return Ok(());
+            }
+            self.check_running()?;
+            if start.elapsed() >= timeout {
+                return Err(DevnetError::NotReady {
+                    service: name,
+                    timeout,
+                });
+            }
+            sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Fail if any service has exited.
+    pub fn check_running(&mut self) -> Result<(), DevnetError> {
+        for (service, child) in &mut self.running {
+            if let Ok(Some(status)) = child.try_wait() {
+                return Err(DevnetError::Exited {
+                    service: service.name,
+                    status,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until any service exits, returning the resulting error.
+    pub async fn watch(&mut self) -> DevnetError {
+        loop {
+            if let Err(err) = self.check_running() {
+                return err;
+            }
+            sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// The running services.
+    pub fn services(&self) -> impl Iterator<Item = &Service> {
+        self.running.iter().map(|(service, _)| service)
+    }
+
+    /// Stop every service, in the reverse of the order in which they were launched.
+    pub fn shut_down(&mut self) {
+        while let Some((service, mut child)) = self.running.pop() {
+            tracing::info!("Stopping {}", service.name);
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+}
+
+impl Drop for Devnet {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn options(args: &[&str]) -> (Options, DemoUpOptions) {
+        let opt = Options::parse_from(["example-l2"].iter().chain(args).chain(&["demo", "up"]));
+        let up = match &opt.command {
+            Some(crate::NodeCommand::Demo {
+                command: crate::DemoCommand::Up(up),
+            }) => up.clone(),
+            command => panic!("unexpected command {command:?}"),
+        };
+        (opt, up)
+    }
+
+    #[test]
+    fn test_plan() {
+        let (opt, up) = options(&[
+            "--api-port",
+            "9000",
+            "--l1-http-provider",
+            "http://localhost:9545",
+        ]);
+        let args = node_args(
+            ["example-l2", "--api-port", "9000", "demo", "up"]
+                .into_iter()
+                .map(String::from),
+        );
+        assert_eq!(args, ["--api-port", "9000"]);
+
+        let services = plan(&opt, &up, "example-l2".into(), args.clone()).unwrap();
+        let names: Vec<_> = services.iter().map(|service| service.name).collect();
+        assert_eq!(names, ["L1 (Anvil)", "Sequencer", "Rollup API"]);
+        assert!(services[0]
+            .args
+            .windows(2)
+            .any(|arg| arg == ["--port", "9545"]));
+
+        // The rollup is configured to use the L1 and sequencer launched before it.
+        let rollup = &services[2];
+        assert_eq!(rollup.args, args);
+        let env = |key: &str| {
+            rollup
+                .env
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(
+            env("ESPRESSO_DEMO_L1_WS_PROVIDER").as_deref(),
+            Some("ws://localhost:9545/")
+        );
+        assert_eq!(
+            env("ESPRESSO_SEQUENCER_URL"),
+            Some(opt.sequencer_url.to_string())
+        );
+        assert_eq!(rollup.readiness.url().as_str(), "http://localhost:9000/v0/");
+
+        let (opt, up) = options(&[]);
+        let up = DemoUpOptions {
+            no_anvil: true,
+            ..up
+        };
+        let services = plan(&opt, &up, "example-l2".into(), vec![]).unwrap();
+        assert_eq!(services[0].name, "Sequencer");
+    }
+}
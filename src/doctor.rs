@@ -0,0 +1,412 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Diagnostics of the node's environment, run by the `doctor` subcommand.
+//!
+//! A misconfigured node usually fails well after startup, with a panic deep in the executor or a
+//! proof submission which reverts. The doctor instead checks each external dependency up front:
+//! the query service, both L1 providers, the light client and rollup contracts, the funding of the
+//! rollup wallet and the ports the node listens on. Each check yields a [`Diagnostic`], with a hint
+//! on how to fix any problem it finds.
+
+use crate::deployment::DeploymentRecord;
+use crate::http::HttpClientPool;
+use crate::l1::L1Provider;
+use crate::light_client::{validate_light_client, LightClientError};
+use crate::signer::{L1SignerConfig, L1SignerKind};
+use crate::Options;
+use contract_bindings::example_rollup::ExampleRollup;
+use espresso_types::Header;
+use ethers::{
+    providers::{Middleware, Provider, Ws},
+    signers::Signer,
+    types::Address,
+    utils::format_ether,
+};
+use std::fmt::{self, Formatter};
+use std::net::{IpAddr, TcpListener};
+use std::sync::Arc;
+use strum_macros::Display;
+
+/// The outcome of a check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// The node can start, but may not behave as intended.
+    Warning,
+    /// The node will fail.
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub check: String,
+    pub status: Status,
+    pub detail: String,
+    /// How to fix the problem, if there is one.
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    fn ok(check: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            check: check.into(),
+            status: Status::Ok,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(
+        check: impl Into<String>,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            check: check.into(),
+            status: Status::Warning,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn failed(
+        check: impl Into<String>,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            check: check.into(),
+            status: Status::Failed,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.status, self.check, self.detail)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n    hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every check against the configuration in `opt`.
+pub async fn diagnose(opt: &Options) -> Vec<Diagnostic> {
+    let mut report = check_ports("rollup API port", &opt.api_bind_addresses, opt.api_port);
+    if let Some(grpc_port) = opt.grpc_port {
+        report.extend(check_ports("gRPC port", &opt.api_bind_addresses, grpc_port));
+    }
+    report.push(check_sequencer(opt).await);
+
+    let (diagnostic, provider) = check_l1_http(opt).await;
+    report.push(diagnostic);
+    let Some((provider, chain_id)) = provider else {
+        return report;
+    };
+    report.push(check_l1_ws(opt, chain_id).await);
+    report.push(check_light_client(&provider, opt.light_client_address).await);
+    report.push(check_rollup_contract(opt, &provider, chain_id).await);
+    report.push(check_wallet(opt, &provider).await);
+    report
+}
+
+/// Check that the node can listen on `port` at each of `addresses`.
+fn check_ports(name: &str, addresses: &[IpAddr], port: u16) -> Vec<Diagnostic> {
+    addresses
+        .iter()
+        .map(|address| {
+            let check = format!("{name} {address}:{port}");
+            match TcpListener::bind((*address, port)) {
+                Ok(_) => Diagnostic::ok(check, "available"),
+                Err(err) => Diagnostic::failed(
+                    check,
+                    format!("cannot listen: {err}"),
+                    "stop the process using the port, or choose another port or bind address",
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Check that the query service is reachable and speaks the API this node was built against.
+async fn check_sequencer(opt: &Options) -> Diagnostic {
+    const CHECK: &str = "query service";
+    let http = HttpClientPool::new(opt.http_client_options());
+    let availability = match opt.sequencer_url.join("availability") {
+        Ok(url) => http.client(&url).await,
+        Err(err) => {
+            return Diagnostic::failed(
+                CHECK,
+                format!("invalid URL {}: {err}", opt.sequencer_url),
+                "set ESPRESSO_SEQUENCER_URL to the query service URL",
+            )
+        }
+    };
+    let version = match availability.get::<serde_json::Value>("version").await {
+        Ok(version) => version,
+        Err(err) => {
+            return Diagnostic::failed(
+                CHECK,
+                format!("cannot reach {}: {err}", opt.sequencer_url),
+                "check ESPRESSO_SEQUENCER_URL, which must include the API version prefix, \
+                 e.g. http://localhost:24000/v0/",
+            )
+        }
+    };
+    let api_version = version
+        .get("api_version")
+        .and_then(|version| version.as_str())
+        .unwrap_or("unknown");
+    // Decoding the genesis header with our own types catches an incompatible sequencer release,
+    // which would otherwise only surface once the executor fetches its first block.
+    match availability.get::<Header>("header/0").await {
+        Ok(_) => Diagnostic::ok(
+            CHECK,
+            format!(
+                "{} serves availability API {api_version}",
+                opt.sequencer_url
+            ),
+        ),
+        Err(err) => Diagnostic::failed(
+            CHECK,
+            format!("availability API {api_version} is not compatible with this node: {err}"),
+            "run a sequencer release matching the espresso-types version this node was built with",
+        ),
+    }
+}
+
+async fn check_l1_http(opt: &Options) -> (Diagnostic, Option<(L1Provider, u64)>) {
+    const CHECK: &str = "L1 HTTP provider";
+    const HINT: &str = "check ESPRESSO_DEMO_L1_HTTP_PROVIDER and ESPRESSO_DEMO_L1_HTTP_FALLBACKS";
+    let provider = match opt.l1_client_pool() {
+        Ok(l1) => l1.provider(),
+        Err(err) => return (Diagnostic::failed(CHECK, err.to_string(), HINT), None),
+    };
+    match provider.get_chainid().await {
+        Ok(chain_id) => (
+            Diagnostic::ok(
+                CHECK,
+                format!("{} is on chain {chain_id}", opt.l1_http_provider),
+            ),
+            Some((provider, chain_id.as_u64())),
+        ),
+        Err(err) => (
+            Diagnostic::failed(
+                CHECK,
+                format!("cannot reach {}: {err}", opt.l1_http_provider),
+                HINT,
+            ),
+            None,
+        ),
+    }
+}
+
+async fn check_l1_ws(opt: &Options, chain_id: u64) -> Diagnostic {
+    const CHECK: &str = "L1 WebSocket provider";
+    const HINT: &str = "check ESPRESSO_DEMO_L1_WS_PROVIDER";
+    let provider = match Provider::<Ws>::connect(opt.l1_ws_provider.as_str()).await {
+        Ok(provider) => provider,
+        Err(err) => {
+            return Diagnostic::failed(
+                CHECK,
+                format!("cannot connect to {}: {err}", opt.l1_ws_provider),
+                HINT,
+            )
+        }
+    };
+    match provider.get_chainid().await {
+        Ok(ws_chain_id) if ws_chain_id.as_u64() == chain_id => Diagnostic::ok(
+            CHECK,
+            format!("{} is on chain {chain_id}", opt.l1_ws_provider),
+        ),
+        Ok(ws_chain_id) => Diagnostic::failed(
+            CHECK,
+            format!("on chain {ws_chain_id}, but the HTTP provider is on chain {chain_id}"),
+            "both L1 providers must serve the same chain",
+        ),
+        Err(err) => Diagnostic::failed(CHECK, err.to_string(), HINT),
+    }
+}
+
+/// Check that there is a contract at `address`, returning a failure if not.
+async fn check_code(
+    provider: &L1Provider,
+    check: &str,
+    address: Address,
+    hint: &str,
+) -> Option<Diagnostic> {
+    match provider.get_code(address, None).await {
+        Ok(code) if code.is_empty() => Some(Diagnostic::failed(
+            check,
+            format!("there is no contract at {address:?}"),
+            hint,
+        )),
+        Ok(_) => None,
+        Err(err) => Some(Diagnostic::failed(
+            check,
+            format!("cannot fetch code at {address:?}: {err}"),
+            hint,
+        )),
+    }
+}
+
+async fn check_light_client(provider: &L1Provider, address: Address) -> Diagnostic {
+    const CHECK: &str = "light client contract";
+    const HINT: &str = "check ESPRESSO_DEMO_LIGHT_CLIENT_ADDRESS";
+    match validate_light_client(provider, address).await {
+        Ok(state) => Diagnostic::ok(
+            CHECK,
+            format!("{address:?} has finalized block {}", state.block_height),
+        ),
+        Err(err @ LightClientError::Contract { .. }) => Diagnostic::failed(
+            CHECK,
+            err.to_string(),
+            "check ESPRESSO_DEMO_L1_HTTP_PROVIDER",
+        ),
+        Err(err @ LightClientError::NoContract { .. }) => Diagnostic::failed(
+            CHECK,
+            err.to_string(),
+            "check ESPRESSO_DEMO_LIGHT_CLIENT_ADDRESS, and that the L1 providers serve the chain \
+             the light client is deployed on",
+        ),
+        Err(err @ LightClientError::NoNewStateEvents { .. }) => Diagnostic::failed(
+            CHECK,
+            err.to_string(),
+            "point ESPRESSO_DEMO_LIGHT_CLIENT_ADDRESS at a light client matching the \
+             hotshot-contract-bindings version this node was built with",
+        ),
+        Err(err) => Diagnostic::failed(CHECK, err.to_string(), HINT),
+    }
+}
+
+async fn check_rollup_contract(opt: &Options, provider: &L1Provider, chain_id: u64) -> Diagnostic {
+    const CHECK: &str = "rollup contract";
+    const HINT: &str = "check ESPRESSO_DEMO_DEPLOYMENT_FILE, or remove it to deploy a new contract";
+    let record = match &opt.deployment_file {
+        Some(path) => match DeploymentRecord::load(path) {
+            Ok(record) => record,
+            Err(err) => {
+                return Diagnostic::failed(
+                    CHECK,
+                    format!("cannot read {}: {err}", path.display()),
+                    HINT,
+                )
+            }
+        },
+        None => None,
+    };
+    let Some(record) = record else {
+        return Diagnostic::ok(CHECK, "a new contract will be deployed at startup");
+    };
+    if record.chain_id != chain_id {
+        return Diagnostic::failed(
+            CHECK,
+            format!(
+                "deployed on chain {}, but the L1 provider is on chain {chain_id}",
+                record.chain_id
+            ),
+            HINT,
+        );
+    }
+    if let Some(failure) = check_code(provider, CHECK, record.rollup_address, HINT).await {
+        return failure;
+    }
+    let rollup = ExampleRollup::new(record.rollup_address, Arc::new(provider.clone()));
+    match rollup.num_verified_blocks().call().await {
+        Ok(num_verified_blocks) => Diagnostic::ok(
+            CHECK,
+            format!(
+                "{:?} has verified {num_verified_blocks} blocks",
+                record.rollup_address
+            ),
+        ),
+        Err(err) => Diagnostic::failed(
+            CHECK,
+            format!(
+                "{:?} does not implement the rollup interface: {err}",
+                record.rollup_address
+            ),
+            HINT,
+        ),
+    }
+}
+
+/// Check that the rollup wallet, which deploys the rollup contract and, with a mnemonic signer,
+/// submits proofs, has ETH to pay for gas.
+async fn check_wallet(opt: &Options, provider: &L1Provider) -> Diagnostic {
+    const CHECK: &str = "rollup wallet";
+    let signer = L1SignerConfig::Mnemonic {
+        mnemonic: opt.rollup_mnemonic.clone(),
+        account_index: opt.rollup_account_index,
+    };
+    let wallet = match signer.local_wallet() {
+        Some(Ok(wallet)) => wallet,
+        Some(Err(err)) => {
+            return Diagnostic::failed(
+                CHECK,
+                err.to_string(),
+                "check ESPRESSO_DEMO_ROLLUP_MNEMONIC and ESPRESSO_DEMO_ROLLUP_ACCOUNT_INDEX",
+            )
+        }
+        None => unreachable!("mnemonic signers have a local wallet"),
+    };
+    let address = wallet.address();
+    match provider.get_balance(address, None).await {
+        Ok(balance) if balance.is_zero() => Diagnostic::failed(
+            CHECK,
+            format!("{address:?} has no ETH"),
+            "fund the account on the L1 to pay for deployment and proof submission",
+        ),
+        Ok(balance) => {
+            let detail = format!("{address:?} holds {} ETH", format_ether(balance));
+            if opt.l1_signer == L1SignerKind::Mnemonic {
+                Diagnostic::ok(CHECK, detail)
+            } else {
+                Diagnostic::warning(
+                    CHECK,
+                    detail,
+                    "proofs are signed by an external key, whose funding is not checked",
+                )
+            }
+        }
+        Err(err) => Diagnostic::failed(
+            CHECK,
+            format!("cannot fetch the balance of {address:?}: {err}"),
+            "check ESPRESSO_DEMO_L1_HTTP_PROVIDER",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_check_ports() {
+        let localhost = [IpAddr::V4(Ipv4Addr::LOCALHOST)];
+        let listener = TcpListener::bind((localhost[0], 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let report = check_ports("test port", &localhost, port);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].status, Status::Failed);
+        assert!(report[0].hint.is_some());
+
+        drop(listener);
+        let report = check_ports("test port", &localhost, port);
+        assert_eq!(report[0].status, Status::Ok);
+        assert_eq!(
+            report[0].to_string(),
+            format!("[ok] test port 127.0.0.1:{port}: available")
+        );
+    }
+}
@@ -4,12 +4,16 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
-use crate::state::Nonce;
+use crate::chain::ChainId;
+use crate::state::{Nonce, State};
+use committable::Commitment;
 use ethers::abi::Address;
+use ethers::types::H256;
 use serde::{Deserialize, Serialize};
 use snafu::Snafu;
 
-#[derive(Snafu, Debug, Eq, PartialEq, Serialize, Deserialize)]
+/// An error executing a rollup transaction.
+#[derive(Snafu, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum RollupError {
     #[snafu(display("Error validating the transaction signature."))]
     SignatureError,
@@ -23,5 +27,206 @@ pub enum RollupError {
         expected: Nonce,
         actual: Nonce,
     },
+    #[snafu(display("Transaction {hash:?} from {address} was already executed recently."))]
+    DuplicateTransaction {
+        address: Address,
+        hash: H256,
+    },
+    #[snafu(display("Malformed transaction: {reason}"))]
+    MalformedTransaction {
+        reason: String,
+    },
+    #[snafu(display("Transaction of {size} bytes exceeds the maximum size of {max} bytes."))]
+    TransactionTooLarge {
+        size: usize,
+        max: usize,
+    },
+    #[snafu(display("Transaction {hash:?} expired at block {expires_at}."))]
+    TransactionExpired {
+        hash: H256,
+        expires_at: u64,
+    },
+    #[snafu(display(
+        "Transaction was signed for chain {actual}, not this rollup's chain {expected}."
+    ))]
+    WrongChain {
+        expected: ChainId,
+        actual: ChainId,
+    },
+    #[snafu(display(
+        "Transaction was not signed for a chain, but this rollup is bound to chain {expected}."
+    ))]
+    MissingChainId {
+        expected: ChainId,
+    },
+    #[snafu(display(
+        "Transaction {hash:?} must expire by block {max}, before it leaves the replay window."
+    ))]
+    ExpiryOutsideReplayWindow {
+        hash: H256,
+        max: u64,
+    },
+    #[snafu(display("Account {address} is frozen."))]
+    AccountFrozen {
+        address: Address,
+    },
+    #[snafu(display("Transaction violates policy {policy}: {reason}"))]
+    PolicyViolation {
+        policy: String,
+        reason: String,
+    },
+    #[snafu(display("Untrusted submission rejected: {reason}"))]
+    UntrustedSubmission {
+        reason: String,
+    },
     InvalidTransaction,
+    #[snafu(display("No handler is registered for custom transaction kind {kind}."))]
+    UnknownTransactionKind {
+        kind: u8,
+    },
+    #[snafu(display("Spend of {inputs} tokens creates notes worth {outputs} tokens."))]
+    UnbalancedSpend {
+        inputs: u128,
+        outputs: u128,
+    },
+    #[snafu(display("Note {note:?} does not exist or was already spent."))]
+    UnknownNote {
+        note: H256,
+    },
+    #[snafu(display("Note {note:?} is not owned by {address}."))]
+    NoteNotOwned {
+        note: H256,
+        address: Address,
+    },
+    #[snafu(display("Crediting {address} would overflow its balance."))]
+    BalanceOverflow {
+        address: Address,
+    },
+}
+
+impl RollupError {
+    /// A stable numeric code identifying the kind of error, for clients which handle errors
+    /// programmatically.
+    ///
+    /// Codes are grouped by category: 1xx for malformed transactions, 2xx for replay and ordering
+    /// errors, 3xx for account errors and 4xx for policy violations.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::MalformedTransaction { .. } => 100,
+            Self::TransactionTooLarge { .. } => 101,
+            Self::SignatureError => 102,
+            Self::InvalidTransaction => 103,
+            Self::UnknownTransactionKind { .. } => 104,
+            Self::UnbalancedSpend { .. } => 105,
+            Self::InvalidNonce { .. } => 200,
+            Self::DuplicateTransaction { .. } => 201,
+            Self::TransactionExpired { .. } => 202,
+            Self::WrongChain { .. } => 203,
+            Self::ExpiryOutsideReplayWindow { .. } => 204,
+            Self::MissingChainId { .. } => 205,
+            Self::InsufficientBalance { .. } => 300,
+            Self::AccountFrozen { .. } => 301,
+            Self::UnknownNote { .. } => 302,
+            Self::NoteNotOwned { .. } => 303,
+            Self::BalanceOverflow { .. } => 304,
+            Self::PolicyViolation { .. } => 400,
+            Self::UntrustedSubmission { .. } => 401,
+        }
+    }
+
+    /// Whether the same transaction may succeed if it is retried later, after the state changes.
+    ///
+    /// A transaction with a nonce ahead of the sender's may become valid once the transactions
+    /// before it execute, and a sender with insufficient balance may receive funds. Every other
+    /// error is permanent: the transaction must be changed or dropped.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::InvalidNonce {
+                expected, actual, ..
+            } => actual > expected,
+            Self::InsufficientBalance { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// A difference between the primary and reference execution of a block.
+#[derive(Snafu, Clone, Debug, Eq, PartialEq)]
+pub enum DeterminismError {
+    #[snafu(display(
+        "Transaction {index} produced {primary} but the reference execution produced {reference}."
+    ))]
+    ResultMismatch {
+        index: usize,
+        primary: String,
+        reference: String,
+    },
+    #[snafu(display("The block emitted different events in the reference execution."))]
+    EventsMismatch,
+    #[snafu(display(
+        "The block produced state commitment {primary} but the reference execution produced {reference}."
+    ))]
+    CommitmentMismatch {
+        primary: Commitment<State>,
+        reference: Commitment<State>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_error_codes() {
+        let address = Address::random();
+        let hash = H256::random();
+        let errors = [
+            RollupError::SignatureError,
+            RollupError::InsufficientBalance { address },
+            RollupError::InvalidNonce {
+                address,
+                expected: 2,
+                actual: 3,
+            },
+            RollupError::DuplicateTransaction { address, hash },
+            RollupError::MalformedTransaction {
+                reason: "EOF".into(),
+            },
+            RollupError::TransactionTooLarge { size: 2, max: 1 },
+            RollupError::TransactionExpired {
+                hash,
+                expires_at: 1,
+            },
+            RollupError::WrongChain {
+                expected: ChainId(H256::random()),
+                actual: ChainId(H256::random()),
+            },
+            RollupError::ExpiryOutsideReplayWindow { hash, max: 1 },
+            RollupError::MissingChainId {
+                expected: ChainId(H256::random()),
+            },
+            RollupError::AccountFrozen { address },
+            RollupError::PolicyViolation {
+                policy: "allow-list".into(),
+                reason: "destination not allowed".into(),
+            },
+            RollupError::UntrustedSubmission {
+                reason: "not countersigned".into(),
+            },
+            RollupError::InvalidTransaction,
+            RollupError::UnknownTransactionKind { kind: 1 },
+        ];
+        let codes: HashSet<_> = errors.iter().map(RollupError::code).collect();
+        assert_eq!(codes.len(), errors.len());
+
+        let retryable: Vec<_> = errors.iter().filter(|err| err.is_retryable()).collect();
+        assert_eq!(retryable, [&errors[1], &errors[2]]);
+        assert!(!RollupError::InvalidNonce {
+            address,
+            expected: 2,
+            actual: 1,
+        }
+        .is_retryable());
+    }
 }
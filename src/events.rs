@@ -0,0 +1,566 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Events emitted by transactions as they are executed.
+
+use crate::history::{BlockInfo, DEFAULT_HISTORY_WINDOW};
+use crate::state::Amount;
+use async_std::channel::{self, Receiver, Sender};
+use async_std::sync::RwLock;
+use ethers::{abi::Address, utils::keccak256};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use strum_macros::{AsRefStr, EnumString};
+
+/// A structured event emitted by an applied transaction, or by crediting a deposit from the L1.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum RollupEvent {
+    /// Tokens were transferred between two accounts.
+    Transfer {
+        from: Address,
+        to: Address,
+        amount: Amount,
+    },
+    /// An account received tokens for the first time.
+    AccountCreated { address: Address },
+    /// Tokens were destroyed, such as the burned share of a transaction fee.
+    Burn { from: Address, amount: Amount },
+    /// Tokens were burned to be claimed by `recipient` on the L1, as the withdrawal at `index`.
+    Withdrawal {
+        from: Address,
+        recipient: Address,
+        amount: Amount,
+        index: u64,
+    },
+    /// Tokens deposited to `to` through the escrow contract on the L1 were credited.
+    Deposit { to: Address, amount: Amount },
+}
+
+/// The kind of a [`RollupEvent`], used to filter events by topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, AsRefStr, EnumString, Serialize, Deserialize)]
+pub enum EventKind {
+    Transfer,
+    AccountCreated,
+    Burn,
+    Withdrawal,
+    Deposit,
+}
+
+impl RollupEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::Transfer { .. } => EventKind::Transfer,
+            Self::AccountCreated { .. } => EventKind::AccountCreated,
+            Self::Burn { .. } => EventKind::Burn,
+            Self::Withdrawal { .. } => EventKind::Withdrawal,
+            Self::Deposit { .. } => EventKind::Deposit,
+        }
+    }
+
+    /// The amount of tokens moved by this event, if any.
+    pub fn amount(&self) -> Option<Amount> {
+        match self {
+            Self::Transfer { amount, .. }
+            | Self::Burn { amount, .. }
+            | Self::Withdrawal { amount, .. }
+            | Self::Deposit { amount, .. } => Some(*amount),
+            Self::AccountCreated { .. } => None,
+        }
+    }
+
+    /// Whether `address` is one of the accounts this event is about.
+    pub fn involves(&self, address: &Address) -> bool {
+        match self {
+            Self::Transfer { from, to, .. } => from == address || to == address,
+            Self::AccountCreated { address: created } => created == address,
+            Self::Deposit { to, .. } => to == address,
+            Self::Burn { from, .. } | Self::Withdrawal { from, .. } => from == address,
+        }
+    }
+}
+
+/// A filter selecting events by topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventFilter {
+    Kind(EventKind),
+    Address(Address),
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &RollupEvent) -> bool {
+        match self {
+            Self::Kind(kind) => event.kind() == *kind,
+            Self::Address(address) => event.involves(address),
+        }
+    }
+}
+
+/// Filter on the events delivered to a subscriber, evaluated by the server.
+///
+/// An event is delivered only if it satisfies every criterion which is set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubscriptionFilter {
+    pub kind: Option<EventKind>,
+    /// Only deliver events involving this account.
+    pub address: Option<Address>,
+    /// Only deliver events moving at least this many tokens. Events which do not move tokens are
+    /// excluded when this is set.
+    pub min_amount: Option<Amount>,
+}
+
+impl SubscriptionFilter {
+    pub fn matches(&self, event: &RollupEvent) -> bool {
+        self.kind.map_or(true, |kind| event.kind() == kind)
+            && self
+                .address
+                .map_or(true, |address| event.involves(&address))
+            && self.min_amount.map_or(true, |min_amount| {
+                event.amount().is_some_and(|amount| amount >= min_amount)
+            })
+    }
+}
+
+/// An event delivered to a subscriber, along with the block which emitted it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockEvent {
+    /// Position of the event among all events published, which a subscriber can resume from.
+    pub seq: u64,
+    pub block_height: u64,
+    /// HotShot view in which the block was decided, if known.
+    #[serde(default)]
+    pub view_number: Option<u64>,
+    /// Unix timestamp (in seconds) of the block.
+    #[serde(default)]
+    pub timestamp: u64,
+    pub event: RollupEvent,
+}
+
+/// The first message sent by a client opening an event stream.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SubscriptionRequest {
+    /// Resume an earlier subscription with the same filter, starting from the event with sequence
+    /// number `from_seq`.
+    Resume { subscription_id: u64, from_seq: u64 },
+    /// Open a new subscription.
+    New(SubscriptionFilter),
+}
+
+/// A message sent by the server on an event stream.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamMessage {
+    /// Sent once when the stream is opened. `next_seq` is the sequence number of the next event to
+    /// be published; events from earlier sequence numbers are only delivered when resuming.
+    Subscribed {
+        subscription_id: u64,
+        next_seq: u64,
+    },
+    Event(BlockEvent),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Snafu)]
+pub enum SubscriptionError {
+    #[snafu(display("Unknown subscription {subscription_id}"))]
+    UnknownSubscription { subscription_id: u64 },
+    #[snafu(display(
+        "Cannot resume from event {from_seq}: only events from {oldest_seq} are retained"
+    ))]
+    Expired { from_seq: u64, oldest_seq: u64 },
+}
+
+/// An open subscription to an [`EventFanout`].
+#[derive(Debug)]
+pub struct Subscription {
+    pub id: u64,
+    /// Sequence number of the next event to be published.
+    pub next_seq: u64,
+    /// Matching events, starting with any replayed from the recent events window.
+    pub events: Receiver<BlockEvent>,
+}
+
+/// Maximum number of undelivered events buffered for a subscriber before it is disconnected.
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+/// Number of recent events retained by default for subscribers resuming a stream.
+pub const DEFAULT_REPLAY_WINDOW: usize = 10_000;
+
+/// Maximum number of subscriptions whose filters are remembered for resumption.
+const MAX_SUBSCRIPTIONS: usize = 10_000;
+
+#[derive(Debug)]
+struct FanoutInner {
+    next_seq: u64,
+    next_subscription_id: u64,
+    window: usize,
+    /// The most recently published events, oldest first.
+    recent: VecDeque<BlockEvent>,
+    /// Filters of recent subscriptions, by ID, whether or not they are connected.
+    filters: BTreeMap<u64, SubscriptionFilter>,
+    /// Connected subscribers.
+    subscribers: Vec<(SubscriptionFilter, Sender<BlockEvent>)>,
+}
+
+/// Fans out the events of each executed block to subscribers.
+///
+/// Each subscriber's filter is evaluated here, as blocks are published, so subscribers only
+/// receive the events they asked for. Subscribers which fall too far behind are disconnected
+/// rather than buffering events without bound.
+///
+/// Every published event is assigned a sequence number, and a window of recent events is retained
+/// so that a subscriber which disconnects can resume its subscription from the last event it saw,
+/// without missing or duplicating events.
+#[derive(Clone, Debug)]
+pub struct EventFanout {
+    inner: Arc<RwLock<FanoutInner>>,
+}
+
+impl Default for EventFanout {
+    fn default() -> Self {
+        Self::new(DEFAULT_REPLAY_WINDOW)
+    }
+}
+
+impl EventFanout {
+    /// A fan-out retaining the `window` most recent events for resumption.
+    pub fn new(window: usize) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(FanoutInner {
+                next_seq: 0,
+                next_subscription_id: 0,
+                window,
+                recent: VecDeque::new(),
+                filters: BTreeMap::new(),
+                subscribers: vec![],
+            })),
+        }
+    }
+
+    /// Subscribe to events matching `filter` from blocks published after this call.
+    pub async fn subscribe(&self, filter: SubscriptionFilter) -> Subscription {
+        let mut inner = self.inner.write().await;
+        let id = inner.next_subscription_id;
+        inner.next_subscription_id += 1;
+        inner.filters.insert(id, filter);
+        while inner.filters.len() > MAX_SUBSCRIPTIONS {
+            inner.filters.pop_first();
+        }
+        let from_seq = inner.next_seq;
+        inner.connect(id, filter, from_seq)
+    }
+
+    /// Resume the subscription `id`, delivering matching events from sequence number `from_seq`.
+    pub async fn resume(&self, id: u64, from_seq: u64) -> Result<Subscription, SubscriptionError> {
+        let mut inner = self.inner.write().await;
+        let filter = *inner
+            .filters
+            .get(&id)
+            .ok_or(SubscriptionError::UnknownSubscription {
+                subscription_id: id,
+            })?;
+        let oldest_seq = inner
+            .recent
+            .front()
+            .map_or(inner.next_seq, |event| event.seq);
+        if from_seq < oldest_seq {
+            return Err(SubscriptionError::Expired {
+                from_seq,
+                oldest_seq,
+            });
+        }
+        Ok(inner.connect(id, filter, from_seq))
+    }
+
+    /// Open or resume a subscription as requested by a client.
+    pub async fn open(
+        &self,
+        request: SubscriptionRequest,
+    ) -> Result<Subscription, SubscriptionError> {
+        match request {
+            SubscriptionRequest::New(filter) => Ok(self.subscribe(filter).await),
+            SubscriptionRequest::Resume {
+                subscription_id,
+                from_seq,
+            } => self.resume(subscription_id, from_seq).await,
+        }
+    }
+
+    /// Deliver the events emitted by the block `block` to each matching subscriber.
+    pub async fn publish(&self, block: BlockInfo, events: &[RollupEvent]) {
+        let mut inner = self.inner.write().await;
+        let events: Vec<_> = events
+            .iter()
+            .map(|event| {
+                let seq = inner.next_seq;
+                inner.next_seq += 1;
+                BlockEvent {
+                    seq,
+                    block_height: block.block_height,
+                    view_number: block.view_number,
+                    timestamp: block.timestamp,
+                    event: event.clone(),
+                }
+            })
+            .collect();
+        inner.subscribers.retain(|(filter, sender)| {
+            events
+                .iter()
+                .filter(|event| filter.matches(&event.event))
+                .all(|event| sender.try_send(event.clone()).is_ok())
+                && !sender.is_closed()
+        });
+        inner.recent.extend(events);
+        while inner.recent.len() > inner.window {
+            inner.recent.pop_front();
+        }
+    }
+}
+
+impl FanoutInner {
+    fn connect(&mut self, id: u64, filter: SubscriptionFilter, from_seq: u64) -> Subscription {
+        let replay: Vec<_> = self
+            .recent
+            .iter()
+            .filter(|event| event.seq >= from_seq && filter.matches(&event.event))
+            .cloned()
+            .collect();
+        let (sender, events) = channel::bounded(SUBSCRIBER_BUFFER + replay.len());
+        for event in replay {
+            // The channel has room for every replayed event.
+            sender.try_send(event).unwrap();
+        }
+        self.subscribers.push((filter, sender));
+        Subscription {
+            id,
+            next_seq: self.next_seq,
+            events,
+        }
+    }
+}
+
+/// Root committing to the events emitted in a block.
+pub fn events_root(events: &[RollupEvent]) -> [u8; 32] {
+    keccak256(serde_json::to_vec(events).expect("Serialization should not fail"))
+}
+
+/// Index of the events emitted in each of a window of recently executed blocks.
+#[derive(Clone, Debug)]
+pub struct EventIndex {
+    window: usize,
+    blocks: Arc<RwLock<BTreeMap<u64, Vec<RollupEvent>>>>,
+}
+
+impl Default for EventIndex {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_WINDOW)
+    }
+}
+
+impl EventIndex {
+    /// Retain the events of the last `window` executed blocks.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            blocks: Default::default(),
+        }
+    }
+
+    pub async fn insert(&self, block_height: u64, events: Vec<RollupEvent>) {
+        let mut blocks = self.blocks.write().await;
+        blocks.insert(block_height, events);
+        while blocks.len() > self.window {
+            blocks.pop_first();
+        }
+    }
+
+    /// The events emitted in the block at `block_height` which match `filter`, or `None` if the
+    /// block has not been executed or is no longer retained.
+    pub async fn get(
+        &self,
+        block_height: u64,
+        filter: Option<EventFilter>,
+    ) -> Option<Vec<RollupEvent>> {
+        let blocks = self.blocks.read().await;
+        let events = blocks.get(&block_height)?;
+        Some(
+            events
+                .iter()
+                .filter(|event| filter.map_or(true, |filter| filter.matches(event)))
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_filtered_subscription() {
+        let alice = Address::random();
+        let bob = Address::random();
+        let events = [
+            RollupEvent::AccountCreated { address: bob },
+            RollupEvent::Transfer {
+                from: alice,
+                to: bob,
+                amount: 5,
+            },
+            RollupEvent::Transfer {
+                from: bob,
+                to: alice,
+                amount: 50,
+            },
+        ];
+
+        let fanout = EventFanout::default();
+        let all = fanout.subscribe(Default::default()).await.events;
+        let large = fanout
+            .subscribe(SubscriptionFilter {
+                min_amount: Some(10),
+                ..Default::default()
+            })
+            .await
+            .events;
+        let created = fanout
+            .subscribe(SubscriptionFilter {
+                kind: Some(EventKind::AccountCreated),
+                address: Some(bob),
+                ..Default::default()
+            })
+            .await
+            .events;
+        drop(fanout.subscribe(Default::default()).await);
+
+        let block = BlockInfo {
+            block_height: 7,
+            view_number: Some(9),
+            timestamp: 1234,
+        };
+        fanout.publish(block, &events).await;
+        assert_eq!(all.len(), 3);
+        assert_eq!(
+            large.recv().await.unwrap(),
+            BlockEvent {
+                seq: 2,
+                block_height: 7,
+                view_number: Some(9),
+                timestamp: 1234,
+                event: events[2].clone(),
+            }
+        );
+        assert!(large.is_empty());
+        assert_eq!(created.recv().await.unwrap().event, events[0]);
+        assert!(created.is_empty());
+
+        // Closed subscriptions are dropped.
+        assert_eq!(fanout.inner.read().await.subscribers.len(), 3);
+    }
+
+    fn block(block_height: u64) -> BlockInfo {
+        BlockInfo {
+            block_height,
+            ..Default::default()
+        }
+    }
+
+    #[async_std::test]
+    async fn test_resume_subscription() {
+        let transfer = |amount| RollupEvent::Transfer {
+            from: Address::zero(),
+            to: Address::zero(),
+            amount,
+        };
+        let fanout = EventFanout::new(3);
+        let filter = SubscriptionFilter {
+            min_amount: Some(10),
+            ..Default::default()
+        };
+        let subscription = fanout.subscribe(filter).await;
+        assert_eq!(subscription.next_seq, 0);
+        fanout.publish(block(1), &[transfer(10), transfer(1)]).await;
+        assert_eq!(subscription.events.recv().await.unwrap().seq, 0);
+
+        // The subscriber disconnects and misses some events.
+        drop(subscription.events);
+        fanout
+            .publish(block(2), &[transfer(20), transfer(30)])
+            .await;
+
+        // Resuming from the next unseen event delivers exactly the missed matching events, then new
+        // events.
+        let resumed = fanout.resume(subscription.id, 1).await.unwrap();
+        assert_eq!(resumed.next_seq, 4);
+        fanout.publish(block(3), &[transfer(40)]).await;
+        let seqs: Vec<_> = (0..3)
+            .map(|_| resumed.events.try_recv().unwrap().seq)
+            .collect();
+        assert_eq!(seqs, [2, 3, 4]);
+        assert!(resumed.events.is_empty());
+
+        // Events which have left the window cannot be replayed.
+        assert_eq!(
+            fanout.resume(subscription.id, 1).await.unwrap_err(),
+            SubscriptionError::Expired {
+                from_seq: 1,
+                oldest_seq: 2
+            }
+        );
+        assert_eq!(
+            fanout
+                .open(SubscriptionRequest::Resume {
+                    subscription_id: 100,
+                    from_seq: 0
+                })
+                .await
+                .unwrap_err(),
+            SubscriptionError::UnknownSubscription {
+                subscription_id: 100
+            }
+        );
+
+        // Clients distinguish resumption from a new subscription by the fields they send.
+        assert_eq!(
+            serde_json::from_str::<SubscriptionRequest>(r#"{"subscription_id":1,"from_seq":2}"#)
+                .unwrap(),
+            SubscriptionRequest::Resume {
+                subscription_id: 1,
+                from_seq: 2
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<SubscriptionRequest>(r#"{"min_amount":10}"#).unwrap(),
+            SubscriptionRequest::New(filter)
+        );
+    }
+
+    #[async_std::test]
+    async fn test_event_index_window() {
+        let alice = Address::random();
+        let index = EventIndex::new(2);
+        for block_height in 1..=3 {
+            let events = vec![RollupEvent::AccountCreated { address: alice }];
+            index.insert(block_height, events).await;
+        }
+
+        // Only the most recent blocks are retained.
+        assert_eq!(index.get(1, None).await, None);
+        assert_eq!(
+            index.get(3, Some(EventFilter::Address(alice))).await,
+            Some(vec![RollupEvent::AccountCreated { address: alice }])
+        );
+        assert_eq!(
+            index
+                .get(2, Some(EventFilter::Kind(EventKind::Transfer)))
+                .await,
+            Some(vec![])
+        );
+    }
+}
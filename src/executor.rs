@@ -4,50 +4,86 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
-use crate::prover::BatchProof;
+use crate::aggregator::ExternalAggregator;
+use crate::backfill::{CatchUpTracker, HeaderFetcher, DEFAULT_MAX_ATTEMPTS};
+use crate::breaker::{BlockProgress, CircuitBreaker};
+use crate::canary::Canary;
+use crate::clock::Clock;
+use crate::data_source::{QueryServiceDataSource, SequencerDataSource};
+use crate::deposit::{follow_deposits, DepositQueue};
+use crate::http::HttpClientPool;
+use crate::l1::{
+    connect_l1_client, follow_light_client, ClientPool, L1Client, L1ClientKind, L1Provider,
+    LightClientEvent,
+};
+use crate::light_client::HeaderVerifier;
+use crate::metrics::NodeMetrics;
+use crate::outbox::Outbox;
+pub use crate::prover::{AggregationStrategy, ProofShape};
+use crate::prover::{PendingProofs, ProofVerifier};
+use crate::recovery::recover_from_l1;
+use crate::scheduler::{BlockScheduler, DaTimeoutPolicy, NamespaceBlock, SharedBlocks};
+use crate::secret::redact_url;
+use crate::signer::L1SignerConfig;
 use crate::state::State;
+use crate::stats::{unix_millis, FinalityLagSample, FinalityLagTracker, LatencyTracker};
+use crate::storage::Checkpointer;
+use crate::warm_start::{Resume, WarmStartWriter};
+use crate::watchdog::ExecutionWatchdog;
 use async_compatibility_layer::async_primitives::broadcast::BroadcastSender;
+use async_std::channel;
 use async_std::sync::{Arc, RwLock};
-use async_std::task::sleep;
+use async_std::task::spawn;
 use committable::Committable;
-use contract_bindings::example_rollup::{self, ExampleRollup, ExampleRollupErrors};
-use espresso_types::{Header, NamespaceId, SeqTypes};
+use espresso_types::{Header, NamespaceId};
 use ethers::core::k256::ecdsa::SigningKey;
 use ethers::prelude::*;
 use ethers::{
     prelude::SignerMiddleware,
-    providers::{Http, Middleware, Provider},
+    providers::Middleware,
     signers::{coins_bip39::English, MnemonicBuilder},
     types::Address,
 };
-use hotshot_contract_bindings::light_client::{LightClient, NewStateFilter};
-use hotshot_query_service::availability::{PayloadQueryData, VidCommonQueryData};
-use sequencer::api::endpoints::NamespaceProofQueryData;
-use sequencer::SequencerApiVersion;
-use sequencer_utils::{commitment_to_u256, contract_send};
-use std::time::Duration;
-use surf_disco::error::ClientError;
+use futures::future::{join_all, select, Either, FutureExt};
+use hotshot_contract_bindings::light_client::NewStateFilter;
+use snafu::Snafu;
+use std::collections::HashSet;
 use surf_disco::Url;
 
 pub async fn connect_rpc(
-    provider: &Url,
+    l1: &ClientPool,
     mnemonic: &str,
     index: u32,
     chain_id: Option<u64>,
-    polling_interval: Option<Duration>,
-) -> Option<SignerMiddleware<Provider<Http>, Wallet<SigningKey>>> {
-    let mut provider = match Provider::try_from(provider.to_string()) {
-        Ok(provider) => provider,
+) -> Option<SignerMiddleware<L1Provider, Wallet<SigningKey>>> {
+    let mnemonic = match MnemonicBuilder::<English>::default()
+        .phrase(mnemonic)
+        .index(index)
+    {
+        Ok(mnemonic) => mnemonic,
+        Err(err) => {
+            tracing::error!("error building walletE: {}", err);
+            return None;
+        }
+    };
+    let wallet = match mnemonic.build() {
+        Ok(wallet) => wallet,
         Err(err) => {
-            tracing::error!("error connecting to RPC {}: {}", provider, err);
+            tracing::error!("error opening wallet: {}", err);
             return None;
         }
     };
-    tracing::info!("Connected to RPC {}", provider.url());
+    connect_rpc_with_signer(l1, wallet, chain_id).await
+}
 
-    if let Some(interval) = polling_interval {
-        provider.set_interval(interval);
-    }
+/// Connect to the L1 through `l1`, signing transactions with `signer`.
+pub async fn connect_rpc_with_signer<S: Signer>(
+    l1: &ClientPool,
+    signer: S,
+    chain_id: Option<u64>,
+) -> Option<SignerMiddleware<L1Provider, S>> {
+    let provider = l1.provider();
+    tracing::info!("Connected to RPC {}", redact_url(&l1.url()));
     tracing::info!("RPC Polling interval is {:?}", provider.get_interval());
 
     let chain_id = match chain_id {
@@ -62,184 +98,1348 @@ pub async fn connect_rpc(
     };
     tracing::info!("Chain ID is {}", chain_id);
 
-    let mnemonic = match MnemonicBuilder::<English>::default()
-        .phrase(mnemonic)
-        .index(index)
-    {
-        Ok(mnemonic) => mnemonic,
-        Err(err) => {
-            tracing::error!("error building walletE: {}", err);
-            return None;
-        }
-    };
-    let wallet = match mnemonic.build() {
-        Ok(wallet) => wallet,
-        Err(err) => {
-            tracing::error!("error opening wallet: {}", err);
-            return None;
-        }
-    };
-    let wallet = wallet.with_chain_id(chain_id);
-    Some(SignerMiddleware::new(provider, wallet))
+    let signer = signer.with_chain_id(chain_id);
+    Some(SignerMiddleware::new(provider, signer))
 }
 
-type HotShotClient = surf_disco::Client<ClientError, SequencerApiVersion>;
-
 #[derive(Clone, Debug)]
 pub struct ExecutorOptions {
     pub sequencer_url: Url,
-    pub l1_http_provider: Url,
+    /// HTTP endpoints of the L1, shared with the rest of the node.
+    pub l1: ClientPool,
     pub l1_ws_provider: Url,
-    pub rollup_account_index: u32,
-    pub rollup_mnemonic: String,
+    /// Key used to sign proof submissions.
+    pub l1_signer: L1SignerConfig,
     pub light_client_address: Address,
+    /// If set, deposits to this escrow contract are credited to the rollup.
+    pub escrow_address: Option<Address>,
     pub rollup_address: Address,
     pub output_stream: Option<BroadcastSender<(u64, State)>>,
+    pub aggregation_strategy: AggregationStrategy,
+    /// If set, block proofs are streamed to an external aggregator instead of being aggregated
+    /// according to `aggregation_strategy`.
+    pub aggregator: Option<ExternalAggregator>,
+    /// Maximum number of blocks covered by a single batch proof when using
+    /// [`AggregationStrategy::Merged`].
+    pub max_batch_size: u64,
+    /// Clock used for retry delays.
+    pub clock: Arc<dyn Clock>,
+    pub finality_lag: FinalityLagTracker,
+    /// Execute blocks against a scratch copy of the state, reporting the resulting commitments
+    /// without updating the shared state or calling the rollup contract.
+    pub dry_run: bool,
+    /// Execute every block a second time with the reference implementation of transaction
+    /// execution, halting if the results differ.
+    pub self_check: bool,
+    /// Verify every header against the light client contract before executing it.
+    pub verify_headers: bool,
+    /// Ethereum client library used to submit proofs.
+    pub l1_client: L1ClientKind,
+    pub proof_shape: ProofShape,
+    /// Batch proofs awaiting submission. A persistent outbox ensures each batch is submitted
+    /// exactly once even if the node restarts.
+    pub outbox: Outbox,
+    /// Halts proof submission if execution looks anomalous.
+    pub breaker: CircuitBreaker,
+    /// Records when transactions are verified on the L1.
+    pub latency: LatencyTracker,
+    /// Clients for the HotShot query service.
+    pub http: HttpClientPool,
+    /// Flags blocks which take too long to execute.
+    pub watchdog: ExecutionWatchdog,
+    /// How long to retry namespace data which is unavailable, and what to do if it never arrives.
+    pub da_policy: DaTimeoutPolicy,
+    /// Number of historical headers fetched by each range query while catching up.
+    pub header_page_size: u64,
+    /// Where the progress of catching up with the sequencer is recorded.
+    pub catch_up: CatchUpTracker,
+    /// Decides which namespace proofs are verified, and records how long verification takes.
+    pub proof_verifier: ProofVerifier,
+    /// Where execution resumes, if the node was warm started or restored from a checkpoint.
+    pub resume: Resume,
+    /// If set, the state is checkpointed after each executed block.
+    pub checkpoints: Option<Checkpointer>,
+    /// Before following new blocks, replay the blocks verified by the rollup contract which have
+    /// not been executed, and check that the state matches the contract's.
+    pub recover_from_l1: bool,
+    /// If set, a copy of the state executes every block with another VM version, reporting any
+    /// divergence from this state.
+    pub canary: Option<Canary>,
+    /// If set, the executor stops between light client events when asked to, writing a warm-start
+    /// bundle.
+    pub warm_start: Option<WarmStartWriter>,
+    /// Counts executed blocks and transactions, and records the finalized and verified heights.
+    pub metrics: NodeMetrics,
+}
+
+/// The handles an executor uses to execute blocks, besides the state itself.
+#[derive(Clone, Copy)]
+pub(crate) struct ExecutorContext<'a> {
+    /// After each executed block, the new state is published here, unless this is a dry run.
+    pub output_stream: Option<&'a BroadcastSender<(u64, State)>>,
+    /// Execute blocks without publishing or checkpointing the results.
+    pub dry_run: bool,
+    /// Also execute each block with the reference implementation, panicking if the results differ,
+    /// so that a non-deterministic state transition is never published or proven.
+    pub self_check: bool,
+    /// Times the execution of each block, recording blocks over its budget without interrupting
+    /// them.
+    pub watchdog: &'a ExecutionWatchdog,
+    /// Decides which namespace proofs are verified.
+    pub verifier: &'a ProofVerifier,
+    /// After each executed block, the state and pending proofs are saved here, unless this is a
+    /// dry run.
+    pub checkpoints: Option<&'a Checkpointer>,
+    /// Executes each block after the state has, comparing its result with the new state.
+    pub canary: Option<&'a Canary>,
+    /// Decides whether a block whose rollup data never arrives halts the executor or is skipped.
+    pub da_policy: &'a DaTimeoutPolicy,
+    /// The clock on which unavailable rollup data is retried.
+    pub clock: &'a dyn Clock,
+    /// If set, the deposits from L1 blocks finalized as of each header are credited at the start
+    /// of the block, once they have all been collected.
+    pub deposits: Option<&'a DepositQueue>,
+    /// If set, each block is scheduled once for all the rollups run by
+    /// [`run_executors`], rather than by this executor alone.
+    pub shared_blocks: Option<&'a SharedBlocks>,
+}
+
+impl<'a> ExecutorContext<'a> {
+    /// The context configured by `opt`, without deposits.
+    pub(crate) fn new(opt: &'a ExecutorOptions) -> Self {
+        Self {
+            output_stream: opt.output_stream.as_ref(),
+            dry_run: opt.dry_run,
+            self_check: opt.self_check,
+            watchdog: &opt.watchdog,
+            verifier: &opt.proof_verifier,
+            checkpoints: opt.checkpoints.as_ref(),
+            canary: opt.canary.as_ref(),
+            da_policy: &opt.da_policy,
+            clock: opt.clock.as_ref(),
+            deposits: None,
+            shared_blocks: None,
+        }
+    }
+}
+
+/// Execute `headers` in order, accumulating the resulting proofs in `pending_proofs`.
+///
+/// Blocks which do not contain the rollup namespace are skipped. Each block is executed, checked,
+/// published and checkpointed as configured by `context`. Returns a summary of each executed
+/// block, for the circuit breaker.
+pub(crate) async fn execute_headers(
+    data_source: &dyn SequencerDataSource,
+    state: &RwLock<State>,
+    headers: Vec<Header>,
+    pending_proofs: &mut PendingProofs,
+    context: &ExecutorContext<'_>,
+) -> Vec<BlockProgress> {
+    let ExecutorContext {
+        output_stream,
+        dry_run,
+        self_check,
+        watchdog,
+        verifier,
+        checkpoints,
+        canary,
+        da_policy,
+        clock,
+        deposits,
+        shared_blocks,
+    } = *context;
+    let namespace_id: NamespaceId = state.read().await.vm.into();
+    let scheduler = BlockScheduler::new(data_source, vec![namespace_id])
+        .with_da_policy(da_policy.clone(), clock);
+    let mut progress = vec![];
+    for header in headers {
+        let block_height = header.height();
+        let scheduled = match shared_blocks {
+            Some(shared_blocks) => shared_blocks.schedule(&header, namespace_id).await,
+            None => scheduler.schedule(&header).await.pop(),
+        };
+        let Some(NamespaceBlock {
+            namespace_proof,
+            vid_common,
+            block_hash,
+            view_number,
+            ..
+        }) = scheduled
+        else {
+            pending_proofs.skip_block();
+            continue;
+        };
+
+        // Every node credits the deposits up to the L1 block finalized as of this header.
+        let l1_finalized = header.l1_finalized().map(|block| block.number);
+        let staged = match (deposits, l1_finalized) {
+            (Some(deposits), Some(l1_block)) => {
+                let credited = state.read().await.l1_deposit_block();
+                if l1_block > credited {
+                    deposits.wait_synced_through(l1_block, clock).await;
+                    Some((l1_block, deposits.take(credited, l1_block).await))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        let mut state = state.write().await;
+        if let Some((l1_block, deposits)) = &staged {
+            state.stage_deposits(*l1_block, deposits.clone());
+        }
+        let prev_state = self_check.then(|| state.clone());
+        // The canary executes the same header once the primary state has.
+        let canary_header = canary.is_some().then(|| header.clone());
+        let prev_accounts_root = state.accounts_root();
+        let prev_total_balance = state.total_balance();
+        let watch = watchdog.start(block_height);
+        let proof = state
+            .execute_block(
+                header,
+                Some(namespace_proof.clone()),
+                vid_common,
+                block_hash,
+                view_number,
+                verifier,
+            )
+            .await;
+        watchdog.finish(watch, state.block_results()).await;
+        if let Some(prev_state) = prev_state {
+            if let Err(err) =
+                prev_state.check_determinism(&state, block_height, &namespace_proof, block_hash)
+            {
+                panic!("Execution of block {block_height} is not deterministic: {err}");
+            }
+        }
+        if let (Some(canary), Some(header)) = (canary, &canary_header) {
+            canary
+                .execute(
+                    header,
+                    &namespace_proof,
+                    block_hash,
+                    staged.as_ref(),
+                    &state,
+                )
+                .await;
+        }
+        pending_proofs.push(proof);
+        let applied_transactions = state
+            .block_results()
+            .iter()
+            .filter(|(_, result)| result.is_ok())
+            .count();
+        progress.push(BlockProgress {
+            block_height,
+            applied_transactions,
+            rejected_transactions: state.block_results().len() - applied_transactions,
+            prev_accounts_root,
+            accounts_root: state.accounts_root(),
+            prev_total_balance,
+            total_balance: state.total_balance(),
+        });
+        if dry_run {
+            tracing::info!(
+                "Dry run: block {block_height} would produce state commitment {}",
+                state.commit()
+            );
+            continue;
+        }
+        if let Some(checkpoints) = checkpoints {
+            checkpoints.checkpoint(&state, block_height + 1, pending_proofs);
+        }
+        if let Some(stream) = output_stream {
+            stream.send_async((block_height, state.clone())).await.ok();
+        }
+    }
+    progress
+}
+
+/// Why several rollups cannot be run together by [`run_executors`].
+#[derive(Clone, Debug, PartialEq, Eq, Snafu)]
+pub enum RollupSetError {
+    #[snafu(display("Namespace {namespace} is executed by more than one rollup"))]
+    DuplicateNamespace { namespace: NamespaceId },
+    #[snafu(display("Rollup contract {rollup_address:?} is used by more than one rollup"))]
+    DuplicateContract { rollup_address: Address },
+    #[snafu(display(
+        "Rollups run together must share a sequencer, but {sequencer_url} is not {expected}"
+    ))]
+    DifferentSequencers { expected: Url, sequencer_url: Url },
+}
+
+/// Runs an executor for each of several rollups sharing a sequencer, in the same process.
+///
+/// Each rollup is given as the options of its executor and its state, whose VM selects the
+/// namespace it executes. The rollups follow one header stream from the sequencer, and each block
+/// is scheduled once for all of their namespaces (see [`SharedBlocks`]), under the DA policy of the
+/// first rollup. Otherwise, each rollup submits proofs to its own rollup contract through its own
+/// outbox. Returns once every executor has returned.
+///
+/// Returns an error, without running any executor, if the rollups do not share a sequencer, or if
+/// two of them execute the same namespace or submit to the same rollup contract, since their proofs
+/// would conflict.
+pub async fn run_executors(
+    rollups: Vec<(ExecutorOptions, Arc<RwLock<State>>)>,
+) -> Result<(), RollupSetError> {
+    let Some((first, _)) = rollups.first() else {
+        return Ok(());
+    };
+    let mut configs = vec![];
+    for (opt, state) in &rollups {
+        if opt.sequencer_url != first.sequencer_url {
+            return Err(RollupSetError::DifferentSequencers {
+                expected: first.sequencer_url.clone(),
+                sequencer_url: opt.sequencer_url.clone(),
+            });
+        }
+        configs.push((state.read().await.vm.into(), opt.rollup_address));
+    }
+    check_distinct_rollups(&configs)?;
+
+    let data_source = QueryServiceDataSource::connect(&first.sequencer_url, &first.http).await;
+    let shared_blocks = SharedBlocks::new(
+        Arc::new(data_source),
+        configs.iter().map(|(namespace, _)| *namespace).collect(),
+    )
+    .with_da_policy(first.da_policy.clone(), first.clock.clone());
+    join_all(rollups.into_iter().map(|(opt, state)| {
+        let shared_blocks = &shared_blocks;
+        async move { execute(&opt, state, shared_blocks, Some(shared_blocks)).await }
+    }))
+    .await;
+    Ok(())
+}
+
+/// Check that no two of `rollups`, given as their namespace and rollup contract, execute the same
+/// namespace or submit to the same rollup contract.
+fn check_distinct_rollups(rollups: &[(NamespaceId, Address)]) -> Result<(), RollupSetError> {
+    let mut namespaces = HashSet::new();
+    let mut contracts = HashSet::new();
+    for (namespace, rollup_address) in rollups {
+        if !namespaces.insert(namespace) {
+            return Err(RollupSetError::DuplicateNamespace {
+                namespace: *namespace,
+            });
+        }
+        if !contracts.insert(rollup_address) {
+            return Err(RollupSetError::DuplicateContract {
+                rollup_address: *rollup_address,
+            });
+        }
+    }
+    Ok(())
 }
 
 /// Runs the executor service, which is responsible for:
 /// 1) Fetching blocks of ordered transactions from HotShot and applying them to the Rollup State.
 /// 2) Submitting mock proofs to the Rollup Contract.
 pub async fn run_executor(opt: &ExecutorOptions, state: Arc<RwLock<State>>) {
-    let ExecutorOptions {
-        rollup_account_index,
-        sequencer_url,
-        l1_http_provider,
-        l1_ws_provider,
-        light_client_address,
-        rollup_address,
-        rollup_mnemonic,
-        output_stream,
-    } = opt;
+    let data_source = QueryServiceDataSource::connect(&opt.sequencer_url, &opt.http).await;
+    run_executor_with_data_source(opt, state, &data_source).await
+}
 
-    let query_service_url = sequencer_url.join("availability").unwrap();
-    let hotshot = HotShotClient::new(query_service_url.clone());
+/// Runs the executor service, reading HotShot blocks from `data_source`.
+pub async fn run_executor_with_data_source(
+    opt: &ExecutorOptions,
+    state: Arc<RwLock<State>>,
+    data_source: &dyn SequencerDataSource,
+) {
+    execute(opt, state, data_source, None).await
+}
+
+/// Runs the executor service, reading HotShot blocks from `data_source` and scheduling them with
+/// `shared_blocks`, if given.
+async fn execute(
+    opt: &ExecutorOptions,
+    state: Arc<RwLock<State>>,
+    data_source: &dyn SequencerDataSource,
+    shared_blocks: Option<&SharedBlocks>,
+) {
+    // Connect to the layer one rollup contract.
+    let rollup = connect_l1_client(opt.l1_client, &opt.l1, &opt.l1_signer, opt.rollup_address)
+        .await
+        .expect("unable to connect to L1, hotshot commitment task exiting");
 
-    hotshot.connect(None).await;
+    // Follow light client updates in the background. This assumes that the L1 node supports both
+    // HTTP and Websocket connections, but falls back to HTTP polling while the websocket is down.
+    let (events_sender, commits_stream) = channel::unbounded();
+    spawn(follow_light_client(
+        opt.l1_ws_provider.clone(),
+        opt.l1.clone(),
+        opt.light_client_address,
+        opt.clock.clone(),
+        events_sender,
+    ));
 
-    // Connect to the layer one HotShot contract.
-    let l1 = connect_rpc(
-        l1_http_provider,
-        rollup_mnemonic,
-        *rollup_account_index,
-        None,
-        None,
+    execute_light_client_events(
+        opt,
+        state,
+        data_source,
+        shared_blocks,
+        rollup.as_ref(),
+        commits_stream,
     )
     .await
-    .expect("unable to connect to L1, hotshot commitment task exiting");
+}
 
-    // Create a socket connection to the L1 to subscribe to contract events
-    // This assumes that the L1 node supports both HTTP and Websocket connections
-    let socket_provider = Provider::<Ws>::connect(l1_ws_provider)
-        .await
-        .expect("Unable to make websocket connection to L1");
+/// Execute the blocks finalized by each light client event in `commits_stream`, submitting their
+/// proofs through `rollup`.
+///
+/// In dry-run mode, blocks are executed against a copy of `state` and `rollup` is never used.
+/// Returns once `commits_stream` ends.
+async fn execute_light_client_events(
+    opt: &ExecutorOptions,
+    state: Arc<RwLock<State>>,
+    data_source: &dyn SequencerDataSource,
+    shared_blocks: Option<&SharedBlocks>,
+    rollup: &dyn L1Client,
+    mut commits_stream: channel::Receiver<LightClientEvent>,
+) {
+    let ExecutorOptions {
+        sequencer_url,
+        l1,
+        l1_ws_provider,
+        light_client_address,
+        escrow_address,
+        aggregation_strategy,
+        aggregator,
+        max_batch_size,
+        clock,
+        finality_lag,
+        dry_run,
+        verify_headers,
+        proof_shape,
+        outbox,
+        breaker,
+        latency,
+        http,
+        header_page_size,
+        catch_up,
+        resume,
+        checkpoints,
+        recover_from_l1: recover,
+        canary,
+        warm_start,
+        metrics,
+        // The handles used to execute blocks are collected in an `ExecutorContext`.
+        ..
+    } = opt;
 
-    let rollup_contract = ExampleRollup::new(*rollup_address, Arc::new(l1));
-    let light_client = LightClient::new(*light_client_address, Arc::new(socket_provider));
+    // In dry-run mode the shared state is never touched, so the API and any other readers continue
+    // to see the state as it was when the executor started.
+    let state = if *dry_run {
+        tracing::info!("Executor running in dry-run mode, no proofs will be submitted");
+        Arc::new(RwLock::new(state.read().await.clone()))
+    } else {
+        state
+    };
 
-    let filter = light_client.new_state_filter().from_block(0);
+    let header_verifier = verify_headers
+        .then(|| HeaderVerifier::new(l1, *light_client_address, sequencer_url, http.clone()));
 
-    let mut commits_stream = filter
-        .subscribe()
-        .await
-        .expect("Unable to subscribe to L1 log stream");
+    // Catch up on history with range queries, then follow the live header stream.
+    let header_fetcher = HeaderFetcher::new(data_source)
+        .with_page_size(*header_page_size)
+        .with_progress(catch_up.clone())
+        .with_retries(DEFAULT_MAX_ATTEMPTS, clock.as_ref());
+    let mut resume = resume.clone();
 
-    let mut header_stream = hotshot
-        .socket("stream/headers/0")
-        .subscribe::<Header>()
+    // Collect deposits in the background, from the first L1 block which has not been credited.
+    let deposits = match escrow_address {
+        Some(escrow_address) => {
+            let deposits = DepositQueue::new(state.read().await.l1_deposit_block());
+            spawn(follow_deposits(
+                l1_ws_provider.clone(),
+                *escrow_address,
+                clock.clone(),
+                deposits.clone(),
+            ));
+            Some(deposits)
+        }
+        None => None,
+    };
+    // Recovery reads the rollup contract, so it is never combined with a dry run.
+    if *recover && !*dry_run {
+        if let Err(err) = recover_from_l1(
+            opt,
+            data_source,
+            rollup,
+            &state,
+            &mut resume,
+            deposits.as_ref(),
+        )
         .await
-        .expect("Unable to subscribe to HotShot block header stream");
-    let namespace_id: NamespaceId = state.read().await.vm.into();
+        {
+            panic!("Unable to recover the state verified by the rollup contract: {err}");
+        }
+    }
+    if resume.next_block > 0 {
+        tracing::info!("Resuming execution at block {}", resume.next_block);
+    }
+    if let Some(canary) = canary {
+        canary.start(&*state.read().await).await;
+    }
+    let mut header_stream = header_fetcher.headers(resume.next_block);
+    let context = ExecutorContext {
+        deposits: deposits.as_ref(),
+        shared_blocks,
+        ..ExecutorContext::new(opt)
+    };
 
-    while let Some(event) = commits_stream.next().await {
-        tracing::info!(" new state event received {:?}", event);
-        let (_view_num, block_height, _block_comm_root) = match event {
-            Ok(NewStateFilter {
-                view_num: _view_num,
-                block_height,
-                block_comm_root: _block_comm_root,
-            }) => (_view_num, block_height, _block_comm_root),
-            Err(err) => {
-                tracing::error!("Error in Light client  stream, retrying: {err}");
-                continue;
+    loop {
+        // Only stop between events, when the state, the pending proofs and the next block agree.
+        let event = match warm_start {
+            Some(writer) => {
+                match select(
+                    commits_stream.next().boxed(),
+                    writer.shutdown.recv().boxed(),
+                )
+                .await
+                {
+                    Either::Left((event, _)) => event,
+                    Either::Right(_) => {
+                        let state = state.read().await;
+                        match writer.write(&state, &resume).await {
+                            Ok(()) => tracing::info!(
+                                "Wrote warm-start bundle to {}, resume at block {}",
+                                writer.path.display(),
+                                resume.next_block
+                            ),
+                            Err(err) => tracing::error!("Unable to write warm-start bundle: {err}"),
+                        }
+                        return;
+                    }
+                }
             }
+            None => commits_stream.next().await,
         };
+        let Some(event) = event else {
+            break;
+        };
+        tracing::info!(" new state event received {:?}", event);
+        let LightClientEvent {
+            event: NewStateFilter { block_height, .. },
+            l1_timestamp,
+        } = event;
+        metrics.set_hotshot_height(block_height);
 
         // Full block content may not be available immediately so wait for all blocks to be ready
         // before building the batch proof
         let headers: Vec<Header> = header_stream
             .by_ref()
-            .take(block_height as usize)
-            .map(|result| result.expect("Error fetching block header"))
+            .take(block_height.saturating_sub(resume.next_block) as usize)
             .collect()
             .await;
+        resume.next_block += headers.len() as u64;
+
+        match l1_timestamp {
+            Some(l1_timestamp) => {
+                for header in &headers {
+                    finality_lag
+                        .record(FinalityLagSample {
+                            block_height: header.height(),
+                            hotshot_timestamp: header.timestamp(),
+                            l1_timestamp,
+                        })
+                        .await;
+                }
+            }
+            None => tracing::warn!(
+                "L1 timestamp of light client update {block_height} is unknown, not recording the \
+                 finality lag of its blocks"
+            ),
+        }
+
+        // Never execute a block which the light client has not finalized.
+        if let Some(verifier) = &header_verifier {
+            if let Err(err) = verifier.verify_headers(&headers).await {
+                panic!("Refusing to execute unverified headers: {err}");
+            }
+        }
 
         // Execute new blocks, generating proofs.
-        let mut proofs = vec![];
-
-        for header in headers.clone().into_iter() {
-            let namespace_proof_query: Result<NamespaceProofQueryData, ClientError> = hotshot
-                .get::<NamespaceProofQueryData>(&format!(
-                    "block/{}/namespace/{}",
-                    header.height(),
-                    namespace_id
-                ))
-                .send()
-                .await;
+        let progress = execute_headers(
+            data_source,
+            &state,
+            headers,
+            &mut resume.pending_proofs,
+            &context,
+        )
+        .await;
+        for block in &progress {
+            breaker.observe(block, block_height).await;
+            if !*dry_run {
+                metrics.record_block(block.applied_transactions, block.rejected_transactions);
+            }
+        }
+        if let Some(trip) = breaker.tripped().await {
+            tracing::error!(
+                "Not submitting proofs: circuit breaker tripped at block {}: {}",
+                trip.block_height,
+                trip.reason
+            );
+            continue;
+        }
 
-            if namespace_proof_query.is_err() {
+        // Compute aggregate proofs according to the configured strategy, unless an external
+        // aggregator returns them through the API.
+        let batches = match aggregator.as_ref().filter(|_| !*dry_run) {
+            Some(aggregator) => {
+                aggregator.stream(resume.pending_proofs.take_proofs()).await;
+                vec![]
+            }
+            None => resume
+                .pending_proofs
+                .take_batches(*aggregation_strategy, *max_batch_size)
+                .expect("Error generating batch proof"),
+        };
+        for (proof, count) in batches {
+            if *dry_run {
+                tracing::info!("Dry run: skipping submission of proof for {count} blocks");
                 continue;
             }
+            tracing::info!(
+                "Enqueuing proof for {count} blocks (aggregation: {aggregation_strategy})"
+            );
+            outbox
+                .enqueue(proof.into(), count, *proof_shape)
+                .await
+                .expect("unable to record batch proof in outbox");
+        }
+        if *dry_run {
+            continue;
+        }
+        // The proofs taken above are now in the outbox, so a restored node must not prove them
+        // again. If it does, because the node stopped before this checkpoint, the outbox ignores
+        // batches it already holds.
+        if let Some(checkpoints) = checkpoints {
+            checkpoints.checkpoint(
+                &*state.read().await,
+                resume.next_block,
+                &resume.pending_proofs,
+            );
+        }
+        outbox.submit_pending(rollup, clock.as_ref()).await;
+        match rollup.num_verified_blocks().await {
+            Ok(verified) => metrics.set_verified_height(verified),
+            Err(err) => tracing::warn!("Unable to read the number of verified blocks: {err}"),
+        }
+        if let Some(last_block) = outbox.latest_confirmed().await {
+            latency.record_verified(last_block, unix_millis()).await;
+        }
+    }
+}
 
-            let namespace_proof = namespace_proof_query.unwrap().proof;
-            if namespace_proof.is_none() {
-                continue;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{SystemClock, VirtualClock};
+    use crate::data_source::{MockBlock, MockDataSource};
+    use crate::error::RollupError;
+    use crate::fixtures::{adversarial_payloads, mock_block};
+    use crate::l1::{BatchProofInput, L1Error, StateUpdate};
+    use crate::receipt::Receipt;
+    use crate::secret::Secret;
+    use crate::state::{SubmissionPolicy, UntrustedSubmissions};
+    use crate::storage::{MemoryStorage, Storage};
+    use crate::transaction::{OperatorEnvelope, SignedTransaction, Transaction};
+    use crate::RollupVM;
+    use async_compatibility_layer::async_primitives::broadcast;
+    use espresso_types::{NodeState, NsProof, Payload, SeqTypes};
+    use futures::future::BoxFuture;
+    use futures::stream::BoxStream;
+    use hotshot_query_service::availability::BlockHash;
+    use hotshot_query_service::VidCommon;
+    use hotshot_types::data::vid_commitment;
+    use hotshot_types::traits::block_contents::{BlockHeader, BlockPayload, EncodeBytes};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn empty_block() -> MockBlock {
+        let (payload, ns_table) = <Payload as BlockPayload<SeqTypes>>::empty();
+        let payload_commitment = vid_commitment(&payload.encode().to_vec(), 1);
+        let header = Header::genesis(
+            &NodeState::mock(),
+            payload_commitment,
+            payload.builder_commitment(&ns_table),
+            ns_table,
+        );
+        MockBlock {
+            header,
+            namespace_proof: None,
+            vid_common: None,
+            view_number: None,
+        }
+    }
+
+    fn context<'a>(
+        watchdog: &'a ExecutionWatchdog,
+        verifier: &'a ProofVerifier,
+        da_policy: &'a DaTimeoutPolicy,
+    ) -> ExecutorContext<'a> {
+        ExecutorContext {
+            output_stream: None,
+            dry_run: false,
+            self_check: true,
+            watchdog,
+            verifier,
+            checkpoints: None,
+            canary: None,
+            da_policy,
+            clock: &SystemClock,
+            deposits: None,
+            shared_blocks: None,
+        }
+    }
+
+    #[async_std::test]
+    async fn test_execute_headers_skips_blocks_without_namespace() {
+        let data_source = MockDataSource::default();
+        for _ in 0..3 {
+            data_source.push(empty_block());
+        }
+        let headers: Vec<Header> = data_source.subscribe_headers(0).await.collect().await;
+        assert_eq!(headers.len(), 3);
+
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let state = RwLock::new(State::from_initial_balances([], vm));
+        let initial_commitment = state.read().await.commit();
+        let (output_stream, _) = broadcast::channel();
+        let mut updates = output_stream.handle_async().await;
+        let mut pending_proofs = PendingProofs::default();
+
+        let (watchdog, verifier, da_policy) = Default::default();
+        execute_headers(
+            &data_source,
+            &state,
+            headers,
+            &mut pending_proofs,
+            &ExecutorContext {
+                output_stream: Some(&output_stream),
+                ..context(&watchdog, &verifier, &da_policy)
+            },
+        )
+        .await;
+
+        // None of the blocks contain the rollup namespace, so nothing is executed or published.
+        assert_eq!(state.read().await.commit(), initial_commitment);
+        assert_eq!(pending_proofs.num_blocks(), 0);
+        assert!(updates.try_recv().is_none());
+        assert!(pending_proofs
+            .take_batches(AggregationStrategy::PerBlock, 1)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_execute_adversarial_payloads() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let payloads = adversarial_payloads(&alice, bob, 1).await;
+
+        // A valid transaction in another rollup's namespace must not be executed by this rollup.
+        let foreign = SignedTransaction::new(
+            Transaction {
+                amount: 1,
+                destination: bob,
+                nonce: 2,
+                ..Default::default()
+            },
+            &alice,
+        )
+        .await;
+        let data_source = MockDataSource::default();
+        let block = mock_block(
+            vm.into(),
+            &[
+                (
+                    vm.into(),
+                    payloads
+                        .iter()
+                        .map(|payload| payload.bytes.clone())
+                        .collect(),
+                ),
+                (NamespaceId::from(2_u64), vec![foreign.encode()]),
+            ],
+        )
+        .await;
+        let timestamp = block.header.timestamp();
+        data_source.push(MockBlock {
+            view_number: Some(42),
+            ..block
+        });
+        let headers: Vec<Header> = data_source.subscribe_headers(0).await.collect().await;
+
+        let genesis = State::from_initial_balances([(alice.address(), 100)], vm);
+        let storage = MemoryStorage::default();
+        let checkpoints = Checkpointer::new(Arc::new(storage.clone()), genesis.commit());
+        let state = RwLock::new(genesis.clone());
+        let mut pending_proofs = PendingProofs::default();
+        let (watchdog, verifier, da_policy) = Default::default();
+        let progress = execute_headers(
+            &data_source,
+            &state,
+            headers,
+            &mut pending_proofs,
+            &ExecutorContext {
+                checkpoints: Some(&checkpoints),
+                ..context(&watchdog, &verifier, &da_policy)
+            },
+        )
+        .await;
+        assert_eq!(pending_proofs.num_blocks(), 1);
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0].applied_transactions, 1);
+        assert_eq!(progress[0].total_balance, progress[0].prev_total_balance);
+
+        // Only the valid transaction was applied.
+        let state = state.read().await;
+        assert_eq!(state.get_balance(&alice.address()), 90);
+        assert_eq!(state.get_balance(&bob), 10);
+        assert_eq!(state.get_nonce(&alice.address()), 1);
+
+        // The block was checkpointed, along with its proof.
+        let (restored, resume) = storage.load().unwrap().unwrap().restore(&genesis).unwrap();
+        assert_eq!(restored.commit(), state.commit());
+        assert_eq!(resume.next_block, 1);
+        assert_eq!(resume.pending_proofs.num_blocks(), 1);
+
+        // Every payload in the namespace has a receipt with the precise reason it failed.
+        assert_eq!(state.block_results().len(), payloads.len());
+        for (payload, (hash, result)) in payloads.iter().zip(state.block_results()) {
+            assert_eq!(*hash, payload.hash, "{:?}", payload.case);
+            assert_eq!(*result, payload.expected, "{:?}", payload.case);
+        }
+        let receipts = Receipt::for_block(&state);
+        assert_eq!(receipts.len(), payloads.len());
+        assert!(receipts
+            .iter()
+            .all(|receipt| receipt.hash != foreign.hash()));
+
+        // Receipts record the view and timestamp of the block.
+        assert!(
+            receipts
+                .iter()
+                .all(|receipt| receipt.view_number == Some(42)
+                    && receipt.block_timestamp == timestamp)
+        );
+    }
+
+    #[async_std::test]
+    async fn test_execute_untrusted_submissions() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let operator = LocalWallet::new(&mut rng);
+        let impostor = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let transfer = |nonce| {
+            SignedTransaction::new(
+                Transaction {
+                    amount: 10,
+                    destination: bob,
+                    nonce,
+                    ..Default::default()
+                },
+                &alice,
+            )
+        };
+
+        // A countersigned transaction, followed by untrusted transactions from the same sender,
+        // one of which is countersigned by someone other than the operator.
+        let payloads = vec![
+            OperatorEnvelope::new(transfer(1).await, &operator)
+                .await
+                .encode(),
+            transfer(2).await.encode(),
+            OperatorEnvelope::new(transfer(3).await, &impostor)
+                .await
+                .encode(),
+        ];
+        let data_source = MockDataSource::default();
+        data_source.push(mock_block(vm.into(), &[(vm.into(), payloads)]).await);
+        let headers: Vec<Header> = data_source.subscribe_headers(0).await.collect().await;
+
+        let (watchdog, verifier, da_policy) = Default::default();
+        for (untrusted, expected_balance) in [
+            (UntrustedSubmissions::Strict, 80),
+            (UntrustedSubmissions::Reject, 90),
+        ] {
+            let policy = SubmissionPolicy {
+                operator: Some(operator.address()),
+                untrusted,
+                ..Default::default()
+            };
+            let state = RwLock::new(
+                State::from_initial_balances([(alice.address(), 100)], vm)
+                    .with_submission_policy(policy),
+            );
+            execute_headers(
+                &data_source,
+                &state,
+                headers.clone(),
+                &mut PendingProofs::default(),
+                &context(&watchdog, &verifier, &da_policy),
+            )
+            .await;
+
+            let state = state.read().await;
+            assert_eq!(state.get_balance(&alice.address()), expected_balance);
+            let results: Vec<_> = state
+                .block_results()
+                .iter()
+                .map(|(_, result)| result.clone())
+                .collect();
+            assert_eq!(results[0], Ok(()));
+            assert!(matches!(
+                results[2],
+                Err(RollupError::UntrustedSubmission { .. })
+            ));
+            match untrusted {
+                UntrustedSubmissions::Strict => assert_eq!(results[1], Ok(())),
+                UntrustedSubmissions::Reject => assert!(matches!(
+                    results[1],
+                    Err(RollupError::UntrustedSubmission { .. })
+                )),
             }
+        }
+    }
+
+    #[async_std::test]
+    async fn test_execute_multiple_rollups() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let transfer = |amount| {
+            SignedTransaction::new(
+                Transaction {
+                    amount,
+                    destination: bob,
+                    nonce: 1,
+                    ..Default::default()
+                },
+                &alice,
+            )
+        };
+
+        // One sequencer block carrying a transaction for each of two rollups.
+        let rollups = [
+            (RollupVM::new(NamespaceId::from(1_u64)), 10),
+            (RollupVM::new(NamespaceId::from(7_u64)), 30),
+        ];
+        let mut payloads = vec![];
+        for (vm, amount) in rollups {
+            payloads.push((vm.into(), vec![transfer(amount).await.encode()]));
+        }
+
+        // Each rollup follows the same headers, executing only the transactions in its namespace.
+        let (watchdog, verifier, da_policy) = Default::default();
+        let mut headers = vec![];
+        for (vm, amount) in rollups {
+            let data_source = MockDataSource::default();
+            data_source.push(mock_block(vm.into(), &payloads).await);
+            let rollup_headers: Vec<Header> =
+                data_source.subscribe_headers(0).await.collect().await;
+            headers.push(rollup_headers.clone());
+
+            let state = RwLock::new(State::from_initial_balances([(alice.address(), 100)], vm));
+            execute_headers(
+                &data_source,
+                &state,
+                rollup_headers,
+                &mut PendingProofs::default(),
+                &context(&watchdog, &verifier, &da_policy),
+            )
+            .await;
+            let state = state.read().await;
+            assert_eq!(state.block_results().len(), 1);
+            assert_eq!(state.get_balance(&bob), amount);
+        }
+        assert_eq!(headers[0], headers[1]);
+
+        // Rollups may not share a namespace or a rollup contract.
+        let (first, second) = (NamespaceId::from(1_u64), NamespaceId::from(7_u64));
+        let (contract, other_contract) = (Address::random(), Address::random());
+        check_distinct_rollups(&[(first, contract), (second, other_contract)]).unwrap();
+        assert_eq!(
+            check_distinct_rollups(&[(first, contract), (first, other_contract)]),
+            Err(RollupSetError::DuplicateNamespace { namespace: first })
+        );
+        assert_eq!(
+            check_distinct_rollups(&[(first, contract), (second, contract)]),
+            Err(RollupSetError::DuplicateContract {
+                rollup_address: contract
+            })
+        );
+    }
+
+    #[async_std::test]
+    async fn test_api_snapshot_changes_at_block_boundaries() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let mut payloads = vec![];
+        for nonce in 1..=2 {
+            let transfer = Transaction {
+                amount: 10,
+                destination: bob,
+                nonce,
+                ..Default::default()
+            };
+            payloads.push(SignedTransaction::new(transfer, &alice).await.encode());
+        }
+
+        // The block's namespace data is not available at first, so the executor waits for it.
+        let block = mock_block(vm.into(), &[(vm.into(), payloads)]).await;
+        let namespace_proof = block.namespace_proof.clone().unwrap();
+        let data_source = MockDataSource::default();
+        data_source.push(MockBlock {
+            namespace_proof: None,
+            ..block
+        });
+        let headers: Vec<Header> = data_source.subscribe_headers(0).await.collect().await;
+
+        // The API follows the states published by the executor, starting from genesis.
+        let genesis = State::from_initial_balances([(alice.address(), 100)], vm);
+        let state = Arc::new(RwLock::new(genesis.clone()));
+        let snapshot = Arc::new(RwLock::new(genesis.clone()));
+        let (output_stream, _) = broadcast::channel();
+        let mut updates = output_stream.handle_async().await;
+        spawn(crate::api::follow_executor(
+            output_stream.handle_async().await,
+            snapshot.clone(),
+            Default::default(),
+        ));
 
-            let vid_common = hotshot
-                .get::<VidCommonQueryData<SeqTypes>>(&format!("vid/common/{}", header.height()))
-                .send()
+        let clock = VirtualClock::default();
+        let executor = spawn({
+            let (data_source, state, clock) = (data_source.clone(), state.clone(), clock.clone());
+            async move {
+                let (watchdog, verifier, da_policy) = Default::default();
+                execute_headers(
+                    &data_source,
+                    &state,
+                    headers,
+                    &mut PendingProofs::default(),
+                    &ExecutorContext {
+                        output_stream: Some(&output_stream),
+                        clock: &clock,
+                        ..context(&watchdog, &verifier, &da_policy)
+                    },
+                )
                 .await
-                .unwrap();
+            }
+        });
+
+        // While the block is pending, the API still serves the state before it.
+        while clock.num_sleepers() == 0 {
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(snapshot.read().await.commit(), genesis.commit());
+
+        // Once the block is executed, it is published whole, and the API moves straight to it.
+        data_source.push_namespace_proof(0, vm.into(), namespace_proof);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(executor.await.len(), 1);
+        let (block_height, published) = updates.recv_async().await.unwrap();
+        assert_eq!(block_height, 0);
+        assert_eq!(published.commit(), state.read().await.commit());
+        assert_eq!(published.get_balance(&bob), 20);
+        assert!(updates.try_recv().is_none());
+        while snapshot.read().await.commit() == genesis.commit() {
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(snapshot.read().await.commit(), published.commit());
+    }
+
+    /// An L1 which fails every request made of it, counting them.
+    #[derive(Debug, Default)]
+    struct UnreachableL1 {
+        requests: AtomicUsize,
+    }
+
+    impl UnreachableL1 {
+        fn request<T: Send + 'static>(&self) -> BoxFuture<'_, Result<T, L1Error>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            async {
+                Err(L1Error::Connection {
+                    message: "L1 unreachable".into(),
+                })
+            }
+            .boxed()
+        }
+    }
+
+    impl L1Client for UnreachableL1 {
+        fn next_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            self.request()
+        }
+
+        fn confirmed_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            self.request()
+        }
+
+        fn send_verify_blocks(
+            &self,
+            _count: u64,
+            _proof: BatchProofInput,
+            _shape: ProofShape,
+            _nonce: u64,
+        ) -> BoxFuture<'_, Result<H256, L1Error>> {
+            self.request()
+        }
+
+        fn transaction_status(&self, _hash: H256) -> BoxFuture<'_, Result<Option<bool>, L1Error>> {
+            self.request()
+        }
+
+        fn state_commitment(&self) -> BoxFuture<'_, Result<[u8; 32], L1Error>> {
+            self.request()
+        }
+
+        fn num_verified_blocks(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            self.request()
+        }
+
+        fn last_state_update(&self) -> BoxFuture<'_, Result<Option<StateUpdate>, L1Error>> {
+            self.request()
+        }
+    }
+
+    /// Options for an executor which runs on `clock`, and whose L1 endpoints are never reached.
+    fn executor_options(clock: &VirtualClock, dry_run: bool) -> ExecutorOptions {
+        let url: Url = "http://localhost:1".parse().unwrap();
+        ExecutorOptions {
+            sequencer_url: url.clone(),
+            l1: ClientPool::new(&url, []).unwrap(),
+            l1_ws_provider: "ws://localhost:1".parse().unwrap(),
+            l1_signer: L1SignerConfig::Mnemonic {
+                mnemonic: Secret::from(
+                    "test test test test test test test test test test test junk".to_string(),
+                ),
+                account_index: 0,
+            },
+            light_client_address: Address::random(),
+            escrow_address: None,
+            rollup_address: Address::random(),
+            output_stream: None,
+            aggregation_strategy: Default::default(),
+            aggregator: None,
+            max_batch_size: 10,
+            clock: Arc::new(clock.clone()),
+            finality_lag: Default::default(),
+            dry_run,
+            self_check: false,
+            verify_headers: false,
+            l1_client: Default::default(),
+            proof_shape: Default::default(),
+            outbox: Default::default(),
+            breaker: Default::default(),
+            latency: Default::default(),
+            http: Default::default(),
+            watchdog: Default::default(),
+            da_policy: Default::default(),
+            header_page_size: 10,
+            catch_up: Default::default(),
+            proof_verifier: Default::default(),
+            resume: Default::default(),
+            checkpoints: None,
+            recover_from_l1: false,
+            canary: None,
+            warm_start: None,
+            metrics: Default::default(),
+        }
+    }
+
+    #[async_std::test]
+    async fn test_dry_run_never_calls_l1() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let transfer = SignedTransaction::new(
+            Transaction {
+                amount: 10,
+                destination: bob,
+                nonce: 1,
+                ..Default::default()
+            },
+            &alice,
+        )
+        .await;
+        let data_source = MockDataSource::default();
+        data_source.push(mock_block(vm.into(), &[(vm.into(), vec![transfer.encode()])]).await);
+        let genesis = State::from_initial_balances([(alice.address(), 100)], vm);
 
-            let block_hash: PayloadQueryData<SeqTypes> = hotshot
-                .get(&format!("payload/{}", header.height()))
-                .send()
+        for dry_run in [true, false] {
+            let clock = VirtualClock::default();
+            let opt = executor_options(&clock, dry_run);
+            let state = Arc::new(RwLock::new(genesis.clone()));
+            let rollup = UnreachableL1::default();
+
+            // The light client finalizes the block, then its event stream ends.
+            let (events, commits_stream) = channel::unbounded();
+            events
+                .send(LightClientEvent {
+                    event: NewStateFilter {
+                        block_height: 1,
+                        ..Default::default()
+                    },
+                    l1_timestamp: None,
+                })
                 .await
                 .unwrap();
+            drop(events);
+            let mut executor = execute_light_client_events(
+                &opt,
+                state.clone(),
+                &data_source,
+                None,
+                &rollup,
+                commits_stream,
+            )
+            .boxed_local();
 
-            let mut state = state.write().await;
-            proofs.push(
-                state
-                    .execute_block(
-                        header,
-                        namespace_proof,
-                        vid_common.common().clone(),
-                        block_hash.block_hash(),
-                    )
-                    .await,
-            );
-            if let Some(stream) = &output_stream {
-                stream.send_async((block_height, state.clone())).await.ok();
+            // Time only passes while the executor is waiting on the clock.
+            let mut waits = 0;
+            while (&mut executor).now_or_never().is_none() {
+                if clock.num_sleepers() > 0 {
+                    clock.advance(Duration::from_secs(1));
+                    waits += 1;
+                }
+                async_std::task::sleep(Duration::from_millis(1)).await;
+            }
+
+            let requests = rollup.requests.load(Ordering::SeqCst);
+            let state = state.read().await;
+            if dry_run {
+                // The block is executed without contacting, or waiting on, the L1, and the shared
+                // state is left as it was.
+                assert_eq!(requests, 0);
+                assert_eq!(waits, 0);
+                assert_eq!(state.commit(), genesis.commit());
+            } else {
+                // The same block executed for real updates the shared state, and its proof is
+                // submitted, retrying on the clock while the L1 is down.
+                assert!(requests > 0);
+                assert!(waits > 0);
+                assert_eq!(state.get_balance(&bob), 10);
             }
         }
+    }
 
-        // Compute an aggregate proof.
-        if proofs.is_empty() {
-            continue;
+    /// A data source which counts the header subscriptions and block data fetches made of it.
+    #[derive(Debug, Default)]
+    struct CountingDataSource {
+        inner: MockDataSource,
+        subscriptions: AtomicUsize,
+        vid_common_fetches: AtomicUsize,
+    }
+
+    impl SequencerDataSource for CountingDataSource {
+        fn subscribe_headers(&self, from: u64) -> BoxFuture<'_, BoxStream<'static, Header>> {
+            self.subscriptions.fetch_add(1, Ordering::SeqCst);
+            self.inner.subscribe_headers(from)
+        }
+
+        fn headers(&self, from: u64, until: u64) -> BoxFuture<'_, Option<Vec<Header>>> {
+            self.inner.headers(from, until)
+        }
+
+        fn block_height(&self) -> BoxFuture<'_, Option<u64>> {
+            self.inner.block_height()
+        }
+
+        fn namespace_proof(
+            &self,
+            height: u64,
+            namespace: NamespaceId,
+        ) -> BoxFuture<'_, Option<NsProof>> {
+            self.inner.namespace_proof(height, namespace)
+        }
+
+        fn vid_common(&self, height: u64) -> BoxFuture<'_, Option<VidCommon>> {
+            self.vid_common_fetches.fetch_add(1, Ordering::SeqCst);
+            self.inner.vid_common(height)
+        }
+
+        fn block_hash(&self, height: u64) -> BoxFuture<'_, BlockHash<SeqTypes>> {
+            self.inner.block_hash(height)
         }
-        let proof = BatchProof::generate(&proofs).expect("Error generating batch proof");
-        let state_comm = commitment_to_u256(state.read().await.commit());
 
-        let proof = example_rollup::BatchProof::from(proof);
-        let call =
-            rollup_contract.verify_blocks(headers.len().try_into().unwrap(), state_comm, proof);
-        let res = contract_send::<_, _, ExampleRollupErrors>(&call).await;
-        if let Err(err) = res {
-            tracing::warn!("Failed to submit proof to contract, retrying: {err}");
-            sleep(Duration::from_secs(1)).await;
-        } else {
-            tracing::info!("Proof submitted successfully");
+        fn view_number(&self, height: u64) -> BoxFuture<'_, Option<u64>> {
+            self.inner.view_number(height)
         }
     }
+
+    #[async_std::test]
+    async fn test_execute_shared_blocks() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let rollups = [
+            (RollupVM::new(NamespaceId::from(1_u64)), 10),
+            (RollupVM::new(NamespaceId::from(7_u64)), 30),
+        ];
+        let mut payloads = vec![];
+        for (vm, amount) in rollups {
+            let transfer = SignedTransaction::new(
+                Transaction {
+                    amount,
+                    destination: bob,
+                    nonce: 1,
+                    ..Default::default()
+                },
+                &alice,
+            )
+            .await;
+            payloads.push((vm.into(), vec![transfer.encode()]));
+        }
+
+        // One sequencer block carrying a transaction for each rollup, with a proof for each
+        // namespace.
+        let data_source = Arc::new(CountingDataSource::default());
+        let (first, second) = (rollups[0].0.into(), rollups[1].0.into());
+        data_source.inner.push(mock_block(first, &payloads).await);
+        let proof = mock_block(second, &payloads).await.namespace_proof.unwrap();
+        data_source.inner.push_namespace_proof(0, second, proof);
+        let shared_blocks = SharedBlocks::new(data_source.clone(), vec![first, second]);
+
+        // Both rollups execute the block concurrently, each taking its own namespace.
+        let (watchdog, verifier, da_policy) = Default::default();
+        let context = ExecutorContext {
+            shared_blocks: Some(&shared_blocks),
+            ..context(&watchdog, &verifier, &da_policy)
+        };
+        join_all(rollups.iter().map(|(vm, amount)| {
+            let (shared_blocks, context, alice) = (&shared_blocks, &context, &alice);
+            async move {
+                let headers: Vec<Header> = shared_blocks.subscribe_headers(0).await.collect().await;
+                assert_eq!(headers.len(), 1);
+                let state =
+                    RwLock::new(State::from_initial_balances([(alice.address(), 100)], *vm));
+                execute_headers(
+                    shared_blocks,
+                    &state,
+                    headers,
+                    &mut PendingProofs::default(),
+                    context,
+                )
+                .await;
+                let state = state.read().await;
+                assert_eq!(state.block_results().len(), 1);
+                assert_eq!(state.get_balance(&bob), *amount);
+            }
+        }))
+        .await;
+
+        // The rollups shared one header stream, and the block's data was fetched once for both.
+        assert_eq!(data_source.subscriptions.load(Ordering::SeqCst), 1);
+        assert_eq!(data_source.vid_common_fetches.load(Ordering::SeqCst), 1);
+    }
 }
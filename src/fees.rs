@@ -0,0 +1,213 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Fee estimation, served by `rollup/estimate-fee`.
+//!
+//! Fees are paid to the submission operator, so they are only charged by a rollup with an operator.
+//! The sequencer does not order transactions by fee, so any fee is accepted by a transaction which
+//! can execute straight away. A fee does matter for a transaction held in the mempool until its
+//! nonce is current: once the mempool is full, a new transaction is only held if it pays more than
+//! the cheapest one it can evict.
+//!
+//! The estimate combines the fee needed to enter the mempool now with the fees of transactions
+//! recently accepted by the rollup, recommending a higher percentile of them as the mempool fills.
+
+use crate::mempool::Mempool;
+use crate::state::{Amount, State, SubmissionPolicy};
+use crate::stats::percentile;
+use async_std::sync::{Arc, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The default number of accepted transactions whose fees are remembered.
+pub const DEFAULT_FEE_WINDOW: usize = 1000;
+
+/// Percentiles of the fees of recently accepted transactions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeePercentiles {
+    /// Number of accepted transactions the percentiles are taken over.
+    pub samples: usize,
+    pub p25: Amount,
+    pub p50: Amount,
+    pub p75: Amount,
+    pub p90: Amount,
+}
+
+/// The fees of the most recently accepted transactions.
+#[derive(Clone, Debug)]
+pub struct FeeHistory {
+    capacity: usize,
+    fees: Arc<RwLock<VecDeque<Amount>>>,
+}
+
+impl Default for FeeHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_FEE_WINDOW)
+    }
+}
+
+impl FeeHistory {
+    /// Remember the fees of the most recent `capacity` accepted transactions.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            fees: Default::default(),
+        }
+    }
+
+    /// Record the fees of the transactions applied in the most recently executed block of `state`.
+    pub async fn record_block(&self, state: &State) {
+        let accepted = state
+            .block_transfers()
+            .iter()
+            .zip(state.block_results())
+            .filter(|(_, (_, result))| result.is_ok())
+            .filter_map(|(transfer, _)| transfer.as_ref().map(|transfer| transfer.fee));
+        let mut fees = self.fees.write().await;
+        for fee in accepted {
+            if fees.len() == self.capacity {
+                fees.pop_front();
+            }
+            if self.capacity > 0 {
+                fees.push_back(fee);
+            }
+        }
+    }
+
+    pub async fn percentiles(&self) -> FeePercentiles {
+        let mut sorted = self.fees.read().await.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        FeePercentiles {
+            samples: sorted.len(),
+            p25: percentile(&sorted, 25),
+            p50: percentile(&sorted, 50),
+            p75: percentile(&sorted, 75),
+            p90: percentile(&sorted, 90),
+        }
+    }
+}
+
+/// The fee a client should offer, served by `rollup/estimate-fee`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    /// Whether fees are charged. Without a submission operator there is no one to pay, and every
+    /// estimate is 0.
+    pub charged: bool,
+    /// The lowest fee with which a transaction is held in the mempool, if its nonce is ahead of the
+    /// sender's.
+    pub minimum: Amount,
+    /// The fee recommended for a transaction to be accepted promptly.
+    pub recommended: Amount,
+    /// Number of transactions held in the mempool.
+    pub mempool_depth: usize,
+    /// Number of transactions the mempool can hold.
+    pub mempool_capacity: usize,
+    /// Fees of recently accepted transactions.
+    pub accepted: FeePercentiles,
+}
+
+impl FeeEstimate {
+    /// Estimate the fee under `policy`, given the `mempool` and the `history` of accepted fees.
+    ///
+    /// The recommendation is the median accepted fee, or the 90th percentile once the mempool is
+    /// at least half full, and never less than the minimum.
+    pub async fn new(policy: &SubmissionPolicy, mempool: &Mempool, history: &FeeHistory) -> Self {
+        let mempool_depth = mempool.depth().await;
+        let mempool_capacity = mempool.capacity();
+        let accepted = history.percentiles().await;
+        if policy.operator.is_none() {
+            return Self {
+                charged: false,
+                minimum: 0,
+                recommended: 0,
+                mempool_depth,
+                mempool_capacity,
+                accepted,
+            };
+        }
+        let minimum = mempool.min_fee().await;
+        let congested = 2 * mempool_depth >= mempool_capacity && mempool_capacity > 0;
+        let typical = if congested {
+            accepted.p90
+        } else {
+            accepted.p50
+        };
+        Self {
+            charged: true,
+            minimum,
+            recommended: typical.max(minimum),
+            mempool_depth,
+            mempool_capacity,
+            accepted,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Nonce;
+    use espresso_types::{NamespaceId, Transaction};
+    use ethers::types::{Address, H256};
+
+    #[async_std::test]
+    async fn test_fee_estimate() {
+        let history = FeeHistory::new(10);
+        for fee in 1..=20 {
+            history.fees.write().await.push_back(fee);
+        }
+        // Only the most recent fees are remembered.
+        history.fees.write().await.drain(..10);
+        let accepted = history.percentiles().await;
+        assert_eq!(
+            accepted,
+            FeePercentiles {
+                samples: 10,
+                p25: 13,
+                p50: 15,
+                p75: 18,
+                p90: 19,
+            }
+        );
+
+        let policy = SubmissionPolicy {
+            operator: Some(Address::random()),
+            ..Default::default()
+        };
+        let mempool = Mempool::new(4);
+        let hold = |nonce: Nonce, fee| {
+            let transaction =
+                Transaction::new(NamespaceId::from(1_u64), nonce.to_le_bytes().to_vec());
+            mempool.hold(Address::random(), nonce, H256::random(), fee, transaction)
+        };
+
+        // With room in the mempool, the median accepted fee is recommended.
+        let estimate = FeeEstimate::new(&policy, &mempool, &history).await;
+        assert_eq!((estimate.minimum, estimate.recommended), (0, 15));
+
+        // Once it is half full, a higher percentile is recommended.
+        hold(2, 3).await.unwrap();
+        hold(2, 30).await.unwrap();
+        let estimate = FeeEstimate::new(&policy, &mempool, &history).await;
+        assert_eq!(estimate.mempool_depth, 2);
+        assert_eq!((estimate.minimum, estimate.recommended), (0, 19));
+
+        // Once it is full, a transaction must outbid the cheapest held transaction.
+        hold(2, 40).await.unwrap();
+        hold(2, 50).await.unwrap();
+        let estimate = FeeEstimate::new(&policy, &mempool, &history).await;
+        assert_eq!((estimate.minimum, estimate.recommended), (4, 19));
+        hold(2, 60).await.unwrap();
+        let estimate = FeeEstimate::new(&policy, &mempool, &history).await;
+        assert_eq!((estimate.minimum, estimate.recommended), (31, 31));
+
+        // Without an operator, no fee is charged.
+        let estimate = FeeEstimate::new(&SubmissionPolicy::default(), &mempool, &history).await;
+        assert!(!estimate.charged);
+        assert_eq!((estimate.minimum, estimate.recommended), (0, 0));
+        assert_eq!(estimate.accepted, accepted);
+    }
+}
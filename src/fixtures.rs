@@ -0,0 +1,177 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Fixtures for feeding malformed and adversarial namespace payloads through the executor.
+
+use crate::data_source::MockBlock;
+use crate::error::RollupError;
+use crate::state::Nonce;
+use crate::transaction::{SignedTransaction, Transaction, MAX_TRANSACTION_SIZE};
+use espresso_types::{
+    Header, NamespaceId, NodeState, NsProof, Payload, SeqTypes, Transaction as SeqTransaction,
+    ValidatedState,
+};
+use ethers::signers::LocalWallet;
+use ethers::types::{Address, H256};
+use hotshot_types::traits::block_contents::{BlockPayload, EncodeBytes};
+use hotshot_types::vid::vid_scheme;
+use jf_vid::VidScheme;
+use serde_json::Value;
+
+/// The ways in which a payload from [`adversarial_payloads`] is constructed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdversarialCase {
+    /// A valid transaction, which the adversarial payloads that follow it try to interfere with.
+    Valid,
+    /// A validly signed transaction reusing the nonce of the valid transaction.
+    DuplicateNonce,
+    /// A transaction whose JSON encoding is cut off part way through.
+    TruncatedJson,
+    /// A signed transaction whose amount was changed after signing, so the signature recovers to
+    /// an unfunded account.
+    TamperedTransaction,
+    /// A transaction with a signature which does not recover to any account.
+    InvalidSignature,
+    /// A transaction padded with a memo field so that it exceeds the maximum transaction size.
+    HugeMemo,
+}
+
+/// A namespace payload along with the result the executor must record for it.
+#[derive(Clone, Debug)]
+pub struct AdversarialPayload {
+    pub case: AdversarialCase,
+    pub bytes: Vec<u8>,
+    /// The hash under which the receipt for this payload is recorded.
+    pub hash: H256,
+    pub expected: Result<(), RollupError>,
+}
+
+impl AdversarialPayload {
+    fn new(case: AdversarialCase, bytes: Vec<u8>, expected: Result<(), RollupError>) -> Self {
+        Self {
+            case,
+            hash: SignedTransaction::payload_hash(&bytes),
+            bytes,
+            expected,
+        }
+    }
+}
+
+/// Generate one payload for each [`AdversarialCase`], in order.
+///
+/// `sender` must be funded with at least 10 tokens and have nonce `nonce - 1`. Executing the
+/// payloads in order applies only the first, which transfers 10 tokens to `recipient`.
+pub async fn adversarial_payloads(
+    sender: &LocalWallet,
+    recipient: Address,
+    nonce: Nonce,
+) -> Vec<AdversarialPayload> {
+    let transaction = |amount, nonce| Transaction {
+        amount,
+        destination: recipient,
+        nonce,
+        ..Default::default()
+    };
+    let valid = SignedTransaction::new(transaction(10, nonce), sender).await;
+    let duplicate = SignedTransaction::new(transaction(20, nonce), sender).await;
+    let next = SignedTransaction::new(transaction(1, nonce + 1), sender).await;
+    let next_json: Value = serde_json::from_slice(&next.encode()).unwrap();
+
+    let truncated = next.encode()[..next.encode().len() / 2].to_vec();
+    let truncated_err = SignedTransaction::decode(&truncated).unwrap_err();
+
+    let mut tampered_json = next_json.clone();
+    tampered_json["transaction"]["amount"] = 2.into();
+    let tampered = serde_json::to_vec(&tampered_json).unwrap();
+    let tampered_signer = SignedTransaction::decode(&tampered)
+        .unwrap()
+        .recover()
+        .unwrap();
+
+    let mut invalid_signature_json = next_json.clone();
+    invalid_signature_json["signature"]["r"] = "0x0".into();
+    invalid_signature_json["signature"]["s"] = "0x0".into();
+    let invalid_signature = serde_json::to_vec(&invalid_signature_json).unwrap();
+
+    let mut huge_json = next_json;
+    huge_json["memo"] = "A".repeat(MAX_TRANSACTION_SIZE).into();
+    let huge = serde_json::to_vec(&huge_json).unwrap();
+    let huge_size = huge.len();
+
+    vec![
+        AdversarialPayload::new(AdversarialCase::Valid, valid.encode(), Ok(())),
+        AdversarialPayload::new(
+            AdversarialCase::DuplicateNonce,
+            duplicate.encode(),
+            Err(RollupError::InvalidNonce {
+                address: valid.recover().unwrap(),
+                expected: nonce + 1,
+                actual: nonce,
+            }),
+        ),
+        AdversarialPayload::new(
+            AdversarialCase::TruncatedJson,
+            truncated,
+            Err(truncated_err),
+        ),
+        AdversarialPayload::new(
+            AdversarialCase::TamperedTransaction,
+            tampered,
+            Err(RollupError::InsufficientBalance {
+                address: tampered_signer,
+            }),
+        ),
+        AdversarialPayload::new(
+            AdversarialCase::InvalidSignature,
+            invalid_signature,
+            Err(RollupError::SignatureError),
+        ),
+        AdversarialPayload::new(
+            AdversarialCase::HugeMemo,
+            huge,
+            Err(RollupError::TransactionTooLarge {
+                size: huge_size,
+                max: MAX_TRANSACTION_SIZE,
+            }),
+        ),
+    ]
+}
+
+/// Build a block containing `payloads` in their respective namespaces, as served to a rollup
+/// executing `namespace`.
+pub async fn mock_block(
+    namespace: NamespaceId,
+    payloads: &[(NamespaceId, Vec<Vec<u8>>)],
+) -> MockBlock {
+    let transactions = payloads.iter().flat_map(|(ns, payloads)| {
+        payloads
+            .iter()
+            .map(|payload| SeqTransaction::new(*ns, payload.clone()))
+    });
+    let (payload, ns_table) = <Payload as BlockPayload<SeqTypes>>::from_transactions(
+        transactions,
+        &ValidatedState::default(),
+        &NodeState::mock(),
+    )
+    .await
+    .unwrap();
+    let disperse = vid_scheme(1).disperse(payload.encode()).unwrap();
+    let namespace_proof = ns_table
+        .find_ns_id(&namespace)
+        .and_then(|index| NsProof::new(&payload, &index, &disperse.common));
+    let header = Header::genesis(
+        &NodeState::mock(),
+        disperse.commit,
+        payload.builder_commitment(&ns_table),
+        ns_table,
+    );
+    MockBlock {
+        header,
+        namespace_proof,
+        vid_common: Some(disperse.common),
+        view_number: None,
+    }
+}
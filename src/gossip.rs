@@ -0,0 +1,361 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Cross-checking of executor determinism between independently operated rollup nodes.
+//!
+//! Each node signs a checkpoint of its state commitment after every block and serves its recent
+//! checkpoints from the API. The gossip task periodically fetches a checkpoint from each peer at
+//! the latest height both nodes have executed, and raises an alert if the peer reports a different
+//! commitment. Each peer is configured with the address it signs with, and checkpoints signed by
+//! any other key are ignored.
+
+use crate::address::parse_checksummed;
+use crate::clock::Clock;
+use crate::state::State;
+use crate::SequencerApiVersion;
+use async_std::sync::{Arc, RwLock};
+use committable::Commitment;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, Signature};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use surf_disco::error::ClientError;
+use surf_disco::{Client, Url};
+
+/// Number of checkpoints retained by default.
+pub const DEFAULT_CHECKPOINT_CAPACITY: usize = 1000;
+
+/// The state commitment of a rollup node after executing the block at `height`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub height: u64,
+    pub commitment: Commitment<State>,
+}
+
+impl Checkpoint {
+    fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Serialization should not fail")
+    }
+
+    pub async fn sign(self, wallet: &impl Signer) -> SignedCheckpoint {
+        let signature = wallet.sign_message(self.encode()).await.unwrap();
+        SignedCheckpoint {
+            checkpoint: self,
+            signature,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedCheckpoint {
+    pub checkpoint: Checkpoint,
+    signature: Signature,
+}
+
+impl SignedCheckpoint {
+    /// The address of the node which signed this checkpoint, if the signature is valid.
+    pub fn signer(&self) -> Option<Address> {
+        self.signature.recover(self.checkpoint.encode()).ok()
+    }
+
+    /// Whether this checkpoint carries a valid signature by `address`.
+    pub fn is_signed_by(&self, address: Address) -> bool {
+        self.signer() == Some(address)
+    }
+}
+
+/// The result of comparing a peer's checkpoint against our own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckpointComparison {
+    /// Both nodes computed the same commitment.
+    Match,
+    /// The nodes computed different commitments for the same height.
+    Diverged { local: Commitment<State> },
+    /// This node has no checkpoint at the peer's height, either because it has not yet executed
+    /// the block or because the checkpoint has been pruned.
+    Unknown,
+}
+
+#[derive(Debug)]
+struct Inner {
+    signer: Option<LocalWallet>,
+    capacity: usize,
+    checkpoints: BTreeMap<u64, SignedCheckpoint>,
+}
+
+/// Recent signed checkpoints of this node's state.
+///
+/// Checkpoints are only recorded if the store has a signing key.
+#[derive(Clone, Debug)]
+pub struct CheckpointStore {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl Default for CheckpointStore {
+    fn default() -> Self {
+        Self::new(None, DEFAULT_CHECKPOINT_CAPACITY)
+    }
+}
+
+impl CheckpointStore {
+    /// Create a store which signs checkpoints with `signer` and retains the `capacity` most recent.
+    pub fn new(signer: Option<LocalWallet>, capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Inner {
+                signer,
+                capacity,
+                checkpoints: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Sign and record the state commitment after executing the block at `height`.
+    pub async fn record(&self, height: u64, commitment: Commitment<State>) {
+        let mut inner = self.inner.write().await;
+        let Some(signer) = &inner.signer else {
+            return;
+        };
+        let checkpoint = Checkpoint { height, commitment }.sign(signer).await;
+        inner.checkpoints.insert(height, checkpoint);
+        while inner.checkpoints.len() > inner.capacity {
+            inner.checkpoints.pop_first();
+        }
+    }
+
+    /// The checkpoint at `height`, if it is retained.
+    pub async fn get(&self, height: u64) -> Option<SignedCheckpoint> {
+        self.inner.read().await.checkpoints.get(&height).cloned()
+    }
+
+    /// The most recent checkpoint.
+    pub async fn latest(&self) -> Option<SignedCheckpoint> {
+        self.inner
+            .read()
+            .await
+            .checkpoints
+            .last_key_value()
+            .map(|(_, checkpoint)| checkpoint.clone())
+    }
+
+    /// The height at which to compare against a peer whose latest checkpoint is at `peer_height`:
+    /// the latest height both nodes have executed, or `None` if this node has no checkpoints.
+    pub async fn common_height(&self, peer_height: u64) -> Option<u64> {
+        let latest = self.latest().await?.checkpoint.height;
+        Some(latest.min(peer_height))
+    }
+
+    /// Compare a checkpoint from a peer against our own checkpoint at the same height.
+    pub async fn compare(&self, peer: &Checkpoint) -> CheckpointComparison {
+        match self.get(peer.height).await {
+            Some(local) if local.checkpoint.commitment == peer.commitment => {
+                CheckpointComparison::Match
+            }
+            Some(local) => CheckpointComparison::Diverged {
+                local: local.checkpoint.commitment,
+            },
+            None => CheckpointComparison::Unknown,
+        }
+    }
+}
+
+/// Parse a gossip peer of the form `ADDRESS@URL`, where `ADDRESS` signs the peer's checkpoints.
+pub fn parse_peer(s: &str) -> Result<(Address, Url), String> {
+    let (address, url) = s
+        .split_once('@')
+        .ok_or_else(|| format!("Invalid gossip peer {s}, expected ADDRESS@URL"))?;
+    let address = parse_checksummed(address).map_err(|err| err.to_string())?;
+    let url = url
+        .parse()
+        .map_err(|err| format!("Invalid URL {url}: {err}"))?;
+    Ok((address, url))
+}
+
+#[derive(Clone, Debug)]
+pub struct GossipOptions {
+    /// Base URLs of the rollup APIs of peer nodes, with the address each signs checkpoints with.
+    pub peers: Vec<(Address, Url)>,
+    /// Delay between rounds of fetching checkpoints from peers.
+    pub interval: Duration,
+}
+
+/// Periodically fetch a checkpoint of each peer at the latest height both have executed, and
+/// compare it against our own.
+///
+/// Divergence is reported as an error in the logs. A peer which is unreachable or serves a
+/// checkpoint not signed by its configured address is logged and retried in the next round.
+pub async fn run_gossip(
+    options: GossipOptions,
+    checkpoints: CheckpointStore,
+    clock: Arc<dyn Clock>,
+) {
+    let peers: Vec<_> = options
+        .peers
+        .iter()
+        .map(|(signer, url)| {
+            let client = Client::<ClientError, SequencerApiVersion>::new(url.clone());
+            (url, *signer, client)
+        })
+        .collect();
+    loop {
+        for (url, signer, client) in &peers {
+            let Some(latest) = fetch_checkpoint(client, url, *signer, None).await else {
+                continue;
+            };
+            let Some(height) = checkpoints.common_height(latest.height).await else {
+                tracing::debug!("No local checkpoints to compare with peer {url}");
+                continue;
+            };
+            // A peer ahead of us is compared at our latest height, rather than at a height we have
+            // not executed yet.
+            let checkpoint = if height == latest.height {
+                latest
+            } else {
+                match fetch_checkpoint(client, url, *signer, Some(height)).await {
+                    Some(checkpoint) => checkpoint,
+                    None => continue,
+                }
+            };
+            let commitment = checkpoint.commitment;
+            match checkpoints.compare(&checkpoint).await {
+                CheckpointComparison::Match => {
+                    tracing::debug!("Peer {url} ({signer:?}) agrees with state at block {height}");
+                }
+                CheckpointComparison::Diverged { local } => {
+                    tracing::error!(
+                        "Peer {url} ({signer:?}) diverged at block {height}: peer commitment {commitment}, local commitment {local}"
+                    );
+                }
+                CheckpointComparison::Unknown => {
+                    tracing::debug!(
+                        "No local checkpoint to compare with peer {url} at block {height}"
+                    );
+                }
+            }
+        }
+        clock.sleep(options.interval).await;
+    }
+}
+
+/// Fetch the checkpoint at `height`, or the latest, from the peer at `url`, if it is signed by
+/// `signer`.
+async fn fetch_checkpoint(
+    client: &Client<ClientError, SequencerApiVersion>,
+    url: &Url,
+    signer: Address,
+    height: Option<u64>,
+) -> Option<Checkpoint> {
+    let path = match height {
+        Some(height) => format!("rollup/checkpoint/{height}"),
+        None => "rollup/checkpoint".to_string(),
+    };
+    let checkpoint = match client.get::<Option<SignedCheckpoint>>(&path).send().await {
+        Ok(Some(checkpoint)) => checkpoint,
+        Ok(None) => return None,
+        Err(err) => {
+            tracing::warn!("Failed to fetch checkpoint from peer {url}: {err}");
+            return None;
+        }
+    };
+    if !checkpoint.is_signed_by(signer) {
+        tracing::warn!("Peer {url} served a checkpoint not signed by {signer:?}");
+        return None;
+    }
+    if height.is_some_and(|height| height != checkpoint.checkpoint.height) {
+        tracing::warn!("Peer {url} served a checkpoint for the wrong height");
+        return None;
+    }
+    Some(checkpoint.checkpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RollupVM;
+    use committable::Committable;
+    use espresso_types::NamespaceId;
+
+    #[async_std::test]
+    async fn test_checkpoint_comparison() {
+        let mut rng = rand::thread_rng();
+        let wallet = LocalWallet::new(&mut rng);
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let state = State::from_initial_balances([(wallet.address(), 100)], vm);
+        let other_state = State::from_initial_balances([(wallet.address(), 200)], vm);
+
+        let store = CheckpointStore::new(Some(wallet.clone()), 2);
+        store.record(1, state.commit()).await;
+        let latest = store.latest().await.unwrap();
+        assert_eq!(latest.signer(), Some(wallet.address()));
+        assert!(latest.is_signed_by(wallet.address()));
+
+        let matching = Checkpoint {
+            height: 1,
+            commitment: state.commit(),
+        };
+        assert_eq!(store.compare(&matching).await, CheckpointComparison::Match);
+        let diverged = Checkpoint {
+            height: 1,
+            commitment: other_state.commit(),
+        };
+        assert_eq!(
+            store.compare(&diverged).await,
+            CheckpointComparison::Diverged {
+                local: state.commit()
+            }
+        );
+
+        // Old checkpoints are pruned once the store is full.
+        store.record(2, state.commit()).await;
+        store.record(3, state.commit()).await;
+        assert_eq!(
+            store.compare(&matching).await,
+            CheckpointComparison::Unknown
+        );
+        assert_eq!(store.latest().await.unwrap().checkpoint.height, 3);
+
+        // A store without a signing key records nothing.
+        let unsigned = CheckpointStore::default();
+        unsigned.record(1, state.commit()).await;
+        assert!(unsigned.latest().await.is_none());
+        assert_eq!(unsigned.common_height(1).await, None);
+    }
+
+    #[async_std::test]
+    async fn test_peer_checkpoints() {
+        let mut rng = rand::thread_rng();
+        let peer = LocalWallet::new(&mut rng);
+        let attacker = LocalWallet::new(&mut rng);
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let state = State::from_initial_balances([(peer.address(), 100)], vm);
+
+        // Only checkpoints signed by the configured peer address are trusted.
+        let checkpoint = Checkpoint {
+            height: 1,
+            commitment: state.commit(),
+        };
+        assert!(checkpoint.sign(&peer).await.is_signed_by(peer.address()));
+        assert!(!checkpoint
+            .sign(&attacker)
+            .await
+            .is_signed_by(peer.address()));
+
+        let (address, url) =
+            parse_peer(&format!("{:?}@http://peer:8080/", peer.address())).unwrap();
+        assert_eq!(address, peer.address());
+        assert_eq!(url.as_str(), "http://peer:8080/");
+        parse_peer("http://peer:8080/").unwrap_err();
+
+        // Peers are compared at the latest height both have executed, whether they are ahead of
+        // or behind this node.
+        let store = CheckpointStore::new(Some(LocalWallet::new(&mut rng)), 10);
+        for height in 1..=5 {
+            store.record(height, state.commit()).await;
+        }
+        assert_eq!(store.common_height(8).await, Some(5));
+        assert_eq!(store.common_height(3).await, Some(3));
+    }
+}
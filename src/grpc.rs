@@ -0,0 +1,402 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Optional gRPC interface to the rollup node, mirroring the HTTP API.
+
+use crate::{
+    address::AddressBook,
+    api::submit_transaction,
+    http::HttpClientPool,
+    receipt::{ExecutionStatus, ReceiptIndex},
+    state::State,
+    stats::LatencyTracker,
+    transaction::SignedTransaction,
+};
+use async_compatibility_layer::async_primitives::broadcast::BroadcastSender;
+use async_std::sync::RwLock;
+use committable::Committable;
+use ethers::signers::LocalWallet;
+use ethers::types::H256;
+use futures::stream::{self, BoxStream, StreamExt};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use surf_disco::Url;
+use tonic::{transport::Server, Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("rollup");
+}
+
+use proto::rollup_server::{Rollup, RollupServer};
+use proto::{
+    AddressRequest, BalanceResponse, NonceResponse, ReceiptResponse, StateUpdate, SubmitRequest,
+    SubmitResponse, SubscribeStateRequest, TransactionRequest, TransactionStatusResponse,
+};
+
+#[derive(Clone, Debug)]
+pub struct GrpcOptions {
+    pub grpc_port: u16,
+    pub sequencer_url: Url,
+    pub address_book: AddressBook,
+    /// Key with which submitted transactions are countersigned.
+    pub operator_signer: Option<LocalWallet>,
+    /// Clients for the HotShot query service.
+    pub http: HttpClientPool,
+    /// Receipts of executed transactions, shared with the HTTP API. Unlike the HTTP API, the gRPC
+    /// interface only serves receipts of transactions executed by this node, and does not fetch
+    /// them from relay peers.
+    pub receipts: ReceiptIndex,
+    /// Timings of recent transactions, shared with the HTTP API.
+    pub latency: LatencyTracker,
+}
+
+struct RollupService {
+    state: Arc<RwLock<State>>,
+    sequencer_url: Url,
+    address_book: AddressBook,
+    operator_signer: Option<LocalWallet>,
+    http: HttpClientPool,
+    receipts: ReceiptIndex,
+    latency: LatencyTracker,
+    state_updates: BroadcastSender<(u64, State)>,
+}
+
+/// Parse the rollup transaction hash of a request.
+fn transaction_hash(request: Request<TransactionRequest>) -> Result<H256, Status> {
+    let hash = request.into_inner().hash;
+    hash.parse().map_err(|err| {
+        Status::invalid_argument(format!("Malformed transaction hash {hash}: {err}"))
+    })
+}
+
+#[tonic::async_trait]
+impl Rollup for RollupService {
+    type SubscribeStateStream = BoxStream<'static, Result<StateUpdate, Status>>;
+
+    async fn submit(
+        &self,
+        request: Request<SubmitRequest>,
+    ) -> Result<Response<SubmitResponse>, Status> {
+        let transaction =
+            SignedTransaction::decode(&request.into_inner().transaction).map_err(|err| {
+                Status::invalid_argument(format!(
+                    "{err} Ensure that the transaction is a JSON serialized SignedTransaction"
+                ))
+            })?;
+        let namespace = self.state.read().await.vm.into();
+        let hash = submit_transaction(
+            &self.http,
+            &self.sequencer_url,
+            namespace,
+            transaction,
+            self.operator_signer.as_ref(),
+        )
+        .await
+        .map_err(|err| Status::unavailable(err.message))?;
+        Ok(Response::new(SubmitResponse {
+            hash: hash.to_string(),
+        }))
+    }
+
+    async fn balance(
+        &self,
+        request: Request<AddressRequest>,
+    ) -> Result<Response<BalanceResponse>, Status> {
+        let address = self
+            .address_book
+            .resolve(&request.into_inner().address)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let balance = self.state.read().await.get_balance(&address);
+        Ok(Response::new(BalanceResponse { balance }))
+    }
+
+    async fn nonce(
+        &self,
+        request: Request<AddressRequest>,
+    ) -> Result<Response<NonceResponse>, Status> {
+        let address = self
+            .address_book
+            .resolve(&request.into_inner().address)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let nonce = self.state.read().await.get_nonce(&address);
+        Ok(Response::new(NonceResponse { nonce }))
+    }
+
+    async fn receipt(
+        &self,
+        request: Request<TransactionRequest>,
+    ) -> Result<Response<ReceiptResponse>, Status> {
+        let hash = transaction_hash(request)?;
+        let mut receipt = self.receipts.get(&hash).await.ok_or_else(|| {
+            Status::not_found(format!("Transaction {hash:?} has not been executed."))
+        })?;
+        // The transaction may have been verified on the L1 since it was executed.
+        if let Some(timings) = self.latency.timings(&hash).await {
+            receipt.timings = timings;
+        }
+        let receipt =
+            serde_json::to_vec(&receipt).map_err(|err| Status::internal(err.to_string()))?;
+        Ok(Response::new(ReceiptResponse { receipt }))
+    }
+
+    async fn transaction_status(
+        &self,
+        request: Request<TransactionRequest>,
+    ) -> Result<Response<TransactionStatusResponse>, Status> {
+        let hash = transaction_hash(request)?;
+        let receipt = self.receipts.get(&hash).await;
+        let timings = self.latency.timings(&hash).await;
+        let status = ExecutionStatus::new(receipt.as_ref(), timings.as_ref()).ok_or_else(|| {
+            Status::not_found(format!("Transaction {hash:?} is not known to this node."))
+        })?;
+        let response = match status {
+            ExecutionStatus::Pending { submitted_ms } => TransactionStatusResponse {
+                status: "pending".into(),
+                submitted_ms,
+                ..Default::default()
+            },
+            ExecutionStatus::Executed { block_height } => TransactionStatusResponse {
+                status: "executed".into(),
+                block_height: Some(block_height),
+                ..Default::default()
+            },
+            ExecutionStatus::Rejected {
+                block_height,
+                error,
+            } => TransactionStatusResponse {
+                status: "rejected".into(),
+                block_height: Some(block_height),
+                error_code: Some(error.code().into()),
+                error: Some(error.to_string()),
+                ..Default::default()
+            },
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn subscribe_state(
+        &self,
+        _request: Request<SubscribeStateRequest>,
+    ) -> Result<Response<Self::SubscribeStateStream>, Status> {
+        let receiver = self.state_updates.handle_async().await;
+        let updates = stream::unfold(receiver, |mut receiver| async move {
+            let (block_height, state) = receiver.recv_async().await.ok()?;
+            let update = StateUpdate {
+                block_height,
+                commitment: state.commit().to_string(),
+            };
+            Some((Ok(update), receiver))
+        });
+        Ok(Response::new(updates.boxed()))
+    }
+}
+
+/// Serve the gRPC interface until the server fails.
+///
+/// `state_updates` should be the executor's output stream, which is used to push state updates to
+/// subscribers.
+pub async fn serve_grpc(
+    options: &GrpcOptions,
+    state: Arc<RwLock<State>>,
+    state_updates: BroadcastSender<(u64, State)>,
+) -> Result<(), tonic::transport::Error> {
+    let service = RollupService {
+        state,
+        sequencer_url: options.sequencer_url.clone(),
+        address_book: options.address_book.clone(),
+        operator_signer: options.operator_signer.clone(),
+        http: options.http.clone(),
+        receipts: options.receipts.clone(),
+        latency: options.latency.clone(),
+        state_updates,
+    };
+    let addr = SocketAddr::from(([0, 0, 0, 0], options.grpc_port));
+    Server::builder()
+        .add_service(RollupServer::new(service))
+        .serve(addr)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::RollupError, receipt::Receipt, transaction::Transaction, RollupVM};
+    use async_compatibility_layer::async_primitives::broadcast;
+    use async_std::task::{sleep, spawn};
+    use espresso_types::NamespaceId;
+    use ethers::signers::Signer;
+    use portpicker::pick_unused_port;
+    use proto::rollup_client::RollupClient;
+    use std::time::Duration;
+    use tonic::{transport::Channel, Code};
+
+    /// Serve the gRPC interface on a free port, returning a client connected to it.
+    async fn start(
+        state: State,
+        receipts: ReceiptIndex,
+        latency: LatencyTracker,
+        state_updates: BroadcastSender<(u64, State)>,
+    ) -> RollupClient<Channel> {
+        // Nothing listens at the sequencer URL, so submissions which reach it fail.
+        let sequencer_port = pick_unused_port().expect("No ports free");
+        let options = GrpcOptions {
+            grpc_port: pick_unused_port().expect("No ports free"),
+            sequencer_url: format!("http://localhost:{sequencer_port}")
+                .parse()
+                .unwrap(),
+            address_book: Default::default(),
+            operator_signer: None,
+            http: Default::default(),
+            receipts,
+            latency,
+        };
+        let url = format!("http://127.0.0.1:{}", options.grpc_port);
+        spawn(
+            async move { serve_grpc(&options, Arc::new(RwLock::new(state)), state_updates).await },
+        );
+        loop {
+            match RollupClient::connect(url.clone()).await {
+                Ok(client) => return client,
+                Err(_) => sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+
+    #[async_std::test]
+    async fn test_grpc_service() {
+        let mut rng = rand::thread_rng();
+        let wallet = LocalWallet::new(&mut rng);
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let state = State::from_initial_balances([(wallet.address(), 100)], vm);
+        let receipts = ReceiptIndex::default();
+        let latency = LatencyTracker::default();
+        let (state_updates, _) = broadcast::channel();
+        let mut client = start(
+            state.clone(),
+            receipts.clone(),
+            latency.clone(),
+            state_updates.clone(),
+        )
+        .await;
+
+        // Balance and Nonce.
+        let address = format!("{:?}", wallet.address());
+        let response = client
+            .balance(AddressRequest {
+                address: address.clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.into_inner().balance, 100);
+        let response = client.nonce(AddressRequest { address }).await.unwrap();
+        assert_eq!(response.into_inner().nonce, 0);
+        let err = client
+            .balance(AddressRequest {
+                address: "nobody".into(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        // Submit.
+        let transaction = SignedTransaction::new(
+            Transaction {
+                amount: 1,
+                destination: wallet.address(),
+                nonce: 1,
+                ..Default::default()
+            },
+            &wallet,
+        )
+        .await;
+        let err = client
+            .submit(SubmitRequest {
+                transaction: b"not a transaction".to_vec(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        let err = client
+            .submit(SubmitRequest {
+                transaction: transaction.encode(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::Unavailable);
+
+        // Receipt and TransactionStatus.
+        let hash = transaction.hash();
+        let request = || TransactionRequest {
+            hash: format!("{hash:?}"),
+        };
+        let err = client.receipt(request()).await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+        let err = client.transaction_status(request()).await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+        let err = client
+            .transaction_status(TransactionRequest {
+                hash: "0x1234".into(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        latency.record_submitted(hash, 1000).await;
+        let status = client
+            .transaction_status(request())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(status.status, "pending");
+        assert_eq!(status.submitted_ms, Some(1000));
+        assert_eq!(status.block_height, None);
+
+        let receipt = Receipt {
+            hash,
+            block_height: 3,
+            view_number: Some(3),
+            block_timestamp: 0,
+            index: 0,
+            result: Err(RollupError::InvalidNonce {
+                address: wallet.address(),
+                expected: 2,
+                actual: 1,
+            }),
+            prev_state_commitment: state.commit(),
+            state_commitment: state.commit(),
+            timings: Default::default(),
+            metrics: Default::default(),
+            chain_id: None,
+        };
+        receipts.insert_block(3, vec![receipt.clone()]).await;
+        let response = client.receipt(request()).await.unwrap().into_inner();
+        let served: Receipt = serde_json::from_slice(&response.receipt).unwrap();
+        assert_eq!(served.block_height, 3);
+        assert_eq!(served.result, receipt.result);
+        // The receipt carries the timings recorded since the transaction executed.
+        assert_eq!(served.timings.submitted_ms, Some(1000));
+        let status = client
+            .transaction_status(request())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(status.status, "rejected");
+        assert_eq!(status.block_height, Some(3));
+        assert_eq!(status.error_code, Some(200));
+        assert_eq!(status.error, Some(receipt.result.unwrap_err().to_string()));
+
+        // SubscribeState.
+        let mut updates = client
+            .subscribe_state(SubscribeStateRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        state_updates.send_async((1, state.clone())).await.unwrap();
+        let update = updates.message().await.unwrap().unwrap();
+        assert_eq!(update.block_height, 1);
+        assert_eq!(update.commitment, state.commit().to_string());
+    }
+}
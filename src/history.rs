@@ -0,0 +1,300 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Historical account state, for comparing the state at two block heights.
+//!
+//! After each executed block, the API records a copy-on-write view of the accounts (see
+//! [`Ledger::shared_accounts`](crate::ledger::Ledger::shared_accounts)). Taking the view is cheap,
+//! but the executor copies the account map the next time it changes, so each retained block holds
+//! a full copy of the accounts and only a bounded window of recent blocks is kept. Blocks without
+//! rollup transactions are not executed, so the state at any height is the state after the last
+//! executed block at or below that height.
+//!
+//! Alongside the accounts, the history records the consensus-level metadata of each block, so that
+//! rollup data can be correlated with HotShot views and timing.
+
+use crate::state::{Account, Amount, Nonce, State};
+use async_std::sync::{Arc, RwLock};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::BTreeMap;
+
+/// Default number of executed blocks whose accounts are retained.
+pub const DEFAULT_HISTORY_WINDOW: usize = 1000;
+
+#[derive(Clone, Debug, Snafu, PartialEq, Eq)]
+pub enum HistoryError {
+    #[snafu(display("Height {from_height} is after height {to_height}."))]
+    InvalidRange { from_height: u64, to_height: u64 },
+    #[snafu(display("The state at height {height} is no longer retained by this node."))]
+    NotRetained { height: u64 },
+    #[snafu(display("Block {height} has not been executed."))]
+    NotExecuted { height: u64 },
+}
+
+/// The change in one account between two heights.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountChange {
+    pub address: Address,
+    pub from_balance: Amount,
+    pub to_balance: Amount,
+    /// Net change in balance, which is negative if the account lost funds.
+    pub balance_change: i128,
+    pub from_nonce: Nonce,
+    pub to_nonce: Nonce,
+}
+
+/// The accounts which changed between two heights.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateDiff {
+    pub from_height: u64,
+    pub to_height: u64,
+    /// Changed accounts, ordered by address. Accounts created in the range have a starting balance
+    /// and nonce of 0.
+    pub changes: Vec<AccountChange>,
+}
+
+/// Consensus-level metadata of an executed block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockInfo {
+    pub block_height: u64,
+    /// HotShot view in which the block was decided, or `None` if the executor could not fetch it.
+    pub view_number: Option<u64>,
+    /// Unix timestamp (in seconds) from the block header.
+    pub timestamp: u64,
+}
+
+impl BlockInfo {
+    /// Metadata of the most recent block executed by `state`.
+    pub fn of(state: &State) -> Self {
+        Self {
+            block_height: state.block_height(),
+            view_number: state.view_number(),
+            timestamp: state.block_timestamp(),
+        }
+    }
+}
+
+type Accounts = Arc<BTreeMap<Address, Account>>;
+
+/// The accounts after each of a window of recently executed blocks, and the metadata of those
+/// blocks.
+#[derive(Clone, Debug)]
+pub struct AccountHistory {
+    window: usize,
+    blocks: Arc<RwLock<BTreeMap<u64, (BlockInfo, Accounts)>>>,
+}
+
+impl Default for AccountHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_WINDOW)
+    }
+}
+
+impl AccountHistory {
+    /// Retain the accounts after the last `window` executed blocks.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            blocks: Default::default(),
+        }
+    }
+
+    /// Record `accounts` as the state after the block described by `info`.
+    pub async fn insert(&self, info: BlockInfo, accounts: Accounts) {
+        let mut blocks = self.blocks.write().await;
+        blocks.insert(info.block_height, (info, accounts));
+        while blocks.len() > self.window {
+            blocks.pop_first();
+        }
+    }
+
+    /// The net change in each account between the state at `from_height` and the state at
+    /// `to_height`.
+    pub async fn diff(&self, from_height: u64, to_height: u64) -> Result<StateDiff, HistoryError> {
+        if from_height > to_height {
+            return Err(HistoryError::InvalidRange {
+                from_height,
+                to_height,
+            });
+        }
+        let blocks = self.blocks.read().await;
+        let from = accounts_at(&blocks, from_height)?;
+        let to = accounts_at(&blocks, to_height)?;
+        Ok(StateDiff {
+            from_height,
+            to_height,
+            changes: diff_accounts(&from, &to),
+        })
+    }
+
+    /// The account of `address` in the state at `height`.
+    pub async fn account_at(
+        &self,
+        address: &Address,
+        height: u64,
+    ) -> Result<Account, HistoryError> {
+        let blocks = self.blocks.read().await;
+        let accounts = accounts_at(&blocks, height)?;
+        Ok(accounts.get(address).cloned().unwrap_or_default())
+    }
+
+    /// Metadata of the executed block at `height`.
+    pub async fn block(&self, height: u64) -> Result<BlockInfo, HistoryError> {
+        let blocks = self.blocks.read().await;
+        if let Some((info, _)) = blocks.get(&height) {
+            return Ok(*info);
+        }
+        match blocks.first_key_value() {
+            Some((first, _)) if height < *first => Err(HistoryError::NotRetained { height }),
+            _ => Err(HistoryError::NotExecuted { height }),
+        }
+    }
+}
+
+/// The accounts which differ between `from` and a later state `to`, ordered by address.
+pub fn diff_accounts(
+    from: &BTreeMap<Address, Account>,
+    to: &BTreeMap<Address, Account>,
+) -> Vec<AccountChange> {
+    // Accounts are never deleted, so every account in `from` is also in `to`.
+    to.iter()
+        .filter_map(|(address, after)| {
+            let before = from.get(address).cloned().unwrap_or_default();
+            (&before != after).then(|| AccountChange {
+                address: *address,
+                from_balance: before.balance,
+                to_balance: after.balance,
+                balance_change: after.balance as i128 - before.balance as i128,
+                from_nonce: before.nonce,
+                to_nonce: after.nonce,
+            })
+        })
+        .collect()
+}
+
+/// The accounts after the last block in `blocks` at or below `height`.
+fn accounts_at(
+    blocks: &BTreeMap<u64, (BlockInfo, Accounts)>,
+    height: u64,
+) -> Result<Accounts, HistoryError> {
+    let latest = blocks.last_key_value().map(|(height, _)| *height);
+    if latest.map_or(true, |latest| height > latest) {
+        return Err(HistoryError::NotExecuted { height });
+    }
+    blocks
+        .range(..=height)
+        .next_back()
+        .map(|(_, (_, accounts))| accounts.clone())
+        .ok_or(HistoryError::NotRetained { height })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts(balances: &[(Address, Amount, Nonce)]) -> Accounts {
+        Arc::new(
+            balances
+                .iter()
+                .map(|(address, balance, nonce)| {
+                    (
+                        *address,
+                        Account {
+                            balance: *balance,
+                            nonce: *nonce,
+                        },
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn block(block_height: u64) -> BlockInfo {
+        BlockInfo {
+            block_height,
+            view_number: Some(block_height + 10),
+            timestamp: 1000 + block_height,
+        }
+    }
+
+    #[async_std::test]
+    async fn test_account_history_diff() {
+        let alice = Address::random();
+        let bob = Address::random();
+        let carol = Address::random();
+        let history = AccountHistory::new(3);
+        history
+            .insert(block(1), accounts(&[(alice, 100, 0), (bob, 50, 0)]))
+            .await;
+        history
+            .insert(block(4), accounts(&[(alice, 70, 1), (bob, 80, 0)]))
+            .await;
+        history
+            .insert(
+                block(6),
+                accounts(&[(alice, 60, 2), (bob, 80, 0), (carol, 10, 0)]),
+            )
+            .await;
+
+        // Heights between executed blocks resolve to the last executed block.
+        let diff = history.diff(2, 5).await.unwrap();
+        assert_eq!(diff.changes.len(), 2);
+        let alice_change = diff.changes.iter().find(|c| c.address == alice).unwrap();
+        assert_eq!(alice_change.balance_change, -30);
+        assert_eq!((alice_change.from_nonce, alice_change.to_nonce), (0, 1));
+
+        let diff = history.diff(4, 6).await.unwrap();
+        let changed = diff.changes.iter().map(|c| c.address).collect::<Vec<_>>();
+        let mut expected = vec![alice, carol];
+        expected.sort();
+        assert_eq!(changed, expected);
+        assert!(history.diff(6, 6).await.unwrap().changes.is_empty());
+        assert_eq!(history.account_at(&alice, 5).await.unwrap().balance, 70);
+        assert_eq!(history.account_at(&carol, 5).await.unwrap().balance, 0);
+
+        assert_eq!(
+            history.diff(6, 4).await,
+            Err(HistoryError::InvalidRange {
+                from_height: 6,
+                to_height: 4
+            })
+        );
+        assert_eq!(
+            history.diff(4, 7).await,
+            Err(HistoryError::NotExecuted { height: 7 })
+        );
+
+        // Once the window is full, the oldest block is discarded.
+        history
+            .insert(
+                block(8),
+                accounts(&[(alice, 60, 2), (bob, 80, 0), (carol, 10, 0)]),
+            )
+            .await;
+        assert_eq!(
+            history.diff(3, 8).await,
+            Err(HistoryError::NotRetained { height: 3 })
+        );
+        assert!(history.diff(4, 8).await.is_ok());
+
+        // Block metadata is retained for the same window.
+        assert_eq!(history.block(6).await, Ok(block(6)));
+        assert_eq!(
+            history.block(5).await,
+            Err(HistoryError::NotExecuted { height: 5 })
+        );
+        assert_eq!(
+            history.block(9).await,
+            Err(HistoryError::NotExecuted { height: 9 })
+        );
+        assert_eq!(
+            history.block(1).await,
+            Err(HistoryError::NotRetained { height: 1 })
+        );
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Execution hooks for custom transaction kinds.
+//!
+//! A payload in the rollup namespace beginning with [`CUSTOM_TRANSACTION_TAG`] is a custom
+//! transaction. The byte after the tag selects its kind, and the rest of the payload is opaque to
+//! the VM, which passes it to the [`TransactionHandler`] registered for that kind. Handlers only
+//! see the state through [`StateAccess`], which allows reading balances and moving funds between
+//! accounts, so a custom transaction can never create or destroy tokens.
+//!
+//! Every node of a rollup must register the same handlers, or their states will diverge.
+
+use crate::error::RollupError;
+use crate::state::{Amount, Nonce, State};
+use ethers::types::Address;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// First byte of every custom transaction payload.
+///
+/// Built-in transactions are JSON objects, so their payloads never begin with this byte.
+pub const CUSTOM_TRANSACTION_TAG: u8 = 0xff;
+
+/// Encode a custom transaction of kind `kind`.
+pub fn encode_custom(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(body.len() + 2);
+    payload.push(CUSTOM_TRANSACTION_TAG);
+    payload.push(kind);
+    payload.extend_from_slice(body);
+    payload
+}
+
+/// Split a custom transaction payload into its kind and body, or return `None` if `payload` is not
+/// a custom transaction.
+pub fn decode_custom(payload: &[u8]) -> Option<(u8, &[u8])> {
+    match payload {
+        [CUSTOM_TRANSACTION_TAG, kind, body @ ..] => Some((*kind, body)),
+        _ => None,
+    }
+}
+
+/// Executes custom transactions of one kind.
+pub trait TransactionHandler: Debug + Send + Sync {
+    /// Apply the custom transaction with body `body`.
+    ///
+    /// The body is not authenticated by the VM: the handler is responsible for checking that
+    /// whoever submitted the transaction may move the funds it moves. If this returns an error,
+    /// none of the changes made through `state` are applied.
+    fn apply(&self, body: &[u8], state: &mut StateAccess<'_>) -> Result<(), RollupError>;
+}
+
+/// The handlers for custom transaction kinds, by kind.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionHooks {
+    handlers: BTreeMap<u8, Arc<dyn TransactionHandler>>,
+}
+
+impl TransactionHooks {
+    /// Execute custom transactions of kind `kind` with `handler`, replacing any handler already
+    /// registered for that kind.
+    pub fn with_handler(mut self, kind: u8, handler: impl TransactionHandler + 'static) -> Self {
+        self.handlers.insert(kind, Arc::new(handler));
+        self
+    }
+
+    pub fn get(&self, kind: u8) -> Option<Arc<dyn TransactionHandler>> {
+        self.handlers.get(&kind).cloned()
+    }
+}
+
+/// The view of the state given to a [`TransactionHandler`].
+///
+/// Changes are buffered, and only applied to the state if the handler succeeds.
+#[derive(Debug)]
+pub struct StateAccess<'a> {
+    state: &'a State,
+    balances: BTreeMap<Address, Amount>,
+    transfers: Vec<(Address, Address, Amount)>,
+    // Number of reads of the underlying state, for execution metrics.
+    reads: Cell<u64>,
+}
+
+impl<'a> StateAccess<'a> {
+    pub(crate) fn new(state: &'a State) -> Self {
+        Self {
+            state,
+            balances: BTreeMap::new(),
+            transfers: vec![],
+            reads: Cell::new(0),
+        }
+    }
+
+    /// The balance of `address`, including transfers already made by this transaction.
+    pub fn balance(&self, address: &Address) -> Amount {
+        self.balances.get(address).copied().unwrap_or_else(|| {
+            self.reads.set(self.reads.get() + 1);
+            self.state.get_balance(address)
+        })
+    }
+
+    pub fn nonce(&self, address: &Address) -> Nonce {
+        self.reads.set(self.reads.get() + 1);
+        self.state.get_nonce(address)
+    }
+
+    /// Height of the HotShot block being executed.
+    pub fn block_height(&self) -> u64 {
+        self.state.block_height()
+    }
+
+    /// Move `amount` tokens from `from` to `to`.
+    pub fn transfer(
+        &mut self,
+        from: Address,
+        to: Address,
+        amount: Amount,
+    ) -> Result<(), RollupError> {
+        let exists = self.balances.contains_key(&from) || {
+            self.reads.set(self.reads.get() + 1);
+            self.state.ledger().get(&from).is_some()
+        };
+        let Some(from_balance) = self.balance(&from).checked_sub(amount).filter(|_| exists) else {
+            return Err(RollupError::InsufficientBalance { address: from });
+        };
+        if from != to && self.balance(&to).checked_add(amount).is_none() {
+            return Err(RollupError::BalanceOverflow { address: to });
+        }
+        // Debit the sender before crediting the destination, so that a transfer to oneself leaves
+        // the balance unchanged.
+        self.balances.insert(from, from_balance);
+        let to_balance = self.balance(&to) + amount;
+        self.balances.insert(to, to_balance);
+        self.transfers.push((from, to, amount));
+        Ok(())
+    }
+
+    /// Number of times the handler read the underlying state.
+    pub(crate) fn state_reads(&self) -> u64 {
+        self.reads.get()
+    }
+
+    /// The transfers made by the handler, in order.
+    pub(crate) fn into_transfers(self) -> Vec<(Address, Address, Amount)> {
+        self.transfers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::RollupEvent;
+    use crate::RollupVM;
+    use espresso_types::NamespaceId;
+    use serde::{Deserialize, Serialize};
+
+    /// Pays the same amount to each of several recipients.
+    #[derive(Debug)]
+    struct Split;
+
+    #[derive(Serialize, Deserialize)]
+    struct SplitBody {
+        from: Address,
+        to: Vec<Address>,
+        amount: Amount,
+    }
+
+    impl TransactionHandler for Split {
+        fn apply(&self, body: &[u8], state: &mut StateAccess<'_>) -> Result<(), RollupError> {
+            let body: SplitBody =
+                serde_json::from_slice(body).map_err(|err| RollupError::MalformedTransaction {
+                    reason: err.to_string(),
+                })?;
+            for to in body.to {
+                state.transfer(body.from, to, body.amount)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_custom_transaction() {
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = Address::random();
+        let bob = Address::random();
+        let carol = Address::random();
+        let mut state = State::from_initial_balances([(alice, 100)], vm)
+            .with_transaction_hooks(TransactionHooks::default().with_handler(1, Split));
+        let split = |amount| {
+            encode_custom(
+                1,
+                &serde_json::to_vec(&SplitBody {
+                    from: alice,
+                    to: vec![bob, carol],
+                    amount,
+                })
+                .unwrap(),
+            )
+        };
+
+        let payload = split(30);
+        let (kind, body) = decode_custom(&payload).unwrap();
+        state.apply_custom(kind, body).unwrap();
+        assert_eq!(state.get_balance(&alice), 40);
+        assert_eq!(state.get_balance(&bob), 30);
+        assert_eq!(state.get_balance(&carol), 30);
+        assert!(state
+            .block_events()
+            .contains(&RollupEvent::AccountCreated { address: bob }));
+
+        // The second transfer would overdraw the sender, so neither is applied.
+        let (kind, body) = decode_custom(&payload).unwrap();
+        assert_eq!(
+            state.apply_custom(kind, body),
+            Err(RollupError::InsufficientBalance { address: alice })
+        );
+        assert_eq!(state.get_balance(&alice), 40);
+        assert_eq!(state.get_balance(&bob), 30);
+
+        assert_eq!(
+            state.apply_custom(2, &[]),
+            Err(RollupError::UnknownTransactionKind { kind: 2 })
+        );
+        assert_eq!(decode_custom(b"{}"), None);
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Shared HTTP clients for calls to the HotShot query service.
+//!
+//! A surf-disco client keeps its connections alive between requests, but only for as long as the
+//! client itself lives. Creating a fresh client for every call therefore opens a new connection
+//! every time, which under catch-up load can exhaust the ephemeral ports of the host. Instead, all
+//! query service calls go through an [`HttpClientPool`], which creates one client per base URL and
+//! reuses it, and which bounds the number of requests in flight to each host.
+
+use crate::SequencerApiVersion;
+use async_std::sync::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use surf_disco::error::ClientError;
+use surf_disco::{Client, Url};
+use tokio::sync::Semaphore;
+
+pub type HotShotClient = Client<ClientError, SequencerApiVersion>;
+
+/// Settings shared by every query service client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HttpClientOptions {
+    /// Timeout for each request.
+    pub request_timeout: Duration,
+    /// Maximum number of requests in flight to any one host.
+    pub max_requests_per_host: usize,
+}
+
+impl Default for HttpClientOptions {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(30),
+            max_requests_per_host: 16,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolInner {
+    clients: HashMap<Url, HotShotClient>,
+    hosts: HashMap<String, Arc<Semaphore>>,
+}
+
+/// Query service clients shared across the node, keyed by base URL.
+#[derive(Clone, Debug, Default)]
+pub struct HttpClientPool {
+    options: HttpClientOptions,
+    inner: Arc<Mutex<PoolInner>>,
+}
+
+impl HttpClientPool {
+    pub fn new(options: HttpClientOptions) -> Self {
+        Self {
+            options,
+            inner: Default::default(),
+        }
+    }
+
+    /// The shared client for the API at `base_url`, creating it if this is the first use.
+    pub async fn client(&self, base_url: &Url) -> PooledClient {
+        let mut inner = self.inner.lock().await;
+        let client = inner
+            .clients
+            .entry(base_url.clone())
+            .or_insert_with(|| {
+                HotShotClient::builder(base_url.clone())
+                    .set_timeout(Some(self.options.request_timeout))
+                    .build()
+            })
+            .clone();
+        let host = format!(
+            "{}:{}",
+            base_url.host_str().unwrap_or_default(),
+            base_url.port_or_known_default().unwrap_or_default()
+        );
+        let permits = inner
+            .hosts
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.options.max_requests_per_host.max(1))))
+            .clone();
+        PooledClient { client, permits }
+    }
+}
+
+/// A client from an [`HttpClientPool`], whose requests count against the limit for its host.
+#[derive(Clone, Debug)]
+pub struct PooledClient {
+    client: HotShotClient,
+    permits: Arc<Semaphore>,
+}
+
+impl PooledClient {
+    pub async fn get<T: DeserializeOwned>(&self, route: &str) -> Result<T, ClientError> {
+        let _permit = self.permits.acquire().await.unwrap();
+        self.client.get::<T>(route).send().await
+    }
+
+    pub async fn post<T: DeserializeOwned>(
+        &self,
+        route: &str,
+        body: &impl Serialize,
+    ) -> Result<T, ClientError> {
+        let _permit = self.permits.acquire().await.unwrap();
+        self.client.post::<T>(route).body_json(body)?.send().await
+    }
+
+    /// The underlying client, for requests such as socket subscriptions which are not subject to
+    /// the per-host limit.
+    pub fn inner(&self) -> &HotShotClient {
+        &self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_clients_are_shared() {
+        let pool = HttpClientPool::new(HttpClientOptions {
+            max_requests_per_host: 2,
+            ..Default::default()
+        });
+        let availability: Url = "http://localhost:24000/v0/availability/".parse().unwrap();
+        let status: Url = "http://localhost:24000/v0/status/".parse().unwrap();
+        let other: Url = "http://localhost:24001/v0/availability/".parse().unwrap();
+
+        // Clients for different APIs on the same host share the host's limit.
+        let a = pool.client(&availability).await;
+        let b = pool.client(&status).await;
+        let c = pool.client(&other).await;
+        assert!(Arc::ptr_eq(&a.permits, &b.permits));
+        assert!(!Arc::ptr_eq(&a.permits, &c.permits));
+
+        let _permits = a.permits.acquire_many(2).await.unwrap();
+        assert_eq!(b.permits.available_permits(), 0);
+        assert_eq!(c.permits.available_permits(), 2);
+        assert_eq!(pool.inner.lock().await.clients.len(), 3);
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+use crate::data_source::NamespaceProofQueryData;
+use crate::http::HttpClientPool;
+use committable::{Commitment, Committable};
+use espresso_types::{Header, NamespaceId, NsProof, SeqTypes, Transaction};
+use hotshot_query_service::availability::{TransactionQueryData, VidCommonQueryData};
+use hotshot_query_service::VidCommon;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use surf_disco::error::ClientError;
+use surf_disco::Url;
+use tide_disco::{Error as _, StatusCode};
+
+/// An error that occurs while verifying an inclusion proof.
+#[derive(Clone, Debug, Snafu, PartialEq, Eq)]
+pub enum InclusionError {
+    #[snafu(display("Namespace proof does not verify against the block header."))]
+    InvalidNamespaceProof,
+    #[snafu(display("Proof is for namespace {actual}, expected {expected}."))]
+    WrongNamespace {
+        expected: NamespaceId,
+        actual: NamespaceId,
+    },
+    #[snafu(display(
+        "Transaction {index} is not in the namespace, which has {len} transactions."
+    ))]
+    IndexOutOfRange { index: usize, len: usize },
+    #[snafu(display("Transaction {index} in the namespace does not match {expected}."))]
+    TransactionMismatch {
+        index: usize,
+        expected: Commitment<Transaction>,
+    },
+}
+
+/// Proof that a transaction was sequenced in a particular HotShot block.
+///
+/// The proof is bound to the block header, so a client which trusts the header (for example,
+/// because it has been certified by the light client contract) can check that the transaction
+/// was sequenced without trusting the rollup API.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub transaction: Commitment<Transaction>,
+    pub header: Header,
+    pub namespace_proof: NsProof,
+    pub vid_common: VidCommon,
+    /// Position of the transaction among the transactions in the rollup's namespace.
+    pub index: usize,
+}
+
+impl InclusionProof {
+    /// Check that the proof shows `self.transaction` in `namespace` of the block `self.header`.
+    ///
+    /// On success, returns the sequenced transaction.
+    pub fn verify(&self, namespace: NamespaceId) -> Result<Transaction, InclusionError> {
+        let (transactions, actual) = self
+            .namespace_proof
+            .verify(
+                self.header.ns_table(),
+                &self.header.payload_commitment(),
+                &self.vid_common,
+            )
+            .ok_or(InclusionError::InvalidNamespaceProof)?;
+        if actual != namespace {
+            return Err(InclusionError::WrongNamespace {
+                expected: namespace,
+                actual,
+            });
+        }
+        let len = transactions.len();
+        let transaction =
+            transactions
+                .into_iter()
+                .nth(self.index)
+                .ok_or(InclusionError::IndexOutOfRange {
+                    index: self.index,
+                    len,
+                })?;
+        if transaction.commit() != self.transaction {
+            return Err(InclusionError::TransactionMismatch {
+                index: self.index,
+                expected: self.transaction,
+            });
+        }
+        Ok(transaction)
+    }
+}
+
+/// Build an inclusion proof for the transaction with sequencer commitment `hash`.
+///
+/// Returns `None` if the transaction has not been sequenced, or if it was not sequenced in
+/// `namespace`. Any other failure to query the sequencer, such as an outage, is an error.
+pub async fn fetch_inclusion_proof(
+    http: &HttpClientPool,
+    sequencer_url: &Url,
+    hash: &str,
+    namespace: NamespaceId,
+) -> Result<Option<InclusionProof>, ClientError> {
+    let client = http
+        .client(&sequencer_url.join("availability").unwrap())
+        .await;
+
+    let sequenced = match client
+        .get::<TransactionQueryData<SeqTypes>>(&format!("transaction/hash/{hash}"))
+        .await
+    {
+        Ok(sequenced) => sequenced,
+        Err(err) if err.status() == StatusCode::NOT_FOUND => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let transaction = sequenced.hash();
+    let proof = fetch_inclusion_proof_at(
+        http,
+        sequencer_url,
+        sequenced.block_height(),
+        namespace,
+        |txn| txn.commit() == transaction,
+    )
+    .await?;
+    Ok(proof.map(|(proof, _)| proof))
+}
+
+/// Build an inclusion proof for the first transaction in `namespace` of the block at `height`
+/// which satisfies `find`, returning the proof along with the transaction.
+///
+/// Returns `None` if the block does not contain such a transaction.
+pub async fn fetch_inclusion_proof_at(
+    http: &HttpClientPool,
+    sequencer_url: &Url,
+    height: u64,
+    namespace: NamespaceId,
+    find: impl Fn(&Transaction) -> bool,
+) -> Result<Option<(InclusionProof, Transaction)>, ClientError> {
+    let client = http
+        .client(&sequencer_url.join("availability").unwrap())
+        .await;
+
+    let header = client.get::<Header>(&format!("header/{height}")).await?;
+    let Some(namespace_proof) = client
+        .get::<NamespaceProofQueryData>(&format!("block/{height}/namespace/{namespace}"))
+        .await?
+        .proof
+    else {
+        return Ok(None);
+    };
+    let vid_common = client
+        .get::<VidCommonQueryData<SeqTypes>>(&format!("vid/common/{height}"))
+        .await?
+        .common()
+        .clone();
+
+    // Locate the transaction within the namespace.
+    let Some((transactions, _)) =
+        namespace_proof.verify(header.ns_table(), &header.payload_commitment(), &vid_common)
+    else {
+        return Ok(None);
+    };
+    let Some(index) = transactions.iter().position(find) else {
+        return Ok(None);
+    };
+    let transaction = transactions.into_iter().nth(index).unwrap();
+
+    let proof = InclusionProof {
+        transaction: transaction.commit(),
+        header,
+        namespace_proof,
+        vid_common,
+        index,
+    };
+    Ok(Some((proof, transaction)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mock_block;
+
+    #[async_std::test]
+    async fn test_verify_inclusion_proof() {
+        let namespace = NamespaceId::from(1_u64);
+        let other = NamespaceId::from(2_u64);
+        let payloads = vec![b"first".to_vec(), b"second".to_vec()];
+        let block = mock_block(
+            namespace,
+            &[
+                (namespace, payloads.clone()),
+                (other, vec![b"other".to_vec()]),
+            ],
+        )
+        .await;
+        let proof = InclusionProof {
+            transaction: Transaction::new(namespace, payloads[1].clone()).commit(),
+            header: block.header,
+            namespace_proof: block.namespace_proof.unwrap(),
+            vid_common: block.vid_common.unwrap(),
+            index: 1,
+        };
+        assert_eq!(
+            proof.verify(namespace),
+            Ok(Transaction::new(namespace, payloads[1].clone()))
+        );
+
+        // The proof is only valid for the rollup's namespace.
+        assert_eq!(
+            proof.verify(other),
+            Err(InclusionError::WrongNamespace {
+                expected: other,
+                actual: namespace,
+            })
+        );
+
+        // The transaction must be at the claimed index.
+        let mut wrong_index = proof.clone();
+        wrong_index.index = 0;
+        assert_eq!(
+            wrong_index.verify(namespace),
+            Err(InclusionError::TransactionMismatch {
+                index: 0,
+                expected: proof.transaction,
+            })
+        );
+        let mut out_of_range = proof.clone();
+        out_of_range.index = 2;
+        assert_eq!(
+            out_of_range.verify(namespace),
+            Err(InclusionError::IndexOutOfRange { index: 2, len: 2 })
+        );
+
+        // The namespace proof must be for the block of the header.
+        let other_block = mock_block(namespace, &[(namespace, vec![b"third".to_vec()])]).await;
+        let mut wrong_header = proof;
+        wrong_header.header = other_block.header;
+        assert_eq!(
+            wrong_header.verify(namespace),
+            Err(InclusionError::InvalidNamespaceProof)
+        );
+    }
+}
@@ -0,0 +1,1430 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Interaction with the layer 1.
+//!
+//! The [`L1Client`] and [`L1Deployer`] interfaces and the proof format the client submits are
+//! always available. Connecting to the L1 and following the light client contract require the
+//! `executor` feature.
+
+use crate::prover::ProofShape;
+use crate::withdrawal::WithdrawalsRoot;
+use clap::ValueEnum;
+use ethers::{
+    types::{Address, H256},
+    utils::keccak256,
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::fmt::Debug;
+use strum_macros::Display;
+
+#[cfg(feature = "executor")]
+use {
+    crate::{
+        clock::Clock,
+        executor::connect_rpc_with_signer,
+        secret::redact_url,
+        signer::{L1SignerConfig, Signer},
+    },
+    async_std::channel::Sender,
+    async_trait::async_trait,
+    contract_bindings::example_rollup::{self, ExampleRollup, ExampleRollupErrors},
+    ethers::{
+        contract::{ContractCall, LogMeta},
+        middleware::SignerMiddleware,
+        providers::{Http, HttpClientError, JsonRpcClient, Middleware, Provider, RpcError, Ws},
+        signers::LocalWallet,
+        types::{BlockNumber, U256},
+    },
+    futures::{future::FutureExt, StreamExt},
+    hotshot_contract_bindings::light_client::{LightClient, NewStateFilter},
+    serde::de::DeserializeOwned,
+    std::{
+        str::FromStr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    surf_disco::Url,
+};
+
+/// Delay between attempts to reconnect to the L1 websocket provider.
+#[cfg(feature = "executor")]
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// An error returned by an [`L1Client`].
+#[derive(Clone, Debug, Snafu)]
+pub enum L1Error {
+    #[snafu(display("Error connecting to L1: {message}"))]
+    Connection { message: String },
+    #[snafu(display("Error submitting transaction to L1: {message}"))]
+    Submission { message: String },
+}
+
+/// A batch proof as accepted by the rollup contract, with each field a big-endian 256-bit word.
+///
+/// This is independent of the Ethereum client library used to submit it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchProofInput {
+    pub first_block: [u8; 32],
+    pub last_block: [u8; 32],
+    pub old_state: [u8; 32],
+    pub new_state: [u8; 32],
+    /// The state commitment after each block with a proof in the batch, ending in `new_state`.
+    ///
+    /// Only submitted on-chain with [`ProofShape::Full`].
+    pub commitments: Vec<[u8; 32]>,
+    /// The root of the withdrawal tree in `new_state`, if the batch added withdrawals.
+    ///
+    /// A batch with withdrawals is submitted with `verifyBlocksWithWithdrawals`, which posts the
+    /// root along with the commitments digest, whatever the [`ProofShape`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub withdrawals: Option<WithdrawalsRoot>,
+}
+
+impl BatchProofInput {
+    /// Hash chain digest of `commitments`, as computed by the rollup contract.
+    pub fn commitments_digest(&self) -> [u8; 32] {
+        self.commitments.iter().fold([0; 32], |digest, commitment| {
+            keccak256([digest, *commitment].concat())
+        })
+    }
+}
+
+#[cfg(feature = "executor")]
+fn u256_to_bytes(value: U256) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+#[cfg(feature = "executor")]
+impl From<example_rollup::BatchProof> for BatchProofInput {
+    fn from(proof: example_rollup::BatchProof) -> Self {
+        let new_state = u256_to_bytes(proof.new_state);
+        Self {
+            first_block: u256_to_bytes(proof.first_block),
+            last_block: u256_to_bytes(proof.last_block),
+            old_state: u256_to_bytes(proof.old_state),
+            new_state,
+            commitments: vec![new_state],
+            withdrawals: (proof.num_withdrawals > 0).then_some(WithdrawalsRoot {
+                root: proof.withdrawals_root,
+                count: proof.num_withdrawals,
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "executor")]
+impl From<&BatchProofInput> for example_rollup::BatchProof {
+    fn from(proof: &BatchProofInput) -> Self {
+        // The contract only checks the withdrawal tree of batches which add withdrawals.
+        let withdrawals = proof.withdrawals.unwrap_or_default();
+        Self {
+            first_block: U256::from_big_endian(&proof.first_block),
+            last_block: U256::from_big_endian(&proof.last_block),
+            old_state: U256::from_big_endian(&proof.old_state),
+            new_state: U256::from_big_endian(&proof.new_state),
+            withdrawals_root: withdrawals.root,
+            num_withdrawals: withdrawals.count,
+        }
+    }
+}
+
+/// The L1 operations performed by the executor.
+///
+/// The executor submits proofs through an `L1Client` rather than a particular Ethereum client
+/// library, so that the ethers-based implementation can be swapped for another one, such as the
+/// alloy implementation enabled by the `alloy` feature.
+pub trait L1Client: Debug + Send + Sync {
+    /// The nonce of the next transaction sent by the executor's account, including transactions
+    /// which are still pending.
+    fn next_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>>;
+
+    /// The nonce of the next transaction sent by the executor's account, counting only
+    /// transactions which have been mined.
+    fn confirmed_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>>;
+
+    /// Broadcast a transaction with the given `nonce` submitting `proof` to the rollup contract in
+    /// the given shape, verifying the next `count` blocks.
+    ///
+    /// Returns the hash of the transaction without waiting for it to be included.
+    fn send_verify_blocks(
+        &self,
+        count: u64,
+        proof: BatchProofInput,
+        shape: ProofShape,
+        nonce: u64,
+    ) -> BoxFuture<'_, Result<H256, L1Error>>;
+
+    /// Whether the transaction `hash` succeeded, or `None` if it has not been included.
+    fn transaction_status(&self, hash: H256) -> BoxFuture<'_, Result<Option<bool>, L1Error>>;
+
+    /// The gas used by the transaction `hash`, or `None` if it has not been included or the client
+    /// does not report gas.
+    fn gas_used(&self, _hash: H256) -> BoxFuture<'_, Result<Option<u64>, L1Error>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// The state commitment currently stored by the rollup contract, as a big-endian word.
+    fn state_commitment(&self) -> BoxFuture<'_, Result<[u8; 32], L1Error>>;
+
+    /// The number of blocks verified by the rollup contract, which is also the height of the first
+    /// block it has not verified.
+    fn num_verified_blocks(&self) -> BoxFuture<'_, Result<u64, L1Error>>;
+
+    /// The HotShot block height finalized by the light client which the rollup contract checks
+    /// batches against, or `None` if the client does not read it.
+    fn finalized_height(&self) -> BoxFuture<'_, Result<Option<u64>, L1Error>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// The most recent `StateUpdate` event emitted by the rollup contract, or `None` if it has not
+    /// verified any blocks.
+    fn last_state_update(&self) -> BoxFuture<'_, Result<Option<StateUpdate>, L1Error>>;
+}
+
+/// A rollup contract deployed by an [`L1Deployer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RollupDeployment {
+    pub rollup_address: Address,
+    /// The account which sent the deployment transaction.
+    pub deployer: Address,
+    pub tx_hash: H256,
+}
+
+/// Deployment of the rollup contract.
+///
+/// Like [`L1Client`], this is independent of the Ethereum client library which sends the
+/// deployment transaction.
+pub trait L1Deployer: Debug + Send + Sync {
+    /// Deploy a rollup contract starting from the state commitment `initial_state`, a big-endian
+    /// word, which checks batches against the light client at `light_client_address` and only
+    /// accepts them from `prover`.
+    ///
+    /// `genesis_supply` wei are sent with the deployment to fund the deposit escrow, so that the
+    /// genesis balances can be withdrawn.
+    fn deploy_rollup(
+        &self,
+        initial_state: [u8; 32],
+        genesis_supply: u128,
+        light_client_address: Address,
+        prover: Address,
+    ) -> BoxFuture<'_, Result<RollupDeployment, L1Error>>;
+}
+
+/// A `StateUpdate` event, emitted by the rollup contract when it verifies a batch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StateUpdate {
+    /// The number of blocks verified by the contract after the batch.
+    pub num_verified_blocks: u64,
+    /// The state commitment after the batch, as a big-endian word.
+    pub state_commitment: [u8; 32],
+}
+
+/// Ethereum client library used to interact with the L1.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum L1ClientKind {
+    #[default]
+    Ethers,
+    /// Requires the `alloy` feature.
+    #[cfg(feature = "alloy")]
+    Alloy,
+}
+
+/// Number of consecutive failed requests after which a [`ClientPool`] moves to its next endpoint.
+#[cfg(feature = "executor")]
+pub const DEFAULT_MAX_FAILURES: u32 = 3;
+
+/// Interval at which providers from a [`ClientPool`] poll for new blocks and transactions.
+#[cfg(feature = "executor")]
+pub const DEFAULT_POLLING_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A provider which sends its requests through a [`ClientPool`].
+#[cfg(feature = "executor")]
+pub type L1Provider = Provider<ClientPool>;
+
+/// Health of one of the endpoints of a [`ClientPool`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointHealth {
+    pub url: String,
+    /// Whether requests are currently sent to this endpoint.
+    pub active: bool,
+    pub consecutive_failures: u32,
+    pub total_requests: u64,
+    pub total_failures: u64,
+    pub last_error: Option<String>,
+}
+
+#[cfg(feature = "executor")]
+#[derive(Debug)]
+struct PoolState {
+    active: usize,
+    health: Vec<EndpointHealth>,
+}
+
+/// The L1 JSON-RPC endpoints shared by every part of the node which talks to the L1 over HTTP.
+///
+/// Requests go to a single active endpoint. Failures to reach it are counted, while error
+/// responses from the node itself are not, since they show the endpoint is up. After
+/// `max_failures` consecutive failures the pool rotates to the next endpoint, so a node configured
+/// with fallbacks survives an outage of its primary provider. Clones share the same health state.
+#[cfg(feature = "executor")]
+#[derive(Clone, Debug)]
+pub struct ClientPool {
+    endpoints: Arc<Vec<Http>>,
+    polling_interval: Duration,
+    max_failures: u32,
+    state: Arc<Mutex<PoolState>>,
+}
+
+#[cfg(feature = "executor")]
+impl ClientPool {
+    /// A pool which sends requests to `primary`, falling back to each of `fallbacks` in turn.
+    pub fn new(primary: &Url, fallbacks: impl IntoIterator<Item = Url>) -> Result<Self, L1Error> {
+        let urls: Vec<_> = std::iter::once(primary.clone()).chain(fallbacks).collect();
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                Http::from_str(url.as_str()).map_err(|err| L1Error::Connection {
+                    message: format!("invalid L1 provider {}: {err}", redact_url(url)),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let health = urls
+            .iter()
+            .enumerate()
+            .map(|(index, url)| EndpointHealth {
+                url: redact_url(url),
+                active: index == 0,
+                consecutive_failures: 0,
+                total_requests: 0,
+                total_failures: 0,
+                last_error: None,
+            })
+            .collect();
+        Ok(Self {
+            endpoints: Arc::new(endpoints),
+            polling_interval: DEFAULT_POLLING_INTERVAL,
+            max_failures: DEFAULT_MAX_FAILURES,
+            state: Arc::new(Mutex::new(PoolState { active: 0, health })),
+        })
+    }
+
+    pub fn with_polling_interval(mut self, interval: Duration) -> Self {
+        self.polling_interval = interval;
+        self
+    }
+
+    pub fn with_max_failures(mut self, max_failures: u32) -> Self {
+        self.max_failures = max_failures.max(1);
+        self
+    }
+
+    /// A provider sending requests through this pool, polling at the configured interval.
+    pub fn provider(&self) -> L1Provider {
+        Provider::new(self.clone()).interval(self.polling_interval)
+    }
+
+    /// A provider for the currently active endpoint alone.
+    ///
+    /// This is for APIs which require a plain HTTP provider. Requests through it do not fail over
+    /// and do not count towards the health of the endpoint.
+    pub fn http_provider(&self) -> Provider<Http> {
+        Provider::new(self.endpoints[self.active()].clone()).interval(self.polling_interval)
+    }
+
+    /// The URL of the currently active endpoint.
+    pub fn url(&self) -> Url {
+        self.endpoints[self.active()].url().clone()
+    }
+
+    pub fn polling_interval(&self) -> Duration {
+        self.polling_interval
+    }
+
+    /// The health of each endpoint, starting with the primary.
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.state.lock().unwrap().health.clone()
+    }
+
+    fn active(&self) -> usize {
+        self.state.lock().unwrap().active
+    }
+
+    /// Record the outcome of a request sent to endpoint `index`, rotating to the next endpoint if
+    /// it has now failed `max_failures` times in a row.
+    fn record(&self, index: usize, error: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        let health = &mut state.health[index];
+        health.total_requests += 1;
+        let Some(error) = error else {
+            health.consecutive_failures = 0;
+            return;
+        };
+        health.consecutive_failures += 1;
+        health.total_failures += 1;
+        health.last_error = Some(error);
+        if health.consecutive_failures < self.max_failures
+            || state.active != index
+            || self.endpoints.len() < 2
+        {
+            return;
+        }
+
+        let next = (index + 1) % self.endpoints.len();
+        tracing::warn!(
+            "L1 provider {} failed {} requests in a row, switching to {}",
+            state.health[index].url,
+            state.health[index].consecutive_failures,
+            state.health[next].url
+        );
+        state.health[index].active = false;
+        state.health[next].active = true;
+        state.active = next;
+    }
+}
+
+#[cfg(feature = "executor")]
+#[async_trait]
+impl JsonRpcClient for ClientPool {
+    type Error = HttpClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let index = self.active();
+        let res = self.endpoints[index].request(method, params).await;
+        match &res {
+            // The node answered, even if it rejected the request.
+            Err(err) if err.as_error_response().is_none() => {
+                self.record(index, Some(err.to_string()))
+            }
+            _ => self.record(index, None),
+        }
+        res
+    }
+}
+
+/// Connect to the rollup contract at `rollup_address` through `l1`, signing transactions with
+/// `signer`.
+#[cfg(feature = "executor")]
+pub async fn connect_l1_client(
+    kind: L1ClientKind,
+    l1: &ClientPool,
+    signer: &L1SignerConfig,
+    rollup_address: Address,
+) -> Result<Arc<dyn L1Client>, L1Error> {
+    match (kind, signer) {
+        (L1ClientKind::Ethers, L1SignerConfig::Mnemonic { .. }) => {
+            let wallet = signer.local_wallet().unwrap()?;
+            Ok(Arc::new(
+                EthersL1Client::connect(l1, wallet, rollup_address).await?,
+            ))
+        }
+        #[cfg(feature = "aws-kms")]
+        (L1ClientKind::Ethers, L1SignerConfig::AwsKms { key_id }) => {
+            let chain_id = l1
+                .provider()
+                .get_chainid()
+                .await
+                .map_err(|err| L1Error::Connection {
+                    message: format!("error getting chain ID: {err}"),
+                })?
+                .as_u64();
+            let signer = crate::signer::aws_kms_signer(key_id, chain_id).await?;
+            Ok(Arc::new(
+                EthersL1Client::connect(l1, signer, rollup_address).await?,
+            ))
+        }
+        #[cfg(feature = "alloy")]
+        (
+            L1ClientKind::Alloy,
+            L1SignerConfig::Mnemonic {
+                mnemonic,
+                account_index,
+            },
+            // The alloy client has its own transport, which does not fail over to other endpoints.
+        ) => Ok(Arc::new(alloy_client::connect(
+            &l1.url(),
+            mnemonic.expose(),
+            *account_index,
+            rollup_address,
+        )?)),
+        #[cfg(all(feature = "alloy", feature = "aws-kms"))]
+        (L1ClientKind::Alloy, L1SignerConfig::AwsKms { .. }) => Err(L1Error::Connection {
+            message: "the alloy L1 client does not support AWS KMS signers".into(),
+        }),
+    }
+}
+
+/// An [`L1Client`] implemented with ethers.
+#[cfg(feature = "executor")]
+#[derive(Debug)]
+pub struct EthersL1Client<S: Signer = LocalWallet> {
+    rollup: ExampleRollup<SignerMiddleware<L1Provider, S>>,
+}
+
+#[cfg(feature = "executor")]
+impl<S: Signer + 'static> EthersL1Client<S> {
+    pub async fn connect(
+        l1: &ClientPool,
+        signer: S,
+        rollup_address: Address,
+    ) -> Result<Self, L1Error> {
+        let l1 = connect_rpc_with_signer(l1, signer, None)
+            .await
+            .ok_or_else(|| L1Error::Connection {
+                message: format!("unable to connect to {}", l1.url()),
+            })?;
+        Ok(Self {
+            rollup: ExampleRollup::new(rollup_address, Arc::new(l1)),
+        })
+    }
+}
+
+#[cfg(feature = "executor")]
+impl<S: Signer + 'static> EthersL1Client<S> {
+    fn verify_blocks_call(
+        &self,
+        count: u64,
+        proof: &BatchProofInput,
+        shape: ProofShape,
+    ) -> ContractCall<SignerMiddleware<L1Provider, S>, ()> {
+        let endpoints = example_rollup::BatchProof::from(proof);
+        let new_state = endpoints.new_state;
+        if let Some(withdrawals) = proof.withdrawals {
+            return self.rollup.verify_blocks_with_withdrawals(
+                count,
+                new_state,
+                endpoints,
+                proof.commitments_digest(),
+                withdrawals.root,
+                withdrawals.count,
+            );
+        }
+        match shape {
+            ProofShape::Endpoints => self.rollup.verify_blocks(count, new_state, endpoints),
+            ProofShape::Full => {
+                let commitments = proof
+                    .commitments
+                    .iter()
+                    .map(|commitment| U256::from_big_endian(commitment))
+                    .collect();
+                self.rollup
+                    .verify_blocks_with_commitments(count, new_state, endpoints, commitments)
+            }
+            ProofShape::Compressed => self.rollup.verify_blocks_compressed(
+                count,
+                new_state,
+                endpoints,
+                proof.commitments_digest(),
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "executor")]
+impl<S: Signer + 'static> L1Client for EthersL1Client<S> {
+    fn next_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+        async move {
+            let client = self.rollup.client();
+            client
+                .get_transaction_count(client.address(), Some(BlockNumber::Pending.into()))
+                .await
+                .map(|nonce| nonce.as_u64())
+                .map_err(|err| L1Error::Connection {
+                    message: err.to_string(),
+                })
+        }
+        .boxed()
+    }
+
+    fn confirmed_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+        async move {
+            let client = self.rollup.client();
+            client
+                .get_transaction_count(client.address(), Some(BlockNumber::Latest.into()))
+                .await
+                .map(|nonce| nonce.as_u64())
+                .map_err(|err| L1Error::Connection {
+                    message: err.to_string(),
+                })
+        }
+        .boxed()
+    }
+
+    fn send_verify_blocks(
+        &self,
+        count: u64,
+        proof: BatchProofInput,
+        shape: ProofShape,
+        nonce: u64,
+    ) -> BoxFuture<'_, Result<H256, L1Error>> {
+        async move {
+            let call = self.verify_blocks_call(count, &proof, shape).nonce(nonce);
+            match call.send().await {
+                Ok(pending) => Ok(pending.tx_hash()),
+                Err(err) => {
+                    let message = match err.decode_contract_revert::<ExampleRollupErrors>() {
+                        Some(reason) => format!("{reason:?}"),
+                        None => err.to_string(),
+                    };
+                    Err(L1Error::Submission { message })
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn transaction_status(&self, hash: H256) -> BoxFuture<'_, Result<Option<bool>, L1Error>> {
+        async move {
+            let receipt = self
+                .rollup
+                .client()
+                .get_transaction_receipt(hash)
+                .await
+                .map_err(|err| L1Error::Connection {
+                    message: err.to_string(),
+                })?;
+            Ok(receipt.map(|receipt| receipt.status == Some(1.into())))
+        }
+        .boxed()
+    }
+
+    fn finalized_height(&self) -> BoxFuture<'_, Result<Option<u64>, L1Error>> {
+        async move {
+            let address =
+                self.rollup
+                    .light_client()
+                    .call()
+                    .await
+                    .map_err(|err| L1Error::Connection {
+                        message: err.to_string(),
+                    })?;
+            let (_, block_height, _, _) = LightClient::new(address, self.rollup.client())
+                .finalized_state()
+                .call()
+                .await
+                .map_err(|err| L1Error::Connection {
+                    message: err.to_string(),
+                })?;
+            Ok(Some(block_height))
+        }
+        .boxed()
+    }
+
+    fn gas_used(&self, hash: H256) -> BoxFuture<'_, Result<Option<u64>, L1Error>> {
+        async move {
+            let receipt = self
+                .rollup
+                .client()
+                .get_transaction_receipt(hash)
+                .await
+                .map_err(|err| L1Error::Connection {
+                    message: err.to_string(),
+                })?;
+            Ok(receipt
+                .and_then(|receipt| receipt.gas_used)
+                .map(|gas| gas.low_u64()))
+        }
+        .boxed()
+    }
+
+    fn state_commitment(&self) -> BoxFuture<'_, Result<[u8; 32], L1Error>> {
+        async move {
+            self.rollup
+                .state_commitment()
+                .call()
+                .await
+                .map(u256_to_bytes)
+                .map_err(|err| L1Error::Connection {
+                    message: err.to_string(),
+                })
+        }
+        .boxed()
+    }
+
+    fn num_verified_blocks(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+        async move {
+            self.rollup
+                .num_verified_blocks()
+                .call()
+                .await
+                .map(|count| count.as_u64())
+                .map_err(|err| L1Error::Connection {
+                    message: err.to_string(),
+                })
+        }
+        .boxed()
+    }
+
+    fn last_state_update(&self) -> BoxFuture<'_, Result<Option<StateUpdate>, L1Error>> {
+        async move {
+            let events = self
+                .rollup
+                .state_update_filter()
+                .from_block(0)
+                .query()
+                .await
+                .map_err(|err| L1Error::Connection {
+                    message: err.to_string(),
+                })?;
+            Ok(events.last().map(|event| StateUpdate {
+                num_verified_blocks: event.block_height.as_u64(),
+                state_commitment: u256_to_bytes(event.state_commitment),
+            }))
+        }
+        .boxed()
+    }
+}
+
+/// An [`L1Deployer`] implemented with ethers, deploying from the account of `client`.
+#[cfg(feature = "executor")]
+#[derive(Debug)]
+pub struct EthersL1Deployer<M> {
+    client: Arc<M>,
+}
+
+#[cfg(feature = "executor")]
+impl<M> EthersL1Deployer<M> {
+    pub fn new(client: Arc<M>) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(feature = "executor")]
+impl<M: Middleware + 'static> L1Deployer for EthersL1Deployer<M> {
+    fn deploy_rollup(
+        &self,
+        initial_state: [u8; 32],
+        genesis_supply: u128,
+        light_client_address: Address,
+        prover: Address,
+    ) -> BoxFuture<'_, Result<RollupDeployment, L1Error>> {
+        async move {
+            let submission_error = |err: ethers::contract::ContractError<M>| L1Error::Submission {
+                message: err.to_string(),
+            };
+            let (rollup, receipt) = ExampleRollup::deploy(
+                self.client.clone(),
+                (
+                    light_client_address,
+                    U256::from_big_endian(&initial_state),
+                    prover,
+                ),
+            )
+            .map_err(submission_error)?
+            .value(genesis_supply)
+            .send_with_receipt()
+            .await
+            .map_err(submission_error)?;
+            Ok(RollupDeployment {
+                rollup_address: rollup.address(),
+                deployer: receipt.from,
+                tx_hash: receipt.transaction_hash,
+            })
+        }
+        .boxed()
+    }
+}
+
+#[cfg(feature = "alloy")]
+mod alloy_client {
+    use super::{BatchProofInput, L1Client, L1Error, ProofShape, StateUpdate};
+    use alloy::{
+        network::EthereumWallet,
+        primitives::{Address, B256, U256},
+        providers::{Provider, ProviderBuilder},
+        signers::local::{coins_bip39::English, MnemonicBuilder},
+        sol,
+        transports::http::{Client, Http},
+    };
+    use ethers::types::H256;
+    use futures::future::{BoxFuture, FutureExt};
+    use std::fmt::Debug;
+    use surf_disco::Url;
+
+    sol!(
+        #[sol(rpc)]
+        ExampleRollup,
+        "contract-bindings/artifacts/ExampleRollup.json"
+    );
+
+    /// An [`L1Client`] implemented with alloy.
+    struct AlloyL1Client<P> {
+        rollup: ExampleRollup::ExampleRollupInstance<Http<Client>, P>,
+        account: Address,
+    }
+
+    impl<P> Debug for AlloyL1Client<P> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("AlloyL1Client")
+                .field("rollup", self.rollup.address())
+                .field("account", &self.account)
+                .finish()
+        }
+    }
+
+    pub(super) fn connect(
+        http_url: &Url,
+        mnemonic: &str,
+        account_index: u32,
+        rollup_address: ethers::types::Address,
+    ) -> Result<impl L1Client, L1Error> {
+        let signer = MnemonicBuilder::<English>::default()
+            .phrase(mnemonic)
+            .index(account_index)
+            .and_then(|builder| builder.build())
+            .map_err(|err| L1Error::Connection {
+                message: format!("error opening wallet: {err}"),
+            })?;
+        let account = signer.address();
+        let provider = ProviderBuilder::new()
+            .with_recommended_fillers()
+            .wallet(EthereumWallet::from(signer))
+            .on_http(http_url.clone());
+        Ok(AlloyL1Client {
+            rollup: ExampleRollup::new(Address::from(rollup_address.0), provider),
+            account,
+        })
+    }
+
+    impl<P: Provider<Http<Client>>> L1Client for AlloyL1Client<P> {
+        fn next_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            async move {
+                self.rollup
+                    .provider()
+                    .get_transaction_count(self.account)
+                    .pending()
+                    .await
+                    .map_err(|err| L1Error::Connection {
+                        message: err.to_string(),
+                    })
+            }
+            .boxed()
+        }
+
+        fn confirmed_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            async move {
+                self.rollup
+                    .provider()
+                    .get_transaction_count(self.account)
+                    .latest()
+                    .await
+                    .map_err(|err| L1Error::Connection {
+                        message: err.to_string(),
+                    })
+            }
+            .boxed()
+        }
+
+        fn send_verify_blocks(
+            &self,
+            count: u64,
+            proof: BatchProofInput,
+            shape: ProofShape,
+            nonce: u64,
+        ) -> BoxFuture<'_, Result<H256, L1Error>> {
+            async move {
+                let new_state = U256::from_be_bytes(proof.new_state);
+                let withdrawals_tree = proof.withdrawals.unwrap_or_default();
+                let endpoints = ExampleRollup::BatchProof {
+                    firstBlock: U256::from_be_bytes(proof.first_block),
+                    lastBlock: U256::from_be_bytes(proof.last_block),
+                    oldState: U256::from_be_bytes(proof.old_state),
+                    newState: new_state,
+                    withdrawalsRoot: B256::from(withdrawals_tree.root),
+                    numWithdrawals: withdrawals_tree.count,
+                };
+                let pending = match (shape, proof.withdrawals) {
+                    (_, Some(withdrawals)) => {
+                        let digest = B256::from(proof.commitments_digest());
+                        self.rollup
+                            .verifyBlocksWithWithdrawals(
+                                count,
+                                new_state,
+                                endpoints,
+                                digest,
+                                B256::from(withdrawals.root),
+                                withdrawals.count,
+                            )
+                            .nonce(nonce)
+                            .send()
+                            .await
+                    }
+                    (ProofShape::Endpoints, None) => {
+                        self.rollup
+                            .verifyBlocks(count, new_state, endpoints)
+                            .nonce(nonce)
+                            .send()
+                            .await
+                    }
+                    (ProofShape::Full, None) => {
+                        let commitments = proof
+                            .commitments
+                            .iter()
+                            .map(|commitment| U256::from_be_bytes(*commitment))
+                            .collect();
+                        self.rollup
+                            .verifyBlocksWithCommitments(count, new_state, endpoints, commitments)
+                            .nonce(nonce)
+                            .send()
+                            .await
+                    }
+                    (ProofShape::Compressed, None) => {
+                        let digest = B256::from(proof.commitments_digest());
+                        self.rollup
+                            .verifyBlocksCompressed(count, new_state, endpoints, digest)
+                            .nonce(nonce)
+                            .send()
+                            .await
+                    }
+                };
+                let pending = pending.map_err(|err| L1Error::Submission {
+                    message: err.to_string(),
+                })?;
+                Ok(H256(pending.tx_hash().0))
+            }
+            .boxed()
+        }
+
+        fn transaction_status(&self, hash: H256) -> BoxFuture<'_, Result<Option<bool>, L1Error>> {
+            async move {
+                let receipt = self
+                    .rollup
+                    .provider()
+                    .get_transaction_receipt(B256::from(hash.0))
+                    .await
+                    .map_err(|err| L1Error::Connection {
+                        message: err.to_string(),
+                    })?;
+                Ok(receipt.map(|receipt| receipt.status()))
+            }
+            .boxed()
+        }
+
+        fn gas_used(&self, hash: H256) -> BoxFuture<'_, Result<Option<u64>, L1Error>> {
+            async move {
+                let receipt = self
+                    .rollup
+                    .provider()
+                    .get_transaction_receipt(B256::from(hash.0))
+                    .await
+                    .map_err(|err| L1Error::Connection {
+                        message: err.to_string(),
+                    })?;
+                Ok(receipt.and_then(|receipt| u64::try_from(receipt.gas_used).ok()))
+            }
+            .boxed()
+        }
+
+        fn state_commitment(&self) -> BoxFuture<'_, Result<[u8; 32], L1Error>> {
+            async move {
+                self.rollup
+                    .stateCommitment()
+                    .call()
+                    .await
+                    .map(|commitment| commitment._0.to_be_bytes())
+                    .map_err(|err| L1Error::Connection {
+                        message: err.to_string(),
+                    })
+            }
+            .boxed()
+        }
+
+        fn num_verified_blocks(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            async move {
+                self.rollup
+                    .numVerifiedBlocks()
+                    .call()
+                    .await
+                    .map(|count| count._0.to::<u64>())
+                    .map_err(|err| L1Error::Connection {
+                        message: err.to_string(),
+                    })
+            }
+            .boxed()
+        }
+
+        fn last_state_update(&self) -> BoxFuture<'_, Result<Option<StateUpdate>, L1Error>> {
+            async move {
+                let events = self
+                    .rollup
+                    .StateUpdate_filter()
+                    .from_block(0)
+                    .query()
+                    .await
+                    .map_err(|err| L1Error::Connection {
+                        message: err.to_string(),
+                    })?;
+                Ok(events.last().map(|(event, _)| StateUpdate {
+                    num_verified_blocks: event.blockHeight.to::<u64>(),
+                    state_commitment: event.stateCommitment.to_be_bytes(),
+                }))
+            }
+            .boxed()
+        }
+    }
+}
+
+/// A `NewState` event emitted by the light client contract.
+#[cfg(feature = "executor")]
+#[derive(Clone, Debug)]
+pub struct LightClientEvent {
+    pub event: NewStateFilter,
+    /// UNIX timestamp, in seconds, of the L1 block which emitted the event, or `None` if it could
+    /// not be read from the L1.
+    pub l1_timestamp: Option<u64>,
+}
+
+/// Follow `NewState` events emitted by the light client contract, forwarding them to `events` with
+/// the timestamps of the L1 blocks which emitted them.
+///
+/// Events are received over a websocket subscription. If the connection drops, the subscription is
+/// re-established starting from the last block in which an event was seen, and events which have
+/// already been forwarded are skipped. While the websocket provider is unavailable, events are
+/// instead polled over HTTP through `l1`.
+///
+/// Returns once the receiving end of `events` is closed.
+#[cfg(feature = "executor")]
+pub async fn follow_light_client(
+    ws_url: Url,
+    l1: ClientPool,
+    light_client_address: Address,
+    clock: Arc<dyn Clock>,
+    events: Sender<LightClientEvent>,
+) {
+    let mut cursor = EventCursor::default();
+    while !events.is_closed() {
+        match Provider::<Ws>::connect(ws_url.as_str()).await {
+            Ok(provider) => {
+                let light_client = LightClient::new(light_client_address, Arc::new(provider));
+                let filter = light_client
+                    .new_state_filter()
+                    .from_block(cursor.from_block());
+                match filter.subscribe_with_meta().await {
+                    Ok(mut stream) => {
+                        tracing::info!(
+                            "Subscribed to light client events from L1 block {}",
+                            cursor.from_block()
+                        );
+                        while let Some(event) = stream.next().await {
+                            match event {
+                                Ok((event, meta)) => {
+                                    if !forward_event(&l1, &mut cursor, event, &meta, &events).await
+                                    {
+                                        return;
+                                    }
+                                }
+                                Err(err) => {
+                                    tracing::error!("Error in light client stream: {err}");
+                                }
+                            }
+                        }
+                        tracing::warn!("Light client event stream ended, reconnecting");
+                    }
+                    Err(err) => {
+                        tracing::warn!("Unable to subscribe to L1 log stream: {err}");
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "Unable to make websocket connection to L1, polling over HTTP: {err}"
+                );
+                if !poll_light_client(&l1, light_client_address, &mut cursor, &events).await {
+                    return;
+                }
+            }
+        }
+        clock.sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Fetch any new light client events over HTTP.
+///
+/// Returns `false` if the receiving end of `events` has been closed.
+#[cfg(feature = "executor")]
+async fn poll_light_client(
+    l1: &ClientPool,
+    light_client_address: Address,
+    cursor: &mut EventCursor,
+    events: &Sender<LightClientEvent>,
+) -> bool {
+    let light_client = LightClient::new(light_client_address, Arc::new(l1.provider()));
+    let logs = match light_client
+        .new_state_filter()
+        .from_block(cursor.from_block())
+        .query_with_meta()
+        .await
+    {
+        Ok(logs) => logs,
+        Err(err) => {
+            tracing::error!("Error polling light client events: {err}");
+            return true;
+        }
+    };
+    for (event, meta) in logs {
+        if !forward_event(l1, cursor, event, &meta, events).await {
+            return false;
+        }
+    }
+    true
+}
+
+/// Forward `event`, emitted at `meta`, with the timestamp of its L1 block, unless `cursor` has
+/// already passed it.
+///
+/// Returns `false` if the receiving end of `events` has been closed.
+#[cfg(feature = "executor")]
+async fn forward_event(
+    l1: &ClientPool,
+    cursor: &mut EventCursor,
+    event: NewStateFilter,
+    meta: &LogMeta,
+    events: &Sender<LightClientEvent>,
+) -> bool {
+    if !cursor.advance(meta) {
+        return true;
+    }
+    let l1_timestamp = match l1.provider().get_block(meta.block_hash).await {
+        Ok(block) => block.map(|block| block.timestamp.as_u64()),
+        Err(err) => {
+            tracing::warn!(
+                "Unable to read the timestamp of L1 block {}: {err}",
+                meta.block_number
+            );
+            None
+        }
+    };
+    events
+        .send(LightClientEvent {
+            event,
+            l1_timestamp,
+        })
+        .await
+        .is_ok()
+}
+
+/// Position of the last light client event forwarded to the executor.
+#[cfg(feature = "executor")]
+#[derive(Clone, Copy, Debug, Default)]
+struct EventCursor {
+    last_seen: Option<(u64, u64)>,
+}
+
+#[cfg(feature = "executor")]
+impl EventCursor {
+    /// The L1 block from which to resume fetching events.
+    fn from_block(&self) -> u64 {
+        self.last_seen.map(|(block, _)| block).unwrap_or(0)
+    }
+
+    /// Move past the event emitted at `meta`, returning `false` if it has already been passed.
+    fn advance(&mut self, meta: &LogMeta) -> bool {
+        let position = (meta.block_number.as_u64(), meta.log_index.as_u64());
+        if self
+            .last_seen
+            .is_some_and(|last_seen| position <= last_seen)
+        {
+            return false;
+        }
+        self.last_seen = Some(position);
+        true
+    }
+}
+
+#[cfg(all(test, feature = "executor"))]
+mod tests {
+    use super::*;
+    use crate::{clock::VirtualClock, secret::Secret};
+    use ethers::{
+        abi::AbiEncode, contract::EthEvent, signers::Signer as _, types::Bytes, utils::Anvil,
+    };
+
+    const MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    /// Deploy the rollup contract to `anvil`, against a light client stub which reports `height`
+    /// as its finalized HotShot block height.
+    async fn deploy_rollup(
+        anvil: &ethers::utils::AnvilInstance,
+        height: u8,
+    ) -> ExampleRollup<SignerMiddleware<L1Provider, LocalWallet>> {
+        let pool = ClientPool::new(&anvil.endpoint().parse().unwrap(), []).unwrap();
+        let light_client = Address::random();
+        // Returns 96 bytes with `height` in the second word, the ABI encoding of a
+        // `finalizedState()` with block height `height`.
+        let stub = Bytes::from(vec![
+            0x60, height, 0x60, 0x20, 0x52, 0x60, 0x60, 0x60, 0x00, 0xf3,
+        ]);
+        pool.provider()
+            .request::<_, ()>("anvil_setCode", (light_client, stub))
+            .await
+            .unwrap();
+        let wallet = LocalWallet::from(anvil.keys()[0].clone()).with_chain_id(anvil.chain_id());
+        let prover = wallet.address();
+        let deployer = Arc::new(SignerMiddleware::new(pool.provider(), wallet));
+        let deployment = EthersL1Deployer::new(deployer.clone())
+            .deploy_rollup([0; 32], 0, light_client, prover)
+            .await
+            .unwrap();
+        assert_eq!(deployment.deployer, prover);
+        ExampleRollup::new(deployment.rollup_address, deployer)
+    }
+
+    async fn submit(client: &dyn L1Client, count: u64, proof: BatchProofInput, shape: ProofShape) {
+        let nonce = client.next_nonce().await.unwrap();
+        let hash = client
+            .send_verify_blocks(count, proof, shape, nonce)
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            if let Some(success) = client.transaction_status(hash).await.unwrap() {
+                assert!(success, "{shape} submission reverted");
+                return;
+            }
+            async_std::task::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("{shape} submission was not included");
+    }
+
+    #[async_std::test]
+    async fn test_submit_proof_shapes() {
+        let anvil = Anvil::new().mnemonic(MNEMONIC).spawn();
+        let rollup = deploy_rollup(&anvil, 3).await;
+        let pool = ClientPool::new(&anvil.endpoint().parse().unwrap(), []).unwrap();
+        let signer = L1SignerConfig::Mnemonic {
+            mnemonic: Secret::from(MNEMONIC.to_string()),
+            account_index: 0,
+        };
+        let client = connect_l1_client(L1ClientKind::Ethers, &pool, &signer, rollup.address())
+            .await
+            .unwrap();
+        assert_eq!(client.num_verified_blocks().await.unwrap(), 0);
+
+        let word = |n: u64| u256_to_bytes(U256::from(n));
+        let full = BatchProofInput {
+            first_block: word(0),
+            last_block: word(1),
+            old_state: word(0),
+            new_state: word(2),
+            commitments: vec![word(1), word(2)],
+            withdrawals: None,
+        };
+        submit(&*client, 2, full, ProofShape::Full).await;
+        assert_eq!(client.num_verified_blocks().await.unwrap(), 2);
+        assert_eq!(client.state_commitment().await.unwrap(), word(2));
+
+        let compressed = BatchProofInput {
+            first_block: word(2),
+            last_block: word(2),
+            old_state: word(2),
+            new_state: word(3),
+            commitments: vec![word(3)],
+            withdrawals: None,
+        };
+        submit(&*client, 1, compressed, ProofShape::Compressed).await;
+        assert_eq!(client.num_verified_blocks().await.unwrap(), 3);
+        assert_eq!(
+            client.last_state_update().await.unwrap(),
+            Some(StateUpdate {
+                num_verified_blocks: 3,
+                state_commitment: word(3),
+            })
+        );
+    }
+
+    #[async_std::test]
+    async fn test_verify_balance_on_chain() {
+        let anvil = Anvil::new().mnemonic(MNEMONIC).spawn();
+        let rollup = deploy_rollup(&anvil, 0).await;
+        let vm = crate::RollupVM::new(espresso_types::NamespaceId::from(1_u64));
+        let accounts = (0..3).map(|_| Address::random()).collect::<Vec<_>>();
+        let state = crate::state::State::from_initial_balances(
+            accounts
+                .iter()
+                .enumerate()
+                .map(|(i, address)| (*address, 100 * i as u64 + 1)),
+            vm,
+        );
+        let root = state.accounts_root();
+
+        for address in &accounts {
+            let proof = state.balance_proof(address).unwrap();
+            // The calldata served to clients is accepted by the contract.
+            let tx = ethers::types::TransactionRequest::new()
+                .to(rollup.address())
+                .data(proof.calldata(root));
+            let result = rollup.client().call(&tx.into(), None).await.unwrap();
+            assert_eq!(result.as_ref(), U256::one().encode().as_slice());
+
+            let mut forged = proof;
+            forged.balance += 1;
+            let verified = rollup
+                .verify_balance(
+                    root.0,
+                    forged.address,
+                    forged.balance,
+                    forged.nonce,
+                    U256::from(forged.index),
+                    forged.siblings.iter().map(|sibling| sibling.0).collect(),
+                )
+                .call()
+                .await
+                .unwrap();
+            assert!(!verified);
+        }
+    }
+
+    /// Call the light client stub at `light_client` once for each of `heights`, mining all the
+    /// calls in a single L1 block.
+    async fn emit_new_state(
+        sender: &SignerMiddleware<L1Provider, LocalWallet>,
+        light_client: Address,
+        heights: &[u64],
+    ) {
+        let nonce = sender
+            .get_transaction_count(sender.address(), Some(BlockNumber::Pending.into()))
+            .await
+            .unwrap();
+        for (i, height) in heights.iter().enumerate() {
+            let word = U256::from(*height).encode();
+            let tx = ethers::types::TransactionRequest::new()
+                .to(light_client)
+                .nonce(nonce + i)
+                .data([word.clone(), word].concat());
+            sender.send_transaction(tx, None).await.unwrap();
+        }
+        sender
+            .provider()
+            .request::<_, serde_json::Value>("evm_mine", ())
+            .await
+            .unwrap();
+    }
+
+    #[async_std::test]
+    async fn test_follow_light_client_resumes_after_reconnect() {
+        let anvil = Anvil::new().mnemonic(MNEMONIC).spawn();
+        let pool = ClientPool::new(&anvil.endpoint().parse().unwrap(), []).unwrap();
+        let provider = pool.provider();
+        provider
+            .request::<_, ()>("evm_setAutomine", [false])
+            .await
+            .unwrap();
+
+        // A light client stub which emits `NewState(calldata[0..32], calldata[32..64], 0)`.
+        let light_client = Address::random();
+        let mut stub = vec![0x60, 0x20, 0x35, 0x60, 0x00, 0x35, 0x7f];
+        stub.extend(NewStateFilter::signature().0);
+        stub.extend([0x60, 0x20, 0x60, 0x00, 0xa3, 0x00]);
+        provider
+            .request::<_, ()>("anvil_setCode", (light_client, Bytes::from(stub)))
+            .await
+            .unwrap();
+        let wallet = LocalWallet::from(anvil.keys()[0].clone()).with_chain_id(anvil.chain_id());
+        let sender = SignerMiddleware::new(provider.clone(), wallet);
+        emit_new_state(&sender, light_client, &[1, 2]).await;
+
+        // The websocket endpoint is unreachable, so every reconnect falls back to HTTP polling,
+        // which resumes from the L1 block of the last event forwarded.
+        let clock = VirtualClock::default();
+        let (events, receiver) = async_std::channel::unbounded();
+        let task = async_std::task::spawn(follow_light_client(
+            "ws://127.0.0.1:1".parse().unwrap(),
+            pool.clone(),
+            light_client,
+            Arc::new(clock.clone()),
+            events,
+        ));
+        let reconnecting = || async {
+            while clock.num_sleepers() == 0 {
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        for height in [1, 2] {
+            let event = receiver.recv().await.unwrap();
+            assert_eq!(event.event.block_height, height);
+            assert!(event.l1_timestamp.is_some());
+        }
+
+        // After reconnecting, the events already forwarded are skipped and no new event is missed.
+        reconnecting().await;
+        emit_new_state(&sender, light_client, &[3]).await;
+        emit_new_state(&sender, light_client, &[4, 5]).await;
+        clock.advance(RECONNECT_DELAY);
+        for height in [3, 4, 5] {
+            assert_eq!(receiver.recv().await.unwrap().event.block_height, height);
+        }
+        reconnecting().await;
+        clock.advance(RECONNECT_DELAY);
+        reconnecting().await;
+        assert!(receiver.try_recv().is_err());
+
+        // Once the executor stops listening, so does the subscription.
+        drop(receiver);
+        clock.advance(RECONNECT_DELAY);
+        task.await;
+    }
+
+    #[test]
+    fn test_client_pool_rotation() {
+        let primary: Url = "http://localhost:8545".parse().unwrap();
+        let fallback: Url = "http://localhost:9545".parse().unwrap();
+        let pool = ClientPool::new(&primary, [fallback.clone()])
+            .unwrap()
+            .with_max_failures(2);
+        assert_eq!(pool.url(), primary);
+
+        // A success resets the count of consecutive failures.
+        pool.record(0, Some("connection refused".into()));
+        pool.record(0, None);
+        pool.record(0, Some("connection refused".into()));
+        assert_eq!(pool.url(), primary);
+
+        pool.record(0, Some("connection refused".into()));
+        assert_eq!(pool.url(), fallback);
+        let health = pool.health();
+        assert_eq!(
+            health[0],
+            EndpointHealth {
+                url: redact_url(&primary),
+                active: false,
+                consecutive_failures: 2,
+                total_requests: 4,
+                total_failures: 3,
+                last_error: Some("connection refused".into()),
+            }
+        );
+        assert!(health[1].active);
+
+        // Late failures of an endpoint which is no longer active do not rotate again.
+        pool.record(0, Some("connection refused".into()));
+        assert_eq!(pool.url(), fallback);
+
+        pool.record(1, Some("timeout".into()));
+        pool.record(1, Some("timeout".into()));
+        assert_eq!(pool.url(), primary);
+    }
+
+    #[test]
+    fn test_client_pool_single_endpoint() {
+        let primary: Url = "http://localhost:8545".parse().unwrap();
+        let pool = ClientPool::new(&primary, []).unwrap();
+        for _ in 0..2 * DEFAULT_MAX_FAILURES {
+            pool.record(0, Some("connection refused".into()));
+        }
+        assert_eq!(pool.url(), primary);
+        assert!(pool.health()[0].active);
+        assert_eq!(pool.provider().get_interval(), DEFAULT_POLLING_INTERVAL);
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Event-sourced account state.
+//!
+//! Account balances and nonces are never modified directly. Every change is recorded as a
+//! [`LedgerEvent`] in an append-only log, and the account map is a projection of that log, kept up
+//! to date as events are recorded. Replaying the log from the last compaction point reproduces the
+//! same accounts, which makes history, audit and replay a matter of reading the log.
+//!
+//! To bound the size of the log, it is periodically compacted: events from blocks older than the
+//! retention window are folded into a snapshot of the accounts and discarded. Until then, the state
+//! of an account after any block in the window can be recovered by replaying the log onto the
+//! snapshot, with [`Ledger::account_at`].
+//!
+//! The projected accounts are shared copy-on-write, so a consistent view of them can be taken in
+//! constant time with [`Ledger::shared_accounts`] and read while execution continues. The map is
+//! only copied if an event is recorded while such a view is still held.
+
+use crate::state::{Account, Amount, Nonce};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+/// A change to account state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum LedgerEvent {
+    /// Tokens credited to an account from outside the rollup, such as a genesis balance.
+    Deposit { to: Address, amount: Amount },
+    /// Tokens moved between accounts by a transaction. `nonce` is the sender's nonce after the
+    /// transfer.
+    Transfer {
+        from: Address,
+        to: Address,
+        amount: Amount,
+        nonce: Nonce,
+    },
+    /// Tokens destroyed, such as the burned share of a transaction fee. `nonce` is the sender's
+    /// nonce after the burn.
+    Burn {
+        from: Address,
+        amount: Amount,
+        nonce: Nonce,
+    },
+}
+
+/// An event in the ledger, with its position in the log and the block which recorded it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub seq: u64,
+    pub block_height: u64,
+    pub event: LedgerEvent,
+}
+
+/// The snapshot and log of a [`Ledger`], from which it can be rebuilt.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerSnapshot {
+    pub snapshot: BTreeMap<Address, Account>,
+    pub log: Vec<LedgerEntry>,
+    pub next_seq: u64,
+    /// Events of blocks before this height have been folded into `snapshot`.
+    #[serde(default)]
+    pub compacted_before: u64,
+}
+
+/// The log of account events and its projection onto account state.
+#[derive(Clone, Debug, Default)]
+pub struct Ledger {
+    /// Accounts as of the last compaction, before the first event in `log`.
+    snapshot: BTreeMap<Address, Account>,
+    log: VecDeque<LedgerEntry>,
+    next_seq: u64,
+    /// Events of blocks before this height have been folded into `snapshot`.
+    compacted_before: u64,
+    /// Accounts after every event in `log`.
+    accounts: Arc<BTreeMap<Address, Account>>,
+}
+
+impl Ledger {
+    /// The current state of every account.
+    pub fn accounts(&self) -> &BTreeMap<Address, Account> {
+        &self.accounts
+    }
+
+    /// A view of the current accounts which is unaffected by later events.
+    pub fn shared_accounts(&self) -> Arc<BTreeMap<Address, Account>> {
+        self.accounts.clone()
+    }
+
+    pub fn get(&self, address: &Address) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    /// Append `event`, recorded in the block at `block_height`, and apply it to the accounts.
+    ///
+    /// The caller is responsible for checking that the event is valid. In particular, a transfer
+    /// must not debit more than the sender's balance.
+    pub fn record(&mut self, block_height: u64, event: LedgerEvent) {
+        project(Arc::make_mut(&mut self.accounts), &event);
+        self.log.push_back(LedgerEntry {
+            seq: self.next_seq,
+            block_height,
+            event,
+        });
+        self.next_seq += 1;
+    }
+
+    /// Events recorded since the last compaction, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &LedgerEntry> {
+        self.log.iter()
+    }
+
+    /// Events of blocks before this height have been folded into the snapshot.
+    pub fn compacted_before(&self) -> u64 {
+        self.compacted_before
+    }
+
+    /// Fold events recorded before `block_height` into the snapshot, discarding them from the log.
+    pub fn compact(&mut self, block_height: u64) {
+        while let Some(entry) = self.log.front() {
+            if entry.block_height >= block_height {
+                break;
+            }
+            project(&mut self.snapshot, &entry.event);
+            self.log.pop_front();
+        }
+        self.compacted_before = self.compacted_before.max(block_height);
+    }
+
+    /// The state of `address` after the events of every block up to and including `height`.
+    ///
+    /// The account is replayed from the snapshot, so this returns `None` if events of blocks after
+    /// `height` have already been folded into it.
+    pub fn account_at(&self, address: &Address, height: u64) -> Option<Account> {
+        if height.saturating_add(1) < self.compacted_before {
+            return None;
+        }
+        let mut account = self.snapshot.get(address).cloned().unwrap_or_default();
+        for entry in self
+            .log
+            .iter()
+            .take_while(|entry| entry.block_height <= height)
+        {
+            match &entry.event {
+                LedgerEvent::Deposit { to, amount } if to == address => account.balance += amount,
+                LedgerEvent::Transfer {
+                    from,
+                    to,
+                    amount,
+                    nonce,
+                } => {
+                    if from == address {
+                        account.balance -= amount;
+                        account.nonce = *nonce;
+                    }
+                    if to == address {
+                        account.balance += amount;
+                    }
+                }
+                LedgerEvent::Burn {
+                    from,
+                    amount,
+                    nonce,
+                } if from == address => {
+                    account.balance -= amount;
+                    account.nonce = *nonce;
+                }
+                _ => {}
+            }
+        }
+        Some(account)
+    }
+
+    /// Rebuild the accounts from the snapshot and the log.
+    ///
+    /// This always equals [`accounts`](Self::accounts).
+    pub fn replay(&self) -> BTreeMap<Address, Account> {
+        let mut accounts = self.snapshot.clone();
+        for entry in &self.log {
+            project(&mut accounts, &entry.event);
+        }
+        accounts
+    }
+
+    /// Export the snapshot and log, for [`from_snapshot`](Self::from_snapshot).
+    pub fn to_snapshot(&self) -> LedgerSnapshot {
+        LedgerSnapshot {
+            snapshot: self.snapshot.clone(),
+            log: self.log.iter().cloned().collect(),
+            next_seq: self.next_seq,
+            compacted_before: self.compacted_before,
+        }
+    }
+
+    /// Rebuild a ledger from an exported snapshot and log, replaying the log onto the snapshot.
+    pub fn from_snapshot(snapshot: LedgerSnapshot) -> Self {
+        let mut ledger = Self {
+            snapshot: snapshot.snapshot,
+            log: snapshot.log.into(),
+            next_seq: snapshot.next_seq,
+            compacted_before: snapshot.compacted_before,
+            accounts: Default::default(),
+        };
+        ledger.accounts = Arc::new(ledger.replay());
+        ledger
+    }
+}
+
+/// Apply `event` to `accounts`.
+///
+/// # Panics
+///
+/// Panics if `event` credits an account with more than its balance can hold. Events are checked
+/// before they are recorded, so this indicates a bug in execution.
+fn project(accounts: &mut BTreeMap<Address, Account>, event: &LedgerEvent) {
+    match event {
+        LedgerEvent::Deposit { to, amount } => credit(accounts, to, *amount),
+        LedgerEvent::Transfer {
+            from,
+            to,
+            amount,
+            nonce,
+        } => {
+            // Debit the sender before crediting the destination, so that a transfer to oneself
+            // leaves the balance unchanged.
+            let sender = accounts
+                .get_mut(from)
+                .expect("Transfer from unknown account");
+            sender.balance -= amount;
+            sender.nonce = *nonce;
+            credit(accounts, to, *amount);
+        }
+        LedgerEvent::Burn {
+            from,
+            amount,
+            nonce,
+        } => {
+            let sender = accounts.get_mut(from).expect("Burn from unknown account");
+            sender.balance -= amount;
+            sender.nonce = *nonce;
+        }
+    }
+}
+
+fn credit(accounts: &mut BTreeMap<Address, Account>, to: &Address, amount: Amount) {
+    let account = accounts.entry(*to).or_default();
+    account.balance = account
+        .balance
+        .checked_add(amount)
+        .unwrap_or_else(|| panic!("Credit of {amount} overflows the balance of {to:?}"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_compaction() {
+        let alice = Address::random();
+        let bob = Address::random();
+        let mut ledger = Ledger::default();
+        ledger.record(
+            0,
+            LedgerEvent::Deposit {
+                to: alice,
+                amount: 100,
+            },
+        );
+        for block_height in 1..=5 {
+            ledger.record(
+                block_height,
+                LedgerEvent::Transfer {
+                    from: alice,
+                    to: bob,
+                    amount: 10,
+                    nonce: block_height,
+                },
+            );
+        }
+        assert_eq!(ledger.get(&alice).unwrap().balance, 50);
+        assert_eq!(ledger.get(&alice).unwrap().nonce, 5);
+        assert_eq!(ledger.get(&bob).unwrap().balance, 50);
+        assert_eq!(&ledger.replay(), ledger.accounts());
+
+        // Compaction discards old events without changing the projection.
+        ledger.compact(3);
+        assert_eq!(
+            ledger
+                .history()
+                .map(|entry| entry.block_height)
+                .collect::<Vec<_>>(),
+            [3, 4, 5]
+        );
+        assert_eq!(ledger.history().next().unwrap().seq, 3);
+        assert_eq!(&ledger.replay(), ledger.accounts());
+        assert_eq!(ledger.get(&bob).unwrap().balance, 50);
+
+        // Accounts after blocks since the compaction are replayed from the snapshot.
+        let alice_at_3 = ledger.account_at(&alice, 3).unwrap();
+        assert_eq!((alice_at_3.balance, alice_at_3.nonce), (70, 3));
+        assert_eq!(ledger.account_at(&bob, 2).unwrap().balance, 20);
+        assert_eq!(ledger.account_at(&bob, 5).as_ref(), ledger.get(&bob));
+        assert_eq!(ledger.account_at(&bob, 1), None);
+
+        // A shared view of the accounts is not affected by later events.
+        let shared = ledger.shared_accounts();
+        ledger.record(6, LedgerEvent::Deposit { to: bob, amount: 1 });
+        assert_eq!(shared[&bob].balance, 50);
+        assert_eq!(ledger.get(&bob).unwrap().balance, 51);
+    }
+}
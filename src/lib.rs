@@ -4,75 +4,97 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
-use clap::Parser;
 use derive_more::{From, Into};
 use espresso_types::NamespaceId;
-use ethers::types::Address;
-use surf_disco::Url;
+use vbs::version::StaticVersion;
 
+pub mod address;
+pub mod aggregator;
 pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod backfill;
+pub mod balance_proof;
+pub mod breaker;
+pub mod canary;
+pub mod chain;
+pub mod client;
+pub mod clock;
+pub mod data_source;
+#[cfg(feature = "executor")]
+pub mod deployment;
+pub mod deposit;
+#[cfg(feature = "executor")]
+pub mod devnet;
+#[cfg(feature = "executor")]
+pub mod doctor;
 pub mod error;
+pub mod events;
+#[cfg(feature = "executor")]
 pub mod executor;
-mod prover;
+pub mod fees;
+#[cfg(test)]
+mod fixtures;
+pub mod gossip;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+pub mod hooks;
+pub mod http;
+pub mod inclusion;
+pub mod l1;
+pub mod ledger;
+#[cfg(feature = "executor")]
+pub mod light_client;
+pub mod machine;
+pub mod mempool;
+pub mod metrics;
+pub mod middleware;
+pub mod mirror;
+pub mod nonce;
+#[cfg(feature = "executor")]
+mod options;
+pub mod outbox;
+pub mod prover;
+pub mod query;
+pub mod random;
+pub mod receipt;
+#[cfg(feature = "executor")]
+pub mod recovery;
+pub mod relay;
+pub mod repl;
+pub mod replay_audit;
+pub mod scheduler;
+pub mod schema;
+pub mod secret;
 pub mod seed;
+#[cfg(feature = "executor")]
+pub mod signer;
+pub mod snapshot;
+pub mod spot_audit;
 pub mod state;
+pub mod stats;
+pub mod storage;
+pub mod submitters;
+pub mod trace;
 pub mod transaction;
+#[cfg(feature = "executor")]
 pub mod utils;
+pub mod utxo;
+pub mod verify;
+pub mod warm_start;
+pub mod watchdog;
+pub mod webhooks;
+pub mod withdrawal;
 
-#[derive(Parser, Clone, Debug)]
-pub struct Options {
-    /// Port where the Rollup API will be served
-    #[clap(short, long, env = "ESPRESSO_DEMO_ROLLUP_PORT", default_value = "8084")]
-    pub api_port: u16,
+#[cfg(feature = "executor")]
+pub use options::{DemoCommand, DemoUpOptions, NodeCommand, Options};
 
-    /// URL of a HotShot sequencer node.
-    #[clap(
-        long,
-        env = "ESPRESSO_SEQUENCER_URL",
-        default_value = "http://0.0.0.0:24000/v0/"
-    )]
-    pub sequencer_url: Url,
-
-    /// URL of layer 1 Ethereum JSON-RPC provider.
-    #[clap(
-        long,
-        env = "ESPRESSO_DEMO_L1_HTTP_PROVIDER",
-        default_value = "http://localhost:8545"
-    )]
-    pub l1_http_provider: Url,
-
-    /// URL of layer 1 Ethereum JSON-RPC provider.
-    #[clap(
-        long,
-        env = "ESPRESSO_DEMO_L1_WS_PROVIDER",
-        default_value = "ws://localhost:8546"
-    )]
-    pub l1_ws_provider: Url,
-
-    /// Address of HotShot contract on layer 1.
-    #[clap(
-        long,
-        env = "ESPRESSO_DEMO_LIGHT_CLIENT_ADDRESS",
-        default_value = "0xed1db453c3156ff3155a97ad217b3087d5dc5f6e"
-    )]
-    pub light_client_address: Address,
-
-    /// Mnemonic phrase for the rollup wallet.
-    ///
-    /// This is the wallet that will be used to send batch proofs of transaction validity to the rollup
-    /// contract. It must be funded with ETH on the layer 1.
-    #[clap(
-        long,
-        env = "ESPRESSO_DEMO_ROLLUP_MNEMONIC",
-        default_value = "test test test test test test test test test test test junk"
-    )]
-    pub rollup_mnemonic: String,
-
-    /// Index of a funded account derived from mnemonic, desginating the account
-    /// that will send proofs to the rollup contract
-    #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_ACCOUNT_INDEX", default_value = "1")]
-    pub rollup_account_index: u32,
-}
+/// Version of the HotShot query service API, which the rollup API is also served with.
+///
+/// This is the sequencer's `SequencerApiVersion`, defined here so that clients of the rollup API do
+/// not depend on the sequencer crate.
+pub type SequencerApiVersion = StaticVersion<0, 1>;
 
 #[derive(Clone, Copy, Debug, Default, Into, From)]
 
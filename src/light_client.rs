@@ -0,0 +1,314 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Verification that HotShot headers are finalized by the light client contract.
+//!
+//! The light client contract stores the height of the latest finalized HotShot block along with
+//! a commitment to the root of the block Merkle tree, which commits to every earlier header. A
+//! header is verified by checking that its height is covered by the finalized state, that the
+//! finalized header served by the query service matches the contract's root, and that the header
+//! is a member of that header's block Merkle tree. Only the contract is trusted; the query service
+//! is not.
+//!
+//! The executor only makes progress when the light client emits `NewState` events, so a wrong
+//! light client address would otherwise leave it waiting forever. [`validate_light_client`] checks
+//! the address at startup, and is also run by the `doctor` subcommand.
+
+use crate::http::HttpClientPool;
+use crate::l1::{ClientPool, L1Provider};
+use ark_serialize::CanonicalSerialize;
+use committable::Committable;
+use espresso_types::{BlockMerkleCommitment, BlockMerkleTree, Header};
+use ethers::providers::Middleware;
+use ethers::types::{Address, U256};
+use hotshot_contract_bindings::light_client::LightClient;
+use hotshot_types::light_client::hash_bytes_to_field;
+use jf_merkle_tree::MerkleTreeScheme;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::sync::Arc;
+use surf_disco::error::ClientError;
+use surf_disco::Url;
+
+/// Proof that a header commitment is a member of the block Merkle tree.
+pub type BlockMerkleProof = <BlockMerkleTree as MerkleTreeScheme>::MembershipProof;
+
+#[derive(Clone, Debug, Snafu)]
+pub enum LightClientError {
+    #[snafu(display("Error reading light client contract: {message}"))]
+    Contract { message: String },
+    #[snafu(display("Error fetching data from the query service: {message}"))]
+    QueryService { message: String },
+    #[snafu(display(
+        "Block {height} is not finalized by the light client, which is at block {finalized_height}."
+    ))]
+    NotFinalized { height: u64, finalized_height: u64 },
+    #[snafu(display(
+        "Header {height} does not match the block commitment root stored by the light client."
+    ))]
+    RootMismatch { height: u64 },
+    #[snafu(display("Header {height} is not in the finalized block Merkle tree."))]
+    NotInTree { height: u64 },
+    #[snafu(display(
+        "There is no contract at {address:?}, check the light client address and that the L1 \
+         provider serves the chain it is deployed on."
+    ))]
+    NoContract { address: Address },
+    #[snafu(display(
+        "The contract at {address:?} does not implement the light client interface, check the \
+         light client address: {message}"
+    ))]
+    NotLightClient { address: Address, message: String },
+    #[snafu(display(
+        "The light client at {address:?} has finalized block {block_height} but has emitted no \
+         NewState events, it may be an incompatible version of the contract."
+    ))]
+    NoNewStateEvents { address: Address, block_height: u64 },
+}
+
+/// The finalized HotShot state stored by the light client contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinalizedState {
+    pub view_number: u64,
+    pub block_height: u64,
+    /// Commitment to the block Merkle tree root of the header at `block_height`.
+    pub block_comm_root: U256,
+}
+
+/// The light client's commitment to a block Merkle tree root.
+pub fn block_comm_root(root: &BlockMerkleCommitment) -> U256 {
+    let mut root_bytes = vec![];
+    root.serialize_compressed(&mut root_bytes)
+        .expect("Serialization should not fail");
+    let field = hash_bytes_to_field(&root_bytes).expect("Hashing to a field should not fail");
+    let mut field_bytes = vec![];
+    field
+        .serialize_compressed(&mut field_bytes)
+        .expect("Serialization should not fail");
+    U256::from_little_endian(&field_bytes)
+}
+
+/// Check that `header` is finalized by `state`.
+///
+/// `finalized_header` must be the header at `state.block_height`, and `proof` the proof of
+/// `header` in the block Merkle tree of `finalized_header`. The proof is not needed when `header`
+/// is the finalized header itself.
+pub fn verify_header(
+    state: &FinalizedState,
+    finalized_header: &Header,
+    header: &Header,
+    proof: Option<&BlockMerkleProof>,
+) -> Result<(), LightClientError> {
+    let height = header.height();
+    if height > state.block_height {
+        return Err(LightClientError::NotFinalized {
+            height,
+            finalized_height: state.block_height,
+        });
+    }
+    if finalized_header.height() != state.block_height
+        || block_comm_root(&finalized_header.block_merkle_tree_root()) != state.block_comm_root
+    {
+        return Err(LightClientError::RootMismatch {
+            height: finalized_header.height(),
+        });
+    }
+    if height == state.block_height {
+        return if header.commit() == finalized_header.commit() {
+            Ok(())
+        } else {
+            Err(LightClientError::NotInTree { height })
+        };
+    }
+    let proof = proof.ok_or(LightClientError::NotInTree { height })?;
+    verify_membership(&finalized_header.block_merkle_tree_root(), header, proof)
+}
+
+/// Check that `proof` shows `header` in the block Merkle tree with root `root`.
+pub fn verify_membership(
+    root: &BlockMerkleCommitment,
+    header: &Header,
+    proof: &BlockMerkleProof,
+) -> Result<(), LightClientError> {
+    let height = header.height();
+    if proof.elem() != Some(&header.commit()) {
+        return Err(LightClientError::NotInTree { height });
+    }
+    match BlockMerkleTree::verify(root, height, proof) {
+        Ok(Ok(())) => Ok(()),
+        _ => Err(LightClientError::NotInTree { height }),
+    }
+}
+
+/// Check that `address` holds a light client contract whose events the executor can follow,
+/// returning its finalized state.
+///
+/// The contract must have code, must answer `finalizedState` at the expected interface, and, once
+/// it has finalized any blocks, must have emitted `NewState` events which decode as expected.
+pub async fn validate_light_client(
+    provider: &L1Provider,
+    address: Address,
+) -> Result<FinalizedState, LightClientError> {
+    let code =
+        provider
+            .get_code(address, None)
+            .await
+            .map_err(|err| LightClientError::Contract {
+                message: err.to_string(),
+            })?;
+    if code.is_empty() {
+        return Err(LightClientError::NoContract { address });
+    }
+    let light_client = LightClient::new(address, Arc::new(provider.clone()));
+    let (view_number, block_height, block_comm_root, _) =
+        light_client.finalized_state().call().await.map_err(|err| {
+            LightClientError::NotLightClient {
+                address,
+                message: err.to_string(),
+            }
+        })?;
+    if block_height > 0 {
+        let events = light_client
+            .new_state_filter()
+            .from_block(0)
+            .query()
+            .await
+            .map_err(|err| LightClientError::Contract {
+                message: err.to_string(),
+            })?;
+        if events.is_empty() {
+            return Err(LightClientError::NoNewStateEvents {
+                address,
+                block_height,
+            });
+        }
+    }
+    Ok(FinalizedState {
+        view_number,
+        block_height,
+        block_comm_root,
+    })
+}
+
+/// Verifies HotShot headers against the light client contract on the L1.
+#[derive(Debug)]
+pub struct HeaderVerifier {
+    light_client: LightClient<L1Provider>,
+    http: HttpClientPool,
+    availability_url: Url,
+    block_state_url: Url,
+}
+
+impl HeaderVerifier {
+    /// Verify headers served by the query service at `sequencer_url` against the light client
+    /// contract at `light_client_address`, using clients from `http` and `l1`.
+    pub fn new(
+        l1: &ClientPool,
+        light_client_address: Address,
+        sequencer_url: &Url,
+        http: HttpClientPool,
+    ) -> Self {
+        Self {
+            light_client: LightClient::new(light_client_address, Arc::new(l1.provider())),
+            http,
+            availability_url: sequencer_url.join("availability").unwrap(),
+            block_state_url: sequencer_url.join("block-state").unwrap(),
+        }
+    }
+
+    /// The finalized state currently stored by the light client contract.
+    pub async fn finalized_state(&self) -> Result<FinalizedState, LightClientError> {
+        let (view_number, block_height, block_comm_root, _) = self
+            .light_client
+            .finalized_state()
+            .call()
+            .await
+            .map_err(|err| LightClientError::Contract {
+                message: err.to_string(),
+            })?;
+        Ok(FinalizedState {
+            view_number,
+            block_height,
+            block_comm_root,
+        })
+    }
+
+    /// Check that each of `headers` is finalized by the light client contract.
+    pub async fn verify_headers(&self, headers: &[Header]) -> Result<(), LightClientError> {
+        let state = self.finalized_state().await?;
+        let finalized_header = self.fetch_header(state.block_height).await?;
+        for header in headers {
+            let proof = if header.height() < state.block_height {
+                Some(
+                    self.http
+                        .client(&self.block_state_url)
+                        .await
+                        .get::<BlockMerkleProof>(&format!(
+                            "{}/{}",
+                            state.block_height,
+                            header.height()
+                        ))
+                        .await
+                        .map_err(query_service_error)?,
+                )
+            } else {
+                None
+            };
+            verify_header(&state, &finalized_header, header, proof.as_ref())?;
+        }
+        Ok(())
+    }
+
+    async fn fetch_header(&self, height: u64) -> Result<Header, LightClientError> {
+        self.http
+            .client(&self.availability_url)
+            .await
+            .get::<Header>(&format!("header/{height}"))
+            .await
+            .map_err(query_service_error)
+    }
+}
+
+fn query_service_error(err: ClientError) -> LightClientError {
+    LightClientError::QueryService {
+        message: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use espresso_types::{NodeState, Payload, SeqTypes};
+    use hotshot_types::data::vid_commitment;
+    use hotshot_types::traits::block_contents::{BlockPayload, EncodeBytes};
+    use jf_merkle_tree::{AppendableMerkleTreeScheme, MerkleCommitment};
+
+    #[test]
+    fn test_verify_membership() {
+        let (payload, ns_table) = <Payload as BlockPayload<SeqTypes>>::empty();
+        let header = Header::genesis(
+            &NodeState::mock(),
+            vid_commitment(&payload.encode(), 1),
+            payload.builder_commitment(&ns_table),
+            ns_table,
+        );
+
+        let mut tree = BlockMerkleTree::new(32);
+        tree.push(header.commit()).unwrap();
+        let (_, proof) = tree.lookup(0).expect_ok().unwrap();
+        let root = tree.commitment().digest();
+        verify_membership(&root, &header, &proof).unwrap();
+
+        // The proof does not verify against a different tree.
+        let mut other = BlockMerkleTree::new(32);
+        other.push(header.commit()).unwrap();
+        other.push(header.commit()).unwrap();
+        assert!(matches!(
+            verify_membership(&other.commitment().digest(), &header, &proof),
+            Err(LightClientError::NotInTree { height: 0 })
+        ));
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! The interface between a rollup state and the machinery which feeds it blocks.
+//!
+//! Fetching headers and namespace proofs from the sequencer, and checking the resulting commitments,
+//! does not depend on how the rollup represents its state. A [`RollupStateMachine`] captures the
+//! little that this machinery needs: the namespace to execute, a transition applying the
+//! transactions of a block, and a commitment to the result. The account model of [`State`] is the
+//! rollup this example runs, and [`UtxoState`] is an alternative built on the same interface.
+//!
+//! Only block execution is generic over the state machine. The node's executor and API, the prover
+//! and the rollup contract are specific to [`State`], so the node runs only [`StateModel::Account`],
+//! while the auditor can follow a rollup under either model.
+//!
+//! [`State`]: crate::state::State
+//! [`UtxoState`]: crate::utxo::UtxoState
+
+use crate::chain::ChainId;
+use crate::error::RollupError;
+use clap::ValueEnum;
+use committable::Committable;
+use espresso_types::{Header, NamespaceId, NsProof, SeqTypes};
+use ethers::types::H256;
+use hotshot_query_service::availability::BlockHash;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use strum_macros::Display;
+
+/// How the rollup represents ownership of tokens, chosen at genesis.
+#[derive(
+    ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum StateModel {
+    /// Balances and nonces per address, as in [`State`](crate::state::State).
+    #[default]
+    Account,
+    /// Notes owned by addresses, as in [`UtxoState`](crate::utxo::UtxoState).
+    Utxo,
+}
+
+/// A rollup state which is advanced by executing the rollup namespace of each HotShot block.
+pub trait RollupStateMachine: Committable + Clone + Debug + Send + Sync + 'static {
+    /// The namespace containing the rollup's transactions.
+    fn namespace(&self) -> NamespaceId;
+
+    /// Height of the most recently executed HotShot block.
+    fn block_height(&self) -> u64;
+
+    /// Bind the state to the rollup chain `chain_id`, once the rollup contract is deployed.
+    ///
+    /// The chain ID is derived from the genesis commitment, so it is not part of the state
+    /// commitment. States whose transactions do not name a chain may ignore it.
+    fn set_chain_id(&mut self, _chain_id: ChainId) {}
+
+    /// Apply the rollup transactions in the block with the given `header`.
+    ///
+    /// Invalid transactions are skipped, and their errors recorded in the
+    /// [`block_results`](Self::block_results). The transition must be deterministic.
+    fn execute_transactions(
+        &mut self,
+        header: &Header,
+        namespace_proof: &NsProof,
+        block_hash: BlockHash<SeqTypes>,
+    );
+
+    /// The hash and result of each transaction in the most recently executed block.
+    fn block_results(&self) -> &[(H256, Result<(), RollupError>)];
+}
@@ -4,26 +4,66 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
+use async_compatibility_layer::async_primitives::broadcast;
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
 use async_std::sync::RwLock;
 use clap::Parser;
 use committable::Committable;
-use espresso_types::NamespaceId;
-use ethers::signers::{LocalWallet, Signer};
+use ethers::providers::Middleware as _;
+use ethers::signers::{coins_bip39::English, MnemonicBuilder, Signer};
 use example_l2::{
-    api::{serve, APIOptions},
+    address::AddressBook,
+    aggregator::ExternalAggregator,
+    api::{follow_executor, serve, APIOptions, ApiServices},
+    breaker::CircuitBreaker,
+    canary::Canary,
+    chain::ChainId,
+    clock::SystemClock,
+    data_source::QueryServiceDataSource,
+    deployment::{ContractState, DeploymentRecord},
+    devnet::{self, Devnet},
+    doctor::{self, Status},
+    events::{EventFanout, EventIndex},
     executor::{run_executor, ExecutorOptions},
-    seed::{SeedIdentity, INITIAL_BALANCE},
-    state::State,
-    utils::{create_provider, deploy_example_contract},
-    Options, RollupVM,
+    gossip::{run_gossip, CheckpointStore, GossipOptions, DEFAULT_CHECKPOINT_CAPACITY},
+    history::AccountHistory,
+    http::HttpClientPool,
+    l1::connect_l1_client,
+    light_client::validate_light_client,
+    machine::{RollupStateMachine, StateModel},
+    mempool::{run_mempool, Mempool},
+    metrics::NodeMetrics,
+    middleware::{CorsAllowList, Middleware},
+    nonce::NonceManager,
+    outbox::Outbox,
+    prover::ProofVerifier,
+    query::TransactionIndex,
+    random::DemoRng,
+    receipt::ReceiptIndex,
+    relay::TransactionRelay,
+    scheduler::DaTimeoutPolicy,
+    schema::ApiSchema,
+    seed::seed_accounts,
+    snapshot::SnapshotExporter,
+    spot_audit::{run_spot_audit, SpotAuditOptions},
+    state::{State, SubmissionPolicy},
+    stats::{FinalityLagTracker, LatencyTracker},
+    storage::{Checkpointer, FileStorage, Storage},
+    submitters::SubmitterRegistry,
+    utils::deploy_example_contract_with_deployment,
+    warm_start::{Resume, WarmStart, WarmStartWriter},
+    watchdog::ExecutionWatchdog,
+    DemoCommand, DemoUpOptions, NodeCommand, Options,
+};
+use futures::{
+    future::{select, Either},
+    join, StreamExt,
 };
-use futures::join;
-use rand::SeedableRng;
-use rand_chacha::ChaChaRng;
 use sequencer_utils::test_utils::TestL1System;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook_async_std::Signals;
 use std::sync::Arc;
-use strum::IntoEnumIterator;
+use std::time::Duration;
 
 #[async_std::main]
 async fn main() {
@@ -31,48 +71,501 @@ async fn main() {
     setup_backtrace();
 
     let opt = Options::parse();
-    let vm = RollupVM::new(NamespaceId::from(1_u64));
+    match &opt.command {
+        Some(NodeCommand::Doctor) => {
+            let report = doctor::diagnose(&opt).await;
+            for diagnostic in &report {
+                println!("{diagnostic}");
+            }
+            if report
+                .iter()
+                .any(|diagnostic| diagnostic.status == Status::Failed)
+            {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(NodeCommand::Schema { out_dir }) => {
+            let schema = ApiSchema::generate();
+            let typescript = out_dir.join("rollup.d.ts");
+            std::fs::write(&typescript, &schema.typescript).unwrap();
+            let json_schema = out_dir.join("rollup.schema.json");
+            std::fs::write(
+                &json_schema,
+                serde_json::to_string_pretty(&schema.json_schema).unwrap(),
+            )
+            .unwrap();
+            println!(
+                "Wrote {} and {}",
+                typescript.display(),
+                json_schema.display()
+            );
+            return;
+        }
+        Some(NodeCommand::Demo {
+            command: DemoCommand::Up(up),
+        }) => {
+            demo_up(&opt, up).await;
+            return;
+        }
+        None => {}
+    }
+    let config = opt.effective_config();
+    tracing::info!("Effective configuration: {config}");
+    if opt.state_model != StateModel::Account {
+        panic!(
+            "The node only runs the account state model, follow a {} rollup with the auditor",
+            opt.state_model
+        );
+    }
+    let rng = DemoRng::new(opt.seed);
+    tracing::info!(
+        "Using random seed {}, restart with --seed {} to reproduce this run",
+        rng.seed(),
+        rng.seed()
+    );
+    let vm = opt.vm();
 
     let mut initial_balances = vec![];
-    for identity in SeedIdentity::iter() {
-        let address = LocalWallet::new(&mut ChaChaRng::seed_from_u64(identity as u64)).address();
-        initial_balances.push((address, INITIAL_BALANCE))
+    let mut address_book = AddressBook::new(opt.address_aliases.clone());
+    for account in seed_accounts(opt.seed_accounts) {
+        let address = account.wallet.address();
+        initial_balances.push((address, opt.seed_balance));
+        address_book.insert(account.alias(), address);
     }
-    let state = Arc::new(RwLock::new(State::from_initial_balances(
-        initial_balances,
-        vm,
-    )));
+    let genesis = State::from_initial_balances(initial_balances, vm)
+        .with_replay_protection(opt.replay_protection, opt.replay_window)
+        .with_ordering_policy(opt.ordering_policy)
+        .with_submission_policy(SubmissionPolicy {
+            operator: opt.submission_operator,
+            untrusted: opt.untrusted_submissions,
+            fee_burn_bps: opt.fee_burn_bps,
+        });
+    let initial_state = genesis.commit();
+    let genesis_supply = genesis.total_balance();
+
+    let receipts = ReceiptIndex::default();
+    let warm_start = match &opt.warm_start {
+        Some(path) => WarmStart::load(path).unwrap(),
+        None => None,
+    };
+    let storage = opt
+        .state_storage
+        .as_ref()
+        .map(|path| Arc::new(FileStorage::new(path)) as Arc<dyn Storage>);
+    let checkpoint = match &storage {
+        Some(storage) => storage.load().unwrap(),
+        None => None,
+    };
+    let (state, resume) = match (warm_start, checkpoint) {
+        (Some(bundle), _) => {
+            tracing::info!(
+                "Warm starting from {}, resuming at block {}",
+                opt.warm_start.as_ref().unwrap().display(),
+                bundle.next_block()
+            );
+            bundle.restore(&genesis, &receipts).await.unwrap()
+        }
+        (None, Some(checkpoint)) => {
+            tracing::info!(
+                "Restoring checkpoint from {}, resuming at block {}",
+                opt.state_storage.as_ref().unwrap().display(),
+                checkpoint.next_block()
+            );
+            checkpoint.restore(&genesis).unwrap()
+        }
+        (None, None) => (genesis, Resume::default()),
+    };
+    let state = Arc::new(RwLock::new(state));
+
+    let rollup_wallet = MnemonicBuilder::<English>::default()
+        .phrase(opt.rollup_mnemonic.expose().as_str())
+        .index(opt.rollup_account_index)
+        .unwrap()
+        .build()
+        .unwrap();
+    let operator_signer = match opt.submission_operator {
+        Some(operator) if operator == rollup_wallet.address() => Some(rollup_wallet.clone()),
+        Some(operator) => {
+            tracing::warn!(
+                "This node's rollup account is not the submission operator {operator:?}, \
+                 transactions submitted through its API will be untrusted"
+            );
+            None
+        }
+        None => None,
+    };
 
+    let http = HttpClientPool::new(opt.http_client_options());
+    let middleware: Vec<Arc<dyn Middleware>> = vec![Arc::new(opt.admin_auth())];
     let api_options = APIOptions {
         api_port: opt.api_port,
+        bind_addresses: opt.api_bind_addresses.clone(),
+        advertise_url: opt.api_advertise_url.clone(),
         sequencer_url: opt.sequencer_url.clone(),
+        middleware,
+        cors: CorsAllowList::new(opt.cors_allowed_origins.clone()),
+        v0_sunset: opt.api_v0_sunset.clone(),
+        address_book: address_book.clone(),
+        dev_signing: opt.dev_signing,
+        submission_control: opt.submission_control,
+        rng: rng.clone(),
+        extensions: Default::default(),
+        operator_signer: operator_signer.clone(),
+        http: http.clone(),
+        config,
     };
 
+    // The API serves block-boundary snapshots published by the executor, rather than the state
+    // the executor is mutating.
+    let (output_stream, _) = broadcast::channel();
+    let api_state = Arc::new(RwLock::new(state.read().await.clone()));
+    let finality_lag =
+        FinalityLagTracker::new(opt.finality_lag_window, opt.finality_lag_csv.clone());
+    let latency = LatencyTracker::new(opt.latency_window);
+    let metrics = NodeMetrics::default();
+    let outbox = match &opt.outbox_file {
+        Some(path) => Outbox::open(path).expect("unable to open outbox"),
+        None => Outbox::in_memory(),
+    }
+    .with_nonce_manager(NonceManager::new(opt.l1_max_pending_txs))
+    .with_metrics(metrics.clone());
+    if opt.pause_submission {
+        outbox.pause().await;
+    }
+    let aggregator = opt.aggregator_url.as_ref().map(|url| {
+        ExternalAggregator::new(url.clone(), http.clone(), outbox.clone())
+            .with_proof_shape(opt.proof_shape)
+    });
+    #[cfg(feature = "sqlite")]
+    let transactions = match &opt.history_db {
+        Some(path) => TransactionIndex::sqlite(path)
+            .await
+            .expect("unable to open history database"),
+        None => TransactionIndex::in_memory(opt.history_window),
+    };
+    #[cfg(not(feature = "sqlite"))]
+    let transactions = {
+        if opt.history_db.is_some() {
+            tracing::warn!("History database configured, but the `sqlite` feature is not enabled");
+        }
+        TransactionIndex::in_memory(opt.history_window)
+    };
+    let api_services = ApiServices {
+        finality_lag: finality_lag.clone(),
+        latency: latency.clone(),
+        outbox: outbox.clone(),
+        fanout: EventFanout::new(opt.event_replay_window),
+        snapshots: SnapshotExporter::new(opt.snapshot_dir.clone()),
+        history: AccountHistory::new(opt.history_window),
+        events: EventIndex::new(opt.history_window),
+        transactions,
+        watchdog: ExecutionWatchdog::new(opt.block_execution_budget_ms.map(Duration::from_millis)),
+        checkpoints: CheckpointStore::new(Some(rollup_wallet), DEFAULT_CHECKPOINT_CAPACITY),
+        receipts: receipts.clone(),
+        proof_verifier: ProofVerifier::new(opt.proof_verification)
+            .with_sample_interval(opt.proof_sample_interval),
+        relay: TransactionRelay::new(http.clone())
+            .with_primary(opt.relay_primary.clone())
+            .with_peers(opt.relay_peers.clone()),
+        mempool: Mempool::new(opt.mempool_capacity)
+            .with_max_per_sender(opt.mempool_max_per_sender)
+            .with_metrics(metrics.clone()),
+        aggregator: aggregator.clone(),
+        metrics: metrics.clone(),
+        canary: opt.canary_vm.map(|vm| Canary::new(Arc::new(vm))),
+        submitters: opt
+            .require_submitter_tokens
+            .then(SubmitterRegistry::default),
+        ..Default::default()
+    };
+    let sync_api_state = follow_executor(
+        output_stream.handle_async().await,
+        api_state.clone(),
+        api_services.clone(),
+    );
+
+    let mempool = run_mempool(
+        api_services.mempool.clone(),
+        output_stream.handle_async().await,
+        http.clone(),
+        opt.sequencer_url.clone(),
+        api_services.latency.clone(),
+    );
+
     let serve_api = async {
-        serve(&api_options, state.clone()).await.unwrap();
+        serve(&api_options, api_state.clone(), api_services.clone())
+            .await
+            .unwrap();
     };
 
-    let initial_state = { state.read().await.commit() };
+    let serve_grpc = async {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc_port) = opt.grpc_port {
+            let grpc_options = example_l2::grpc::GrpcOptions {
+                grpc_port,
+                sequencer_url: opt.sequencer_url.clone(),
+                address_book: address_book.clone(),
+                operator_signer: operator_signer.clone(),
+                http: http.clone(),
+                receipts: api_services.receipts.clone(),
+                latency: api_services.latency.clone(),
+            };
+            example_l2::grpc::serve_grpc(&grpc_options, api_state.clone(), output_stream.clone())
+                .await
+                .unwrap();
+        }
+        #[cfg(not(feature = "grpc"))]
+        if opt.grpc_port.is_some() {
+            tracing::warn!("gRPC port configured, but the `grpc` feature is not enabled");
+        }
+    };
 
-    tracing::info!("Deploying Rollup contracts");
-    let provider = create_provider(&opt.l1_http_provider);
-    let test_system = TestL1System::new(provider, opt.light_client_address)
-        .await
-        .unwrap();
-    let rollup_contract =
-        deploy_example_contract(&test_system, initial_state, opt.light_client_address).await;
+    let gossip = async {
+        if !opt.gossip_peers.is_empty() {
+            let gossip_options = GossipOptions {
+                peers: opt.gossip_peers.clone(),
+                interval: Duration::from_secs(opt.gossip_interval),
+            };
+            run_gossip(
+                gossip_options,
+                api_services.checkpoints.clone(),
+                Arc::new(SystemClock),
+            )
+            .await;
+        }
+    };
+
+    let spot_audit = async {
+        if let Some(interval) = opt.spot_audit_interval {
+            let data_source = QueryServiceDataSource::connect(&opt.sequencer_url, &http).await;
+            let spot_audit_options = SpotAuditOptions {
+                namespace: vm.into(),
+                ordering_policy: opt.ordering_policy,
+                interval: Duration::from_secs(interval),
+            };
+            run_spot_audit(
+                spot_audit_options,
+                Arc::new(data_source),
+                api_services.receipts.clone(),
+                api_services.spot_audits.clone(),
+                rng.clone(),
+                Arc::new(SystemClock),
+            )
+            .await;
+        }
+    };
+
+    let l1 = opt.l1_client_pool().unwrap();
+    let provider = l1.provider();
+    let chain_id = provider.get_chainid().await.unwrap().as_u64();
+    // A wrong light client address would leave the executor waiting for events forever.
+    match validate_light_client(&provider, opt.light_client_address).await {
+        Ok(state) => tracing::info!(
+            "Light client at {:?} has finalized block {}",
+            opt.light_client_address,
+            state.block_height
+        ),
+        Err(err) => {
+            tracing::error!("{err} Run the doctor subcommand to check the configuration.");
+            std::process::exit(1);
+        }
+    }
+    let deployment = match &opt.deployment_file {
+        Some(path) => DeploymentRecord::load(path).unwrap(),
+        None => None,
+    };
+    let rollup_address = match deployment {
+        Some(record) => {
+            record
+                .check(chain_id, opt.light_client_address, initial_state)
+                .unwrap();
+            let rollup = connect_l1_client(
+                opt.l1_client,
+                &l1,
+                &opt.l1_signer_config(),
+                record.rollup_address,
+            )
+            .await
+            .unwrap();
+            ContractState::fetch(rollup.as_ref())
+                .await
+                .unwrap()
+                .check(initial_state, &outbox.entries().await)
+                .unwrap();
+            tracing::info!(
+                "Reusing Rollup contract at {:?} deployed in transaction {:?}",
+                record.rollup_address,
+                record.tx_hash
+            );
+            record.rollup_address
+        }
+        None => {
+            tracing::info!("Deploying Rollup contracts");
+            let test_system = TestL1System::new(l1.http_provider(), opt.light_client_address)
+                .await
+                .unwrap();
+            let prover = opt.l1_signer_config().address(chain_id).await.unwrap();
+            let (_, deployment) = deploy_example_contract_with_deployment(
+                &test_system,
+                initial_state,
+                genesis_supply,
+                opt.light_client_address,
+                prover,
+            )
+            .await;
+            let record = DeploymentRecord {
+                rollup_address: deployment.rollup_address,
+                chain_id,
+                light_client_address: opt.light_client_address,
+                genesis_commitment: initial_state,
+                deployer: deployment.deployer,
+                tx_hash: deployment.tx_hash,
+            };
+            if let Some(path) = &opt.deployment_file {
+                record.save(path).unwrap();
+                tracing::info!("Deployment record written to {}", path.display());
+            }
+            record.rollup_address
+        }
+    };
+
+    let (shutdown_sender, shutdown) = async_std::channel::bounded(1);
+    let warm_start_writer = match &opt.warm_start {
+        Some(_) if opt.dry_run => {
+            tracing::warn!("Not writing a warm-start bundle in dry-run mode");
+            None
+        }
+        Some(path) => Some(WarmStartWriter {
+            path: path.clone(),
+            genesis: initial_state,
+            receipts: receipts.clone(),
+            shutdown,
+        }),
+        None => None,
+    };
+
+    // Bind both the executed state and the state served by the API to this deployment, before the
+    // first block executes.
+    let rollup_chain_id = ChainId::derive(vm.into(), initial_state, chain_id, rollup_address);
+    state.write().await.set_chain_id(rollup_chain_id);
+    api_state.write().await.set_chain_id(rollup_chain_id);
+    tracing::info!("Rollup chain ID is {rollup_chain_id}");
 
     let executor_options = ExecutorOptions {
         light_client_address: opt.light_client_address,
-        l1_http_provider: opt.l1_http_provider.clone(),
+        escrow_address: opt.escrow_address,
+        l1,
         l1_ws_provider: opt.l1_ws_provider.clone(),
-        rollup_address: rollup_contract.address(),
-        rollup_account_index: opt.rollup_account_index,
-        rollup_mnemonic: opt.rollup_mnemonic.clone(),
+        rollup_address,
+        l1_signer: opt.l1_signer_config(),
         sequencer_url: opt.sequencer_url.clone(),
-        output_stream: None,
+        output_stream: Some(output_stream.clone()),
+        aggregation_strategy: opt.aggregation_strategy,
+        aggregator,
+        max_batch_size: opt.max_batch_size,
+        clock: Arc::new(SystemClock),
+        finality_lag: finality_lag.clone(),
+        dry_run: opt.dry_run,
+        self_check: opt.execution_self_check,
+        verify_headers: opt.verify_headers,
+        breaker: CircuitBreaker::from_kinds(
+            &opt.circuit_breaker_checks,
+            opt.circuit_breaker_max_lag,
+        ),
+        l1_client: opt.l1_client,
+        proof_shape: opt.proof_shape,
+        outbox: outbox.clone(),
+        latency,
+        http: http.clone(),
+        watchdog: api_services.watchdog.clone(),
+        da_policy: DaTimeoutPolicy {
+            timeout: Duration::from_secs(opt.da_timeout),
+            action: opt.da_timeout_action,
+            incidents: api_services.da_incidents.clone(),
+            cadence: Default::default(),
+        },
+        header_page_size: opt.header_page_size,
+        catch_up: api_services.catch_up.clone(),
+        proof_verifier: api_services.proof_verifier.clone(),
+        resume,
+        checkpoints: storage.map(|storage| Checkpointer::new(storage, initial_state)),
+        recover_from_l1: opt.recover_from_l1,
+        canary: api_services.canary.clone(),
+        warm_start: warm_start_writer,
+        metrics,
+    };
+
+    // With a warm-start bundle configured, the executor returns once it has been asked to stop and
+    // has written the bundle, at which point the other tasks are abandoned.
+    let executor = async {
+        run_executor(&executor_options, state.clone()).await;
+        if executor_options.warm_start.is_some() {
+            std::process::exit(0);
+        }
+    };
+
+    // With a warm-start bundle configured, the first SIGINT or SIGTERM asks the executor to stop
+    // after the batch it is executing. A second signal exits immediately, without a bundle.
+    let handle_signals = async {
+        if executor_options.warm_start.is_some() {
+            let mut signals = Signals::new([SIGINT, SIGTERM]).unwrap();
+            if signals.next().await.is_some() {
+                tracing::info!(
+                    "Stopping after the current batch, signal again to exit immediately"
+                );
+                shutdown_sender.send(()).await.ok();
+            }
+            if signals.next().await.is_some() {
+                std::process::exit(1);
+            }
+        }
     };
 
     tracing::info!("Launching Example Rollup API and Executor");
-    join!(run_executor(&executor_options, state.clone()), serve_api,);
+    join!(
+        executor,
+        handle_signals,
+        serve_api,
+        serve_grpc,
+        sync_api_state,
+        mempool,
+        gossip,
+        spot_audit
+    );
+}
+
+/// Launch a devnet of Anvil, a sequencer and this rollup, and run it until a signal is received or
+/// any of its services exits.
+async fn demo_up(opt: &Options, up: &DemoUpOptions) {
+    let rollup_program = std::env::current_exe()
+        .expect("unable to locate this executable")
+        .display()
+        .to_string();
+    let services =
+        devnet::plan(opt, up, rollup_program, devnet::node_args(std::env::args())).unwrap();
+    let http = HttpClientPool::new(opt.http_client_options());
+    let mut devnet =
+        match Devnet::launch(services, &http, Duration::from_secs(up.ready_timeout)).await {
+            Ok(devnet) => devnet,
+            Err(err) => {
+                tracing::error!("Unable to launch the devnet: {err}");
+                std::process::exit(1);
+            }
+        };
+    println!("Devnet is ready, press Ctrl-C to stop it:");
+    for service in devnet.services() {
+        println!("  {service}");
+    }
+
+    let mut signals = Signals::new([SIGINT, SIGTERM]).unwrap();
+    let exited = match select(signals.next(), Box::pin(devnet.watch())).await {
+        Either::Left(_) => None,
+        Either::Right((err, _)) => Some(err),
+    };
+    devnet.shut_down();
+    if let Some(err) = exited {
+        tracing::error!("Devnet stopped: {err}");
+        std::process::exit(1);
+    }
 }
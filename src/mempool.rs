@@ -0,0 +1,482 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Local holding of transactions submitted ahead of their sender's nonce.
+//!
+//! A transaction whose nonce is ahead of its sender's next nonce would be rejected if it were
+//! sequenced now, but may become valid once the transactions before it execute. Rather than
+//! forwarding such a transaction to the sequencer, the `submit` route holds it here, ordered by
+//! nonce per sender. After each block, [`run_mempool`] submits every held transaction whose nonce
+//! has become the sender's next nonce, and drops those whose nonce has been used by another
+//! transaction. Held transactions are listed per sender by `rollup/mempool/:address`.
+//!
+//! Transactions are prepared for the sequencer, including the operator's countersignature, when
+//! they are held, so the client receives the same sequencer transaction hash as for a transaction
+//! submitted immediately. The mempool is kept in memory, so held transactions are lost when the
+//! node restarts.
+//!
+//! The mempool holds a bounded number of transactions, in total and per sender. When it is full, a
+//! new transaction evicts the held transaction with the lowest fee, the oldest first, if the new
+//! one pays a higher fee. Only the highest nonce held for a sender is evicted, so that the
+//! transactions left for the sender can still execute in order. A sender at its own limit can
+//! replace its highest held nonce with a lower one, which is closer to executing. Evictions are
+//! counted in the node's metrics.
+
+use crate::api::post_transaction;
+use crate::http::HttpClientPool;
+use crate::metrics::NodeMetrics;
+use crate::state::{Amount, Nonce, State};
+use crate::stats::{unix_millis, LatencyTracker};
+use async_compatibility_layer::async_primitives::broadcast::BroadcastReceiver;
+use async_std::sync::{Arc, RwLock};
+use committable::{Commitment, Committable};
+use espresso_types::Transaction;
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::{BTreeMap, HashMap};
+use surf_disco::Url;
+use tide_disco::{error::ServerError, StatusCode};
+
+/// The default number of transactions held across all senders.
+pub const DEFAULT_MEMPOOL_CAPACITY: usize = 1024;
+
+/// The default number of transactions held for any one sender.
+pub const DEFAULT_MAX_HELD_PER_SENDER: usize = 16;
+
+#[derive(Clone, Debug, Snafu, PartialEq, Eq)]
+pub enum MempoolError {
+    #[snafu(display("{address:?} already has the maximum of {max} transactions held."))]
+    SenderFull { address: Address, max: usize },
+    #[snafu(display(
+        "The mempool is full of transactions paying at least the same fee, retry once the \
+         sender's earlier nonces execute."
+    ))]
+    Full,
+}
+
+impl From<MempoolError> for ServerError {
+    fn from(err: MempoolError) -> Self {
+        Self {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            message: err.to_string(),
+        }
+    }
+}
+
+/// A transaction held until its sender's nonce catches up.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingTransaction {
+    /// Hash of the rollup transaction.
+    pub hash: H256,
+    pub nonce: Nonce,
+    /// The fee offered by the transaction, which decides which transactions are evicted when the
+    /// mempool is full.
+    pub fee: Amount,
+    /// Hash of the sequencer transaction which will be submitted.
+    pub sequencer_hash: Commitment<Transaction>,
+    pub received_ms: u64,
+}
+
+#[derive(Clone, Debug)]
+struct Held {
+    pending: PendingTransaction,
+    transaction: Transaction,
+}
+
+#[derive(Debug, Default)]
+struct MempoolInner {
+    senders: HashMap<Address, BTreeMap<Nonce, Held>>,
+    len: usize,
+}
+
+/// Transactions with future nonces, by sender and nonce.
+#[derive(Clone, Debug)]
+pub struct Mempool {
+    capacity: usize,
+    max_per_sender: usize,
+    metrics: NodeMetrics,
+    inner: Arc<RwLock<MempoolInner>>,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMPOOL_CAPACITY)
+    }
+}
+
+impl Mempool {
+    /// Hold up to `capacity` transactions. With a capacity of 0, transactions with future nonces
+    /// are submitted immediately.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            max_per_sender: DEFAULT_MAX_HELD_PER_SENDER,
+            metrics: Default::default(),
+            inner: Default::default(),
+        }
+    }
+
+    /// Hold at most `max` transactions for any one sender.
+    pub fn with_max_per_sender(mut self, max: usize) -> Self {
+        self.max_per_sender = max;
+        self
+    }
+
+    /// Count evictions in `metrics`.
+    pub fn with_metrics(mut self, metrics: NodeMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// The number of transactions the mempool can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of transactions held.
+    pub async fn depth(&self) -> usize {
+        self.inner.read().await.len
+    }
+
+    /// The lowest fee with which a new transaction can currently be held.
+    ///
+    /// This is 0 unless the mempool is full, in which case the transaction must outbid the cheapest
+    /// held transaction which could be evicted.
+    pub async fn min_fee(&self) -> Amount {
+        let inner = self.inner.read().await;
+        if !self.is_enabled() || inner.len < self.capacity {
+            return 0;
+        }
+        inner
+            .senders
+            .values()
+            .filter_map(|held| held.last_key_value())
+            .map(|(_, held)| held.pending.fee.saturating_add(1))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Hold `transaction`, the sequencer transaction for the rollup transaction `hash` from
+    /// `sender` with `nonce` offering `fee`, returning the hash of the sequencer transaction.
+    ///
+    /// A transaction from the same sender with the same nonce replaces the one already held. If the
+    /// mempool is full, a held transaction may be evicted to make room, as described in the
+    /// [module documentation](self).
+    pub async fn hold(
+        &self,
+        sender: Address,
+        nonce: Nonce,
+        hash: H256,
+        fee: Amount,
+        transaction: Transaction,
+    ) -> Result<Commitment<Transaction>, MempoolError> {
+        let mut inner = self.inner.write().await;
+        let MempoolInner { senders, len } = &mut *inner;
+        let sender_held = senders.get(&sender);
+        if !sender_held.is_some_and(|held| held.contains_key(&nonce)) {
+            let highest =
+                sender_held.and_then(|held| held.last_key_value().map(|(nonce, _)| *nonce));
+            if sender_held.map_or(0, BTreeMap::len) >= self.max_per_sender {
+                // A lower nonce is closer to executing than the sender's highest.
+                match highest.filter(|highest| nonce < *highest) {
+                    Some(highest) => self.evict(senders, sender, highest),
+                    None => {
+                        return Err(MempoolError::SenderFull {
+                            address: sender,
+                            max: self.max_per_sender,
+                        })
+                    }
+                }
+            } else if *len >= self.capacity {
+                // The new transaction may take the place of the highest nonce of another sender,
+                // or of its own sender if it comes before it.
+                let lowest = senders
+                    .iter()
+                    .filter_map(|(address, held)| {
+                        let (last, held) = held.last_key_value()?;
+                        Some((held.pending.fee, held.pending.received_ms, *address, *last))
+                    })
+                    .filter(|(_, _, address, _)| *address != sender || highest > Some(nonce))
+                    .min();
+                match lowest.filter(|(lowest_fee, ..)| *lowest_fee < fee) {
+                    Some((_, _, address, last)) => self.evict(senders, address, last),
+                    None => return Err(MempoolError::Full),
+                }
+            } else {
+                *len += 1;
+            }
+        }
+        let sequencer_hash = transaction.commit();
+        senders.entry(sender).or_default().insert(
+            nonce,
+            Held {
+                pending: PendingTransaction {
+                    hash,
+                    nonce,
+                    fee,
+                    sequencer_hash,
+                    received_ms: unix_millis(),
+                },
+                transaction,
+            },
+        );
+        tracing::debug!("Holding transaction {hash:?} from {sender:?} with nonce {nonce}");
+        Ok(sequencer_hash)
+    }
+
+    /// Remove the transaction held for `address` with `nonce`, to make room for another.
+    fn evict(
+        &self,
+        senders: &mut HashMap<Address, BTreeMap<Nonce, Held>>,
+        address: Address,
+        nonce: Nonce,
+    ) {
+        let Some(held) = senders.get_mut(&address) else {
+            return;
+        };
+        if let Some(evicted) = held.remove(&nonce) {
+            tracing::info!(
+                "Evicting held transaction {:?}, nonce {nonce} of {address:?}, with fee {}",
+                evicted.pending.hash,
+                evicted.pending.fee
+            );
+            self.metrics.record_mempool_eviction();
+        }
+        if held.is_empty() {
+            senders.remove(&address);
+        }
+    }
+
+    /// The transactions held for `address`, in nonce order.
+    pub async fn pending(&self, address: &Address) -> Vec<PendingTransaction> {
+        self.inner
+            .read()
+            .await
+            .senders
+            .get(address)
+            .map(|held| held.values().map(|held| held.pending.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// The highest nonce held for `address`, if any.
+    pub async fn highest_nonce(&self, address: &Address) -> Option<Nonce> {
+        self.inner
+            .read()
+            .await
+            .senders
+            .get(address)
+            .and_then(|held| held.last_key_value().map(|(nonce, _)| *nonce))
+    }
+
+    /// The sequencer transaction held for the rollup transaction `hash`, if any.
+    pub async fn get(&self, hash: &H256) -> Option<Transaction> {
+        self.inner
+            .read()
+            .await
+            .senders
+            .values()
+            .flat_map(BTreeMap::values)
+            .find(|held| held.pending.hash == *hash)
+            .map(|held| held.transaction.clone())
+    }
+
+    /// Remove the held transactions which `state` makes current, with their rollup transaction
+    /// hashes.
+    ///
+    /// Transactions whose nonce has already been used are dropped.
+    pub async fn release(&self, state: &State) -> Vec<(H256, Transaction)> {
+        self.release_with(|address| state.get_nonce(address)).await
+    }
+
+    async fn release_with(&self, nonce: impl Fn(&Address) -> Nonce) -> Vec<(H256, Transaction)> {
+        let mut inner = self.inner.write().await;
+        let MempoolInner { senders, len } = &mut *inner;
+        let mut released = vec![];
+        senders.retain(|address, held| {
+            let next = nonce(address) + 1;
+            let current = held.split_off(&next);
+            for held in std::mem::replace(held, current).into_values() {
+                tracing::info!(
+                    "Dropping held transaction {:?}, nonce {} of {address:?} has been used",
+                    held.pending.hash,
+                    held.pending.nonce
+                );
+            }
+            if let Some(entry) = held.first_entry().filter(|entry| *entry.key() == next) {
+                let held = entry.remove();
+                released.push((held.pending.hash, held.transaction));
+            }
+            !held.is_empty()
+        });
+        *len = senders.values().map(BTreeMap::len).sum();
+        released
+    }
+}
+
+/// Submit held transactions to the sequencer at `sequencer_url` as the states published on the
+/// executor's output stream make them current.
+pub async fn run_mempool(
+    mempool: Mempool,
+    mut updates: BroadcastReceiver<(u64, State)>,
+    http: HttpClientPool,
+    sequencer_url: Url,
+    latency: LatencyTracker,
+) {
+    while let Ok((block_height, state)) = updates.recv_async().await {
+        for (hash, transaction) in mempool.release(&state).await {
+            match post_transaction(&http, &sequencer_url, &transaction).await {
+                Ok(()) => {
+                    tracing::info!(
+                        "Submitted held transaction {hash:?} after block {block_height}"
+                    );
+                    latency.record_submitted(hash, unix_millis()).await;
+                }
+                Err(err) => {
+                    tracing::warn!("Unable to submit held transaction {hash:?}: {err}");
+                }
+            }
+        }
+    }
+    tracing::warn!("Executor output stream closed, held transactions will no longer be submitted");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use espresso_types::NamespaceId;
+
+    fn transaction(nonce: Nonce) -> (H256, Transaction) {
+        (
+            H256::from_low_u64_be(nonce),
+            Transaction::new(NamespaceId::from(1_u64), nonce.to_le_bytes().to_vec()),
+        )
+    }
+
+    #[async_std::test]
+    async fn test_mempool_release_in_nonce_order() {
+        let mempool = Mempool::default();
+        let alice = Address::random();
+        for nonce in [4, 3, 2] {
+            let (hash, txn) = transaction(nonce);
+            let sequencer_hash = mempool
+                .hold(alice, nonce, hash, 0, txn.clone())
+                .await
+                .unwrap();
+            assert_eq!(sequencer_hash, txn.commit());
+        }
+        let pending = mempool.pending(&alice).await;
+        assert_eq!(
+            pending.iter().map(|txn| txn.nonce).collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+        assert_eq!(mempool.highest_nonce(&alice).await, Some(4));
+        assert_eq!(mempool.highest_nonce(&Address::random()).await, None);
+        assert_eq!(mempool.get(&transaction(3).0).await, Some(transaction(3).1));
+        assert_eq!(mempool.get(&H256::random()).await, None);
+
+        // Nothing is current until nonce 1 executes.
+        assert_eq!(mempool.release_with(|_| 0).await, []);
+        // Only the next nonce is released, not the whole run after it.
+        assert_eq!(mempool.release_with(|_| 1).await, [transaction(2)]);
+        assert_eq!(mempool.release_with(|_| 1).await, []);
+        // A nonce used by another transaction is dropped.
+        assert_eq!(mempool.release_with(|_| 3).await, [transaction(4)]);
+        assert_eq!(mempool.pending(&alice).await, []);
+    }
+
+    #[async_std::test]
+    async fn test_mempool_capacity() {
+        let mempool = Mempool::new(DEFAULT_MAX_HELD_PER_SENDER + 1);
+        let alice = Address::random();
+        for nonce in 2..DEFAULT_MAX_HELD_PER_SENDER as Nonce + 2 {
+            let (hash, txn) = transaction(nonce);
+            mempool.hold(alice, nonce, hash, 0, txn).await.unwrap();
+        }
+        let (hash, txn) = transaction(100);
+        assert_eq!(
+            mempool.hold(alice, 100, hash, 0, txn).await,
+            Err(MempoolError::SenderFull {
+                address: alice,
+                max: DEFAULT_MAX_HELD_PER_SENDER
+            })
+        );
+        // Replacing a held transaction takes no more space.
+        let (hash, txn) = transaction(2);
+        mempool.hold(alice, 2, hash, 0, txn).await.unwrap();
+
+        let bob = Address::random();
+        let (hash, txn) = transaction(2);
+        mempool.hold(bob, 2, hash, 0, txn).await.unwrap();
+        let (hash, txn) = transaction(3);
+        assert_eq!(
+            mempool.hold(bob, 3, hash, 0, txn).await,
+            Err(MempoolError::Full)
+        );
+    }
+
+    #[async_std::test]
+    async fn test_mempool_eviction() {
+        let metrics = NodeMetrics::default();
+        let mempool = Mempool::new(4)
+            .with_max_per_sender(3)
+            .with_metrics(metrics.clone());
+        let mempool = &mempool;
+        let hold = |sender, nonce, fee| {
+            let (hash, txn) = transaction(nonce);
+            mempool.hold(sender, nonce, hash, fee, txn)
+        };
+        let nonces = |sender| async move {
+            mempool
+                .pending(&sender)
+                .await
+                .iter()
+                .map(|txn| txn.nonce)
+                .collect::<Vec<_>>()
+        };
+        let [alice, bob, carol, dave] = [(); 4].map(|_| Address::random());
+
+        // A sender at its limit can replace its highest nonce with a lower one, but not add a
+        // higher one.
+        for nonce in [3, 4, 5] {
+            hold(alice, nonce, 5).await.unwrap();
+        }
+        assert_eq!(
+            hold(alice, 6, 100).await,
+            Err(MempoolError::SenderFull {
+                address: alice,
+                max: 3
+            })
+        );
+        hold(alice, 2, 5).await.unwrap();
+        assert_eq!(nonces(alice).await, [2, 3, 4]);
+
+        // When the mempool is full, a transaction evicts the one with the lowest fee, if it pays
+        // more.
+        hold(bob, 2, 1).await.unwrap();
+        assert_eq!(hold(carol, 2, 1).await, Err(MempoolError::Full));
+        hold(carol, 2, 2).await.unwrap();
+        assert_eq!(nonces(bob).await, []);
+        assert_eq!(nonces(carol).await, [2]);
+
+        // Only the highest nonce of a sender is evicted, so the rest can still execute in order.
+        hold(dave, 2, 6).await.unwrap();
+        hold(bob, 2, 6).await.unwrap();
+        assert_eq!(nonces(carol).await, []);
+        assert_eq!(nonces(alice).await, [2, 3]);
+        assert_eq!(nonces(bob).await, [2]);
+
+        // Every eviction is counted.
+        assert!(metrics
+            .render()
+            .lines()
+            .any(|line| line == "rollup_mempool_evictions_total 4"));
+    }
+}
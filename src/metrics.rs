@@ -0,0 +1,220 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Executor metrics, served in the Prometheus text format at `status/metrics`.
+//!
+//! The executor counts the blocks it executes and the transactions in them, the outbox counts the
+//! batch proofs it submits and the L1 gas they use, and both record the heights from which the
+//! verification lag is derived. The mempool counts the held transactions it evicts. Counters start
+//! from zero when the node starts.
+
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Default)]
+struct Counters {
+    blocks_executed: AtomicU64,
+    transactions_applied: AtomicU64,
+    transactions_rejected: AtomicU64,
+    proofs_submitted: AtomicU64,
+    proofs_confirmed: AtomicU64,
+    proofs_reverted: AtomicU64,
+    l1_gas_used: AtomicU64,
+    hotshot_height: AtomicU64,
+    verified_height: AtomicU64,
+    mempool_evictions: AtomicU64,
+}
+
+/// Metrics shared by the executor, the outbox, the mempool and the API.
+#[derive(Clone, Debug, Default)]
+pub struct NodeMetrics {
+    counters: Arc<Counters>,
+}
+
+impl NodeMetrics {
+    /// Record an executed block, with the number of its transactions which were applied and
+    /// rejected.
+    pub fn record_block(&self, applied: usize, rejected: usize) {
+        let counters = &self.counters;
+        counters.blocks_executed.fetch_add(1, Ordering::Relaxed);
+        counters
+            .transactions_applied
+            .fetch_add(applied as u64, Ordering::Relaxed);
+        counters
+            .transactions_rejected
+            .fetch_add(rejected as u64, Ordering::Relaxed);
+    }
+
+    /// Record the first broadcast of a batch proof to the rollup contract.
+    pub fn record_proof_submitted(&self) {
+        self.counters
+            .proofs_submitted
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the inclusion of a proof submission on the L1, which pays for `gas_used` whether or
+    /// not it succeeded.
+    pub fn record_proof_included(&self, success: bool, gas_used: Option<u64>) {
+        let counters = &self.counters;
+        if success {
+            counters.proofs_confirmed.fetch_add(1, Ordering::Relaxed);
+        } else {
+            counters.proofs_reverted.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(gas_used) = gas_used {
+            counters.l1_gas_used.fetch_add(gas_used, Ordering::Relaxed);
+        }
+    }
+
+    /// Record the latest HotShot block height finalized by the light client.
+    pub fn set_hotshot_height(&self, height: u64) {
+        self.counters
+            .hotshot_height
+            .fetch_max(height, Ordering::Relaxed);
+    }
+
+    /// Record the number of blocks verified by the rollup contract.
+    pub fn set_verified_height(&self, height: u64) {
+        self.counters
+            .verified_height
+            .fetch_max(height, Ordering::Relaxed);
+    }
+
+    /// Record the eviction of a held transaction from the full mempool.
+    pub fn record_mempool_eviction(&self) {
+        self.counters
+            .mempool_evictions
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let counters = &self.counters;
+        let hotshot_height = counters.hotshot_height.load(Ordering::Relaxed);
+        let verified_height = counters.verified_height.load(Ordering::Relaxed);
+        let metrics = [
+            (
+                "rollup_blocks_executed_total",
+                "counter",
+                "Blocks containing the rollup namespace executed.",
+                counters.blocks_executed.load(Ordering::Relaxed),
+            ),
+            (
+                "rollup_transactions_applied_total",
+                "counter",
+                "Transactions applied to the rollup state.",
+                counters.transactions_applied.load(Ordering::Relaxed),
+            ),
+            (
+                "rollup_transactions_rejected_total",
+                "counter",
+                "Transactions in executed blocks which failed to apply.",
+                counters.transactions_rejected.load(Ordering::Relaxed),
+            ),
+            (
+                "rollup_proofs_submitted_total",
+                "counter",
+                "Batch proofs submitted to the rollup contract.",
+                counters.proofs_submitted.load(Ordering::Relaxed),
+            ),
+            (
+                "rollup_proofs_confirmed_total",
+                "counter",
+                "Batch proof submissions which succeeded on the L1.",
+                counters.proofs_confirmed.load(Ordering::Relaxed),
+            ),
+            (
+                "rollup_proofs_reverted_total",
+                "counter",
+                "Batch proof submissions which reverted on the L1.",
+                counters.proofs_reverted.load(Ordering::Relaxed),
+            ),
+            (
+                "rollup_l1_gas_used_total",
+                "counter",
+                "L1 gas used by included batch proof submissions.",
+                counters.l1_gas_used.load(Ordering::Relaxed),
+            ),
+            (
+                "rollup_mempool_evictions_total",
+                "counter",
+                "Held transactions evicted from the full mempool.",
+                counters.mempool_evictions.load(Ordering::Relaxed),
+            ),
+            (
+                "rollup_hotshot_height",
+                "gauge",
+                "Latest HotShot block height finalized by the light client.",
+                hotshot_height,
+            ),
+            (
+                "rollup_verified_height",
+                "gauge",
+                "Number of blocks verified by the rollup contract.",
+                verified_height,
+            ),
+            (
+                "rollup_verification_lag_blocks",
+                "gauge",
+                "Finalized HotShot blocks not yet verified by the rollup contract.",
+                hotshot_height.saturating_sub(verified_height),
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            writeln!(out, "# HELP {name} {help}").unwrap();
+            writeln!(out, "# TYPE {name} {kind}").unwrap();
+            writeln!(out, "{name} {value}").unwrap();
+        }
+        out
+    }
+}
+
+impl tide_disco::metrics::Metrics for NodeMetrics {
+    type Error = Infallible;
+
+    fn export(&self) -> Result<String, Self::Error> {
+        Ok(self.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = NodeMetrics::default();
+        metrics.record_block(3, 1);
+        metrics.record_block(2, 0);
+        metrics.record_proof_submitted();
+        metrics.record_proof_included(true, Some(21_000));
+        metrics.set_hotshot_height(10);
+        metrics.set_verified_height(4);
+        // Heights never go backwards.
+        metrics.set_hotshot_height(9);
+        metrics.record_mempool_eviction();
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE rollup_blocks_executed_total counter",
+            "rollup_blocks_executed_total 2",
+            "rollup_transactions_applied_total 5",
+            "rollup_transactions_rejected_total 1",
+            "rollup_proofs_submitted_total 1",
+            "rollup_proofs_confirmed_total 1",
+            "rollup_proofs_reverted_total 0",
+            "rollup_l1_gas_used_total 21000",
+            "rollup_mempool_evictions_total 1",
+            "# TYPE rollup_hotshot_height gauge",
+            "rollup_verification_lag_blocks 6",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {line:?}");
+        }
+    }
+}
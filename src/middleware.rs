@@ -0,0 +1,395 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Hooks which run on every request to the rollup API.
+
+use crate::api::CBOR_CONTENT_TYPE;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tide::listener::{ListenInfo, Listener, ToListener};
+use tide_disco::{error::ServerError, RequestParams};
+
+/// A hook run on every request to the rollup API before the request is handled.
+///
+/// Embedding applications can register middleware through
+/// [`APIOptions::middleware`](crate::api::APIOptions) to implement cross-cutting concerns such as
+/// authentication or request logging.
+pub trait Middleware: Debug + Send + Sync {
+    /// Inspect a request to `route`, returning an error to reject it.
+    fn on_request(&self, route: &str, req: &RequestParams) -> Result<(), ServerError>;
+}
+
+/// Run each middleware in order, stopping at the first one which rejects the request.
+///
+/// Handlers for routes added through [`ApiExtensions`](crate::api::ApiExtensions) should call this
+/// first, as the built-in handlers do.
+pub fn run_middleware(
+    middleware: &[Arc<dyn Middleware>],
+    route: &str,
+    req: &RequestParams,
+) -> Result<(), ServerError> {
+    middleware
+        .iter()
+        .try_for_each(|middleware| middleware.on_request(route, req))
+}
+
+/// App middleware enforcing the origins allowed to make cross-origin requests.
+///
+/// Requests without an `Origin` header (e.g. from `curl` or the CLI) are always allowed. An empty
+/// allow-list allows every origin. Responses to allowed origins carry the `Access-Control-Allow-*`
+/// headers, and `OPTIONS` preflight requests from them are answered directly. Requests from any
+/// other origin are rejected with `403 Forbidden`.
+///
+/// Unlike [`Middleware`], this runs in front of the whole app, as it must answer requests to routes
+/// the app does not serve and set headers on the responses; it is registered with an
+/// [`AppListener`].
+#[derive(Clone, Debug, Default)]
+pub struct CorsAllowList {
+    allowed_origins: Vec<String>,
+}
+
+/// Methods allowed in cross-origin requests to the API.
+const CORS_ALLOWED_METHODS: &str = "GET, POST, OPTIONS";
+
+/// Headers allowed in cross-origin requests, unless the preflight request names its own.
+const CORS_ALLOWED_HEADERS: &str = "Accept, Authorization, Content-Type";
+
+/// How long, in seconds, browsers may cache the result of a preflight request.
+const CORS_MAX_AGE: &str = "3600";
+
+impl CorsAllowList {
+    pub fn new(allowed_origins: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            allowed_origins: allowed_origins
+                .into_iter()
+                .map(|origin| origin.trim_end_matches('/').to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Whether requests from `origin` are allowed.
+    pub fn allows(&self, origin: &str) -> bool {
+        let origin = origin.trim_end_matches('/').to_lowercase();
+        self.allowed_origins.is_empty()
+            || self
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed == "*" || *allowed == origin)
+    }
+}
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> tide::Middleware<S> for CorsAllowList {
+    async fn handle(&self, req: tide::Request<S>, next: tide::Next<'_, S>) -> tide::Result {
+        let Some(origin) = req
+            .header("Origin")
+            .map(|origin| origin.last().as_str().to_string())
+        else {
+            return Ok(next.run(req).await);
+        };
+        if !self.allows(&origin) {
+            return Ok(tide::Response::builder(tide::StatusCode::Forbidden)
+                .body(format!(
+                    "Cross-origin requests from {origin} are not allowed."
+                ))
+                .build());
+        }
+
+        let mut res = if req.method() == tide::http::Method::Options {
+            let headers = req
+                .header("Access-Control-Request-Headers")
+                .map(|headers| headers.last().as_str().to_string())
+                .unwrap_or_else(|| CORS_ALLOWED_HEADERS.to_string());
+            let mut res = tide::Response::new(tide::StatusCode::NoContent);
+            res.insert_header("Access-Control-Allow-Methods", CORS_ALLOWED_METHODS);
+            res.insert_header("Access-Control-Allow-Headers", headers);
+            res.insert_header("Access-Control-Max-Age", CORS_MAX_AGE);
+            res
+        } else {
+            next.run(req).await
+        };
+        // The origin is echoed, rather than `*`, so that browsers also accept responses to
+        // requests with credentials, such as the admin routes' `Authorization` header.
+        res.insert_header("Access-Control-Allow-Origin", origin);
+        res.insert_header("Access-Control-Allow-Credentials", "true");
+        res.insert_header("Vary", "Origin");
+        Ok(res)
+    }
+}
+
+/// App middleware serving CBOR encoded responses to clients which send
+/// `Accept: application/cbor`.
+///
+/// tide-disco only serializes responses as JSON or bincode, so the app is asked for JSON, which is
+/// then transcoded. Responses which are not JSON, such as the Prometheus metrics, and socket
+/// upgrades are passed through unchanged.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CborResponses;
+
+impl CborResponses {
+    /// Whether `req` asks for a CBOR encoded response.
+    fn accepts_cbor<S>(req: &tide::Request<S>) -> bool {
+        req.header("Upgrade").is_none()
+            && req.header("Accept").is_some_and(|accept| {
+                accept.iter().any(|value| {
+                    value
+                        .as_str()
+                        .split(',')
+                        .any(|media| media.trim().starts_with(CBOR_CONTENT_TYPE))
+                })
+            })
+    }
+}
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> tide::Middleware<S> for CborResponses {
+    async fn handle(&self, mut req: tide::Request<S>, next: tide::Next<'_, S>) -> tide::Result {
+        if !Self::accepts_cbor(&req) {
+            return Ok(next.run(req).await);
+        }
+        req.insert_header("Accept", "application/json");
+        let mut res = next.run(req).await;
+        let body = res.take_body().into_bytes().await?;
+        match serde_json::from_slice::<serde_json::Value>(&body) {
+            Ok(value) => {
+                let mut cbor = vec![];
+                ciborium::into_writer(&value, &mut cbor).map_err(|err| {
+                    tide::Error::from_str(tide::StatusCode::InternalServerError, err.to_string())
+                })?;
+                res.set_body(cbor);
+                res.insert_header("Content-Type", CBOR_CONTENT_TYPE);
+            }
+            Err(_) => res.set_body(body),
+        }
+        Ok(res)
+    }
+}
+
+/// App middleware, run in front of every module of the app.
+pub type AppMiddleware = Arc<dyn tide::Middleware<()>>;
+
+/// Runs one of a list of [`AppMiddleware`].
+struct Layer(AppMiddleware);
+
+#[tide::utils::async_trait]
+impl tide::Middleware<()> for Layer {
+    async fn handle(&self, req: tide::Request<()>, next: tide::Next<'_, ()>) -> tide::Result {
+        self.0.handle(req, next).await
+    }
+
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+}
+
+/// A listener which serves the app behind a list of app middleware, such as [`CorsAllowList`] and
+/// [`CborResponses`].
+///
+/// tide-disco builds the server for an [`App`](tide_disco::App) itself, so the app is wrapped when
+/// it is bound: the wrapping server runs the app middleware in order, then hands every request to
+/// the app.
+pub struct AppListener {
+    address: SocketAddr,
+    layers: Vec<AppMiddleware>,
+    inner: <SocketAddr as ToListener<()>>::Listener,
+}
+
+impl AppListener {
+    pub fn new(address: SocketAddr, layers: Vec<AppMiddleware>) -> io::Result<Self> {
+        Ok(Self {
+            address,
+            layers,
+            inner: ToListener::<()>::to_listener(address)?,
+        })
+    }
+}
+
+/// Wrap `app` in a server which runs `layers` in front of it, outermost first.
+fn wrap_app<S: Clone + Send + Sync + 'static>(
+    app: tide::Server<S>,
+    layers: &[AppMiddleware],
+) -> tide::Server<()> {
+    let mut server = tide::new();
+    for layer in layers {
+        server.with(Layer(layer.clone()));
+    }
+    server.at("/").all(app.clone());
+    server.at("*").all(app);
+    server
+}
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> Listener<S> for AppListener {
+    async fn bind(&mut self, app: tide::Server<S>) -> io::Result<()> {
+        self.inner.bind(wrap_app(app, &self.layers)).await
+    }
+
+    async fn accept(&mut self) -> io::Result<()> {
+        self.inner.accept().await
+    }
+
+    fn info(&self) -> Vec<ListenInfo> {
+        self.inner.info()
+    }
+}
+
+impl<S: Clone + Send + Sync + 'static> ToListener<S> for AppListener {
+    type Listener = Self;
+
+    fn to_listener(self) -> io::Result<Self> {
+        Ok(self)
+    }
+}
+
+impl Debug for AppListener {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppListener")
+            .field("address", &self.address)
+            .field(
+                "layers",
+                &self
+                    .layers
+                    .iter()
+                    .map(|layer| layer.name())
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Display for AppListener {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}", self.address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tide::http::{Method, Request, Response, StatusCode};
+
+    #[test]
+    fn test_cors_allow_list() {
+        let open = CorsAllowList::default();
+        assert!(open.allows("https://example.com"));
+
+        let restricted = CorsAllowList::new(["https://Demo.Example.com/".to_string()]);
+        assert!(restricted.allows("https://demo.example.com"));
+        assert!(!restricted.allows("https://evil.example.com"));
+
+        let wildcard = CorsAllowList::new(["*".to_string()]);
+        assert!(wildcard.allows("https://evil.example.com"));
+    }
+
+    #[async_std::test]
+    async fn test_cors_headers() {
+        let mut app = tide::new();
+        app.at("/rollup/height").get(|_| async { Ok("0") });
+        let app = wrap_app(
+            app,
+            &[Arc::new(CorsAllowList::new([
+                "https://demo.example.com".to_string()
+            ]))],
+        );
+        let request = |method, origin: Option<&str>| {
+            let mut req = Request::new(method, "http://localhost/rollup/height");
+            if let Some(origin) = origin {
+                req.insert_header("Origin", origin);
+            }
+            req
+        };
+        let header = |res: &Response, name: &str| {
+            res.header(name)
+                .map(|values| values.last().as_str().to_string())
+        };
+
+        // Responses to an allowed origin carry the CORS headers.
+        let res: Response = app
+            .respond(request(Method::Get, Some("https://demo.example.com")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(
+            header(&res, "Access-Control-Allow-Origin").as_deref(),
+            Some("https://demo.example.com")
+        );
+        assert_eq!(header(&res, "Vary").as_deref(), Some("Origin"));
+
+        // Preflight requests from an allowed origin are answered without reaching the app.
+        let mut preflight = request(Method::Options, Some("https://demo.example.com"));
+        preflight.insert_header("Access-Control-Request-Method", "POST");
+        preflight.insert_header("Access-Control-Request-Headers", "content-type");
+        let res: Response = app.respond(preflight).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NoContent);
+        assert_eq!(
+            header(&res, "Access-Control-Allow-Origin").as_deref(),
+            Some("https://demo.example.com")
+        );
+        assert_eq!(
+            header(&res, "Access-Control-Allow-Methods").as_deref(),
+            Some(CORS_ALLOWED_METHODS)
+        );
+        assert_eq!(
+            header(&res, "Access-Control-Allow-Headers").as_deref(),
+            Some("content-type")
+        );
+
+        // Requests and preflights from any other origin are rejected, without CORS headers.
+        for method in [Method::Get, Method::Options] {
+            let res: Response = app
+                .respond(request(method, Some("https://evil.example.com")))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::Forbidden);
+            assert_eq!(header(&res, "Access-Control-Allow-Origin"), None);
+        }
+
+        // Requests without an origin are served as usual.
+        let res: Response = app.respond(request(Method::Get, None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(header(&res, "Access-Control-Allow-Origin"), None);
+    }
+
+    #[async_std::test]
+    async fn test_cbor_responses() {
+        let mut app = tide::new();
+        app.at("/rollup/balance")
+            .get(|req: tide::Request<()>| async move {
+                // The app is always asked for JSON.
+                assert_eq!(
+                    req.header("Accept").map(|accept| accept.last().as_str()),
+                    Some("application/json")
+                );
+                Ok(tide::Body::from_json(
+                    &serde_json::json!({ "balance": 9999 }),
+                )?)
+            });
+        app.at("/status/metrics")
+            .get(|_| async { Ok("blocks_executed 1") });
+        let app = wrap_app(app, &[Arc::new(CborResponses)]);
+        let request = |path: &str| {
+            let mut req = Request::new(Method::Get, format!("http://localhost{path}").as_str());
+            req.insert_header("Accept", "application/cbor, application/json;q=0.5");
+            req
+        };
+
+        let mut res: Response = app.respond(request("/rollup/balance")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(
+            res.header("Content-Type").map(|ty| ty.last().as_str()),
+            Some(CBOR_CONTENT_TYPE)
+        );
+        let body = res.body_bytes().await.unwrap();
+        let value: serde_json::Value = ciborium::from_reader(body.as_slice()).unwrap();
+        assert_eq!(value, serde_json::json!({ "balance": 9999 }));
+
+        // Responses which are not JSON are passed through.
+        let mut res: Response = app.respond(request("/status/metrics")).await.unwrap();
+        assert_eq!(res.body_string().await.unwrap(), "blocks_executed 1");
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! A Rust mirror of the verification logic of the `ExampleRollup` contract.
+//!
+//! [`ContractMirror`] applies the same rules as `verifyBlocks`, `verifyBlocksWithCommitments`,
+//! `verifyBlocksCompressed` and `verifyBlocksWithWithdrawals`, in the same order, and fails with
+//! the same errors. The outbox runs it against the contract's current state as a preflight check,
+//! so a batch which would revert is never paid for, and tests use it in place of a deployed
+//! contract.
+//!
+//! Like the contract, the mirror does not check that a batch starts from the stored state
+//! commitment; the outbox compares `old_state` with the contract before submitting. The mirror
+//! must be kept in step with `contracts/src/ExampleRollup.sol`.
+
+use crate::l1::{BatchProofInput, StateUpdate};
+use crate::prover::ProofShape;
+use crate::withdrawal::WithdrawalsRoot;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+
+/// A revert of the rollup contract, named after its Solidity error.
+#[derive(Clone, Debug, Snafu, PartialEq, Eq)]
+pub enum MirrorError {
+    #[snafu(display(
+        "Blocks {num_verified_blocks}..{} are not yet finalized by the light client, which is at \
+         block {block_height}.",
+        num_verified_blocks + count
+    ))]
+    NotYetSequenced {
+        num_verified_blocks: u64,
+        count: u64,
+        block_height: u64,
+    },
+    #[snafu(display("A batch must verify at least one block."))]
+    NoBlocks,
+    #[snafu(display("The batch proof is not consistent with the submitted state commitments."))]
+    InvalidProof,
+}
+
+/// The outcome of a successful verification, as emitted in the contract's events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Verified {
+    pub state_update: StateUpdate,
+    /// The `CommitmentsDigest` event, emitted unless the batch was submitted with
+    /// [`ProofShape::Endpoints`] and without withdrawals.
+    pub commitments_digest: Option<[u8; 32]>,
+    /// The `WithdrawalsUpdate` event, emitted for a batch with withdrawals.
+    pub withdrawals: Option<WithdrawalsRoot>,
+}
+
+/// The storage of the rollup contract relevant to verification, along with the block height
+/// finalized by its light client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractMirror {
+    pub state_commitment: [u8; 32],
+    pub num_verified_blocks: u64,
+    /// `blockHeight` of the light client's finalized state.
+    pub finalized_height: u64,
+}
+
+impl ContractMirror {
+    /// A freshly deployed contract with `initial_state`.
+    pub fn new(initial_state: [u8; 32], finalized_height: u64) -> Self {
+        Self {
+            state_commitment: initial_state,
+            num_verified_blocks: 0,
+            finalized_height,
+        }
+    }
+
+    /// Verify `proof` of the next `count` blocks, submitted in `shape`, updating the mirrored
+    /// storage as the contract would.
+    ///
+    /// A batch with withdrawals is submitted with `verifyBlocksWithWithdrawals` whatever `shape`
+    /// is, as the L1 clients do.
+    ///
+    /// The executor always submits the proof's `new_state` as the next state commitment, so the
+    /// contract's check that the two are equal always passes and is not mirrored.
+    pub fn verify_blocks(
+        &mut self,
+        count: u64,
+        proof: &BatchProofInput,
+        shape: ProofShape,
+    ) -> Result<Verified, MirrorError> {
+        // `verifyBlocksWithCommitments` checks the commitments before verifying the batch.
+        if shape == ProofShape::Full
+            && proof.withdrawals.is_none()
+            && proof.commitments.last() != Some(&proof.new_state)
+        {
+            return Err(MirrorError::InvalidProof);
+        }
+        if count == 0 {
+            return Err(MirrorError::NoBlocks);
+        }
+        // The contract's counter is a 256-bit word, so the sum never wraps.
+        if self.num_verified_blocks.saturating_add(count) > self.finalized_height {
+            return Err(MirrorError::NotYetSequenced {
+                num_verified_blocks: self.num_verified_blocks,
+                count,
+                block_height: self.finalized_height,
+            });
+        }
+        self.num_verified_blocks += count;
+        self.state_commitment = proof.new_state;
+        let commitments_digest = match (shape, proof.withdrawals) {
+            (ProofShape::Endpoints, None) => None,
+            _ => Some(proof.commitments_digest()),
+        };
+        Ok(Verified {
+            state_update: StateUpdate {
+                num_verified_blocks: self.num_verified_blocks,
+                state_commitment: self.state_commitment,
+            },
+            commitments_digest,
+            withdrawals: proof.withdrawals,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proof(old_state: u8, new_state: u8) -> BatchProofInput {
+        BatchProofInput {
+            old_state: [old_state; 32],
+            new_state: [new_state; 32],
+            commitments: vec![[new_state; 32]],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_contract_mirror() {
+        let mut contract = ContractMirror::new([0; 32], 5);
+        let verified = contract
+            .verify_blocks(3, &proof(0, 1), ProofShape::Endpoints)
+            .unwrap();
+        assert_eq!(
+            verified,
+            Verified {
+                state_update: StateUpdate {
+                    num_verified_blocks: 3,
+                    state_commitment: [1; 32],
+                },
+                commitments_digest: None,
+                withdrawals: None,
+            }
+        );
+
+        // Rejected batches leave the contract unchanged.
+        let before = contract;
+        assert_eq!(
+            contract.verify_blocks(3, &proof(1, 2), ProofShape::Endpoints),
+            Err(MirrorError::NotYetSequenced {
+                num_verified_blocks: 3,
+                count: 3,
+                block_height: 5,
+            })
+        );
+        assert_eq!(
+            contract.verify_blocks(0, &proof(1, 2), ProofShape::Endpoints),
+            Err(MirrorError::NoBlocks)
+        );
+        let mut inconsistent = proof(1, 2);
+        inconsistent.commitments = vec![[7; 32]];
+        assert_eq!(
+            contract.verify_blocks(2, &inconsistent, ProofShape::Full),
+            Err(MirrorError::InvalidProof)
+        );
+        assert_eq!(contract, before);
+
+        // The commitments are only checked when they are submitted.
+        let verified = contract
+            .verify_blocks(2, &inconsistent, ProofShape::Compressed)
+            .unwrap();
+        assert_eq!(
+            verified.commitments_digest,
+            Some(inconsistent.commitments_digest())
+        );
+        assert_eq!(contract.num_verified_blocks, 5);
+        assert_eq!(contract.state_commitment, [2; 32]);
+
+        // A batch with withdrawals posts them, along with the commitments digest, in any shape.
+        let mut contract = ContractMirror::new([0; 32], 5);
+        let withdrawals = WithdrawalsRoot {
+            root: [9; 32],
+            count: 1,
+        };
+        let with_withdrawals = BatchProofInput {
+            withdrawals: Some(withdrawals),
+            ..proof(0, 1)
+        };
+        let verified = contract
+            .verify_blocks(1, &with_withdrawals, ProofShape::Endpoints)
+            .unwrap();
+        assert_eq!(verified.withdrawals, Some(withdrawals));
+        assert_eq!(
+            verified.commitments_digest,
+            Some(with_withdrawals.commitments_digest())
+        );
+    }
+}
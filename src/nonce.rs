@@ -0,0 +1,178 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Allocation of nonces for the executor's L1 transactions.
+//!
+//! The account which submits proofs may be shared with other tooling, such as deployment scripts or
+//! `ops submit-batch`, which send transactions of their own. Asking the L1 for the pending nonce
+//! right before each submission races with those transactions: two services can pick the same
+//! nonce, and only one of their transactions is ever mined. The [`NonceManager`] hands out each
+//! nonce at most once, tracks the executor's transactions until they are mined, and resyncs with the
+//! chain whenever the account's nonce has moved on without it. A nonce claimed by the executor but
+//! used by another service is detected with [`NonceManager::is_used`], so that the submission can be
+//! retried with a fresh nonce.
+//!
+//! Submissions are also throttled: while the account already has
+//! [`max_pending`](NonceManager::new) transactions waiting to be mined, whether sent by the executor
+//! or by anyone else, no new nonce is handed out, and the submission is deferred until some of them
+//! are mined.
+
+use crate::l1::{L1Client, L1Error};
+use async_std::sync::{Arc, Mutex};
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Default maximum number of pending transactions from the submitter account.
+pub const DEFAULT_MAX_PENDING: u64 = 4;
+
+/// A transaction broadcast by the executor which has not yet been mined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InFlight {
+    pub nonce: u64,
+    /// The most recent transaction broadcast with `nonce`.
+    pub tx_hash: H256,
+}
+
+/// The state of the [`NonceManager`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NonceStats {
+    /// The nonce which will be handed out next, or `None` if the manager has not yet synced with the
+    /// chain.
+    pub next_nonce: Option<u64>,
+    /// Transactions broadcast by the executor which have not yet been mined, in nonce order.
+    pub in_flight: Vec<InFlight>,
+    /// Number of times the account's nonce was found ahead of the manager's, because another
+    /// service sent transactions from the same account.
+    pub resyncs: u64,
+    /// Number of nonces claimed by the executor which were used by another transaction.
+    pub lost: u64,
+    /// Number of submissions deferred because the account had too many pending transactions.
+    pub deferred: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next: Option<u64>,
+    in_flight: BTreeMap<u64, H256>,
+    resyncs: u64,
+    lost: u64,
+    deferred: u64,
+}
+
+/// Hands out nonces for the executor's L1 transactions, one submission at a time.
+#[derive(Clone, Debug)]
+pub struct NonceManager {
+    max_pending: u64,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Default for NonceManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PENDING)
+    }
+}
+
+impl NonceManager {
+    /// A manager which defers submissions while the account has `max_pending` transactions waiting
+    /// to be mined.
+    pub fn new(max_pending: u64) -> Self {
+        Self {
+            max_pending: max_pending.max(1),
+            inner: Default::default(),
+        }
+    }
+
+    /// Claim the next nonce for a transaction.
+    ///
+    /// The nonce is never lower than the account's pending nonce on the L1, so nonces used by other
+    /// services are skipped, nor than any nonce already handed out. Returns `None` if the account
+    /// has too many pending transactions, in which case the caller should try again later.
+    pub async fn reserve(&self, l1: &dyn L1Client) -> Result<Option<u64>, L1Error> {
+        let mut inner = self.inner.lock().await;
+        let confirmed = l1.confirmed_nonce().await?;
+        let pending = l1.next_nonce().await?;
+        inner.in_flight.retain(|nonce, _| *nonce >= confirmed);
+
+        let waiting = pending.saturating_sub(confirmed);
+        if waiting >= self.max_pending {
+            tracing::warn!(
+                "L1 account has {waiting} pending transactions, deferring submission until some \
+                 are mined"
+            );
+            inner.deferred += 1;
+            return Ok(None);
+        }
+
+        let nonce = match inner.next {
+            Some(next) if next < pending => {
+                tracing::warn!(
+                    "L1 account nonce moved from {next} to {pending} outside the executor, \
+                     resyncing"
+                );
+                inner.resyncs += 1;
+                pending
+            }
+            Some(next) => next,
+            None => pending,
+        };
+        inner.next = Some(nonce + 1);
+        Ok(Some(nonce))
+    }
+
+    /// Record that a transaction `tx_hash` was broadcast with `nonce`.
+    pub async fn sent(&self, nonce: u64, tx_hash: H256) {
+        self.inner.lock().await.in_flight.insert(nonce, tx_hash);
+    }
+
+    /// Record that the transaction with `nonce` was mined, successfully or not.
+    pub async fn mined(&self, nonce: u64) {
+        self.inner.lock().await.in_flight.remove(&nonce);
+    }
+
+    /// Return `nonce`, which was reserved but never used, so that it is handed out again.
+    ///
+    /// Only the most recently reserved nonce can be returned, since handing out an earlier one
+    /// would reuse the nonces reserved after it.
+    pub async fn release(&self, nonce: u64) {
+        let mut inner = self.inner.lock().await;
+        if inner.next == Some(nonce + 1) {
+            inner.next = Some(nonce);
+        }
+    }
+
+    /// Whether a transaction with `nonce` has been mined, by anyone.
+    pub async fn is_used(&self, l1: &dyn L1Client, nonce: u64) -> Result<bool, L1Error> {
+        Ok(l1.confirmed_nonce().await? > nonce)
+    }
+
+    /// Record that `nonce`, claimed by the executor, was used by another transaction.
+    ///
+    /// The next nonce is resynced from the chain when it is next reserved.
+    pub async fn lost(&self, nonce: u64) {
+        let mut inner = self.inner.lock().await;
+        inner.in_flight.remove(&nonce);
+        inner.lost += 1;
+    }
+
+    pub async fn stats(&self) -> NonceStats {
+        let inner = self.inner.lock().await;
+        NonceStats {
+            next_nonce: inner.next,
+            in_flight: inner
+                .in_flight
+                .iter()
+                .map(|(nonce, tx_hash)| InFlight {
+                    nonce: *nonce,
+                    tx_hash: *tx_hash,
+                })
+                .collect(),
+            resyncs: inner.resyncs,
+            lost: inner.lost,
+            deferred: inner.deferred,
+        }
+    }
+}
@@ -0,0 +1,804 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Command line options of the rollup node.
+
+use crate::auth::{AdminAuth, Role};
+use crate::breaker::SafetyCheckKind;
+use crate::canary::CanaryVm;
+use crate::executor::{AggregationStrategy, ProofShape};
+use crate::http::HttpClientOptions;
+use crate::l1::{ClientPool, L1ClientKind, L1Error, DEFAULT_MAX_FAILURES};
+use crate::machine::StateModel;
+use crate::nonce::DEFAULT_MAX_PENDING;
+use crate::prover::VerificationMode;
+use crate::scheduler::DaTimeoutAction;
+use crate::secret::Secret;
+use crate::seed::INITIAL_BALANCE;
+use crate::signer::{L1SignerConfig, L1SignerKind};
+use crate::state::{OrderingPolicy, ReplayProtection, UntrustedSubmissions, MAX_FEE_BURN_BPS};
+use crate::RollupVM;
+use clap::{Args, Parser, Subcommand};
+use espresso_types::NamespaceId;
+use ethers::types::Address;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use surf_disco::Url;
+
+#[derive(Parser, Clone, Debug, Serialize)]
+pub struct Options {
+    /// Port where the Rollup API will be served
+    #[clap(short, long, env = "ESPRESSO_DEMO_ROLLUP_PORT", default_value = "8084")]
+    pub api_port: u16,
+
+    /// Local addresses on which the Rollup API listens.
+    ///
+    /// Several addresses may be given, for example `127.0.0.1,::1` to listen on the IPv4 and IPv6
+    /// loopback interfaces. On most systems `::` alone accepts both IPv4 and IPv6 connections, and
+    /// cannot be combined with `0.0.0.0` on the same port.
+    #[clap(
+        long = "api-bind-address",
+        env = "ESPRESSO_DEMO_ROLLUP_BIND_ADDRESS",
+        value_delimiter = ',',
+        default_value = "0.0.0.0"
+    )]
+    pub api_bind_addresses: Vec<IpAddr>,
+
+    /// Public URL at which clients reach the Rollup API.
+    ///
+    /// Set this when the API is served behind a load balancer or proxy, so that links reported to
+    /// clients (for example by `rollup/info`) use the public address rather than the bind address.
+    #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_ADVERTISE_URL")]
+    pub api_advertise_url: Option<Url>,
+
+    /// Date after which the deprecated `rollup/v0` API may be removed, as an HTTP date such as
+    /// `Thu, 31 Dec 2026 23:59:59 GMT`.
+    ///
+    /// If set, responses from `rollup` and `rollup/v0` carry it in a `Sunset` header.
+    #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_V0_SUNSET")]
+    pub api_v0_sunset: Option<String>,
+
+    /// Port where the optional gRPC interface will be served.
+    ///
+    /// Requires the `grpc` feature. If not provided, the gRPC interface is disabled.
+    #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_GRPC_PORT")]
+    pub grpc_port: Option<u16>,
+
+    /// Origins allowed to make cross-origin requests to the Rollup API.
+    ///
+    /// If empty, requests from any origin are allowed.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_CORS_ALLOWED_ORIGINS",
+        value_delimiter = ','
+    )]
+    pub cors_allowed_origins: Vec<String>,
+
+    /// Aliases which may be used in place of addresses in API requests, as `NAME=ADDRESS`.
+    ///
+    /// The seed identities are always available as `bob.rollup`, `alice.rollup` and
+    /// `charlie.rollup`.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_ADDRESS_ALIASES",
+        value_delimiter = ',',
+        value_parser = crate::address::parse_alias
+    )]
+    pub address_aliases: Vec<(String, Address)>,
+
+    /// URL of a HotShot sequencer node.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_URL",
+        default_value = "http://0.0.0.0:24000/v0/"
+    )]
+    #[serde(serialize_with = "crate::secret::serialize_url")]
+    pub sequencer_url: Url,
+
+    /// Timeout, in seconds, for each request to the HotShot query service.
+    #[clap(long, env = "ESPRESSO_DEMO_HTTP_REQUEST_TIMEOUT", default_value = "30")]
+    pub http_request_timeout: u64,
+
+    /// Maximum number of requests in flight to any one query service host.
+    ///
+    /// All query service calls share one connection pool per host, and requests beyond this limit
+    /// wait for an earlier request to finish.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_HTTP_MAX_REQUESTS_PER_HOST",
+        default_value = "16"
+    )]
+    pub http_max_requests_per_host: usize,
+
+    /// URL of layer 1 Ethereum JSON-RPC provider.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_L1_HTTP_PROVIDER",
+        default_value = "http://localhost:8545"
+    )]
+    #[serde(serialize_with = "crate::secret::serialize_url")]
+    pub l1_http_provider: Url,
+
+    /// Further L1 JSON-RPC providers, used in turn when the current provider is unreachable.
+    #[clap(long, env = "ESPRESSO_DEMO_L1_HTTP_FALLBACKS", value_delimiter = ',')]
+    #[serde(serialize_with = "crate::secret::serialize_urls")]
+    pub l1_http_fallbacks: Vec<Url>,
+
+    /// Number of consecutive failed requests after which the node switches to the next L1 HTTP
+    /// provider.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_L1_MAX_FAILURES",
+        default_value_t = DEFAULT_MAX_FAILURES
+    )]
+    pub l1_max_failures: u32,
+
+    /// Interval in milliseconds at which L1 providers poll for new blocks and transactions.
+    #[clap(long, env = "ESPRESSO_DEMO_L1_POLLING_INTERVAL", default_value = "10")]
+    pub l1_polling_interval: u64,
+
+    /// URL of layer 1 Ethereum JSON-RPC provider.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_L1_WS_PROVIDER",
+        default_value = "ws://localhost:8546"
+    )]
+    #[serde(serialize_with = "crate::secret::serialize_url")]
+    pub l1_ws_provider: Url,
+
+    /// Address of HotShot contract on layer 1.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_LIGHT_CLIENT_ADDRESS",
+        default_value = "0xed1db453c3156ff3155a97ad217b3087d5dc5f6e"
+    )]
+    pub light_client_address: Address,
+
+    /// Address of the deposit escrow contract on layer 1, deployed by the rollup contract and
+    /// returned by its `escrow` function.
+    ///
+    /// If set, `Deposited` events emitted by the escrow are followed over `l1_ws_provider`, and
+    /// each deposit is credited to its account on the rollup in the first block whose HotShot
+    /// header finalizes the L1 block containing it. If not provided, deposits are disabled.
+    #[clap(long, env = "ESPRESSO_DEMO_ESCROW_ADDRESS")]
+    pub escrow_address: Option<Address>,
+
+    /// Namespace of the sequencer in which this rollup's transactions are sequenced.
+    ///
+    /// Rollups sharing a sequencer must each use a different namespace.
+    #[clap(long, env = "ESPRESSO_DEMO_NAMESPACE", default_value = "1")]
+    pub namespace: u64,
+
+    /// Mnemonic phrase for the rollup wallet.
+    ///
+    /// This is the wallet that will be used to send batch proofs of transaction validity to the rollup
+    /// contract. It must be funded with ETH on the layer 1.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_ROLLUP_MNEMONIC",
+        default_value = "test test test test test test test test test test test junk"
+    )]
+    pub rollup_mnemonic: Secret<String>,
+
+    /// Index of a funded account derived from mnemonic, desginating the account
+    /// that will send proofs to the rollup contract
+    #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_ACCOUNT_INDEX", default_value = "1")]
+    pub rollup_account_index: u32,
+
+    /// Where the key used to sign proof submissions is held.
+    ///
+    /// With `mnemonic`, proofs are signed by the account at `rollup_account_index` derived from
+    /// `rollup_mnemonic`. With `aws-kms`, they are signed by the KMS key `aws_kms_key_id`, using
+    /// the AWS region and credentials from the environment. The rollup contract is still deployed
+    /// from the mnemonic account.
+    #[clap(long, env = "ESPRESSO_DEMO_L1_SIGNER", value_enum, default_value_t)]
+    pub l1_signer: L1SignerKind,
+
+    /// ID of the AWS KMS key used to sign proof submissions when `l1_signer` is `aws-kms`.
+    #[cfg(feature = "aws-kms")]
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_AWS_KMS_KEY_ID",
+        required_if_eq("l1_signer", "aws-kms")
+    )]
+    pub aws_kms_key_id: Option<String>,
+
+    /// Strategy used to aggregate block proofs before submitting them to the rollup contract.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_AGGREGATION_STRATEGY",
+        value_enum,
+        default_value_t = AggregationStrategy::PerEvent
+    )]
+    pub aggregation_strategy: AggregationStrategy,
+
+    /// Maximum number of blocks covered by a single batch proof with the `merged` aggregation
+    /// strategy.
+    #[clap(long, env = "ESPRESSO_DEMO_MAX_BATCH_SIZE", default_value = "10")]
+    pub max_batch_size: u64,
+
+    /// URL of an external service which aggregates block proofs.
+    ///
+    /// If set, each block proof is posted to the aggregator's `proofs` endpoint instead of being
+    /// aggregated according to `aggregation_strategy`, and the aggregator returns batch proofs for
+    /// submission through the `rollup/aggregator/batch` route.
+    #[clap(long, env = "ESPRESSO_DEMO_AGGREGATOR_URL")]
+    pub aggregator_url: Option<Url>,
+
+    /// Number of recent finality lag samples served by the `stats/finality-lag` endpoint.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_FINALITY_LAG_WINDOW",
+        default_value = "1000"
+    )]
+    pub finality_lag_window: usize,
+
+    /// Number of recent transactions over which the `stats/latency` endpoint summarizes latency.
+    #[clap(long, env = "ESPRESSO_DEMO_LATENCY_WINDOW", default_value = "1000")]
+    pub latency_window: usize,
+
+    /// Number of recent executed blocks whose accounts and events are retained for the `diff` and
+    /// `block/:height/events` endpoints.
+    #[clap(long, env = "ESPRESSO_DEMO_HISTORY_WINDOW", default_value = "1000")]
+    pub history_window: usize,
+
+    /// SQLite database in which to index executed transactions for the `transactions` endpoint.
+    ///
+    /// Requires the `sqlite` feature. If not provided, the transactions of the last
+    /// `history_window` blocks are indexed in memory.
+    #[clap(long, env = "ESPRESSO_DEMO_HISTORY_DB")]
+    pub history_db: Option<PathBuf>,
+
+    /// Optional CSV file to which every finality lag sample is appended.
+    #[clap(long, env = "ESPRESSO_DEMO_FINALITY_LAG_CSV")]
+    pub finality_lag_csv: Option<PathBuf>,
+
+    /// Execute blocks without updating the served state or submitting proofs to the L1.
+    ///
+    /// The state commitment after each block is logged instead, so that a shadow deployment can
+    /// be compared against a production node. The rollup contract is never called, so this cannot
+    /// be combined with `--recover-from-l1`.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_DRY_RUN",
+        conflicts_with = "recover_from_l1"
+    )]
+    pub dry_run: bool,
+
+    /// Execute every block twice, the second time with an independent reference implementation
+    /// of the state transition, and halt if the results differ.
+    ///
+    /// This is a debugging aid for catching non-determinism before it produces a bad state
+    /// commitment on the L1. It roughly doubles the cost of execution.
+    #[clap(long, env = "ESPRESSO_DEMO_EXECUTION_SELF_CHECK")]
+    pub execution_self_check: bool,
+
+    /// Run a VM version as a canary alongside the primary state.
+    ///
+    /// The canary executes every block on a copy of the state, and the first block at which its
+    /// state commitment or transaction results differ from the primary state's is logged and
+    /// served by `rollup/stats/canary`. The canary never affects the primary state or the proofs.
+    #[clap(long, env = "ESPRESSO_DEMO_CANARY_VM", value_enum)]
+    pub canary_vm: Option<CanaryVm>,
+
+    /// Require every submission to carry a token registered to its sender.
+    ///
+    /// Users register an address at `rollup/submitters` and pass the token they receive in an
+    /// `Authorization: Bearer` header. Transactions from any other sender are rejected by the API,
+    /// which stops users of a hosted node from submitting in each other's names.
+    #[clap(long, env = "ESPRESSO_DEMO_REQUIRE_SUBMITTER_TOKENS")]
+    pub require_submitter_tokens: bool,
+
+    /// Wall-clock budget for executing a single block, in milliseconds.
+    ///
+    /// Blocks which take longer are logged and recorded as incidents, served by the
+    /// `rollup/incidents` endpoint, but are still executed in full so that execution stays
+    /// deterministic. If not set, execution time is not checked.
+    #[clap(long, env = "ESPRESSO_DEMO_BLOCK_EXECUTION_BUDGET_MS")]
+    pub block_execution_budget_ms: Option<u64>,
+
+    /// Seconds for which namespace data listed in a block's namespace table is retried when the
+    /// query service cannot serve it.
+    #[clap(long, env = "ESPRESSO_DEMO_DA_TIMEOUT", default_value = "60")]
+    pub da_timeout: u64,
+
+    /// What to do with a block whose namespace data is still unavailable after `da_timeout`.
+    ///
+    /// `skip` records an incident, served by the `rollup/incidents/data-unavailability` endpoint,
+    /// and continues as if the block had no rollup transactions. Any node which does execute the
+    /// block computes a different state, so `skip` is only suitable for nodes which do not submit
+    /// proofs.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_DA_TIMEOUT_ACTION",
+        value_enum,
+        default_value_t = DaTimeoutAction::Halt
+    )]
+    pub da_timeout_action: DaTimeoutAction,
+
+    /// Number of historical headers fetched by each range query while catching up with the
+    /// sequencer. The live header stream is only used once the node has caught up. Progress is
+    /// logged before each page and served from `stats/catch-up`.
+    #[clap(long, env = "ESPRESSO_DEMO_HEADER_PAGE_SIZE", default_value = "100")]
+    pub header_page_size: u64,
+
+    /// Verify each HotShot header against the light client contract before executing it.
+    ///
+    /// The header must be covered by the light client's finalized state, with a Merkle proof
+    /// fetched from the query service, so that a faulty query service cannot feed the executor
+    /// blocks which HotShot did not finalize.
+    #[clap(long, env = "ESPRESSO_DEMO_VERIFY_HEADERS")]
+    pub verify_headers: bool,
+
+    /// Safety checks run after every block. If any fails, proof submission halts until the node is
+    /// restarted.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_CIRCUIT_BREAKER_CHECKS",
+        value_enum,
+        value_delimiter = ','
+    )]
+    pub circuit_breaker_checks: Vec<SafetyCheckKind>,
+
+    /// Maximum number of blocks execution may fall behind the light client with the
+    /// `execution-lag` circuit breaker check.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_CIRCUIT_BREAKER_MAX_LAG",
+        default_value = "100"
+    )]
+    pub circuit_breaker_max_lag: u64,
+
+    /// Number of recent events retained so that disconnected event stream subscribers can resume.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_EVENT_REPLAY_WINDOW",
+        default_value = "10000"
+    )]
+    pub event_replay_window: usize,
+
+    /// How the rollup VM protects against replayed transactions.
+    ///
+    /// With `recent-hashes`, sequencer ordering is the sole authority on transaction order and the
+    /// transaction nonce is only a salt, so clients can submit concurrently without tracking nonces.
+    /// Transactions must then expire within the replay window.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_REPLAY_PROTECTION",
+        value_enum,
+        default_value_t = ReplayProtection::Nonce
+    )]
+    pub replay_protection: ReplayProtection,
+
+    /// Number of HotShot blocks for which executed transactions are remembered when using
+    /// `recent-hashes` replay protection.
+    #[clap(long, env = "ESPRESSO_DEMO_REPLAY_WINDOW", default_value = "100")]
+    pub replay_window: u64,
+
+    /// State model of the rollup, chosen at genesis.
+    ///
+    /// The node only runs the `account` model: proving, the rollup contract and the API are built
+    /// around account balances and nonces. A `utxo` rollup can be followed with the auditor, which
+    /// reads this variable too, so the node refuses to start with any other model.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_STATE_MODEL",
+        value_enum,
+        default_value_t = StateModel::Account
+    )]
+    pub state_model: StateModel,
+
+    /// Order in which the rollup VM executes the transactions in each block.
+    ///
+    /// With `sender-nonce`, the execution order depends only on which transactions were sequenced,
+    /// not on their order in the namespace. Every node of the rollup must use the same policy, since
+    /// it is part of the genesis state.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_ORDERING_POLICY",
+        value_enum,
+        default_value_t = OrderingPolicy::Namespace
+    )]
+    pub ordering_policy: OrderingPolicy,
+
+    /// Operator whose countersignature marks a transaction as submitted through the rollup API.
+    ///
+    /// If set, transactions without the operator's countersignature, such as data posted directly
+    /// to the rollup namespace, are treated as untrusted and handled according to
+    /// `untrusted_submissions`. If this node's rollup account is the operator, the API countersigns
+    /// every transaction it submits. Every node of the rollup must be configured with the same
+    /// operator, since it is part of the genesis state.
+    #[clap(long, env = "ESPRESSO_DEMO_SUBMISSION_OPERATOR")]
+    pub submission_operator: Option<Address>,
+
+    /// How transactions without the operator's countersignature are handled, if a
+    /// `submission_operator` is configured.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_UNTRUSTED_SUBMISSIONS",
+        value_enum,
+        default_value_t = UntrustedSubmissions::Strict
+    )]
+    pub untrusted_submissions: UntrustedSubmissions,
+
+    /// Share of each transaction fee which is burned instead of paid to the submission operator,
+    /// in basis points.
+    ///
+    /// Fees are only charged if a `submission_operator` is configured. Every node of the rollup
+    /// must be configured with the same fraction, since it is part of the genesis state.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_FEE_BURN_BPS",
+        default_value = "0",
+        value_parser = clap::value_parser!(u16).range(0..=MAX_FEE_BURN_BPS as i64)
+    )]
+    pub fee_burn_bps: u16,
+
+    /// Rollup APIs of peer nodes to cross-check state checkpoints with, as `ADDRESS@URL`.
+    ///
+    /// Each node signs its state commitment after every block with its rollup account key, whose
+    /// address is given as `ADDRESS`. If peers are given, their checkpoints at the latest block
+    /// both nodes have executed are fetched periodically and an error is logged if any peer
+    /// computed a different commitment for the same block. Checkpoints signed by any other key are
+    /// ignored.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_GOSSIP_PEERS",
+        value_delimiter = ',',
+        value_parser = crate::gossip::parse_peer
+    )]
+    pub gossip_peers: Vec<(Address, Url)>,
+
+    /// Interval, in seconds, between rounds of fetching checkpoints from gossip peers.
+    #[clap(long, env = "ESPRESSO_DEMO_GOSSIP_INTERVAL", default_value = "10")]
+    pub gossip_interval: u64,
+
+    /// Rollup API of the primary replica, to which transactions submitted to this node are
+    /// forwarded.
+    ///
+    /// Use this on API replicas which do not hold the submission operator's key, so that every
+    /// transaction is countersigned by the primary whichever replica receives it. If the primary
+    /// cannot be reached, transactions are submitted straight to the sequencer instead.
+    #[clap(long, env = "ESPRESSO_DEMO_RELAY_PRIMARY")]
+    pub relay_primary: Option<Url>,
+
+    /// Rollup APIs of other replicas, asked for the receipts of transactions this node has not
+    /// yet executed.
+    ///
+    /// The primary, if any, is always asked first.
+    #[clap(long, env = "ESPRESSO_DEMO_RELAY_PEERS", value_delimiter = ',')]
+    pub relay_peers: Vec<Url>,
+
+    /// Number of transactions with future nonces held until they can execute.
+    ///
+    /// Submissions whose nonce is ahead of the sender's are held in a mempool, and submitted to the
+    /// sequencer once the transactions before them execute. When the mempool is full, held
+    /// transactions with the lowest fee are evicted for ones paying more. With 0, they are
+    /// submitted immediately, and fail unless the gap is filled in the same block.
+    #[clap(long, env = "ESPRESSO_DEMO_MEMPOOL_CAPACITY", default_value = "1024")]
+    pub mempool_capacity: usize,
+
+    /// Number of transactions with future nonces held for any one sender.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_MEMPOOL_MAX_PER_SENDER",
+        default_value = "16"
+    )]
+    pub mempool_max_per_sender: usize,
+
+    /// Interval, in seconds, between spot audits of executed blocks.
+    ///
+    /// Each audit refetches the namespace proof and VID common data of a random executed block,
+    /// verifies them against the block's header and checks that the node executed the transactions
+    /// they prove. Mismatches are logged as errors. If not provided, no spot audits are run.
+    #[clap(long, env = "ESPRESSO_DEMO_SPOT_AUDIT_INTERVAL")]
+    pub spot_audit_interval: Option<u64>,
+
+    /// Ethereum client library used to submit proofs to the L1.
+    ///
+    /// `alloy` is only available when built with the `alloy` feature.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_L1_CLIENT",
+        value_enum,
+        default_value_t = L1ClientKind::Ethers
+    )]
+    pub l1_client: L1ClientKind,
+
+    /// Shape in which batch proofs are submitted to the rollup contract.
+    ///
+    /// `endpoints` submits only the first and last state of each batch, `full` also submits the
+    /// state commitment after every block, and `compressed` submits a hash chain digest of those
+    /// commitments, which are served off-chain at `rollup/block/:height/commitment`.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_PROOF_SHAPE",
+        value_enum,
+        default_value_t = ProofShape::Endpoints
+    )]
+    pub proof_shape: ProofShape,
+
+    /// Which blocks have their namespace proof verified before they are proven.
+    ///
+    /// `always` verifies every block, `sampled` verifies one in every `proof_sample_interval`
+    /// blocks, and `skip-in-dev` skips verification in debug builds only. Verification timings
+    /// are served by `rollup/stats/proof-verification`.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_PROOF_VERIFICATION",
+        value_enum,
+        default_value_t = VerificationMode::Always
+    )]
+    pub proof_verification: VerificationMode,
+
+    /// Interval between the heights of blocks whose namespace proof is verified in `sampled` mode.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_PROOF_SAMPLE_INTERVAL",
+        default_value = "10"
+    )]
+    pub proof_sample_interval: u64,
+
+    /// Number of seed accounts funded at genesis.
+    ///
+    /// Accounts are derived deterministically, and always include the named identities (Bob, Alice
+    /// and Charlie). Additional accounts are registered under the aliases `seed<N>.rollup`.
+    #[clap(long, env = "ESPRESSO_DEMO_SEED_ACCOUNTS", default_value = "3")]
+    pub seed_accounts: u64,
+
+    /// Initial balance of each seed account.
+    #[clap(long, env = "ESPRESSO_DEMO_SEED_BALANCE", default_value_t = INITIAL_BALANCE)]
+    pub seed_balance: u64,
+
+    /// Enable the development-only `rollup/sign-and-submit` route, which signs transactions with
+    /// the (publicly known) keys of the seed identities.
+    #[clap(long, env = "ESPRESSO_DEMO_DEV_SIGNING")]
+    pub dev_signing: bool,
+
+    /// Start with submission of batch proofs paused.
+    ///
+    /// Blocks are still executed and served, and batch proofs are buffered in the outbox until
+    /// submission is resumed through the API.
+    #[clap(long, env = "ESPRESSO_DEMO_PAUSE_SUBMISSION")]
+    pub pause_submission: bool,
+
+    /// Enable the `rollup/pending-batches/pause` and `rollup/pending-batches/resume` routes.
+    ///
+    /// These are administrative routes, so they are disabled unless admin API keys or JWT issuers
+    /// are configured.
+    #[clap(long, env = "ESPRESSO_DEMO_SUBMISSION_CONTROL")]
+    pub submission_control: bool,
+
+    /// Static API keys granting access to administrative API routes, as `ROLE:KEY`.
+    ///
+    /// Administrative routes (pausing and resuming proof submission, snapshot exports, the effective
+    /// configuration and aggregated batch proofs) require an `Authorization: Bearer` header carrying
+    /// a key or token which grants the route's role. Roles are `operator` and `admin`. If no API
+    /// keys or JWT issuers are configured, these routes reject every request.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_ADMIN_API_KEYS",
+        value_delimiter = ',',
+        value_parser = crate::auth::parse_api_key
+    )]
+    pub admin_api_keys: Vec<(Role, Secret<String>)>,
+
+    /// Addresses trusted to issue JSON Web Tokens for administrative API routes.
+    ///
+    /// Tokens carry the holder's role in a `role` claim and are signed with the issuer's Ethereum
+    /// key, so the node needs no shared secret.
+    #[clap(long, env = "ESPRESSO_DEMO_ADMIN_JWT_ISSUERS", value_delimiter = ',')]
+    pub admin_jwt_issuers: Vec<Address>,
+
+    /// Seed for all randomness used by the node.
+    ///
+    /// If not given, a random seed is chosen. Either way, the seed is logged at startup, and
+    /// restarting with the same seed reproduces the same random choices.
+    #[clap(long, env = "ESPRESSO_DEMO_SEED")]
+    pub seed: Option<u64>,
+
+    /// JSON file recording the deployment of the rollup contract.
+    ///
+    /// If the file exists, the contract it records is reused instead of deploying a new one, after
+    /// checking that it was deployed on the same chain with the same light client and genesis state.
+    /// Otherwise a new contract is deployed and its record is written to the file.
+    #[clap(long, env = "ESPRESSO_DEMO_DEPLOYMENT_FILE")]
+    pub deployment_file: Option<PathBuf>,
+
+    /// JSON file recording batch proofs awaiting submission to the rollup contract.
+    ///
+    /// Each batch is recorded before it is submitted and tracked until it is confirmed, so that a
+    /// node which restarts resumes pending submissions instead of submitting batches again. If not
+    /// set, pending submissions are only kept in memory.
+    #[clap(long, env = "ESPRESSO_DEMO_OUTBOX_FILE")]
+    pub outbox_file: Option<PathBuf>,
+
+    /// File holding a warm-start bundle, from which the node resumes instead of executing every
+    /// block since genesis.
+    ///
+    /// If the file exists, the state, recent receipts, pending proofs and next block to execute are
+    /// restored from it at startup. On SIGINT or SIGTERM, the node finishes the batch it is
+    /// executing and writes a new bundle to the file before exiting. Requires `--outbox-file`, so
+    /// that batches handed to the outbox before shutdown are still submitted.
+    #[clap(long, env = "ESPRESSO_DEMO_WARM_START", requires = "outbox_file")]
+    pub warm_start: Option<PathBuf>,
+
+    /// File in which the state is checkpointed after each executed block.
+    ///
+    /// If the file exists, the node resumes from the checkpoint at startup instead of executing
+    /// every block since genesis, even if it was not shut down cleanly. Requires `--outbox-file`,
+    /// so that batches handed to the outbox before the node stopped are still submitted.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_STATE_STORAGE",
+        requires = "outbox_file",
+        conflicts_with = "warm_start"
+    )]
+    pub state_storage: Option<PathBuf>,
+
+    /// Before following new blocks, replay the blocks already verified by the rollup contract.
+    ///
+    /// Blocks the node has not executed, up to the contract's `numVerifiedBlocks`, are fetched from
+    /// the query service and executed without being proven again, and the node refuses to start if
+    /// its state commitment then differs from the contract's latest `StateUpdate`.
+    #[clap(long, env = "ESPRESSO_DEMO_RECOVER_FROM_L1")]
+    pub recover_from_l1: bool,
+
+    /// Maximum number of pending L1 transactions from the submitter account.
+    ///
+    /// The account may be shared with other tooling. While it has this many transactions waiting to
+    /// be mined, whoever sent them, batch proofs are not submitted.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_L1_MAX_PENDING_TXS",
+        default_value_t = DEFAULT_MAX_PENDING
+    )]
+    pub l1_max_pending_txs: u64,
+
+    /// Directory to which account state snapshots are exported by the `rollup/snapshot` endpoint.
+    ///
+    /// If not set, snapshot exports are disabled.
+    #[clap(long, env = "ESPRESSO_DEMO_SNAPSHOT_DIR")]
+    pub snapshot_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub command: Option<NodeCommand>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum NodeCommand {
+    /// Check the node's configuration and environment, print diagnostics and exit without starting
+    /// the node.
+    ///
+    /// Checks connectivity to the query service and both L1 providers, that the light client and
+    /// rollup contracts are deployed with the expected interfaces, that the rollup wallet is funded
+    /// and that the API ports are free. Exits with a non-zero status if any check fails.
+    Doctor,
+    /// Write TypeScript definitions and a JSON schema of the API payload types to `out_dir`, as
+    /// `rollup.d.ts` and `rollup.schema.json`, and exit.
+    ///
+    /// The same descriptions are served by the `rollup/schema` endpoint.
+    Schema {
+        #[clap(long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+    /// Run a local devnet.
+    Demo {
+        #[command(subcommand)]
+        command: DemoCommand,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum DemoCommand {
+    /// Launch Anvil, a sequencer and this rollup as child processes, wait until each is ready and
+    /// print their URLs.
+    ///
+    /// The services are configured from this node's options, so the L1 listens on the port of
+    /// `l1_http_provider`, the sequencer serves `sequencer_url` and the rollup serves its API on
+    /// `api_port`. Every service is stopped when this command exits, on SIGINT or SIGTERM, or as
+    /// soon as any of them exits.
+    Up(DemoUpOptions),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct DemoUpOptions {
+    /// Anvil executable, launched as the L1.
+    #[clap(long, env = "ESPRESSO_DEMO_ANVIL_BIN", default_value = "anvil")]
+    pub anvil_bin: String,
+
+    /// Use the L1 at `l1_http_provider` rather than launching Anvil.
+    #[clap(long)]
+    pub no_anvil: bool,
+
+    /// Sequencer executable, launched as a single-node sequencer network. It is configured with
+    /// the `ESPRESSO_SEQUENCER_*` environment of the Espresso dev node.
+    #[clap(
+        long,
+        env = "ESPRESSO_DEMO_SEQUENCER_BIN",
+        default_value = "espresso-dev-node"
+    )]
+    pub sequencer_bin: String,
+
+    /// Seconds to wait for each service to become ready.
+    #[clap(long, env = "ESPRESSO_DEMO_READY_TIMEOUT", default_value = "120")]
+    pub ready_timeout: u64,
+}
+
+impl Options {
+    /// The VM executing the transactions in the configured namespace.
+    pub fn vm(&self) -> RollupVM {
+        RollupVM::new(NamespaceId::from(self.namespace))
+    }
+
+    /// The effective configuration, with secrets redacted, for logging and `rollup/config`.
+    pub fn effective_config(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("Serialization should not fail")
+    }
+
+    /// Authentication of administrative API routes, which are disabled if no credentials are
+    /// configured.
+    pub fn admin_auth(&self) -> AdminAuth {
+        let auth = self
+            .admin_api_keys
+            .iter()
+            .fold(AdminAuth::new(), |auth, (role, key)| {
+                auth.with_api_key(key.expose(), *role)
+            });
+        self.admin_jwt_issuers
+            .iter()
+            .fold(auth, |auth, issuer| auth.with_jwt_issuer(*issuer))
+    }
+
+    /// The configured settings for query service clients.
+    pub fn http_client_options(&self) -> HttpClientOptions {
+        HttpClientOptions {
+            request_timeout: Duration::from_secs(self.http_request_timeout),
+            max_requests_per_host: self.http_max_requests_per_host,
+        }
+    }
+
+    /// The L1 HTTP providers shared by the node.
+    pub fn l1_client_pool(&self) -> Result<ClientPool, L1Error> {
+        Ok(
+            ClientPool::new(&self.l1_http_provider, self.l1_http_fallbacks.clone())?
+                .with_polling_interval(Duration::from_millis(self.l1_polling_interval))
+                .with_max_failures(self.l1_max_failures),
+        )
+    }
+
+    /// The configured key for signing proof submissions.
+    pub fn l1_signer_config(&self) -> L1SignerConfig {
+        match self.l1_signer {
+            L1SignerKind::Mnemonic => L1SignerConfig::Mnemonic {
+                mnemonic: self.rollup_mnemonic.clone(),
+                account_index: self.rollup_account_index,
+            },
+            #[cfg(feature = "aws-kms")]
+            L1SignerKind::AwsKms => L1SignerConfig::AwsKms {
+                // Required by the argument parser when `l1_signer` is `aws-kms`.
+                key_id: self.aws_kms_key_id.clone().unwrap(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace() {
+        let opt = Options::parse_from(["example-l2"]);
+        assert_eq!(NamespaceId::from(opt.vm()), NamespaceId::from(1_u64));
+
+        let opt = Options::parse_from(["example-l2", "--namespace", "7"]);
+        assert_eq!(NamespaceId::from(opt.vm()), NamespaceId::from(7_u64));
+    }
+}
@@ -0,0 +1,1076 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Durable queue of batch proofs awaiting submission to the rollup contract.
+//!
+//! Each batch is recorded in the outbox before it is submitted, and its progress is written back
+//! after every step: when a nonce is claimed for it, when a transaction is broadcast, and when the
+//! transaction is confirmed. Because each submission of a batch reuses the nonce claimed for it, at
+//! most one transaction per batch can ever be included on the L1, and a node which restarts
+//! resumes from the recorded progress instead of submitting the batch again. Batches are dropped
+//! from the outbox once they are final and more than [`RETAINED_FINAL_BATCHES`] batches behind the
+//! most recently verified one.
+//!
+//! Submission can be paused, for example while the L1 RPC is down or gas prices are high. The
+//! executor keeps executing blocks and recording their batches in the outbox, and once submission
+//! is resumed the buffered batches are submitted in order.
+//!
+//! Before each batch is sent, the state it was proven to start from is compared with the state
+//! commitment stored by the rollup contract, since the contract would reject it anyway. A mismatch
+//! means the outbox and the contract disagree about which batches have been verified, and the outbox
+//! is reconciled with the contract instead of wasting gas on a doomed transaction: if the contract is
+//! ahead, the batches it has already verified are skipped; if it is behind, for example after an L1
+//! reorg, confirmed batches are submitted again; and if its state matches no batch at all, submission
+//! is paused for an operator to investigate. The results of these checks are reported by
+//! [`Outbox::state_checks`].
+//!
+//! Nonces are claimed from a [`NonceManager`], which throttles submissions while the account has
+//! many pending transactions. If the nonce claimed for a batch is used by another transaction from
+//! the same account, none of the batch's transactions can be mined, so the batch is returned to
+//! [`SubmissionStatus::Pending`] and submitted again with a fresh nonce. Should the other
+//! transaction have been an earlier submission of the same batch, the state check finds the batch
+//! already verified.
+//!
+//! Submitting batches requires the `executor` feature. Without it, the outbox is only a record of
+//! the batches, as served by the rollup API.
+
+use crate::l1::BatchProofInput;
+use crate::metrics::NodeMetrics;
+use crate::nonce::{NonceManager, NonceStats};
+use crate::prover::ProofShape;
+use async_std::sync::{Arc, Mutex};
+use async_std::task::spawn_blocking;
+use ethers::types::{H256, U256};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "executor")]
+use {
+    crate::{
+        clock::Clock,
+        l1::{L1Client, L1Error},
+        mirror::ContractMirror,
+    },
+    std::time::Duration,
+};
+
+/// Number of verified or reverted batches kept below the most recently verified one, so that the
+/// outbox can still be reconciled with the rollup contract if an L1 reorg undoes its latest
+/// verifications.
+pub const RETAINED_FINAL_BATCHES: usize = 8;
+
+/// Delay between checks for the confirmation of a submitted batch.
+#[cfg(feature = "executor")]
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of confirmation checks per call to [`Outbox::submit_pending`].
+#[cfg(feature = "executor")]
+const MAX_POLLS: usize = 30;
+
+#[derive(Debug, Snafu)]
+pub enum OutboxError {
+    #[snafu(display("Error accessing outbox file {}: {source}", path.display()))]
+    Io { path: PathBuf, source: io::Error },
+    #[snafu(display("Malformed outbox file {}: {source}", path.display()))]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+/// Progress of a batch through submission.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubmissionStatus {
+    /// The batch has not been submitted.
+    Pending,
+    /// A nonce has been reserved for the batch, but no transaction is known to have been sent.
+    Claimed { nonce: u64 },
+    /// The batch has been broadcast with `nonce`, possibly more than once. At most one of
+    /// `tx_hashes` can be included.
+    Submitted { nonce: u64, tx_hashes: Vec<H256> },
+    /// The batch was verified by the rollup contract in `tx_hash`.
+    Confirmed { tx_hash: H256 },
+    /// The transaction submitting the batch was included but reverted. The batch is not retried.
+    Reverted { tx_hash: H256 },
+    /// The rollup contract verified the batch, but not in a transaction sent from this outbox. For
+    /// example, the batch was submitted with `ops submit-batch`, or the transaction submitting it
+    /// was included after the outbox lost track of it.
+    VerifiedExternally,
+}
+
+impl SubmissionStatus {
+    fn is_final(&self) -> bool {
+        matches!(
+            self,
+            Self::Confirmed { .. } | Self::Reverted { .. } | Self::VerifiedExternally
+        )
+    }
+
+    /// Whether the batch has been verified by the rollup contract.
+    pub fn is_verified(&self) -> bool {
+        matches!(self, Self::Confirmed { .. } | Self::VerifiedExternally)
+    }
+}
+
+/// A batch proof recorded in the outbox.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub first_block: u64,
+    pub last_block: u64,
+    /// Number of blocks verified by the batch.
+    pub count: u64,
+    pub proof: BatchProofInput,
+    pub shape: ProofShape,
+    pub status: SubmissionStatus,
+    /// UNIX timestamp, in seconds, at which the batch was recorded.
+    #[serde(default)]
+    pub enqueued_at: u64,
+    /// Number of times a transaction submitting the batch has been sent.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// A batch which has been proven locally but not yet confirmed on the L1.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingBatch {
+    pub first_block: u64,
+    pub last_block: u64,
+    pub status: SubmissionStatus,
+    /// Seconds since the batch was recorded.
+    pub age: u64,
+    /// Number of times a transaction submitting the batch has been sent.
+    pub attempts: u32,
+}
+
+/// A comparison of the state a batch was proven to start from with the state stored by the rollup
+/// contract, made before submitting the batch.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateCheck {
+    pub first_block: u64,
+    pub last_block: u64,
+    /// The `old_state` of the batch proof.
+    pub expected: H256,
+    /// The state commitment stored by the rollup contract.
+    pub contract: H256,
+}
+
+impl StateCheck {
+    pub fn matches(&self) -> bool {
+        self.expected == self.contract
+    }
+}
+
+/// Results of the [`StateCheck`]s made since the node started.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateCheckStats {
+    /// Number of batches checked before submission.
+    pub checks: u64,
+    /// Number of checks in which the contract state did not match the batch.
+    pub mismatches: u64,
+    /// Number of times batches were skipped because the contract had already verified them.
+    pub resyncs: u64,
+    /// Number of times confirmed batches were resubmitted because the contract no longer reflected
+    /// them.
+    pub rollbacks: u64,
+    /// The most recent check which did not match.
+    pub last_mismatch: Option<StateCheck>,
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: Option<PathBuf>,
+    /// Entries keyed by the last block in the batch.
+    entries: BTreeMap<u64, OutboxEntry>,
+    /// Whether submission is paused. This is not persisted.
+    paused: bool,
+    state_checks: StateCheckStats,
+}
+
+impl Inner {
+    /// Drop the final entries more than [`RETAINED_FINAL_BATCHES`] below the most recently verified
+    /// batch. They will never be submitted again, and keeping them would make every write grow
+    /// with the age of the node.
+    fn compact(&mut self) {
+        let Some(latest) = self
+            .entries
+            .values()
+            .rev()
+            .find(|entry| entry.status.is_verified())
+            .map(|entry| entry.last_block)
+        else {
+            return;
+        };
+        let stale: Vec<_> = self
+            .entries
+            .range(..latest)
+            .rev()
+            .filter(|(_, entry)| entry.status.is_final())
+            .skip(RETAINED_FINAL_BATCHES)
+            .map(|(last_block, _)| *last_block)
+            .collect();
+        for last_block in stale {
+            self.entries.remove(&last_block);
+        }
+    }
+
+    /// Compact the outbox and write it to its file, if it has one.
+    ///
+    /// The file is written on a blocking thread while the outbox stays locked, so writes land in
+    /// order.
+    async fn persist(&mut self) -> Result<(), OutboxError> {
+        self.compact();
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let entries: Vec<_> = self.entries.values().collect();
+        let contents = serde_json::to_vec(&entries).context(JsonSnafu {
+            path: path.as_path(),
+        })?;
+        spawn_blocking(move || {
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, contents).context(IoSnafu { path: &tmp })?;
+            std::fs::rename(&tmp, &path).context(IoSnafu {
+                path: path.as_path(),
+            })
+        })
+        .await
+    }
+
+    #[cfg(feature = "executor")]
+    async fn set_status(&mut self, key: u64, status: SubmissionStatus) -> Result<(), OutboxError> {
+        self.entries.get_mut(&key).unwrap().status = status;
+        self.persist().await
+    }
+
+    /// Compare the state the batch ending at `key` was proven to start from with `contract`, the
+    /// state commitment stored by the rollup contract, reconciling the outbox with the contract if
+    /// they differ.
+    ///
+    /// Returns whether the batch can be submitted.
+    #[cfg(feature = "executor")]
+    async fn check_state(&mut self, key: u64, contract: [u8; 32]) -> Result<bool, OutboxError> {
+        let entry = &self.entries[&key];
+        let check = StateCheck {
+            first_block: entry.first_block,
+            last_block: entry.last_block,
+            expected: H256(entry.proof.old_state),
+            contract: H256(contract),
+        };
+        self.state_checks.checks += 1;
+        if check.matches() {
+            return Ok(true);
+        }
+        self.state_checks.mismatches += 1;
+        self.state_checks.last_mismatch = Some(check.clone());
+        let range = format!("{}-{}", check.first_block, check.last_block);
+
+        // If the contract holds the state produced by this or a later batch, those batches have
+        // already been verified.
+        if let Some(verified) = self
+            .entries
+            .range(key..)
+            .find(|(_, entry)| entry.proof.new_state == contract)
+            .map(|(last_block, _)| *last_block)
+        {
+            tracing::warn!(
+                "Rollup contract has already verified blocks {}-{verified}, skipping them",
+                check.first_block
+            );
+            for entry in self
+                .entries
+                .range_mut(key..=verified)
+                .map(|(_, entry)| entry)
+            {
+                entry.status = SubmissionStatus::VerifiedExternally;
+            }
+            self.state_checks.resyncs += 1;
+            self.persist().await?;
+            return Ok(false);
+        }
+
+        // If the contract holds the state an earlier batch started from, the batches since then
+        // are no longer reflected on the L1 and must be submitted again, in order. Any nonce
+        // claimed for this batch is released, since the earlier batches are submitted first.
+        if let Some(restart) = self
+            .entries
+            .range(..key)
+            .rev()
+            .find(|(_, entry)| entry.proof.old_state == contract)
+            .map(|(last_block, _)| *last_block)
+        {
+            let first_block = self.entries[&restart].first_block;
+            tracing::warn!(
+                "Rollup contract has rolled back to the state before block {first_block}, \
+                 resubmitting batches from there"
+            );
+            for entry in self
+                .entries
+                .range_mut(restart..=key)
+                .map(|(_, entry)| entry)
+            {
+                entry.status = SubmissionStatus::Pending;
+            }
+            self.state_checks.rollbacks += 1;
+            self.persist().await?;
+            return Ok(false);
+        }
+
+        tracing::error!(
+            "Rollup contract state {:#x} does not match the state {:#x} batch {range} starts from, \
+             or any other batch; pausing proof submission",
+            check.contract,
+            check.expected
+        );
+        self.paused = true;
+        Ok(false)
+    }
+}
+
+/// Queue of batch proofs to submit to the rollup contract, in order.
+#[derive(Clone, Debug)]
+pub struct Outbox {
+    inner: Arc<Mutex<Inner>>,
+    nonces: NonceManager,
+    // Only updated by submissions, which require the `executor` feature.
+    #[cfg_attr(not(feature = "executor"), allow(dead_code))]
+    metrics: NodeMetrics,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl Outbox {
+    /// An outbox which is not persisted, and so provides no guarantees across restarts.
+    pub fn in_memory() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                path: None,
+                entries: BTreeMap::new(),
+                paused: false,
+                state_checks: Default::default(),
+            })),
+            nonces: Default::default(),
+            metrics: Default::default(),
+        }
+    }
+
+    /// Open the outbox stored at `path`, creating an empty one if there is no file at `path`.
+    pub fn open(path: &Path) -> Result<Self, OutboxError> {
+        let entries: Vec<OutboxEntry> = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).context(JsonSnafu { path })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(source) => {
+                return Err(OutboxError::Io {
+                    path: path.into(),
+                    source,
+                })
+            }
+        };
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Inner {
+                path: Some(path.into()),
+                entries: entries
+                    .into_iter()
+                    .map(|entry| (entry.last_block, entry))
+                    .collect(),
+                paused: false,
+                state_checks: Default::default(),
+            })),
+            nonces: Default::default(),
+            metrics: Default::default(),
+        })
+    }
+
+    /// Claim nonces for submissions from `nonces`.
+    pub fn with_nonce_manager(mut self, nonces: NonceManager) -> Self {
+        self.nonces = nonces;
+        self
+    }
+
+    /// Count submissions and the gas they use in `metrics`.
+    pub fn with_metrics(mut self, metrics: NodeMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Record a batch proof for submission.
+    ///
+    /// Returns `false` without recording the batch if its blocks are already covered by a batch in
+    /// the outbox, as happens when a restarted node re-executes blocks it has already proven.
+    pub async fn enqueue(
+        &self,
+        proof: BatchProofInput,
+        count: u64,
+        shape: ProofShape,
+    ) -> Result<bool, OutboxError> {
+        let first_block = U256::from_big_endian(&proof.first_block).as_u64();
+        let last_block = U256::from_big_endian(&proof.last_block).as_u64();
+        let mut inner = self.inner.lock().await;
+        if inner
+            .entries
+            .last_key_value()
+            .is_some_and(|(last, _)| *last >= last_block)
+        {
+            tracing::info!("Batch {first_block}-{last_block} is already in the outbox");
+            return Ok(false);
+        }
+        inner.entries.insert(
+            last_block,
+            OutboxEntry {
+                first_block,
+                last_block,
+                count,
+                proof,
+                shape,
+                status: SubmissionStatus::Pending,
+                enqueued_at: unix_time(),
+                attempts: 0,
+            },
+        );
+        inner.persist().await?;
+        Ok(true)
+    }
+
+    /// All entries in the outbox, in order.
+    pub async fn entries(&self) -> Vec<OutboxEntry> {
+        self.inner.lock().await.entries.values().cloned().collect()
+    }
+
+    /// Batches which have not yet been confirmed on the L1, in order.
+    pub async fn pending(&self) -> Vec<PendingBatch> {
+        let now = unix_time();
+        self.inner
+            .lock()
+            .await
+            .entries
+            .values()
+            .filter(|entry| !entry.status.is_final())
+            .map(|entry| PendingBatch {
+                first_block: entry.first_block,
+                last_block: entry.last_block,
+                status: entry.status.clone(),
+                age: now.saturating_sub(entry.enqueued_at),
+                attempts: entry.attempts,
+            })
+            .collect()
+    }
+
+    /// The last block of the most recent batch confirmed on the L1.
+    pub async fn latest_confirmed(&self) -> Option<u64> {
+        self.inner
+            .lock()
+            .await
+            .entries
+            .values()
+            .rev()
+            .find(|entry| entry.status.is_verified())
+            .map(|entry| entry.last_block)
+    }
+
+    /// Results of comparing batches with the rollup contract state before submitting them.
+    pub async fn state_checks(&self) -> StateCheckStats {
+        self.inner.lock().await.state_checks.clone()
+    }
+
+    /// The nonces claimed for submissions and the transactions in flight.
+    pub async fn nonces(&self) -> NonceStats {
+        self.nonces.stats().await
+    }
+
+    /// Stop submitting batches. Batches are still recorded, and are submitted once submission is
+    /// resumed.
+    pub async fn pause(&self) {
+        self.inner.lock().await.paused = true;
+        tracing::warn!("Proof submission paused");
+    }
+
+    pub async fn resume(&self) {
+        self.inner.lock().await.paused = false;
+        tracing::info!("Proof submission resumed");
+    }
+
+    pub async fn is_paused(&self) -> bool {
+        self.inner.lock().await.paused
+    }
+
+    /// Submit pending batches in order, waiting for each to be confirmed before submitting the
+    /// next.
+    ///
+    /// Returns once every batch has been confirmed, or after a bounded number of confirmation
+    /// checks, in which case the remaining batches are picked up by the next call. Does nothing
+    /// while submission is paused.
+    #[cfg(feature = "executor")]
+    pub async fn submit_pending(&self, l1: &dyn L1Client, clock: &dyn Clock) {
+        if self.is_paused().await {
+            tracing::info!("Proof submission paused, buffering batch proofs");
+            return;
+        }
+        let mut rebroadcast = true;
+        for _ in 0..MAX_POLLS {
+            // Submission may be paused while waiting for a confirmation.
+            if self.is_paused().await {
+                return;
+            }
+            match self.step(l1, rebroadcast).await {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(err) => tracing::warn!("Failed to submit batch proof, retrying: {err}"),
+            }
+            rebroadcast = false;
+            clock.sleep(POLL_INTERVAL).await;
+        }
+        tracing::warn!("Batch proofs not yet confirmed, will retry");
+    }
+
+    /// Advance the first unconfirmed batch by one step.
+    ///
+    /// If `rebroadcast` is set, a batch which has been broadcast but not yet included is sent
+    /// again with the same nonce, in case the original transaction was dropped.
+    ///
+    /// Returns `true` if there are no unconfirmed batches.
+    #[cfg(feature = "executor")]
+    async fn step(&self, l1: &dyn L1Client, rebroadcast: bool) -> Result<bool, L1Error> {
+        let mut inner = self.inner.lock().await;
+        let Some(entry) = inner
+            .entries
+            .values()
+            .find(|entry| !entry.status.is_final())
+            .cloned()
+        else {
+            return Ok(true);
+        };
+        let key = entry.last_block;
+        let range = format!("{}-{}", entry.first_block, entry.last_block);
+        let persist = |err: OutboxError| L1Error::Submission {
+            message: format!("unable to update outbox: {err}"),
+        };
+
+        let (nonce, mut tx_hashes) = match entry.status {
+            SubmissionStatus::Pending => {
+                let Some(nonce) = self.nonces.reserve(l1).await? else {
+                    return Ok(false);
+                };
+                inner
+                    .set_status(key, SubmissionStatus::Claimed { nonce })
+                    .await
+                    .map_err(persist)?;
+                (nonce, vec![])
+            }
+            SubmissionStatus::Claimed { nonce } => {
+                if self.nonces.is_used(l1, nonce).await? {
+                    tracing::warn!(
+                        "Nonce {nonce} claimed for blocks {range} was used by another \
+                         transaction, claiming a new one"
+                    );
+                    self.nonces.lost(nonce).await;
+                    inner
+                        .set_status(key, SubmissionStatus::Pending)
+                        .await
+                        .map_err(persist)?;
+                    return Ok(false);
+                }
+                (nonce, vec![])
+            }
+            SubmissionStatus::Submitted { nonce, tx_hashes } => {
+                // Check whether the nonce is used before checking our own transactions, so that a
+                // transaction mined in between is not mistaken for someone else's.
+                let used = self.nonces.is_used(l1, nonce).await?;
+                for tx_hash in &tx_hashes {
+                    match l1.transaction_status(*tx_hash).await? {
+                        Some(true) => {
+                            self.nonces.mined(nonce).await;
+                            let gas_used = l1.gas_used(*tx_hash).await.ok().flatten();
+                            self.metrics.record_proof_included(true, gas_used);
+                            tracing::info!(
+                                "Proof for blocks {range} confirmed in transaction {tx_hash:?}"
+                            );
+                            inner
+                                .set_status(key, SubmissionStatus::Confirmed { tx_hash: *tx_hash })
+                                .await
+                                .map_err(persist)?;
+                            return Ok(false);
+                        }
+                        Some(false) => {
+                            self.nonces.mined(nonce).await;
+                            let gas_used = l1.gas_used(*tx_hash).await.ok().flatten();
+                            self.metrics.record_proof_included(false, gas_used);
+                            tracing::error!(
+                                "Proof for blocks {range} reverted in transaction {tx_hash:?}"
+                            );
+                            inner
+                                .set_status(key, SubmissionStatus::Reverted { tx_hash: *tx_hash })
+                                .await
+                                .map_err(persist)?;
+                            return Ok(false);
+                        }
+                        None => {}
+                    }
+                }
+                if used {
+                    tracing::warn!(
+                        "Nonce {nonce} of blocks {range} was used by another transaction, \
+                         resubmitting with a new nonce"
+                    );
+                    self.nonces.lost(nonce).await;
+                    inner
+                        .set_status(key, SubmissionStatus::Pending)
+                        .await
+                        .map_err(persist)?;
+                    return Ok(false);
+                }
+                if !rebroadcast {
+                    return Ok(false);
+                }
+                (nonce, tx_hashes)
+            }
+            SubmissionStatus::Confirmed { .. }
+            | SubmissionStatus::Reverted { .. }
+            | SubmissionStatus::VerifiedExternally => {
+                unreachable!()
+            }
+        };
+
+        // The contract rejects a batch which does not start from its current state, so don't pay
+        // for a transaction which is bound to revert.
+        let contract = l1.state_commitment().await?;
+        if !inner.check_state(key, contract).await.map_err(persist)? {
+            // If the batch no longer holds the nonce, and it was never sent, the nonce is free for
+            // the next batch. A batch the contract has already verified may have been compacted
+            // away.
+            if !inner.entries.get(&key).is_some_and(|entry| {
+                matches!(
+                    entry.status,
+                    SubmissionStatus::Claimed { .. } | SubmissionStatus::Submitted { .. }
+                )
+            }) && tx_hashes.is_empty()
+            {
+                self.nonces.release(nonce).await;
+            }
+            return Ok(false);
+        }
+
+        // Nor for a batch which the contract would reject for another reason, such as blocks the
+        // light client has not finalized yet. The batch keeps its nonce and is retried.
+        let mut mirror = ContractMirror {
+            state_commitment: contract,
+            num_verified_blocks: l1.num_verified_blocks().await?,
+            finalized_height: l1.finalized_height().await?.unwrap_or(u64::MAX),
+        };
+        if let Err(err) = mirror.verify_blocks(entry.count, &entry.proof, entry.shape) {
+            return Err(L1Error::Submission {
+                message: format!("blocks {range} would be rejected by the rollup contract: {err}"),
+            });
+        }
+
+        inner.entries.get_mut(&key).unwrap().attempts += 1;
+        inner.persist().await.map_err(persist)?;
+        let tx_hash = l1
+            .send_verify_blocks(entry.count, entry.proof, entry.shape, nonce)
+            .await?;
+        tracing::info!("Submitted proof for blocks {range} in transaction {tx_hash:?}");
+        self.nonces.sent(nonce, tx_hash).await;
+        if tx_hashes.is_empty() {
+            self.metrics.record_proof_submitted();
+        }
+        if !tx_hashes.contains(&tx_hash) {
+            tx_hashes.push(tx_hash);
+        }
+        inner
+            .set_status(key, SubmissionStatus::Submitted { nonce, tx_hashes })
+            .await
+            .map_err(persist)?;
+        Ok(false)
+    }
+}
+
+#[cfg(all(test, feature = "executor"))]
+mod tests {
+    use super::*;
+    use crate::l1::StateUpdate;
+    use futures::future::{BoxFuture, FutureExt};
+    use std::collections::HashMap;
+    use std::sync::Mutex as SyncMutex;
+
+    /// An L1 which includes every transaction it is sent the next time it is queried, unless it is
+    /// holding transactions.
+    #[derive(Debug, Default)]
+    struct MockL1 {
+        /// The account's nonce, counting mined transactions.
+        nonce: SyncMutex<u64>,
+        /// Number of transactions sent from the account by other services which are not yet mined.
+        external_pending: SyncMutex<u64>,
+        /// Whether transactions are left unmined.
+        hold: SyncMutex<bool>,
+        sent: SyncMutex<Vec<(u64, u64)>>,
+        mined: SyncMutex<HashMap<H256, bool>>,
+        state: SyncMutex<[u8; 32]>,
+        verified: SyncMutex<u64>,
+        /// Height finalized by the light client, if known.
+        finalized: SyncMutex<Option<u64>>,
+    }
+
+    impl L1Client for MockL1 {
+        fn next_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            let nonce = *self.nonce.lock().unwrap() + *self.external_pending.lock().unwrap();
+            async move { Ok(nonce) }.boxed()
+        }
+
+        fn confirmed_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            let nonce = *self.nonce.lock().unwrap();
+            async move { Ok(nonce) }.boxed()
+        }
+
+        fn send_verify_blocks(
+            &self,
+            count: u64,
+            proof: BatchProofInput,
+            shape: ProofShape,
+            nonce: u64,
+        ) -> BoxFuture<'_, Result<H256, L1Error>> {
+            let hash = H256::random();
+            self.sent.lock().unwrap().push((count, nonce));
+            if !*self.hold.lock().unwrap() {
+                let mut contract = ContractMirror {
+                    state_commitment: *self.state.lock().unwrap(),
+                    num_verified_blocks: *self.verified.lock().unwrap(),
+                    finalized_height: self.finalized.lock().unwrap().unwrap_or(u64::MAX),
+                };
+                let success = contract.verify_blocks(count, &proof, shape).is_ok();
+                *self.state.lock().unwrap() = contract.state_commitment;
+                *self.verified.lock().unwrap() = contract.num_verified_blocks;
+                *self.nonce.lock().unwrap() = nonce + 1;
+                self.mined.lock().unwrap().insert(hash, success);
+            }
+            async move { Ok(hash) }.boxed()
+        }
+
+        fn transaction_status(&self, hash: H256) -> BoxFuture<'_, Result<Option<bool>, L1Error>> {
+            let status = self.mined.lock().unwrap().get(&hash).copied();
+            async move { Ok(status) }.boxed()
+        }
+
+        fn state_commitment(&self) -> BoxFuture<'_, Result<[u8; 32], L1Error>> {
+            let state = *self.state.lock().unwrap();
+            async move { Ok(state) }.boxed()
+        }
+
+        fn num_verified_blocks(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            let verified = *self.verified.lock().unwrap();
+            async move { Ok(verified) }.boxed()
+        }
+
+        fn finalized_height(&self) -> BoxFuture<'_, Result<Option<u64>, L1Error>> {
+            let finalized = *self.finalized.lock().unwrap();
+            async move { Ok(finalized) }.boxed()
+        }
+
+        fn last_state_update(&self) -> BoxFuture<'_, Result<Option<StateUpdate>, L1Error>> {
+            let update = match *self.verified.lock().unwrap() {
+                0 => None,
+                num_verified_blocks => Some(StateUpdate {
+                    num_verified_blocks,
+                    state_commitment: *self.state.lock().unwrap(),
+                }),
+            };
+            async move { Ok(update) }.boxed()
+        }
+    }
+
+    fn batch(first_block: u64, last_block: u64) -> BatchProofInput {
+        let mut proof = BatchProofInput::default();
+        U256::from(first_block).to_big_endian(&mut proof.first_block);
+        U256::from(last_block).to_big_endian(&mut proof.last_block);
+        proof
+    }
+
+    /// A batch taking the contract from state `old_state` to state `new_state`.
+    fn transition(
+        first_block: u64,
+        last_block: u64,
+        old_state: u8,
+        new_state: u8,
+    ) -> BatchProofInput {
+        BatchProofInput {
+            old_state: [old_state; 32],
+            new_state: [new_state; 32],
+            ..batch(first_block, last_block)
+        }
+    }
+
+    #[async_std::test]
+    async fn test_outbox_exactly_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("outbox.json");
+        let l1 = MockL1::default();
+
+        let outbox = Outbox::open(&path).unwrap();
+        assert!(outbox
+            .enqueue(batch(0, 2), 3, ProofShape::Endpoints)
+            .await
+            .unwrap());
+        assert!(outbox
+            .enqueue(batch(3, 4), 2, ProofShape::Endpoints)
+            .await
+            .unwrap());
+
+        // Submit the first batch, then "crash" before its confirmation is recorded.
+        *l1.nonce.lock().unwrap() = 7;
+        outbox.step(&l1, true).await.unwrap();
+        let pending = outbox.pending().await;
+        assert_eq!(pending.len(), 2);
+        assert!(matches!(
+            pending[0].status,
+            SubmissionStatus::Submitted { nonce: 7, .. }
+        ));
+        assert_eq!(pending[0].attempts, 1);
+        assert_eq!(pending[1].status, SubmissionStatus::Pending);
+
+        // After a restart, the node re-executes and re-proves the same blocks, which are not
+        // enqueued again.
+        let outbox = Outbox::open(&path).unwrap();
+        assert!(!outbox
+            .enqueue(batch(0, 2), 3, ProofShape::Endpoints)
+            .await
+            .unwrap());
+        while !outbox.step(&l1, false).await.unwrap() {}
+
+        // Each batch was sent exactly once, with consecutive nonces.
+        assert_eq!(*l1.sent.lock().unwrap(), [(3, 7), (2, 8)]);
+        assert!(outbox.pending().await.is_empty());
+        assert_eq!(outbox.latest_confirmed().await, Some(4));
+        let entries = Outbox::open(&path).unwrap().entries().await;
+        assert!(entries
+            .iter()
+            .all(|entry| matches!(entry.status, SubmissionStatus::Confirmed { .. })));
+    }
+
+    #[async_std::test]
+    async fn test_outbox_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("outbox.json");
+        let l1 = MockL1::default();
+
+        let outbox = Outbox::open(&path).unwrap();
+        let batches = RETAINED_FINAL_BATCHES as u64 + 3;
+        for block in 0..batches {
+            outbox
+                .enqueue(batch(block, block), 1, ProofShape::Endpoints)
+                .await
+                .unwrap();
+        }
+        while !outbox.step(&l1, false).await.unwrap() {}
+
+        // Only the most recently verified batch and those just below it are kept, in memory and on
+        // disk.
+        assert_eq!(outbox.latest_confirmed().await, Some(batches - 1));
+        let retained = (batches - 1 - RETAINED_FINAL_BATCHES as u64..batches).collect::<Vec<_>>();
+        for entries in [
+            outbox.entries().await,
+            Outbox::open(&path).unwrap().entries().await,
+        ] {
+            assert_eq!(
+                entries
+                    .iter()
+                    .map(|entry| entry.last_block)
+                    .collect::<Vec<_>>(),
+                retained
+            );
+        }
+
+        // Batches below the retained ones are not enqueued again.
+        assert!(!outbox
+            .enqueue(batch(0, 0), 1, ProofShape::Endpoints)
+            .await
+            .unwrap());
+    }
+
+    #[async_std::test]
+    async fn test_outbox_state_check() {
+        let l1 = MockL1::default();
+        let outbox = Outbox::in_memory();
+        for (first_block, last_block, old_state, new_state) in [(0, 2, 0, 1), (3, 4, 1, 2)] {
+            outbox
+                .enqueue(
+                    transition(first_block, last_block, old_state, new_state),
+                    last_block + 1 - first_block,
+                    ProofShape::Endpoints,
+                )
+                .await
+                .unwrap();
+        }
+
+        // The first batch was verified behind the outbox's back, so it is skipped rather than
+        // submitted again.
+        *l1.state.lock().unwrap() = [1; 32];
+        while !outbox.step(&l1, false).await.unwrap() {}
+        assert_eq!(*l1.sent.lock().unwrap(), [(2, 0)]);
+        let entries = outbox.entries().await;
+        assert_eq!(entries[0].status, SubmissionStatus::VerifiedExternally);
+        assert!(matches!(
+            entries[1].status,
+            SubmissionStatus::Confirmed { .. }
+        ));
+        assert_eq!(outbox.latest_confirmed().await, Some(4));
+        let stats = outbox.state_checks().await;
+        assert_eq!((stats.checks, stats.mismatches, stats.resyncs), (2, 1, 1));
+        assert_eq!(
+            stats.last_mismatch,
+            Some(StateCheck {
+                first_block: 0,
+                last_block: 2,
+                expected: H256([0; 32]),
+                contract: H256([1; 32]),
+            })
+        );
+
+        // An L1 reorg undoes the second batch, so it is submitted again before the next one.
+        *l1.state.lock().unwrap() = [1; 32];
+        outbox
+            .enqueue(transition(5, 6, 2, 3), 2, ProofShape::Endpoints)
+            .await
+            .unwrap();
+        while !outbox.step(&l1, false).await.unwrap() {}
+        assert_eq!(*l1.sent.lock().unwrap(), [(2, 0), (2, 1), (2, 2)]);
+        assert_eq!(*l1.state.lock().unwrap(), [3; 32]);
+        assert_eq!(outbox.state_checks().await.rollbacks, 1);
+
+        // A contract state which matches no batch halts submission.
+        *l1.state.lock().unwrap() = [9; 32];
+        outbox
+            .enqueue(transition(7, 8, 3, 4), 2, ProofShape::Endpoints)
+            .await
+            .unwrap();
+        assert!(!outbox.step(&l1, false).await.unwrap());
+        assert!(outbox.is_paused().await);
+        assert_eq!(l1.sent.lock().unwrap().len(), 3);
+        assert_eq!(outbox.pending().await.len(), 1);
+    }
+
+    #[async_std::test]
+    async fn test_outbox_nonce_races() {
+        let l1 = MockL1::default();
+        let nonces = NonceManager::new(2);
+        let outbox = Outbox::in_memory().with_nonce_manager(nonces.clone());
+        outbox
+            .enqueue(batch(0, 2), 3, ProofShape::Endpoints)
+            .await
+            .unwrap();
+
+        // While other services have too many transactions pending, submission is deferred.
+        *l1.external_pending.lock().unwrap() = 2;
+        assert!(!outbox.step(&l1, false).await.unwrap());
+        assert!(l1.sent.lock().unwrap().is_empty());
+        assert_eq!(outbox.pending().await[0].status, SubmissionStatus::Pending);
+        assert_eq!(outbox.nonces().await.deferred, 1);
+
+        // Once one of them is mined, the batch is submitted after the other.
+        *l1.nonce.lock().unwrap() = 1;
+        *l1.external_pending.lock().unwrap() = 1;
+        *l1.hold.lock().unwrap() = true;
+        while !matches!(
+            outbox.pending().await[0].status,
+            SubmissionStatus::Submitted { .. }
+        ) {
+            outbox.step(&l1, false).await.unwrap();
+        }
+        assert_eq!(*l1.sent.lock().unwrap(), [(3, 2)]);
+        let stats = outbox.nonces().await;
+        assert_eq!(stats.next_nonce, Some(3));
+        assert_eq!(stats.in_flight.len(), 1);
+
+        // Another service replaces the batch's transaction with one of its own, using the same
+        // nonce. The batch is resubmitted with a fresh nonce, skipping those used meanwhile.
+        *l1.nonce.lock().unwrap() = 5;
+        *l1.external_pending.lock().unwrap() = 0;
+        *l1.hold.lock().unwrap() = false;
+        while !outbox.step(&l1, false).await.unwrap() {}
+        assert_eq!(*l1.sent.lock().unwrap(), [(3, 2), (3, 5)]);
+        assert_eq!(outbox.latest_confirmed().await, Some(2));
+        let stats = nonces.stats().await;
+        assert_eq!((stats.lost, stats.resyncs), (1, 1));
+        assert_eq!(stats.next_nonce, Some(6));
+        assert!(stats.in_flight.is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_outbox_preflight() {
+        let l1 = MockL1::default();
+        *l1.finalized.lock().unwrap() = Some(2);
+        let outbox = Outbox::in_memory();
+        outbox
+            .enqueue(transition(0, 2, 0, 1), 3, ProofShape::Endpoints)
+            .await
+            .unwrap();
+
+        // The light client has not finalized all of the blocks, so the batch would revert.
+        assert!(matches!(
+            outbox.step(&l1, false).await,
+            Err(L1Error::Submission { .. })
+        ));
+        assert!(l1.sent.lock().unwrap().is_empty());
+        assert!(matches!(
+            outbox.pending().await[0].status,
+            SubmissionStatus::Claimed { nonce: 0 }
+        ));
+
+        // Once it has, the batch is submitted with the nonce it claimed.
+        *l1.finalized.lock().unwrap() = Some(3);
+        while !outbox.step(&l1, false).await.unwrap() {}
+        assert_eq!(*l1.sent.lock().unwrap(), [(3, 0)]);
+        assert_eq!(*l1.verified.lock().unwrap(), 3);
+
+        // A full batch whose commitments do not end in its new state can never be verified.
+        let mut inconsistent = transition(3, 4, 1, 2);
+        inconsistent.commitments = vec![[7; 32]];
+        *l1.finalized.lock().unwrap() = Some(5);
+        outbox
+            .enqueue(inconsistent, 2, ProofShape::Full)
+            .await
+            .unwrap();
+        assert!(outbox.step(&l1, false).await.is_err());
+        assert_eq!(l1.sent.lock().unwrap().len(), 1);
+    }
+
+    #[async_std::test]
+    async fn test_outbox_pause() {
+        let l1 = MockL1::default();
+        let clock = crate::clock::SystemClock;
+        let outbox = Outbox::in_memory();
+        outbox.pause().await;
+        outbox
+            .enqueue(batch(0, 2), 3, ProofShape::Endpoints)
+            .await
+            .unwrap();
+        outbox
+            .enqueue(batch(3, 4), 2, ProofShape::Endpoints)
+            .await
+            .unwrap();
+
+        // Nothing is submitted while paused, but batches are buffered.
+        outbox.submit_pending(&l1, &clock).await;
+        assert!(l1.sent.lock().unwrap().is_empty());
+        assert_eq!(outbox.pending().await.len(), 2);
+
+        // Once resumed, the buffered batches are submitted in order.
+        outbox.resume().await;
+        outbox.submit_pending(&l1, &clock).await;
+        assert_eq!(*l1.sent.lock().unwrap(), [(3, 0), (2, 1)]);
+        assert_eq!(outbox.latest_confirmed().await, Some(4));
+    }
+}
@@ -6,16 +6,175 @@
 
 extern crate derive_more;
 
-use committable::Commitment;
+use async_std::sync::{Arc, RwLock};
+use async_std::task::spawn_blocking;
+use clap::ValueEnum;
+use committable::{Commitment, Committable};
+#[cfg(feature = "executor")]
 use contract_bindings::example_rollup as bindings;
 use derive_more::Into;
 use espresso_types::{Header, NsProof, SeqTypes};
 use hotshot_query_service::availability::BlockHash;
 use hotshot_query_service::VidCommon;
 use sequencer_utils::commitment_to_u256;
+use serde::{Deserialize, Serialize};
 use snafu::Snafu;
+use std::time::Instant;
+use strum_macros::Display;
 
+use crate::l1::BatchProofInput;
 use crate::state::State;
+use crate::withdrawal::WithdrawalsRoot;
+
+/// Strategy used to aggregate per-block proofs into the batch proofs submitted to the rollup
+/// contract.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum AggregationStrategy {
+    /// Submit a separate proof for every executed block.
+    PerBlock,
+    /// Merge all proofs generated in response to a single light client update.
+    #[default]
+    PerEvent,
+    /// Merge proofs across light client updates, submitting once `max_batch_size` blocks are
+    /// pending.
+    Merged,
+}
+
+/// Shape in which batch proofs are submitted to the rollup contract.
+#[derive(
+    ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum ProofShape {
+    /// Submit only the endpoints of the batch.
+    #[default]
+    Endpoints,
+    /// Submit the endpoints along with the state commitment after every block in the batch.
+    Full,
+    /// Submit the endpoints along with a hash chain digest of the state commitment after every
+    /// block in the batch. The commitments themselves are served off-chain by the rollup API.
+    Compressed,
+}
+
+/// Which blocks have their namespace proof verified before a proof of their execution is
+/// generated.
+///
+/// Verifying the namespace proof shows that the executor was given every transaction in the rollup
+/// namespace, and is the bulk of the cost of executing a block. A node which skips verification of
+/// some blocks can still catch missing transactions after the fact with spot audits.
+#[derive(
+    ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum VerificationMode {
+    /// Verify the namespace proof of every block.
+    #[default]
+    Always,
+    /// Verify the namespace proof of blocks whose height is a multiple of the sample interval.
+    Sampled,
+    /// Skip verification in debug builds, and verify every block in release builds.
+    SkipInDev,
+}
+
+/// Counters and timings of namespace proof verification.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationStats {
+    pub mode: VerificationMode,
+    /// Number of namespace proofs verified.
+    pub verified: u64,
+    /// Number of blocks executed without verifying their namespace proof.
+    pub skipped: u64,
+    /// Total time spent verifying namespace proofs, in microseconds.
+    pub total_us: u64,
+    /// Longest time spent verifying a single namespace proof, in microseconds.
+    pub max_us: u64,
+}
+
+/// Verifies the namespace proofs of executed blocks according to a [`VerificationMode`].
+///
+/// Verification is CPU bound, so it runs on the blocking thread pool rather than on the async
+/// executor.
+#[derive(Clone, Debug)]
+pub struct ProofVerifier {
+    mode: VerificationMode,
+    sample_interval: u64,
+    stats: Arc<RwLock<VerificationStats>>,
+}
+
+impl Default for ProofVerifier {
+    fn default() -> Self {
+        Self::new(VerificationMode::default())
+    }
+}
+
+impl ProofVerifier {
+    pub fn new(mode: VerificationMode) -> Self {
+        Self {
+            mode,
+            sample_interval: 1,
+            stats: Arc::new(RwLock::new(VerificationStats {
+                mode,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Verify one in every `interval` blocks in [`VerificationMode::Sampled`] mode.
+    pub fn with_sample_interval(mut self, interval: u64) -> Self {
+        self.sample_interval = interval.max(1);
+        self
+    }
+
+    pub fn mode(&self) -> VerificationMode {
+        self.mode
+    }
+
+    /// Whether the namespace proof of the block at `block_height` is verified.
+    pub fn should_verify(&self, block_height: u64) -> bool {
+        match self.mode {
+            VerificationMode::Always => true,
+            VerificationMode::Sampled => block_height % self.sample_interval == 0,
+            VerificationMode::SkipInDev => !cfg!(debug_assertions),
+        }
+    }
+
+    pub async fn stats(&self) -> VerificationStats {
+        *self.stats.read().await
+    }
+
+    /// Verify `namespace_proof` against `header`, unless the mode skips this block.
+    ///
+    /// Returns `false` only if the proof was verified and is invalid.
+    pub(crate) async fn verify(
+        &self,
+        header: &Header,
+        namespace_proof: NsProof,
+        vid_common: VidCommon,
+    ) -> bool {
+        if !self.should_verify(header.height()) {
+            self.stats.write().await.skipped += 1;
+            return true;
+        }
+        let header = header.clone();
+        let start = Instant::now();
+        let valid = spawn_blocking(move || {
+            namespace_proof
+                .verify(header.ns_table(), &header.payload_commitment(), &vid_common)
+                .is_some()
+        })
+        .await;
+        let elapsed = start.elapsed().as_micros() as u64;
+        let mut stats = self.stats.write().await;
+        stats.verified += 1;
+        stats.total_us += elapsed;
+        stats.max_us = stats.max_us.max(elapsed);
+        valid
+    }
+}
 
 /// An error that occurs while generating proofs.
 #[derive(Clone, Debug, Snafu)]
@@ -30,11 +189,29 @@ pub enum ProofError {
 
 /// A mock proof that state_commitment represents a valid state transition from
 /// previous_state_commitment when the transactions in a given block are applied.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct Proof {
     block: BlockHash<SeqTypes>,
     old_state: Commitment<State>,
     new_state: Commitment<State>,
+    // The root of the withdrawal tree in `new_state`, if the block added withdrawals.
+    #[serde(default)]
+    withdrawals: Option<WithdrawalsRoot>,
+}
+
+/// The inputs to a [`Proof`] of the execution of a block.
+pub(crate) struct BlockInputs {
+    pub header: Header,
+    pub block: BlockHash<SeqTypes>,
+    /// The namespace proof for the rollup's transactions in the block.
+    pub namespace_proof: Option<NsProof>,
+    pub vid_common: VidCommon,
+    /// The state commitment before the block.
+    pub old_state: Commitment<State>,
+    /// The state commitment after the block.
+    pub new_state: Commitment<State>,
+    /// The root of the withdrawal tree after the block, if the block added withdrawals.
+    pub withdrawals: Option<WithdrawalsRoot>,
 }
 
 impl Proof {
@@ -43,33 +220,68 @@ impl Proof {
     ///
     /// Transaction data comes from the 'get_namespaced_leaves' method of the NamespaceProof interface.
     /// A real prover would incorporate this data during proof construction.
-    pub fn generate(
-        header: Header,
-        state_commitment: Commitment<State>,
-        previous_state_commitment: Commitment<State>,
-        namespace_proof: Option<NsProof>,
-        vid_common: VidCommon,
+    ///
+    /// Whether the namespace proof is verified first is decided by `verifier`.
+    pub async fn generate(inputs: BlockInputs, verifier: &ProofVerifier) -> Self {
+        let BlockInputs {
+            header,
+            block,
+            namespace_proof,
+            vid_common,
+            old_state,
+            new_state,
+            withdrawals,
+        } = inputs;
+        if !verifier
+            .verify(&header, namespace_proof.unwrap(), vid_common)
+            .await
+        {
+            panic!("Namespace proof failure, cannot continue");
+        }
+        Self {
+            block,
+            old_state,
+            new_state,
+            withdrawals,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn new(
         block: BlockHash<SeqTypes>,
+        old_state: Commitment<State>,
+        new_state: Commitment<State>,
     ) -> Self {
-        namespace_proof
-            .unwrap()
-            .verify(header.ns_table(), &header.payload_commitment(), &vid_common)
-            .expect("Namespace proof failure, cannot continue");
         Self {
             block,
-            old_state: previous_state_commitment,
-            new_state: state_commitment,
+            old_state,
+            new_state,
+            withdrawals: None,
         }
     }
+
+    /// The block whose execution is proven.
+    pub fn block(&self) -> BlockHash<SeqTypes> {
+        self.block
+    }
+
+    pub fn new_state(&self) -> Commitment<State> {
+        self.new_state
+    }
 }
 
 /// A mock proof aggregating a batch of proofs for a range of blocks.
-#[derive(Into)]
+#[derive(Clone, Debug, PartialEq, Eq, Into, Serialize, Deserialize)]
 pub(crate) struct BatchProof {
     first_block: BlockHash<SeqTypes>,
     last_block: BlockHash<SeqTypes>,
     old_state: Commitment<State>,
     new_state: Commitment<State>,
+    // The state commitment after each block with a proof in the batch, ending in `new_state`.
+    commitments: Vec<Commitment<State>>,
+    // The root of the withdrawal tree in `new_state`, if any block in the batch added withdrawals.
+    #[serde(default)]
+    withdrawals: Option<WithdrawalsRoot>,
 }
 
 impl BatchProof {
@@ -95,17 +307,186 @@ impl BatchProof {
             last_block: proofs[proofs.len() - 1].clone().block,
             old_state: proofs[0].old_state,
             new_state: proofs[proofs.len() - 1].new_state,
+            commitments: proofs.iter().map(|proof| proof.new_state).collect(),
+            withdrawals: proofs.iter().rev().find_map(|proof| proof.withdrawals),
         })
     }
+
+    pub fn first_block(&self) -> BlockHash<SeqTypes> {
+        self.first_block
+    }
+
+    pub fn last_block(&self) -> BlockHash<SeqTypes> {
+        self.last_block
+    }
 }
 
+#[cfg(feature = "executor")]
 impl From<BatchProof> for bindings::BatchProof {
     fn from(p: BatchProof) -> Self {
+        let withdrawals = p.withdrawals.unwrap_or_default();
         Self {
             first_block: commitment_to_u256(p.first_block),
             last_block: commitment_to_u256(p.last_block),
             old_state: commitment_to_u256(p.old_state),
             new_state: commitment_to_u256(p.new_state),
+            withdrawals_root: withdrawals.root,
+            num_withdrawals: withdrawals.count,
+        }
+    }
+}
+
+/// `commitment` as a big-endian word, as stored by the rollup contract.
+fn commitment_word<T: Committable>(commitment: Commitment<T>) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    commitment_to_u256(commitment).to_big_endian(&mut bytes);
+    bytes
+}
+
+impl From<BatchProof> for BatchProofInput {
+    fn from(p: BatchProof) -> Self {
+        Self {
+            first_block: commitment_word(p.first_block),
+            last_block: commitment_word(p.last_block),
+            old_state: commitment_word(p.old_state),
+            new_state: commitment_word(p.new_state),
+            commitments: p.commitments.into_iter().map(commitment_word).collect(),
+            withdrawals: p.withdrawals,
+        }
+    }
+}
+
+/// Proofs which have been generated but not yet aggregated and submitted to the rollup contract.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct PendingProofs {
+    // Each proof is paired with the number of blocks it covers, which includes any blocks without
+    // transactions for this rollup that were executed alongside it.
+    proofs: Vec<(Proof, u64)>,
+    // Blocks executed before the first pending proof which did not produce a proof of their own.
+    skipped: u64,
+}
+
+impl PendingProofs {
+    /// Add a proof for a newly executed block.
+    pub fn push(&mut self, proof: Proof) {
+        self.proofs.push((proof, self.skipped + 1));
+        self.skipped = 0;
+    }
+
+    /// Record a block which was executed without producing a proof.
+    pub fn skip_block(&mut self) {
+        match self.proofs.last_mut() {
+            Some((_, count)) => *count += 1,
+            None => self.skipped += 1,
+        }
+    }
+
+    /// Remove every pending proof, each paired with the number of blocks it covers, to be
+    /// aggregated elsewhere.
+    pub fn take_proofs(&mut self) -> Vec<(Proof, u64)> {
+        std::mem::take(&mut self.proofs)
+    }
+
+    /// Total number of blocks covered by the pending proofs.
+    pub fn num_blocks(&self) -> u64 {
+        self.proofs.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Remove the proofs which are ready for submission and aggregate them according to
+    /// `strategy`.
+    ///
+    /// Each batch is returned along with the number of blocks it covers. With
+    /// [`AggregationStrategy::Merged`], proofs remain pending until they cover at least
+    /// `max_batch_size` blocks, and no batch covers more than `max_batch_size` blocks unless a
+    /// single proof does.
+    pub fn take_batches(
+        &mut self,
+        strategy: AggregationStrategy,
+        max_batch_size: u64,
+    ) -> Result<Vec<(BatchProof, u64)>, ProofError> {
+        let mut groups = vec![];
+        match strategy {
+            AggregationStrategy::PerBlock => {
+                groups.extend(self.proofs.drain(..).map(|proof| vec![proof]));
+            }
+            AggregationStrategy::PerEvent => {
+                if !self.proofs.is_empty() {
+                    groups.push(self.proofs.drain(..).collect());
+                }
+            }
+            AggregationStrategy::Merged => {
+                while !self.proofs.is_empty() && self.num_blocks() >= max_batch_size {
+                    let mut size = 0;
+                    let mut len = 0;
+                    for (_, count) in &self.proofs {
+                        if len > 0 && size + count > max_batch_size {
+                            break;
+                        }
+                        size += count;
+                        len += 1;
+                    }
+                    groups.push(self.proofs.drain(..len).collect());
+                }
+            }
         }
+
+        groups
+            .into_iter()
+            .map(|group: Vec<(Proof, u64)>| {
+                let count: u64 = group.iter().map(|(_, count)| count).sum();
+                let proofs = group
+                    .into_iter()
+                    .map(|(proof, _)| proof)
+                    .collect::<Vec<_>>();
+                Ok((BatchProof::generate(&proofs)?, count))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mock_block;
+    use espresso_types::NamespaceId;
+
+    #[test]
+    fn test_verification_modes() {
+        assert!((0..4).all(|height| ProofVerifier::default().should_verify(height)));
+
+        let sampled = ProofVerifier::new(VerificationMode::Sampled).with_sample_interval(3);
+        let verified: Vec<_> = (0..7).filter(|h| sampled.should_verify(*h)).collect();
+        assert_eq!(verified, [0, 3, 6]);
+
+        // Tests are built with debug assertions.
+        assert!(!ProofVerifier::new(VerificationMode::SkipInDev).should_verify(0));
+    }
+
+    #[async_std::test]
+    async fn test_verification_stats() {
+        let namespace = NamespaceId::from(1_u64);
+        let block = mock_block(namespace, &[(namespace, vec![b"transaction".to_vec()])]).await;
+        let namespace_proof = block.namespace_proof.unwrap();
+        let vid_common = block.vid_common.unwrap();
+
+        let verifier = ProofVerifier::new(VerificationMode::SkipInDev);
+        assert!(
+            verifier
+                .verify(&block.header, namespace_proof.clone(), vid_common.clone())
+                .await
+        );
+        let stats = verifier.stats().await;
+        assert_eq!(stats.mode, VerificationMode::SkipInDev);
+        assert_eq!((stats.verified, stats.skipped), (0, 1));
+
+        let verifier = ProofVerifier::default();
+        assert!(
+            verifier
+                .verify(&block.header, namespace_proof, vid_common)
+                .await
+        );
+        let stats = verifier.stats().await;
+        assert_eq!((stats.verified, stats.skipped), (1, 0));
+        assert_eq!(stats.max_us, stats.total_us);
     }
 }
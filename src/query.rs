@@ -0,0 +1,585 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Filtered queries over the history of executed transactions.
+//!
+//! The receipt index only finds a transaction by its hash. The [`TransactionIndex`] records the
+//! sender, recipient, amount, block time and status of every executed transaction, so that the
+//! `transactions` route can find, for example, the failed transfers of an account over an hour, or
+//! every transfer above some amount.
+//!
+//! By default the index is kept in memory, retaining the transactions of a window of recent blocks
+//! like the account history. With the `sqlite` feature, the index can instead be stored in a SQLite
+//! database, which retains every transaction, survives restarts and evaluates the filters in SQL.
+//! Both backends return the same records for the same query.
+
+use crate::error::RollupError;
+use crate::history::DEFAULT_HISTORY_WINDOW;
+use crate::state::{Amount, State};
+use async_std::sync::{Arc, RwLock};
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::BTreeMap;
+use strum_macros::{AsRefStr, EnumString};
+
+#[cfg(feature = "sqlite")]
+use snafu::ResultExt;
+#[cfg(feature = "sqlite")]
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions},
+    QueryBuilder, Sqlite,
+};
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+
+/// Number of records returned by a query which does not set a limit.
+pub const DEFAULT_QUERY_LIMIT: usize = 100;
+
+/// Largest number of records a single query may return.
+pub const MAX_QUERY_LIMIT: usize = 1000;
+
+#[derive(Debug, Snafu)]
+pub enum QueryError {
+    #[snafu(display("Minimum amount {min} is greater than maximum amount {max}."))]
+    InvalidAmountRange { min: Amount, max: Amount },
+    #[snafu(display("Time {from} is after time {to}."))]
+    InvalidTimeRange { from: u64, to: u64 },
+    #[snafu(display("Limit {limit} exceeds the maximum of {MAX_QUERY_LIMIT}."))]
+    LimitTooLarge { limit: usize },
+    #[cfg(feature = "sqlite")]
+    #[snafu(display("Malformed transaction record: {source}"))]
+    Json { source: serde_json::Error },
+    #[cfg(feature = "sqlite")]
+    #[snafu(display("Transaction database error: {source}"))]
+    Database { source: sqlx::Error },
+}
+
+impl QueryError {
+    /// Whether the error is caused by the query, rather than by the index.
+    pub fn is_invalid_query(&self) -> bool {
+        matches!(
+            self,
+            Self::InvalidAmountRange { .. }
+                | Self::InvalidTimeRange { .. }
+                | Self::LimitTooLarge { .. }
+        )
+    }
+}
+
+/// Whether an executed transaction succeeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, AsRefStr, EnumString, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum TransactionStatus {
+    Succeeded,
+    Failed,
+}
+
+/// An executed transaction, as recorded by the [`TransactionIndex`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub hash: H256,
+    pub block_height: u64,
+    /// Position of the transaction among the rollup transactions in its block.
+    pub index: usize,
+    /// Unix timestamp (in seconds) of the block.
+    pub timestamp: u64,
+    /// The sender of a transfer, or `None` if the transaction is not a transfer or its signature
+    /// is invalid.
+    pub from: Option<Address>,
+    /// The recipient of a transfer, or `None` if the transaction is not a transfer.
+    pub to: Option<Address>,
+    /// The amount of a transfer, or `None` if the transaction is not a transfer.
+    pub amount: Option<Amount>,
+    pub fee: Amount,
+    pub result: Result<(), RollupError>,
+}
+
+impl TransactionRecord {
+    /// Records of the transactions in the most recent block executed by `state`.
+    pub fn for_block(state: &State) -> Vec<Self> {
+        state
+            .block_results()
+            .iter()
+            .zip(state.block_transfers())
+            .enumerate()
+            .map(|(index, ((hash, result), transfer))| Self {
+                hash: *hash,
+                block_height: state.block_height(),
+                index,
+                timestamp: state.block_timestamp(),
+                from: transfer.and_then(|transfer| transfer.from),
+                to: transfer.map(|transfer| transfer.to),
+                amount: transfer.map(|transfer| transfer.amount),
+                fee: transfer.map_or(0, |transfer| transfer.fee),
+                result: result.clone(),
+            })
+            .collect()
+    }
+
+    pub fn status(&self) -> TransactionStatus {
+        match self.result {
+            Ok(()) => TransactionStatus::Succeeded,
+            Err(_) => TransactionStatus::Failed,
+        }
+    }
+}
+
+/// A filter on executed transactions. Every condition which is set must hold.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionQuery {
+    /// Transactions sent by or to this address.
+    pub address: Option<Address>,
+    /// Transfers of at least this amount.
+    pub min_amount: Option<Amount>,
+    /// Transfers of at most this amount.
+    pub max_amount: Option<Amount>,
+    /// Transactions in blocks with a timestamp at or after this Unix time (in seconds).
+    pub from_time: Option<u64>,
+    /// Transactions in blocks with a timestamp at or before this Unix time (in seconds).
+    pub to_time: Option<u64>,
+    pub status: Option<TransactionStatus>,
+    /// Maximum number of records to return.
+    pub limit: usize,
+}
+
+impl Default for TransactionQuery {
+    fn default() -> Self {
+        Self {
+            address: None,
+            min_amount: None,
+            max_amount: None,
+            from_time: None,
+            to_time: None,
+            status: None,
+            limit: DEFAULT_QUERY_LIMIT,
+        }
+    }
+}
+
+impl TransactionQuery {
+    /// Check that the ranges in the query are well formed and the limit is allowed.
+    pub fn validate(&self) -> Result<(), QueryError> {
+        if let (Some(min), Some(max)) = (self.min_amount, self.max_amount) {
+            if min > max {
+                return Err(QueryError::InvalidAmountRange { min, max });
+            }
+        }
+        if let (Some(from), Some(to)) = (self.from_time, self.to_time) {
+            if from > to {
+                return Err(QueryError::InvalidTimeRange { from, to });
+            }
+        }
+        if self.limit > MAX_QUERY_LIMIT {
+            return Err(QueryError::LimitTooLarge { limit: self.limit });
+        }
+        Ok(())
+    }
+
+    /// Whether `record` satisfies every condition of the query.
+    ///
+    /// Transactions which are not transfers have no amount, so they never satisfy an amount
+    /// condition.
+    pub fn matches(&self, record: &TransactionRecord) -> bool {
+        self.address.map_or(true, |address| {
+            record.from == Some(address) || record.to == Some(address)
+        }) && self.min_amount.map_or(true, |min| {
+            record.amount.is_some_and(|amount| amount >= min)
+        }) && self.max_amount.map_or(true, |max| {
+            record.amount.is_some_and(|amount| amount <= max)
+        }) && self.from_time.map_or(true, |from| record.timestamp >= from)
+            && self.to_time.map_or(true, |to| record.timestamp <= to)
+            && self.status.map_or(true, |status| record.status() == status)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Backend {
+    Memory {
+        window: usize,
+        blocks: Arc<RwLock<BTreeMap<u64, Vec<TransactionRecord>>>>,
+    },
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqlitePool),
+}
+
+/// Index of executed transactions, answering [`TransactionQuery`]s.
+#[derive(Clone, Debug)]
+pub struct TransactionIndex {
+    backend: Backend,
+}
+
+impl Default for TransactionIndex {
+    fn default() -> Self {
+        Self::in_memory(DEFAULT_HISTORY_WINDOW)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+const SQLITE_SCHEMA: [&str; 4] = [
+    "CREATE TABLE IF NOT EXISTS transactions (
+        block_height INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        sender TEXT,
+        destination TEXT,
+        amount INTEGER,
+        status TEXT NOT NULL,
+        record TEXT NOT NULL,
+        PRIMARY KEY (block_height, position)
+    )",
+    "CREATE INDEX IF NOT EXISTS transactions_sender ON transactions (sender)",
+    "CREATE INDEX IF NOT EXISTS transactions_destination ON transactions (destination)",
+    "CREATE INDEX IF NOT EXISTS transactions_timestamp ON transactions (timestamp)",
+];
+
+/// Convert `value` to a SQLite integer which sorts in the same order.
+///
+/// SQLite integers are signed, so the sign bit is flipped: 0 is stored as `i64::MIN` and
+/// `u64::MAX` as `i64::MAX`. Every value is stored exactly, and comparisons in SQL agree with
+/// comparisons of the original values, so range filters work across the whole `u64` range.
+#[cfg(feature = "sqlite")]
+fn sql_integer(value: u64) -> i64 {
+    (value ^ (1 << 63)) as i64
+}
+
+impl TransactionIndex {
+    /// An index in memory, retaining the transactions of the last `window` executed blocks.
+    pub fn in_memory(window: usize) -> Self {
+        Self {
+            backend: Backend::Memory {
+                window: window.max(1),
+                blocks: Default::default(),
+            },
+        }
+    }
+
+    /// An index in the SQLite database at `path`, which is created if it does not exist.
+    ///
+    /// The database retains every transaction. Blocks which are executed again after a restart
+    /// replace their earlier records.
+    #[cfg(feature = "sqlite")]
+    pub async fn sqlite(path: &Path) -> Result<Self, QueryError> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .context(DatabaseSnafu)?;
+        for statement in SQLITE_SCHEMA {
+            sqlx::query(statement)
+                .execute(&pool)
+                .await
+                .context(DatabaseSnafu)?;
+        }
+        Ok(Self {
+            backend: Backend::Sqlite(pool),
+        })
+    }
+
+    /// Index the transactions of the block at `block_height`.
+    pub async fn insert_block(
+        &self,
+        block_height: u64,
+        records: Vec<TransactionRecord>,
+    ) -> Result<(), QueryError> {
+        match &self.backend {
+            Backend::Memory { window, blocks } => {
+                let mut blocks = blocks.write().await;
+                blocks.insert(block_height, records);
+                while blocks.len() > *window {
+                    blocks.pop_first();
+                }
+                Ok(())
+            }
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(pool) => {
+                let mut tx = pool.begin().await.context(DatabaseSnafu)?;
+                for record in &records {
+                    sqlx::query(
+                        "INSERT OR REPLACE INTO transactions
+                            (block_height, position, timestamp, sender, destination, amount,
+                             status, record)
+                         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(sql_integer(block_height))
+                    .bind(sql_integer(record.index as u64))
+                    .bind(sql_integer(record.timestamp))
+                    .bind(record.from.map(|address| format!("{address:?}")))
+                    .bind(record.to.map(|address| format!("{address:?}")))
+                    .bind(record.amount.map(sql_integer))
+                    .bind(record.status().as_ref().to_string())
+                    .bind(serde_json::to_string(record).context(JsonSnafu)?)
+                    .execute(&mut *tx)
+                    .await
+                    .context(DatabaseSnafu)?;
+                }
+                tx.commit().await.context(DatabaseSnafu)
+            }
+        }
+    }
+
+    /// The transactions matching `query`, most recent first.
+    pub async fn query(
+        &self,
+        query: &TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, QueryError> {
+        query.validate()?;
+        match &self.backend {
+            Backend::Memory { blocks, .. } => Ok(blocks
+                .read()
+                .await
+                .values()
+                .rev()
+                .flat_map(|records| records.iter().rev())
+                .filter(|record| query.matches(record))
+                .take(query.limit)
+                .cloned()
+                .collect()),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(pool) => {
+                let mut sql =
+                    QueryBuilder::<Sqlite>::new("SELECT record FROM transactions WHERE 1 = 1");
+                if let Some(address) = query.address {
+                    let address = format!("{address:?}");
+                    sql.push(" AND (sender = ")
+                        .push_bind(address.clone())
+                        .push(" OR destination = ")
+                        .push_bind(address)
+                        .push(")");
+                }
+                if let Some(min) = query.min_amount {
+                    sql.push(" AND amount >= ").push_bind(sql_integer(min));
+                }
+                if let Some(max) = query.max_amount {
+                    sql.push(" AND amount <= ").push_bind(sql_integer(max));
+                }
+                if let Some(from) = query.from_time {
+                    sql.push(" AND timestamp >= ").push_bind(sql_integer(from));
+                }
+                if let Some(to) = query.to_time {
+                    sql.push(" AND timestamp <= ").push_bind(sql_integer(to));
+                }
+                if let Some(status) = query.status {
+                    sql.push(" AND status = ")
+                        .push_bind(status.as_ref().to_string());
+                }
+                // The limit is not compared with stored values, so it is bound as is.
+                sql.push(" ORDER BY block_height DESC, position DESC LIMIT ")
+                    .push_bind(query.limit as i64);
+                let rows: Vec<String> = sql
+                    .build_query_scalar()
+                    .fetch_all(pool)
+                    .await
+                    .context(DatabaseSnafu)?;
+                rows.iter()
+                    .map(|row| serde_json::from_str(row).context(JsonSnafu))
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mock_block;
+    use crate::machine::RollupStateMachine;
+    use crate::transaction::{SignedTransaction, Transaction};
+    use crate::RollupVM;
+    use committable::Committable;
+    use espresso_types::NamespaceId;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[async_std::test]
+    async fn test_records_for_block() {
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rand::thread_rng());
+        let bob = Address::random();
+        let transfer = SignedTransaction::new(
+            Transaction {
+                amount: 10,
+                destination: bob,
+                nonce: 1,
+                fee: 1,
+                ..Default::default()
+            },
+            &alice,
+        )
+        .await;
+        let payloads = vec![transfer.encode(), b"not a transaction".to_vec()];
+        let block = mock_block(vm.into(), &[(vm.into(), payloads)]).await;
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        state.execute_transactions(
+            &block.header,
+            block.namespace_proof.as_ref().unwrap(),
+            block.header.commit(),
+        );
+
+        let records = TransactionRecord::for_block(&state);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].hash, transfer.hash());
+        assert_eq!(records[0].from, Some(alice.address()));
+        assert_eq!(records[0].to, Some(bob));
+        assert_eq!(records[0].amount, Some(10));
+        assert_eq!(records[0].fee, 1);
+        assert_eq!(records[0].status(), TransactionStatus::Succeeded);
+        // A payload which does not decode is recorded, but is not a transfer.
+        assert_eq!((records[1].from, records[1].amount), (None, None));
+        assert_eq!(records[1].status(), TransactionStatus::Failed);
+    }
+
+    fn record(block_height: u64, from: Address, to: Address, amount: Amount) -> TransactionRecord {
+        TransactionRecord {
+            hash: H256::random(),
+            block_height,
+            index: 0,
+            timestamp: block_height * 10,
+            from: Some(from),
+            to: Some(to),
+            amount: Some(amount),
+            fee: 0,
+            result: Ok(()),
+        }
+    }
+
+    /// Check the filters of `index`, which must be empty.
+    async fn check_queries(index: &TransactionIndex) {
+        let alice = Address::random();
+        let bob = Address::random();
+        let carol = Address::random();
+        let failed = TransactionRecord {
+            index: 1,
+            result: Err(RollupError::InsufficientBalance { address: bob }),
+            ..record(2, bob, carol, 500)
+        };
+        let blocks = [
+            vec![record(1, alice, bob, 10)],
+            vec![record(2, alice, carol, 50), failed.clone()],
+            vec![record(3, carol, alice, 5)],
+        ];
+        for records in &blocks {
+            index
+                .insert_block(records[0].block_height, records.clone())
+                .await
+                .unwrap();
+        }
+        let positions = |records: Vec<TransactionRecord>| {
+            records
+                .into_iter()
+                .map(|record| (record.block_height, record.index))
+                .collect::<Vec<_>>()
+        };
+
+        // Every transaction, most recent first.
+        let all = index.query(&Default::default()).await.unwrap();
+        assert_eq!(positions(all), [(3, 0), (2, 1), (2, 0), (1, 0)]);
+
+        let query = TransactionQuery {
+            address: Some(alice),
+            ..Default::default()
+        };
+        assert_eq!(
+            positions(index.query(&query).await.unwrap()),
+            [(3, 0), (2, 0), (1, 0)]
+        );
+
+        let query = TransactionQuery {
+            min_amount: Some(10),
+            max_amount: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(
+            positions(index.query(&query).await.unwrap()),
+            [(2, 0), (1, 0)]
+        );
+
+        let query = TransactionQuery {
+            from_time: Some(20),
+            to_time: Some(20),
+            status: Some(TransactionStatus::Failed),
+            ..Default::default()
+        };
+        assert_eq!(index.query(&query).await.unwrap(), [failed]);
+
+        let query = TransactionQuery {
+            limit: 1,
+            ..Default::default()
+        };
+        assert_eq!(positions(index.query(&query).await.unwrap()), [(3, 0)]);
+
+        let query = TransactionQuery {
+            min_amount: Some(10),
+            max_amount: Some(1),
+            ..Default::default()
+        };
+        assert!(index.query(&query).await.unwrap_err().is_invalid_query());
+
+        // Amounts and times beyond the range of a signed integer are told apart.
+        let large = [
+            record(4, alice, bob, i64::MAX as Amount),
+            TransactionRecord {
+                index: 1,
+                timestamp: u64::MAX,
+                ..record(4, alice, bob, Amount::MAX)
+            },
+        ];
+        index.insert_block(4, large.to_vec()).await.unwrap();
+        let query = TransactionQuery {
+            min_amount: Some(i64::MAX as Amount + 1),
+            ..Default::default()
+        };
+        assert_eq!(positions(index.query(&query).await.unwrap()), [(4, 1)]);
+        let query = TransactionQuery {
+            min_amount: Some(100),
+            max_amount: Some(i64::MAX as Amount),
+            ..Default::default()
+        };
+        assert_eq!(
+            positions(index.query(&query).await.unwrap()),
+            [(4, 0), (2, 1)]
+        );
+        let query = TransactionQuery {
+            from_time: Some(i64::MAX as u64 + 1),
+            ..Default::default()
+        };
+        assert_eq!(positions(index.query(&query).await.unwrap()), [(4, 1)]);
+    }
+
+    #[async_std::test]
+    async fn test_in_memory_queries() {
+        check_queries(&TransactionIndex::default()).await;
+
+        // Only a window of recent blocks is retained.
+        let index = TransactionIndex::in_memory(1);
+        let (alice, bob) = (Address::random(), Address::random());
+        index
+            .insert_block(1, vec![record(1, alice, bob, 1)])
+            .await
+            .unwrap();
+        index
+            .insert_block(2, vec![record(2, alice, bob, 2)])
+            .await
+            .unwrap();
+        let records = index.query(&Default::default()).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].block_height, 2);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[async_std::test]
+    async fn test_sqlite_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transactions.db");
+        check_queries(&TransactionIndex::sqlite(&path).await.unwrap()).await;
+
+        // The records survive reopening the database.
+        let index = TransactionIndex::sqlite(&path).await.unwrap();
+        assert_eq!(index.query(&Default::default()).await.unwrap().len(), 6);
+    }
+}
@@ -0,0 +1,299 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+use crate::chain::ChainId;
+use crate::error::RollupError;
+use crate::random::DemoRng;
+use crate::state::State;
+use crate::stats::{ExecutionMetrics, TransactionTimings};
+use async_std::sync::{Arc, RwLock};
+use committable::{Commitment, Committable};
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// The outcome of executing a rollup transaction.
+///
+/// Receipts are anchored to the state commitment history: `prev_state_commitment` and
+/// `state_commitment` are the commitments before and after the block which included the
+/// transaction. A client can check that the commitments chain together across receipts and match
+/// the commitments verified by the rollup contract on the L1, rather than trusting the API's report
+/// of whether the transaction executed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub hash: H256,
+    /// Height of the HotShot block in which the transaction executed.
+    pub block_height: u64,
+    /// HotShot view in which the block was decided, if known.
+    #[serde(default)]
+    pub view_number: Option<u64>,
+    /// Unix timestamp (in seconds) of the block.
+    #[serde(default)]
+    pub block_timestamp: u64,
+    /// Position of the transaction among the rollup transactions in its block.
+    pub index: usize,
+    pub result: Result<(), RollupError>,
+    pub prev_state_commitment: Commitment<State>,
+    pub state_commitment: Commitment<State>,
+    /// When the transaction reached each stage of its path to the L1, as far as this node observed.
+    #[serde(default)]
+    pub timings: TransactionTimings,
+    /// Resources consumed executing the transaction on this node.
+    #[serde(default)]
+    pub metrics: ExecutionMetrics,
+    /// The chain the transaction executed on, if the node is bound to one.
+    #[serde(default)]
+    pub chain_id: Option<ChainId>,
+}
+
+impl Receipt {
+    /// Receipts for the transactions in the most recent block executed by `state`.
+    pub fn for_block(state: &State) -> Vec<Self> {
+        let state_commitment = state.commit();
+        let timings = TransactionTimings {
+            finalized_ms: Some(state.block_timestamp() * 1000),
+            ..Default::default()
+        };
+        let Some(prev_state_commitment) = state.prev_state_commitment() else {
+            return vec![];
+        };
+        state
+            .block_results()
+            .iter()
+            .zip(state.block_metrics())
+            .enumerate()
+            .map(|(index, ((hash, result), metrics))| Self {
+                hash: *hash,
+                block_height: state.block_height(),
+                view_number: state.view_number(),
+                block_timestamp: state.block_timestamp(),
+                index,
+                result: result.clone(),
+                prev_state_commitment,
+                state_commitment,
+                timings,
+                metrics: *metrics,
+                chain_id: state.chain_id(),
+            })
+            .collect()
+    }
+
+    /// Whether this receipt's block executed on top of the state with commitment `commitment`.
+    pub fn extends(&self, commitment: Commitment<State>) -> bool {
+        self.prev_state_commitment == commitment
+    }
+}
+
+/// Where a transaction is on its way to execution, served by `rollup/tx/:hash`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ExecutionStatus {
+    /// Submitted through this node, but not yet executed.
+    Pending { submitted_ms: Option<u64> },
+    /// Executed successfully in the block at `block_height`.
+    Executed { block_height: u64 },
+    /// Sequenced in the block at `block_height`, but rejected by the rollup.
+    Rejected {
+        block_height: u64,
+        error: RollupError,
+    },
+}
+
+impl ExecutionStatus {
+    /// The status of a transaction with `receipt`, if it has been executed, and `timings`, if it
+    /// was submitted through this node, or `None` if neither is known.
+    pub fn new(receipt: Option<&Receipt>, timings: Option<&TransactionTimings>) -> Option<Self> {
+        match (receipt, timings) {
+            (Some(receipt), _) => Some(match &receipt.result {
+                Ok(()) => Self::Executed {
+                    block_height: receipt.block_height,
+                },
+                Err(error) => Self::Rejected {
+                    block_height: receipt.block_height,
+                    error: error.clone(),
+                },
+            }),
+            (None, Some(timings)) => Some(Self::Pending {
+                submitted_ms: timings.submitted_ms,
+            }),
+            (None, None) => None,
+        }
+    }
+}
+
+/// The most recent blocks of a [`ReceiptIndex`] and the receipts of their transactions, exported
+/// with [`ReceiptIndex::tail`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptTail {
+    /// Height and transaction hashes of each block, oldest first.
+    pub blocks: Vec<(u64, Vec<H256>)>,
+    pub receipts: Vec<Receipt>,
+}
+
+/// Index of the receipts of executed transactions by transaction hash.
+///
+/// The index also records, for each executed block, the hashes of the transactions it executed in
+/// order, including those of replays whose receipts are not indexed.
+#[derive(Clone, Debug, Default)]
+pub struct ReceiptIndex {
+    receipts: Arc<RwLock<HashMap<H256, Receipt>>>,
+    blocks: Arc<RwLock<BTreeMap<u64, Vec<H256>>>>,
+}
+
+impl ReceiptIndex {
+    /// Index the receipts of the block at `block_height`.
+    ///
+    /// A replayed transaction has the same hash as the original, so the receipt of a successful
+    /// execution is never replaced by that of a rejected replay.
+    pub async fn insert_block(&self, block_height: u64, receipts: Vec<Receipt>) {
+        self.blocks.write().await.insert(
+            block_height,
+            receipts.iter().map(|receipt| receipt.hash).collect(),
+        );
+        self.insert_receipts(receipts).await;
+    }
+
+    async fn insert_receipts(&self, receipts: Vec<Receipt>) {
+        let mut index = self.receipts.write().await;
+        for receipt in receipts {
+            let succeeded = index
+                .get(&receipt.hash)
+                .is_some_and(|existing| existing.result.is_ok());
+            if !succeeded {
+                index.insert(receipt.hash, receipt);
+            }
+        }
+    }
+
+    /// Export the `num_blocks` most recently executed blocks, with the receipts of their
+    /// transactions.
+    pub async fn tail(&self, num_blocks: usize) -> ReceiptTail {
+        let blocks: Vec<_> = {
+            let blocks = self.blocks.read().await;
+            let skip = blocks.len().saturating_sub(num_blocks);
+            blocks
+                .iter()
+                .skip(skip)
+                .map(|(height, hashes)| (*height, hashes.clone()))
+                .collect()
+        };
+        let index = self.receipts.read().await;
+        let hashes: BTreeSet<_> = blocks.iter().flat_map(|(_, hashes)| hashes).collect();
+        let receipts = hashes
+            .into_iter()
+            .filter_map(|hash| index.get(hash).cloned())
+            .collect();
+        ReceiptTail { blocks, receipts }
+    }
+
+    /// Index the blocks and receipts exported by [`tail`](Self::tail).
+    pub async fn restore(&self, tail: ReceiptTail) {
+        self.blocks.write().await.extend(tail.blocks);
+        self.insert_receipts(tail.receipts).await;
+    }
+
+    pub async fn get(&self, hash: &H256) -> Option<Receipt> {
+        self.receipts.read().await.get(hash).cloned()
+    }
+
+    /// Hashes of the transactions executed in the block at `height`, in execution order, or `None`
+    /// if the block has not been executed.
+    pub async fn block(&self, height: u64) -> Option<Vec<H256>> {
+        self.blocks.read().await.get(&height).cloned()
+    }
+
+    /// The height of an executed block chosen uniformly at random, or `None` if no blocks have
+    /// been executed.
+    pub async fn random_block(&self, rng: &DemoRng) -> Option<u64> {
+        let blocks = self.blocks.read().await;
+        if blocks.is_empty() {
+            return None;
+        }
+        let index = rng.random::<u64>() % blocks.len() as u64;
+        blocks.keys().nth(index as usize).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{SignedTransaction, Transaction};
+    use crate::RollupVM;
+    use espresso_types::NamespaceId;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[async_std::test]
+    async fn test_replay_does_not_replace_receipt() {
+        let mut rng = rand::thread_rng();
+        let wallet = LocalWallet::new(&mut rng);
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let state = State::from_initial_balances([(wallet.address(), 100)], vm);
+        let transaction = SignedTransaction::new(
+            Transaction {
+                amount: 1,
+                destination: wallet.address(),
+                nonce: 1,
+                ..Default::default()
+            },
+            &wallet,
+        )
+        .await;
+        let receipt = Receipt {
+            hash: transaction.hash(),
+            block_height: 1,
+            view_number: Some(1),
+            block_timestamp: 0,
+            index: 0,
+            result: Ok(()),
+            prev_state_commitment: state.commit(),
+            state_commitment: state.commit(),
+            timings: Default::default(),
+            metrics: Default::default(),
+            chain_id: None,
+        };
+        let replay = Receipt {
+            block_height: 2,
+            result: Err(RollupError::InvalidNonce {
+                address: wallet.address(),
+                expected: 2,
+                actual: 1,
+            }),
+            ..receipt.clone()
+        };
+
+        let index = ReceiptIndex::default();
+        index.insert_block(1, vec![receipt.clone()]).await;
+        index.insert_block(2, vec![replay.clone()]).await;
+        assert_eq!(index.get(&transaction.hash()).await, Some(receipt.clone()));
+        // The replay is still recorded as executed in its block.
+        assert_eq!(index.block(2).await, Some(vec![transaction.hash()]));
+        assert_eq!(index.block(3).await, None);
+        assert!(receipt.extends(state.commit()));
+
+        let timings = TransactionTimings {
+            submitted_ms: Some(1000),
+            ..Default::default()
+        };
+        assert_eq!(
+            ExecutionStatus::new(None, Some(&timings)),
+            Some(ExecutionStatus::Pending {
+                submitted_ms: Some(1000)
+            })
+        );
+        assert_eq!(
+            ExecutionStatus::new(Some(&receipt), Some(&timings)),
+            Some(ExecutionStatus::Executed { block_height: 1 })
+        );
+        assert!(matches!(
+            ExecutionStatus::new(Some(&replay), None),
+            Some(ExecutionStatus::Rejected {
+                block_height: 2,
+                error: RollupError::InvalidNonce { .. },
+            })
+        ));
+        assert_eq!(ExecutionStatus::new(None, None), None);
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Recovery of the executor's state from the rollup contract.
+//!
+//! An executor which restarts without a warm start or a checkpoint begins again from genesis,
+//! while the rollup contract may have verified many blocks since. With `--recover-from-l1`, the
+//! executor first replays every block the contract has verified, re-fetching the headers and
+//! namespace proofs from the query service, and checks that its state commitment then matches the
+//! one in the contract's latest `StateUpdate` event before it resumes live processing. The replayed
+//! blocks are not proven again, since the contract has already verified them.
+
+use crate::backfill::{FetchError, HeaderFetcher, DEFAULT_MAX_ATTEMPTS};
+use crate::data_source::SequencerDataSource;
+use crate::deposit::DepositQueue;
+use crate::executor::{execute_headers, ExecutorContext, ExecutorOptions};
+use crate::l1::{L1Client, L1Error, StateUpdate};
+use crate::prover::PendingProofs;
+use crate::state::State;
+use crate::warm_start::Resume;
+use async_std::sync::RwLock;
+use committable::Committable;
+use ethers::types::U256;
+use sequencer_utils::commitment_to_u256;
+use snafu::{ResultExt, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum RecoveryError {
+    #[snafu(display("Error reading rollup contract state: {source}"))]
+    Contract { source: L1Error },
+    #[snafu(display(
+        "Rollup contract has verified {num_verified_blocks} blocks, but its latest StateUpdate \
+         event records {recorded:?}"
+    ))]
+    MissingStateUpdate {
+        num_verified_blocks: u64,
+        recorded: Option<u64>,
+    },
+    #[snafu(display("Error fetching headers to replay: {source}"))]
+    Fetch { source: FetchError },
+    #[snafu(display(
+        "After replaying {num_verified_blocks} blocks the state commitment is {local:#x}, but the \
+         rollup contract records {contract:#x}"
+    ))]
+    Diverged {
+        num_verified_blocks: u64,
+        local: U256,
+        contract: U256,
+    },
+}
+
+/// The latest state verified by the rollup contract, or `None` if it has not verified any blocks.
+pub async fn verified_state(rollup: &dyn L1Client) -> Result<Option<StateUpdate>, RecoveryError> {
+    let num_verified_blocks = rollup.num_verified_blocks().await.context(ContractSnafu)?;
+    if num_verified_blocks == 0 {
+        return Ok(None);
+    }
+    match rollup.last_state_update().await.context(ContractSnafu)? {
+        Some(update) if update.num_verified_blocks == num_verified_blocks => Ok(Some(update)),
+        update => Err(RecoveryError::MissingStateUpdate {
+            num_verified_blocks,
+            recorded: update.map(|update| update.num_verified_blocks),
+        }),
+    }
+}
+
+/// Check that `state`, after executing every block verified by `update`, has the verified state
+/// commitment.
+pub fn check_recovered(state: &State, update: &StateUpdate) -> Result<(), RecoveryError> {
+    let local = commitment_to_u256(state.commit());
+    let contract = U256::from_big_endian(&update.state_commitment);
+    if local != contract {
+        return Err(RecoveryError::Diverged {
+            num_verified_blocks: update.num_verified_blocks,
+            local,
+            contract,
+        });
+    }
+    Ok(())
+}
+
+/// Replay the blocks verified by `rollup` which `state` has not yet executed, resuming at
+/// `resume`.
+///
+/// On success, `resume` points at the first block the contract has not verified, with no pending
+/// proofs. A state which is already ahead of the contract is left as it is, since the commitments of
+/// earlier blocks are not recorded by the contract.
+///
+/// The replayed blocks credit the deposits collected in `deposits`, as they did when they were
+/// first executed.
+pub(crate) async fn recover_from_l1(
+    opt: &ExecutorOptions,
+    data_source: &dyn SequencerDataSource,
+    rollup: &dyn L1Client,
+    state: &RwLock<State>,
+    resume: &mut Resume,
+    deposits: Option<&DepositQueue>,
+) -> Result<(), RecoveryError> {
+    let Some(update) = verified_state(rollup).await? else {
+        tracing::info!("Rollup contract has not verified any blocks, nothing to recover");
+        return Ok(());
+    };
+    let target = update.num_verified_blocks;
+    if resume.next_block > target {
+        tracing::info!(
+            "State is ahead of the rollup contract, which has verified {target} blocks, not \
+             recovering"
+        );
+        return Ok(());
+    }
+    if resume.next_block < target {
+        tracing::info!(
+            "Replaying blocks {}-{target} verified by the rollup contract",
+            resume.next_block
+        );
+    }
+
+    let fetcher = HeaderFetcher::new(data_source)
+        .with_page_size(opt.header_page_size)
+        .with_retries(DEFAULT_MAX_ATTEMPTS, opt.clock.as_ref());
+    // Replayed blocks are not checkpointed, and the canary starts after recovery.
+    let context = ExecutorContext {
+        checkpoints: None,
+        canary: None,
+        deposits,
+        ..ExecutorContext::new(opt)
+    };
+    while resume.next_block < target {
+        let until = target.min(resume.next_block + opt.header_page_size.max(1));
+        let headers = fetcher
+            .fetch_page(resume.next_block, until)
+            .await
+            .context(FetchSnafu)?;
+        // Proofs of the replayed blocks are discarded, so the replay is not checkpointed either,
+        // or a checkpoint could hand them to the outbox after a restart.
+        let mut replayed = PendingProofs::default();
+        execute_headers(data_source, state, headers, &mut replayed, &context).await;
+        resume.next_block = until;
+    }
+
+    let state = state.read().await;
+    check_recovered(&state, &update)?;
+    // Every block before the target has been verified, so none of the pending proofs, replayed or
+    // restored, are needed.
+    resume.pending_proofs = PendingProofs::default();
+    if let Some(checkpoints) = opt.checkpoints.as_ref().filter(|_| !opt.dry_run) {
+        checkpoints.checkpoint(&state, resume.next_block, &resume.pending_proofs);
+    }
+    tracing::info!(
+        "Recovered the state verified by the rollup contract after {target} blocks, {}",
+        state.commit()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::l1::BatchProofInput;
+    use crate::prover::ProofShape;
+    use crate::RollupVM;
+    use espresso_types::NamespaceId;
+    use ethers::types::{Address, H256};
+    use futures::future::{BoxFuture, FutureExt};
+
+    /// A rollup contract in a fixed state.
+    #[derive(Debug)]
+    struct MockRollup {
+        num_verified_blocks: u64,
+        last_update: Option<StateUpdate>,
+    }
+
+    impl L1Client for MockRollup {
+        fn next_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            async { Ok(0) }.boxed()
+        }
+
+        fn confirmed_nonce(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            async { Ok(0) }.boxed()
+        }
+
+        fn send_verify_blocks(
+            &self,
+            _count: u64,
+            _proof: BatchProofInput,
+            _shape: ProofShape,
+            _nonce: u64,
+        ) -> BoxFuture<'_, Result<H256, L1Error>> {
+            async { Ok(H256::random()) }.boxed()
+        }
+
+        fn transaction_status(&self, _hash: H256) -> BoxFuture<'_, Result<Option<bool>, L1Error>> {
+            async { Ok(None) }.boxed()
+        }
+
+        fn state_commitment(&self) -> BoxFuture<'_, Result<[u8; 32], L1Error>> {
+            let state = self
+                .last_update
+                .map_or([0; 32], |update| update.state_commitment);
+            async move { Ok(state) }.boxed()
+        }
+
+        fn num_verified_blocks(&self) -> BoxFuture<'_, Result<u64, L1Error>> {
+            let count = self.num_verified_blocks;
+            async move { Ok(count) }.boxed()
+        }
+
+        fn last_state_update(&self) -> BoxFuture<'_, Result<Option<StateUpdate>, L1Error>> {
+            let update = self.last_update;
+            async move { Ok(update) }.boxed()
+        }
+    }
+
+    #[async_std::test]
+    async fn test_verified_state() {
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let state = State::from_initial_balances([(Address::random(), 100)], vm);
+        let mut commitment = [0; 32];
+        commitment_to_u256(state.commit()).to_big_endian(&mut commitment);
+        let update = StateUpdate {
+            num_verified_blocks: 5,
+            state_commitment: commitment,
+        };
+
+        let fresh = MockRollup {
+            num_verified_blocks: 0,
+            last_update: None,
+        };
+        assert_eq!(verified_state(&fresh).await.unwrap(), None);
+
+        let rollup = MockRollup {
+            num_verified_blocks: 5,
+            last_update: Some(update),
+        };
+        assert_eq!(verified_state(&rollup).await.unwrap(), Some(update));
+        check_recovered(&state, &update).unwrap();
+
+        // The contract's counter and its events must agree on the verified blocks.
+        let lagging = MockRollup {
+            num_verified_blocks: 7,
+            last_update: Some(update),
+        };
+        assert!(matches!(
+            verified_state(&lagging).await,
+            Err(RecoveryError::MissingStateUpdate {
+                num_verified_blocks: 7,
+                recorded: Some(5),
+            })
+        ));
+
+        // A state which does not reach the verified commitment has diverged.
+        let other = State::from_initial_balances([(Address::random(), 100)], vm);
+        assert!(matches!(
+            check_recovered(&other, &update),
+            Err(RecoveryError::Diverged {
+                num_verified_blocks: 5,
+                ..
+            })
+        ));
+    }
+}
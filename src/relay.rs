@@ -0,0 +1,193 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Relay of submitted transactions between replicas of the rollup API.
+//!
+//! In a deployment with several API replicas, usually only one of them, the primary, holds the
+//! submission operator's key. A replica configured with a primary forwards every transaction
+//! submitted to it to the primary's `submit` route, so that the transaction takes the same
+//! submission path whichever replica received it. If the primary cannot be reached, the replica
+//! submits the transaction straight to the sequencer instead, where it is an untrusted submission
+//! if an operator is configured. A transaction the primary rejects is rejected by the replica too.
+//!
+//! Requests from one replica to another carry the [`RELAYED_HEADER`] header, and a node never
+//! relays a request which carries it, so replicas which relay to each other cannot forward a
+//! transaction or a receipt lookup in a loop.
+//!
+//! Every replica executes the rollup itself and indexes the receipts of the transactions it
+//! executes. Until a replica has caught up with the block that executed a transaction, its
+//! `receipt` route asks the primary and then its relay peers for the receipt, so that the status of
+//! a transaction is visible from every replica as soon as any of them has executed it.
+
+use crate::http::HttpClientPool;
+use crate::receipt::Receipt;
+use crate::transaction::SignedTransaction;
+use async_std::sync::{Arc, RwLock};
+use committable::Commitment;
+use espresso_types::Transaction;
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use surf_disco::{error::ClientError, Url};
+use tide_disco::Error as _;
+
+/// Header marking a request made by another replica.
+pub const RELAYED_HEADER: &str = "X-Rollup-Relayed";
+
+/// Counters of the transactions relayed by this node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayStats {
+    /// Submissions forwarded to the primary.
+    pub forwarded: u64,
+    /// Submissions sent straight to the sequencer because the primary could not be reached.
+    pub fallbacks: u64,
+    /// Submissions received from other replicas.
+    pub received: u64,
+    /// Receipts fetched from other replicas because this node had not yet indexed them.
+    pub remote_receipts: u64,
+}
+
+/// Forwards submissions to the primary replica and looks up receipts from other replicas.
+///
+/// A relay without a primary or peers does nothing, which is the behavior of a single node.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionRelay {
+    primary: Option<Url>,
+    peers: Vec<Url>,
+    http: HttpClientPool,
+    stats: Arc<RwLock<RelayStats>>,
+}
+
+impl TransactionRelay {
+    pub fn new(http: HttpClientPool) -> Self {
+        Self {
+            http,
+            ..Default::default()
+        }
+    }
+
+    /// Forward submissions to the rollup API at `primary`.
+    pub fn with_primary(mut self, primary: Option<Url>) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// Look up receipts which this node has not indexed from the rollup APIs at `peers`.
+    pub fn with_peers(mut self, peers: Vec<Url>) -> Self {
+        self.peers = peers;
+        self
+    }
+
+    pub fn primary(&self) -> Option<&Url> {
+        self.primary.as_ref()
+    }
+
+    pub async fn stats(&self) -> RelayStats {
+        *self.stats.read().await
+    }
+
+    /// Forward `transaction` to the primary, returning the hash of the sequencer transaction.
+    ///
+    /// Returns `None` if this node has no primary.
+    pub async fn forward(
+        &self,
+        transaction: &SignedTransaction,
+    ) -> Option<Result<Commitment<Transaction>, ClientError>> {
+        let primary = self.primary.as_ref()?;
+        let client = self.http.client(primary).await;
+        let result = async {
+            client
+                .inner()
+                .post::<Commitment<Transaction>>("rollup/submit")
+                .header(RELAYED_HEADER, "1")
+                .body_json(transaction)?
+                .send()
+                .await
+        }
+        .await;
+        if result.is_ok() {
+            self.stats.write().await.forwarded += 1;
+        }
+        Some(result)
+    }
+
+    /// Record a submission received from another replica.
+    pub async fn record_received(&self) {
+        self.stats.write().await.received += 1;
+    }
+
+    /// Record a submission sent straight to the sequencer after forwarding it failed.
+    pub async fn record_fallback(&self) {
+        self.stats.write().await.fallbacks += 1;
+    }
+
+    /// Ask the primary, then each peer, for the receipt of the transaction `hash`.
+    ///
+    /// Returns `None` if no other replica has executed the transaction, or none can be reached.
+    pub async fn fetch_receipt(&self, hash: H256) -> Option<Receipt> {
+        let route = format!("rollup/tx/{hash:?}/receipt");
+        for url in self.primary.iter().chain(&self.peers) {
+            let client = self.http.client(url).await;
+            let result = client
+                .inner()
+                .get::<Receipt>(&route)
+                .header(RELAYED_HEADER, "1")
+                .send()
+                .await;
+            match result {
+                Ok(receipt) => {
+                    self.stats.write().await.remote_receipts += 1;
+                    return Some(receipt);
+                }
+                Err(err) if err.status().is_client_error() => {}
+                Err(err) => {
+                    tracing::debug!("Unable to fetch receipt of {hash:?} from {url}: {err}");
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Whether a submission which the primary failed to accept with `err` should be sent straight to
+/// the sequencer.
+///
+/// Only failures to reach the primary fall back. A transaction which the primary rejected would be
+/// rejected by the sequencer path as well.
+pub fn should_fall_back(err: &ClientError) -> bool {
+    !err.status().is_client_error()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tide_disco::StatusCode;
+
+    #[async_std::test]
+    async fn test_relay_without_primary() {
+        let relay = TransactionRelay::default();
+        assert_eq!(relay.primary(), None);
+        assert!(relay.fetch_receipt(H256::random()).await.is_none());
+
+        relay.record_received().await;
+        relay.record_fallback().await;
+        assert_eq!(
+            relay.stats().await,
+            RelayStats {
+                received: 1,
+                fallbacks: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_should_fall_back() {
+        let rejected = ClientError::catch_all(StatusCode::BAD_REQUEST, "invalid nonce".into());
+        assert!(!should_fall_back(&rejected));
+        let unreachable = ClientError::catch_all(StatusCode::BAD_GATEWAY, "unreachable".into());
+        assert!(should_fall_back(&unreachable));
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Commands and session state of the interactive `cli repl`.
+//!
+//! A one-shot CLI command looks up the sender's nonce and the rollup chain for every transfer, and
+//! only knows the seed identities. A REPL [`Session`] instead remembers the last nonce it used for
+//! each sender, so that transfers can be sent back to back without waiting for the API to execute
+//! the previous one, lets the user name further seed accounts, and keeps a history of the commands
+//! entered.
+
+use crate::seed::{seed_wallet, SeedIdentity};
+use crate::state::{Amount, Nonce};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::Address;
+use snafu::Snafu;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+
+/// Summary of the REPL commands, printed by `help`.
+pub const REPL_HELP: &str = "\
+balance <who>                  print the balance of an account
+nonce <who>                    print the nonce of an account
+send <from> <to> <amount> [fee]  transfer tokens, signed by <from>
+watch <who>                    print balance changes until Enter is pressed
+identity <name> <seed-index>   name the seed account at <seed-index>
+identities                     list the named identities
+history                        list the commands entered in this session
+help                           print this summary
+exit                           end the session
+
+<who> is a named identity or a hex address. <from> must be a named identity.";
+
+/// An error in a line entered at the REPL.
+#[derive(Clone, Debug, PartialEq, Eq, Snafu)]
+pub enum ReplError {
+    #[snafu(display("Unknown command {command}, try `help`"))]
+    UnknownCommand { command: String },
+    #[snafu(display("Usage: {usage}"))]
+    Usage { usage: &'static str },
+    #[snafu(display("Invalid number {value}"))]
+    InvalidNumber { value: String },
+    #[snafu(display("Unknown identity {name}, try `identities`"))]
+    UnknownIdentity { name: String },
+}
+
+/// A command entered at the REPL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplCommand {
+    Balance(String),
+    Nonce(String),
+    Send {
+        from: String,
+        to: String,
+        amount: Amount,
+        fee: Amount,
+    },
+    Watch(String),
+    Identity {
+        name: String,
+        index: u64,
+    },
+    Identities,
+    History,
+    Help,
+    Exit,
+}
+
+fn number(value: &str) -> Result<u64, ReplError> {
+    value.parse().map_err(|_| ReplError::InvalidNumber {
+        value: value.into(),
+    })
+}
+
+impl FromStr for ReplCommand {
+    type Err = ReplError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let usage = |usage| Err::<Self, _>(ReplError::Usage { usage });
+        match words.as_slice() {
+            ["balance", who] => Ok(Self::Balance(who.to_string())),
+            ["balance", ..] => usage("balance <who>"),
+            ["nonce", who] => Ok(Self::Nonce(who.to_string())),
+            ["nonce", ..] => usage("nonce <who>"),
+            ["send", from, to, amount, fee @ ..] if fee.len() <= 1 => Ok(Self::Send {
+                from: from.to_string(),
+                to: to.to_string(),
+                amount: number(amount)?,
+                fee: fee.first().map_or(Ok(0), |fee| number(fee))?,
+            }),
+            ["send", ..] => usage("send <from> <to> <amount> [fee]"),
+            ["watch", who] => Ok(Self::Watch(who.to_string())),
+            ["watch", ..] => usage("watch <who>"),
+            ["identity", name, index] => Ok(Self::Identity {
+                name: name.to_string(),
+                index: number(index)?,
+            }),
+            ["identity", ..] => usage("identity <name> <seed-index>"),
+            ["identities"] => Ok(Self::Identities),
+            ["history"] => Ok(Self::History),
+            ["help"] => Ok(Self::Help),
+            ["exit"] | ["quit"] => Ok(Self::Exit),
+            [command, ..] => Err(ReplError::UnknownCommand {
+                command: command.to_string(),
+            }),
+            [] => usage("enter a command, or `help`"),
+        }
+    }
+}
+
+/// State kept across the commands of a REPL session.
+#[derive(Clone, Debug)]
+pub struct Session {
+    identities: BTreeMap<String, LocalWallet>,
+    /// The nonce of the last transfer submitted by each sender in this session.
+    nonces: HashMap<Address, Nonce>,
+    history: Vec<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    /// A session knowing the seed identities, by their lower case names.
+    pub fn new() -> Self {
+        let identities = SeedIdentity::iter()
+            .map(|identity| (format!("{identity:?}").to_lowercase(), identity.wallet()))
+            .collect();
+        Self {
+            identities,
+            nonces: HashMap::new(),
+            history: vec![],
+        }
+    }
+
+    /// Name the seed account at `index`, replacing any identity with the same name.
+    pub fn add_identity(&mut self, name: &str, index: u64) -> Address {
+        let wallet = seed_wallet(index);
+        let address = wallet.address();
+        self.identities.insert(name.to_lowercase(), wallet);
+        address
+    }
+
+    /// The named identities and their addresses.
+    pub fn identities(&self) -> impl Iterator<Item = (&str, Address)> {
+        self.identities
+            .iter()
+            .map(|(name, wallet)| (name.as_str(), wallet.address()))
+    }
+
+    /// The wallet of the identity `name`.
+    pub fn wallet(&self, name: &str) -> Result<&LocalWallet, ReplError> {
+        self.identities
+            .get(&name.to_lowercase())
+            .ok_or_else(|| ReplError::UnknownIdentity { name: name.into() })
+    }
+
+    /// The address of `who`, either a named identity or a hex address.
+    pub fn resolve(&self, who: &str) -> Result<Address, ReplError> {
+        match self.wallet(who) {
+            Ok(wallet) => Ok(wallet.address()),
+            Err(err) => who.parse().map_err(|_| err),
+        }
+    }
+
+    /// The nonce for the next transfer from `sender`, if this session has submitted one before.
+    pub fn next_nonce(&self, sender: Address) -> Option<Nonce> {
+        self.nonces.get(&sender).map(|nonce| nonce + 1)
+    }
+
+    /// Remember that a transfer from `sender` was submitted with `nonce`.
+    pub fn record_nonce(&mut self, sender: Address, nonce: Nonce) {
+        self.nonces.insert(sender, nonce);
+    }
+
+    /// Forget the cached nonce of `sender`, so that it is looked up again before the next
+    /// transfer.
+    pub fn forget_nonce(&mut self, sender: Address) {
+        self.nonces.remove(&sender);
+    }
+
+    /// Add `line` to the history, unless it is blank.
+    pub fn record(&mut self, line: &str) {
+        let line = line.trim();
+        if !line.is_empty() {
+            self.history.push(line.into());
+        }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            "balance alice".parse::<ReplCommand>(),
+            Ok(ReplCommand::Balance("alice".into()))
+        );
+        assert_eq!(
+            "  send alice   bob 10 ".parse::<ReplCommand>(),
+            Ok(ReplCommand::Send {
+                from: "alice".into(),
+                to: "bob".into(),
+                amount: 10,
+                fee: 0,
+            })
+        );
+        assert_eq!(
+            "send alice bob 10 2".parse::<ReplCommand>(),
+            Ok(ReplCommand::Send {
+                from: "alice".into(),
+                to: "bob".into(),
+                amount: 10,
+                fee: 2,
+            })
+        );
+        assert_eq!(
+            "send alice bob ten".parse::<ReplCommand>(),
+            Err(ReplError::InvalidNumber {
+                value: "ten".into()
+            })
+        );
+        assert!(matches!(
+            "send alice bob 10 2 3".parse::<ReplCommand>(),
+            Err(ReplError::Usage { .. })
+        ));
+        assert_eq!("quit".parse::<ReplCommand>(), Ok(ReplCommand::Exit));
+        assert_eq!(
+            "mint alice 10".parse::<ReplCommand>(),
+            Err(ReplError::UnknownCommand {
+                command: "mint".into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_session() {
+        let mut session = Session::new();
+        let alice = SeedIdentity::Alice.wallet().address();
+        assert_eq!(session.resolve("alice"), Ok(alice));
+        assert_eq!(session.resolve("Alice"), Ok(alice));
+        assert_eq!(session.resolve(&format!("{alice:?}")), Ok(alice));
+        assert_eq!(
+            session.resolve("dave"),
+            Err(ReplError::UnknownIdentity {
+                name: "dave".into()
+            })
+        );
+
+        let dave = session.add_identity("dave", 5);
+        assert_eq!(dave, seed_wallet(5).address());
+        assert_eq!(session.resolve("dave"), Ok(dave));
+        assert_eq!(session.identities().count(), 4);
+
+        // Nonces are cached once a transfer has been submitted.
+        assert_eq!(session.next_nonce(alice), None);
+        session.record_nonce(alice, 3);
+        assert_eq!(session.next_nonce(alice), Some(4));
+        session.forget_nonce(alice);
+        assert_eq!(session.next_nonce(alice), None);
+
+        session.record("balance alice");
+        session.record("   ");
+        assert_eq!(session.history(), ["balance alice"]);
+    }
+}
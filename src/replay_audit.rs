@@ -0,0 +1,308 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Audit of the rollup's history for replayed transactions.
+//!
+//! A [`ReplayAudit`] re-executes the rollup namespace from the genesis state, like an
+//! [`Auditor`](crate::audit::Auditor), and checks every transaction in it for signs of a replay:
+//! the same `(sender, nonce)` pair submitted more than once, a signature for another rollup chain,
+//! or the same `(sender, nonce)` pair executing successfully more than once. With `nonce` replay
+//! protection the last must never happen, so after a change to the VM's replay rules the audit shows
+//! whether any past bug let a transaction execute twice. With `recent-hashes` replay protection
+//! nonces are only salts, and a transaction replayed after the replay window executes again.
+
+use crate::chain::ChainId;
+use crate::data_source::SequencerDataSource;
+use crate::hooks;
+use crate::machine::RollupStateMachine;
+use crate::scheduler::{BlockScheduler, NamespaceBlock};
+use crate::state::{Nonce, ReplayProtection, State};
+use crate::transaction::Submission;
+use espresso_types::{Header, NsProof, SeqTypes};
+use ethers::types::{Address, H256};
+use hotshot_query_service::availability::BlockHash;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// The position of a transaction in the rollup's history.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionLocation {
+    pub block_height: u64,
+    /// Index of the transaction in the block, in execution order.
+    pub index: usize,
+    pub hash: H256,
+}
+
+/// A sign of a replayed transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum ReplayFinding {
+    /// A `(sender, nonce)` pair was submitted again, whether or not either submission executed.
+    DuplicateNonce {
+        sender: Address,
+        nonce: Nonce,
+        first: TransactionLocation,
+        duplicate: TransactionLocation,
+    },
+    /// A transaction was signed for another rollup chain.
+    ForeignChain {
+        sender: Address,
+        chain_id: ChainId,
+        location: TransactionLocation,
+        executed: bool,
+    },
+    /// A `(sender, nonce)` pair executed successfully more than once.
+    ExecutedTwice {
+        sender: Address,
+        nonce: Nonce,
+        first: TransactionLocation,
+        again: TransactionLocation,
+    },
+}
+
+/// The findings of a [`ReplayAudit`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub replay_protection: ReplayProtection,
+    pub chain_id: Option<ChainId>,
+    pub blocks_scanned: u64,
+    /// Number of validly signed transfers scanned.
+    pub transactions_scanned: u64,
+    pub findings: Vec<ReplayFinding>,
+}
+
+/// Re-executes the rollup and records signs of replayed transactions.
+#[derive(Debug)]
+pub struct ReplayAudit {
+    state: State,
+    /// First submission of each `(sender, nonce)` pair.
+    submitted: HashMap<(Address, Nonce), TransactionLocation>,
+    /// First successful execution of each `(sender, nonce)` pair.
+    executed: HashMap<(Address, Nonce), TransactionLocation>,
+    report: ReplayReport,
+}
+
+impl ReplayAudit {
+    /// Audit a rollup starting from the `genesis` state, which must be bound to the rollup's
+    /// chain ID if it has one.
+    pub fn new(genesis: State) -> Self {
+        let report = ReplayReport {
+            replay_protection: genesis.replay_protection(),
+            chain_id: genesis.chain_id(),
+            blocks_scanned: 0,
+            transactions_scanned: 0,
+            findings: vec![],
+        };
+        Self {
+            state: genesis,
+            submitted: Default::default(),
+            executed: Default::default(),
+            report,
+        }
+    }
+
+    /// Scan the next block, fetching its rollup data from `data_source`.
+    pub async fn scan(&mut self, data_source: &dyn SequencerDataSource, header: Header) {
+        assert_eq!(
+            header.height(),
+            self.report.blocks_scanned,
+            "blocks must be scanned in order"
+        );
+        self.report.blocks_scanned += 1;
+        let Some(NamespaceBlock {
+            namespace_proof,
+            block_hash,
+            ..
+        }) = BlockScheduler::new(data_source, vec![self.state.namespace()])
+            .schedule(&header)
+            .await
+            .pop()
+        else {
+            return;
+        };
+        self.scan_block(&header, &namespace_proof, block_hash);
+    }
+
+    fn scan_block(
+        &mut self,
+        header: &Header,
+        namespace_proof: &NsProof,
+        block_hash: BlockHash<SeqTypes>,
+    ) {
+        let block_height = header.height();
+        // The transactions in the order the state executes them, so that they line up with its
+        // results.
+        let transactions = self
+            .state
+            .ordering_policy()
+            .order(namespace_proof.export_all_txs(&self.state.namespace()));
+        self.state
+            .execute_transactions(header, namespace_proof, block_hash);
+        let results = self.state.block_results();
+        for (index, (txn, (hash, result))) in transactions.iter().zip(results).enumerate() {
+            if hooks::decode_custom(txn.payload()).is_some() {
+                continue;
+            }
+            let Ok(submission) = Submission::decode(txn.payload()) else {
+                continue;
+            };
+            let signed = submission.transaction();
+            let Ok(sender) = signed.recover() else {
+                continue;
+            };
+            self.report.transactions_scanned += 1;
+            let location = TransactionLocation {
+                block_height,
+                index,
+                hash: *hash,
+            };
+            let nonce = signed.transaction.nonce;
+
+            if let Some(chain_id) = signed
+                .chain_id()
+                .filter(|chain_id| Some(*chain_id) != self.report.chain_id)
+            {
+                self.report.findings.push(ReplayFinding::ForeignChain {
+                    sender,
+                    chain_id,
+                    location,
+                    executed: result.is_ok(),
+                });
+            }
+            match self.submitted.entry((sender, nonce)) {
+                Entry::Occupied(first) => {
+                    self.report.findings.push(ReplayFinding::DuplicateNonce {
+                        sender,
+                        nonce,
+                        first: *first.get(),
+                        duplicate: location,
+                    })
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(location);
+                }
+            }
+            if result.is_ok() {
+                match self.executed.entry((sender, nonce)) {
+                    Entry::Occupied(first) => {
+                        tracing::error!(
+                            "Transaction {:?} from {sender:?} with nonce {nonce} executed again \
+                             in block {block_height}",
+                            hash
+                        );
+                        self.report.findings.push(ReplayFinding::ExecutedTwice {
+                            sender,
+                            nonce,
+                            first: *first.get(),
+                            again: location,
+                        })
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(location);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The findings so far.
+    pub fn report(&self) -> &ReplayReport {
+        &self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mock_block;
+    use crate::transaction::{SignedTransaction, Transaction};
+    use crate::RollupVM;
+    use committable::Committable;
+    use espresso_types::NamespaceId;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[async_std::test]
+    async fn test_replay_audit() {
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let namespace: NamespaceId = vm.into();
+        let alice = LocalWallet::new(&mut rand::thread_rng());
+        let chain_id = ChainId(H256::random());
+        let foreign_chain_id = ChainId(H256::random());
+        // With recent-hashes replay protection, nonces are salts, so different transfers with the
+        // same nonce both execute.
+        let mut genesis = State::from_initial_balances([(alice.address(), 100)], vm)
+            .with_replay_protection(ReplayProtection::RecentHashes, 100);
+        genesis.set_chain_id(chain_id);
+
+        let transfer = |amount, chain_id| {
+            let transaction = Transaction {
+                amount,
+                destination: Address::random(),
+                nonce: 7,
+                expires_at: Some(100),
+                ..Default::default()
+            };
+            SignedTransaction::new_for_chain(transaction, chain_id, &alice)
+        };
+        let first = transfer(10, chain_id).await;
+        let second = transfer(20, chain_id).await;
+        let foreign = transfer(30, foreign_chain_id).await;
+        let block = mock_block(
+            namespace,
+            &[(
+                namespace,
+                vec![first.encode(), second.encode(), foreign.encode()],
+            )],
+        )
+        .await;
+
+        let mut audit = ReplayAudit::new(genesis);
+        audit.scan_block(
+            &block.header,
+            block.namespace_proof.as_ref().unwrap(),
+            block.header.commit(),
+        );
+        let report = audit.report();
+        assert_eq!(report.chain_id, Some(chain_id));
+        assert_eq!(report.transactions_scanned, 3);
+
+        let location = |index, hash| TransactionLocation {
+            block_height: block.header.height(),
+            index,
+            hash,
+        };
+        assert_eq!(
+            report.findings,
+            [
+                ReplayFinding::DuplicateNonce {
+                    sender: alice.address(),
+                    nonce: 7,
+                    first: location(0, first.hash()),
+                    duplicate: location(1, second.hash()),
+                },
+                ReplayFinding::ExecutedTwice {
+                    sender: alice.address(),
+                    nonce: 7,
+                    first: location(0, first.hash()),
+                    again: location(1, second.hash()),
+                },
+                ReplayFinding::ForeignChain {
+                    sender: alice.address(),
+                    chain_id: foreign_chain_id,
+                    location: location(2, foreign.hash()),
+                    executed: false,
+                },
+                ReplayFinding::DuplicateNonce {
+                    sender: alice.address(),
+                    nonce: 7,
+                    first: location(0, first.hash()),
+                    duplicate: location(2, foreign.hash()),
+                },
+            ]
+        );
+    }
+}
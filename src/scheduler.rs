@@ -0,0 +1,694 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Scheduling of namespace execution within HotShot blocks.
+//!
+//! For each block, the scheduler reads the namespace table from the header to find which of the
+//! configured namespaces have data, so blocks without any of them cost no queries beyond the
+//! header itself. Data shared by every namespace in a block, such as the VID common data, is
+//! fetched once per block and handed to the execution of each namespace, rather than once per
+//! namespace.
+//!
+//! Data which is not yet available is polled on a schedule adapted to the sequencer. The
+//! [`BlockCadence`] estimates the block interval from the timestamps of recent headers, and learns
+//! how long after its header the data of a new block usually becomes available. Fetches for a block
+//! which was just decided wait until then rather than failing and backing off, and retries are
+//! spaced according to the block interval, so a fast sequencer is retried promptly while a slow or
+//! stalled one is not polled more often than it produces blocks.
+
+use crate::clock::{Clock, SystemClock};
+use crate::data_source::SequencerDataSource;
+use crate::stats::unix_millis;
+use async_std::channel;
+use async_std::sync::{Arc, Mutex, RwLock};
+use async_std::task::spawn;
+use clap::ValueEnum;
+use espresso_types::{Header, NamespaceId, NsProof, SeqTypes};
+use futures::future::{join_all, ready, BoxFuture, FutureExt};
+use futures::join;
+use futures::stream::{self, BoxStream, StreamExt};
+use hotshot_query_service::availability::BlockHash;
+use hotshot_query_service::VidCommon;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+use strum_macros::Display;
+
+/// The data needed to execute one namespace of a HotShot block.
+#[derive(Clone, Debug)]
+pub struct NamespaceBlock {
+    pub namespace: NamespaceId,
+    pub namespace_proof: NsProof,
+    /// VID common data for the block, shared by all of its namespaces.
+    pub vid_common: VidCommon,
+    pub block_hash: BlockHash<SeqTypes>,
+    /// View in which the block was decided, or `None` if it could not be fetched. This is only
+    /// metadata, so execution does not wait for it.
+    pub view_number: Option<u64>,
+}
+
+/// Default time for which unavailable namespace data is retried.
+pub const DEFAULT_DA_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Delay before the first retry of unavailable namespace data while the block interval is unknown.
+/// The delay doubles after each attempt, up to [`MAX_DA_BACKOFF`] or the block interval.
+const INITIAL_DA_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_DA_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Number of recent data unavailability incidents retained.
+pub const MAX_DA_INCIDENTS: usize = 100;
+
+/// Number of recent consecutive headers from which the block interval is estimated.
+pub const CADENCE_WINDOW: usize = 20;
+
+/// Number of recent blocks whose headers and schedules [`SharedBlocks`] keeps for executors which
+/// have not yet reached them.
+pub const SHARED_BLOCK_WINDOW: usize = 1000;
+
+/// Shortest wait before fetching block data, so that a sequencer with very fast blocks is not
+/// polled in a tight loop.
+const MIN_FETCH_DELAY: Duration = Duration::from_millis(100);
+
+/// Weight, in percent, of each new observation in the moving average of the delay before the data
+/// of a new block becomes available.
+const AVAILABILITY_WEIGHT: u32 = 25;
+
+/// What to do with a namespace whose data is still unavailable once the DA timeout has expired.
+#[derive(
+    ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum DaTimeoutAction {
+    /// Halt the executor. A node which skips a block its peers execute computes a different state,
+    /// so this is the only safe choice for a node which submits proofs.
+    #[default]
+    Halt,
+    /// Record an incident and continue as if the block did not contain the namespace.
+    Skip,
+}
+
+/// A namespace skipped because its data never became available.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaIncident {
+    pub block_height: u64,
+    pub namespace: NamespaceId,
+    /// How long the data was retried for before the namespace was skipped.
+    pub waited_ms: u64,
+}
+
+/// Recent data unavailability incidents, shared with the API.
+#[derive(Clone, Debug, Default)]
+pub struct DaIncidentLog {
+    incidents: Arc<RwLock<VecDeque<DaIncident>>>,
+}
+
+impl DaIncidentLog {
+    async fn record(&self, incident: DaIncident) {
+        let mut incidents = self.incidents.write().await;
+        incidents.push_back(incident);
+        if incidents.len() > MAX_DA_INCIDENTS {
+            incidents.pop_front();
+        }
+    }
+
+    /// Recent incidents, oldest first.
+    pub async fn incidents(&self) -> Vec<DaIncident> {
+        self.incidents.read().await.iter().copied().collect()
+    }
+}
+
+/// The observed pace of the sequencer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CadenceEstimate {
+    /// Average time between recent blocks, or `None` until two consecutive headers have been seen.
+    pub block_interval: Option<Duration>,
+    /// Moving average of how long after its header the data of a new block becomes available, or
+    /// `None` until the data of a new block has been fetched.
+    pub availability_delay: Option<Duration>,
+}
+
+impl CadenceEstimate {
+    /// Whether a block with `timestamp`, in seconds, was decided recently enough that its data may
+    /// not yet be available.
+    ///
+    /// Blocks fetched while catching up with history are older than a couple of block intervals,
+    /// and their data is expected to be available at once.
+    pub fn is_recent(&self, timestamp: u64) -> bool {
+        let window = self
+            .block_interval
+            .unwrap_or_default()
+            .max(Duration::from_secs(1))
+            * 2;
+        let age = (unix_millis() / 1000).saturating_sub(timestamp);
+        Duration::from_secs(age) <= window
+    }
+
+    /// How long to wait before the first fetch of the data of a recent block.
+    ///
+    /// This is somewhat less than the usual delay, so that when the data starts to arrive sooner,
+    /// the first fetch succeeds earlier and the estimate follows.
+    pub fn first_fetch_delay(&self) -> Duration {
+        match self.availability_delay {
+            Some(delay) if delay * 3 / 4 >= MIN_FETCH_DELAY => delay * 3 / 4,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Delay before retrying block data after `attempt` failed fetches, counting from zero.
+    ///
+    /// Retries start at a quarter of the block interval, or [`INITIAL_DA_BACKOFF`] while it is
+    /// unknown, and double after each attempt, up to the longer of the block interval and
+    /// [`MAX_DA_BACKOFF`].
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        let (base, max) = match self.block_interval {
+            Some(interval) => (
+                (interval / 4).max(MIN_FETCH_DELAY),
+                interval.max(MAX_DA_BACKOFF),
+            ),
+            None => (INITIAL_DA_BACKOFF, MAX_DA_BACKOFF),
+        };
+        base.saturating_mul(1 << attempt.min(16)).min(max)
+    }
+}
+
+/// Estimates the pace of the sequencer from the headers passing through the scheduler.
+#[derive(Clone, Debug, Default)]
+pub struct BlockCadence {
+    inner: Arc<RwLock<CadenceState>>,
+}
+
+#[derive(Debug, Default)]
+struct CadenceState {
+    /// Height and timestamp of recent consecutive headers, oldest first.
+    headers: VecDeque<(u64, u64)>,
+    availability_delay: Option<Duration>,
+}
+
+impl BlockCadence {
+    /// Record a header with `height` and `timestamp`, in seconds.
+    ///
+    /// Only consecutive headers are used, so that a restart or a gap in the headers does not
+    /// distort the estimate.
+    pub async fn observe(&self, height: u64, timestamp: u64) {
+        let mut inner = self.inner.write().await;
+        if inner
+            .headers
+            .back()
+            .is_some_and(|(last, _)| *last + 1 != height)
+        {
+            inner.headers.clear();
+        }
+        inner.headers.push_back((height, timestamp));
+        if inner.headers.len() > CADENCE_WINDOW {
+            inner.headers.pop_front();
+        }
+    }
+
+    /// Record that the data of a recent block became available `delay` after its header.
+    pub async fn record_availability(&self, delay: Duration) {
+        let mut inner = self.inner.write().await;
+        inner.availability_delay = Some(match inner.availability_delay {
+            Some(average) => {
+                (average * (100 - AVAILABILITY_WEIGHT) + delay * AVAILABILITY_WEIGHT) / 100
+            }
+            None => delay,
+        });
+    }
+
+    pub async fn estimate(&self) -> CadenceEstimate {
+        let inner = self.inner.read().await;
+        // Header timestamps only have a resolution of one second, but the differences between
+        // consecutive timestamps sum to the span of the window, so the average is accurate.
+        let block_interval = match (inner.headers.front(), inner.headers.back()) {
+            (Some((first_height, first_time)), Some((last_height, last_time)))
+                if last_height > first_height =>
+            {
+                Some(Duration::from_millis(
+                    last_time.saturating_sub(*first_time) * 1000 / (last_height - first_height),
+                ))
+            }
+            _ => None,
+        };
+        CadenceEstimate {
+            block_interval,
+            availability_delay: inner.availability_delay,
+        }
+    }
+}
+
+/// How long to wait for namespace data which the namespace table says exists, and what to do if
+/// it never becomes available.
+#[derive(Clone, Debug)]
+pub struct DaTimeoutPolicy {
+    pub timeout: Duration,
+    pub action: DaTimeoutAction,
+    /// Where skipped namespaces are recorded.
+    pub incidents: DaIncidentLog,
+    /// The pace of the sequencer, which decides when unavailable data is fetched again.
+    pub cadence: BlockCadence,
+}
+
+impl Default for DaTimeoutPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_DA_TIMEOUT,
+            action: DaTimeoutAction::default(),
+            incidents: Default::default(),
+            cadence: Default::default(),
+        }
+    }
+}
+
+/// Determines which configured namespaces each block contains and fetches the data to execute
+/// them.
+#[derive(Debug)]
+pub struct BlockScheduler<'a> {
+    data_source: &'a dyn SequencerDataSource,
+    namespaces: Vec<NamespaceId>,
+    da_policy: DaTimeoutPolicy,
+    clock: &'a dyn Clock,
+}
+
+impl<'a> BlockScheduler<'a> {
+    pub fn new(data_source: &'a dyn SequencerDataSource, namespaces: Vec<NamespaceId>) -> Self {
+        Self {
+            data_source,
+            namespaces,
+            da_policy: Default::default(),
+            clock: &SystemClock,
+        }
+    }
+
+    /// Retry unavailable namespace data according to `policy`, waiting between attempts on
+    /// `clock`.
+    pub fn with_da_policy(mut self, policy: DaTimeoutPolicy, clock: &'a dyn Clock) -> Self {
+        self.da_policy = policy;
+        self.clock = clock;
+        self
+    }
+
+    /// Fetch the data for each configured namespace with transactions in the block `header`.
+    ///
+    /// Namespaces are returned in the order they were configured. The data of a block which was
+    /// just decided is first fetched when it usually becomes available. If the data for a
+    /// namespace in the namespace table is unavailable, it is retried with a backoff adapted to
+    /// the block interval until the DA timeout expires. After that, the executor halts, or the
+    /// namespace is omitted and an incident is recorded, depending on the [`DaTimeoutAction`].
+    pub async fn schedule(&self, header: &Header) -> Vec<NamespaceBlock> {
+        let height = header.height();
+        let cadence = &self.da_policy.cadence;
+        cadence.observe(height, header.timestamp()).await;
+        let present: Vec<_> = self
+            .namespaces
+            .iter()
+            .copied()
+            .filter(|namespace| header.ns_table().find_ns_id(namespace).is_some())
+            .collect();
+        if present.is_empty() {
+            return vec![];
+        }
+
+        let estimate = cadence.estimate().await;
+        let recent = estimate.is_recent(header.timestamp());
+        let started = self.clock.now();
+        let first_fetch_delay = estimate.first_fetch_delay();
+        if recent && !first_fetch_delay.is_zero() {
+            self.clock.sleep(first_fetch_delay).await;
+        }
+        let mut attempt = 0;
+        loop {
+            let ((vid_common, block_hash, view_number), proofs) = join!(
+                async {
+                    join!(
+                        self.data_source.vid_common(height),
+                        self.data_source.block_hash(height),
+                        self.data_source.view_number(height)
+                    )
+                },
+                join_all(
+                    present
+                        .iter()
+                        .map(|namespace| self.data_source.namespace_proof(height, *namespace))
+                )
+            );
+            let mut available = vec![];
+            let mut missing = vec![];
+            for (namespace, proof) in present.iter().copied().zip(proofs) {
+                match (proof, &vid_common) {
+                    (Some(namespace_proof), Some(vid_common)) => available.push(NamespaceBlock {
+                        namespace,
+                        namespace_proof,
+                        vid_common: vid_common.clone(),
+                        block_hash,
+                        view_number,
+                    }),
+                    _ => missing.push(namespace),
+                }
+            }
+            if missing.is_empty() {
+                // Only recent blocks tell how long new data takes to arrive.
+                if recent {
+                    cadence
+                        .record_availability(self.clock.now().saturating_duration_since(started))
+                        .await;
+                }
+                return available;
+            }
+
+            let waited = self.clock.now().saturating_duration_since(started);
+            if waited >= self.da_policy.timeout {
+                match self.da_policy.action {
+                    DaTimeoutAction::Halt => panic!(
+                        "Data for namespaces {missing:?} in block {height} is unavailable after \
+                         {waited:?}"
+                    ),
+                    DaTimeoutAction::Skip => {
+                        for namespace in missing {
+                            tracing::error!(
+                                "Skipping namespace {namespace} in block {height}: data unavailable \
+                                 after {waited:?}"
+                            );
+                            self.da_policy
+                                .incidents
+                                .record(DaIncident {
+                                    block_height: height,
+                                    namespace,
+                                    waited_ms: waited.as_millis() as u64,
+                                })
+                                .await;
+                        }
+                        return available;
+                    }
+                }
+            }
+            let delay = estimate.retry_delay(attempt);
+            tracing::warn!(
+                "Data for namespaces {missing:?} in block {height} is unavailable, retrying in \
+                 {delay:?}"
+            );
+            self.clock.sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// The schedule of one block, shared by the executors of several rollups.
+#[derive(Debug, Default)]
+struct SharedSchedule {
+    blocks: Arc<Mutex<Option<Vec<NamespaceBlock>>>>,
+    /// Number of executors which have taken the schedule.
+    taken: usize,
+}
+
+/// The live header stream, fanned out to the executors of several rollups.
+#[derive(Debug, Default)]
+struct HeaderFanout {
+    /// Height of the next header from the live stream, once it is followed.
+    next: Option<u64>,
+    /// The most recent headers of the live stream, oldest first.
+    recent: VecDeque<Header>,
+    subscribers: Vec<channel::Sender<Header>>,
+    /// Whether the live stream has ended.
+    ended: bool,
+}
+
+/// Block data shared by the executors of several rollups following the same sequencer.
+///
+/// Each executor would otherwise follow its own header stream and fetch the data common to all
+/// namespaces of every block, such as the VID common data, for itself. Instead, one live header
+/// stream is followed and fanned out to every executor, and each block is scheduled once, for the
+/// namespaces of all the rollups, by a [`BlockScheduler`]. The schedule of a block is kept until
+/// the executor of every namespace has taken it, or until it falls out of the most recent
+/// [`SHARED_BLOCK_WINDOW`] blocks, after which an executor which reaches it schedules it again.
+///
+/// Other queries, such as the range queries with which an executor catches up, are passed through
+/// to the underlying data source.
+#[derive(Clone, Debug)]
+pub struct SharedBlocks {
+    data_source: Arc<dyn SequencerDataSource>,
+    namespaces: Vec<NamespaceId>,
+    da_policy: DaTimeoutPolicy,
+    clock: Arc<dyn Clock>,
+    schedules: Arc<Mutex<BTreeMap<u64, SharedSchedule>>>,
+    headers: Arc<Mutex<HeaderFanout>>,
+}
+
+impl SharedBlocks {
+    /// Share the blocks of `data_source` between the executors of `namespaces`, one executor per
+    /// namespace.
+    pub fn new(data_source: Arc<dyn SequencerDataSource>, namespaces: Vec<NamespaceId>) -> Self {
+        Self {
+            data_source,
+            namespaces,
+            da_policy: Default::default(),
+            clock: Arc::new(SystemClock),
+            schedules: Default::default(),
+            headers: Default::default(),
+        }
+    }
+
+    /// Retry unavailable namespace data according to `policy`, waiting between attempts on
+    /// `clock`. The policy applies to the namespaces of every rollup.
+    pub fn with_da_policy(mut self, policy: DaTimeoutPolicy, clock: Arc<dyn Clock>) -> Self {
+        self.da_policy = policy;
+        self.clock = clock;
+        self
+    }
+
+    /// The data needed to execute `namespace` in the block `header`, or `None` if the block does
+    /// not contain it.
+    ///
+    /// The first executor to reach a block schedules it for every namespace, as
+    /// [`BlockScheduler::schedule`] does; the others wait for its result.
+    pub async fn schedule(
+        &self,
+        header: &Header,
+        namespace: NamespaceId,
+    ) -> Option<NamespaceBlock> {
+        let height = header.height();
+        let blocks = {
+            let mut schedules = self.schedules.lock().await;
+            let schedule = schedules.entry(height).or_default();
+            schedule.taken += 1;
+            let blocks = schedule.blocks.clone();
+            if schedule.taken >= self.namespaces.len() {
+                schedules.remove(&height);
+            }
+            while schedules.len() > SHARED_BLOCK_WINDOW {
+                schedules.pop_first();
+            }
+            blocks
+        };
+        let mut blocks = blocks.lock().await;
+        if blocks.is_none() {
+            let scheduler = BlockScheduler::new(self.data_source.as_ref(), self.namespaces.clone())
+                .with_da_policy(self.da_policy.clone(), self.clock.as_ref());
+            *blocks = Some(scheduler.schedule(header).await);
+        }
+        blocks
+            .iter()
+            .flatten()
+            .find(|block| block.namespace == namespace)
+            .cloned()
+    }
+}
+
+/// Fan the headers of `upstream` out to the subscribers of `fanout`.
+async fn fan_out_headers(
+    mut upstream: BoxStream<'static, Header>,
+    fanout: Arc<Mutex<HeaderFanout>>,
+) {
+    while let Some(header) = upstream.next().await {
+        let mut fanout = fanout.lock().await;
+        fanout.next = Some(header.height() + 1);
+        if fanout.recent.len() == SHARED_BLOCK_WINDOW {
+            fanout.recent.pop_front();
+        }
+        fanout.recent.push_back(header.clone());
+        fanout
+            .subscribers
+            .retain(|subscriber| subscriber.try_send(header.clone()).is_ok());
+    }
+    let mut fanout = fanout.lock().await;
+    fanout.ended = true;
+    fanout.subscribers.clear();
+}
+
+impl SequencerDataSource for SharedBlocks {
+    /// Stream headers from height `from`, sharing one live header stream between all subscribers.
+    ///
+    /// The live stream is followed from the height at which the first executor subscribes. Headers
+    /// older than those it has retained are fetched with a range query, or, if they are
+    /// unavailable, from a header stream of the subscriber's own.
+    fn subscribe_headers(&self, from: u64) -> BoxFuture<'_, BoxStream<'static, Header>> {
+        async move {
+            // Headers are fanned out while the lock is held, so a new subscriber sees every header
+            // exactly once, either among the retained headers or on its channel.
+            let mut fanout = self.headers.lock().await;
+            if fanout.next.is_none() {
+                let upstream = self.data_source.subscribe_headers(from).await;
+                fanout.next = Some(from);
+                spawn(fan_out_headers(upstream, self.headers.clone()));
+            }
+            let oldest = fanout
+                .recent
+                .front()
+                .map(|header| header.height())
+                .or(fanout.next)
+                .unwrap_or(from);
+            let mut backfill = vec![];
+            if from < oldest {
+                match self.data_source.headers(from, oldest).await {
+                    Some(headers) if headers.len() as u64 == oldest - from => backfill = headers,
+                    _ => {
+                        drop(fanout);
+                        return self.data_source.subscribe_headers(from).await;
+                    }
+                }
+            }
+            let retained: Vec<_> = fanout
+                .recent
+                .iter()
+                .filter(|header| header.height() >= from)
+                .cloned()
+                .collect();
+            let (sender, receiver) = channel::unbounded();
+            if !fanout.ended {
+                fanout.subscribers.push(sender);
+            }
+            stream::iter(backfill)
+                .chain(stream::iter(retained))
+                .chain(receiver.filter(move |header| ready(header.height() >= from)))
+                .boxed()
+        }
+        .boxed()
+    }
+
+    fn headers(&self, from: u64, until: u64) -> BoxFuture<'_, Option<Vec<Header>>> {
+        self.data_source.headers(from, until)
+    }
+
+    fn block_height(&self) -> BoxFuture<'_, Option<u64>> {
+        self.data_source.block_height()
+    }
+
+    fn namespace_proof(
+        &self,
+        height: u64,
+        namespace: NamespaceId,
+    ) -> BoxFuture<'_, Option<NsProof>> {
+        self.data_source.namespace_proof(height, namespace)
+    }
+
+    fn vid_common(&self, height: u64) -> BoxFuture<'_, Option<VidCommon>> {
+        self.data_source.vid_common(height)
+    }
+
+    fn block_hash(&self, height: u64) -> BoxFuture<'_, BlockHash<SeqTypes>> {
+        self.data_source.block_hash(height)
+    }
+
+    fn view_number(&self, height: u64) -> BoxFuture<'_, Option<u64>> {
+        self.data_source.view_number(height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::data_source::{MockBlock, MockDataSource};
+    use crate::fixtures::mock_block;
+    use async_std::task::{sleep, spawn};
+    use futures::StreamExt;
+
+    #[async_std::test]
+    async fn test_schedule_unavailable_namespace() {
+        let namespace = NamespaceId::from(1_u64);
+        let block = mock_block(namespace, &[(namespace, vec![vec![1, 2, 3]])]).await;
+        // The namespace table lists the namespace, but its proof is never served.
+        let data_source = MockDataSource::default();
+        data_source.push(MockBlock {
+            namespace_proof: None,
+            ..block
+        });
+        let header = data_source.subscribe_headers(0).await.next().await.unwrap();
+
+        let clock = VirtualClock::default();
+        let policy = DaTimeoutPolicy {
+            timeout: Duration::from_secs(5),
+            action: DaTimeoutAction::Skip,
+            incidents: Default::default(),
+            cadence: Default::default(),
+        };
+        let incidents = policy.incidents.clone();
+        let schedule_clock = clock.clone();
+        let schedule = spawn(async move {
+            BlockScheduler::new(&data_source, vec![namespace])
+                .with_da_policy(policy, &schedule_clock)
+                .schedule(&header)
+                .await
+        });
+        // The first attempt fails, and the scheduler waits to retry.
+        while clock.num_sleepers() == 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+        // The retry comes after the timeout has expired, so the namespace is skipped.
+        clock.advance(MAX_DA_BACKOFF);
+        assert!(schedule.await.is_empty());
+        assert_eq!(
+            incidents.incidents().await,
+            [DaIncident {
+                block_height: 0,
+                namespace,
+                waited_ms: 10_000,
+            }]
+        );
+    }
+
+    #[async_std::test]
+    async fn test_block_cadence() {
+        let cadence = BlockCadence::default();
+        assert_eq!(cadence.estimate().await, CadenceEstimate::default());
+        assert_eq!(
+            CadenceEstimate::default().retry_delay(0),
+            INITIAL_DA_BACKOFF
+        );
+
+        // Timestamps have a resolution of a second, but four blocks in three seconds average 750ms.
+        for (height, timestamp) in [(10, 100), (11, 101), (12, 101), (13, 102), (14, 103)] {
+            cadence.observe(height, timestamp).await;
+        }
+        let estimate = cadence.estimate().await;
+        assert_eq!(estimate.block_interval, Some(Duration::from_millis(750)));
+        assert_eq!(estimate.retry_delay(0), Duration::from_micros(187_500));
+        assert_eq!(estimate.retry_delay(2), Duration::from_millis(750));
+        assert_eq!(estimate.retry_delay(10), MAX_DA_BACKOFF);
+        assert!(!estimate.is_recent(103));
+        assert!(estimate.is_recent(unix_millis() / 1000));
+
+        // A slow sequencer is not polled more often than it produces blocks.
+        let slow = CadenceEstimate {
+            block_interval: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        assert_eq!(slow.retry_delay(0), Duration::from_secs(15));
+        assert_eq!(slow.retry_delay(10), Duration::from_secs(60));
+
+        // A gap in the headers restarts the estimate.
+        cadence.observe(20, 200).await;
+        assert_eq!(cadence.estimate().await.block_interval, None);
+
+        // The availability delay is a moving average.
+        assert_eq!(estimate.first_fetch_delay(), Duration::ZERO);
+        cadence.record_availability(Duration::from_secs(2)).await;
+        cadence.record_availability(Duration::from_secs(6)).await;
+        let estimate = cadence.estimate().await;
+        assert_eq!(estimate.availability_delay, Some(Duration::from_secs(3)));
+        assert_eq!(estimate.first_fetch_delay(), Duration::from_millis(2250));
+    }
+}
@@ -0,0 +1,848 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Client-side types for the payloads of the rollup API.
+//!
+//! The shape of each payload type, as encoded by its serde implementation, is described once here
+//! as a [`Schema`], from which both a JSON schema and TypeScript definitions are generated. The
+//! result is served at `rollup/schema` and written out by the `schema` subcommand, so that web
+//! frontends can be type checked against the API. The tests check each description against the
+//! actual serialization of the Rust type, so that the two cannot drift apart unnoticed.
+//!
+//! Integers are unsigned 64-bit values, which TypeScript represents as `number`. Values above
+//! 2^53 lose precision, which is of no concern for the amounts in this demo.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The shape of a JSON value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Schema {
+    Null,
+    /// An unsigned integer.
+    Integer,
+    String,
+    /// A hex string prefixed with `0x`, of `bytes` bytes if the length is fixed.
+    Hex {
+        bytes: Option<usize>,
+    },
+    /// A constant string.
+    Literal(&'static str),
+    Array(Box<Schema>),
+    Nullable(Box<Schema>),
+    Object(Vec<Field>),
+    /// Any one of several shapes.
+    OneOf(Vec<Schema>),
+    /// The named [`Definition`].
+    Ref(&'static str),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub schema: Schema,
+    /// Whether the field must be present. Optional fields may be omitted by clients.
+    pub required: bool,
+}
+
+/// A named payload type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Definition {
+    pub name: &'static str,
+    pub doc: &'static str,
+    pub schema: Schema,
+}
+
+fn field(name: &'static str, schema: Schema) -> Field {
+    Field {
+        name,
+        schema,
+        required: true,
+    }
+}
+
+fn optional(name: &'static str, schema: Schema) -> Field {
+    Field {
+        name,
+        schema: nullable(schema),
+        required: false,
+    }
+}
+
+fn object(fields: impl IntoIterator<Item = Field>) -> Schema {
+    Schema::Object(fields.into_iter().collect())
+}
+
+fn nullable(schema: Schema) -> Schema {
+    Schema::Nullable(Box::new(schema))
+}
+
+/// A variant of an externally tagged enum, encoded as an object with a single key.
+fn variant(name: &'static str, schema: Schema) -> Schema {
+    object([field(name, schema)])
+}
+
+/// A variant of an enum tagged with a `type` field.
+fn tagged(name: &'static str, fields: impl IntoIterator<Item = Field>) -> Schema {
+    object(
+        [field("type", Schema::Literal(name))]
+            .into_iter()
+            .chain(fields),
+    )
+}
+
+/// The payload types of the API.
+pub fn definitions() -> Vec<Definition> {
+    use Schema::{Integer, Literal, OneOf, Ref};
+
+    let address = || Ref("Address");
+    let hash = || Ref("Hash");
+    vec![
+        Definition {
+            name: "Address",
+            doc: "An Ethereum address.",
+            schema: Schema::Hex { bytes: Some(20) },
+        },
+        Definition {
+            name: "Hash",
+            doc: "A 32-byte hash.",
+            schema: Schema::Hex { bytes: Some(32) },
+        },
+        Definition {
+            name: "ChainId",
+            doc: "Identifier of one deployment of the rollup, served by `rollup/info`.",
+            schema: Schema::Hex { bytes: Some(32) },
+        },
+        Definition {
+            name: "Commitment",
+            doc: "A state commitment, encoded in tagged base64.",
+            schema: Schema::String,
+        },
+        Definition {
+            name: "Transaction",
+            doc: "A transfer, or a withdrawal to the L1, with an optional memo, fee and expiry.",
+            schema: object([
+                field("amount", Integer),
+                field("destination", address()),
+                field("nonce", Integer),
+                optional("memo", Schema::String),
+                optional("fee", Integer),
+                optional("expires_at", Integer),
+                optional(
+                    "kind",
+                    OneOf(vec![Literal("transfer"), Literal("withdraw")]),
+                ),
+            ]),
+        },
+        Definition {
+            name: "Signature",
+            doc: "An ECDSA signature.",
+            schema: object([
+                field("r", Schema::Hex { bytes: None }),
+                field("s", Schema::Hex { bytes: None }),
+                field("v", Integer),
+            ]),
+        },
+        Definition {
+            name: "SignedTransaction",
+            doc: "A transaction signed by its sender, as submitted to `rollup/submit`.",
+            schema: object([
+                field("transaction", Ref("Transaction")),
+                field("signature", Ref("Signature")),
+                optional("chain_id", Ref("ChainId")),
+            ]),
+        },
+        Definition {
+            name: "RollupError",
+            doc: "The reason a transaction failed.",
+            schema: OneOf(vec![
+                Literal("SignatureError"),
+                Literal("InvalidTransaction"),
+                variant("InsufficientBalance", object([field("address", address())])),
+                variant(
+                    "InvalidNonce",
+                    object([
+                        field("address", address()),
+                        field("expected", Integer),
+                        field("actual", Integer),
+                    ]),
+                ),
+                variant(
+                    "DuplicateTransaction",
+                    object([field("address", address()), field("hash", hash())]),
+                ),
+                variant(
+                    "MalformedTransaction",
+                    object([field("reason", Schema::String)]),
+                ),
+                variant(
+                    "TransactionTooLarge",
+                    object([field("size", Integer), field("max", Integer)]),
+                ),
+                variant(
+                    "TransactionExpired",
+                    object([field("hash", hash()), field("expires_at", Integer)]),
+                ),
+                variant(
+                    "WrongChain",
+                    object([
+                        field("expected", Ref("ChainId")),
+                        field("actual", Ref("ChainId")),
+                    ]),
+                ),
+                variant(
+                    "MissingChainId",
+                    object([field("expected", Ref("ChainId"))]),
+                ),
+                variant(
+                    "ExpiryOutsideReplayWindow",
+                    object([field("hash", hash()), field("max", Integer)]),
+                ),
+                variant("AccountFrozen", object([field("address", address())])),
+                variant(
+                    "PolicyViolation",
+                    object([
+                        field("policy", Schema::String),
+                        field("reason", Schema::String),
+                    ]),
+                ),
+                variant(
+                    "UntrustedSubmission",
+                    object([field("reason", Schema::String)]),
+                ),
+                variant("UnknownTransactionKind", object([field("kind", Integer)])),
+                variant(
+                    "UnbalancedSpend",
+                    object([field("inputs", Integer), field("outputs", Integer)]),
+                ),
+                variant("UnknownNote", object([field("note", hash())])),
+                variant(
+                    "NoteNotOwned",
+                    object([field("note", hash()), field("address", address())]),
+                ),
+                variant("BalanceOverflow", object([field("address", address())])),
+            ]),
+        },
+        Definition {
+            name: "TransactionResult",
+            doc: "The result of executing a transaction.",
+            schema: OneOf(vec![
+                variant("Ok", Schema::Null),
+                variant("Err", Ref("RollupError")),
+            ]),
+        },
+        Definition {
+            name: "TransactionTimings",
+            doc: "When a transaction reached each stage, as Unix timestamps in milliseconds.",
+            schema: object(
+                [
+                    "received_ms",
+                    "submitted_ms",
+                    "finalized_ms",
+                    "executed_ms",
+                    "verified_ms",
+                ]
+                .map(|name| field(name, nullable(Integer))),
+            ),
+        },
+        Definition {
+            name: "ExecutionMetrics",
+            doc: "Resources consumed executing a transaction; the time is in microseconds.",
+            schema: object(
+                [
+                    "time_us",
+                    "signature_verifications",
+                    "state_reads",
+                    "state_writes",
+                ]
+                .map(|name| field(name, Integer)),
+            ),
+        },
+        Definition {
+            name: "BlockExecutionStats",
+            doc: "Resources consumed by a block, served by `rollup/block/:height/stats`.",
+            schema: object([
+                field("block_height", Integer),
+                field("transactions", Integer),
+                field("total", Ref("ExecutionMetrics")),
+                field("max", Ref("ExecutionMetrics")),
+            ]),
+        },
+        Definition {
+            name: "BlockInfo",
+            doc: "Consensus metadata of an executed block, served by `rollup/block/:height`.",
+            schema: object([
+                field("block_height", Integer),
+                field("view_number", nullable(Integer)),
+                field("timestamp", Integer),
+            ]),
+        },
+        Definition {
+            name: "Receipt",
+            doc: "The outcome of executing a transaction, served by `rollup/tx/:hash/receipt`.",
+            schema: object([
+                field("hash", hash()),
+                field("block_height", Integer),
+                field("view_number", nullable(Integer)),
+                field("block_timestamp", Integer),
+                field("index", Integer),
+                field("result", Ref("TransactionResult")),
+                field("prev_state_commitment", Ref("Commitment")),
+                field("state_commitment", Ref("Commitment")),
+                field("timings", Ref("TransactionTimings")),
+                field("metrics", Ref("ExecutionMetrics")),
+                field("chain_id", nullable(Ref("ChainId"))),
+            ]),
+        },
+        Definition {
+            name: "Supply",
+            doc: "The token supply of the rollup, served by `rollup/supply`.",
+            schema: object([
+                field("circulating", Integer),
+                field("burned", Integer),
+                field("fee_revenue", Integer),
+                field("withdrawn", Integer),
+                field("deposited", Integer),
+            ]),
+        },
+        Definition {
+            name: "WithdrawalProof",
+            doc: "Proof of a withdrawal, served by `rollup/withdrawal/:index`.",
+            schema: object([
+                field("recipient", address()),
+                field("amount", Integer),
+                field("index", Integer),
+                field("siblings", Schema::Array(Box::new(hash()))),
+            ]),
+        },
+        Definition {
+            name: "RollupEvent",
+            doc: "An event emitted by an executed transaction or a credited deposit.",
+            schema: OneOf(vec![
+                tagged(
+                    "Transfer",
+                    [
+                        field("from", address()),
+                        field("to", address()),
+                        field("amount", Integer),
+                    ],
+                ),
+                tagged("AccountCreated", [field("address", address())]),
+                tagged("Burn", [field("from", address()), field("amount", Integer)]),
+                tagged(
+                    "Withdrawal",
+                    [
+                        field("from", address()),
+                        field("recipient", address()),
+                        field("amount", Integer),
+                        field("index", Integer),
+                    ],
+                ),
+                tagged(
+                    "Deposit",
+                    [field("to", address()), field("amount", Integer)],
+                ),
+            ]),
+        },
+        Definition {
+            name: "EventKind",
+            doc: "The type of a rollup event.",
+            schema: OneOf(vec![
+                Literal("Transfer"),
+                Literal("AccountCreated"),
+                Literal("Burn"),
+                Literal("Withdrawal"),
+                Literal("Deposit"),
+            ]),
+        },
+        Definition {
+            name: "SubscriptionFilter",
+            doc: "Criteria selecting the events delivered on `rollup/stream/events`.",
+            schema: object([
+                optional("kind", Ref("EventKind")),
+                optional("address", address()),
+                optional("min_amount", Integer),
+            ]),
+        },
+        Definition {
+            name: "SubscriptionRequest",
+            doc: "The first message sent by a client on `rollup/stream/events`.",
+            schema: OneOf(vec![
+                object([
+                    field("subscription_id", Integer),
+                    field("from_seq", Integer),
+                ]),
+                Ref("SubscriptionFilter"),
+            ]),
+        },
+        Definition {
+            name: "BlockEvent",
+            doc: "An event delivered to a subscriber, with the block which emitted it.",
+            schema: object([
+                field("seq", Integer),
+                field("block_height", Integer),
+                field("view_number", nullable(Integer)),
+                field("timestamp", Integer),
+                field("event", Ref("RollupEvent")),
+            ]),
+        },
+        Definition {
+            name: "StreamMessage",
+            doc: "A message sent by the server on `rollup/stream/events`.",
+            schema: OneOf(vec![
+                variant(
+                    "Subscribed",
+                    object([
+                        field("subscription_id", Integer),
+                        field("next_seq", Integer),
+                    ]),
+                ),
+                variant("Event", Ref("BlockEvent")),
+            ]),
+        },
+        Definition {
+            name: "ErrorEnvelope",
+            doc: "The body of an error response, with its HTTP status code.",
+            schema: object([field("status", Integer), field("message", Schema::String)]),
+        },
+    ]
+}
+
+impl Schema {
+    fn json_schema(&self) -> Value {
+        match self {
+            Self::Null => json!({ "type": "null" }),
+            Self::Integer => json!({ "type": "integer", "minimum": 0 }),
+            Self::String => json!({ "type": "string" }),
+            Self::Hex { bytes: Some(bytes) } => json!({
+                "type": "string",
+                "pattern": format!("^0x[0-9a-fA-F]{{{}}}$", 2 * bytes),
+            }),
+            Self::Hex { bytes: None } => json!({ "type": "string", "pattern": "^0x[0-9a-fA-F]*$" }),
+            Self::Literal(value) => json!({ "const": value }),
+            Self::Array(items) => json!({ "type": "array", "items": items.json_schema() }),
+            Self::Nullable(schema) => {
+                json!({ "anyOf": [schema.json_schema(), { "type": "null" }] })
+            }
+            Self::Object(fields) => {
+                let properties = fields
+                    .iter()
+                    .map(|field| (field.name.to_string(), field.schema.json_schema()))
+                    .collect::<Map<_, _>>();
+                let required = fields
+                    .iter()
+                    .filter(|field| field.required)
+                    .map(|field| field.name)
+                    .collect::<Vec<_>>();
+                json!({ "type": "object", "properties": properties, "required": required })
+            }
+            Self::OneOf(schemas) => json!({
+                "oneOf": schemas.iter().map(Self::json_schema).collect::<Vec<_>>(),
+            }),
+            Self::Ref(name) => json!({ "$ref": format!("#/$defs/{name}") }),
+        }
+    }
+
+    fn typescript(&self, indent: usize) -> String {
+        match self {
+            Self::Null => "null".into(),
+            Self::Integer => "number".into(),
+            Self::String | Self::Hex { .. } => "string".into(),
+            Self::Literal(value) => format!("{value:?}"),
+            Self::Array(items) => match **items {
+                Self::Nullable(_) | Self::OneOf(_) => format!("({})[]", items.typescript(indent)),
+                _ => format!("{}[]", items.typescript(indent)),
+            },
+            Self::Nullable(schema) => format!("{} | null", schema.typescript(indent)),
+            Self::Object(fields) => {
+                let pad = "  ".repeat(indent + 1);
+                let mut ts = "{\n".to_string();
+                for field in fields {
+                    let optional = if field.required { "" } else { "?" };
+                    writeln!(
+                        ts,
+                        "{pad}{}{optional}: {};",
+                        field.name,
+                        field.schema.typescript(indent + 1)
+                    )
+                    .unwrap();
+                }
+                ts + &"  ".repeat(indent) + "}"
+            }
+            Self::OneOf(schemas) => schemas
+                .iter()
+                .map(|schema| schema.typescript(indent))
+                .collect::<Vec<_>>()
+                .join(" | "),
+            Self::Ref(name) => name.to_string(),
+        }
+    }
+
+    /// Check that `value` has this shape, resolving references in `definitions`.
+    pub fn check(&self, value: &Value, definitions: &BTreeMap<&str, Schema>) -> Result<(), String> {
+        let mismatch = || Err(format!("{value} does not match {self:?}"));
+        match (self, value) {
+            (Self::Null, Value::Null) => Ok(()),
+            (Self::Integer, value) if value.is_u64() => Ok(()),
+            (Self::String, Value::String(_)) => Ok(()),
+            (Self::Hex { bytes }, Value::String(hex)) => {
+                let digits = hex.strip_prefix("0x").ok_or(format!("{hex} lacks 0x"))?;
+                if !digits.chars().all(|c| c.is_ascii_hexdigit())
+                    || bytes.is_some_and(|bytes| digits.len() != 2 * bytes)
+                {
+                    return mismatch();
+                }
+                Ok(())
+            }
+            (Self::Literal(expected), Value::String(actual)) if expected == actual => Ok(()),
+            (Self::Array(items), Value::Array(values)) => values
+                .iter()
+                .try_for_each(|value| items.check(value, definitions)),
+            (Self::Nullable(_), Value::Null) => Ok(()),
+            (Self::Nullable(schema), value) => schema.check(value, definitions),
+            (Self::Object(fields), Value::Object(map)) => {
+                if let Some(key) = map
+                    .keys()
+                    .find(|key| !fields.iter().any(|field| field.name == key.as_str()))
+                {
+                    return Err(format!("unexpected field {key} in {value}"));
+                }
+                for field in fields {
+                    match map.get(field.name) {
+                        Some(value) => field.schema.check(value, definitions)?,
+                        None if field.required => {
+                            return Err(format!("missing field {} in {value}", field.name))
+                        }
+                        None => {}
+                    }
+                }
+                Ok(())
+            }
+            (Self::OneOf(schemas), value) => {
+                if schemas
+                    .iter()
+                    .any(|schema| schema.check(value, definitions).is_ok())
+                {
+                    Ok(())
+                } else {
+                    mismatch()
+                }
+            }
+            (Self::Ref(name), value) => definitions
+                .get(name)
+                .ok_or(format!("undefined type {name}"))?
+                .check(value, definitions),
+            _ => mismatch(),
+        }
+    }
+}
+
+/// Client-side descriptions of the API payload types, served by `rollup/schema`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiSchema {
+    /// A JSON schema with a definition for each payload type under `$defs`.
+    pub json_schema: Value,
+    /// TypeScript definitions of the payload types.
+    pub typescript: String,
+}
+
+impl ApiSchema {
+    pub fn generate() -> Self {
+        let definitions = definitions();
+        let defs = definitions
+            .iter()
+            .map(|def| {
+                let mut schema = def.schema.json_schema();
+                schema["description"] = def.doc.into();
+                (def.name.to_string(), schema)
+            })
+            .collect::<Map<_, _>>();
+        let json_schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Example rollup API payloads",
+            "$defs": defs,
+        });
+
+        let mut typescript =
+            "// Types of the example rollup API payloads. Generated, do not edit.\n".to_string();
+        for def in &definitions {
+            write!(
+                typescript,
+                "\n/** {} */\nexport type {} = {};\n",
+                def.doc,
+                def.name,
+                def.schema.typescript(0)
+            )
+            .unwrap();
+        }
+        Self {
+            json_schema,
+            typescript,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainId;
+    use crate::error::RollupError;
+    use crate::events::{BlockEvent, RollupEvent, StreamMessage, SubscriptionRequest};
+    use crate::history::BlockInfo;
+    use crate::receipt::Receipt;
+    use crate::state::State;
+    use crate::stats::{BlockExecutionStats, ExecutionMetrics};
+    use crate::transaction::{SignedTransaction, Transaction, TransactionBuilder};
+    use crate::withdrawal::{Withdrawal, WithdrawalProof};
+    use crate::RollupVM;
+    use committable::Committable;
+    use espresso_types::NamespaceId;
+    use ethers::signers::LocalWallet;
+    use ethers::types::{Address, H256};
+
+    fn check<T: Serialize>(name: &'static str, value: &T) {
+        let definitions = definitions()
+            .into_iter()
+            .map(|def| (def.name, def.schema))
+            .collect::<BTreeMap<_, _>>();
+        let value = serde_json::to_value(value).unwrap();
+        if let Err(err) = Schema::Ref(name).check(&value, &definitions) {
+            panic!("{name} does not match its schema: {err}");
+        }
+    }
+
+    #[async_std::test]
+    async fn test_schema_matches_serialization() {
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let address = Address::random();
+        let transaction = SignedTransaction::new(
+            Transaction {
+                amount: 10,
+                destination: address,
+                nonce: 1,
+                ..Default::default()
+            },
+            &wallet,
+        )
+        .await;
+        check("SignedTransaction", &transaction);
+        let chain_transaction = SignedTransaction::new_for_chain(
+            transaction.transaction.clone(),
+            ChainId(H256::random()),
+            &wallet,
+        )
+        .await;
+        check("SignedTransaction", &chain_transaction);
+        let full_transaction = TransactionBuilder::new()
+            .amount(10)
+            .destination(address)
+            .nonce(2)
+            .memo("rent")
+            .fee(1)
+            .expires_at(100)
+            .sign(&wallet)
+            .await
+            .unwrap();
+        check("SignedTransaction", &full_transaction);
+        let withdrawal = TransactionBuilder::new()
+            .amount(10)
+            .destination(address)
+            .nonce(3)
+            .withdraw()
+            .sign(&wallet)
+            .await
+            .unwrap();
+        check("SignedTransaction", &withdrawal);
+
+        let errors = [
+            RollupError::SignatureError,
+            RollupError::InvalidTransaction,
+            RollupError::InsufficientBalance { address },
+            RollupError::InvalidNonce {
+                address,
+                expected: 2,
+                actual: 1,
+            },
+            RollupError::DuplicateTransaction {
+                address,
+                hash: H256::random(),
+            },
+            RollupError::MalformedTransaction {
+                reason: "bad".into(),
+            },
+            RollupError::TransactionTooLarge { size: 2, max: 1 },
+            RollupError::TransactionExpired {
+                hash: H256::random(),
+                expires_at: 3,
+            },
+            RollupError::WrongChain {
+                expected: ChainId(H256::random()),
+                actual: ChainId(H256::random()),
+            },
+            RollupError::MissingChainId {
+                expected: ChainId(H256::random()),
+            },
+            RollupError::ExpiryOutsideReplayWindow {
+                hash: H256::random(),
+                max: 3,
+            },
+            RollupError::AccountFrozen { address },
+            RollupError::PolicyViolation {
+                policy: "policy".into(),
+                reason: "bad".into(),
+            },
+            RollupError::UntrustedSubmission {
+                reason: "bad".into(),
+            },
+            RollupError::UnknownTransactionKind { kind: 2 },
+            RollupError::UnbalancedSpend {
+                inputs: 1,
+                outputs: 2,
+            },
+            RollupError::UnknownNote {
+                note: H256::random(),
+            },
+            RollupError::NoteNotOwned {
+                note: H256::random(),
+                address,
+            },
+            RollupError::BalanceOverflow { address },
+        ];
+        for err in &errors {
+            check("RollupError", err);
+        }
+
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let commitment = State::from_initial_balances([(address, 1)], vm).commit();
+        for result in [Ok(()), Err(errors[2].clone())] {
+            let receipt = Receipt {
+                hash: H256::random(),
+                block_height: 1,
+                view_number: Some(3),
+                block_timestamp: 1700000000,
+                index: 0,
+                result,
+                prev_state_commitment: commitment,
+                state_commitment: commitment,
+                timings: Default::default(),
+                metrics: Default::default(),
+                chain_id: None,
+            };
+            check("Receipt", &receipt);
+        }
+        check(
+            "BlockExecutionStats",
+            &BlockExecutionStats::new(
+                1,
+                &[ExecutionMetrics {
+                    time_us: 12,
+                    signature_verifications: 1,
+                    state_reads: 3,
+                    state_writes: 2,
+                }],
+            ),
+        );
+
+        check(
+            "BlockInfo",
+            &BlockInfo {
+                block_height: 1,
+                view_number: None,
+                timestamp: 1700000000,
+            },
+        );
+
+        let event = BlockEvent {
+            seq: 0,
+            block_height: 1,
+            view_number: Some(3),
+            timestamp: 1700000000,
+            event: RollupEvent::Transfer {
+                from: address,
+                to: Address::random(),
+                amount: 5,
+            },
+        };
+        check("StreamMessage", &StreamMessage::Event(event));
+        check(
+            "StreamMessage",
+            &StreamMessage::Subscribed {
+                subscription_id: 1,
+                next_seq: 2,
+            },
+        );
+        check("RollupEvent", &RollupEvent::AccountCreated { address });
+        check(
+            "RollupEvent",
+            &RollupEvent::Burn {
+                from: address,
+                amount: 1,
+            },
+        );
+        check(
+            "RollupEvent",
+            &RollupEvent::Withdrawal {
+                from: address,
+                recipient: Address::random(),
+                amount: 1,
+                index: 0,
+            },
+        );
+        check(
+            "RollupEvent",
+            &RollupEvent::Deposit {
+                to: address,
+                amount: 1,
+            },
+        );
+        check(
+            "Supply",
+            &State::from_initial_balances([(address, 1)], vm).supply(),
+        );
+        check(
+            "WithdrawalProof",
+            &WithdrawalProof::new(
+                &[Withdrawal {
+                    recipient: address,
+                    amount: 1,
+                }],
+                0,
+            )
+            .unwrap(),
+        );
+        check(
+            "SubscriptionRequest",
+            &SubscriptionRequest::Resume {
+                subscription_id: 1,
+                from_seq: 2,
+            },
+        );
+        check(
+            "SubscriptionRequest",
+            &SubscriptionRequest::New(Default::default()),
+        );
+    }
+
+    #[test]
+    fn test_generated_schema() {
+        let schema = ApiSchema::generate();
+        let defs = schema.json_schema["$defs"].as_object().unwrap();
+        assert_eq!(defs.len(), definitions().len());
+        assert_eq!(
+            defs["SignedTransaction"]["properties"]["transaction"]["$ref"],
+            "#/$defs/Transaction"
+        );
+        assert!(schema.typescript.contains(
+            "export type Transaction = {\n  amount: number;\n  destination: Address;\n  nonce: number;\n};"
+        ));
+        assert!(schema.typescript.contains(
+            "export type TransactionResult = {\n  Ok: null;\n} | {\n  Err: RollupError;\n};"
+        ));
+        assert!(schema.typescript.contains("  min_amount?: number | null;"));
+    }
+}
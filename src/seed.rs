@@ -5,14 +5,92 @@
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
 use clap::ValueEnum;
+use ethers::signers::LocalWallet;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 pub const INITIAL_BALANCE: u64 = 9999;
 
-#[derive(ValueEnum, Clone, Copy, Debug, EnumIter)]
+#[derive(ValueEnum, Clone, Copy, Debug, EnumIter, Serialize, Deserialize)]
 #[value(rename_all = "verbatim")]
 pub enum SeedIdentity {
     Bob = 0,
     Alice = 1,
     Charlie = 2,
 }
+
+impl SeedIdentity {
+    /// The wallet of this identity.
+    pub fn wallet(self) -> LocalWallet {
+        seed_wallet(self as u64)
+    }
+}
+
+/// Deterministically derive the wallet of the seed account at `index`.
+///
+/// The first accounts are those of the named [`SeedIdentity`]s.
+pub fn seed_wallet(index: u64) -> LocalWallet {
+    LocalWallet::new(&mut ChaChaRng::seed_from_u64(index))
+}
+
+/// A seed account funded at genesis.
+#[derive(Clone, Debug)]
+pub struct SeedAccount {
+    pub index: u64,
+    /// The named identity of this account, if it has one.
+    pub identity: Option<SeedIdentity>,
+    pub wallet: LocalWallet,
+}
+
+impl SeedAccount {
+    /// Alias under which this account is registered in the address book.
+    pub fn alias(&self) -> String {
+        match self.identity {
+            Some(identity) => format!("{identity:?}.rollup"),
+            None => format!("seed{}.rollup", self.index),
+        }
+    }
+}
+
+/// The first `count` seed accounts.
+///
+/// The named identities are always included, even if `count` is smaller than the number of named
+/// identities.
+pub fn seed_accounts(count: u64) -> impl Iterator<Item = SeedAccount> {
+    let named = SeedIdentity::iter().count() as u64;
+    (0..count.max(named)).map(|index| SeedAccount {
+        index,
+        identity: SeedIdentity::iter().find(|identity| *identity as u64 == index),
+        wallet: seed_wallet(index),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::Signer;
+
+    #[test]
+    fn test_seed_accounts() {
+        // The named identities are included regardless of the requested count.
+        let accounts = seed_accounts(0).collect::<Vec<_>>();
+        assert_eq!(accounts.len(), 3);
+        for identity in SeedIdentity::iter() {
+            let account = &accounts[identity as usize];
+            assert_eq!(account.wallet.address(), identity.wallet().address());
+            assert_eq!(account.alias(), format!("{identity:?}.rollup"));
+        }
+
+        // Additional accounts are derived deterministically.
+        let accounts = seed_accounts(10).collect::<Vec<_>>();
+        assert_eq!(accounts.len(), 10);
+        assert_eq!(accounts[9].alias(), "seed9.rollup");
+        assert_eq!(
+            accounts[9].wallet.address(),
+            seed_accounts(10).last().unwrap().wallet.address()
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Keys used to sign the transactions which submit proofs to the L1.
+//!
+//! The executor signs proof submissions with any [`Signer`], so the key need not be held in
+//! memory. Besides a wallet derived from a mnemonic, which is convenient for development, a key
+//! held in AWS KMS can be used when the `aws-kms` feature is enabled.
+
+use crate::l1::L1Error;
+use crate::secret::Secret;
+use clap::ValueEnum;
+use ethers::{
+    signers::{coins_bip39::English, LocalWallet, MnemonicBuilder},
+    types::Address,
+};
+use serde::Serialize;
+use strum_macros::Display;
+
+pub use ethers::signers::Signer;
+
+/// Where the key used to sign proof submissions is held.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum L1SignerKind {
+    /// An in-memory wallet derived from the rollup mnemonic.
+    #[default]
+    Mnemonic,
+    /// A key held in AWS KMS. Requires the `aws-kms` feature.
+    #[cfg(feature = "aws-kms")]
+    AwsKms,
+}
+
+/// Configuration of the key used to sign proof submissions.
+#[derive(Clone, Debug)]
+pub enum L1SignerConfig {
+    Mnemonic {
+        mnemonic: Secret<String>,
+        account_index: u32,
+    },
+    /// A secp256k1 key in AWS KMS. The region and credentials are read from the environment.
+    #[cfg(feature = "aws-kms")]
+    AwsKms { key_id: String },
+}
+
+impl L1SignerConfig {
+    /// Derive the in-memory wallet for a [`Mnemonic`](Self::Mnemonic) signer.
+    ///
+    /// Returns `None` for signers whose key is held elsewhere.
+    pub fn local_wallet(&self) -> Option<Result<LocalWallet, L1Error>> {
+        match self {
+            Self::Mnemonic {
+                mnemonic,
+                account_index,
+            } => Some(
+                MnemonicBuilder::<English>::default()
+                    .phrase(mnemonic.expose().as_str())
+                    .index(*account_index)
+                    .and_then(|builder| builder.build())
+                    .map_err(|err| L1Error::Connection {
+                        message: format!("error opening wallet: {err}"),
+                    }),
+            ),
+            #[cfg(feature = "aws-kms")]
+            Self::AwsKms { .. } => None,
+        }
+    }
+
+    /// The address which signs proof submissions on chain `chain_id`, the only account the rollup
+    /// contract accepts proofs from.
+    pub async fn address(&self, chain_id: u64) -> Result<Address, L1Error> {
+        match self {
+            Self::Mnemonic { .. } => Ok(self
+                .local_wallet()
+                .unwrap()?
+                .with_chain_id(chain_id)
+                .address()),
+            #[cfg(feature = "aws-kms")]
+            Self::AwsKms { key_id } => Ok(aws_kms_signer(key_id, chain_id).await?.address()),
+        }
+    }
+}
+
+/// Connect to the AWS KMS key `key_id`, for signing transactions on chain `chain_id`.
+#[cfg(feature = "aws-kms")]
+pub async fn aws_kms_signer(key_id: &str, chain_id: u64) -> Result<impl Signer, L1Error> {
+    use ethers::signers::AwsSigner;
+    use rusoto_core::Region;
+    use rusoto_kms::KmsClient;
+
+    let client = KmsClient::new(Region::default());
+    AwsSigner::new(client, key_id, chain_id)
+        .await
+        .map_err(|err| L1Error::Connection {
+            message: format!("error connecting to AWS KMS key {key_id}: {err}"),
+        })
+}
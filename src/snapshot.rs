@@ -0,0 +1,337 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Export of the account state to a file while the node keeps running.
+//!
+//! An export starts from a copy-on-write view of the accounts (see
+//! [`Ledger::shared_accounts`](crate::ledger::Ledger::shared_accounts)), so it is consistent as of
+//! a single block and execution never waits for it. The export is written as JSON lines: a
+//! [`SnapshotHeader`] followed by one [`SnapshotAccount`] per account, ordered by address. Once
+//! written, the file is read back and the Merkle root of its accounts is checked against the root
+//! of the accounts it was exported from.
+
+use crate::balance_proof;
+use crate::state::{Account, Amount, Nonce, State};
+use async_std::sync::{Arc, RwLock};
+use async_std::task::{spawn, yield_now};
+use committable::Commitment;
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of accounts exported between progress updates.
+const PROGRESS_INTERVAL: usize = 1000;
+
+#[derive(Debug, Snafu)]
+pub enum SnapshotError {
+    #[snafu(display("Error accessing snapshot file {}: {source}", path.display()))]
+    Io { path: PathBuf, source: io::Error },
+    #[snafu(display("Malformed snapshot file {}: {source}", path.display()))]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[snafu(display("Snapshot file {} is empty.", path.display()))]
+    MissingHeader { path: PathBuf },
+    #[snafu(display(
+        "Snapshot file {} is corrupt: expected {expected} accounts with root {expected_root:?}, \
+         found {actual} accounts with root {actual_root:?}.",
+        path.display()
+    ))]
+    Corrupt {
+        path: PathBuf,
+        expected: usize,
+        actual: usize,
+        expected_root: H256,
+        actual_root: H256,
+    },
+    #[snafu(display("Snapshot export is not enabled on this node."))]
+    Disabled,
+    #[snafu(display("A snapshot export is already running."))]
+    Busy,
+}
+
+/// The first line of a snapshot file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    pub block_height: u64,
+    /// Commitment to the full state after `block_height`, if it was known at export time.
+    pub state_commitment: Option<Commitment<State>>,
+    /// Merkle root of the exported accounts, as computed by
+    /// [`State::accounts_root`](crate::state::State::accounts_root).
+    pub accounts_root: H256,
+    pub num_accounts: usize,
+}
+
+/// An account in a snapshot file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotAccount {
+    pub address: Address,
+    pub balance: Amount,
+    pub nonce: Nonce,
+}
+
+/// Progress of the most recent snapshot export.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ExportStatus {
+    /// No export has been started.
+    #[default]
+    Idle,
+    Running {
+        block_height: u64,
+        exported: usize,
+        total: usize,
+    },
+    /// The export was written and validated.
+    Complete {
+        path: PathBuf,
+        header: SnapshotHeader,
+    },
+    Failed {
+        block_height: u64,
+        message: String,
+    },
+}
+
+/// Runs snapshot exports in the background, one at a time.
+#[derive(Clone, Debug, Default)]
+pub struct SnapshotExporter {
+    /// Directory to which snapshots are written. Exports are disabled if this is not set.
+    dir: Option<PathBuf>,
+    status: Arc<RwLock<ExportStatus>>,
+}
+
+impl SnapshotExporter {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            status: Default::default(),
+        }
+    }
+
+    pub async fn status(&self) -> ExportStatus {
+        self.status.read().await.clone()
+    }
+
+    /// Start exporting the accounts of `state` in the background.
+    ///
+    /// Only a constant-time copy-on-write view of the state is taken here, so `state` may be
+    /// updated as soon as this returns. Returns the path the snapshot will be written to.
+    pub async fn start(
+        &self,
+        state: &State,
+        state_commitment: Option<Commitment<State>>,
+    ) -> Result<PathBuf, SnapshotError> {
+        let dir = self.dir.as_ref().ok_or(SnapshotError::Disabled)?;
+        let block_height = state.block_height();
+        let accounts = state.ledger().shared_accounts();
+        let path = dir.join(format!("snapshot-{block_height}.jsonl"));
+        {
+            let mut status = self.status.write().await;
+            if matches!(*status, ExportStatus::Running { .. }) {
+                return Err(SnapshotError::Busy);
+            }
+            *status = ExportStatus::Running {
+                block_height,
+                exported: 0,
+                total: accounts.len(),
+            };
+        }
+
+        let status = self.status.clone();
+        let export_path = path.clone();
+        spawn(async move {
+            let result = export(
+                &export_path,
+                block_height,
+                state_commitment,
+                &accounts,
+                &status,
+            )
+            .await;
+            *status.write().await = match result {
+                Ok(header) => {
+                    tracing::info!(
+                        "Exported snapshot of {} accounts at block {block_height} to {}",
+                        header.num_accounts,
+                        export_path.display()
+                    );
+                    ExportStatus::Complete {
+                        path: export_path,
+                        header,
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Snapshot export at block {block_height} failed: {err}");
+                    ExportStatus::Failed {
+                        block_height,
+                        message: err.to_string(),
+                    }
+                }
+            };
+        });
+        Ok(path)
+    }
+}
+
+/// Write `accounts` to a snapshot file at `path` and validate the result.
+async fn export(
+    path: &Path,
+    block_height: u64,
+    state_commitment: Option<Commitment<State>>,
+    accounts: &BTreeMap<Address, Account>,
+    status: &RwLock<ExportStatus>,
+) -> Result<SnapshotHeader, SnapshotError> {
+    let header = SnapshotHeader {
+        block_height,
+        state_commitment,
+        accounts_root: accounts_root(accounts.iter().map(|(address, account)| SnapshotAccount {
+            address: *address,
+            balance: account.balance,
+            nonce: account.nonce,
+        })),
+        num_accounts: accounts.len(),
+    };
+
+    let tmp = path.with_extension("tmp");
+    let file = File::create(&tmp).context(IoSnafu { path: &tmp })?;
+    let mut writer = BufWriter::new(file);
+    write_line(&mut writer, &tmp, &header)?;
+    for (i, (address, account)) in accounts.iter().enumerate() {
+        let account = SnapshotAccount {
+            address: *address,
+            balance: account.balance,
+            nonce: account.nonce,
+        };
+        write_line(&mut writer, &tmp, &account)?;
+        if (i + 1) % PROGRESS_INTERVAL == 0 {
+            *status.write().await = ExportStatus::Running {
+                block_height,
+                exported: i + 1,
+                total: accounts.len(),
+            };
+            yield_now().await;
+        }
+    }
+    writer
+        .into_inner()
+        .map_err(|err| err.into_error())
+        .and_then(|file| file.sync_all())
+        .context(IoSnafu { path: &tmp })?;
+
+    let written = validate(&tmp)?;
+    if written != header {
+        return Err(SnapshotError::Corrupt {
+            path: tmp,
+            expected: header.num_accounts,
+            actual: written.num_accounts,
+            expected_root: header.accounts_root,
+            actual_root: written.accounts_root,
+        });
+    }
+    std::fs::rename(&tmp, path).context(IoSnafu { path })?;
+    Ok(header)
+}
+
+fn write_line(
+    writer: &mut impl Write,
+    path: &Path,
+    value: &impl Serialize,
+) -> Result<(), SnapshotError> {
+    serde_json::to_writer(&mut *writer, value).context(JsonSnafu { path })?;
+    writeln!(writer).context(IoSnafu { path })
+}
+
+/// Read the snapshot file at `path`, checking that its accounts match its header.
+pub fn validate(path: &Path) -> Result<SnapshotHeader, SnapshotError> {
+    let file = File::open(path).context(IoSnafu { path })?;
+    let mut lines = BufReader::new(file).lines();
+    let header_line = lines
+        .next()
+        .ok_or_else(|| SnapshotError::MissingHeader { path: path.into() })?
+        .context(IoSnafu { path })?;
+    let header: SnapshotHeader = serde_json::from_str(&header_line).context(JsonSnafu { path })?;
+    let accounts = lines
+        .map(|line| {
+            let line = line.context(IoSnafu { path })?;
+            serde_json::from_str::<SnapshotAccount>(&line).context(JsonSnafu { path })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Leaves are ordered by address, so an out of order file cannot reproduce the root.
+    let ordered = accounts
+        .windows(2)
+        .all(|pair| pair[0].address < pair[1].address);
+    let root = accounts_root(accounts.iter().copied());
+    if !ordered || accounts.len() != header.num_accounts || root != header.accounts_root {
+        return Err(SnapshotError::Corrupt {
+            path: path.into(),
+            expected: header.num_accounts,
+            actual: accounts.len(),
+            expected_root: header.accounts_root,
+            actual_root: root,
+        });
+    }
+    Ok(header)
+}
+
+fn accounts_root(accounts: impl Iterator<Item = SnapshotAccount>) -> H256 {
+    let leaves = accounts
+        .map(|account| balance_proof::leaf_hash(&account.address, account.balance, account.nonce))
+        .collect();
+    balance_proof::tree_levels(leaves).last().unwrap()[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RollupVM;
+    use espresso_types::NamespaceId;
+
+    #[async_std::test]
+    async fn test_snapshot_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let balances = (0..2500).map(|i| (Address::random(), i));
+        let state = State::from_initial_balances(balances, vm);
+
+        let exporter = SnapshotExporter::new(Some(dir.path().into()));
+        let path = exporter.start(&state, None).await.unwrap();
+        let header = loop {
+            match exporter.status().await {
+                ExportStatus::Complete { header, .. } => break header,
+                ExportStatus::Failed { message, .. } => panic!("{message}"),
+                _ => yield_now().await,
+            }
+        };
+        assert_eq!(header.num_accounts, 2500);
+        assert_eq!(header.accounts_root, state.accounts_root());
+        assert_eq!(validate(&path).unwrap(), header);
+
+        // Tampering with an account is detected.
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+        let mut account: SnapshotAccount = serde_json::from_str(&lines[1]).unwrap();
+        account.balance += 1;
+        lines[1] = serde_json::to_string(&account).unwrap();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        assert!(matches!(
+            validate(&path),
+            Err(SnapshotError::Corrupt { .. })
+        ));
+
+        // Exports are disabled without a directory.
+        assert!(matches!(
+            SnapshotExporter::default().start(&state, None).await,
+            Err(SnapshotError::Disabled)
+        ));
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Continuous spot checks of the sequencer data the rollup executed.
+//!
+//! The executor checks each namespace proof as it executes a block, and never looks at the data
+//! again. The spot-audit task periodically picks a random block which the node has executed,
+//! refetches its header, namespace proof and VID common data from the query service, checks the
+//! proof against the header, and compares the transactions it proves with those the node recorded
+//! executing in its [`ReceiptIndex`]. A difference means either that the query service now serves
+//! different data for a decided block, or that the node executed something other than what was
+//! sequenced. Either is logged as an error and recorded in a [`SpotAuditLog`] served by the API.
+
+use crate::clock::Clock;
+use crate::data_source::SequencerDataSource;
+use crate::random::DemoRng;
+use crate::receipt::ReceiptIndex;
+use crate::state::OrderingPolicy;
+use crate::transaction::SignedTransaction;
+use async_std::sync::{Arc, RwLock};
+use espresso_types::NamespaceId;
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::time::Duration;
+
+/// Maximum number of findings retained by a [`SpotAuditLog`].
+pub const MAX_SPOT_AUDIT_FINDINGS: usize = 100;
+
+/// A block whose refetched data does not match what the node executed.
+#[derive(Clone, Debug, PartialEq, Eq, Snafu, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum SpotAuditFinding {
+    /// The namespace proof served for the block does not verify against its header.
+    #[snafu(display("Namespace proof for block {height} does not verify against its header"))]
+    InvalidProof { height: u64 },
+    /// The block proves different transactions, or the same transactions in a different order,
+    /// from those the node executed.
+    #[snafu(display(
+        "Block {height} contains transactions {sequenced:?}, but the node executed {executed:?}"
+    ))]
+    TransactionMismatch {
+        height: u64,
+        executed: Vec<H256>,
+        sequenced: Vec<H256>,
+    },
+}
+
+/// The result of auditing a single block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpotAuditOutcome {
+    /// The block's data verifies and matches what the node executed.
+    Verified,
+    /// The block could not be audited, because it has not been executed or its data is
+    /// unavailable from the query service.
+    Unavailable,
+    Failed(SpotAuditFinding),
+}
+
+/// Summary of the spot audits run so far.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpotAuditReport {
+    /// Number of blocks audited, whether or not they matched.
+    pub audited: u64,
+    /// Number of sampled blocks which could not be audited.
+    pub unavailable: u64,
+    /// Recent findings, oldest first.
+    pub findings: Vec<SpotAuditFinding>,
+}
+
+/// Results of the spot-audit task, shared with the API.
+#[derive(Clone, Debug, Default)]
+pub struct SpotAuditLog {
+    report: Arc<RwLock<SpotAuditReport>>,
+}
+
+impl SpotAuditLog {
+    async fn record(&self, outcome: SpotAuditOutcome) {
+        let mut report = self.report.write().await;
+        match outcome {
+            SpotAuditOutcome::Verified => report.audited += 1,
+            SpotAuditOutcome::Unavailable => report.unavailable += 1,
+            SpotAuditOutcome::Failed(finding) => {
+                report.audited += 1;
+                report.findings.push(finding);
+                if report.findings.len() > MAX_SPOT_AUDIT_FINDINGS {
+                    report.findings.remove(0);
+                }
+            }
+        }
+    }
+
+    pub async fn report(&self) -> SpotAuditReport {
+        self.report.read().await.clone()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SpotAuditOptions {
+    /// The rollup namespace.
+    pub namespace: NamespaceId,
+    /// The order in which the node executes the transactions of a block.
+    pub ordering_policy: OrderingPolicy,
+    /// Delay between audits.
+    pub interval: Duration,
+}
+
+/// Refetch the data of the block at `height` and compare it with what the node executed.
+pub async fn audit_block(
+    data_source: &dyn SequencerDataSource,
+    receipts: &ReceiptIndex,
+    options: &SpotAuditOptions,
+    height: u64,
+) -> SpotAuditOutcome {
+    let Some(executed) = receipts.block(height).await else {
+        return SpotAuditOutcome::Unavailable;
+    };
+    let Some(header) = data_source
+        .headers(height, height + 1)
+        .await
+        .and_then(|headers| headers.into_iter().next())
+    else {
+        return SpotAuditOutcome::Unavailable;
+    };
+
+    let transactions = match data_source.namespace_proof(height, options.namespace).await {
+        Some(proof) => {
+            let Some(vid_common) = data_source.vid_common(height).await else {
+                return SpotAuditOutcome::Unavailable;
+            };
+            let Some((transactions, _)) =
+                proof.verify(header.ns_table(), &header.payload_commitment(), &vid_common)
+            else {
+                return SpotAuditOutcome::Failed(SpotAuditFinding::InvalidProof { height });
+            };
+            transactions
+        }
+        // A block whose namespace table does not list the rollup has no rollup transactions.
+        None if header.ns_table().find_ns_id(&options.namespace).is_none() => vec![],
+        None => return SpotAuditOutcome::Unavailable,
+    };
+    let sequenced: Vec<_> = options
+        .ordering_policy
+        .order(transactions)
+        .iter()
+        .map(|txn| SignedTransaction::payload_hash(txn.payload()))
+        .collect();
+
+    if sequenced == executed {
+        SpotAuditOutcome::Verified
+    } else {
+        SpotAuditOutcome::Failed(SpotAuditFinding::TransactionMismatch {
+            height,
+            executed,
+            sequenced,
+        })
+    }
+}
+
+/// Periodically audit a randomly chosen executed block, recording the results in `log`.
+///
+/// Mismatches are also reported as errors in the logs.
+pub async fn run_spot_audit(
+    options: SpotAuditOptions,
+    data_source: Arc<dyn SequencerDataSource>,
+    receipts: ReceiptIndex,
+    log: SpotAuditLog,
+    rng: DemoRng,
+    clock: Arc<dyn Clock>,
+) {
+    loop {
+        clock.sleep(options.interval).await;
+        let Some(height) = receipts.random_block(&rng).await else {
+            continue;
+        };
+        let outcome = audit_block(&*data_source, &receipts, &options, height).await;
+        match &outcome {
+            SpotAuditOutcome::Verified => {
+                tracing::debug!("Spot audit of block {height} passed");
+            }
+            SpotAuditOutcome::Unavailable => {
+                tracing::warn!("Unable to spot audit block {height}, data is unavailable");
+            }
+            SpotAuditOutcome::Failed(finding) => {
+                tracing::error!("Spot audit failed: {finding}");
+            }
+        }
+        log.record(outcome).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_source::MockDataSource;
+    use crate::fixtures::mock_block;
+    use crate::receipt::Receipt;
+    use crate::state::State;
+    use crate::RollupVM;
+    use committable::Committable;
+
+    #[async_std::test]
+    async fn test_spot_audit() {
+        let namespace = NamespaceId::from(1_u64);
+        let other = NamespaceId::from(2_u64);
+        let options = SpotAuditOptions {
+            namespace,
+            ordering_policy: OrderingPolicy::Namespace,
+            interval: Duration::from_secs(1),
+        };
+        let payloads = vec![b"first".to_vec(), b"second".to_vec()];
+        let hashes: Vec<_> = payloads
+            .iter()
+            .map(|payload| SignedTransaction::payload_hash(payload))
+            .collect();
+
+        let data_source = MockDataSource::default();
+        let block = mock_block(namespace, &[(namespace, payloads.clone())]).await;
+        data_source.push(block.clone());
+        data_source.push(block.clone());
+        data_source.push(mock_block(namespace, &[(other, vec![b"other".to_vec()])]).await);
+        // A proof for the rollup namespace served alongside the header of a different block.
+        let mut forged = mock_block(namespace, &[(namespace, vec![b"forged".to_vec()])]).await;
+        forged.namespace_proof = block.namespace_proof.clone();
+        data_source.push(forged);
+
+        let commitment = State::from_initial_balances([], RollupVM::new(namespace)).commit();
+        let executed_hashes = |hashes: &[H256]| {
+            hashes
+                .iter()
+                .enumerate()
+                .map(|(index, hash)| Receipt {
+                    hash: *hash,
+                    block_height: 0,
+                    view_number: None,
+                    block_timestamp: 0,
+                    index,
+                    result: Ok(()),
+                    prev_state_commitment: commitment,
+                    state_commitment: commitment,
+                    timings: Default::default(),
+                    metrics: Default::default(),
+                    chain_id: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let receipts = ReceiptIndex::default();
+        receipts.insert_block(0, executed_hashes(&hashes)).await;
+        // The node executed the transactions of block 1 in the wrong order.
+        let reversed: Vec<_> = hashes.iter().rev().copied().collect();
+        receipts.insert_block(1, executed_hashes(&reversed)).await;
+        receipts.insert_block(2, vec![]).await;
+        receipts
+            .insert_block(3, executed_hashes(&hashes[..1]))
+            .await;
+
+        let log = SpotAuditLog::default();
+        for height in 0..5 {
+            let outcome = audit_block(&data_source, &receipts, &options, height).await;
+            let expected = match height {
+                0 | 2 => SpotAuditOutcome::Verified,
+                1 => SpotAuditOutcome::Failed(SpotAuditFinding::TransactionMismatch {
+                    height,
+                    executed: reversed.clone(),
+                    sequenced: hashes.clone(),
+                }),
+                3 => SpotAuditOutcome::Failed(SpotAuditFinding::InvalidProof { height }),
+                // Not executed.
+                _ => SpotAuditOutcome::Unavailable,
+            };
+            assert_eq!(outcome, expected, "block {height}");
+            log.record(outcome).await;
+        }
+
+        let report = log.report().await;
+        assert_eq!((report.audited, report.unavailable), (4, 1));
+        assert_eq!(report.findings.len(), 2);
+    }
+}
@@ -4,44 +4,337 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
-use crate::error::RollupError;
-use crate::prover::Proof;
-use crate::transaction::SignedTransaction;
+use crate::balance_proof::{self, BalanceProof};
+use crate::chain::ChainId;
+use crate::deposit::Deposit;
+use crate::error::{DeterminismError, RollupError};
+use crate::events::{self, RollupEvent};
+use crate::history::HistoryError;
+use crate::hooks::{self, StateAccess, TransactionHooks};
+use crate::ledger::{Ledger, LedgerEvent, LedgerSnapshot};
+use crate::machine::RollupStateMachine;
+use crate::prover::{BlockInputs, Proof, ProofVerifier};
+use crate::stats::ExecutionMetrics;
+use crate::transaction::{SignedTransaction, Submission, TransactionKind};
+use crate::withdrawal::{Withdrawal, WithdrawalProof, WithdrawalsRoot};
 use crate::RollupVM;
+use async_std::sync::{Arc, RwLock};
+use clap::ValueEnum;
 use committable::{Commitment, Committable};
-use espresso_types::{Header, NsProof, SeqTypes};
+use espresso_types::{Header, NamespaceId, NsProof, SeqTypes, Transaction};
 use ethers::abi::Address;
+use ethers::types::H256;
 use hotshot_query_service::availability::BlockHash;
 use hotshot_query_service::VidCommon;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Instant;
+use strum_macros::Display;
 
 pub type Amount = u64;
 pub type Nonce = u64;
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+/// How the VM prevents a transaction from being executed more than once.
+///
+/// The mode is fixed at genesis and is part of the state commitment.
+#[derive(
+    ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplayProtection {
+    /// Each transaction must carry the sender's next nonce.
+    #[default]
+    Nonce,
+    /// Transactions are rejected if the same transaction from the same sender was executed within
+    /// the replay window. The transaction nonce is treated as an arbitrary salt, so stateless
+    /// clients can submit concurrently without coordinating nonces. Every transaction must expire
+    /// within the replay window, so it cannot be replayed once it is forgotten.
+    RecentHashes,
+}
+
+/// How the VM handles transactions which were not countersigned by the operator.
+#[derive(
+    ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum UntrustedSubmissions {
+    /// Execute untrusted transactions subject to stricter limits: they must be at most
+    /// [`MAX_UNTRUSTED_TRANSACTION_SIZE`] bytes, and each sender may have at most one untrusted
+    /// transaction in a block.
+    #[default]
+    Strict,
+    /// Reject every untrusted transaction.
+    Reject,
+}
+
+/// The order in which the VM executes the transactions in the rollup namespace of a block.
+///
+/// The policy is fixed at genesis and is part of the state commitment.
+#[derive(
+    ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum OrderingPolicy {
+    /// Execute transactions in the order they appear in the namespace, as sequenced.
+    #[default]
+    Namespace,
+    /// Execute transactions sorted by sender address, then by nonce. Transactions from the same
+    /// sender with the same nonce keep their namespace order. Payloads without a recoverable
+    /// sender, such as malformed or custom transactions, are executed after every sorted
+    /// transaction, in namespace order.
+    ///
+    /// The execution order then depends only on the set of transactions in the block, so a sender
+    /// can submit several transactions at once without them failing if they are sequenced out of
+    /// nonce order.
+    SenderNonce,
+}
+
+impl OrderingPolicy {
+    /// Arrange the transactions of a block in execution order.
+    pub fn order(self, transactions: Vec<Transaction>) -> Vec<Transaction> {
+        match self {
+            Self::Namespace => transactions,
+            Self::SenderNonce => {
+                let (mut sorted, unsorted): (Vec<_>, Vec<_>) = transactions
+                    .into_iter()
+                    .map(|txn| (Self::sort_key(&txn), txn))
+                    .partition(|(key, _)| key.is_some());
+                // `sort_by_key` is stable, so ties keep their namespace order.
+                sorted.sort_by_key(|(key, _)| *key);
+                sorted
+                    .into_iter()
+                    .chain(unsorted)
+                    .map(|(_, txn)| txn)
+                    .collect()
+            }
+        }
+    }
+
+    fn sort_key(txn: &Transaction) -> Option<(Address, Nonce)> {
+        if hooks::decode_custom(txn.payload()).is_some() {
+            return None;
+        }
+        let submission = Submission::decode(txn.payload()).ok()?;
+        let transaction = submission.transaction();
+        Some((transaction.recover().ok()?, transaction.transaction.nonce))
+    }
+}
+
+/// Maximum size, in bytes, of a transaction which was not countersigned by the operator.
+pub const MAX_UNTRUSTED_TRANSACTION_SIZE: usize = 512;
+
+/// Which transactions in the rollup namespace the VM trusts.
+///
+/// Anyone can post data to the rollup namespace, bypassing the checks made by the rollup API. If an
+/// operator is configured, the API countersigns the transactions it submits, and transactions
+/// without the operator's countersignature are treated as untrusted third-party submissions.
+///
+/// The policy is fixed at genesis and is part of the state commitment.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmissionPolicy {
+    /// The operator whose countersignature marks a transaction as trusted. If not set, every
+    /// transaction is trusted.
+    pub operator: Option<Address>,
+    pub untrusted: UntrustedSubmissions,
+    /// Share of each transaction fee which is burned instead of paid to the operator, in basis
+    /// points. Omitted when zero, so that the commitments of existing genesis states are unchanged.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub fee_burn_bps: u16,
+}
+
+/// Maximum value of [`SubmissionPolicy::fee_burn_bps`], which burns the whole fee.
+pub const MAX_FEE_BURN_BPS: u16 = 10_000;
+
+fn is_zero(bps: &u16) -> bool {
+    *bps == 0
+}
+
+impl SubmissionPolicy {
+    /// Split `fee` into the share paid to the operator and the share burned.
+    pub fn split_fee(&self, fee: Amount) -> (Amount, Amount) {
+        let bps = self.fee_burn_bps.min(MAX_FEE_BURN_BPS);
+        let burned = (fee as u128 * bps as u128 / MAX_FEE_BURN_BPS as u128) as Amount;
+        (fee - burned, burned)
+    }
+}
+
+/// The token supply of the rollup, served by `rollup/supply`.
+///
+/// `burned`, `fee_revenue` and `deposited` are part of the state commitment. `circulating` is the
+/// sum of the committed account balances, and `withdrawn` the sum of the committed withdrawals.
+/// Sums over many accounts, withdrawals or deposits may not fit in a single balance, so they are
+/// `u128`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Supply {
+    /// Tokens held by accounts.
+    pub circulating: u128,
+    /// Tokens destroyed by burning a share of transaction fees.
+    pub burned: Amount,
+    /// Transaction fees paid to the submission operator.
+    pub fee_revenue: Amount,
+    /// Tokens burned by withdrawals to the L1.
+    pub withdrawn: u128,
+    /// Tokens deposited from the L1, including any share refunded because it would have overflowed
+    /// the recipient's balance.
+    pub deposited: u128,
+}
+
+/// Index of the state commitment after each executed block.
+///
+/// When batch proofs are submitted with [`ProofShape::Compressed`](crate::prover::ProofShape),
+/// only a digest of these commitments is posted to the L1, so they are kept here to be served by
+/// the API.
+#[derive(Clone, Debug, Default)]
+pub struct CommitmentIndex {
+    blocks: Arc<RwLock<BTreeMap<u64, Commitment<State>>>>,
+}
+
+impl CommitmentIndex {
+    pub async fn insert(&self, block_height: u64, commitment: Commitment<State>) {
+        self.blocks.write().await.insert(block_height, commitment);
+    }
+
+    /// The state commitment after the block at `block_height`, or `None` if the block has not been
+    /// executed.
+    pub async fn get(&self, block_height: u64) -> Option<Commitment<State>> {
+        self.blocks.read().await.get(&block_height).copied()
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Account {
-    balance: Amount,
-    nonce: Nonce,
+    pub(crate) balance: Amount,
+    pub(crate) nonce: Nonce,
+}
+
+/// Minimum number of blocks between compactions of the account ledger. Each compaction discards
+/// events from blocks more than this many blocks old.
+const LEDGER_COMPACTION_INTERVAL: u64 = 100;
+
+/// The outcome of simulating a block of transactions against a copy of the state.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Simulation {
+    /// The result of applying each transaction, in order.
+    pub results: Vec<Result<(), RollupError>>,
+    /// The resources used by each transaction, in the same order.
+    pub metrics: Vec<ExecutionMetrics>,
+    /// Commitment to the state after applying every valid transaction.
+    ///
+    /// This does not include the block metadata which is updated when a real block is executed.
+    pub commitment: Commitment<State>,
+}
+
+/// The parties and amounts of a transfer in the most recent block, as submitted.
+///
+/// A summary is recorded for every transaction which decodes as a transfer, whether or not it
+/// executed successfully.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferSummary {
+    /// The sender, or `None` if the signature is invalid.
+    pub from: Option<Address>,
+    pub to: Address,
+    pub amount: Amount,
+    pub fee: Amount,
+}
+
+/// The part of a [`State`] which outlives a single block, from which the state can be rebuilt
+/// without re-executing the chain.
+///
+/// The results, events and metrics of the most recent block are not included, so a state rebuilt
+/// from a snapshot has the same commitment as the original but reports an empty latest block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    ledger: LedgerSnapshot,
+    prev_state_commitment: Option<Commitment<State>>,
+    namespace: NamespaceId,
+    block_hash: Option<BlockHash<SeqTypes>>,
+    events_root: [u8; 32],
+    block_height: u64,
+    replay_protection: ReplayProtection,
+    replay_window: u64,
+    recent_transactions: BTreeMap<Address, BTreeMap<H256, u64>>,
+    submission_policy: SubmissionPolicy,
+    ordering_policy: OrderingPolicy,
+    chain_id: Option<ChainId>,
+    block_timestamp: u64,
+    view_number: Option<u64>,
+    burned: Amount,
+    fee_revenue: Amount,
+    #[serde(default)]
+    withdrawals: Vec<Withdrawal>,
+    #[serde(default)]
+    deposited: u128,
+    #[serde(default)]
+    l1_deposit_block: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct State {
-    // Account state, represented as a BTreeMap so that we can obtain a canonical serialization of the data structure for the state commitment
+    // Account state, projected from a log of account events onto a BTreeMap so that we can obtain a canonical serialization of the data structure for the state commitment
     // A live rollup would likely represent accounts as a Sparse Merkle Tree instead of a BTreeMap.
     // Rollup clients would then be able to use merkle proofs to authenticate a subset of user balances
     // without knowledge of the entire account state. Such "light clients" are less constrained by bandwidth
     // because they do not need to constantly sync up with a full node.
-    accounts: BTreeMap<Address, Account>,
+    ledger: Ledger,
     prev_state_commitment: Option<Commitment<State>>, // Previous state commitment, used to create a chain linking state committments
     pub(crate) vm: RollupVM,
     block_hash: Option<BlockHash<SeqTypes>>, // Hash of most recent hotshot consensus block
+    block_events: Vec<RollupEvent>, // Events emitted by transactions in the most recent block
+    events_root: [u8; 32],          // Commitment to the events emitted in the most recent block
+    block_height: u64,              // Height of the most recent hotshot block executed
+    replay_protection: ReplayProtection,
+    // Number of blocks for which transaction hashes are remembered in `RecentHashes` mode.
+    replay_window: u64,
+    // Hashes of recently executed transactions by sender, with the height at which each executed.
+    recent_transactions: BTreeMap<Address, BTreeMap<H256, u64>>,
+    // Hash and result of each transaction in the most recent block, in execution order.
+    block_results: Vec<(H256, Result<(), RollupError>)>,
+    // Resources consumed by each transaction in the most recent block, in execution order. Not
+    // committed, since execution time varies between nodes.
+    block_metrics: Vec<ExecutionMetrics>,
+    // The transfer made by each transaction in the most recent block, in execution order, or
+    // `None` for transactions which are not transfers. Not committed, since it is derived from the
+    // block.
+    block_transfers: Vec<Option<TransferSummary>>,
+    // Resources consumed so far by the transaction being executed.
+    meter: ExecutionMetrics,
+    submission_policy: SubmissionPolicy,
+    ordering_policy: OrderingPolicy,
+    // The chain to which transactions must be bound, once known. Not committed, since it is derived
+    // from the genesis commitment.
+    chain_id: Option<ChainId>,
+    // Unix timestamp (in seconds) of the most recent HotShot block executed. Not committed, since
+    // it is derived from the header.
+    block_timestamp: u64,
+    // HotShot view in which the most recent block executed was decided, if known. Not committed,
+    // since it is metadata fetched alongside the block.
+    view_number: Option<u64>,
+    // Senders of untrusted transactions in the most recent block.
+    untrusted_senders: BTreeSet<Address>,
+    // Total fees burned and paid to the operator since genesis.
+    burned: Amount,
+    fee_revenue: Amount,
+    // Every withdrawal to the L1 since genesis, in execution order. Committed through the root of
+    // the withdrawal tree.
+    withdrawals: Vec<Withdrawal>,
+    // Total tokens deposited from the L1 since genesis, including refunds.
+    deposited: u128,
+    // The last L1 block whose deposits have been credited.
+    l1_deposit_block: u64,
+    // Deposits to credit at the start of the next block, with the L1 block through which they were
+    // collected. Not committed, since they are credited before the next commitment is computed.
+    staged_deposits: Option<(u64, Vec<Deposit>)>,
+    // Handlers for custom transaction kinds.
+    hooks: TransactionHooks,
 }
 
 impl Committable for State {
     fn commit(&self) -> Commitment<State> {
         let serialized_accounts =
-            serde_json::to_string(&self.accounts).expect("Serialization should not fail");
+            serde_json::to_string(self.ledger.accounts()).expect("Serialization should not fail");
 
         committable::RawCommitmentBuilder::new("State Commitment")
             .array_field(
@@ -63,7 +356,29 @@ impl Committable for State {
                     .collect::<Vec<_>>(),
             )
             .var_size_field("accounts", serialized_accounts.as_bytes())
+            .fixed_size_field("events_root", &self.events_root)
+            .u64_field("replay_protection", self.replay_protection as u64)
+            .u64_field("replay_window", self.replay_window)
+            .var_size_field(
+                "submission_policy",
+                serde_json::to_string(&self.submission_policy)
+                    .expect("Serialization should not fail")
+                    .as_bytes(),
+            )
+            .u64_field("ordering_policy", self.ordering_policy as u64)
+            .var_size_field(
+                "recent_transactions",
+                serde_json::to_string(&self.recent_transactions)
+                    .expect("Serialization should not fail")
+                    .as_bytes(),
+            )
             .u64_field("Namespace", u64::from(self.vm.0))
+            .u64_field("burned", self.burned)
+            .u64_field("fee_revenue", self.fee_revenue)
+            .fixed_size_field("withdrawals_root", &self.withdrawals_root().root)
+            .u64_field("withdrawals", self.withdrawals.len() as u64)
+            .fixed_size_field("deposited", &self.deposited.to_le_bytes())
+            .u64_field("l1_deposit_block", self.l1_deposit_block)
             .finalize()
     }
 }
@@ -74,78 +389,535 @@ impl State {
         initial_balances: impl IntoIterator<Item = (Address, Amount)>,
         vm: RollupVM,
     ) -> Self {
-        let mut accounts = BTreeMap::new();
+        let mut ledger = Ledger::default();
         for (addr, amount) in initial_balances.into_iter() {
-            accounts.insert(
-                addr,
-                Account {
-                    balance: amount,
-                    nonce: 0,
-                },
-            );
+            ledger.record(0, LedgerEvent::Deposit { to: addr, amount });
         }
         State {
-            accounts,
+            ledger,
             block_hash: None,
             prev_state_commitment: None,
             vm,
+            block_events: vec![],
+            events_root: events::events_root(&[]),
+            block_height: 0,
+            replay_protection: ReplayProtection::Nonce,
+            replay_window: 0,
+            recent_transactions: BTreeMap::new(),
+            block_results: vec![],
+            block_metrics: vec![],
+            block_transfers: vec![],
+            meter: ExecutionMetrics::default(),
+            submission_policy: SubmissionPolicy::default(),
+            ordering_policy: OrderingPolicy::default(),
+            chain_id: None,
+            block_timestamp: 0,
+            view_number: None,
+            untrusted_senders: BTreeSet::new(),
+            burned: 0,
+            fee_revenue: 0,
+            withdrawals: vec![],
+            deposited: 0,
+            l1_deposit_block: 0,
+            staged_deposits: None,
+            hooks: TransactionHooks::default(),
+        }
+    }
+
+    /// Select the replay protection mode of a genesis state.
+    ///
+    /// `replay_window` is the number of blocks for which executed transactions are remembered in
+    /// [`ReplayProtection::RecentHashes`] mode, and is ignored otherwise.
+    pub fn with_replay_protection(mut self, mode: ReplayProtection, replay_window: u64) -> Self {
+        self.replay_protection = mode;
+        self.replay_window = replay_window;
+        self
+    }
+
+    /// Select which transactions a genesis state trusts.
+    pub fn with_submission_policy(mut self, policy: SubmissionPolicy) -> Self {
+        self.submission_policy = policy;
+        self
+    }
+
+    /// Select the order in which a genesis state executes the transactions in each block.
+    pub fn with_ordering_policy(mut self, policy: OrderingPolicy) -> Self {
+        self.ordering_policy = policy;
+        self
+    }
+
+    /// Execute custom transactions with `hooks`.
+    ///
+    /// Every node of the rollup must use the same hooks, since they determine the state transition.
+    pub fn with_transaction_hooks(mut self, hooks: TransactionHooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Export the state for [`from_snapshot`](Self::from_snapshot).
+    pub fn to_snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            ledger: self.ledger.to_snapshot(),
+            prev_state_commitment: self.prev_state_commitment,
+            namespace: self.vm.into(),
+            block_hash: self.block_hash,
+            events_root: self.events_root,
+            block_height: self.block_height,
+            replay_protection: self.replay_protection,
+            replay_window: self.replay_window,
+            recent_transactions: self.recent_transactions.clone(),
+            submission_policy: self.submission_policy,
+            ordering_policy: self.ordering_policy,
+            chain_id: self.chain_id,
+            block_timestamp: self.block_timestamp,
+            view_number: self.view_number,
+            burned: self.burned,
+            fee_revenue: self.fee_revenue,
+            withdrawals: self.withdrawals.clone(),
+            deposited: self.deposited,
+            l1_deposit_block: self.l1_deposit_block,
+        }
+    }
+
+    /// Rebuild a state from a snapshot.
+    ///
+    /// Transaction hooks are not part of the snapshot, and must be installed again with
+    /// [`with_transaction_hooks`](Self::with_transaction_hooks).
+    pub fn from_snapshot(snapshot: StateSnapshot) -> Self {
+        State {
+            ledger: Ledger::from_snapshot(snapshot.ledger),
+            prev_state_commitment: snapshot.prev_state_commitment,
+            vm: RollupVM::new(snapshot.namespace),
+            block_hash: snapshot.block_hash,
+            block_events: vec![],
+            events_root: snapshot.events_root,
+            block_height: snapshot.block_height,
+            replay_protection: snapshot.replay_protection,
+            replay_window: snapshot.replay_window,
+            recent_transactions: snapshot.recent_transactions,
+            block_results: vec![],
+            block_metrics: vec![],
+            block_transfers: vec![],
+            meter: ExecutionMetrics::default(),
+            submission_policy: snapshot.submission_policy,
+            ordering_policy: snapshot.ordering_policy,
+            chain_id: snapshot.chain_id,
+            block_timestamp: snapshot.block_timestamp,
+            view_number: snapshot.view_number,
+            untrusted_senders: BTreeSet::new(),
+            burned: snapshot.burned,
+            fee_revenue: snapshot.fee_revenue,
+            withdrawals: snapshot.withdrawals,
+            deposited: snapshot.deposited,
+            l1_deposit_block: snapshot.l1_deposit_block,
+            staged_deposits: None,
+            hooks: TransactionHooks::default(),
+        }
+    }
+
+    pub fn submission_policy(&self) -> SubmissionPolicy {
+        self.submission_policy
+    }
+
+    /// The circulating supply, and the fees burned, the fees paid to the operator and the tokens
+    /// withdrawn and deposited since genesis.
+    pub fn supply(&self) -> Supply {
+        Supply {
+            circulating: self.total_balance(),
+            burned: self.burned,
+            fee_revenue: self.fee_revenue,
+            withdrawn: self
+                .withdrawals
+                .iter()
+                .map(|withdrawal| withdrawal.amount as u128)
+                .sum(),
+            deposited: self.deposited,
+        }
+    }
+
+    /// The rollup chain to which this state is bound, if it is known.
+    pub fn chain_id(&self) -> Option<ChainId> {
+        self.chain_id
+    }
+
+    pub fn ordering_policy(&self) -> OrderingPolicy {
+        self.ordering_policy
+    }
+
+    pub fn replay_protection(&self) -> ReplayProtection {
+        self.replay_protection
+    }
+
+    pub fn replay_window(&self) -> u64 {
+        self.replay_window
+    }
+
+    /// Height of the most recently executed HotShot block.
+    pub fn block_height(&self) -> u64 {
+        self.block_height
+    }
+
+    /// Unix timestamp (in seconds) of the most recently executed HotShot block.
+    pub fn block_timestamp(&self) -> u64 {
+        self.block_timestamp
+    }
+
+    /// HotShot view in which the most recently executed block was decided, if known.
+    pub fn view_number(&self) -> Option<u64> {
+        self.view_number
+    }
+
+    /// Check that `transaction` was signed for the chain this state is bound to, if any.
+    ///
+    /// Once the state is bound, a transaction signed without a chain ID is rejected, since the same
+    /// signature would be valid on every other rollup.
+    pub(crate) fn check_chain(&self, transaction: &SignedTransaction) -> Result<(), RollupError> {
+        match (self.chain_id, transaction.chain_id()) {
+            (Some(expected), Some(actual)) if expected != actual => {
+                Err(RollupError::WrongChain { expected, actual })
+            }
+            (Some(expected), None) => Err(RollupError::MissingChainId { expected }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that `transaction` has not expired.
+    ///
+    /// In [`ReplayProtection::RecentHashes`] mode, a transaction is only remembered for the replay
+    /// window after it executes, so it must also expire within the replay window. Otherwise it could
+    /// be replayed once it is forgotten.
+    pub(crate) fn check_expiry(
+        &self,
+        transaction: &SignedTransaction,
+        hash: H256,
+    ) -> Result<(), RollupError> {
+        let expires_at = transaction.transaction.expires_at;
+        if let Some(expires_at) = expires_at {
+            if self.block_height >= expires_at {
+                return Err(RollupError::TransactionExpired { hash, expires_at });
+            }
+        }
+        if self.replay_protection == ReplayProtection::RecentHashes {
+            let max = self.block_height + self.replay_window;
+            if !expires_at.is_some_and(|expires_at| expires_at <= max) {
+                return Err(RollupError::ExpiryOutsideReplayWindow { hash, max });
+            }
         }
+        Ok(())
     }
 
     /// If the transaction is valid, transition the state and return the new state with updated balances.
     ///
     /// A transaction is valid iff
+    /// 0) If the state is bound to a chain, the transaction was signed for that chain
     /// 1) The signature on the transaction
-    /// 2) The nonce of the transaction is greater than the sender nonce (this prevent replay attacks)
-    /// 3) The sender has a high enough balance to cover the transfer amount
+    /// 2) If the transaction has an expiry height, the current block is below it. In
+    ///    [`ReplayProtection::RecentHashes`] mode, the transaction must have an expiry height within
+    ///    the replay window
+    /// 3) The transaction is not a replay. Depending on the [`ReplayProtection`] mode, either the
+    ///    nonce of the transaction is one greater than the sender nonce, or the same transaction has
+    ///    not been executed within the replay window
+    /// 4) The sender has a high enough balance to cover the transfer amount plus the fee
+    /// 5) Crediting the transfer amount to the destination, and the operator's share of the fee to
+    ///    the operator, does not overflow either balance
+    ///
+    /// A valid [`TransactionKind::Transfer`] moves the amount to the destination account. A valid
+    /// [`TransactionKind::Withdraw`] burns the amount instead, and appends a [`Withdrawal`] of it
+    /// to the destination address on the L1.
+    ///
+    /// The fee of a valid transaction is paid to the operator of the [`SubmissionPolicy`], less the
+    /// share burned according to [`SubmissionPolicy::fee_burn_bps`]. If no operator is configured,
+    /// there is no one to pay, and the fee is not charged.
     pub fn apply_transaction(
         &mut self,
         transaction: &SignedTransaction,
     ) -> Result<(), RollupError> {
-        // convert transaction_payload to signed transaction
+        // 0)
+        self.check_chain(transaction)?;
 
+        self.meter.signature_verifications += 1;
         let sender = transaction.recover()?;
         let destination = transaction.transaction.destination;
         let next_nonce = transaction.transaction.nonce;
         let transfer_amount = transaction.transaction.amount;
+        let hash = transaction.hash();
+
+        // 2)
+        self.check_expiry(transaction, hash)?;
+
+        let fee = self.fee_recipient(transaction).map_or(0, |(_, fee)| fee);
+        self.meter.state_reads += 1;
         let Account {
             nonce: prev_nonce,
             balance: sender_balance,
         } = self
-            .accounts
-            .get_mut(&sender)
+            .ledger
+            .get(&sender)
+            .cloned()
             .ok_or(RollupError::InsufficientBalance { address: sender })?;
 
-        // 2)
-        if next_nonce != *prev_nonce + 1 {
-            return Err(RollupError::InvalidNonce {
-                address: sender,
-                expected: *prev_nonce + 1,
-                actual: next_nonce,
-            });
+        // 3)
+        match self.replay_protection {
+            ReplayProtection::Nonce => {
+                if next_nonce != prev_nonce + 1 {
+                    return Err(RollupError::InvalidNonce {
+                        address: sender,
+                        expected: prev_nonce + 1,
+                        actual: next_nonce,
+                    });
+                }
+            }
+            ReplayProtection::RecentHashes => {
+                self.meter.state_reads += 1;
+                if self.is_recent_transaction(&sender, &hash) {
+                    return Err(RollupError::DuplicateTransaction {
+                        address: sender,
+                        hash,
+                    });
+                }
+            }
         }
 
-        // 3)
-        if transfer_amount > *sender_balance {
+        // 4)
+        if transfer_amount.saturating_add(fee) > sender_balance {
             return Err(RollupError::InsufficientBalance { address: sender });
         }
 
+        // 5)
+        let mut credits = vec![];
+        if transaction.transaction.kind == TransactionKind::Transfer {
+            credits.push((destination, transfer_amount));
+        }
+        if let Some((operator, fee)) = self.fee_recipient(transaction) {
+            credits.push((operator, self.submission_policy.split_fee(fee).0));
+        }
+        self.check_credits(sender, &credits)?;
+
         // Transaction is valid, return the updated state
-        *sender_balance -= transfer_amount;
-        *prev_nonce = next_nonce;
-        let Account {
-            balance: destination_balance,
-            ..
-        } = self.accounts.entry(destination).or_default();
-        *destination_balance += transfer_amount;
+        let sender_nonce = match self.replay_protection {
+            ReplayProtection::Nonce => next_nonce,
+            ReplayProtection::RecentHashes => {
+                self.meter.state_writes += 1;
+                self.recent_transactions
+                    .entry(sender)
+                    .or_default()
+                    .insert(hash, self.block_height);
+                prev_nonce
+            }
+        };
+        match transaction.transaction.kind {
+            TransactionKind::Transfer => {
+                self.meter.state_reads += 1;
+                if self.ledger.get(&destination).is_none() {
+                    self.block_events.push(RollupEvent::AccountCreated {
+                        address: destination,
+                    });
+                }
+                // The transfer writes both the sender and destination accounts.
+                self.meter.state_writes += 2;
+                self.ledger.record(
+                    self.block_height,
+                    LedgerEvent::Transfer {
+                        from: sender,
+                        to: destination,
+                        amount: transfer_amount,
+                        nonce: sender_nonce,
+                    },
+                );
+                self.block_events.push(RollupEvent::Transfer {
+                    from: sender,
+                    to: destination,
+                    amount: transfer_amount,
+                });
+            }
+            TransactionKind::Withdraw => {
+                // The withdrawal writes the sender account and appends to the withdrawals.
+                self.meter.state_writes += 2;
+                self.ledger.record(
+                    self.block_height,
+                    LedgerEvent::Burn {
+                        from: sender,
+                        amount: transfer_amount,
+                        nonce: sender_nonce,
+                    },
+                );
+                self.block_events.push(RollupEvent::Withdrawal {
+                    from: sender,
+                    recipient: destination,
+                    amount: transfer_amount,
+                    index: self.withdrawals.len() as u64,
+                });
+                self.withdrawals.push(Withdrawal {
+                    recipient: destination,
+                    amount: transfer_amount,
+                });
+            }
+        }
+        if let Some((operator, fee)) = self.fee_recipient(transaction) {
+            let (revenue, burned) = self.submission_policy.split_fee(fee);
+            if revenue > 0 {
+                self.meter.state_reads += 1;
+                if self.ledger.get(&operator).is_none() {
+                    self.block_events
+                        .push(RollupEvent::AccountCreated { address: operator });
+                }
+                self.meter.state_writes += 2;
+                self.ledger.record(
+                    self.block_height,
+                    LedgerEvent::Transfer {
+                        from: sender,
+                        to: operator,
+                        amount: revenue,
+                        nonce: sender_nonce,
+                    },
+                );
+                self.block_events.push(RollupEvent::Transfer {
+                    from: sender,
+                    to: operator,
+                    amount: revenue,
+                });
+                self.fee_revenue += revenue;
+            }
+            if burned > 0 {
+                self.meter.state_writes += 1;
+                self.ledger.record(
+                    self.block_height,
+                    LedgerEvent::Burn {
+                        from: sender,
+                        amount: burned,
+                        nonce: sender_nonce,
+                    },
+                );
+                self.block_events.push(RollupEvent::Burn {
+                    from: sender,
+                    amount: burned,
+                });
+                self.burned += burned;
+            }
+        }
 
         tracing::info!("Applied transaction {next_nonce} for {sender}");
         Ok(())
     }
 
+    /// The operator to which the fee of `transaction` is paid, and the fee, if there is one.
+    /// Check that paying `credits` out of a transaction from `sender` does not overflow the
+    /// balance of any account. Credits to the sender only return tokens it was debited, so they
+    /// cannot overflow.
+    fn check_credits(
+        &self,
+        sender: Address,
+        credits: &[(Address, Amount)],
+    ) -> Result<(), RollupError> {
+        let mut balances = BTreeMap::new();
+        for &(to, amount) in credits.iter().filter(|(to, _)| *to != sender) {
+            let balance = balances.entry(to).or_insert_with(|| self.get_balance(&to));
+            *balance = balance
+                .checked_add(amount)
+                .ok_or(RollupError::BalanceOverflow { address: to })?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn fee_recipient(
+        &self,
+        transaction: &SignedTransaction,
+    ) -> Option<(Address, Amount)> {
+        let fee = transaction.transaction.fee;
+        let operator = self.submission_policy.operator?;
+        (fee > 0).then_some((operator, fee))
+    }
+
+    /// Apply a custom transaction of kind `kind` with the registered handler.
+    ///
+    /// The transfers made by the handler are applied if and only if it succeeds.
+    pub(crate) fn apply_custom(&mut self, kind: u8, body: &[u8]) -> Result<(), RollupError> {
+        let handler = self
+            .hooks
+            .get(kind)
+            .ok_or(RollupError::UnknownTransactionKind { kind })?;
+        let mut access = StateAccess::new(self);
+        let res = handler.apply(body, &mut access);
+        let reads = access.state_reads();
+        let transfers = access.into_transfers();
+        self.meter.state_reads += reads;
+        res?;
+        for (from, to, amount) in transfers {
+            // Each transfer reads the destination account and the sender's nonce, and writes both
+            // accounts.
+            self.meter.state_reads += 2;
+            self.meter.state_writes += 2;
+            if self.ledger.get(&to).is_none() {
+                self.block_events
+                    .push(RollupEvent::AccountCreated { address: to });
+            }
+            self.ledger.record(
+                self.block_height,
+                LedgerEvent::Transfer {
+                    from,
+                    to,
+                    amount,
+                    nonce: self.get_nonce(&from),
+                },
+            );
+            self.block_events
+                .push(RollupEvent::Transfer { from, to, amount });
+        }
+        Ok(())
+    }
+
+    /// Apply `transactions` in order to a copy of this state, without modifying the state itself.
+    pub fn simulate<'a>(
+        &self,
+        transactions: impl IntoIterator<Item = &'a SignedTransaction>,
+    ) -> Simulation {
+        let mut scratch = self.clone();
+        let mut results = vec![];
+        let mut metrics = vec![];
+        for transaction in transactions {
+            scratch.meter = ExecutionMetrics::default();
+            let start = Instant::now();
+            results.push(scratch.apply_transaction(transaction));
+            scratch.meter.time_us = start.elapsed().as_micros() as u64;
+            metrics.push(scratch.meter);
+        }
+        Simulation {
+            results,
+            metrics,
+            commitment: scratch.commit(),
+        }
+    }
+
+    /// Events emitted by transactions in the most recently executed block.
+    pub fn block_events(&self) -> &[RollupEvent] {
+        &self.block_events
+    }
+
+    /// The hash and result of each transaction in the most recently executed block.
+    pub fn block_results(&self) -> &[(H256, Result<(), RollupError>)] {
+        &self.block_results
+    }
+
+    /// The resources consumed by each transaction in the most recently executed block, in the same
+    /// order as [`block_results`](Self::block_results).
+    pub fn block_metrics(&self) -> &[ExecutionMetrics] {
+        &self.block_metrics
+    }
+
+    /// The transfer made by each transaction in the most recently executed block, in the same
+    /// order as [`block_results`](Self::block_results).
+    pub fn block_transfers(&self) -> &[Option<TransferSummary>] {
+        &self.block_transfers
+    }
+
+    /// The state commitment before the most recently executed block.
+    pub fn prev_state_commitment(&self) -> Option<Commitment<State>> {
+        self.prev_state_commitment
+    }
+
     /// Fetch the balance of an address
     pub fn get_balance(&self, address: &Address) -> Amount {
-        self.accounts
+        self.ledger
             .get(address)
             .map(|account| account.balance)
             .unwrap_or(0)
@@ -153,55 +925,633 @@ impl State {
 
     /// Fetch the nonce of an address
     pub fn get_nonce(&self, address: &Address) -> Nonce {
-        self.accounts
+        self.ledger
             .get(address)
             .map(|account| account.nonce)
             .unwrap_or(0)
     }
 
+    /// The account of `address` in the state at `height`, replayed from the ledger.
+    ///
+    /// Only blocks since the last compaction of the ledger can be replayed.
+    pub fn account_at(&self, address: &Address, height: u64) -> Result<Account, HistoryError> {
+        if height > self.block_height {
+            return Err(HistoryError::NotExecuted { height });
+        }
+        self.ledger
+            .account_at(address, height)
+            .ok_or(HistoryError::NotRetained { height })
+    }
+
+    /// Whether `sender` executed the transaction with hash `hash` within the replay window. Only
+    /// tracked under [`ReplayProtection::RecentHashes`].
+    pub(crate) fn is_recent_transaction(&self, sender: &Address, hash: &H256) -> bool {
+        self.recent_transactions
+            .get(sender)
+            .is_some_and(|seen| seen.contains_key(hash))
+    }
+
+    /// The log of account changes from which account state is projected.
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Sum of the balances of all accounts.
+    pub fn total_balance(&self) -> u128 {
+        self.ledger
+            .accounts()
+            .values()
+            .map(|account| account.balance as u128)
+            .sum()
+    }
+
+    /// Root of a Merkle tree over all accounts, against which balance proofs can be verified.
+    pub fn accounts_root(&self) -> H256 {
+        let levels = balance_proof::tree_levels(self.account_leaves());
+        levels.last().unwrap()[0]
+    }
+
+    /// Prove the balance and nonce of an account against [`accounts_root`](Self::accounts_root).
+    ///
+    /// Returns `None` if the account does not exist.
+    pub fn balance_proof(&self, address: &Address) -> Option<BalanceProof> {
+        let accounts = self.ledger.accounts();
+        let index = accounts.keys().position(|addr| addr == address)?;
+        let account = &accounts[address];
+        let levels = balance_proof::tree_levels(self.account_leaves());
+        Some(BalanceProof {
+            address: *address,
+            balance: account.balance,
+            nonce: account.nonce,
+            index: index as u64,
+            siblings: balance_proof::sibling_path(&levels, index),
+        })
+    }
+
+    /// Every withdrawal to the L1 since genesis, in execution order.
+    pub fn withdrawals(&self) -> &[Withdrawal] {
+        &self.withdrawals
+    }
+
+    /// Root of the tree over every withdrawal, which is part of the state commitment.
+    pub fn withdrawals_root(&self) -> WithdrawalsRoot {
+        WithdrawalsRoot::new(&self.withdrawals)
+    }
+
+    /// Prove the withdrawal at `index` against the root of the tree over the first `count`
+    /// withdrawals, or over every withdrawal if `count` is `None`.
+    ///
+    /// A claim on the L1 must be proven against the root last posted to the rollup contract, which
+    /// may lag behind this state. Returns `None` if there is no such withdrawal.
+    pub fn withdrawal_proof(&self, index: u64, count: Option<u64>) -> Option<WithdrawalProof> {
+        let count = match count {
+            Some(count) => usize::try_from(count).ok()?,
+            None => self.withdrawals.len(),
+        };
+        WithdrawalProof::new(self.withdrawals.get(..count)?, index)
+    }
+
+    /// The last L1 block whose deposits have been credited.
+    pub fn l1_deposit_block(&self) -> u64 {
+        self.l1_deposit_block
+    }
+
+    /// Stage `deposits`, collected from the L1 up to and including `l1_block`, to be credited at the
+    /// start of the next block executed.
+    ///
+    /// Every node must stage the same deposits before executing the same block: those from L1
+    /// blocks after [`l1_deposit_block`](Self::l1_deposit_block), up to the latest finalized L1
+    /// block recorded in the block's HotShot header. Deposits outside this range are ignored.
+    pub fn stage_deposits(&mut self, l1_block: u64, deposits: Vec<Deposit>) {
+        self.staged_deposits = Some((l1_block, deposits));
+    }
+
+    /// Credit the staged deposits, in the order they occurred on the L1.
+    ///
+    /// A deposit which would overflow the balance of its account is not credited, and its tokens
+    /// remain in escrow on the L1.
+    fn credit_deposits(&mut self) {
+        let Some((l1_block, deposits)) = self.staged_deposits.take() else {
+            return;
+        };
+        let credited = self.l1_deposit_block;
+        for Deposit {
+            account, amount, ..
+        } in deposits
+            .into_iter()
+            .filter(|deposit| deposit.l1_block > credited && deposit.l1_block <= l1_block)
+        {
+            // A deposit is credited up to the largest balance the account can hold, and the rest is
+            // refunded as a withdrawal to the depositing account, so that no ether stays locked in
+            // the escrow.
+            let credit = amount.min(Amount::MAX - self.get_balance(&account));
+            let refund = amount - credit;
+            if credit > 0 {
+                if self.ledger.get(&account).is_none() {
+                    self.block_events
+                        .push(RollupEvent::AccountCreated { address: account });
+                }
+                self.ledger.record(
+                    self.block_height,
+                    LedgerEvent::Deposit {
+                        to: account,
+                        amount: credit,
+                    },
+                );
+                self.block_events.push(RollupEvent::Deposit {
+                    to: account,
+                    amount: credit,
+                });
+            }
+            if refund > 0 {
+                tracing::warn!(
+                    "Deposit of {amount} to {account} would overflow its balance, refunding {refund}"
+                );
+                self.block_events.push(RollupEvent::Withdrawal {
+                    from: account,
+                    recipient: account,
+                    amount: refund,
+                    index: self.withdrawals.len() as u64,
+                });
+                self.withdrawals.push(Withdrawal {
+                    recipient: account,
+                    amount: refund,
+                });
+            }
+            self.deposited += amount as u128;
+        }
+        self.l1_deposit_block = self.l1_deposit_block.max(l1_block);
+    }
+
+    fn account_leaves(&self) -> Vec<H256> {
+        self.ledger
+            .accounts()
+            .iter()
+            .map(|(address, account)| {
+                balance_proof::leaf_hash(address, account.balance, account.nonce)
+            })
+            .collect()
+    }
+
+    /// Forget transactions which executed before the current replay window.
+    fn prune_recent_transactions(&mut self) {
+        let (height, window) = (self.block_height, self.replay_window);
+        for seen in self.recent_transactions.values_mut() {
+            seen.retain(|_, executed_at| *executed_at + window > height);
+        }
+        self.recent_transactions.retain(|_, seen| !seen.is_empty());
+    }
+
     pub(crate) async fn execute_block(
         &mut self,
         header: Header,
         namespace_proof: Option<NsProof>,
         vid_common: VidCommon,
         block_hash: BlockHash<SeqTypes>,
+        view_number: Option<u64>,
+        verifier: &ProofVerifier,
     ) -> Proof {
+        let num_withdrawals = self.withdrawals.len();
+        self.execute_transactions(&header, namespace_proof.as_ref().unwrap(), block_hash);
+        self.view_number = view_number;
+
+        // Blocks which add withdrawals carry the new root of the withdrawal tree to the L1.
+        let withdrawals =
+            (self.withdrawals.len() > num_withdrawals).then(|| self.withdrawals_root());
+        let inputs = BlockInputs {
+            header,
+            block: block_hash,
+            namespace_proof,
+            vid_common,
+            old_state: self.prev_state_commitment.unwrap(),
+            new_state: self.commit(),
+            withdrawals,
+        };
+        Proof::generate(inputs, verifier).await
+    }
+
+    /// Apply the rollup transactions in the block with the given `header`, as
+    /// [`execute_transactions`](RollupStateMachine::execute_transactions) does, but executing each
+    /// transfer with `apply` instead of [`apply_transaction`](Self::apply_transaction).
+    pub(crate) fn execute_transactions_with(
+        &mut self,
+        header: &Header,
+        namespace_proof: &NsProof,
+        block_hash: BlockHash<SeqTypes>,
+        apply: impl Fn(&mut State, &SignedTransaction) -> Result<(), RollupError>,
+    ) {
+        self.block_timestamp = header.timestamp();
+        self.view_number = None;
+        self.apply_block(header.height(), namespace_proof, block_hash, apply);
+    }
+
+    /// Re-execute a block with the reference implementation of transaction execution, and check
+    /// that it produces the same results as `executed`.
+    ///
+    /// `self` must be the state before the block, and `executed` the state after the block was
+    /// executed by [`execute_block`](Self::execute_block). Any difference indicates that
+    /// execution is not deterministic, or that the primary implementation has diverged from the
+    /// reference semantics.
+    pub(crate) fn check_determinism(
+        mut self,
+        executed: &State,
+        block_height: u64,
+        namespace_proof: &NsProof,
+        block_hash: BlockHash<SeqTypes>,
+    ) -> Result<(), DeterminismError> {
+        self.apply_block(
+            block_height,
+            namespace_proof,
+            block_hash,
+            reference::apply_transaction,
+        );
+        for (index, (primary, reference)) in executed
+            .block_results
+            .iter()
+            .zip(&self.block_results)
+            .enumerate()
+        {
+            if primary != reference {
+                return Err(DeterminismError::ResultMismatch {
+                    index,
+                    primary: format!("{:?}", primary.1),
+                    reference: format!("{:?}", reference.1),
+                });
+            }
+        }
+        if executed.block_events != self.block_events {
+            return Err(DeterminismError::EventsMismatch);
+        }
+        let (primary, reference) = (executed.commit(), self.commit());
+        if primary != reference {
+            return Err(DeterminismError::CommitmentMismatch { primary, reference });
+        }
+        Ok(())
+    }
+
+    /// Check that `submission`, a payload of `size` bytes, may be executed under the submission
+    /// policy.
+    pub(crate) fn check_submission(
+        &mut self,
+        submission: &Submission,
+        size: usize,
+    ) -> Result<(), RollupError> {
+        let Some(operator) = self.submission_policy.operator else {
+            return Ok(());
+        };
+        // A countersignature by anyone other than the operator counts for nothing.
+        if let Submission::Wrapped(envelope) = submission {
+            self.meter.signature_verifications += 1;
+            if envelope.recover_operator().ok() == Some(operator) {
+                return Ok(());
+            }
+        }
+        match self.submission_policy.untrusted {
+            UntrustedSubmissions::Reject => Err(RollupError::UntrustedSubmission {
+                reason: "transaction was not countersigned by the operator".into(),
+            }),
+            UntrustedSubmissions::Strict => {
+                if size > MAX_UNTRUSTED_TRANSACTION_SIZE {
+                    return Err(RollupError::UntrustedSubmission {
+                        reason: format!(
+                            "{size} bytes exceeds the maximum size of {MAX_UNTRUSTED_TRANSACTION_SIZE} bytes"
+                        ),
+                    });
+                }
+                self.meter.signature_verifications += 1;
+                let sender = submission.transaction().recover()?;
+                if !self.untrusted_senders.insert(sender) {
+                    return Err(RollupError::UntrustedSubmission {
+                        reason: format!(
+                            "{sender} already has an untrusted transaction in this block"
+                        ),
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Apply the rollup transactions in a block, using `apply` to execute each transaction.
+    fn apply_block(
+        &mut self,
+        block_height: u64,
+        namespace_proof: &NsProof,
+        block_hash: BlockHash<SeqTypes>,
+        apply: impl Fn(&mut State, &SignedTransaction) -> Result<(), RollupError>,
+    ) {
         let state_commitment = self.commit();
-        let transactions = namespace_proof.clone().unwrap().export_all_txs(&self.vm.0);
+        self.block_events.clear();
+        self.block_results.clear();
+        self.block_metrics.clear();
+        self.block_transfers.clear();
+        self.untrusted_senders.clear();
+        self.block_height = block_height;
+        self.prune_recent_transactions();
+        // Blocks without rollup transactions are never executed, so the heights of executed blocks
+        // are sparse. Compact once an interval has passed since the last compaction, which kept the
+        // blocks from `compacted_before`, rather than at multiples of the interval.
+        let retain_from = block_height.saturating_sub(LEDGER_COMPACTION_INTERVAL);
+        if retain_from >= self.ledger.compacted_before() + LEDGER_COMPACTION_INTERVAL {
+            self.ledger.compact(retain_from);
+        }
+        // Deposits are credited before any transaction, so they can be spent in the same block.
+        self.credit_deposits();
+        let transactions = self
+            .ordering_policy
+            .order(namespace_proof.export_all_txs(&self.vm.0));
         for txn in transactions {
-            let signed_transaction = SignedTransaction::decode(txn.payload());
-            if signed_transaction.is_none() {
-                tracing::error!("Transaction invalid: Could not decode transaction");
-                continue;
-            }
-            let res = self.apply_transaction(&signed_transaction.unwrap());
-            if let Err(err) = res {
+            self.meter = ExecutionMetrics::default();
+            let start = Instant::now();
+            let mut transfer = None;
+            let (hash, res) = if let Some((kind, body)) = hooks::decode_custom(txn.payload()) {
+                (
+                    SignedTransaction::payload_hash(txn.payload()),
+                    self.apply_custom(kind, body),
+                )
+            } else {
+                match Submission::decode(txn.payload()) {
+                    Ok(submission) => {
+                        let signed = submission.transaction();
+                        transfer = Some(TransferSummary {
+                            from: signed.recover().ok(),
+                            to: signed.transaction.destination,
+                            amount: signed.transaction.amount,
+                            fee: signed.transaction.fee,
+                        });
+                        let res = self
+                            .check_submission(&submission, txn.payload().len())
+                            .and_then(|()| apply(self, signed));
+                        (signed.hash(), res)
+                    }
+                    Err(err) => (SignedTransaction::payload_hash(txn.payload()), Err(err)),
+                }
+            };
+            self.meter.time_us = start.elapsed().as_micros() as u64;
+            if let Err(err) = &res {
                 tracing::error!("Transaction invalid: {}", err)
             }
+            self.block_results.push((hash, res));
+            self.block_metrics.push(self.meter);
+            self.block_transfers.push(transfer);
         }
         self.block_hash = Some(block_hash);
         self.prev_state_commitment = Some(state_commitment);
+        self.events_root = events::events_root(&self.block_events);
+    }
+}
+
+impl RollupStateMachine for State {
+    fn namespace(&self) -> NamespaceId {
+        self.vm.into()
+    }
+
+    fn set_chain_id(&mut self, chain_id: ChainId) {
+        self.chain_id = Some(chain_id);
+    }
+
+    fn block_height(&self) -> u64 {
+        self.block_height
+    }
 
-        Proof::generate(
+    fn execute_transactions(
+        &mut self,
+        header: &Header,
+        namespace_proof: &NsProof,
+        block_hash: BlockHash<SeqTypes>,
+    ) {
+        self.execute_transactions_with(
             header,
-            self.commit(),
-            self.prev_state_commitment.unwrap(),
-            namespace_proof.clone(),
-            vid_common,
+            namespace_proof,
             block_hash,
-        )
+            Self::apply_transaction,
+        );
+    }
+
+    fn block_results(&self) -> &[(H256, Result<(), RollupError>)] {
+        &self.block_results
     }
 }
-#[cfg(test)]
-mod tests {
+
+/// An independent implementation of transaction execution, used to check the primary
+/// implementation in [`State::check_determinism`].
+///
+/// This is written directly from the validity rules documented on [`State::apply_transaction`],
+/// favoring obviousness over efficiency, and deliberately shares no code with it.
+pub(crate) mod reference {
+    use super::*;
     use crate::transaction::Transaction;
-    use espresso_types::NamespaceId;
 
-    use ethers::signers::{LocalWallet, Signer};
+    pub(crate) fn apply_transaction(
+        state: &mut State,
+        transaction: &SignedTransaction,
+    ) -> Result<(), RollupError> {
+        if let Some(expected) = state.chain_id {
+            match transaction.chain_id() {
+                None => return Err(RollupError::MissingChainId { expected }),
+                Some(actual) if actual != expected => {
+                    return Err(RollupError::WrongChain { expected, actual });
+                }
+                Some(_) => {}
+            }
+        }
+        let sender = transaction.recover()?;
+        let Transaction {
+            amount,
+            destination,
+            nonce,
+            fee,
+            expires_at,
+            kind,
+            ..
+        } = transaction.transaction.clone();
+        let hash = transaction.hash();
+        // Without an operator, there is no one to pay the fee.
+        let fee = match state.submission_policy.operator {
+            Some(operator) if fee > 0 => Some((operator, fee)),
+            _ => None,
+        };
 
-    use super::*;
-    #[async_std::test]
-    async fn smoke_test() {
+        if let Some(expires_at) = expires_at.filter(|&height| height <= state.block_height) {
+            return Err(RollupError::TransactionExpired { hash, expires_at });
+        }
+        // A remembered transaction must expire before it is forgotten.
+        let max = state.block_height + state.replay_window;
+        if state.replay_protection == ReplayProtection::RecentHashes
+            && expires_at.map_or(true, |height| height > max)
+        {
+            return Err(RollupError::ExpiryOutsideReplayWindow { hash, max });
+        }
+
+        // An account which has never received tokens cannot send any.
+        let Some(sender_account) = state.ledger.get(&sender).cloned() else {
+            return Err(RollupError::InsufficientBalance { address: sender });
+        };
+
+        let is_replay = match state.replay_protection {
+            ReplayProtection::Nonce => nonce != sender_account.nonce + 1,
+            ReplayProtection::RecentHashes => state
+                .recent_transactions
+                .get(&sender)
+                .map_or(false, |seen| seen.contains_key(&hash)),
+        };
+        if is_replay {
+            return Err(match state.replay_protection {
+                ReplayProtection::Nonce => RollupError::InvalidNonce {
+                    address: sender,
+                    expected: sender_account.nonce + 1,
+                    actual: nonce,
+                },
+                ReplayProtection::RecentHashes => RollupError::DuplicateTransaction {
+                    address: sender,
+                    hash,
+                },
+            });
+        }
+        let charged = fee.map_or(0, |(_, fee)| fee);
+        if sender_account
+            .balance
+            .checked_sub(amount)
+            .and_then(|balance| balance.checked_sub(charged))
+            .is_none()
+        {
+            return Err(RollupError::InsufficientBalance { address: sender });
+        }
+        // The destination and the operator, if they are not the sender, receive new tokens, which
+        // must fit in their balances.
+        let mut credits = vec![];
+        if kind != TransactionKind::Withdraw {
+            credits.push((destination, amount as u128));
+        }
+        if let Some((operator, fee)) = fee {
+            let bps = state.submission_policy.fee_burn_bps.min(10_000) as u128;
+            credits.push((operator, fee as u128 - fee as u128 * bps / 10_000));
+        }
+        for (account, _) in credits.iter().filter(|(account, _)| *account != sender) {
+            let received: u128 = credits
+                .iter()
+                .filter(|(to, _)| to == account)
+                .map(|(_, amount)| amount)
+                .sum();
+            if state.get_balance(account) as u128 + received > Amount::MAX as u128 {
+                return Err(RollupError::BalanceOverflow { address: *account });
+            }
+        }
+
+        // The transaction is valid.
+        let sender_nonce = match state.replay_protection {
+            ReplayProtection::Nonce => nonce,
+            ReplayProtection::RecentHashes => {
+                let mut seen = state
+                    .recent_transactions
+                    .remove(&sender)
+                    .unwrap_or_default();
+                seen.insert(hash, state.block_height);
+                state.recent_transactions.insert(sender, seen);
+                sender_account.nonce
+            }
+        };
+        if kind == TransactionKind::Withdraw {
+            // The tokens leave the rollup, to be claimed on the L1 from the withdrawal tree.
+            state.ledger.record(
+                state.block_height,
+                LedgerEvent::Burn {
+                    from: sender,
+                    amount,
+                    nonce: sender_nonce,
+                },
+            );
+            let index = state.withdrawals.len() as u64;
+            state.withdrawals.push(Withdrawal {
+                recipient: destination,
+                amount,
+            });
+            state.block_events.push(RollupEvent::Withdrawal {
+                from: sender,
+                recipient: destination,
+                amount,
+                index,
+            });
+        } else {
+            if state.ledger.get(&destination).is_none() {
+                state.block_events.push(RollupEvent::AccountCreated {
+                    address: destination,
+                });
+            }
+            state.ledger.record(
+                state.block_height,
+                LedgerEvent::Transfer {
+                    from: sender,
+                    to: destination,
+                    amount,
+                    nonce: sender_nonce,
+                },
+            );
+            state.block_events.push(RollupEvent::Transfer {
+                from: sender,
+                to: destination,
+                amount,
+            });
+        }
+        if let Some((operator, fee)) = fee {
+            // The burned share is rounded down, in favor of the operator.
+            let bps = state.submission_policy.fee_burn_bps.min(10_000) as u128;
+            let burned = (fee as u128 * bps / 10_000) as Amount;
+            let paid = fee - burned;
+            if paid != 0 {
+                if state.ledger.get(&operator).is_none() {
+                    state
+                        .block_events
+                        .push(RollupEvent::AccountCreated { address: operator });
+                }
+                state.ledger.record(
+                    state.block_height,
+                    LedgerEvent::Transfer {
+                        from: sender,
+                        to: operator,
+                        amount: paid,
+                        nonce: sender_nonce,
+                    },
+                );
+                state.block_events.push(RollupEvent::Transfer {
+                    from: sender,
+                    to: operator,
+                    amount: paid,
+                });
+                state.fee_revenue += paid;
+            }
+            if burned != 0 {
+                state.ledger.record(
+                    state.block_height,
+                    LedgerEvent::Burn {
+                        from: sender,
+                        amount: burned,
+                        nonce: sender_nonce,
+                    },
+                );
+                state.block_events.push(RollupEvent::Burn {
+                    from: sender,
+                    amount: burned,
+                });
+                state.burned += burned;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_source::MockBlock;
+    use crate::fixtures::mock_block;
+    use crate::transaction::{Transaction, TransactionBuilder};
+    use espresso_types::NamespaceId;
+
+    use ethers::signers::{LocalWallet, Signer};
+
+    use super::*;
+    #[async_std::test]
+    async fn smoke_test() {
         let mut rng = rand::thread_rng();
         let vm = RollupVM::new(NamespaceId::from(1_u64));
         let alice = LocalWallet::new(&mut rng);
@@ -212,6 +1562,7 @@ mod tests {
             amount: 110,
             destination: bob.address(),
             nonce: 1,
+            ..Default::default()
         };
 
         // Try to overspend
@@ -248,4 +1599,871 @@ mod tests {
             }
         );
     }
+
+    #[async_std::test]
+    async fn test_block_events() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let carol = Address::random();
+        let mut state =
+            State::from_initial_balances([(alice.address(), 100), (bob.address(), 100)], vm);
+        let transfer = |destination, amount, nonce| {
+            let transaction = Transaction {
+                amount,
+                destination,
+                nonce,
+                ..Default::default()
+            };
+            let alice = alice.clone();
+            async move { SignedTransaction::new(transaction, &alice).await }
+        };
+
+        // A transfer to an existing account emits only the transfer.
+        let signed_transaction = transfer(bob.address(), 50, 1).await;
+        state.apply_transaction(&signed_transaction).unwrap();
+        assert_eq!(
+            state.block_events(),
+            [RollupEvent::Transfer {
+                from: alice.address(),
+                to: bob.address(),
+                amount: 50
+            }]
+        );
+
+        // A transfer to a new account creates it first, and a failed transfer emits nothing.
+        let signed_transaction = transfer(carol, 10, 2).await;
+        state.apply_transaction(&signed_transaction).unwrap();
+        let signed_transaction = transfer(carol, 1000, 3).await;
+        state.apply_transaction(&signed_transaction).unwrap_err();
+        assert_eq!(
+            state.block_events()[1..],
+            [
+                RollupEvent::AccountCreated { address: carol },
+                RollupEvent::Transfer {
+                    from: alice.address(),
+                    to: carol,
+                    amount: 10
+                },
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn test_recent_hashes_replay_protection() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm)
+            .with_replay_protection(ReplayProtection::RecentHashes, 10);
+
+        // Every transaction must expire within the replay window.
+        for expires_at in [None, Some(11)] {
+            let transaction = Transaction {
+                amount: 10,
+                destination: bob.address(),
+                expires_at,
+                ..Default::default()
+            };
+            let signed_transaction = SignedTransaction::new(transaction, &alice).await;
+            assert_eq!(
+                state.apply_transaction(&signed_transaction),
+                Err(RollupError::ExpiryOutsideReplayWindow {
+                    hash: signed_transaction.hash(),
+                    max: 10,
+                })
+            );
+        }
+
+        // Nonces are arbitrary salts, and need not be sequential.
+        let transaction = Transaction {
+            amount: 10,
+            destination: bob.address(),
+            nonce: 42,
+            expires_at: Some(10),
+            ..Default::default()
+        };
+        let signed_transaction = SignedTransaction::new(transaction.clone(), &alice).await;
+        state
+            .apply_transaction(&signed_transaction)
+            .expect("Valid transaction should transition state");
+
+        // Replaying the same transaction fails.
+        let err = state
+            .apply_transaction(&signed_transaction)
+            .expect_err("Replayed transaction should throw error.");
+        assert_eq!(
+            err,
+            RollupError::DuplicateTransaction {
+                address: alice.address(),
+                hash: signed_transaction.hash(),
+            }
+        );
+
+        // An otherwise identical transaction with a different salt succeeds.
+        let salted = SignedTransaction::new(
+            Transaction {
+                nonce: 7,
+                ..transaction
+            },
+            &alice,
+        )
+        .await;
+        state
+            .apply_transaction(&salted)
+            .expect("Valid transaction should transition state");
+        assert_eq!(state.get_balance(&bob.address()), 20);
+        assert_eq!(state.get_nonce(&alice.address()), 0);
+
+        // Once the replay window has passed, the transaction is forgotten, but it has also expired,
+        // so it still cannot be replayed.
+        state.block_height = 10;
+        state.prune_recent_transactions();
+        assert!(!state.is_recent_transaction(&alice.address(), &signed_transaction.hash()));
+        assert_eq!(
+            state.apply_transaction(&signed_transaction),
+            Err(RollupError::TransactionExpired {
+                hash: signed_transaction.hash(),
+                expires_at: 10,
+            })
+        );
+    }
+
+    #[async_std::test]
+    async fn test_chain_binding() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let genesis = state.commit();
+        let chain_id = ChainId::derive(vm.into(), genesis, 1337, Address::random());
+        let other_chain = ChainId::derive(vm.into(), genesis, 1, Address::random());
+        state.set_chain_id(chain_id);
+
+        // Binding the state to a chain does not change its commitment.
+        assert_eq!(state.commit(), genesis);
+        assert_eq!(state.chain_id(), Some(chain_id));
+
+        let transfer = |nonce| Transaction {
+            amount: 10,
+            destination: bob.address(),
+            nonce,
+            ..Default::default()
+        };
+        let wrong_chain = SignedTransaction::new_for_chain(transfer(1), other_chain, &alice).await;
+        let same_chain = SignedTransaction::new_for_chain(transfer(1), chain_id, &alice).await;
+        let legacy = SignedTransaction::new(transfer(2), &alice).await;
+        let next = SignedTransaction::new_for_chain(transfer(2), chain_id, &alice).await;
+
+        let mut reference = state.clone();
+        for (transaction, expected) in [
+            (
+                &wrong_chain,
+                Err(RollupError::WrongChain {
+                    expected: chain_id,
+                    actual: other_chain,
+                }),
+            ),
+            (&same_chain, Ok(())),
+            // Once the state is bound, a transaction must name its chain.
+            (
+                &legacy,
+                Err(RollupError::MissingChainId { expected: chain_id }),
+            ),
+            (&next, Ok(())),
+        ] {
+            assert_eq!(state.apply_transaction(transaction), expected);
+            assert_eq!(
+                reference::apply_transaction(&mut reference, transaction),
+                expected
+            );
+        }
+        assert_eq!(state.get_balance(&bob.address()), 20);
+        assert_eq!(state.commit(), reference.commit());
+    }
+
+    #[async_std::test]
+    async fn test_fees_and_expiry() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let operator = Address::random();
+        let genesis = State::from_initial_balances([(alice.address(), 100)], vm)
+            .with_submission_policy(SubmissionPolicy {
+                operator: Some(operator),
+                ..Default::default()
+            });
+        let transfer = |nonce, amount, fee, expires_at| Transaction {
+            amount,
+            destination: bob.address(),
+            nonce,
+            fee,
+            expires_at,
+            ..Default::default()
+        };
+
+        // The balance must cover the fee as well as the amount.
+        let mut state = genesis.clone();
+        let overspend = SignedTransaction::new(transfer(1, 95, 10, None), &alice).await;
+        assert_eq!(
+            state.apply_transaction(&overspend),
+            Err(RollupError::InsufficientBalance {
+                address: alice.address()
+            })
+        );
+
+        // The fee is paid to the operator.
+        let paid = SignedTransaction::new(transfer(1, 50, 10, Some(5)), &alice).await;
+        state.apply_transaction(&paid).unwrap();
+        assert_eq!(state.get_balance(&alice.address()), 40);
+        assert_eq!(state.get_balance(&bob.address()), 50);
+        assert_eq!(state.get_balance(&operator), 10);
+        assert_eq!(state.get_nonce(&alice.address()), 1);
+        assert_eq!(
+            state.block_events(),
+            [
+                RollupEvent::AccountCreated {
+                    address: bob.address()
+                },
+                RollupEvent::Transfer {
+                    from: alice.address(),
+                    to: bob.address(),
+                    amount: 50
+                },
+                RollupEvent::AccountCreated { address: operator },
+                RollupEvent::Transfer {
+                    from: alice.address(),
+                    to: operator,
+                    amount: 10
+                },
+            ]
+        );
+
+        // A transaction is rejected from its expiry height onwards.
+        let expiring = SignedTransaction::new(transfer(2, 10, 0, Some(5)), &alice).await;
+        state.block_height = 5;
+        assert_eq!(
+            state.apply_transaction(&expiring),
+            Err(RollupError::TransactionExpired {
+                hash: expiring.hash(),
+                expires_at: 5
+            })
+        );
+
+        // Without an operator, the fee is not charged.
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let unpaid = SignedTransaction::new(transfer(1, 95, 10, None), &alice).await;
+        state.apply_transaction(&unpaid).unwrap();
+        assert_eq!(state.get_balance(&alice.address()), 5);
+
+        // The reference implementation agrees.
+        for block_height in [0, 5] {
+            let mut primary = genesis.clone();
+            let mut reference = genesis.clone();
+            primary.block_height = block_height;
+            reference.block_height = block_height;
+            for transaction in [&overspend, &paid, &expiring] {
+                assert_eq!(
+                    primary.apply_transaction(transaction),
+                    reference::apply_transaction(&mut reference, transaction)
+                );
+            }
+            assert_eq!(primary.block_events(), reference.block_events());
+            assert_eq!(primary.commit(), reference.commit());
+        }
+    }
+
+    #[async_std::test]
+    async fn test_balance_overflow() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let whale = Address::random();
+        let operator = Address::random();
+        let transfer = |destination, nonce, fee| Transaction {
+            amount: 10,
+            destination,
+            nonce,
+            fee,
+            ..Default::default()
+        };
+        let genesis = State::from_initial_balances(
+            [
+                (alice.address(), 100),
+                (whale, Amount::MAX - 5),
+                (operator, Amount::MAX - 5),
+            ],
+            vm,
+        )
+        .with_submission_policy(SubmissionPolicy {
+            operator: Some(operator),
+            ..Default::default()
+        });
+
+        // The circulating supply does not fit in a single balance.
+        assert_eq!(
+            genesis.supply().circulating,
+            2 * (Amount::MAX - 5) as u128 + 100
+        );
+
+        // A transfer, or a fee, which would overflow the balance it is credited to is rejected,
+        // and leaves the state unchanged. The reference implementation agrees.
+        let bob = Address::random();
+        for (transaction, overflowed) in [
+            (transfer(whale, 1, 0), whale),
+            (transfer(bob, 1, 10), operator),
+            (transfer(operator, 1, 0), operator),
+        ] {
+            let transaction = SignedTransaction::new(transaction, &alice).await;
+            let mut state = genesis.clone();
+            let mut reference = genesis.clone();
+            let expected = Err(RollupError::BalanceOverflow {
+                address: overflowed,
+            });
+            assert_eq!(state.apply_transaction(&transaction), expected);
+            assert_eq!(
+                reference::apply_transaction(&mut reference, &transaction),
+                expected
+            );
+            assert_eq!(state.commit(), genesis.commit());
+            assert_eq!(reference.commit(), genesis.commit());
+        }
+
+        // A transfer to oneself never overflows.
+        let mut state = State::from_initial_balances([(alice.address(), Amount::MAX)], vm);
+        let transaction = SignedTransaction::new(transfer(alice.address(), 1, 0), &alice).await;
+        state.apply_transaction(&transaction).unwrap();
+        assert_eq!(state.get_balance(&alice.address()), Amount::MAX);
+    }
+
+    #[async_std::test]
+    async fn test_fee_burn() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let operator = Address::random();
+        let transfer = |nonce, fee| Transaction {
+            amount: 10,
+            destination: bob.address(),
+            nonce,
+            fee,
+            ..Default::default()
+        };
+
+        // The burned share of each fee is rounded down.
+        for (fee_burn_bps, revenue, burned, first_burn) in
+            [(2_500, 9, 2, 2), (MAX_FEE_BURN_BPS, 0, 11, 10)]
+        {
+            let genesis = State::from_initial_balances([(alice.address(), 100)], vm)
+                .with_submission_policy(SubmissionPolicy {
+                    operator: Some(operator),
+                    fee_burn_bps,
+                    ..Default::default()
+                });
+            let transactions = [
+                SignedTransaction::new(transfer(1, 10), &alice).await,
+                SignedTransaction::new(transfer(2, 1), &alice).await,
+            ];
+
+            let mut state = genesis.clone();
+            let mut reference = genesis.clone();
+            for transaction in &transactions {
+                state.apply_transaction(transaction).unwrap();
+                reference::apply_transaction(&mut reference, transaction).unwrap();
+            }
+            assert_eq!(state.get_balance(&alice.address()), 100 - 20 - 11);
+            assert_eq!(state.get_balance(&operator), revenue);
+            assert_eq!(state.get_nonce(&alice.address()), 2);
+            assert_eq!(
+                state.supply(),
+                Supply {
+                    circulating: (100 - burned).into(),
+                    burned,
+                    fee_revenue: revenue,
+                    withdrawn: 0,
+                    deposited: 0,
+                }
+            );
+            assert!(state.block_events().contains(&RollupEvent::Burn {
+                from: alice.address(),
+                amount: first_burn,
+            }));
+            assert_eq!(&state.ledger.replay(), state.ledger.accounts());
+
+            // The totals are committed, and the reference implementation agrees.
+            assert_ne!(state.commit(), genesis.commit());
+            assert_eq!(state.block_events(), reference.block_events());
+            assert_eq!(state.commit(), reference.commit());
+        }
+
+        // A policy without a burn serializes as before, so existing genesis commitments are
+        // unchanged.
+        let policy = SubmissionPolicy::default();
+        assert!(!serde_json::to_string(&policy)
+            .unwrap()
+            .contains("fee_burn_bps"));
+        assert_eq!(policy.split_fee(10), (10, 0));
+    }
+
+    #[async_std::test]
+    async fn test_withdrawal() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let recipient = Address::random();
+        let genesis = State::from_initial_balances([(alice.address(), 100)], vm);
+        let withdraw = |amount, nonce| {
+            TransactionBuilder::new()
+                .amount(amount)
+                .destination(recipient)
+                .nonce(nonce)
+                .withdraw()
+        };
+        let transactions = [
+            withdraw(30, 1).sign(&alice).await.unwrap(),
+            withdraw(20, 2).sign(&alice).await.unwrap(),
+        ];
+
+        let mut state = genesis.clone();
+        let mut reference = genesis.clone();
+        for transaction in &transactions {
+            state.apply_transaction(transaction).unwrap();
+            reference::apply_transaction(&mut reference, transaction).unwrap();
+        }
+
+        // The tokens are burned rather than credited to the recipient on the rollup.
+        assert_eq!(state.get_balance(&alice.address()), 50);
+        assert_eq!(state.ledger().get(&recipient), None);
+        assert_eq!(state.supply().circulating, 50);
+        assert_eq!(state.supply().withdrawn, 50);
+        assert_eq!(
+            state.block_events().last(),
+            Some(&RollupEvent::Withdrawal {
+                from: alice.address(),
+                recipient,
+                amount: 20,
+                index: 1,
+            })
+        );
+        assert_eq!(&state.ledger.replay(), state.ledger.accounts());
+
+        // Each withdrawal can be proven against the committed root, or against an earlier root
+        // which the rollup contract may still hold.
+        let root = state.withdrawals_root();
+        assert_eq!(root.count, 2);
+        let proof = state.withdrawal_proof(1, None).unwrap();
+        assert_eq!((proof.recipient, proof.amount), (recipient, 20));
+        assert!(proof.verify(H256(root.root)));
+        let earlier = WithdrawalsRoot::new(&state.withdrawals()[..1]);
+        assert!(state
+            .withdrawal_proof(0, Some(1))
+            .unwrap()
+            .verify(H256(earlier.root)));
+        assert_eq!(state.withdrawal_proof(1, Some(1)), None);
+        assert_eq!(state.withdrawal_proof(0, Some(3)), None);
+
+        // The withdrawals are committed, and the reference implementation agrees.
+        assert_ne!(state.commit(), genesis.commit());
+        assert_eq!(state.block_events(), reference.block_events());
+        assert_eq!(state.commit(), reference.commit());
+        assert_eq!(
+            State::from_snapshot(state.to_snapshot()).commit(),
+            state.commit()
+        );
+
+        // A withdrawal cannot exceed the sender's balance.
+        assert_eq!(
+            state.apply_transaction(&withdraw(51, 3).sign(&alice).await.unwrap()),
+            Err(RollupError::InsufficientBalance {
+                address: alice.address()
+            })
+        );
+    }
+
+    #[async_std::test]
+    async fn test_ledger_compaction_sparse_blocks() {
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = Address::random();
+        let block = mock_block(
+            vm.into(),
+            &[(vm.into(), vec![b"not a transaction".to_vec()])],
+        )
+        .await;
+        let namespace_proof = block.namespace_proof.as_ref().unwrap();
+        let block_hash = block.header.commit();
+
+        // None of the executed heights is a multiple of the compaction interval. Each block
+        // records a deposit to alice.
+        let mut state = State::from_initial_balances([], vm);
+        for (l1_block, height) in [(1, 7), (2, 133), (3, 251)] {
+            let deposit = Deposit {
+                account: alice,
+                amount: 10,
+                l1_block,
+                log_index: 0,
+            };
+            state.stage_deposits(l1_block, vec![deposit]);
+            state.apply_block(
+                height,
+                namespace_proof,
+                block_hash,
+                State::apply_transaction,
+            );
+        }
+
+        // The ledger was compacted at block 251, over an interval after genesis, keeping the
+        // blocks of the last interval.
+        assert_eq!(state.ledger.compacted_before(), 151);
+        assert_eq!(state.ledger.history().count(), 1);
+        assert!(matches!(
+            state.account_at(&alice, 133),
+            Err(HistoryError::NotRetained { height: 133 })
+        ));
+        assert_eq!(state.account_at(&alice, 251).unwrap().balance, 30);
+
+        // It is not compacted again until another interval has passed.
+        state.apply_block(300, namespace_proof, block_hash, State::apply_transaction);
+        assert_eq!(state.ledger.compacted_before(), 151);
+        state.apply_block(389, namespace_proof, block_hash, State::apply_transaction);
+        assert_eq!(state.ledger.compacted_before(), 289);
+        assert_eq!(state.ledger.history().count(), 0);
+        assert_eq!(state.ledger.replay(), *state.ledger.accounts());
+        assert_eq!(state.get_balance(&alice), 30);
+    }
+
+    #[async_std::test]
+    async fn test_deposits() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let deposit = |account, amount, l1_block| Deposit {
+            account,
+            amount,
+            l1_block,
+            log_index: 0,
+        };
+        let transfer = TransactionBuilder::new()
+            .amount(20)
+            .destination(bob)
+            .nonce(1)
+            .sign(&alice)
+            .await
+            .unwrap();
+        let block = mock_block(vm.into(), &[(vm.into(), vec![transfer.encode()])]).await;
+        let namespace_proof = block.namespace_proof.as_ref().unwrap();
+        let block_hash = block.header.commit();
+
+        // A deposit is credited before the transactions of the block, so it can be spent at once.
+        // Deposits after the staged L1 block are left for a later block.
+        let genesis = State::from_initial_balances([], vm);
+        let mut state = genesis.clone();
+        state.stage_deposits(
+            5,
+            vec![deposit(alice.address(), 50, 3), deposit(bob, 10, 6)],
+        );
+        let prev_state = state.clone();
+        state.execute_transactions(&block.header, namespace_proof, block_hash);
+        assert_eq!(state.block_results()[0].1, Ok(()));
+        assert_eq!(state.get_balance(&alice.address()), 30);
+        assert_eq!(state.get_balance(&bob), 20);
+        assert_eq!(state.l1_deposit_block(), 5);
+        assert_eq!(state.supply().deposited, 50);
+        assert_eq!(
+            state.block_events()[..2],
+            [
+                RollupEvent::AccountCreated {
+                    address: alice.address()
+                },
+                RollupEvent::Deposit {
+                    to: alice.address(),
+                    amount: 50,
+                },
+            ]
+        );
+        assert_eq!(&state.ledger.replay(), state.ledger.accounts());
+
+        // The deposits are committed, and the reference implementation agrees.
+        let mut without_deposits = genesis.clone();
+        without_deposits.execute_transactions(&block.header, namespace_proof, block_hash);
+        assert_ne!(state.commit(), without_deposits.commit());
+        prev_state
+            .check_determinism(&state, block.header.height(), namespace_proof, block_hash)
+            .unwrap();
+        assert_eq!(
+            State::from_snapshot(state.to_snapshot()).commit(),
+            state.commit()
+        );
+
+        // Deposits from L1 blocks which were already credited are ignored, and the part of a
+        // deposit which would overflow a balance is refunded as a withdrawal.
+        state.stage_deposits(
+            6,
+            vec![
+                deposit(alice.address(), 50, 3),
+                deposit(bob, 10, 6),
+                deposit(alice.address(), Amount::MAX, 6),
+            ],
+        );
+        state.execute_transactions(&block.header, namespace_proof, block_hash);
+        assert_eq!(state.get_balance(&alice.address()), Amount::MAX);
+        assert_eq!(state.get_balance(&bob), 30);
+        assert_eq!(state.l1_deposit_block(), 6);
+        assert_eq!(
+            state.withdrawals(),
+            [Withdrawal {
+                recipient: alice.address(),
+                amount: 30,
+            }]
+        );
+        // Every deposited token is either credited or refunded.
+        let supply = state.supply();
+        assert_eq!(supply.deposited, 60 + Amount::MAX as u128);
+        assert_eq!(supply.withdrawn, 30);
+        assert_eq!(supply.circulating, supply.deposited - supply.withdrawn);
+    }
+
+    #[async_std::test]
+    async fn test_simulate() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let commitment = state.commit();
+
+        let mut transactions = vec![];
+        for (nonce, amount) in [(1, 60), (2, 60), (2, 40)] {
+            let transaction = Transaction {
+                amount,
+                destination: bob.address(),
+                nonce,
+                ..Default::default()
+            };
+            transactions.push(SignedTransaction::new(transaction, &alice).await);
+        }
+
+        let simulation = state.simulate(&transactions);
+        assert_eq!(
+            simulation.results,
+            vec![
+                Ok(()),
+                Err(RollupError::InsufficientBalance {
+                    address: alice.address()
+                }),
+                Ok(()),
+            ]
+        );
+        assert_ne!(simulation.commitment, commitment);
+        // Each transaction verifies its signature, and only the valid ones write state.
+        assert_eq!(simulation.metrics.len(), 3);
+        for (metrics, result) in simulation.metrics.iter().zip(&simulation.results) {
+            assert_eq!(metrics.signature_verifications, 1);
+            assert_eq!(metrics.state_writes > 0, result.is_ok());
+        }
+
+        // The simulated state is discarded.
+        assert_eq!(state.commit(), commitment);
+        assert_eq!(state.get_balance(&bob.address()), 0);
+    }
+
+    #[async_std::test]
+    async fn test_reference_execution() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let carol = LocalWallet::new(&mut rng);
+
+        // Valid transfers, a transfer to self, an overspend, a stale nonce, a new account and an
+        // unfunded sender.
+        let transactions = [
+            (&alice, bob.address(), 30, 1),
+            (&alice, alice.address(), 10, 2),
+            (&bob, alice.address(), 1000, 1),
+            (&alice, bob.address(), 5, 2),
+            (&bob, carol.address(), 20, 1),
+            (&carol, alice.address(), 50, 1),
+            (&alice, bob.address(), 5, 2),
+        ];
+        let mut signed = vec![];
+        for (sender, destination, amount, nonce) in transactions {
+            let transaction = Transaction {
+                amount,
+                destination,
+                nonce,
+                expires_at: Some(10),
+                ..Default::default()
+            };
+            signed.push(SignedTransaction::new(transaction, sender).await);
+        }
+
+        for mode in [ReplayProtection::Nonce, ReplayProtection::RecentHashes] {
+            let genesis =
+                State::from_initial_balances([(alice.address(), 100), (bob.address(), 100)], vm)
+                    .with_replay_protection(mode, 10);
+            let mut primary = genesis.clone();
+            let mut reference = genesis;
+            for transaction in &signed {
+                assert_eq!(
+                    primary.apply_transaction(transaction),
+                    reference::apply_transaction(&mut reference, transaction)
+                );
+            }
+            assert_eq!(primary.block_events(), reference.block_events());
+            assert_eq!(primary.commit(), reference.commit());
+        }
+    }
+
+    #[async_std::test]
+    async fn test_ordering_policy() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+
+        // Alice's second transaction is sequenced before her first.
+        let transactions = [
+            (&alice, bob.address(), 10, 2),
+            (&bob, alice.address(), 5, 1),
+            (&alice, bob.address(), 20, 1),
+        ];
+        let mut payloads = vec![b"not a transaction".to_vec()];
+        for (sender, destination, amount, nonce) in transactions {
+            let transaction = Transaction {
+                amount,
+                destination,
+                nonce,
+                ..Default::default()
+            };
+            payloads.push(SignedTransaction::new(transaction, sender).await.encode());
+        }
+        let mut reversed = payloads.clone();
+        reversed.reverse();
+        let block = mock_block(vm.into(), &[(vm.into(), payloads)]).await;
+        let reversed_block = mock_block(vm.into(), &[(vm.into(), reversed)]).await;
+        let block_hash = block.header.commit();
+
+        let execute = |policy, block: &MockBlock| {
+            let mut state =
+                State::from_initial_balances([(alice.address(), 100), (bob.address(), 100)], vm)
+                    .with_ordering_policy(policy);
+            state.execute_transactions(
+                &block.header,
+                block.namespace_proof.as_ref().unwrap(),
+                block_hash,
+            );
+            state
+        };
+
+        // In namespace order, Alice's second transaction has the wrong nonce.
+        let state = execute(OrderingPolicy::Namespace, &block);
+        let failures = state
+            .block_results
+            .iter()
+            .filter(|(_, res)| res.is_err())
+            .count();
+        assert_eq!(failures, 2);
+        assert_eq!(state.get_balance(&bob.address()), 115);
+
+        // Sorted by sender and nonce, every transfer succeeds, and the malformed payload comes last
+        // regardless of where it was sequenced.
+        let state = execute(OrderingPolicy::SenderNonce, &block);
+        let results = state
+            .block_results
+            .iter()
+            .map(|(_, res)| res.is_ok())
+            .collect::<Vec<_>>();
+        assert_eq!(results, [true, true, true, false]);
+        assert_eq!(state.get_balance(&alice.address()), 75);
+        assert_eq!(state.get_balance(&bob.address()), 125);
+
+        // The same set of payloads always yields the same state, whatever the namespace order, and
+        // re-executing it from scratch reproduces the commitment exactly.
+        let reordered = execute(OrderingPolicy::SenderNonce, &reversed_block);
+        assert_eq!(state.block_results, reordered.block_results);
+        assert_eq!(state.commit(), reordered.commit());
+        assert_eq!(
+            state.commit(),
+            execute(OrderingPolicy::SenderNonce, &block).commit()
+        );
+
+        // The policy is committed by its discriminant, which must not change between builds.
+        assert_eq!(OrderingPolicy::Namespace as u64, 0);
+        assert_eq!(OrderingPolicy::SenderNonce as u64, 1);
+        assert_ne!(
+            State::from_initial_balances([], vm).commit(),
+            State::from_initial_balances([], vm)
+                .with_ordering_policy(OrderingPolicy::SenderNonce)
+                .commit()
+        );
+    }
+
+    #[async_std::test]
+    async fn test_execution_metrics() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let transfer = SignedTransaction::new(
+            Transaction {
+                amount: 10,
+                destination: Address::random(),
+                nonce: 1,
+                expires_at: Some(10),
+                ..Default::default()
+            },
+            &alice,
+        )
+        .await;
+        let payloads = vec![transfer.encode(), b"not a transaction".to_vec()];
+        let block = mock_block(vm.into(), &[(vm.into(), payloads)]).await;
+
+        for (mode, reads, writes) in [
+            (ReplayProtection::Nonce, 2, 2),
+            (ReplayProtection::RecentHashes, 3, 3),
+        ] {
+            let mut state = State::from_initial_balances([(alice.address(), 100)], vm)
+                .with_replay_protection(mode, 10);
+            let commitment = state.commit();
+            state.execute_transactions(
+                &block.header,
+                block.namespace_proof.as_ref().unwrap(),
+                block.header.commit(),
+            );
+            let metrics = state.block_metrics();
+            assert_eq!(metrics.len(), 2);
+            assert_eq!(
+                (
+                    metrics[0].signature_verifications,
+                    metrics[0].state_reads,
+                    metrics[0].state_writes
+                ),
+                (1, reads, writes)
+            );
+            // The malformed payload fails to decode before touching the state.
+            assert_eq!(
+                ExecutionMetrics {
+                    time_us: 0,
+                    ..metrics[1]
+                },
+                ExecutionMetrics::default()
+            );
+
+            // Metrics are not committed, so they cannot cause nodes to diverge.
+            let mut replayed = State::from_initial_balances([(alice.address(), 100)], vm)
+                .with_replay_protection(mode, 10);
+            assert_eq!(replayed.commit(), commitment);
+            replayed.execute_transactions(
+                &block.header,
+                block.namespace_proof.as_ref().unwrap(),
+                block.header.commit(),
+            );
+            assert_eq!(replayed.commit(), state.commit());
+        }
+    }
 }
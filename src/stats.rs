@@ -0,0 +1,577 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Statistics collected by the executor and served by the rollup API.
+
+use async_std::sync::RwLock;
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use strum_macros::Display;
+
+/// Delay between a HotShot block becoming available and the light client update on the L1 which
+/// finalizes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinalityLagSample {
+    pub block_height: u64,
+    /// Unix timestamp (in seconds) of the HotShot header.
+    pub hotshot_timestamp: u64,
+    /// Unix timestamp (in seconds) of the L1 block containing the light client update covering the
+    /// block.
+    pub l1_timestamp: u64,
+}
+
+impl FinalityLagSample {
+    pub fn lag_secs(&self) -> u64 {
+        self.l1_timestamp.saturating_sub(self.hotshot_timestamp)
+    }
+}
+
+/// Summary of the finality lag over the samples currently retained.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FinalityLagSummary {
+    pub count: usize,
+    pub min_secs: u64,
+    pub max_secs: u64,
+    pub mean_secs: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FinalityLagReport {
+    pub summary: FinalityLagSummary,
+    pub samples: Vec<FinalityLagSample>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    samples: VecDeque<FinalityLagSample>,
+    capacity: usize,
+    csv: Option<PathBuf>,
+}
+
+/// A time series of finality lag samples, kept in a bounded ring buffer and optionally appended
+/// to a CSV file.
+#[derive(Clone, Debug)]
+pub struct FinalityLagTracker {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl Default for FinalityLagTracker {
+    fn default() -> Self {
+        Self::new(1000, None)
+    }
+}
+
+impl FinalityLagTracker {
+    /// Track the most recent `capacity` samples, appending every sample to `csv` if provided.
+    pub fn new(capacity: usize, csv: Option<PathBuf>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Inner {
+                samples: VecDeque::with_capacity(capacity),
+                capacity,
+                csv,
+            })),
+        }
+    }
+
+    pub async fn record(&self, sample: FinalityLagSample) {
+        let mut inner = self.inner.write().await;
+        if let Some(path) = &inner.csv {
+            if let Err(err) = append_csv(path, &sample) {
+                tracing::warn!("Failed to export finality lag sample to {path:?}: {err}");
+            }
+        }
+        if inner.samples.len() == inner.capacity {
+            inner.samples.pop_front();
+        }
+        if inner.capacity > 0 {
+            inner.samples.push_back(sample);
+        }
+    }
+
+    pub async fn report(&self) -> FinalityLagReport {
+        let inner = self.inner.read().await;
+        let samples = inner.samples.iter().copied().collect::<Vec<_>>();
+        let summary = if samples.is_empty() {
+            FinalityLagSummary::default()
+        } else {
+            let lags = samples.iter().map(FinalityLagSample::lag_secs);
+            FinalityLagSummary {
+                count: samples.len(),
+                min_secs: lags.clone().min().unwrap(),
+                max_secs: lags.clone().max().unwrap(),
+                mean_secs: lags.sum::<u64>() as f64 / samples.len() as f64,
+            }
+        };
+        FinalityLagReport { summary, samples }
+    }
+}
+
+/// The current Unix time in milliseconds.
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// When a transaction reached each stage of its path from the rollup API to verification on the
+/// L1, as Unix timestamps in milliseconds.
+///
+/// Stages which this node did not observe are `None`. For example, a transaction submitted through
+/// another node has no `received_ms` or `submitted_ms`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionTimings {
+    /// Received by the rollup API.
+    pub received_ms: Option<u64>,
+    /// Submitted to the sequencer.
+    pub submitted_ms: Option<u64>,
+    /// Timestamp of the HotShot header of the block which included the transaction. HotShot
+    /// timestamps have a resolution of one second.
+    pub finalized_ms: Option<u64>,
+    /// Executed by the rollup.
+    pub executed_ms: Option<u64>,
+    /// A proof covering the transaction's block was confirmed by the rollup contract.
+    pub verified_ms: Option<u64>,
+}
+
+/// A segment of the path of a transaction through the system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum LatencyStage {
+    /// From receipt by the API to submission to the sequencer.
+    Submission,
+    /// From submission to the sequencer to HotShot finality.
+    Finality,
+    /// From HotShot finality to execution by the rollup.
+    Execution,
+    /// From execution to verification on the L1.
+    Verification,
+    /// From receipt by the API to verification on the L1.
+    EndToEnd,
+}
+
+impl LatencyStage {
+    pub const ALL: [Self; 5] = [
+        Self::Submission,
+        Self::Finality,
+        Self::Execution,
+        Self::Verification,
+        Self::EndToEnd,
+    ];
+
+    /// The duration of this stage for a transaction, if it has completed the stage.
+    pub fn duration_ms(&self, timings: &TransactionTimings) -> Option<u64> {
+        let (start, end) = match self {
+            Self::Submission => (timings.received_ms, timings.submitted_ms),
+            Self::Finality => (timings.submitted_ms, timings.finalized_ms),
+            Self::Execution => (timings.finalized_ms, timings.executed_ms),
+            Self::Verification => (timings.executed_ms, timings.verified_ms),
+            Self::EndToEnd => (timings.received_ms, timings.verified_ms),
+        };
+        Some(end?.saturating_sub(start?))
+    }
+}
+
+/// Percentiles of the duration of one stage over the transactions currently retained.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageLatency {
+    pub stage: LatencyStage,
+    /// Number of retained transactions which completed the stage.
+    pub count: usize,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyReport {
+    /// Number of transactions currently retained.
+    pub transactions: usize,
+    pub stages: Vec<StageLatency>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct TrackedTransaction {
+    block_height: Option<u64>,
+    timings: TransactionTimings,
+}
+
+#[derive(Debug)]
+struct LatencyInner {
+    transactions: HashMap<H256, TrackedTransaction>,
+    // Hashes in the order they were first seen, for evicting the oldest.
+    order: VecDeque<H256>,
+    capacity: usize,
+}
+
+impl LatencyInner {
+    fn entry(&mut self, hash: H256) -> Option<&mut TrackedTransaction> {
+        if self.capacity == 0 {
+            return None;
+        }
+        if !self.transactions.contains_key(&hash) {
+            if self.order.len() == self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.transactions.remove(&oldest);
+                }
+            }
+            self.order.push_back(hash);
+        }
+        Some(self.transactions.entry(hash).or_default())
+    }
+}
+
+/// Timings of the most recent transactions, by transaction hash, over a sliding window.
+#[derive(Clone, Debug)]
+pub struct LatencyTracker {
+    inner: Arc<RwLock<LatencyInner>>,
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
+
+impl LatencyTracker {
+    /// Track the timings of the `capacity` most recently seen transactions.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(LatencyInner {
+                transactions: HashMap::new(),
+                order: VecDeque::with_capacity(capacity),
+                capacity,
+            })),
+        }
+    }
+
+    pub async fn record_received(&self, hash: H256, at_ms: u64) {
+        if let Some(tracked) = self.inner.write().await.entry(hash) {
+            tracked.timings.received_ms.get_or_insert(at_ms);
+        }
+    }
+
+    pub async fn record_submitted(&self, hash: H256, at_ms: u64) {
+        if let Some(tracked) = self.inner.write().await.entry(hash) {
+            tracked.timings.submitted_ms.get_or_insert(at_ms);
+        }
+    }
+
+    /// Record the execution of `hashes` in the block at `block_height`, which HotShot finalized at
+    /// `finalized_ms`.
+    pub async fn record_executed(
+        &self,
+        block_height: u64,
+        finalized_ms: u64,
+        executed_ms: u64,
+        hashes: impl IntoIterator<Item = H256>,
+    ) {
+        let mut inner = self.inner.write().await;
+        for hash in hashes {
+            let Some(tracked) = inner.entry(hash) else {
+                return;
+            };
+            // A replayed transaction has the same hash as the original, whose timings are kept.
+            if tracked.block_height.is_none() {
+                tracked.block_height = Some(block_height);
+                tracked.timings.finalized_ms = Some(finalized_ms);
+                tracked.timings.executed_ms = Some(executed_ms);
+            }
+        }
+    }
+
+    /// Record that every block up to and including `block_height` has been verified on the L1.
+    pub async fn record_verified(&self, block_height: u64, at_ms: u64) {
+        let mut inner = self.inner.write().await;
+        for tracked in inner.transactions.values_mut() {
+            if tracked
+                .block_height
+                .is_some_and(|height| height <= block_height)
+            {
+                tracked.timings.verified_ms.get_or_insert(at_ms);
+            }
+        }
+    }
+
+    pub async fn timings(&self, hash: &H256) -> Option<TransactionTimings> {
+        self.inner
+            .read()
+            .await
+            .transactions
+            .get(hash)
+            .map(|tracked| tracked.timings)
+    }
+
+    pub async fn report(&self) -> LatencyReport {
+        let inner = self.inner.read().await;
+        let stages = LatencyStage::ALL
+            .into_iter()
+            .map(|stage| {
+                let mut durations = inner
+                    .transactions
+                    .values()
+                    .filter_map(|tracked| stage.duration_ms(&tracked.timings))
+                    .collect::<Vec<_>>();
+                durations.sort_unstable();
+                StageLatency {
+                    stage,
+                    count: durations.len(),
+                    p50_ms: percentile(&durations, 50),
+                    p95_ms: percentile(&durations, 95),
+                    p99_ms: percentile(&durations, 99),
+                }
+            })
+            .collect();
+        LatencyReport {
+            transactions: inner.transactions.len(),
+            stages,
+        }
+    }
+}
+
+/// Resources consumed by executing a single rollup transaction.
+///
+/// The counts are deterministic, so every node reports the same counts for the same transaction,
+/// but the execution time depends on the node. Neither is part of the state commitment, so they can
+/// be used to calibrate experimental fee models without affecting consensus on the rollup state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionMetrics {
+    /// Wall-clock time spent executing the transaction, in microseconds.
+    pub time_us: u64,
+    /// Number of signatures recovered, including the operator countersignature, if any.
+    pub signature_verifications: u64,
+    /// Number of reads of account and replay protection state.
+    pub state_reads: u64,
+    /// Number of writes to account and replay protection state.
+    pub state_writes: u64,
+}
+
+impl ExecutionMetrics {
+    /// The sum of `self` and `other`, resource by resource.
+    pub fn add(self, other: Self) -> Self {
+        Self {
+            time_us: self.time_us + other.time_us,
+            signature_verifications: self.signature_verifications + other.signature_verifications,
+            state_reads: self.state_reads + other.state_reads,
+            state_writes: self.state_writes + other.state_writes,
+        }
+    }
+
+    /// The greater of `self` and `other`, resource by resource.
+    pub fn max(self, other: Self) -> Self {
+        Self {
+            time_us: self.time_us.max(other.time_us),
+            signature_verifications: self
+                .signature_verifications
+                .max(other.signature_verifications),
+            state_reads: self.state_reads.max(other.state_reads),
+            state_writes: self.state_writes.max(other.state_writes),
+        }
+    }
+}
+
+/// Summary of the resources consumed by the rollup transactions in one block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockExecutionStats {
+    pub block_height: u64,
+    /// Number of rollup transactions in the block, including invalid ones.
+    pub transactions: usize,
+    /// Resources consumed by all transactions in the block.
+    pub total: ExecutionMetrics,
+    /// The most of each resource consumed by any single transaction in the block.
+    pub max: ExecutionMetrics,
+}
+
+impl BlockExecutionStats {
+    pub fn new(block_height: u64, metrics: &[ExecutionMetrics]) -> Self {
+        Self {
+            block_height,
+            transactions: metrics.len(),
+            total: metrics
+                .iter()
+                .fold(ExecutionMetrics::default(), |acc, m| acc.add(*m)),
+            max: metrics
+                .iter()
+                .fold(ExecutionMetrics::default(), |acc, m| acc.max(*m)),
+        }
+    }
+}
+
+/// Index of the execution statistics of each executed block.
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionStatsIndex {
+    blocks: Arc<RwLock<BTreeMap<u64, BlockExecutionStats>>>,
+}
+
+impl ExecutionStatsIndex {
+    pub async fn insert(&self, stats: BlockExecutionStats) {
+        self.blocks.write().await.insert(stats.block_height, stats);
+    }
+
+    /// The statistics of the block at `block_height`, or `None` if the block has not been executed.
+    pub async fn get(&self, block_height: u64) -> Option<BlockExecutionStats> {
+        self.blocks.read().await.get(&block_height).copied()
+    }
+}
+
+/// The `p`th percentile of `sorted`, by the nearest-rank method, or 0 if `sorted` is empty.
+pub(crate) fn percentile(sorted: &[u64], p: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn append_csv(path: &Path, sample: &FinalityLagSample) -> io::Result<()> {
+    let exists = path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if !exists {
+        writeln!(file, "block_height,hotshot_timestamp,l1_timestamp,lag_secs")?;
+    }
+    writeln!(
+        file,
+        "{},{},{},{}",
+        sample.block_height,
+        sample.hotshot_timestamp,
+        sample.l1_timestamp,
+        sample.lag_secs()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_finality_lag_tracker() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv = dir.path().join("lag.csv");
+        let tracker = FinalityLagTracker::new(2, Some(csv.clone()));
+        for (block_height, lag) in [(1, 10), (2, 2), (3, 4)] {
+            tracker
+                .record(FinalityLagSample {
+                    block_height,
+                    hotshot_timestamp: 100,
+                    l1_timestamp: 100 + lag,
+                })
+                .await;
+        }
+
+        // Only the most recent samples are retained in memory.
+        let report = tracker.report().await;
+        assert_eq!(
+            report
+                .samples
+                .iter()
+                .map(|s| s.block_height)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(report.summary.count, 2);
+        assert_eq!(report.summary.min_secs, 2);
+        assert_eq!(report.summary.max_secs, 4);
+        assert_eq!(report.summary.mean_secs, 3.0);
+
+        // Every sample is exported.
+        let exported = std::fs::read_to_string(csv).unwrap();
+        assert_eq!(exported.lines().count(), 4);
+    }
+
+    #[async_std::test]
+    async fn test_latency_tracker() {
+        let tracker = LatencyTracker::new(100);
+        let hashes = (0..100).map(|_| H256::random()).collect::<Vec<_>>();
+        for (i, hash) in hashes.iter().enumerate() {
+            tracker.record_received(*hash, 1000).await;
+            tracker.record_submitted(*hash, 1000 + i as u64 + 1).await;
+        }
+        tracker
+            .record_executed(1, 5000, 6000, hashes[..50].iter().copied())
+            .await;
+        tracker.record_verified(1, 10000).await;
+
+        let timings = tracker.timings(&hashes[0]).await.unwrap();
+        assert_eq!(
+            timings,
+            TransactionTimings {
+                received_ms: Some(1000),
+                submitted_ms: Some(1001),
+                finalized_ms: Some(5000),
+                executed_ms: Some(6000),
+                verified_ms: Some(10000),
+            }
+        );
+
+        let report = tracker.report().await;
+        assert_eq!(report.transactions, 100);
+        let submission = &report.stages[0];
+        assert_eq!(submission.stage, LatencyStage::Submission);
+        assert_eq!(
+            (
+                submission.count,
+                submission.p50_ms,
+                submission.p95_ms,
+                submission.p99_ms
+            ),
+            (100, 50, 95, 99)
+        );
+        let end_to_end = &report.stages[4];
+        assert_eq!(end_to_end.stage, LatencyStage::EndToEnd);
+        assert_eq!((end_to_end.count, end_to_end.p99_ms), (50, 9000));
+
+        // The oldest transaction is evicted once the window is full.
+        tracker.record_received(H256::random(), 2000).await;
+        assert_eq!(tracker.timings(&hashes[0]).await, None);
+        assert_eq!(tracker.report().await.transactions, 100);
+    }
+
+    #[test]
+    fn test_block_execution_stats() {
+        let metrics = [
+            ExecutionMetrics {
+                time_us: 10,
+                signature_verifications: 1,
+                state_reads: 2,
+                state_writes: 3,
+            },
+            ExecutionMetrics {
+                time_us: 30,
+                signature_verifications: 2,
+                state_reads: 1,
+                state_writes: 0,
+            },
+        ];
+        let stats = BlockExecutionStats::new(7, &metrics);
+        assert_eq!(stats.transactions, 2);
+        assert_eq!(
+            stats.total,
+            ExecutionMetrics {
+                time_us: 40,
+                signature_verifications: 3,
+                state_reads: 3,
+                state_writes: 3,
+            }
+        );
+        assert_eq!(
+            stats.max,
+            ExecutionMetrics {
+                time_us: 30,
+                signature_verifications: 2,
+                state_reads: 2,
+                state_writes: 3,
+            }
+        );
+        assert_eq!(BlockExecutionStats::new(8, &[]).total, Default::default());
+    }
+}
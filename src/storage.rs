@@ -0,0 +1,284 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Persistent checkpoints of the rollup state.
+//!
+//! A node started with `--state-storage <path>` saves a [`Checkpoint`] after every block it
+//! executes, and resumes from the most recent checkpoint when it restarts, even if it was not shut
+//! down cleanly. Unlike a [warm start](crate::warm_start), which is written once at shutdown, a
+//! checkpoint holds only what is needed to continue executing: the state, the proofs not yet handed
+//! to the outbox and the next block to execute. Receipts of transactions executed before a restart
+//! are not restored.
+//!
+//! Checkpoints are saved through the [`Storage`] trait, so that the file-backed [`FileStorage`] can
+//! be replaced by a database without changes to the executor.
+
+use crate::prover::PendingProofs;
+use crate::state::{State, StateSnapshot};
+use crate::warm_start::Resume;
+use committable::{Commitment, Committable};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::fmt::Debug;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Version of the checkpoint format, bumped whenever a checkpoint written by an older node can no
+/// longer be restored.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+#[derive(Debug, Snafu)]
+pub enum StorageError {
+    #[snafu(display("Error accessing state storage {}: {source}", path.display()))]
+    Io { path: PathBuf, source: io::Error },
+    #[snafu(display("Malformed checkpoint in {}: {source}", path.display()))]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[snafu(display(
+        "Checkpoint has format version {version}, but this node writes version \
+         {CHECKPOINT_VERSION}. Remove the checkpoint to start from genesis."
+    ))]
+    Version { version: u32 },
+    #[snafu(display(
+        "Checkpoint was written by a node with genesis state {recorded}, but this node has genesis \
+         state {configured}. Remove the checkpoint to start from genesis."
+    ))]
+    GenesisMismatch {
+        recorded: Commitment<State>,
+        configured: Commitment<State>,
+    },
+}
+
+/// The state of the executor after a block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    version: u32,
+    /// Commitment to the genesis state of the node which wrote the checkpoint.
+    genesis: Commitment<State>,
+    state: StateSnapshot,
+    resume: Resume,
+}
+
+impl Checkpoint {
+    /// Checkpoint `state`, from which execution resumes at `resume`.
+    pub fn new(genesis: Commitment<State>, state: &State, resume: Resume) -> Self {
+        Self {
+            version: CHECKPOINT_VERSION,
+            genesis,
+            state: state.to_snapshot(),
+            resume,
+        }
+    }
+
+    /// Height of the first block which had not been executed when the checkpoint was saved.
+    pub fn next_block(&self) -> u64 {
+        self.resume.next_block
+    }
+
+    /// Restore the checkpoint on a node whose genesis state is `genesis`.
+    ///
+    /// Returns the state to execute from and where the executor resumes. Fails if the checkpoint
+    /// was written by a node with a different genesis state, since its state would not extend this
+    /// node's.
+    ///
+    /// Transaction hooks are not part of the checkpoint, so the returned state must be given the
+    /// same hooks as `genesis`.
+    pub fn restore(self, genesis: &State) -> Result<(State, Resume), StorageError> {
+        if self.version != CHECKPOINT_VERSION {
+            return Err(StorageError::Version {
+                version: self.version,
+            });
+        }
+        let configured = genesis.commit();
+        if self.genesis != configured {
+            return Err(StorageError::GenesisMismatch {
+                recorded: self.genesis,
+                configured,
+            });
+        }
+        Ok((State::from_snapshot(self.state), self.resume))
+    }
+}
+
+/// Where checkpoints are kept.
+///
+/// Only the most recent checkpoint is needed, so saving a checkpoint may discard the previous one,
+/// but must never leave storage without a complete checkpoint.
+pub trait Storage: Debug + Send + Sync {
+    /// The most recently saved checkpoint, or `None` if none has been saved.
+    fn load(&self) -> Result<Option<Checkpoint>, StorageError>;
+
+    /// Save `checkpoint`, replacing the previous one.
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), StorageError>;
+}
+
+/// Storage in a single JSON file.
+#[derive(Clone, Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(&self) -> Result<Option<Checkpoint>, StorageError> {
+        let path = &self.path;
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(StorageError::Io {
+                    path: path.clone(),
+                    source,
+                })
+            }
+        };
+        serde_json::from_str(&contents)
+            .context(JsonSnafu { path })
+            .map(Some)
+    }
+
+    /// The checkpoint is written to a temporary file which is then renamed over the previous one,
+    /// so that a crash never leaves a partially written checkpoint behind.
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), StorageError> {
+        let path = &self.path;
+        let contents = serde_json::to_string(checkpoint).context(JsonSnafu { path })?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, contents).context(IoSnafu { path: &tmp })?;
+        std::fs::rename(&tmp, path).context(IoSnafu { path })
+    }
+}
+
+/// Storage which lasts only as long as the process, for tests and for nodes which do not persist
+/// their state.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    checkpoint: Arc<Mutex<Option<Checkpoint>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn load(&self) -> Result<Option<Checkpoint>, StorageError> {
+        Ok(self.checkpoint.lock().unwrap().clone())
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), StorageError> {
+        *self.checkpoint.lock().unwrap() = Some(checkpoint.clone());
+        Ok(())
+    }
+}
+
+/// Saves a checkpoint of the executor's state after each block.
+#[derive(Clone, Debug)]
+pub struct Checkpointer {
+    storage: Arc<dyn Storage>,
+    /// Commitment to this node's genesis state, checked when a checkpoint is restored.
+    genesis: Commitment<State>,
+}
+
+impl Checkpointer {
+    pub fn new(storage: Arc<dyn Storage>, genesis: Commitment<State>) -> Self {
+        Self { storage, genesis }
+    }
+
+    /// Save a checkpoint from which execution continues with `state` at `next_block`.
+    ///
+    /// A checkpoint which cannot be saved is logged rather than halting execution, since the state
+    /// is still held in memory. If the node restarts before the next checkpoint is saved, it
+    /// resumes from an earlier one and executes the missing blocks again.
+    pub(crate) fn checkpoint(
+        &self,
+        state: &State,
+        next_block: u64,
+        pending_proofs: &PendingProofs,
+    ) {
+        let resume = Resume {
+            next_block,
+            pending_proofs: pending_proofs.clone(),
+        };
+        if let Err(err) = self
+            .storage
+            .save(&Checkpoint::new(self.genesis, state, resume))
+        {
+            tracing::error!("Unable to checkpoint the state before block {next_block}: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ReplayProtection;
+    use crate::transaction::{SignedTransaction, Transaction};
+    use crate::RollupVM;
+    use espresso_types::NamespaceId;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[async_std::test]
+    async fn test_checkpoint_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = FileStorage::new(dir.path().join("state.json"));
+        let memory = MemoryStorage::default();
+        assert!(file.load().unwrap().is_none());
+        assert!(memory.load().unwrap().is_none());
+
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let bob = LocalWallet::new(&mut rand::thread_rng()).address();
+        let genesis = State::from_initial_balances(
+            [(wallet.address(), 100)],
+            RollupVM::new(NamespaceId::from(1_u64)),
+        );
+        let transaction = SignedTransaction::new(
+            Transaction {
+                amount: 10,
+                destination: bob,
+                nonce: 1,
+                ..Default::default()
+            },
+            &wallet,
+        )
+        .await;
+        let mut state = genesis.clone();
+        state.apply_transaction(&transaction).unwrap();
+
+        for storage in [
+            Arc::new(file) as Arc<dyn Storage>,
+            Arc::new(memory) as Arc<dyn Storage>,
+        ] {
+            let checkpointer = Checkpointer::new(storage.clone(), genesis.commit());
+            checkpointer.checkpoint(&genesis, 3, &PendingProofs::default());
+            // A later checkpoint replaces the earlier one.
+            checkpointer.checkpoint(&state, 4, &PendingProofs::default());
+
+            let checkpoint = storage.load().unwrap().unwrap();
+            assert_eq!(checkpoint.next_block(), 4);
+
+            // The checkpoint only restores on a node with the same genesis state.
+            let other = genesis
+                .clone()
+                .with_replay_protection(ReplayProtection::RecentHashes, 10);
+            assert!(matches!(
+                checkpoint.clone().restore(&other),
+                Err(StorageError::GenesisMismatch { .. })
+            ));
+
+            let (restored, resume) = checkpoint.restore(&genesis).unwrap();
+            assert_eq!(restored.commit(), state.commit());
+            assert_eq!(restored.get_balance(&bob), 10);
+            assert_eq!(resume.next_block, 4);
+        }
+    }
+}
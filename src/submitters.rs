@@ -0,0 +1,197 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Address-scoped tokens for submitting transactions.
+//!
+//! A hosted node may require every submission to present a token registered to its sender. The
+//! owner of an address registers it by signing a [`SubmitterRegistration`], in exchange for a random
+//! token. Submissions must then carry the token in an `Authorization: Bearer` header, and are
+//! rejected by the API unless their sender is the address the token was registered to, so one user
+//! cannot submit transactions in the name of another before they ever reach the sequencer.
+//!
+//! Tokens are kept in memory, so users must register again after the node restarts.
+
+use async_std::sync::{Arc, RwLock};
+use ethers::signers::Signer;
+use ethers::types::{Address, Signature, H256};
+use ethers::utils::keccak256;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::HashMap;
+use tide_disco::{error::ServerError, StatusCode};
+
+#[derive(Clone, Debug, Snafu, PartialEq, Eq)]
+pub enum SubmitterError {
+    #[snafu(display("Submitter registration is not signed by {address:?}."))]
+    InvalidSignature { address: Address },
+    #[snafu(display("Submissions require an Authorization: Bearer submitter token."))]
+    MissingToken,
+    #[snafu(display("Unknown submitter token."))]
+    UnknownToken,
+    #[snafu(display(
+        "Submitter token is registered to {registered:?}, but the transaction is from {sender:?}."
+    ))]
+    WrongSender {
+        registered: Address,
+        sender: Address,
+    },
+}
+
+impl SubmitterError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::WrongSender { .. } => StatusCode::FORBIDDEN,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+impl From<SubmitterError> for ServerError {
+    fn from(err: SubmitterError) -> Self {
+        Self {
+            status: err.status(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// A request for a token to submit transactions from `address`, signed by `address`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmitterRegistration {
+    pub address: Address,
+    signature: Signature,
+}
+
+impl SubmitterRegistration {
+    fn message(address: &Address) -> String {
+        format!("Register submitter {address:?}")
+    }
+
+    /// Register the address of `wallet`.
+    pub async fn new(wallet: &impl Signer) -> Self {
+        let address = wallet.address();
+        let signature = wallet.sign_message(Self::message(&address)).await.unwrap();
+        Self { address, signature }
+    }
+
+    fn verify(&self) -> Result<(), SubmitterError> {
+        self.signature
+            .verify(Self::message(&self.address), self.address)
+            .map_err(|_| SubmitterError::InvalidSignature {
+                address: self.address,
+            })
+    }
+}
+
+/// The registered submitter tokens.
+#[derive(Clone, Debug, Default)]
+pub struct SubmitterRegistry {
+    // The address of each token, by the hash of the token, so that tokens are not compared
+    // byte-by-byte and are never logged.
+    tokens: Arc<RwLock<HashMap<H256, Address>>>,
+}
+
+impl SubmitterRegistry {
+    /// Issue a new token for the address of `registration`, after checking that it is signed by
+    /// that address.
+    ///
+    /// An address may hold several tokens, for example one per device.
+    pub async fn register(
+        &self,
+        registration: SubmitterRegistration,
+    ) -> Result<String, SubmitterError> {
+        registration.verify()?;
+        let mut bytes = [0; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+        self.tokens
+            .write()
+            .await
+            .insert(H256(keccak256(&token)), registration.address);
+        tracing::info!(
+            "Registered a submitter token for {:?}",
+            registration.address
+        );
+        Ok(token)
+    }
+
+    /// Check that the token in the value of an `Authorization` header may submit transactions from
+    /// `sender`.
+    pub async fn authorize(
+        &self,
+        authorization: Option<&str>,
+        sender: Address,
+    ) -> Result<(), SubmitterError> {
+        let token = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .ok_or(SubmitterError::MissingToken)?;
+        let registered = *self
+            .tokens
+            .read()
+            .await
+            .get(&H256(keccak256(token)))
+            .ok_or(SubmitterError::UnknownToken)?;
+        if registered != sender {
+            return Err(SubmitterError::WrongSender { registered, sender });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::LocalWallet;
+
+    #[async_std::test]
+    async fn test_submitter_tokens() {
+        let alice = LocalWallet::new(&mut rand::thread_rng());
+        let bob = LocalWallet::new(&mut rand::thread_rng());
+        let registry = SubmitterRegistry::default();
+
+        let token = registry
+            .register(SubmitterRegistration::new(&alice).await)
+            .await
+            .unwrap();
+        let bearer = format!("Bearer {token}");
+        registry
+            .authorize(Some(&bearer), alice.address())
+            .await
+            .unwrap();
+
+        // Alice's token cannot submit Bob's transactions.
+        assert_eq!(
+            registry.authorize(Some(&bearer), bob.address()).await,
+            Err(SubmitterError::WrongSender {
+                registered: alice.address(),
+                sender: bob.address(),
+            })
+        );
+        assert_eq!(
+            registry.authorize(None, alice.address()).await,
+            Err(SubmitterError::MissingToken)
+        );
+        assert_eq!(
+            registry
+                .authorize(Some("Bearer not-a-token"), alice.address())
+                .await,
+            Err(SubmitterError::UnknownToken)
+        );
+
+        // Only the owner of an address can register it.
+        let mut forged = SubmitterRegistration::new(&bob).await;
+        forged.address = alice.address();
+        assert_eq!(
+            registry.register(forged).await,
+            Err(SubmitterError::InvalidSignature {
+                address: alice.address()
+            })
+        );
+    }
+}
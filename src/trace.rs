@@ -0,0 +1,422 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Step-by-step tracing of transaction validation, for debugging failing transactions.
+//!
+//! A trace runs the checks made when a transaction payload is executed, in the same order, against
+//! a copy of the current state, and records the outcome of each. Once a check fails, the remaining
+//! checks are skipped, as they are during execution. If every check passes, the transaction is
+//! applied to the copy, and the trace reports the accounts it would change and the events it would
+//! emit.
+
+use crate::error::RollupError;
+use crate::events::RollupEvent;
+use crate::history::{diff_accounts, AccountChange};
+use crate::hooks;
+use crate::state::{ReplayProtection, State};
+use crate::transaction::{Submission, TransactionKind};
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+
+/// A check made when executing a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum TraceCheck {
+    /// The payload decodes as a signed transaction, possibly countersigned by an operator.
+    Decode,
+    /// The submission is allowed by the VM's policy for transactions not countersigned by the
+    /// operator.
+    SubmissionPolicy,
+    /// The transaction was not signed for another rollup chain.
+    ChainId,
+    /// The signature recovers to a sender.
+    Signature,
+    /// The transaction has not expired.
+    Expiry,
+    /// The sender has an account.
+    SenderAccount,
+    /// The transaction is not a replay, by nonce or by recent hash depending on the VM.
+    ReplayProtection,
+    /// The sender can afford the transfer.
+    Balance,
+    /// The handler for a custom transaction accepts it.
+    CustomHandler,
+}
+
+/// The outcome of one check.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum StepOutcome {
+    Passed {
+        detail: String,
+    },
+    Failed {
+        error: RollupError,
+    },
+    /// An earlier check failed.
+    Skipped,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStep {
+    pub check: TraceCheck,
+    #[serde(flatten)]
+    pub outcome: StepOutcome,
+}
+
+/// The trace of a transaction against the state after `block_height`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionTrace {
+    pub block_height: u64,
+    pub steps: Vec<TraceStep>,
+    /// The result of executing the transaction, which is the error of the failed check, if any.
+    pub result: Result<(), RollupError>,
+    /// Accounts which the transaction would change, ordered by address.
+    pub changes: Vec<AccountChange>,
+    /// Events which the transaction would emit.
+    pub events: Vec<RollupEvent>,
+}
+
+/// Records the outcome of each check, skipping checks after the first failure.
+struct Tracer {
+    steps: Vec<TraceStep>,
+    failure: Option<RollupError>,
+}
+
+impl Tracer {
+    /// Record the outcome of `check`, returning the checked value if it passed.
+    fn check<T>(
+        &mut self,
+        check: TraceCheck,
+        result: Result<T, RollupError>,
+        detail: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.steps.push(TraceStep {
+                    check,
+                    outcome: StepOutcome::Passed {
+                        detail: detail(&value),
+                    },
+                });
+                Some(value)
+            }
+            Err(error) => {
+                self.steps.push(TraceStep {
+                    check,
+                    outcome: StepOutcome::Failed {
+                        error: error.clone(),
+                    },
+                });
+                self.failure = Some(error);
+                None
+            }
+        }
+    }
+
+    fn skip(&mut self, checks: &[TraceCheck]) {
+        self.steps.extend(checks.iter().map(|&check| TraceStep {
+            check,
+            outcome: StepOutcome::Skipped,
+        }));
+    }
+}
+
+const TRANSFER_CHECKS: [TraceCheck; 7] = [
+    TraceCheck::SubmissionPolicy,
+    TraceCheck::ChainId,
+    TraceCheck::Signature,
+    TraceCheck::Expiry,
+    TraceCheck::SenderAccount,
+    TraceCheck::ReplayProtection,
+    TraceCheck::Balance,
+];
+
+/// Trace the execution of `payload`, as it would be found in the rollup namespace, against
+/// `state`.
+pub fn trace_transaction(state: &State, payload: &[u8]) -> TransactionTrace {
+    let mut scratch = state.clone();
+    let events_before = scratch.block_events().len();
+    let mut tracer = Tracer {
+        steps: vec![],
+        failure: None,
+    };
+
+    if let Some((kind, body)) = hooks::decode_custom(payload) {
+        tracer.check(TraceCheck::Decode, Ok(kind), |kind| {
+            format!("custom transaction of kind {kind}")
+        });
+        let result = scratch.apply_custom(kind, body);
+        tracer.check(TraceCheck::CustomHandler, result, |()| "accepted".into());
+    } else {
+        trace_transfer(&mut scratch, payload, &mut tracer);
+    }
+
+    let (result, changes, events) = match tracer.failure {
+        Some(error) => (Err(error), vec![], vec![]),
+        None => (
+            Ok(()),
+            diff_accounts(state.ledger().accounts(), scratch.ledger().accounts()),
+            scratch.block_events()[events_before..].to_vec(),
+        ),
+    };
+    TransactionTrace {
+        block_height: state.block_height(),
+        steps: tracer.steps,
+        result,
+        changes,
+        events,
+    }
+}
+
+/// Trace a built-in transfer, mirroring [`State::apply_transaction`], and apply it to `scratch` if
+/// every check passes.
+fn trace_transfer(scratch: &mut State, payload: &[u8], tracer: &mut Tracer) {
+    let Some(submission) = tracer.check(
+        TraceCheck::Decode,
+        Submission::decode(payload),
+        |s| match s {
+            Submission::Wrapped(_) => "transaction countersigned by an operator".into(),
+            Submission::Unwrapped(_) => "transaction without a countersignature".into(),
+        },
+    ) else {
+        return tracer.skip(&TRANSFER_CHECKS);
+    };
+    let policy = scratch.check_submission(&submission, payload.len());
+    if tracer
+        .check(TraceCheck::SubmissionPolicy, policy, |()| "accepted".into())
+        .is_none()
+    {
+        return tracer.skip(&TRANSFER_CHECKS[1..]);
+    }
+
+    let transaction = submission.transaction();
+    let chain = scratch.check_chain(transaction);
+    if tracer
+        .check(TraceCheck::ChainId, chain, |()| {
+            match transaction.chain_id() {
+                Some(chain_id) => format!("signed for chain {chain_id}"),
+                None => "not bound to a chain".into(),
+            }
+        })
+        .is_none()
+    {
+        return tracer.skip(&TRANSFER_CHECKS[2..]);
+    }
+    let Some(sender) = tracer.check(TraceCheck::Signature, transaction.recover(), |sender| {
+        format!("signed by {sender:?}")
+    }) else {
+        return tracer.skip(&TRANSFER_CHECKS[3..]);
+    };
+    let hash = transaction.hash();
+    let expiry = scratch
+        .check_expiry(transaction, hash)
+        .map(|()| transaction.transaction.expires_at);
+    if tracer
+        .check(TraceCheck::Expiry, expiry, |expires_at| match expires_at {
+            Some(expires_at) => format!("valid until block {expires_at}"),
+            None => "does not expire".into(),
+        })
+        .is_none()
+    {
+        return tracer.skip(&TRANSFER_CHECKS[4..]);
+    }
+    let account = scratch
+        .ledger()
+        .get(&sender)
+        .cloned()
+        .ok_or(RollupError::InsufficientBalance { address: sender });
+    let Some(account) = tracer.check(TraceCheck::SenderAccount, account, |account| {
+        format!("balance {}, nonce {}", account.balance, account.nonce)
+    }) else {
+        return tracer.skip(&TRANSFER_CHECKS[5..]);
+    };
+
+    let nonce = transaction.transaction.nonce;
+    let replay = match scratch.replay_protection() {
+        ReplayProtection::Nonce if nonce != account.nonce + 1 => Err(RollupError::InvalidNonce {
+            address: sender,
+            expected: account.nonce + 1,
+            actual: nonce,
+        }),
+        ReplayProtection::Nonce => Ok(format!("nonce {nonce} follows {}", account.nonce)),
+        ReplayProtection::RecentHashes if scratch.is_recent_transaction(&sender, &hash) => {
+            Err(RollupError::DuplicateTransaction {
+                address: sender,
+                hash,
+            })
+        }
+        ReplayProtection::RecentHashes => Ok(format!(
+            "{hash:?} not executed in the last {} blocks",
+            scratch.replay_window()
+        )),
+    };
+    if tracer
+        .check(TraceCheck::ReplayProtection, replay, String::clone)
+        .is_none()
+    {
+        return tracer.skip(&TRANSFER_CHECKS[6..]);
+    }
+
+    let amount = transaction.transaction.amount;
+    let action = match transaction.transaction.kind {
+        TransactionKind::Transfer => "transfer",
+        TransactionKind::Withdraw => "withdrawal",
+    };
+    let fee = scratch.fee_recipient(transaction).map_or(0, |(_, fee)| fee);
+    let balance = if amount.saturating_add(fee) > account.balance {
+        Err(RollupError::InsufficientBalance { address: sender })
+    } else {
+        Ok(())
+    };
+    if tracer
+        .check(TraceCheck::Balance, balance, |()| {
+            format!(
+                "{action} of {amount} with fee {fee} from balance {}",
+                account.balance
+            )
+        })
+        .is_some()
+    {
+        // Every check passed, so this cannot fail.
+        scratch
+            .apply_transaction(transaction)
+            .expect("traced transaction should apply");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainId;
+    use crate::machine::RollupStateMachine;
+    use crate::transaction::{SignedTransaction, Transaction};
+    use crate::RollupVM;
+    use espresso_types::NamespaceId;
+    use ethers::signers::{LocalWallet, Signer};
+    use ethers::types::{Address, H256};
+
+    #[async_std::test]
+    async fn test_trace_transaction() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let transfer = |amount, nonce| {
+            SignedTransaction::new(
+                Transaction {
+                    amount,
+                    destination: bob,
+                    nonce,
+                    ..Default::default()
+                },
+                &alice,
+            )
+        };
+
+        // A valid transfer passes every check and reports its effects.
+        let trace = trace_transaction(&state, &transfer(30, 1).await.encode());
+        assert_eq!(trace.result, Ok(()));
+        assert_eq!(
+            trace
+                .steps
+                .iter()
+                .map(|step| step.check)
+                .collect::<Vec<_>>(),
+            [
+                TraceCheck::Decode,
+                TraceCheck::SubmissionPolicy,
+                TraceCheck::ChainId,
+                TraceCheck::Signature,
+                TraceCheck::Expiry,
+                TraceCheck::SenderAccount,
+                TraceCheck::ReplayProtection,
+                TraceCheck::Balance,
+            ]
+        );
+        assert!(trace
+            .steps
+            .iter()
+            .all(|step| matches!(step.outcome, StepOutcome::Passed { .. })));
+        let bob_change = trace.changes.iter().find(|c| c.address == bob).unwrap();
+        assert_eq!(bob_change.balance_change, 30);
+        assert!(trace.events.contains(&RollupEvent::Transfer {
+            from: alice.address(),
+            to: bob,
+            amount: 30,
+        }));
+        // The state itself is unchanged.
+        assert_eq!(state.get_balance(&bob), 0);
+
+        // A bad nonce fails the replay check, and later checks are skipped.
+        let trace = trace_transaction(&state, &transfer(30, 5).await.encode());
+        let expected = RollupError::InvalidNonce {
+            address: alice.address(),
+            expected: 1,
+            actual: 5,
+        };
+        assert_eq!(trace.result, Err(expected.clone()));
+        assert_eq!(
+            trace.steps[6].outcome,
+            StepOutcome::Failed { error: expected }
+        );
+        assert_eq!(trace.steps[7].outcome, StepOutcome::Skipped);
+        assert!(trace.changes.is_empty());
+        assert_eq!(
+            trace.result,
+            state
+                .simulate([&transfer(30, 5).await])
+                .results
+                .pop()
+                .unwrap()
+        );
+
+        // A malformed payload fails to decode.
+        let trace = trace_transaction(&state, b"not a transaction");
+        assert!(matches!(
+            trace.result,
+            Err(RollupError::MalformedTransaction { .. })
+        ));
+        assert_eq!(trace.steps.len(), 8);
+
+        // A transaction signed for another chain fails the chain check.
+        let mut bound = state.clone();
+        let chain_id = ChainId(H256::random());
+        bound.set_chain_id(chain_id);
+        let other_chain = ChainId(H256::random());
+        let foreign = SignedTransaction::new_for_chain(
+            Transaction {
+                amount: 30,
+                destination: bob,
+                nonce: 1,
+                ..Default::default()
+            },
+            other_chain,
+            &alice,
+        )
+        .await;
+        let trace = trace_transaction(&bound, &foreign.encode());
+        assert_eq!(
+            trace.result,
+            Err(RollupError::WrongChain {
+                expected: chain_id,
+                actual: other_chain,
+            })
+        );
+        assert_eq!(trace.steps[2].check, TraceCheck::ChainId);
+
+        // So does a transaction signed without a chain ID.
+        let unbound = transfer(30, 1).await;
+        let trace = trace_transaction(&bound, &unbound.encode());
+        assert_eq!(
+            trace.result,
+            Err(RollupError::MissingChainId { expected: chain_id })
+        );
+        assert_eq!(trace.steps[2].check, TraceCheck::ChainId);
+    }
+}
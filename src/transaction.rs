@@ -4,20 +4,81 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
+use crate::chain::ChainId;
 use crate::error::RollupError;
 use crate::state::{Amount, Nonce};
-use ethers::{abi::Address, signers::Signer, types::Signature};
+use ethers::{
+    abi::{self, Address, Token},
+    signers::Signer,
+    types::{
+        transaction::eip712::{EIP712Domain, Eip712},
+        Signature, H256,
+    },
+    utils::keccak256,
+};
 use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::convert::Infallible;
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+/// Maximum size, in bytes, of an encoded transaction.
+///
+/// Encoded transactions are far smaller than this. Larger payloads, such as transactions padded
+/// with unknown fields, are rejected before they are decoded.
+pub const MAX_TRANSACTION_SIZE: usize = 4096;
+
+/// Maximum length, in bytes, of a transaction memo.
+pub const MAX_MEMO_LENGTH: usize = 256;
+
+/// What a built-in transaction does with its `amount`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransactionKind {
+    /// Move the tokens to the rollup account `destination`.
+    #[default]
+    Transfer,
+    /// Burn the tokens on the rollup, so that `destination` can claim them on the L1 from the
+    /// rollup contract. See [`withdrawal`](crate::withdrawal).
+    Withdraw,
+}
+
+impl TransactionKind {
+    fn is_transfer(&self) -> bool {
+        *self == Self::Transfer
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
 pub struct Transaction {
     pub amount: Amount,
+    /// The rollup account receiving a transfer, or the L1 address receiving a withdrawal.
     pub destination: Address,
+    /// May be omitted from a transaction which is yet to be signed, such as one sent to
+    /// `rollup/sign-and-submit`, which fills it in.
+    #[serde(default)]
     pub nonce: Nonce,
+    /// Free-form note attached by the sender. It has no effect on execution.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// Fee paid by the sender to the rollup operator, on top of `amount`.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub fee: Amount,
+    /// Block height from which the transaction is no longer valid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "TransactionKind::is_transfer")]
+    pub kind: TransactionKind,
+}
+
+fn is_zero(amount: &Amount) -> bool {
+    *amount == 0
 }
 
 impl Transaction {
-    fn encode(&self) -> Vec<u8> {
+    /// The canonical encoding of the transaction, which is signed and hashed.
+    ///
+    /// Optional fields are omitted when unset, so transactions which do not use them encode, and
+    /// hash, exactly as they did before those fields existed.
+    pub fn encode(&self) -> Vec<u8> {
         serde_json::to_string(&self)
             .expect("Serialization should not fail")
             .as_bytes()
@@ -25,10 +86,213 @@ impl Transaction {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Snafu)]
+pub enum BuildError {
+    #[snafu(display("Transaction is missing required field {field}"))]
+    MissingField { field: &'static str },
+    #[snafu(display("Transaction amount must be nonzero"))]
+    ZeroAmount,
+    #[snafu(display("Transaction destination must not be the zero address"))]
+    ZeroDestination,
+    #[snafu(display("Memo is {len} bytes, exceeding the maximum of {max}"))]
+    MemoTooLong { len: usize, max: usize },
+    #[snafu(display("Transaction amount plus fee overflows"))]
+    Overflow,
+    #[snafu(display("Transaction cannot expire at block 0"))]
+    InvalidExpiry,
+}
+
+/// Builds and validates a [`Transaction`], and optionally signs it.
+///
+/// The amount and destination are required. The nonce is also required to build the transaction,
+/// but may be left unset for a [`RollupClient`](crate::client::RollupClient) to fill in with the
+/// sender's next nonce.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionBuilder {
+    amount: Option<Amount>,
+    destination: Option<Address>,
+    nonce: Option<Nonce>,
+    memo: Option<String>,
+    fee: Amount,
+    expires_at: Option<u64>,
+    kind: TransactionKind,
+    chain_id: Option<ChainId>,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn amount(mut self, amount: Amount) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    pub fn destination(mut self, destination: Address) -> Self {
+        self.destination = Some(destination);
+        self
+    }
+
+    pub fn nonce(mut self, nonce: Nonce) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    pub fn memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    pub fn fee(mut self, fee: Amount) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    /// Make the transaction invalid in blocks at or above `height`.
+    pub fn expires_at(mut self, height: u64) -> Self {
+        self.expires_at = Some(height);
+        self
+    }
+
+    /// Withdraw `amount` to the L1 address `destination`, rather than transferring it.
+    pub fn withdraw(mut self) -> Self {
+        self.kind = TransactionKind::Withdraw;
+        self
+    }
+
+    /// Sign the transaction for the rollup chain `chain_id`, rather than as a plain message.
+    pub fn chain_id(mut self, chain_id: ChainId) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    /// The nonce set so far, if any.
+    pub fn get_nonce(&self) -> Option<Nonce> {
+        self.nonce
+    }
+
+    /// The chain ID set so far, if any.
+    pub fn get_chain_id(&self) -> Option<ChainId> {
+        self.chain_id
+    }
+
+    /// The expiry height set so far, if any.
+    pub fn get_expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    /// Validate the fields and build the transaction.
+    pub fn build(&self) -> Result<Transaction, BuildError> {
+        let amount = self
+            .amount
+            .ok_or(BuildError::MissingField { field: "amount" })?;
+        let destination = self.destination.ok_or(BuildError::MissingField {
+            field: "destination",
+        })?;
+        let nonce = self
+            .nonce
+            .ok_or(BuildError::MissingField { field: "nonce" })?;
+        if amount == 0 {
+            return Err(BuildError::ZeroAmount);
+        }
+        if destination.is_zero() {
+            return Err(BuildError::ZeroDestination);
+        }
+        if let Some(memo) = &self.memo {
+            if memo.len() > MAX_MEMO_LENGTH {
+                return Err(BuildError::MemoTooLong {
+                    len: memo.len(),
+                    max: MAX_MEMO_LENGTH,
+                });
+            }
+        }
+        amount.checked_add(self.fee).ok_or(BuildError::Overflow)?;
+        if self.expires_at == Some(0) {
+            return Err(BuildError::InvalidExpiry);
+        }
+        Ok(Transaction {
+            amount,
+            destination,
+            nonce,
+            memo: self.memo.clone(),
+            fee: self.fee,
+            expires_at: self.expires_at,
+            kind: self.kind,
+        })
+    }
+
+    /// Build the transaction and produce its canonical encoding.
+    pub fn encode(&self) -> Result<Vec<u8>, BuildError> {
+        Ok(self.build()?.encode())
+    }
+
+    /// Build the transaction and sign it with `wallet`.
+    pub async fn sign(&self, wallet: &impl Signer) -> Result<SignedTransaction, BuildError> {
+        let transaction = self.build()?;
+        Ok(match self.chain_id {
+            Some(chain_id) => SignedTransaction::new_for_chain(transaction, chain_id, wallet).await,
+            None => SignedTransaction::new(transaction, wallet).await,
+        })
+    }
+}
+
+/// A transaction bound to a rollup chain, in the form signed as EIP-712 typed data.
+struct ChainTransaction<'a> {
+    transaction: &'a Transaction,
+    chain_id: ChainId,
+}
+
+impl Eip712 for ChainTransaction<'_> {
+    type Error = Infallible;
+
+    fn domain(&self) -> Result<EIP712Domain, Self::Error> {
+        Ok(self.chain_id.eip712_domain())
+    }
+
+    fn type_hash() -> Result<[u8; 32], Self::Error> {
+        Ok(keccak256(
+            "Transaction(uint64 amount,address destination,uint64 nonce,string memo,uint64 fee,uint64 expiresAt)",
+        ))
+    }
+
+    fn struct_hash(&self) -> Result<[u8; 32], Self::Error> {
+        // Withdrawals are a distinct type, so that a signed withdrawal can never be presented as a
+        // transfer, or vice versa.
+        let type_hash = match self.transaction.kind {
+            TransactionKind::Transfer => Self::type_hash()?,
+            TransactionKind::Withdraw => keccak256(
+                "Withdrawal(uint64 amount,address destination,uint64 nonce,string memo,uint64 fee,uint64 expiresAt)",
+            ),
+        };
+        Ok(keccak256(abi::encode(&[
+            Token::FixedBytes(type_hash.to_vec()),
+            Token::Uint(self.transaction.amount.into()),
+            Token::Address(self.transaction.destination),
+            Token::Uint(self.transaction.nonce.into()),
+            Token::FixedBytes(
+                keccak256(self.transaction.memo.as_deref().unwrap_or_default()).to_vec(),
+            ),
+            Token::Uint(self.transaction.fee.into()),
+            Token::Uint(self.transaction.expires_at.unwrap_or_default().into()),
+        ])))
+    }
+}
+
+impl ChainTransaction<'_> {
+    fn digest(&self) -> H256 {
+        H256(self.encode_eip712().unwrap_or_else(|never| match never {}))
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SignedTransaction {
     pub transaction: Transaction,
     signature: Signature,
+    /// The rollup chain for which the transaction was signed. Transactions without a chain ID are
+    /// signed as plain messages, and are valid on any chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chain_id: Option<ChainId>,
 }
 
 impl SignedTransaction {
@@ -39,23 +303,151 @@ impl SignedTransaction {
             .to_vec()
     }
 
-    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
-        serde_json::from_slice(bytes).ok()
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, RollupError> {
+        if bytes.len() > MAX_TRANSACTION_SIZE {
+            return Err(RollupError::TransactionTooLarge {
+                size: bytes.len(),
+                max: MAX_TRANSACTION_SIZE,
+            });
+        }
+        serde_json::from_slice(bytes).map_err(|err| RollupError::MalformedTransaction {
+            reason: err.to_string(),
+        })
+    }
+
+    /// The hash identifying the transaction encoded in `payload`.
+    ///
+    /// This is the [`hash`](Self::hash) of the decoded transaction, whether or not it is wrapped
+    /// in an [`OperatorEnvelope`], or, if the payload cannot be decoded, the hash of the raw
+    /// payload, so that a receipt can be recorded for any payload.
+    pub fn payload_hash(payload: &[u8]) -> H256 {
+        match Submission::decode(payload) {
+            Ok(submission) => submission.transaction().hash(),
+            Err(_) => H256(keccak256(payload)),
+        }
+    }
+
+    /// Hash of the signed transaction payload.
+    ///
+    /// The signature is deliberately excluded, so that re-signing the same transaction does not
+    /// change its hash.
+    pub fn hash(&self) -> H256 {
+        H256(keccak256(self.transaction.encode()))
     }
 
     pub fn recover(&self) -> Result<Address, RollupError> {
-        let bytes = self.transaction.encode();
-        self.signature
-            .recover(bytes)
-            .map_err(|_| RollupError::SignatureError)
+        let recovered = match self.chain_id {
+            Some(chain_id) => self.signature.recover(
+                ChainTransaction {
+                    transaction: &self.transaction,
+                    chain_id,
+                }
+                .digest(),
+            ),
+            None => self.signature.recover(self.transaction.encode()),
+        };
+        recovered.map_err(|_| RollupError::SignatureError)
     }
 
+    /// The rollup chain for which the transaction was signed, if any.
+    pub fn chain_id(&self) -> Option<ChainId> {
+        self.chain_id
+    }
+
+    /// Sign `transaction` as a plain message, valid on any rollup chain.
     pub async fn new(transaction: Transaction, wallet: &impl Signer) -> Self {
         let bytes = transaction.encode();
         let signature = wallet.sign_message(&bytes).await.unwrap();
         Self {
             signature,
             transaction,
+            chain_id: None,
+        }
+    }
+
+    /// Sign `transaction` as EIP-712 typed data, valid only on the rollup chain `chain_id`.
+    pub async fn new_for_chain(
+        transaction: Transaction,
+        chain_id: ChainId,
+        wallet: &impl Signer,
+    ) -> Self {
+        let signature = wallet
+            .sign_typed_data(&ChainTransaction {
+                transaction: &transaction,
+                chain_id,
+            })
+            .await
+            .unwrap();
+        Self {
+            signature,
+            transaction,
+            chain_id: Some(chain_id),
+        }
+    }
+}
+
+/// A transaction countersigned by the rollup operator when it was submitted through the rollup API.
+///
+/// The countersignature lets the VM distinguish transactions submitted through the operator's API,
+/// which has already checked them, from data posted directly to the rollup namespace by third
+/// parties.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OperatorEnvelope {
+    pub wrapped: SignedTransaction,
+    operator_signature: Signature,
+}
+
+impl OperatorEnvelope {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        serde_json::to_string(&self)
+            .expect("Serialization should not fail")
+            .as_bytes()
+            .to_vec()
+    }
+
+    /// The address of the operator who countersigned the transaction.
+    pub fn recover_operator(&self) -> Result<Address, RollupError> {
+        self.operator_signature
+            .recover(self.wrapped.encode())
+            .map_err(|_| RollupError::SignatureError)
+    }
+
+    pub async fn new(wrapped: SignedTransaction, operator: &impl Signer) -> Self {
+        let operator_signature = operator.sign_message(wrapped.encode()).await.unwrap();
+        Self {
+            wrapped,
+            operator_signature,
+        }
+    }
+}
+
+/// A transaction found in the rollup namespace.
+#[derive(Clone, Debug)]
+pub(crate) enum Submission {
+    /// Countersigned by an operator, though not necessarily the one the VM trusts.
+    Wrapped(OperatorEnvelope),
+    /// Posted without a countersignature.
+    Unwrapped(SignedTransaction),
+}
+
+impl Submission {
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, RollupError> {
+        if bytes.len() > MAX_TRANSACTION_SIZE {
+            return Err(RollupError::TransactionTooLarge {
+                size: bytes.len(),
+                max: MAX_TRANSACTION_SIZE,
+            });
+        }
+        match serde_json::from_slice(bytes) {
+            Ok(envelope) => Ok(Self::Wrapped(envelope)),
+            Err(_) => SignedTransaction::decode(bytes).map(Self::Unwrapped),
+        }
+    }
+
+    pub(crate) fn transaction(&self) -> &SignedTransaction {
+        match self {
+            Self::Wrapped(envelope) => &envelope.wrapped,
+            Self::Unwrapped(transaction) => transaction,
         }
     }
 }
@@ -74,6 +466,7 @@ mod tests {
             amount: 100,
             destination: alice.address(),
             nonce: 1,
+            ..Default::default()
         };
         let signed_transaction = SignedTransaction::new(transaction, &alice).await;
         let recovered_address = signed_transaction
@@ -81,4 +474,188 @@ mod tests {
             .expect("Should recover address");
         assert_eq!(recovered_address, alice.address());
     }
+
+    #[async_std::test]
+    async fn test_operator_envelope() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let operator = LocalWallet::new(&mut rng);
+        let transaction = Transaction {
+            amount: 100,
+            destination: alice.address(),
+            nonce: 1,
+            ..Default::default()
+        };
+        let signed_transaction = SignedTransaction::new(transaction, &alice).await;
+        let envelope = OperatorEnvelope::new(signed_transaction.clone(), &operator).await;
+        assert_eq!(envelope.recover_operator().unwrap(), operator.address());
+
+        // Wrapped and unwrapped transactions decode to the same transaction, with the same hash.
+        let wrapped = envelope.encode();
+        let unwrapped = signed_transaction.encode();
+        assert!(matches!(
+            Submission::decode(&wrapped).unwrap(),
+            Submission::Wrapped(_)
+        ));
+        assert!(matches!(
+            Submission::decode(&unwrapped).unwrap(),
+            Submission::Unwrapped(_)
+        ));
+        assert_eq!(
+            SignedTransaction::payload_hash(&wrapped),
+            signed_transaction.hash()
+        );
+        assert_eq!(
+            SignedTransaction::payload_hash(&unwrapped),
+            signed_transaction.hash()
+        );
+    }
+
+    #[async_std::test]
+    async fn test_chain_signature() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let transaction = Transaction {
+            amount: 100,
+            destination: alice.address(),
+            nonce: 1,
+            ..Default::default()
+        };
+        let chain_id = ChainId(H256::random());
+        let signed = SignedTransaction::new_for_chain(transaction.clone(), chain_id, &alice).await;
+        assert_eq!(signed.chain_id(), Some(chain_id));
+        assert_eq!(signed.recover().unwrap(), alice.address());
+
+        // The chain ID survives encoding, and is omitted for transactions without one.
+        let decoded = SignedTransaction::decode(&signed.encode()).unwrap();
+        assert_eq!(decoded.chain_id(), Some(chain_id));
+        let legacy = SignedTransaction::new(transaction, &alice).await;
+        assert!(!String::from_utf8(legacy.encode())
+            .unwrap()
+            .contains("chain_id"));
+
+        // The signature is bound to the chain, so it does not verify for any other chain.
+        let moved = SignedTransaction {
+            chain_id: Some(ChainId(H256::random())),
+            ..signed.clone()
+        };
+        assert_ne!(moved.recover().ok(), Some(alice.address()));
+        let stripped = SignedTransaction {
+            chain_id: None,
+            ..signed.clone()
+        };
+        assert_ne!(stripped.recover().ok(), Some(alice.address()));
+
+        // Re-signing for another chain does not change the transaction hash.
+        assert_eq!(signed.hash(), legacy.hash());
+    }
+
+    #[async_std::test]
+    async fn test_transaction_builder() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let bob = Address::random();
+        let base = TransactionBuilder::new().amount(100).destination(bob);
+
+        // Required fields must be set, and every field is validated.
+        assert_eq!(
+            base.build(),
+            Err(BuildError::MissingField { field: "nonce" })
+        );
+        let base = base.nonce(1);
+        assert_eq!(base.clone().amount(0).build(), Err(BuildError::ZeroAmount));
+        assert_eq!(
+            base.clone().destination(Address::zero()).build(),
+            Err(BuildError::ZeroDestination)
+        );
+        assert_eq!(
+            base.clone().memo("x".repeat(MAX_MEMO_LENGTH + 1)).build(),
+            Err(BuildError::MemoTooLong {
+                len: MAX_MEMO_LENGTH + 1,
+                max: MAX_MEMO_LENGTH
+            })
+        );
+        assert_eq!(
+            base.clone().fee(Amount::MAX).build(),
+            Err(BuildError::Overflow)
+        );
+        assert_eq!(
+            base.clone().expires_at(0).build(),
+            Err(BuildError::InvalidExpiry)
+        );
+
+        // Without the optional fields, the encoding and hash are those of a legacy transaction.
+        let legacy = Transaction {
+            amount: 100,
+            destination: bob,
+            nonce: 1,
+            ..Default::default()
+        };
+        assert_eq!(base.build().unwrap(), legacy);
+        assert_eq!(base.encode().unwrap(), legacy.encode());
+        assert_eq!(
+            String::from_utf8(legacy.encode()).unwrap(),
+            format!(r#"{{"amount":100,"destination":"{bob:?}","nonce":1}}"#)
+        );
+        let signed = base.sign(&alice).await.unwrap();
+        assert_eq!(
+            signed.hash(),
+            SignedTransaction::new(legacy, &alice).await.hash()
+        );
+
+        // The optional fields are covered by the signature and the hash.
+        let full = base.clone().memo("rent").fee(5).expires_at(10);
+        let full_signed = full.sign(&alice).await.unwrap();
+        assert_eq!(full_signed.recover().unwrap(), alice.address());
+        assert_ne!(full_signed.hash(), signed.hash());
+        let decoded = SignedTransaction::decode(&full_signed.encode()).unwrap();
+        assert_eq!(decoded.transaction, full.build().unwrap());
+        let tampered = SignedTransaction {
+            transaction: Transaction {
+                fee: 0,
+                ..full_signed.transaction.clone()
+            },
+            ..full_signed.clone()
+        };
+        assert_ne!(tampered.recover().ok(), Some(alice.address()));
+
+        // Setting a chain ID signs for that chain.
+        let chain_id = ChainId(H256::random());
+        let bound = full.chain_id(chain_id).sign(&alice).await.unwrap();
+        assert_eq!(bound.chain_id(), Some(chain_id));
+        assert_eq!(bound.recover().unwrap(), alice.address());
+        assert_eq!(bound.hash(), full_signed.hash());
+    }
+
+    #[async_std::test]
+    async fn test_withdrawal_signature() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let recipient = Address::random();
+        let chain_id = ChainId(H256::random());
+        let transfer = TransactionBuilder::new()
+            .amount(100)
+            .destination(recipient)
+            .nonce(1)
+            .chain_id(chain_id);
+        let withdrawal = transfer.clone().withdraw();
+        assert_eq!(withdrawal.build().unwrap().kind, TransactionKind::Withdraw);
+        assert!(String::from_utf8(withdrawal.encode().unwrap())
+            .unwrap()
+            .contains(r#""kind":"withdraw""#));
+
+        // A withdrawal is signed as its own type, so its signature does not carry over to the
+        // transfer with the same fields.
+        let signed = withdrawal.sign(&alice).await.unwrap();
+        assert_eq!(signed.recover().unwrap(), alice.address());
+        assert_ne!(signed.hash(), transfer.sign(&alice).await.unwrap().hash());
+        let as_transfer = SignedTransaction {
+            transaction: Transaction {
+                kind: TransactionKind::Transfer,
+                ..signed.transaction.clone()
+            },
+            ..signed.clone()
+        };
+        assert_ne!(as_transfer.recover().ok(), Some(alice.address()));
+    }
 }
@@ -4,34 +4,58 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
-use std::time::Duration;
-
+use crate::l1::{EthersL1Deployer, L1Deployer, RollupDeployment};
 use crate::state::State;
 use committable::Commitment;
 use contract_bindings::example_rollup::ExampleRollup;
-use ethers::{prelude::*, providers::Provider};
+use ethers::prelude::*;
 use sequencer_utils::{commitment_to_u256, test_utils::TestL1System, Signer};
-use surf_disco::Url;
 
 pub type ExampleRollupContract = ExampleRollup<Signer>;
 
 pub async fn deploy_example_contract(
     test_l1: &TestL1System,
     initial_state: Commitment<State>,
+    genesis_supply: u128,
     light_client_address: Address,
+    prover: Address,
 ) -> ExampleRollupContract {
-    ExampleRollup::deploy(
-        test_l1.clients.deployer.provider.clone(),
-        (light_client_address, commitment_to_u256(initial_state)),
+    deploy_example_contract_with_deployment(
+        test_l1,
+        initial_state,
+        genesis_supply,
+        light_client_address,
+        prover,
     )
-    .unwrap()
-    .send()
     .await
-    .unwrap()
+    .0
 }
 
-pub fn create_provider(l1_url: &Url) -> Provider<Http> {
-    let mut provider = Provider::try_from(l1_url.to_string()).unwrap();
-    provider.set_interval(Duration::from_millis(10));
-    provider
+/// Deploy the rollup contract through an [`L1Deployer`], also returning the deployment.
+///
+/// Only `prover` can submit batch proofs to the contract. The deployer funds the deposit escrow
+/// with one wei per token of the `genesis_supply`, so that the genesis balances can be withdrawn.
+pub async fn deploy_example_contract_with_deployment(
+    test_l1: &TestL1System,
+    initial_state: Commitment<State>,
+    genesis_supply: u128,
+    light_client_address: Address,
+    prover: Address,
+) -> (ExampleRollupContract, RollupDeployment) {
+    let client = test_l1.clients.deployer.provider.clone();
+    let mut state_commitment = [0; 32];
+    commitment_to_u256(initial_state).to_big_endian(&mut state_commitment);
+    let deployment = EthersL1Deployer::new(client.clone())
+        .deploy_rollup(
+            state_commitment,
+            genesis_supply,
+            light_client_address,
+            prover,
+        )
+        .await
+        .unwrap();
+    (
+        ExampleRollup::new(deployment.rollup_address, client),
+        deployment,
+    )
 }
@@ -0,0 +1,398 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! A UTXO variant of the rollup state.
+//!
+//! Instead of account balances, tokens are held in [`Note`]s, each owned by an address. A
+//! [`SignedSpend`] consumes notes owned by its signer, referenced by their [`NoteId`]s, and creates
+//! new notes of the same total value. Each note can be spent only once, which also rules out
+//! replays, so spends carry no nonce.
+//!
+//! This demonstrates that the execution scaffolding is not tied to the account model: a
+//! [`UtxoState`] is a [`RollupStateMachine`] like [`State`](crate::state::State), and can be
+//! executed by anything generic over that trait, such as the [`Auditor`](crate::audit::Auditor).
+
+use crate::error::RollupError;
+use crate::machine::RollupStateMachine;
+use crate::state::Amount;
+use crate::transaction::MAX_TRANSACTION_SIZE;
+use crate::RollupVM;
+use committable::{Commitment, Committable};
+use espresso_types::{Header, NamespaceId, NsProof, SeqTypes};
+use ethers::{
+    abi::Address,
+    signers::Signer,
+    types::{Signature, H256},
+    utils::keccak256,
+};
+use hotshot_query_service::availability::BlockHash;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Identifier of a note, derived from the spend which created it.
+pub type NoteId = H256;
+
+/// Tokens owned by an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    pub owner: Address,
+    pub amount: Amount,
+}
+
+/// A transfer consuming some notes and creating others.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Spend {
+    pub inputs: Vec<NoteId>,
+    pub outputs: Vec<Note>,
+}
+
+impl Spend {
+    fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Serialization should not fail")
+    }
+
+    pub fn hash(&self) -> H256 {
+        H256(keccak256(self.encode()))
+    }
+
+    /// The identifier of the note created by the output at `index`.
+    ///
+    /// A valid spend consumes at least one note, and a note can be consumed only once, so no two
+    /// valid spends have the same hash and output identifiers never collide.
+    pub fn output_id(&self, index: usize) -> NoteId {
+        let mut bytes = self.hash().as_bytes().to_vec();
+        bytes.extend_from_slice(&(index as u64).to_le_bytes());
+        H256(keccak256(bytes))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignedSpend {
+    pub spend: Spend,
+    signature: Signature,
+}
+
+impl SignedSpend {
+    pub async fn new(spend: Spend, wallet: &impl Signer) -> Self {
+        let signature = wallet.sign_message(spend.encode()).await.unwrap();
+        Self { spend, signature }
+    }
+
+    /// Encode the spend as a payload for the rollup namespace.
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Serialization should not fail")
+    }
+
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, RollupError> {
+        if bytes.len() > MAX_TRANSACTION_SIZE {
+            return Err(RollupError::TransactionTooLarge {
+                size: bytes.len(),
+                max: MAX_TRANSACTION_SIZE,
+            });
+        }
+        serde_json::from_slice(bytes).map_err(|err| RollupError::MalformedTransaction {
+            reason: err.to_string(),
+        })
+    }
+
+    /// Hash of the spend. As with transactions, the signature is excluded.
+    pub fn hash(&self) -> H256 {
+        self.spend.hash()
+    }
+
+    /// The address which signed the spend, and must own every input.
+    pub fn recover(&self) -> Result<Address, RollupError> {
+        self.signature
+            .recover(self.spend.encode())
+            .map_err(|_| RollupError::SignatureError)
+    }
+}
+
+/// The identifier of the note funding the account at `index` in the genesis balances.
+pub fn genesis_note_id(index: u64) -> NoteId {
+    let mut bytes = b"genesis".to_vec();
+    bytes.extend_from_slice(&index.to_le_bytes());
+    H256(keccak256(bytes))
+}
+
+#[derive(Clone, Debug)]
+pub struct UtxoState {
+    notes: BTreeMap<NoteId, Note>,
+    namespace: NamespaceId,
+    block_hash: Option<BlockHash<SeqTypes>>,
+    prev_state_commitment: Option<Commitment<UtxoState>>,
+    block_height: u64,
+    block_results: Vec<(H256, Result<(), RollupError>)>,
+}
+
+impl Committable for UtxoState {
+    fn commit(&self) -> Commitment<UtxoState> {
+        let serialized_notes =
+            serde_json::to_string(&self.notes).expect("Serialization should not fail");
+
+        committable::RawCommitmentBuilder::new("UTXO State Commitment")
+            .array_field(
+                "block_hash",
+                &self
+                    .block_hash
+                    .iter()
+                    .cloned()
+                    .map(BlockHash::<SeqTypes>::from)
+                    .collect::<Vec<_>>(),
+            )
+            .array_field(
+                "prev_state_commitment",
+                &self
+                    .prev_state_commitment
+                    .iter()
+                    .cloned()
+                    .map(Commitment::<UtxoState>::from)
+                    .collect::<Vec<_>>(),
+            )
+            .var_size_field("notes", serialized_notes.as_bytes())
+            .u64_field("Namespace", u64::from(self.namespace))
+            .finalize()
+    }
+}
+
+impl UtxoState {
+    /// Create a genesis state with one note for each of the initial balances.
+    pub fn from_initial_balances(
+        initial_balances: impl IntoIterator<Item = (Address, Amount)>,
+        vm: RollupVM,
+    ) -> Self {
+        let notes = initial_balances
+            .into_iter()
+            .enumerate()
+            .map(|(index, (owner, amount))| (genesis_note_id(index as u64), Note { owner, amount }))
+            .collect();
+        Self {
+            notes,
+            namespace: vm.into(),
+            block_hash: None,
+            prev_state_commitment: None,
+            block_height: 0,
+            block_results: vec![],
+        }
+    }
+
+    /// The unspent note `id`, if there is one.
+    pub fn note(&self, id: &NoteId) -> Option<Note> {
+        self.notes.get(id).copied()
+    }
+
+    /// The unspent notes owned by `owner`.
+    pub fn notes_owned_by(&self, owner: Address) -> impl Iterator<Item = (NoteId, Note)> + '_ {
+        self.notes
+            .iter()
+            .filter(move |(_, note)| note.owner == owner)
+            .map(|(id, note)| (*id, *note))
+    }
+
+    /// Total value of the unspent notes owned by `owner`.
+    pub fn balance(&self, owner: Address) -> u128 {
+        self.notes_owned_by(owner)
+            .map(|(_, note)| note.amount as u128)
+            .sum()
+    }
+
+    /// If the spend is valid, consume its inputs and create its outputs.
+    ///
+    /// A spend is valid iff
+    /// 1) The signature on the spend is valid
+    /// 2) It consumes at least one note, and no note more than once
+    /// 3) Every input is an unspent note owned by the signer
+    /// 4) The outputs are worth exactly as much as the inputs
+    pub fn apply_spend(&mut self, spend: &SignedSpend) -> Result<(), RollupError> {
+        let signer = spend.recover()?;
+        let Spend { inputs, outputs } = &spend.spend;
+        if inputs.is_empty() {
+            return Err(RollupError::MalformedTransaction {
+                reason: "a spend must consume at least one note".into(),
+            });
+        }
+
+        let mut seen = BTreeSet::new();
+        let mut input_total = 0u128;
+        for id in inputs {
+            if !seen.insert(id) {
+                return Err(RollupError::MalformedTransaction {
+                    reason: format!("note {id:?} is spent more than once"),
+                });
+            }
+            let note = self
+                .notes
+                .get(id)
+                .ok_or(RollupError::UnknownNote { note: *id })?;
+            if note.owner != signer {
+                return Err(RollupError::NoteNotOwned {
+                    note: *id,
+                    address: signer,
+                });
+            }
+            input_total += note.amount as u128;
+        }
+        let output_total = outputs.iter().map(|note| note.amount as u128).sum();
+        if input_total != output_total {
+            return Err(RollupError::UnbalancedSpend {
+                inputs: input_total,
+                outputs: output_total,
+            });
+        }
+
+        for id in inputs {
+            self.notes.remove(id);
+        }
+        for (index, note) in outputs.iter().enumerate() {
+            self.notes.insert(spend.spend.output_id(index), *note);
+        }
+        Ok(())
+    }
+}
+
+impl RollupStateMachine for UtxoState {
+    fn namespace(&self) -> NamespaceId {
+        self.namespace
+    }
+
+    fn block_height(&self) -> u64 {
+        self.block_height
+    }
+
+    fn execute_transactions(
+        &mut self,
+        header: &Header,
+        namespace_proof: &NsProof,
+        block_hash: BlockHash<SeqTypes>,
+    ) {
+        let state_commitment = self.commit();
+        self.block_results.clear();
+        self.block_height = header.height();
+        for txn in namespace_proof.export_all_txs(&self.namespace) {
+            let (hash, res) = match SignedSpend::decode(txn.payload()) {
+                Ok(spend) => (spend.hash(), self.apply_spend(&spend)),
+                Err(err) => (H256(keccak256(txn.payload())), Err(err)),
+            };
+            if let Err(err) = &res {
+                tracing::error!("Spend invalid: {}", err)
+            }
+            self.block_results.push((hash, res));
+        }
+        self.block_hash = Some(block_hash);
+        self.prev_state_commitment = Some(state_commitment);
+    }
+
+    fn block_results(&self) -> &[(H256, Result<(), RollupError>)] {
+        &self.block_results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mock_block;
+    use ethers::signers::LocalWallet;
+
+    #[async_std::test]
+    async fn test_utxo_execution() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let mut state = UtxoState::from_initial_balances([(alice.address(), 100)], vm);
+        let genesis = state.commit();
+        let alice_note = genesis_note_id(0);
+
+        // Alice sends 30 to Bob and keeps the change.
+        let split = SignedSpend::new(
+            Spend {
+                inputs: vec![alice_note],
+                outputs: vec![
+                    Note {
+                        owner: bob.address(),
+                        amount: 30,
+                    },
+                    Note {
+                        owner: alice.address(),
+                        amount: 70,
+                    },
+                ],
+            },
+            &alice,
+        )
+        .await;
+        let bob_note = split.spend.output_id(0);
+
+        // Spends which are invalid, given that the split executes first.
+        let double_spend = split.clone();
+        let stolen = SignedSpend::new(
+            Spend {
+                inputs: vec![bob_note],
+                outputs: vec![Note {
+                    owner: alice.address(),
+                    amount: 30,
+                }],
+            },
+            &alice,
+        )
+        .await;
+        let inflated = SignedSpend::new(
+            Spend {
+                inputs: vec![bob_note],
+                outputs: vec![Note {
+                    owner: bob.address(),
+                    amount: 31,
+                }],
+            },
+            &bob,
+        )
+        .await;
+
+        let payloads = [&split, &double_spend, &stolen, &inflated]
+            .iter()
+            .map(|spend| spend.encode())
+            .collect::<Vec<_>>();
+        let block = mock_block(vm.into(), &[(vm.into(), payloads)]).await;
+        state.execute_transactions(
+            &block.header,
+            block.namespace_proof.as_ref().unwrap(),
+            block.header.commit(),
+        );
+
+        let results = state
+            .block_results()
+            .iter()
+            .map(|(_, res)| res.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            [
+                Ok(()),
+                Err(RollupError::UnknownNote { note: alice_note }),
+                Err(RollupError::NoteNotOwned {
+                    note: bob_note,
+                    address: alice.address(),
+                }),
+                Err(RollupError::UnbalancedSpend {
+                    inputs: 30,
+                    outputs: 31,
+                }),
+            ]
+        );
+        assert_eq!(state.balance(alice.address()), 70);
+        assert_eq!(state.balance(bob.address()), 30);
+        assert_eq!(state.note(&alice_note), None);
+        assert_eq!(
+            state.note(&bob_note),
+            Some(Note {
+                owner: bob.address(),
+                amount: 30,
+            })
+        );
+        assert_ne!(state.commit(), genesis);
+        assert_eq!(state.prev_state_commitment, Some(genesis));
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Offline verification of receipt bundles.
+//!
+//! A [`ReceiptBundle`], served by `rollup/tx/:hash/receipt-bundle`, is a self-contained proof that
+//! a transfer was made. It holds the transfer as signed by the sender, its receipt, an inclusion
+//! proof showing that the transfer was sequenced in the rollup namespace of the block the receipt
+//! names, and, once the block has been proven, the batch proof submitted to the rollup contract. A
+//! recipient can keep the bundle and check it with [`ReceiptBundle::verify`] without access to the
+//! rollup API or the sequencer.
+//!
+//! The bundle shows that the transfer was sequenced and that the batch proof commits to the state
+//! after its block. Whether the batch was in fact verified by the rollup contract is a fact about
+//! the L1, so a verifier who does not trust the bundle's author should look up the reported L1
+//! transaction.
+
+use crate::error::RollupError;
+use crate::http::HttpClientPool;
+use crate::inclusion::{fetch_inclusion_proof_at, InclusionError, InclusionProof};
+use crate::outbox::{Outbox, OutboxEntry, SubmissionStatus};
+use crate::receipt::Receipt;
+use crate::state::{Amount, State};
+use crate::transaction::{SignedTransaction, Submission};
+use committable::Commitment;
+use espresso_types::NamespaceId;
+use ethers::types::{Address, H256};
+use sequencer_utils::commitment_to_u256;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use surf_disco::{error::ClientError, Url};
+
+/// Version of the bundle format.
+pub const RECEIPT_BUNDLE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Snafu, PartialEq, Eq)]
+pub enum BundleError {
+    #[snafu(display(
+        "Bundle has format version {version}, but this verifier reads version \
+         {RECEIPT_BUNDLE_VERSION}."
+    ))]
+    Version { version: u32 },
+    #[snafu(display("Inclusion proof is invalid: {source}"))]
+    Inclusion { source: InclusionError },
+    #[snafu(display(
+        "Inclusion proof is for block {proven}, but the receipt is for block {receipt}."
+    ))]
+    WrongBlock { proven: u64, receipt: u64 },
+    #[snafu(display("The sequenced transaction is not the transfer in the bundle."))]
+    TransactionMismatch,
+    #[snafu(display("The transfer is not validly signed."))]
+    InvalidSignature,
+    #[snafu(display("The transfer was sequenced but failed: {error}"))]
+    Failed { error: RollupError },
+    #[snafu(display(
+        "The batch proof does not commit to the state after block {block_height}, {commitment}."
+    ))]
+    L1UpdateMismatch {
+        block_height: u64,
+        commitment: Commitment<State>,
+    },
+}
+
+/// A portable proof that a transfer was executed, verifiable offline.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReceiptBundle {
+    pub version: u32,
+    /// The transfer, as signed by the sender.
+    pub transaction: SignedTransaction,
+    /// The receipt of the transfer, with the state commitments before and after its block.
+    pub receipt: Receipt,
+    /// Proof that the transfer was sequenced, including the header of its block.
+    pub inclusion: InclusionProof,
+    /// The batch proof which covers the block, or `None` if it has not yet been proven.
+    pub l1_update: Option<OutboxEntry>,
+}
+
+/// The facts established by a valid [`ReceiptBundle`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiedPayment {
+    pub hash: H256,
+    pub from: Address,
+    pub to: Address,
+    pub amount: Amount,
+    pub fee: Amount,
+    pub block_height: u64,
+    /// Commitment to the rollup state after the block.
+    pub state_commitment: Commitment<State>,
+    /// Whether the batch proof covering the block has been verified by the rollup contract,
+    /// according to the node which produced the bundle.
+    pub verified_on_l1: bool,
+    /// The L1 transaction in which the batch proof was verified, if known.
+    pub l1_tx_hash: Option<H256>,
+}
+
+impl ReceiptBundle {
+    /// Check that the bundle proves the execution of a transfer in `namespace`.
+    pub fn verify(&self, namespace: NamespaceId) -> Result<VerifiedPayment, BundleError> {
+        if self.version != RECEIPT_BUNDLE_VERSION {
+            return Err(BundleError::Version {
+                version: self.version,
+            });
+        }
+        let sequenced = self.inclusion.verify(namespace).context(InclusionSnafu)?;
+        let block_height = self.inclusion.header.height();
+        if block_height != self.receipt.block_height {
+            return Err(BundleError::WrongBlock {
+                proven: block_height,
+                receipt: self.receipt.block_height,
+            });
+        }
+        let submission = Submission::decode(sequenced.payload())
+            .map_err(|_| BundleError::TransactionMismatch)?;
+        if submission.transaction().encode() != self.transaction.encode()
+            || self.transaction.hash() != self.receipt.hash
+        {
+            return Err(BundleError::TransactionMismatch);
+        }
+        if let Err(error) = &self.receipt.result {
+            return Err(BundleError::Failed {
+                error: error.clone(),
+            });
+        }
+        let from = self
+            .transaction
+            .recover()
+            .map_err(|_| BundleError::InvalidSignature)?;
+
+        let (verified_on_l1, l1_tx_hash) = match &self.l1_update {
+            Some(entry) => {
+                if !commits_to(entry, self.receipt.state_commitment) {
+                    return Err(BundleError::L1UpdateMismatch {
+                        block_height,
+                        commitment: self.receipt.state_commitment,
+                    });
+                }
+                match &entry.status {
+                    SubmissionStatus::Confirmed { tx_hash } => (true, Some(*tx_hash)),
+                    status => (status.is_verified(), None),
+                }
+            }
+            None => (false, None),
+        };
+
+        let transfer = &self.transaction.transaction;
+        Ok(VerifiedPayment {
+            hash: self.receipt.hash,
+            from,
+            to: transfer.destination,
+            amount: transfer.amount,
+            fee: transfer.fee,
+            block_height,
+            state_commitment: self.receipt.state_commitment,
+            verified_on_l1,
+            l1_tx_hash,
+        })
+    }
+}
+
+/// Whether the batch proof of `entry` commits to the state `commitment` after one of its blocks.
+fn commits_to(entry: &OutboxEntry, commitment: Commitment<State>) -> bool {
+    let mut bytes = [0; 32];
+    commitment_to_u256(commitment).to_big_endian(&mut bytes);
+    entry.proof.commitments.contains(&bytes)
+}
+
+/// Assemble the bundle for the transfer with `receipt`, fetching its inclusion proof from the
+/// query service at `sequencer_url` and the batch proof covering its block from `outbox`.
+///
+/// Returns `None` if the transaction is not a transfer, or the query service does not have its
+/// block.
+pub async fn fetch_receipt_bundle(
+    http: &HttpClientPool,
+    sequencer_url: &Url,
+    namespace: NamespaceId,
+    receipt: Receipt,
+    outbox: &Outbox,
+) -> Result<Option<ReceiptBundle>, ClientError> {
+    let hash = receipt.hash;
+    let Some((inclusion, sequenced)) = fetch_inclusion_proof_at(
+        http,
+        sequencer_url,
+        receipt.block_height,
+        namespace,
+        |txn| {
+            Submission::decode(txn.payload())
+                .is_ok_and(|submission| submission.transaction().hash() == hash)
+        },
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
+    let Ok(submission) = Submission::decode(sequenced.payload()) else {
+        return Ok(None);
+    };
+    let l1_update = outbox
+        .entries()
+        .await
+        .into_iter()
+        .find(|entry| commits_to(entry, receipt.state_commitment));
+    Ok(Some(ReceiptBundle {
+        version: RECEIPT_BUNDLE_VERSION,
+        transaction: submission.transaction().clone(),
+        receipt,
+        inclusion,
+        l1_update,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mock_block;
+    use crate::l1::BatchProofInput;
+    use crate::machine::RollupStateMachine;
+    use crate::prover::ProofShape;
+    use crate::transaction::Transaction;
+    use crate::RollupVM;
+    use committable::Committable;
+    use espresso_types::Transaction as SeqTransaction;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[async_std::test]
+    async fn test_verify_receipt_bundle() {
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let namespace: NamespaceId = vm.into();
+        let alice = LocalWallet::new(&mut rand::thread_rng());
+        let bob = Address::random();
+        let transfer = SignedTransaction::new(
+            Transaction {
+                amount: 10,
+                destination: bob,
+                nonce: 1,
+                ..Default::default()
+            },
+            &alice,
+        )
+        .await;
+        let block = mock_block(namespace, &[(namespace, vec![transfer.encode()])]).await;
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        state.execute_transactions(
+            &block.header,
+            block.namespace_proof.as_ref().unwrap(),
+            block.header.commit(),
+        );
+        let receipt = Receipt::for_block(&state).pop().unwrap();
+
+        let mut commitment = [0; 32];
+        commitment_to_u256(receipt.state_commitment).to_big_endian(&mut commitment);
+        let tx_hash = H256::random();
+        let bundle = ReceiptBundle {
+            version: RECEIPT_BUNDLE_VERSION,
+            transaction: transfer.clone(),
+            receipt,
+            inclusion: InclusionProof {
+                transaction: SeqTransaction::new(namespace, transfer.encode()).commit(),
+                header: block.header,
+                namespace_proof: block.namespace_proof.unwrap(),
+                vid_common: block.vid_common.unwrap(),
+                index: 0,
+            },
+            l1_update: Some(OutboxEntry {
+                first_block: 0,
+                last_block: 0,
+                count: 1,
+                proof: BatchProofInput {
+                    commitments: vec![commitment],
+                    ..Default::default()
+                },
+                shape: ProofShape::Full,
+                status: SubmissionStatus::Confirmed { tx_hash },
+                enqueued_at: 0,
+                attempts: 0,
+            }),
+        };
+
+        // The bundle survives a round trip through its portable encoding.
+        let bundle: ReceiptBundle =
+            serde_json::from_str(&serde_json::to_string(&bundle).unwrap()).unwrap();
+        let payment = bundle.verify(namespace).unwrap();
+        assert_eq!(payment.hash, transfer.hash());
+        assert_eq!((payment.from, payment.to), (alice.address(), bob));
+        assert_eq!(payment.amount, 10);
+        assert!(payment.verified_on_l1);
+        assert_eq!(payment.l1_tx_hash, Some(tx_hash));
+
+        // A bundle claiming a different transfer does not verify.
+        let forged = SignedTransaction::new(
+            Transaction {
+                amount: 1000,
+                destination: bob,
+                nonce: 1,
+                ..Default::default()
+            },
+            &alice,
+        )
+        .await;
+        let mut tampered = bundle.clone();
+        tampered.transaction = forged;
+        assert_eq!(
+            tampered.verify(namespace),
+            Err(BundleError::TransactionMismatch)
+        );
+
+        // Nor does one whose batch proof is for another state.
+        let mut tampered = bundle.clone();
+        tampered.l1_update.as_mut().unwrap().proof.commitments = vec![[1; 32]];
+        assert!(matches!(
+            tampered.verify(namespace),
+            Err(BundleError::L1UpdateMismatch { .. })
+        ));
+
+        assert!(matches!(
+            bundle.verify(NamespaceId::from(2_u64)),
+            Err(BundleError::Inclusion { .. })
+        ));
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Warm starts from a bundle written at shutdown.
+//!
+//! Without a warm start, a node rebuilds its state by executing every block since genesis. A node
+//! started with `--warm-start <path>` instead writes a [`WarmStart`] bundle to `path` when it is
+//! asked to stop, and restores it from `path` the next time it starts. The bundle holds everything
+//! the node needs to continue where it stopped: a snapshot of the state, the receipts of the most
+//! recently executed blocks, the proofs which had been generated but not yet handed to the outbox,
+//! and the next block to execute.
+//!
+//! The executor writes the bundle between light client events, when the state, the pending proofs
+//! and the next block are consistent with each other. Proofs which have already been handed to
+//! the outbox are not part of the bundle, so the outbox must be persistent for a warm-started node
+//! to submit every batch.
+
+use crate::prover::PendingProofs;
+use crate::receipt::{ReceiptIndex, ReceiptTail};
+use crate::state::{State, StateSnapshot};
+use async_std::channel::Receiver;
+use committable::{Commitment, Committable};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Version of the bundle format, bumped whenever a bundle written by an older node can no longer
+/// be restored.
+pub const WARM_START_VERSION: u32 = 1;
+
+/// Number of recently executed blocks whose receipts are included in a bundle.
+pub const WARM_START_RECEIPT_BLOCKS: usize = 1000;
+
+#[derive(Debug, Snafu)]
+pub enum WarmStartError {
+    #[snafu(display("Error accessing warm-start bundle {}: {source}", path.display()))]
+    Io { path: PathBuf, source: io::Error },
+    #[snafu(display("Malformed warm-start bundle {}: {source}", path.display()))]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[snafu(display(
+        "Warm-start bundle has format version {version}, but this node writes version \
+         {WARM_START_VERSION}. Remove the bundle to start from genesis."
+    ))]
+    Version { version: u32 },
+    #[snafu(display(
+        "Warm-start bundle was written by a node with genesis state {recorded}, but this node has \
+         genesis state {configured}. Remove the bundle to start from genesis."
+    ))]
+    GenesisMismatch {
+        recorded: Commitment<State>,
+        configured: Commitment<State>,
+    },
+}
+
+/// Where the executor resumes after a warm start.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Resume {
+    /// Height of the first block which has not been executed.
+    pub next_block: u64,
+    /// Proofs of executed blocks which have not yet been handed to the outbox.
+    pub(crate) pending_proofs: PendingProofs,
+}
+
+/// Everything a node needs to continue where it stopped.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WarmStart {
+    version: u32,
+    /// Commitment to the genesis state of the node which wrote the bundle.
+    genesis: Commitment<State>,
+    state: StateSnapshot,
+    receipts: ReceiptTail,
+    resume: Resume,
+}
+
+impl WarmStart {
+    /// Load the bundle at `path`, or `None` if there is no file at `path`.
+    pub fn load(path: &Path) -> Result<Option<Self>, WarmStartError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(WarmStartError::Io {
+                    path: path.into(),
+                    source,
+                })
+            }
+        };
+        let bundle: Self = serde_json::from_str(&contents).context(JsonSnafu { path })?;
+        if bundle.version != WARM_START_VERSION {
+            return Err(WarmStartError::Version {
+                version: bundle.version,
+            });
+        }
+        Ok(Some(bundle))
+    }
+
+    /// Write the bundle to `path`.
+    ///
+    /// The bundle is written to a temporary file which is then renamed over `path`, so that a
+    /// crash never leaves a partially written bundle behind.
+    pub fn save(&self, path: &Path) -> Result<(), WarmStartError> {
+        let contents = serde_json::to_string(self).context(JsonSnafu { path })?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, contents).context(IoSnafu { path: &tmp })?;
+        std::fs::rename(&tmp, path).context(IoSnafu { path })
+    }
+
+    /// Height of the first block which the node that wrote the bundle had not executed.
+    pub fn next_block(&self) -> u64 {
+        self.resume.next_block
+    }
+
+    /// Restore the bundle on a node whose genesis state is `genesis`.
+    ///
+    /// The receipts in the bundle are added to `receipts`. Returns the state to execute from and
+    /// where the executor resumes. Fails if the bundle was written by a node with a different
+    /// genesis state, since its state would not extend this node's.
+    ///
+    /// Transaction hooks are not part of the bundle, so the returned state must be given the same
+    /// hooks as `genesis`.
+    pub async fn restore(
+        self,
+        genesis: &State,
+        receipts: &ReceiptIndex,
+    ) -> Result<(State, Resume), WarmStartError> {
+        let configured = genesis.commit();
+        if self.genesis != configured {
+            return Err(WarmStartError::GenesisMismatch {
+                recorded: self.genesis,
+                configured,
+            });
+        }
+        receipts.restore(self.receipts).await;
+        Ok((State::from_snapshot(self.state), self.resume))
+    }
+}
+
+/// Writes a warm-start bundle when the node is asked to stop.
+#[derive(Clone, Debug)]
+pub struct WarmStartWriter {
+    pub path: PathBuf,
+    /// Commitment to this node's genesis state, checked when the bundle is restored.
+    pub genesis: Commitment<State>,
+    /// Receipts of executed transactions, the tail of which is written to the bundle.
+    pub receipts: ReceiptIndex,
+    /// Signalled when the node should stop.
+    pub shutdown: Receiver<()>,
+}
+
+impl WarmStartWriter {
+    /// Write a bundle from which a node can continue with `state`, resuming at `resume`.
+    pub(crate) async fn write(&self, state: &State, resume: &Resume) -> Result<(), WarmStartError> {
+        let bundle = WarmStart {
+            version: WARM_START_VERSION,
+            genesis: self.genesis,
+            state: state.to_snapshot(),
+            receipts: self.receipts.tail(WARM_START_RECEIPT_BLOCKS).await,
+            resume: resume.clone(),
+        };
+        bundle.save(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::receipt::Receipt;
+    use crate::state::ReplayProtection;
+    use crate::transaction::{SignedTransaction, Transaction};
+    use crate::RollupVM;
+    use async_std::channel;
+    use espresso_types::NamespaceId;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[async_std::test]
+    async fn test_warm_start_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("warm-start.json");
+        assert!(WarmStart::load(&path).unwrap().is_none());
+
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let bob = LocalWallet::new(&mut rand::thread_rng()).address();
+        let genesis = State::from_initial_balances(
+            [(wallet.address(), 100)],
+            RollupVM::new(NamespaceId::from(1_u64)),
+        );
+        let transaction = SignedTransaction::new(
+            Transaction {
+                amount: 10,
+                destination: bob,
+                nonce: 1,
+                ..Default::default()
+            },
+            &wallet,
+        )
+        .await;
+        let mut state = genesis.clone();
+        state.apply_transaction(&transaction).unwrap();
+
+        let receipts = ReceiptIndex::default();
+        let receipt = Receipt {
+            hash: transaction.hash(),
+            block_height: 3,
+            view_number: None,
+            block_timestamp: 0,
+            index: 0,
+            result: Ok(()),
+            prev_state_commitment: genesis.commit(),
+            state_commitment: state.commit(),
+            timings: Default::default(),
+            metrics: Default::default(),
+            chain_id: None,
+        };
+        receipts.insert_block(3, vec![receipt.clone()]).await;
+
+        let (_, shutdown) = channel::bounded(1);
+        let writer = WarmStartWriter {
+            path: path.clone(),
+            genesis: genesis.commit(),
+            receipts,
+            shutdown,
+        };
+        let resume = Resume {
+            next_block: 4,
+            ..Default::default()
+        };
+        writer.write(&state, &resume).await.unwrap();
+
+        let bundle = WarmStart::load(&path).unwrap().unwrap();
+        assert_eq!(bundle.next_block(), 4);
+
+        // The bundle only restores on a node with the same genesis state.
+        let other = genesis
+            .clone()
+            .with_replay_protection(ReplayProtection::RecentHashes, 10);
+        assert!(matches!(
+            bundle
+                .clone()
+                .restore(&other, &ReceiptIndex::default())
+                .await,
+            Err(WarmStartError::GenesisMismatch { .. })
+        ));
+
+        let restored_receipts = ReceiptIndex::default();
+        let (restored, resume) = bundle.restore(&genesis, &restored_receipts).await.unwrap();
+        assert_eq!(restored.commit(), state.commit());
+        assert_eq!(restored.get_balance(&bob), 10);
+        assert_eq!(restored.ledger().history().count(), 2);
+        assert_eq!(resume.next_block, 4);
+        assert_eq!(
+            restored_receipts.get(&transaction.hash()).await,
+            Some(receipt)
+        );
+        assert_eq!(
+            restored_receipts.block(3).await,
+            Some(vec![transaction.hash()])
+        );
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Watchdog flagging blocks whose execution exceeds a wall-clock budget.
+//!
+//! Execution must be deterministic, so a slow block cannot be cut short: every node would stop at
+//! a different transaction and compute a different state. Instead, the watchdog warns while a block
+//! is still executing past its budget, so that a stalled executor is visible in the logs, and once
+//! the block finishes it records an [`ExecutionIncident`] describing it. The executor then continues
+//! with the next block as usual. Incidents point operators at pathological payloads, which can be
+//! examined with the transaction trace endpoint.
+
+use crate::error::RollupError;
+use async_std::sync::{Arc, RwLock};
+use async_std::task::{sleep, spawn, JoinHandle};
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of recent incidents retained.
+pub const MAX_INCIDENTS: usize = 100;
+
+/// A block whose execution took longer than the budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionIncident {
+    pub block_height: u64,
+    pub budget_ms: u64,
+    pub elapsed_ms: u64,
+    /// Number of rollup transactions in the block.
+    pub transactions: usize,
+    /// Number of those transactions which failed.
+    pub failed_transactions: usize,
+}
+
+/// Times the execution of each block against an optional budget.
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionWatchdog {
+    budget: Option<Duration>,
+    incidents: Arc<RwLock<VecDeque<ExecutionIncident>>>,
+}
+
+/// The execution of one block, started with [`ExecutionWatchdog::start`].
+#[derive(Debug)]
+pub struct BlockWatch {
+    block_height: u64,
+    started: Instant,
+    alarm: Option<JoinHandle<()>>,
+}
+
+impl ExecutionWatchdog {
+    /// A watchdog flagging blocks which take longer than `budget` to execute, or which never flags
+    /// anything if there is no budget.
+    pub fn new(budget: Option<Duration>) -> Self {
+        Self {
+            budget,
+            incidents: Default::default(),
+        }
+    }
+
+    /// Start timing the execution of the block at `block_height`.
+    pub fn start(&self, block_height: u64) -> BlockWatch {
+        let alarm = self.budget.map(|budget| {
+            spawn(async move {
+                sleep(budget).await;
+                tracing::warn!(
+                    "Block {block_height} is still executing after its budget of {budget:?}"
+                );
+            })
+        });
+        BlockWatch {
+            block_height,
+            started: Instant::now(),
+            alarm,
+        }
+    }
+
+    /// Finish timing a block, whose transactions had the given `results`, returning the incident
+    /// recorded if it exceeded the budget.
+    pub async fn finish(
+        &self,
+        watch: BlockWatch,
+        results: &[(H256, Result<(), RollupError>)],
+    ) -> Option<ExecutionIncident> {
+        let elapsed = watch.started.elapsed();
+        if let Some(alarm) = watch.alarm {
+            alarm.cancel().await;
+        }
+        let budget = self.budget?;
+        if elapsed <= budget {
+            return None;
+        }
+        let incident = ExecutionIncident {
+            block_height: watch.block_height,
+            budget_ms: budget.as_millis() as u64,
+            elapsed_ms: elapsed.as_millis() as u64,
+            transactions: results.len(),
+            failed_transactions: results.iter().filter(|(_, res)| res.is_err()).count(),
+        };
+        tracing::error!(
+            "Block {} took {}ms to execute, exceeding its budget of {}ms",
+            incident.block_height,
+            incident.elapsed_ms,
+            incident.budget_ms
+        );
+        let mut incidents = self.incidents.write().await;
+        incidents.push_back(incident);
+        if incidents.len() > MAX_INCIDENTS {
+            incidents.pop_front();
+        }
+        Some(incident)
+    }
+
+    /// Recent incidents, oldest first.
+    pub async fn incidents(&self) -> Vec<ExecutionIncident> {
+        self.incidents.read().await.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_watchdog_records_slow_blocks() {
+        let results = [
+            (H256::random(), Ok(())),
+            (H256::random(), Err(RollupError::SignatureError)),
+        ];
+
+        // Without a budget, nothing is flagged.
+        let watchdog = ExecutionWatchdog::default();
+        let watch = watchdog.start(1);
+        assert_eq!(watchdog.finish(watch, &results).await, None);
+
+        // A block within its budget is not flagged.
+        let watchdog = ExecutionWatchdog::new(Some(Duration::from_secs(60)));
+        let watch = watchdog.start(1);
+        assert_eq!(watchdog.finish(watch, &results).await, None);
+        assert!(watchdog.incidents().await.is_empty());
+
+        // A block over its budget is recorded, and the watchdog keeps timing later blocks.
+        let watchdog = ExecutionWatchdog::new(Some(Duration::from_millis(1)));
+        let watch = watchdog.start(2);
+        sleep(Duration::from_millis(20)).await;
+        let incident = watchdog.finish(watch, &results).await.unwrap();
+        assert_eq!(incident.block_height, 2);
+        assert_eq!(incident.budget_ms, 1);
+        assert!(incident.elapsed_ms >= 20);
+        assert_eq!(
+            (incident.transactions, incident.failed_transactions),
+            (2, 1)
+        );
+
+        let watch = watchdog.start(3);
+        sleep(Duration::from_millis(20)).await;
+        watchdog.finish(watch, &[]).await.unwrap();
+        assert_eq!(
+            watchdog
+                .incidents()
+                .await
+                .iter()
+                .map(|incident| incident.block_height)
+                .collect::<Vec<_>>(),
+            [2, 3]
+        );
+    }
+}
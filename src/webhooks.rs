@@ -0,0 +1,240 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Webhook notifications of balance changes.
+//!
+//! The owner of an address may register a callback URL for it by signing a
+//! [`WebhookRegistration`]. After each executed block, every registered callback of an address
+//! whose balance changed in the block receives a [`BalanceNotification`] as a JSON `POST`.
+//! Delivery is best effort: notifications are sent in the background, failures are logged, and
+//! nothing is retried. A callback should respond with a JSON body, such as `null`, or the delivery
+//! is logged as failed.
+
+use crate::events::RollupEvent;
+use crate::state::{Amount, State};
+use crate::SequencerApiVersion;
+use async_std::sync::{Arc, RwLock};
+use async_std::task::spawn;
+use ethers::signers::Signer;
+use ethers::types::{Address, Signature};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::{BTreeMap, BTreeSet};
+use surf_disco::error::ClientError;
+use surf_disco::{Client, Url};
+
+/// Maximum number of callbacks registered for one address.
+pub const MAX_WEBHOOKS_PER_ADDRESS: usize = 8;
+
+#[derive(Clone, Debug, Snafu, PartialEq, Eq)]
+pub enum WebhookError {
+    #[snafu(display("Webhook registration is not signed by {address:?}."))]
+    InvalidSignature { address: Address },
+    #[snafu(display("{address:?} already has the maximum of {max} webhooks."))]
+    TooManyWebhooks { address: Address, max: usize },
+}
+
+/// A request to notify `callback` of changes to the balance of `address`, signed by `address`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookRegistration {
+    pub address: Address,
+    pub callback: Url,
+    signature: Signature,
+}
+
+impl WebhookRegistration {
+    fn message(address: &Address, callback: &Url) -> String {
+        format!("Register webhook {callback} for {address:?}")
+    }
+
+    /// Register `callback` for the address of `wallet`.
+    pub async fn new(callback: Url, wallet: &impl Signer) -> Self {
+        let address = wallet.address();
+        let signature = wallet
+            .sign_message(Self::message(&address, &callback))
+            .await
+            .unwrap();
+        Self {
+            address,
+            callback,
+            signature,
+        }
+    }
+
+    fn verify(&self) -> Result<(), WebhookError> {
+        self.signature
+            .verify(Self::message(&self.address, &self.callback), self.address)
+            .map_err(|_| WebhookError::InvalidSignature {
+                address: self.address,
+            })
+    }
+}
+
+/// The body posted to a webhook when the balance of its address changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceNotification {
+    pub block_height: u64,
+    pub address: Address,
+    pub previous_balance: Amount,
+    pub balance: Amount,
+}
+
+/// The registered webhooks, by address.
+#[derive(Clone, Debug, Default)]
+pub struct WebhookRegistry {
+    webhooks: Arc<RwLock<BTreeMap<Address, BTreeSet<Url>>>>,
+}
+
+impl WebhookRegistry {
+    /// Register a webhook, after checking that it is signed by the address it watches.
+    ///
+    /// Registering the same callback again has no effect.
+    pub async fn register(&self, registration: WebhookRegistration) -> Result<(), WebhookError> {
+        registration.verify()?;
+        let mut webhooks = self.webhooks.write().await;
+        let callbacks = webhooks.entry(registration.address).or_default();
+        if !callbacks.contains(&registration.callback)
+            && callbacks.len() >= MAX_WEBHOOKS_PER_ADDRESS
+        {
+            return Err(WebhookError::TooManyWebhooks {
+                address: registration.address,
+                max: MAX_WEBHOOKS_PER_ADDRESS,
+            });
+        }
+        callbacks.insert(registration.callback);
+        Ok(())
+    }
+
+    /// The notifications due for the block which took `prev` to `state`.
+    pub async fn notifications(
+        &self,
+        prev: &State,
+        state: &State,
+    ) -> Vec<(Url, BalanceNotification)> {
+        let webhooks = self.webhooks.read().await;
+        if webhooks.is_empty() {
+            return vec![];
+        }
+        // Every balance change is made by a transfer, a burn, a withdrawal or a deposit, so only
+        // the accounts they involve need to be checked.
+        let touched = state
+            .block_events()
+            .iter()
+            .flat_map(|event| match event {
+                RollupEvent::Transfer { from, to, .. } => vec![*from, *to],
+                RollupEvent::Burn { from, .. } | RollupEvent::Withdrawal { from, .. } => {
+                    vec![*from]
+                }
+                RollupEvent::Deposit { to, .. } => vec![*to],
+                RollupEvent::AccountCreated { .. } => vec![],
+            })
+            .collect::<BTreeSet<_>>();
+        let mut notifications = vec![];
+        for address in touched {
+            let Some(callbacks) = webhooks.get(&address) else {
+                continue;
+            };
+            let notification = BalanceNotification {
+                block_height: state.block_height(),
+                address,
+                previous_balance: prev.get_balance(&address),
+                balance: state.get_balance(&address),
+            };
+            if notification.previous_balance == notification.balance {
+                continue;
+            }
+            notifications.extend(
+                callbacks
+                    .iter()
+                    .map(|callback| (callback.clone(), notification)),
+            );
+        }
+        notifications
+    }
+
+    /// Post the notifications due for the block which took `prev` to `state`, in the background.
+    pub async fn notify(&self, prev: &State, state: &State) {
+        for (callback, notification) in self.notifications(prev, state).await {
+            spawn(async move {
+                let client = Client::<ClientError, SequencerApiVersion>::new(callback.clone());
+                let result = match client
+                    .post::<serde_json::Value>("")
+                    .body_json(&notification)
+                {
+                    Ok(request) => request.send().await.map(|_| ()),
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    tracing::warn!("Failed to deliver balance notification to {callback}: {err}");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{SignedTransaction, Transaction};
+    use crate::RollupVM;
+    use espresso_types::NamespaceId;
+    use ethers::signers::LocalWallet;
+
+    #[async_std::test]
+    async fn test_webhook_notifications() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let carol = Address::random();
+        let callback: Url = "http://localhost:9000/hook".parse().unwrap();
+        let registry = WebhookRegistry::default();
+
+        registry
+            .register(WebhookRegistration::new(callback.clone(), &alice).await)
+            .await
+            .unwrap();
+        registry
+            .register(WebhookRegistration::new(callback.clone(), &bob).await)
+            .await
+            .unwrap();
+
+        // A registration must be signed by the address it watches.
+        let mut forged = WebhookRegistration::new(callback.clone(), &alice).await;
+        forged.address = carol;
+        assert_eq!(
+            registry.register(forged).await,
+            Err(WebhookError::InvalidSignature { address: carol })
+        );
+
+        let vm = RollupVM::new(NamespaceId::from(1_u64));
+        let prev = State::from_initial_balances([(alice.address(), 100)], vm);
+        let mut state = prev.clone();
+        let transfer = Transaction {
+            amount: 40,
+            destination: carol,
+            nonce: 1,
+            ..Default::default()
+        };
+        state
+            .apply_transaction(&SignedTransaction::new(transfer, &alice).await)
+            .unwrap();
+
+        // Only Alice's balance changed, and Carol has no webhook.
+        let notifications = registry.notifications(&prev, &state).await;
+        assert_eq!(
+            notifications,
+            [(
+                callback,
+                BalanceNotification {
+                    block_height: 0,
+                    address: alice.address(),
+                    previous_balance: 100,
+                    balance: 60,
+                }
+            )]
+        );
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Withdrawals from the rollup to the L1.
+//!
+//! A transaction of kind [`Withdraw`](crate::transaction::TransactionKind::Withdraw) burns tokens
+//! on the rollup and appends a [`Withdrawal`] to the state. Withdrawals are hashed, in the order
+//! they executed, into a binary Merkle tree built like the accounts tree of
+//! [`balance_proof`](crate::balance_proof), with leaves
+//! `keccak256(abi.encodePacked(address recipient, uint64 amount, uint64 index))`. The root of this
+//! tree is part of the state commitment, and is posted to the rollup contract with each batch
+//! proof covering blocks which executed withdrawals. The recipient then claims a withdrawal by
+//! calling `withdraw` on the contract with a [`WithdrawalProof`] against the posted root.
+
+use crate::balance_proof;
+use crate::state::Amount;
+use ethers::{abi::Address, types::H256, utils::keccak256};
+use serde::{Deserialize, Serialize};
+
+/// Tokens burned on the rollup, to be claimed by `recipient` on the L1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Withdrawal {
+    pub recipient: Address,
+    pub amount: Amount,
+}
+
+/// The root of the tree over the first `count` withdrawals, as posted to the rollup contract.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawalsRoot {
+    pub root: [u8; 32],
+    pub count: u64,
+}
+
+impl WithdrawalsRoot {
+    /// The root of the tree over `withdrawals`.
+    ///
+    /// The root of the empty tree is zero, like the root stored by a freshly deployed contract.
+    pub fn new(withdrawals: &[Withdrawal]) -> Self {
+        let levels = balance_proof::tree_levels(leaves(withdrawals));
+        Self {
+            root: levels.last().unwrap()[0].0,
+            count: withdrawals.len() as u64,
+        }
+    }
+}
+
+/// Proof that a withdrawal is in the tree with a particular root.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawalProof {
+    pub recipient: Address,
+    pub amount: Amount,
+    /// Position of the withdrawal among all withdrawals, which is also the position of its leaf.
+    pub index: u64,
+    /// Sibling hashes on the path from the leaf to the root, starting at the leaf.
+    pub siblings: Vec<H256>,
+}
+
+fn leaf_hash(withdrawal: &Withdrawal, index: u64) -> H256 {
+    let mut bytes = withdrawal.recipient.as_bytes().to_vec();
+    bytes.extend_from_slice(&withdrawal.amount.to_be_bytes());
+    bytes.extend_from_slice(&index.to_be_bytes());
+    H256(keccak256(bytes))
+}
+
+fn leaves(withdrawals: &[Withdrawal]) -> Vec<H256> {
+    withdrawals
+        .iter()
+        .enumerate()
+        .map(|(index, withdrawal)| leaf_hash(withdrawal, index as u64))
+        .collect()
+}
+
+impl WithdrawalProof {
+    /// Prove the withdrawal at `index` against the root of the tree over `withdrawals`.
+    ///
+    /// Returns `None` if there is no such withdrawal.
+    pub fn new(withdrawals: &[Withdrawal], index: u64) -> Option<Self> {
+        let withdrawal = withdrawals.get(usize::try_from(index).ok()?)?;
+        let levels = balance_proof::tree_levels(leaves(withdrawals));
+        Some(Self {
+            recipient: withdrawal.recipient,
+            amount: withdrawal.amount,
+            index,
+            siblings: balance_proof::sibling_path(&levels, index as usize),
+        })
+    }
+
+    /// Compute the root implied by this proof.
+    pub fn root(&self) -> H256 {
+        let withdrawal = Withdrawal {
+            recipient: self.recipient,
+            amount: self.amount,
+        };
+        let mut node = leaf_hash(&withdrawal, self.index);
+        for (i, sibling) in self.siblings.iter().enumerate() {
+            node = if (self.index >> i) & 1 == 1 {
+                balance_proof::node_hash(sibling, &node)
+            } else {
+                balance_proof::node_hash(&node, sibling)
+            };
+        }
+        node
+    }
+
+    /// Check the proof against a known withdrawals root.
+    pub fn verify(&self, root: H256) -> bool {
+        self.root() == root
+    }
+
+    /// ABI encoded calldata for a `withdraw` call on the rollup contract claiming this withdrawal.
+    #[cfg(feature = "executor")]
+    pub fn calldata(&self) -> ethers::types::Bytes {
+        use contract_bindings::example_rollup::{self, WithdrawCall};
+        use ethers::abi::AbiEncode;
+
+        WithdrawCall {
+            proof: example_rollup::WithdrawalProof {
+                recipient: self.recipient,
+                amount: self.amount,
+                index: self.index,
+                siblings: self.siblings.iter().map(|sibling| sibling.0).collect(),
+            },
+        }
+        .encode()
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_withdrawal_proof() {
+        let withdrawals = (1..=3)
+            .map(|amount| Withdrawal {
+                recipient: Address::random(),
+                amount,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(WithdrawalsRoot::new(&[]).root, [0; 32]);
+
+        // Proofs are against the root of the tree over the withdrawals made so far, which changes
+        // as more are appended.
+        let root = WithdrawalsRoot::new(&withdrawals);
+        let earlier_root = WithdrawalsRoot::new(&withdrawals[..2]);
+        assert_eq!(root.count, 3);
+        assert_ne!(root.root, earlier_root.root);
+        for index in 0..3 {
+            let proof = WithdrawalProof::new(&withdrawals, index).unwrap();
+            assert!(proof.verify(H256(root.root)));
+            match WithdrawalProof::new(&withdrawals[..2], index) {
+                Some(earlier) => assert!(earlier.verify(H256(earlier_root.root))),
+                None => assert_eq!(index, 2),
+            }
+
+            // The index is part of the leaf, so a withdrawal cannot be claimed at another position.
+            let mut forged = proof.clone();
+            forged.index ^= 1;
+            assert!(!forged.verify(H256(root.root)));
+            let mut forged = proof;
+            forged.amount += 1;
+            assert!(!forged.verify(H256(root.root)));
+        }
+        assert!(WithdrawalProof::new(&withdrawals, 3).is_none());
+    }
+}